@@ -0,0 +1,560 @@
+//! Cobalto admin
+//!
+//! CRUD pages generated from registered models: a model index, a paginated and
+//! searchable list per model, and create / edit / delete forms, all rendered
+//! through the template engine and mounted under a prefix:
+//!
+//! ```ignore
+//! router.manage(db);
+//! Admin::new("/admin").register::<Post>().register::<Author>().mount(&mut router);
+//! ```
+//!
+//! The database comes from the router state (`req.state::<Db>()`). Access is
+//! decided by a guard; the default one admits sessions where `is_staff` is
+//! `"true"`, so sessions must be enabled on the router.
+
+use crate::datatable::{Column, DataTable};
+use crate::forms::{FormField, Widget, humanize};
+use crate::orm::{
+    Db, DeleteError, Field, FieldType, Model, QuerySet, SqlValue, insert_sql_for,
+    registered_relations, update_sql_for,
+};
+use crate::router::{Request, Response, Router, handler, parse_urlencoded, percent_encode};
+use crate::template::{TemplateValue, escape_html, parse_tokens, render_nodes, tokenize_template};
+use sqlx::Row;
+use sqlx::any::AnyRow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Rows per list page unless changed with `Admin::per_page`.
+pub const DEFAULT_PER_PAGE: usize = 25;
+
+/// Decides whether a request may use the admin.
+pub type AdminGuard = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+
+const LAYOUT_START: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>{{ title }} · Admin</title></head>
+<body class="cobalto-admin"><header><a href="{{ prefix }}">Administration</a></header><main>
+<h1>{{ title }}</h1>
+"#;
+
+const LAYOUT_END: &str = "</main></body></html>\n";
+
+const INDEX_TEMPLATE: &str = r#"<ul>{% for model in models %}<li><a href="{{ model.url }}">{{ model.name }}</a></li>{% endfor %}</ul>
+"#;
+
+const LIST_TEMPLATE: &str = r#"<form method="get"><input type="search" name="q" value="{{ q }}"><button>Search</button></form>
+<p><a href="{{ new_url }}">Add {{ model }}</a> · {{ total }} rows</p>
+<table><thead><tr>{% for column in columns %}<th>{{ column }}</th>{% endfor %}<th></th></tr></thead>
+<tbody>{% for row in rows %}<tr>{% for cell in row.cells %}<td>{{ cell }}</td>{% endfor %}<td><a href="{{ row.url }}">Edit</a></td></tr>{% endfor %}</tbody></table>
+<nav>{% if has_prev %}<a href="{{ prev_url }}">Previous</a> {% endif %}Page {{ page }} of {{ pages }}{% if has_next %} <a href="{{ next_url }}">Next</a>{% endif %}</nav>
+"#;
+
+const FORM_TEMPLATE: &str = r#"<form method="post">{{ form|safe }}<button type="submit">Save</button></form>
+{% if editing %}<form method="post" action="{{ delete_url }}"><button type="submit">Delete</button></form>{% endif %}
+<p><a href="{{ list_url }}">Back to list</a></p>
+"#;
+
+/// How one model appears in the admin.
+#[derive(Clone, Debug)]
+pub struct ModelAdmin {
+    pub table: String,
+    pub fields: Vec<Field>,
+    pub primary_key: String,
+    pub list_display: Vec<String>,
+    pub search_fields: Vec<String>,
+}
+
+impl ModelAdmin {
+    /// Every field listed, text fields searchable.
+    pub fn of<M: Model>() -> Self {
+        let fields = M::fields();
+        ModelAdmin {
+            table: M::table_name().to_string(),
+            list_display: fields.iter().map(|f| f.name.clone()).collect(),
+            search_fields: fields
+                .iter()
+                .filter(|f| f.field_type == FieldType::Text)
+                .map(|f| f.name.clone())
+                .collect(),
+            primary_key: M::primary_key(),
+            fields,
+        }
+    }
+
+    /// Builder for the columns shown in the list
+    pub fn list_display(mut self, columns: &[&str]) -> Self {
+        self.list_display = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Builder for the columns matched by the search box
+    pub fn search_fields(mut self, columns: &[&str]) -> Self {
+        self.search_fields = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Form fields for everything but database-assigned keys.
+    fn form_fields(&self) -> Vec<FormField> {
+        self.fields
+            .iter()
+            .filter(|f| !f.is_auto())
+            .map(|f| {
+                let widget = match f.field_type {
+                    FieldType::Integer | FieldType::Float => Widget::Number,
+                    FieldType::Boolean => Widget::Checkbox,
+                    FieldType::Text if f.max_length.is_none() => Widget::Textarea,
+                    _ => Widget::Text,
+                };
+                let form_field = FormField::new(&f.name).widget(widget);
+                if f.nullable || f.default.is_some() || f.field_type == FieldType::Boolean {
+                    form_field.optional()
+                } else {
+                    form_field
+                }
+            })
+            .collect()
+    }
+}
+
+/// The admin site: registered models, URL prefix and access guard.
+#[derive(Clone)]
+pub struct Admin {
+    prefix: String,
+    models: Vec<ModelAdmin>,
+    per_page: usize,
+    guard: AdminGuard,
+}
+
+impl Admin {
+    pub fn new(prefix: &str) -> Self {
+        Admin {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            models: Vec::new(),
+            per_page: DEFAULT_PER_PAGE,
+            guard: Arc::new(|req: &Request| {
+                req.session()
+                    .and_then(|s| s.get("is_staff"))
+                    .is_some_and(|v| v == "true")
+            }),
+        }
+    }
+
+    /// Builder registering a model with the default options
+    pub fn register<M: Model>(self) -> Self {
+        self.register_with(ModelAdmin::of::<M>())
+    }
+
+    /// Builder registering a model with custom options
+    pub fn register_with(mut self, model: ModelAdmin) -> Self {
+        self.models.push(model);
+        self
+    }
+
+    /// Builder for the list page size
+    pub fn per_page(mut self, per_page: usize) -> Self {
+        self.per_page = per_page.max(1);
+        self
+    }
+
+    /// Builder replacing the access check
+    pub fn guard<F>(mut self, guard: F) -> Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.guard = Arc::new(guard);
+        self
+    }
+
+    /// Add the admin routes to `router`.
+    pub fn mount(self, router: &mut Router) {
+        let admin = Arc::new(self);
+        let prefix = admin.prefix.clone();
+        let routes: [(&str, &str, Page); 7] = [
+            ("GET", "/", Page::Index),
+            ("GET", "/:model", Page::List),
+            ("GET", "/:model/new", Page::Create),
+            ("POST", "/:model/new", Page::Create),
+            ("POST", "/:model/:pk/delete", Page::Delete),
+            ("GET", "/:model/:pk", Page::Edit),
+            ("POST", "/:model/:pk", Page::Edit),
+        ];
+        router.group(&prefix, |g| {
+            for (method, path, page) in routes {
+                let admin = admin.clone();
+                g.add_route(
+                    method,
+                    path,
+                    handler(move |req| {
+                        let admin = admin.clone();
+                        let post = method == "POST";
+                        async move { admin.serve(page, post, req).await }
+                    }),
+                    "admin",
+                );
+            }
+        });
+    }
+
+    async fn serve(&self, page: Page, post: bool, req: Request) -> Response {
+        if !(self.guard)(&req) {
+            return Response::html("Forbidden").with_status(403);
+        }
+        let Some(db) = req.state::<Db>() else {
+            return Response::html("Admin needs a Db registered with Router::manage")
+                .with_status(500);
+        };
+        if page == Page::Index {
+            return self.index();
+        }
+        let Some(model) = req
+            .params
+            .get("model")
+            .and_then(|name| self.models.iter().find(|m| &m.table == name))
+        else {
+            return Response::html("Not found").with_status(404);
+        };
+        let pk = req.params.get("pk").cloned();
+        let result = match (page, post) {
+            (Page::List, _) => self.list(&db, model, &req.query).await,
+            (Page::Create, false) => Ok(self.form(model, None, &HashMap::new(), &HashMap::new())),
+            (Page::Create, true) => self.save(&db, model, None, &req.body).await,
+            (Page::Edit, false) => self.edit(&db, model, pk.as_deref().unwrap_or("")).await,
+            (Page::Edit, true) => self.save(&db, model, pk.as_deref(), &req.body).await,
+            (Page::Delete, _) => self.delete(&db, model, pk.as_deref().unwrap_or("")).await,
+            (Page::Index, _) => unreachable!(),
+        };
+        result.unwrap_or_else(|e| match e {
+            sqlx::Error::RowNotFound => Response::html("Not found").with_status(404),
+            other => Response::html(format!(
+                "Database error: {}",
+                escape_html(&other.to_string())
+            ))
+            .with_status(500),
+        })
+    }
+
+    fn model_url(&self, model: &ModelAdmin) -> String {
+        format!("{}/{}", self.prefix, model.table)
+    }
+
+    fn render(
+        &self,
+        title: &str,
+        body: &str,
+        mut context: HashMap<String, TemplateValue>,
+    ) -> Response {
+        context.insert("title".into(), TemplateValue::String(title.to_string()));
+        context.insert("prefix".into(), TemplateValue::String(self.prefix.clone()));
+        let source = format!("{}{}{}", LAYOUT_START, body, LAYOUT_END);
+        Response::html(render_nodes(
+            &parse_tokens(&tokenize_template(&source)),
+            &context,
+        ))
+    }
+
+    fn index(&self) -> Response {
+        let models = self
+            .models
+            .iter()
+            .map(|m| object([("name", humanize(&m.table)), ("url", self.model_url(m))]))
+            .collect();
+        self.render(
+            "Site administration",
+            INDEX_TEMPLATE,
+            HashMap::from([("models".to_string(), TemplateValue::List(models))]),
+        )
+    }
+
+    async fn list(
+        &self,
+        db: &Db,
+        model: &ModelAdmin,
+        query: &HashMap<String, String>,
+    ) -> Result<Response, sqlx::Error> {
+        let base = self.model_url(model);
+        let search: Vec<&str> = model.search_fields.iter().map(|f| f.as_str()).collect();
+        let table = DataTable::new(
+            &model.table,
+            &base,
+            model
+                .list_display
+                .iter()
+                .map(|c| Column::new(c, &humanize(c)))
+                .collect(),
+        )
+        .per_page(self.per_page)
+        .search_fields(&search)
+        .default_order(&format!("-{}", model.primary_key));
+        let objects = QuerySet::for_table(db, &model.table, &model.fields);
+        let (state, objects) = table.query(objects, query).await?;
+        let rows = objects.rows().await?;
+        let (q, page) = (state.filter.as_str(), state.page);
+
+        let columns: Vec<&Field> = model
+            .list_display
+            .iter()
+            .filter_map(|c| model.field(c))
+            .collect();
+        let pk_field = model.field(&model.primary_key);
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let cells = columns
+                    .iter()
+                    .map(|f| TemplateValue::String(cell(row, f)))
+                    .collect();
+                let pk = pk_field.map(|f| cell(row, f)).unwrap_or_default();
+                TemplateValue::Object(HashMap::from([
+                    ("cells".to_string(), TemplateValue::List(cells)),
+                    (
+                        "url".to_string(),
+                        TemplateValue::String(format!("{}/{}", base, percent_encode(&pk))),
+                    ),
+                ]))
+            })
+            .collect();
+        let page_url = |n: usize| {
+            let mut url = format!("{}?page={}", base, n);
+            if !q.is_empty() {
+                url.push_str("&q=");
+                url.push_str(&percent_encode(q));
+            }
+            url
+        };
+
+        let mut context = HashMap::new();
+        let mut put = |k: &str, v: TemplateValue| {
+            context.insert(k.to_string(), v);
+        };
+        put("model", TemplateValue::String(humanize(&model.table)));
+        put("q", TemplateValue::String(q.to_string()));
+        put("new_url", TemplateValue::String(format!("{}/new", base)));
+        put("total", TemplateValue::Number(state.total as f64));
+        put(
+            "columns",
+            TemplateValue::List(
+                columns
+                    .iter()
+                    .map(|f| TemplateValue::String(humanize(&f.name)))
+                    .collect(),
+            ),
+        );
+        put("rows", TemplateValue::List(rows));
+        put("page", TemplateValue::Number(page as f64));
+        put("pages", TemplateValue::Number(state.pages as f64));
+        put("has_prev", TemplateValue::Bool(page > 1));
+        put("has_next", TemplateValue::Bool(page < state.pages));
+        put(
+            "prev_url",
+            TemplateValue::String(page_url(page.saturating_sub(1))),
+        );
+        put("next_url", TemplateValue::String(page_url(page + 1)));
+        Ok(self.render(&humanize(&model.table), LIST_TEMPLATE, context))
+    }
+
+    fn form(
+        &self,
+        model: &ModelAdmin,
+        pk: Option<&str>,
+        values: &HashMap<String, String>,
+        errors: &HashMap<String, String>,
+    ) -> Response {
+        let base = self.model_url(model);
+        let form: String = model
+            .form_fields()
+            .iter()
+            .map(|f| {
+                f.render(
+                    values.get(&f.name).map(|s| s.as_str()),
+                    errors.get(&f.name).map(|s| s.as_str()),
+                )
+            })
+            .collect();
+        let title = match pk {
+            Some(pk) => format!("Edit {} {}", humanize(&model.table), pk),
+            None => format!("Add {}", humanize(&model.table)),
+        };
+        let mut context = HashMap::new();
+        context.insert("form".to_string(), TemplateValue::String(form));
+        context.insert("editing".to_string(), TemplateValue::Bool(pk.is_some()));
+        context.insert("list_url".to_string(), TemplateValue::String(base.clone()));
+        if let Some(pk) = pk {
+            context.insert(
+                "delete_url".to_string(),
+                TemplateValue::String(format!("{}/{}/delete", base, percent_encode(pk))),
+            );
+        }
+        let resp = self.render(&title, FORM_TEMPLATE, context);
+        if errors.is_empty() {
+            resp
+        } else {
+            resp.with_status(400)
+        }
+    }
+
+    async fn edit(&self, db: &Db, model: &ModelAdmin, pk: &str) -> Result<Response, sqlx::Error> {
+        let sql = format!(
+            "SELECT * FROM {} WHERE {} = ?",
+            model.table, model.primary_key
+        );
+        let sql = db.backend().placeholders(&sql);
+        let row = sqlx::query(&sql).bind(pk).fetch_optional(&db.pool).await?;
+        let row = row.ok_or(sqlx::Error::RowNotFound)?;
+        let values = model
+            .fields
+            .iter()
+            .map(|f| (f.name.clone(), cell(&row, f)))
+            .collect();
+        Ok(self.form(model, Some(pk), &values, &HashMap::new()))
+    }
+
+    async fn save(
+        &self,
+        db: &Db,
+        model: &ModelAdmin,
+        pk: Option<&str>,
+        body: &str,
+    ) -> Result<Response, sqlx::Error> {
+        let submitted = parse_urlencoded(body);
+        let creating = pk.is_none();
+        let mut values = Vec::new();
+        let mut errors = HashMap::new();
+        for field in model.fields.iter().filter(|f| !f.is_auto()) {
+            if !creating && field.primary_key {
+                continue;
+            }
+            match form_value(
+                field,
+                submitted.get(&field.name).map(|s| s.as_str()),
+                creating,
+            ) {
+                Ok(value) => values.push(value),
+                Err(e) => {
+                    errors.insert(field.name.clone(), e);
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Ok(self.form(model, pk, &submitted, &errors));
+        }
+        match pk {
+            None => {
+                db.execute_with(&insert_sql_for(&model.table, &model.fields), values)
+                    .await?;
+            }
+            Some(pk) => {
+                values.push(SqlValue::Text(pk.to_string()));
+                let sql = update_sql_for(&model.table, &model.fields, &model.primary_key);
+                if db.execute_with(&sql, values).await? == 0 {
+                    return Err(sqlx::Error::RowNotFound);
+                }
+            }
+        }
+        Ok(Response::redirect(&self.model_url(model)))
+    }
+
+    async fn delete(&self, db: &Db, model: &ModelAdmin, pk: &str) -> Result<Response, sqlx::Error> {
+        let soft_delete = model.fields.iter().any(|f| f.name == "deleted_at");
+        let deleted = db
+            .delete_with_relations(
+                &model.table,
+                &model.primary_key,
+                SqlValue::Text(pk.to_string()),
+                &registered_relations(),
+                soft_delete,
+            )
+            .await;
+        match deleted {
+            Ok(_) => Ok(Response::redirect(&self.model_url(model))),
+            Err(e @ DeleteError::Restricted(..)) => {
+                Ok(Response::html(escape_html(&e.to_string())).with_status(409))
+            }
+            Err(DeleteError::Db(e)) => Err(e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Page {
+    Index,
+    List,
+    Create,
+    Edit,
+    Delete,
+}
+
+/// Display text of a column, whatever its storage type.
+fn cell(row: &AnyRow, field: &Field) -> String {
+    let name = field.name.as_str();
+    let text = match field.field_type {
+        FieldType::Integer => row
+            .try_get::<Option<i64>, _>(name)
+            .ok()
+            .flatten()
+            .map(|v| v.to_string()),
+        FieldType::Float => row
+            .try_get::<Option<f64>, _>(name)
+            .ok()
+            .flatten()
+            .map(|v| v.to_string()),
+        FieldType::Boolean => row
+            .try_get::<Option<bool>, _>(name)
+            .ok()
+            .flatten()
+            .map(|v| v.to_string()),
+        FieldType::Blob => row
+            .try_get::<Option<Vec<u8>>, _>(name)
+            .ok()
+            .flatten()
+            .map(|v| format!("{} bytes", v.len())),
+        FieldType::Text | FieldType::DateTime => {
+            row.try_get::<Option<String>, _>(name).ok().flatten()
+        }
+    };
+    text.unwrap_or_default()
+}
+
+/// Convert a submitted form value to the field's SQL type.
+fn form_value(field: &Field, raw: Option<&str>, creating: bool) -> Result<SqlValue, String> {
+    let raw = raw.map(str::trim).unwrap_or("");
+    if field.field_type == FieldType::Boolean {
+        return Ok(SqlValue::Bool(matches!(raw, "true" | "on" | "1")));
+    }
+    if raw.is_empty() {
+        return if field.nullable || (creating && field.default.is_some()) {
+            Ok(SqlValue::Null)
+        } else {
+            Err("This field is required.".to_string())
+        };
+    }
+    match field.field_type {
+        FieldType::Integer => raw
+            .parse()
+            .map(SqlValue::Int)
+            .map_err(|_| "Enter a whole number.".to_string()),
+        FieldType::Float => raw
+            .parse()
+            .map(SqlValue::Float)
+            .map_err(|_| "Enter a number.".to_string()),
+        _ => match field.max_length {
+            Some(n) if raw.chars().count() > n as usize => {
+                Err(format!("Ensure this value has at most {} characters.", n))
+            }
+            _ => Ok(SqlValue::Text(raw.to_string())),
+        },
+    }
+}
+
+fn object<const N: usize>(pairs: [(&str, String); N]) -> TemplateValue {
+    TemplateValue::Object(
+        pairs
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), TemplateValue::String(v)))
+            .collect(),
+    )
+}
@@ -0,0 +1,145 @@
+//! Cobalto allocation stats (feature `alloc-stats`)
+//!
+//! A counting wrapper around the system allocator. Install it in the binary:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: cobalto::alloc_stats::CountingAllocator = cobalto::alloc_stats::CountingAllocator;
+//! ```
+//!
+//! Counters are kept per thread, and `measure` attributes to a future exactly
+//! the allocations made while it is being polled, so concurrent requests on
+//! the same worker do not pollute each other's numbers.
+//!
+//! The process totals are exported on `/metrics` as
+//! `cobalto_alloc_allocations_total` and `cobalto_alloc_bytes_total` once
+//! `register_metrics` (or `wrap`) has been called, and on the debug page.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+use std::sync::{Arc, Once};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::router::Handler;
+use crate::settings::Settings;
+
+static TOTAL_ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static TOTAL_BYTES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static THREAD_ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    static THREAD_BYTES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Allocation count and bytes requested.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    pub allocations: u64,
+    pub bytes: u64,
+}
+
+/// `System` allocator that counts allocations.
+pub struct CountingAllocator;
+
+fn record(bytes: usize) {
+    TOTAL_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    TOTAL_BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+    // try_with: thread-locals may already be gone during thread teardown
+    let _ = THREAD_ALLOCATIONS.try_with(|c| c.set(c.get() + 1));
+    let _ = THREAD_BYTES.try_with(|c| c.set(c.get() + bytes as u64));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size.saturating_sub(layout.size()));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Process-wide totals since startup.
+pub fn totals() -> AllocStats {
+    AllocStats {
+        allocations: TOTAL_ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: TOTAL_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// Export the process totals through `metrics::register_counter`; later
+/// calls do nothing.
+pub fn register_metrics() {
+    static REGISTERED: Once = Once::new();
+    REGISTERED.call_once(|| {
+        crate::metrics::register_counter(
+            "cobalto_alloc_allocations_total",
+            "Heap allocations since startup.",
+            || totals().allocations,
+        );
+        crate::metrics::register_counter(
+            "cobalto_alloc_bytes_total",
+            "Heap bytes allocated since startup.",
+            || totals().bytes,
+        );
+    });
+}
+
+fn thread_stats() -> AllocStats {
+    AllocStats {
+        allocations: THREAD_ALLOCATIONS.try_with(Cell::get).unwrap_or(0),
+        bytes: THREAD_BYTES.try_with(Cell::get).unwrap_or(0),
+    }
+}
+
+/// Run `fut` and report the allocations made while polling it.
+pub async fn measure<F: Future>(fut: F) -> (F::Output, AllocStats) {
+    let mut fut = std::pin::pin!(fut);
+    let mut stats = AllocStats::default();
+    let output = std::future::poll_fn(|cx| {
+        let before = thread_stats();
+        let poll = fut.as_mut().poll(cx);
+        let after = thread_stats();
+        stats.allocations += after.allocations.wrapping_sub(before.allocations);
+        stats.bytes += after.bytes.wrapping_sub(before.bytes);
+        poll
+    })
+    .await;
+    (output, stats)
+}
+
+/// Wrap a handler so that, when `settings.debug` is on, its responses carry
+/// `X-Alloc-Count`/`X-Alloc-Bytes` headers; outside debug mode the handler is
+/// returned unchanged. The totals are exported on `/metrics` either way, and
+/// shown on the debug page.
+pub fn wrap(handler: Handler, settings: &Settings) -> Handler {
+    register_metrics();
+    if !settings.debug {
+        return handler;
+    }
+    Arc::new(move |req| {
+        let handler = handler.clone();
+        Box::pin(async move {
+            let (resp, stats) = measure(handler(req)).await;
+            log::debug!(
+                "request allocated {} times ({} bytes)",
+                stats.allocations,
+                stats.bytes
+            );
+            resp.add_header("X-Alloc-Count".to_string(), stats.allocations.to_string())
+                .add_header("X-Alloc-Bytes".to_string(), stats.bytes.to_string())
+        })
+    })
+}
@@ -0,0 +1,215 @@
+//! Cobalto apps
+//!
+//! An `App` bundles one part of a larger project: its routes (mounted under a
+//! prefix), a template directory, its models and its migrations. Apps are
+//! composed into the main `Router` with `Router::mount`:
+//!
+//! ```ignore
+//! let mut blog = App::new("blog", "/blog");
+//! blog.routes(|g| {
+//!     g.get("/", index).name("index");
+//!     g.get("/:slug", post).name("post");
+//! })
+//! .templates("blog/templates")
+//! .model::<Post>()
+//! .migration("0002_post_summary", "ALTER TABLE post ADD COLUMN summary TEXT");
+//! router.mount(blog);
+//! router.migrate(&db).await?;
+//! ```
+//!
+//! Route names are namespaced by the app: the `post` route above reverses as
+//! `blog:post`, so two apps can both name a route `index`.
+
+use crate::orm::{Backend, Db, Index, Model, SqlValue, create_table_sql};
+use crate::router::{Route, RouteGroup, Router};
+
+/// Table recording the applied migrations.
+pub const MIGRATIONS_TABLE: &str = "cobalto_migrations";
+
+/// A named schema change, applied once per database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Migration {
+    pub name: String,
+    pub sql: String,
+}
+
+/// Table, DDL and indexes of a model registered by an app.
+#[derive(Clone, Debug)]
+pub struct AppModel {
+    pub table: &'static str,
+    pub create_sql: fn(Backend) -> String,
+    pub indexes: Vec<Index>,
+}
+
+impl AppModel {
+    /// Model `M`, created from its declared fields.
+    pub fn of<M: Model>() -> Self {
+        AppModel {
+            table: M::table_name(),
+            create_sql: |backend| create_table_sql::<M>(backend, &[]),
+            indexes: M::indexes(),
+        }
+    }
+}
+
+/// A self-contained part of a project, mounted with `Router::mount`.
+pub struct App {
+    pub name: String,
+    pub prefix: String,
+    routes: Vec<Route>,
+    template_dir: Option<String>,
+    models: Vec<AppModel>,
+    migrations: Vec<Migration>,
+}
+
+impl App {
+    /// App `name` with its routes under `prefix` ("" or "/" for the root).
+    pub fn new(name: &str, prefix: &str) -> Self {
+        App {
+            name: name.to_string(),
+            prefix: prefix.trim_end_matches('/').to_string(),
+            routes: Vec::new(),
+            template_dir: None,
+            models: Vec::new(),
+            migrations: Vec::new(),
+        }
+    }
+
+    /// Register routes relative to the app prefix; group middleware applies
+    /// to this call's routes only.
+    pub fn routes<F: FnOnce(&mut RouteGroup)>(&mut self, f: F) -> &mut Self {
+        let mut group = RouteGroup::new(&self.prefix);
+        f(&mut group);
+        self.routes.extend(group.into_routes());
+        self
+    }
+
+    /// Directory searched for templates not found in the project's own
+    pub fn templates(&mut self, dir: &str) -> &mut Self {
+        self.template_dir = Some(dir.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Model whose table `Router::migrate` creates; its foreign keys are
+    /// registered for `Model::delete`
+    pub fn model<M: Model>(&mut self) -> &mut Self {
+        crate::orm::register_relations(&M::foreign_keys());
+        self.models.push(AppModel::of::<M>());
+        self
+    }
+
+    /// Migration applied by `Router::migrate`, after the models' tables and
+    /// the app's earlier migrations
+    pub fn migration(&mut self, name: &str, sql: &str) -> &mut Self {
+        self.migrations.push(Migration {
+            name: name.to_string(),
+            sql: sql.to_string(),
+        });
+        self
+    }
+
+    pub fn template_dir(&self) -> Option<&str> {
+        self.template_dir.as_deref()
+    }
+
+    pub fn models(&self) -> &[AppModel] {
+        &self.models
+    }
+
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+}
+
+impl Router {
+    /// Add `app`'s routes and template directory; its models and migrations
+    /// are kept for `migrate`.
+    ///
+    /// Panics on conflicting routes or an app name mounted twice.
+    pub fn mount(&mut self, mut app: App) {
+        assert!(
+            self.apps.iter().all(|a| a.name != app.name),
+            "app '{}' is already mounted",
+            app.name
+        );
+        for mut route in std::mem::take(&mut app.routes) {
+            if let Some(name) = route.name.take() {
+                route.name(&format!("{}:{}", app.name, name));
+            }
+            self.push_route(route);
+        }
+        if let Some(dir) = &app.template_dir {
+            crate::template::add_template_dir(dir);
+        }
+        self.apps.push(app);
+    }
+
+    /// Names of the mounted apps, in mount order.
+    pub fn apps(&self) -> Vec<&str> {
+        self.apps.iter().map(|a| a.name.as_str()).collect()
+    }
+
+    /// Create the mounted apps' tables and missing indexes, then apply their
+    /// pending migrations in mount order, each in a transaction with its
+    /// record where the backend allows it. Returns the applied
+    /// `app.migration` names.
+    pub async fn migrate(&self, db: &Db) -> Result<Vec<String>, sqlx::Error> {
+        migrate(db, &self.apps).await
+    }
+}
+
+/// `Router::migrate` for apps that are not mounted on a router.
+pub async fn migrate(db: &Db, apps: &[App]) -> Result<Vec<String>, sqlx::Error> {
+    db.execute(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (app VARCHAR(100) NOT NULL, name VARCHAR(200) NOT NULL, \
+         applied_at VARCHAR(40) NOT NULL, PRIMARY KEY (app, name))",
+        MIGRATIONS_TABLE
+    ))
+    .await?;
+    let done: Vec<(String, String)> = db
+        .fetch_all(&format!("SELECT app, name FROM {}", MIGRATIONS_TABLE))
+        .await?;
+
+    let mut applied = Vec::new();
+    for app in apps {
+        for model in &app.models {
+            db.execute(&(model.create_sql)(db.backend())).await?;
+            db.create_indexes(&model.indexes).await?;
+        }
+        for migration in &app.migrations {
+            if done
+                .iter()
+                .any(|(a, n)| *a == app.name && *n == migration.name)
+            {
+                continue;
+            }
+            log::info!("applying migration {}.{}", app.name, migration.name);
+            let record = format!(
+                "INSERT INTO {} (app, name, applied_at) VALUES (?, ?, ?)",
+                MIGRATIONS_TABLE
+            );
+            let values = vec![
+                SqlValue::Text(app.name.clone()),
+                SqlValue::Text(migration.name.clone()),
+                SqlValue::Text(crate::clock::now().to_rfc3339()),
+            ];
+            // The migration and its record go in one transaction, except where
+            // DDL cannot be rolled back (MySQL commits it implicitly) or must
+            // run outside one (Postgres `CREATE INDEX CONCURRENTLY`)
+            let transactional = db.backend() != Backend::MySql
+                && !migration.sql.to_ascii_uppercase().contains("CONCURRENTLY");
+            if transactional {
+                db.execute_atomic(&[
+                    (migration.sql.as_str(), Vec::new()),
+                    (record.as_str(), values),
+                ])
+                .await?;
+            } else {
+                db.execute(&migration.sql).await?;
+                db.execute_with(&record, values).await?;
+            }
+            applied.push(format!("{}.{}", app.name, migration.name));
+        }
+    }
+    Ok(applied)
+}
@@ -0,0 +1,85 @@
+//! `cobalto-admin`: create Cobalto projects and apps.
+//!
+//! Built with `cargo install cobalto --features cli`.
+
+use cobalto::scaffold::{EXAMPLES, start_app, start_example, start_project};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+  cobalto-admin startproject <name> [directory]   create project <name> in [directory]/<name>
+  cobalto-admin startproject <name> [directory] --example <example>
+                                                  create it from a complete example (blog)
+  cobalto-admin startapp <name> [project]         add app <name> to the project (default: .)";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let example = match args.iter().position(|arg| arg == "--example") {
+        Some(i) if i + 1 < args.len() => {
+            let example = args.remove(i + 1);
+            args.remove(i);
+            Some(example)
+        }
+        Some(_) => {
+            eprintln!("--example needs one of: {}\n\n{}", EXAMPLES.join(", "), USAGE);
+            return ExitCode::from(2);
+        }
+        None => None,
+    };
+    let (command, name, dir) = match args.as_slice() {
+        [command, name] => (command.as_str(), name.as_str(), PathBuf::from(".")),
+        [command, name, dir] => (command.as_str(), name.as_str(), PathBuf::from(dir)),
+        [help] if help == "-h" || help == "--help" || help == "help" => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let result = match command {
+        "startproject" => match &example {
+            Some(example) => start_example(example, name, &dir),
+            None => start_project(name, &dir),
+        },
+        "startapp" if example.is_none() => start_app(name, &dir),
+        "startapp" => {
+            eprintln!("--example only applies to startproject\n\n{}", USAGE);
+            return ExitCode::from(2);
+        }
+        other => {
+            eprintln!("unknown command '{}'\n\n{}", other, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(files) => {
+            for file in &files {
+                println!("  created {}", file.display());
+            }
+            match command {
+                "startproject" => println!(
+                    "\nProject '{name}' created. Run it with:\n  cd {} && cargo run -- migrate && cargo run{}",
+                    dir.join(name).display(),
+                    if example.is_some() {
+                        "\nSet admin_password in settings.toml to log in; `cargo test` runs its tests."
+                    } else {
+                        ""
+                    }
+                ),
+                _ => println!(
+                    "\nApp '{name}' created. Add `mod {name};` and \
+                     `router.mount({name}::app());` to src/main.rs, then run \
+                     `cargo run -- migrate`."
+                ),
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Cobalto cache
+//!
+//! A process-local key/value cache with per-entry TTLs (read through
+//! `clock::now()`) and surrogate keys: entries can be tagged (e.g. `post:42`,
+//! `template:page.html`) and every entry carrying a tag is dropped at once with
+//! `invalidate_tag`, typically from a model-save hook. Expired entries are
+//! dropped on the next write; a cache built with `Cache::with_capacity` also
+//! evicts the least recently used entry when full.
+//!
+//! Code that should work with any backend goes through the `CacheStore` trait,
+//! implemented by `Cache` and, with the `redis` feature, by `RedisCache`:
+//!
+//! ```ignore
+//! let store: Arc<dyn CacheStore> = Arc::new(RedisCache::connect("redis://127.0.0.1/").await?);
+//! let stats = cached(&*store, "stats", Some(Duration::from_secs(60)), || async {
+//!     compute_stats().await
+//! })
+//! .await;
+//! router.add_route("/reports", cache_page_with(store, Duration::from_secs(300), reports));
+//! ```
+
+use crate::clock;
+use crate::router::{Handler, Headers, Request, Response};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+struct Entry {
+    value: String,
+    expires_at: Option<DateTime<Utc>>,
+    tags: Vec<String>,
+    /// Tick of the last read or write, for LRU eviction
+    last_used: AtomicU64,
+    /// Tick the entry is filed under in `Inner::lru`
+    queued: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    tags: HashMap<String, HashSet<String>>,
+    tick: AtomicU64,
+    capacity: Option<usize>,
+    /// Keys by the tick they were filed at, oldest first. Reads only bump
+    /// `Entry::last_used` (under the read lock); eviction refiles an entry
+    /// used since it was filed instead of dropping it.
+    lru: BTreeMap<u64, String>,
+    /// Entries with a TTL by expiry, dropped on the next write once expired
+    expiry: BTreeSet<(DateTime<Utc>, String)>,
+}
+
+/// In-memory cache handle (cheap to clone).
+#[derive(Clone, Default)]
+pub struct Cache {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache holding at most `max_entries`, dropping the least recently
+    /// used entry to make room.
+    pub fn with_capacity(max_entries: usize) -> Self {
+        let cache = Self::default();
+        cache.inner.write().unwrap().capacity = Some(max_entries.max(1));
+        cache
+    }
+
+    /// Get a live value.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let inner = self.inner.read().unwrap();
+        let entry = inner.entries.get(key)?;
+        match entry.expires_at {
+            Some(at) if at <= clock::now() => None,
+            _ => {
+                let tick = inner.tick.fetch_add(1, Ordering::Relaxed);
+                entry.last_used.store(tick, Ordering::Relaxed);
+                Some(entry.value.clone())
+            }
+        }
+    }
+
+    /// Store a value; `ttl = None` keeps it until evicted.
+    pub fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        self.set_tagged(key, value, ttl, &[]);
+    }
+
+    /// Store a value under surrogate keys.
+    pub fn set_tagged(&self, key: &str, value: String, ttl: Option<Duration>, tags: &[&str]) {
+        let expires_at =
+            ttl.and_then(|t| chrono::Duration::from_std(t).ok().map(|d| clock::now() + d));
+        let mut inner = self.inner.write().unwrap();
+        Self::purge_expired_locked(&mut inner);
+        Self::remove_locked(&mut inner, key);
+        if let Some(at) = expires_at {
+            inner.expiry.insert((at, key.to_string()));
+        }
+        for tag in tags {
+            inner
+                .tags
+                .entry(tag.to_string())
+                .or_default()
+                .insert(key.to_string());
+        }
+        let tick = inner.tick.fetch_add(1, Ordering::Relaxed);
+        inner.lru.insert(tick, key.to_string());
+        inner.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires_at,
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                last_used: AtomicU64::new(tick),
+                queued: tick,
+            },
+        );
+        if let Some(capacity) = inner.capacity {
+            Self::evict_locked(&mut inner, capacity);
+        }
+    }
+
+    /// The live value of `key`, or the value of `f` after storing it.
+    pub fn get_or_set<F>(&self, key: &str, ttl: Option<Duration>, f: F) -> String
+    where
+        F: FnOnce() -> String,
+    {
+        if let Some(value) = self.get(key) {
+            return value;
+        }
+        let value = f();
+        self.set(key, value.clone(), ttl);
+        value
+    }
+
+    fn remove_locked(inner: &mut Inner, key: &str) {
+        if let Some(entry) = inner.entries.remove(key) {
+            inner.lru.remove(&entry.queued);
+            if let Some(at) = entry.expires_at {
+                inner.expiry.remove(&(at, key.to_string()));
+            }
+            for tag in entry.tags {
+                if let Some(keys) = inner.tags.get_mut(&tag) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        inner.tags.remove(&tag);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop the entries whose TTL has run out.
+    fn purge_expired_locked(inner: &mut Inner) {
+        let now = clock::now();
+        while let Some((at, key)) = inner.expiry.first().cloned()
+            && at <= now
+        {
+            Self::remove_locked(inner, &key);
+        }
+    }
+
+    /// Drop least recently used entries down to `capacity`.
+    fn evict_locked(inner: &mut Inner, capacity: usize) {
+        while inner.entries.len() > capacity {
+            let Some((tick, key)) = inner.lru.pop_first() else {
+                break;
+            };
+            match inner.entries.get_mut(&key) {
+                Some(entry) if *entry.last_used.get_mut() > tick => {
+                    let used = *entry.last_used.get_mut();
+                    entry.queued = used;
+                    inner.lru.insert(used, key);
+                }
+                _ => Self::remove_locked(inner, &key),
+            }
+        }
+    }
+
+    /// Drop expired entries now rather than on the next write, e.g. from a
+    /// periodic task after a burst of short-lived keys.
+    pub fn purge_expired(&self) {
+        Self::purge_expired_locked(&mut self.inner.write().unwrap());
+    }
+
+    /// Number of stored entries, expired ones not yet purged included.
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove a single key.
+    pub fn delete(&self, key: &str) {
+        Self::remove_locked(&mut self.inner.write().unwrap(), key);
+    }
+
+    /// Remove every entry tagged with `tag`; returns how many were dropped.
+    pub fn invalidate_tag(&self, tag: &str) -> usize {
+        let mut inner = self.inner.write().unwrap();
+        let keys = inner.tags.remove(tag).unwrap_or_default();
+        for key in &keys {
+            Self::remove_locked(&mut inner, key);
+        }
+        keys.len()
+    }
+
+    /// Drop every entry.
+    pub fn clear(&self) {
+        let mut inner = self.inner.write().unwrap();
+        inner.entries.clear();
+        inner.tags.clear();
+        inner.lru.clear();
+        inner.expiry.clear();
+    }
+}
+
+static DEFAULT: Lazy<Cache> = Lazy::new(Cache::new);
+
+/// The process-wide default cache.
+pub fn default_cache() -> Cache {
+    DEFAULT.clone()
+}
+
+/// A cache backend. Failures of remote backends are logged and treated as
+/// misses, so a cache outage never fails a request.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    /// A live value.
+    async fn get(&self, key: &str) -> Option<String>;
+    /// Store a value; `ttl = None` keeps it until evicted.
+    async fn set(&self, key: &str, value: String, ttl: Option<Duration>);
+    async fn delete(&self, key: &str);
+}
+
+#[async_trait]
+impl CacheStore for Cache {
+    async fn get(&self, key: &str) -> Option<String> {
+        Cache::get(self, key)
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        Cache::set(self, key, value, ttl)
+    }
+
+    async fn delete(&self, key: &str) {
+        Cache::delete(self, key)
+    }
+}
+
+/// The live value of `key` in `store`, or the output of `f` after storing it.
+pub async fn cached<F, Fut>(
+    store: &dyn CacheStore,
+    key: &str,
+    ttl: Option<Duration>,
+    f: F,
+) -> String
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = String>,
+{
+    if let Some(value) = store.get(key).await {
+        return value;
+    }
+    let value = f().await;
+    store.set(key, value.clone(), ttl).await;
+    value
+}
+
+/// Cache key of `request` for `cache_page`; `None` for requests that are not
+/// GET or HEAD or that carry credentials, whose responses may be personal.
+fn page_key(request: &Request) -> Option<String> {
+    if !matches!(request.method(), "GET" | "HEAD")
+        || request.headers().contains("authorization")
+        || request.headers().contains("cookie")
+    {
+        return None;
+    }
+    let mut query: Vec<_> = request.query.iter().collect();
+    query.sort();
+    let query: Vec<String> = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    Some(format!("view:{}?{}", request.path(), query.join("&")))
+}
+
+/// Key of the variant of `key` selected by `vary` (lowercased header names).
+fn variant_key(key: &str, vary: &[String], headers: &Headers) -> String {
+    vary.iter().fold(key.to_string(), |key, name| {
+        format!("{}|{}={}", key, name, headers.get(name).unwrap_or(""))
+    })
+}
+
+/// Headers named by the response's `Vary`, lowercased, or `None` when the
+/// response must not be shared: not a 200 with a text body, setting cookies,
+/// `Cache-Control: private`, `no-store` or `no-cache`, or `Vary: *`.
+fn shared_vary(response: &Response) -> Option<Vec<String>> {
+    let header = |name: &str| {
+        response
+            .headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+            .flat_map(|(_, v)| v.split(','))
+            .map(|v| v.trim().to_ascii_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>()
+    };
+    let private = header("cache-control").iter().any(|directive| {
+        matches!(directive.as_str(), "private" | "no-store" | "no-cache")
+            || directive.starts_with("private=")
+            || directive.starts_with("no-cache=")
+    });
+    let mut vary = header("vary");
+    if response.status != 200
+        || response.binary.is_some()
+        || response.stream.is_some()
+        || !response.cookies().is_empty()
+        || private
+        || vary.iter().any(|name| name == "*")
+    {
+        return None;
+    }
+    vary.sort();
+    vary.dedup();
+    Some(vary)
+}
+
+/// Wrap `handler` so its successful GET responses are served from the
+/// default cache for `ttl`.
+pub fn cache_page(ttl: Duration, handler: Handler) -> Handler {
+    cache_page_with(Arc::new(default_cache()), ttl, handler)
+}
+
+/// `cache_page` with another store, e.g. Redis shared between instances.
+///
+/// Requests with an `Authorization` or `Cookie` header are never served from
+/// the cache. Only 200 responses with a text body, no `Set-Cookie` and no
+/// `Cache-Control: private`, `no-store` or `no-cache` are stored, one copy
+/// per value of the request headers their `Vary` names.
+pub fn cache_page_with(store: Arc<dyn CacheStore>, ttl: Duration, handler: Handler) -> Handler {
+    Arc::new(move |request| {
+        let handler = handler.clone();
+        let store = store.clone();
+        Box::pin(async move {
+            let Some(key) = page_key(&request) else {
+                return handler(request).await;
+            };
+            // `key` holds the `Vary` names of the page, its variants the pages
+            if let Some(vary) = store.get(&key).await
+                && let Ok(vary) = serde_json::from_str::<Vec<String>>(&vary)
+                && let Some(cached) = store
+                    .get(&variant_key(&key, &vary, request.headers()))
+                    .await
+                && let Ok((headers, body)) =
+                    serde_json::from_str::<(HashMap<String, String>, String)>(&cached)
+            {
+                return Response {
+                    headers,
+                    ..Response::html(body)
+                };
+            }
+            let headers = request.headers().clone();
+            let response = handler(request).await;
+            if let Some(vary) = shared_vary(&response)
+                && let Ok(value) = serde_json::to_string(&(&response.headers, &response.body))
+            {
+                let variant = variant_key(&key, &vary, &headers);
+                store.set(&variant, value, Some(ttl)).await;
+                store
+                    .set(
+                        &key,
+                        serde_json::to_string(&vary).unwrap_or_default(),
+                        Some(ttl),
+                    )
+                    .await;
+            }
+            response
+        })
+    })
+}
+
+/// Redis-backed store, shared by every instance of the application.
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct RedisCache {
+    conn: redis::aio::ConnectionManager,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisCache {
+    /// Connect to `url` (`redis://host:port/db`).
+    pub async fn connect(url: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        Ok(RedisCache {
+            conn: redis::aio::ConnectionManager::new(client).await?,
+            prefix: "cobalto:".to_string(),
+        })
+    }
+
+    /// Builder for the prefix put before every key (default `cobalto:`)
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    fn warn(op: &str, e: redis::RedisError) {
+        log::warn!("redis cache {} failed: {e}", op);
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl CacheStore for RedisCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        let mut conn = self.conn.clone();
+        redis::cmd("GET")
+            .arg(format!("{}{}", self.prefix, key))
+            .query_async(&mut conn)
+            .await
+            .unwrap_or_else(|e| {
+                Self::warn("get", e);
+                None
+            })
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Option<Duration>) {
+        let mut conn = self.conn.clone();
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(format!("{}{}", self.prefix, key)).arg(value);
+        if let Some(ttl) = ttl {
+            cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+        }
+        let result: redis::RedisResult<()> = cmd.query_async(&mut conn).await;
+        if let Err(e) = result {
+            Self::warn("set", e);
+        }
+    }
+
+    async fn delete(&self, key: &str) {
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<()> = redis::cmd("DEL")
+            .arg(format!("{}{}", self.prefix, key))
+            .query_async(&mut conn)
+            .await;
+        if let Err(e) = result {
+            Self::warn("delete", e);
+        }
+    }
+}
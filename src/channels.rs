@@ -0,0 +1,441 @@
+//! Cobalto channels
+//!
+//! A declarative policy layer over the pub/sub hub. Policies are registered per
+//! channel pattern (`"room:*"` matches every channel starting with `room:`) and
+//! are evaluated when a client joins or publishes, so room access control lives
+//! in one place instead of being scattered across websocket handlers.
+//!
+//! ```ignore
+//! channels.authorize("room:*", |ctx, room| ctx.user.is_some() && room != "admins");
+//! ```
+//!
+//! The channel layer groups connections by name, Django Channels style: each
+//! websocket handler opens a `Connection`, subscribes it to groups, and
+//! relays what it receives, while any code can broadcast to a group.
+//!
+//! ```ignore
+//! router.add_websocket("/ws/room/:id", Arc::new(|ctx, mut ws| Box::pin(async move {
+//!     let mut conn = channels::connect();
+//!     conn.subscribe(&format!("room:{}", ctx.params["id"]));
+//!     loop {
+//!         tokio::select! {
+//!             Some(WsMessage::Text(text)) = ws.recv() => { /* ... */ }
+//!             Some(message) = conn.recv() => { let _ = ws.send_text(message).await; }
+//!             else => break,
+//!         }
+//!     }
+//! })));
+//!
+//! channels::group("room:42").broadcast("hello");
+//! ```
+//!
+//! The default layer is in-process (`InMemoryLayer`): every connection reads
+//! a bounded `PubSub` topic, so a slow client never holds more than the
+//! topic capacity and overflow follows the topic's `Backpressure` policy.
+//! `set_layer` installs another `ChannelLayer`, e.g. one backed by Redis for
+//! several processes.
+
+use crate::pubsub::{PubSub, Subscription, TopicConfig};
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Who is asking, as known by the websocket handler.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelContext {
+    pub user: Option<String>,
+    pub params: HashMap<String, String>,
+}
+
+/// Operation being authorized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelAction {
+    Join,
+    Publish,
+}
+
+/// Policy callback: receives the context and the part of the channel name
+/// matched by `*` (or the whole name for exact patterns).
+pub type ChannelPolicy = Arc<dyn Fn(&ChannelContext, &str) -> bool + Send + Sync>;
+
+struct Rule {
+    pattern: String,
+    action: Option<ChannelAction>,
+    policy: ChannelPolicy,
+}
+
+/// Returned when a policy refuses access.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChannelDenied {
+    pub channel: String,
+    pub action: ChannelAction,
+}
+
+impl fmt::Display for ChannelDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} on channel '{}' denied", self.action, self.channel)
+    }
+}
+
+impl std::error::Error for ChannelDenied {}
+
+/// Authorized access to the pub/sub hub.
+#[derive(Clone)]
+pub struct Channels {
+    hub: PubSub,
+    rules: Vec<Arc<Rule>>,
+    /// Whether channels matched by no policy are open (defaults to `false`).
+    pub allow_unmatched: bool,
+}
+
+impl Channels {
+    pub fn new(hub: PubSub) -> Self {
+        Channels {
+            hub,
+            rules: Vec::new(),
+            allow_unmatched: false,
+        }
+    }
+
+    fn push_rule<F>(&mut self, pattern: &str, action: Option<ChannelAction>, f: F) -> &mut Self
+    where
+        F: Fn(&ChannelContext, &str) -> bool + Send + Sync + 'static,
+    {
+        self.rules.push(Arc::new(Rule {
+            pattern: pattern.to_string(),
+            action,
+            policy: Arc::new(f),
+        }));
+        self
+    }
+
+    /// Policy applied to both join and publish.
+    pub fn authorize<F>(&mut self, pattern: &str, f: F) -> &mut Self
+    where
+        F: Fn(&ChannelContext, &str) -> bool + Send + Sync + 'static,
+    {
+        self.push_rule(pattern, None, f)
+    }
+
+    /// Policy applied to joins only.
+    pub fn authorize_join<F>(&mut self, pattern: &str, f: F) -> &mut Self
+    where
+        F: Fn(&ChannelContext, &str) -> bool + Send + Sync + 'static,
+    {
+        self.push_rule(pattern, Some(ChannelAction::Join), f)
+    }
+
+    /// Policy applied to publishes only.
+    pub fn authorize_publish<F>(&mut self, pattern: &str, f: F) -> &mut Self
+    where
+        F: Fn(&ChannelContext, &str) -> bool + Send + Sync + 'static,
+    {
+        self.push_rule(pattern, Some(ChannelAction::Publish), f)
+    }
+
+    /// Evaluate every matching policy; all of them must allow the action.
+    pub fn is_allowed(&self, ctx: &ChannelContext, channel: &str, action: ChannelAction) -> bool {
+        let mut matched = false;
+        for rule in &self.rules {
+            if rule.action.is_some_and(|a| a != action) {
+                continue;
+            }
+            if let Some(captured) = match_channel(&rule.pattern, channel) {
+                matched = true;
+                if !(rule.policy)(ctx, captured) {
+                    return false;
+                }
+            }
+        }
+        matched || self.allow_unmatched
+    }
+
+    /// Join a channel if the policies allow it.
+    pub fn join(&self, ctx: &ChannelContext, channel: &str) -> Result<Subscription, ChannelDenied> {
+        if !self.is_allowed(ctx, channel, ChannelAction::Join) {
+            return Err(ChannelDenied {
+                channel: channel.to_string(),
+                action: ChannelAction::Join,
+            });
+        }
+        Ok(self.hub.subscribe(channel))
+    }
+
+    /// Publish on a channel if the policies allow it.
+    pub fn publish<M: Into<String>>(
+        &self,
+        ctx: &ChannelContext,
+        channel: &str,
+        message: M,
+    ) -> Result<bool, ChannelDenied> {
+        if !self.is_allowed(ctx, channel, ChannelAction::Publish) {
+            return Err(ChannelDenied {
+                channel: channel.to_string(),
+                action: ChannelAction::Publish,
+            });
+        }
+        Ok(self.hub.publish(channel, message))
+    }
+
+    /// Subscribe `connection` to `group` if the join policies allow it.
+    pub fn join_group(
+        &self,
+        ctx: &ChannelContext,
+        connection: &mut Connection,
+        group: &str,
+    ) -> Result<(), ChannelDenied> {
+        if !self.is_allowed(ctx, group, ChannelAction::Join) {
+            return Err(ChannelDenied {
+                channel: group.to_string(),
+                action: ChannelAction::Join,
+            });
+        }
+        connection.subscribe(group);
+        Ok(())
+    }
+}
+
+/// Match `channel` against `pattern`, returning the part captured by a
+/// trailing `*` (or the whole channel for exact patterns).
+fn match_channel<'a>(pattern: &str, channel: &'a str) -> Option<&'a str> {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => channel.strip_prefix(prefix),
+        None => (pattern == channel).then_some(channel),
+    }
+}
+
+/// Delivers messages to named connections and the groups they belong to.
+///
+/// Methods never block: an implementation talking to an external broker
+/// hands the work to a background task.
+pub trait ChannelLayer: Send + Sync {
+    /// Open a connection, returning its unique name and its message receiver.
+    fn open(&self) -> (String, Subscription);
+
+    /// Drop a connection and its group memberships.
+    fn close(&self, channel: &str);
+
+    fn group_add(&self, group: &str, channel: &str);
+
+    fn group_discard(&self, group: &str, channel: &str);
+
+    /// Send to one connection. Returns `false` if it is gone or its queue
+    /// refused the message.
+    fn send(&self, channel: &str, message: String) -> bool;
+
+    /// Send to every connection of `group`, returning how many queued it.
+    fn group_send(&self, group: &str, message: String) -> usize;
+
+    /// Number of connections in `group`.
+    fn group_size(&self, group: &str) -> usize;
+}
+
+#[derive(Default)]
+struct LayerState {
+    channels: HashSet<String>,
+    groups: HashMap<String, HashSet<String>>,
+}
+
+/// Channel layer for a single process (the default). Each connection reads
+/// its own topic of a `PubSub` hub.
+#[derive(Default)]
+pub struct InMemoryLayer {
+    hub: PubSub,
+    state: RwLock<LayerState>,
+    next_id: AtomicU64,
+}
+
+impl InMemoryLayer {
+    /// Layer whose connection queues use the `PubSub` defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Layer whose connection queues use `config` (capacity and overflow
+    /// policy).
+    pub fn with_config(config: TopicConfig) -> Self {
+        InMemoryLayer {
+            hub: PubSub::with_defaults(config),
+            ..Self::default()
+        }
+    }
+
+    /// Queue `message` for the open connection `channel`, under the state
+    /// lock so a concurrent `close` cannot revive its topic.
+    fn deliver(&self, state: &LayerState, channel: &str, message: String) -> bool {
+        state.channels.contains(channel) && self.hub.publish(channel, message)
+    }
+}
+
+impl ChannelLayer for InMemoryLayer {
+    fn open(&self) -> (String, Subscription) {
+        let name = format!("conn.{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let mut state = self.state.write().unwrap();
+        let receiver = self.hub.subscribe(&name);
+        state.channels.insert(name.clone());
+        (name, receiver)
+    }
+
+    fn close(&self, channel: &str) {
+        let mut state = self.state.write().unwrap();
+        state.channels.remove(channel);
+        state.groups.retain(|_, members| {
+            members.remove(channel);
+            !members.is_empty()
+        });
+        self.hub.remove(channel);
+    }
+
+    fn group_add(&self, group: &str, channel: &str) {
+        self.state
+            .write()
+            .unwrap()
+            .groups
+            .entry(group.to_string())
+            .or_default()
+            .insert(channel.to_string());
+    }
+
+    fn group_discard(&self, group: &str, channel: &str) {
+        let mut state = self.state.write().unwrap();
+        if let Some(members) = state.groups.get_mut(group) {
+            members.remove(channel);
+            if members.is_empty() {
+                state.groups.remove(group);
+            }
+        }
+    }
+
+    fn send(&self, channel: &str, message: String) -> bool {
+        let state = self.state.read().unwrap();
+        self.deliver(&state, channel, message)
+    }
+
+    fn group_send(&self, group: &str, message: String) -> usize {
+        let state = self.state.read().unwrap();
+        state
+            .groups
+            .get(group)
+            .into_iter()
+            .flatten()
+            .filter(|member| self.deliver(&state, member, message.clone()))
+            .count()
+    }
+
+    fn group_size(&self, group: &str) -> usize {
+        self.state
+            .read()
+            .unwrap()
+            .groups
+            .get(group)
+            .map_or(0, HashSet::len)
+    }
+}
+
+static LAYER: Lazy<RwLock<Arc<dyn ChannelLayer>>> =
+    Lazy::new(|| RwLock::new(Arc::new(InMemoryLayer::new())));
+
+/// Replace the process-wide channel layer. Open connections stay on the old one.
+pub fn set_layer(layer: Arc<dyn ChannelLayer>) {
+    *LAYER.write().unwrap() = layer;
+}
+
+/// The process-wide channel layer.
+pub fn layer() -> Arc<dyn ChannelLayer> {
+    LAYER.read().unwrap().clone()
+}
+
+/// A named group of connections on a channel layer.
+#[derive(Clone)]
+pub struct Group {
+    pub name: String,
+    layer: Arc<dyn ChannelLayer>,
+}
+
+impl Group {
+    /// Send `message` to every connection in the group, returning how many
+    /// queued it.
+    pub fn broadcast<M: Into<String>>(&self, message: M) -> usize {
+        self.layer.group_send(&self.name, message.into())
+    }
+
+    /// Number of connections in the group.
+    pub fn size(&self) -> usize {
+        self.layer.group_size(&self.name)
+    }
+}
+
+/// Group `name` on the process-wide layer.
+pub fn group(name: &str) -> Group {
+    Group {
+        name: name.to_string(),
+        layer: layer(),
+    }
+}
+
+/// One client's membership in the channel layer; leaves all its groups
+/// when dropped.
+pub struct Connection {
+    name: String,
+    receiver: Subscription,
+    groups: Vec<String>,
+    layer: Arc<dyn ChannelLayer>,
+}
+
+impl Connection {
+    /// Open a connection on `layer`.
+    pub fn open(layer: Arc<dyn ChannelLayer>) -> Self {
+        let (name, receiver) = layer.open();
+        Connection {
+            name,
+            receiver,
+            groups: Vec::new(),
+            layer,
+        }
+    }
+
+    /// Unique name, for `ChannelLayer::send` from elsewhere.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Groups joined, in order.
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    pub fn subscribe(&mut self, group: &str) {
+        if !self.groups.iter().any(|g| g == group) {
+            self.layer.group_add(group, &self.name);
+            self.groups.push(group.to_string());
+        }
+    }
+
+    pub fn unsubscribe(&mut self, group: &str) {
+        self.layer.group_discard(group, &self.name);
+        self.groups.retain(|g| g != group);
+    }
+
+    /// Next message sent to this connection or one of its groups.
+    pub async fn recv(&mut self) -> Option<String> {
+        self.receiver.recv().await
+    }
+
+    /// Non-blocking receive.
+    pub fn try_recv(&mut self) -> Option<String> {
+        self.receiver.try_recv()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.layer.close(&self.name);
+    }
+}
+
+/// Open a connection on the process-wide layer.
+pub fn connect() -> Connection {
+    Connection::open(layer())
+}
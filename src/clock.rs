@@ -0,0 +1,87 @@
+//! Cobalto uniform clock
+//!
+//! Every time-dependent part of the framework (throttling windows, sessions,
+//! tokens, scheduling, ORM timestamps) reads the time through `clock::now()`
+//! instead of the system clock directly. Tests can freeze or advance time per
+//! thread with `cobalto::test::freeze_time`, making expiry logic deterministic.
+
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real wall clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that always returns the same instant.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+static GLOBAL: Lazy<RwLock<Arc<dyn Clock>>> = Lazy::new(|| RwLock::new(Arc::new(SystemClock)));
+
+thread_local! {
+    static FROZEN: RefCell<Option<DateTime<Utc>>> = const { RefCell::new(None) };
+}
+
+/// Replace the process-wide clock.
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *GLOBAL.write().unwrap() = clock;
+}
+
+/// Current time: the frozen time of this thread if any, else the global clock.
+pub fn now() -> DateTime<Utc> {
+    FROZEN
+        .with(|f| *f.borrow())
+        .unwrap_or_else(|| GLOBAL.read().unwrap().now())
+}
+
+/// Restores the previous frozen time of the thread when dropped.
+pub struct FreezeGuard {
+    previous: Option<DateTime<Utc>>,
+}
+
+impl FreezeGuard {
+    /// Move the frozen time forward.
+    pub fn advance(&self, by: Duration) {
+        FROZEN.with(|f| {
+            let mut f = f.borrow_mut();
+            *f = f.map(|t| t + by);
+        });
+    }
+
+    /// Jump to an absolute instant.
+    pub fn set(&self, at: DateTime<Utc>) {
+        FROZEN.with(|f| *f.borrow_mut() = Some(at));
+    }
+}
+
+impl Drop for FreezeGuard {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        FROZEN.with(|f| *f.borrow_mut() = previous);
+    }
+}
+
+/// Freeze `now()` on the current thread until the guard is dropped.
+pub fn freeze_time(at: DateTime<Utc>) -> FreezeGuard {
+    let previous = FROZEN.with(|f| f.borrow_mut().replace(at));
+    FreezeGuard { previous }
+}
@@ -0,0 +1,138 @@
+//! Cobalto request coalescing
+//!
+//! An opt-in wrapper for expensive GET endpoints: identical requests arriving
+//! while one is already in flight wait for that execution and share its
+//! response instead of running the handler again, so a burst of cache misses
+//! (a thundering herd) costs a single computation.
+//!
+//! Only GET and HEAD requests are coalesced by default, keyed on the caller's
+//! `Authorization` and `Cookie` headers too, so responses never cross users.
+//! A response that sets cookies or streams its body is not shared: waiters run
+//! the handler themselves.
+
+use crate::router::{Handler, Request, Response};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Builds the coalescing key of a request; `None` opts the request out.
+pub type KeyResolver = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// Coalescing policy; each wrapped handler gets its own in-flight table.
+#[derive(Clone)]
+pub struct Coalesce {
+    key: KeyResolver,
+}
+
+impl Default for Coalesce {
+    fn default() -> Self {
+        Coalesce {
+            key: Arc::new(default_key),
+        }
+    }
+}
+
+impl Coalesce {
+    /// Coalesce GET and HEAD requests on path, parameters, query string and
+    /// the caller's credentials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder for a custom key (e.g. to add the user id so users never share responses)
+    pub fn with_key<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Wrap a handler so that identical concurrent requests share one execution.
+    pub fn wrap(&self, handler: Handler) -> Handler {
+        let key_fn = self.key.clone();
+        let in_flight: InFlightTable = Arc::default();
+        Arc::new(move |req| {
+            let key_fn = key_fn.clone();
+            let handler = handler.clone();
+            let in_flight = in_flight.clone();
+            Box::pin(async move {
+                let Some(key) = key_fn(&req) else {
+                    return handler(req).await;
+                };
+                let waiter = {
+                    let mut table = in_flight.lock().unwrap();
+                    match table.get(&key) {
+                        Some(tx) => Some(tx.subscribe()),
+                        None => {
+                            table.insert(key.clone(), broadcast::channel(1).0);
+                            None
+                        }
+                    }
+                };
+                if let Some(mut rx) = waiter {
+                    // The leading request was cancelled: run our own
+                    return match rx.recv().await {
+                        Ok(resp) => resp,
+                        Err(_) => handler(req).await,
+                    };
+                }
+                let _guard = InFlight {
+                    table: in_flight.clone(),
+                    key: key.clone(),
+                };
+                let resp = handler(req).await;
+                // Dropping the sender without sending makes the waiters run their own
+                if let Some(tx) = in_flight.lock().unwrap().remove(&key)
+                    && shareable(&resp)
+                {
+                    let _ = tx.send(resp.clone());
+                }
+                resp
+            })
+        })
+    }
+}
+
+type InFlightTable = Arc<Mutex<HashMap<String, broadcast::Sender<Response>>>>;
+
+/// Clears the entry of a leading request, even when its future is dropped.
+struct InFlight {
+    table: InFlightTable,
+    key: String,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut table) = self.table.lock() {
+            table.remove(&self.key);
+        }
+    }
+}
+
+/// Whether `resp` may be handed to other callers: not user-specific through
+/// `Set-Cookie`, and not a stream only one of them could read.
+fn shareable(resp: &Response) -> bool {
+    resp.cookies().is_empty() && resp.stream.is_none()
+}
+
+/// Method, path, `params`, `query` (in a stable order) and credentials of a
+/// GET or HEAD request.
+fn default_key(req: &Request) -> Option<String> {
+    if !matches!(req.method(), "GET" | "HEAD") {
+        return None;
+    }
+    let mut params: Vec<_> = req.params.iter().collect();
+    params.sort();
+    let mut query: Vec<_> = req.query.iter().collect();
+    query.sort();
+    Some(format!(
+        "{} {} {:?}?{:?} {:?} {:?}",
+        req.method(),
+        req.path(),
+        params,
+        query,
+        req.header("authorization"),
+        req.header("cookie")
+    ))
+}
@@ -0,0 +1,225 @@
+//! Cobalto response compression
+//!
+//! Responses are Brotli-, gzip- or deflate-encoded on the way out when the client's
+//! `Accept-Encoding` allows it and the body is at least `compression.min_bytes`
+//! long. Streamed bodies, responses that already carry a `Content-Encoding`
+//! and content types that are compressed already (images, video, archives,
+//! fonts, PDFs) are sent as they are, as are partial (206) and unsatisfiable
+//! range (416) responses, whose byte ranges refer to the identity body. A
+//! strong `ETag` is weakened on an encoded response, since its bytes differ.
+//!
+//! Brotli runs at `BROTLI_QUALITY`, a level fast enough to encode per request.
+
+use crate::router::Response;
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use once_cell::sync::Lazy;
+use std::io::Write;
+use std::sync::RwLock;
+
+/// Compression settings: `[compression]` in the settings file,
+/// `COBALTO_COMPRESSION__MIN_BYTES`, ...
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressionSettings {
+    pub enabled: bool,
+    /// Smaller bodies are sent uncompressed.
+    pub min_bytes: usize,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        CompressionSettings {
+            enabled: true,
+            min_bytes: 1024,
+        }
+    }
+}
+
+/// Brotli level used for responses (0-11); higher levels cost too much
+/// time per request.
+pub const BROTLI_QUALITY: u32 = 4;
+
+/// A content coding the server can produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    /// The zlib format, which is what HTTP calls `deflate`
+    Deflate,
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// `body` encoded with this coding.
+    pub fn encode(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, 22);
+                encoder.write_all(body)?;
+                // Finishes the stream
+                Ok(encoder.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+static SETTINGS: Lazy<RwLock<CompressionSettings>> =
+    Lazy::new(|| RwLock::new(CompressionSettings::default()));
+
+/// Use `settings` for every response (done by `Router::new`).
+pub fn configure(settings: &CompressionSettings) {
+    *SETTINGS.write().unwrap() = settings.clone();
+}
+
+/// The preferred encoding allowed by an `Accept-Encoding` header, Brotli then
+/// gzip first on equal quality. `*` stands for the codings not listed, so
+/// `br;q=0, gzip;q=0, *` allows deflate only.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut listed: Vec<(Encoding, f32)> = Vec::new();
+    let mut wildcard = None;
+    for part in accept_encoding.split(',') {
+        let mut params = part.split(';');
+        let coding = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality: f32 = params
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse().ok())
+            .unwrap_or(1.0);
+        let encoding = match coding.as_str() {
+            "br" => Encoding::Brotli,
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            "deflate" => Encoding::Deflate,
+            "*" => {
+                wildcard.get_or_insert(quality);
+                continue;
+            }
+            _ => continue,
+        };
+        if !listed.iter().any(|(e, _)| *e == encoding) {
+            listed.push((encoding, quality));
+        }
+    }
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate] {
+        let quality = listed
+            .iter()
+            .find(|(e, _)| *e == encoding)
+            .map(|(_, q)| *q)
+            .or(wildcard);
+        if let Some(quality) = quality
+            && quality > 0.0
+            && best.is_none_or(|(_, q)| quality > q)
+        {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Whether bodies of `content_type` are worth compressing.
+fn compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if mime == "image/svg+xml" {
+        return true;
+    }
+    let precompressed = ["image/", "video/", "audio/", "font/woff"];
+    let archives = [
+        "application/zip",
+        "application/gzip",
+        "application/x-gzip",
+        "application/x-bzip2",
+        "application/x-7z-compressed",
+        "application/x-rar-compressed",
+        "application/pdf",
+        "application/octet-stream",
+        "application/wasm",
+    ];
+    !(precompressed.iter().any(|p| mime.starts_with(p)) || archives.contains(&mime.as_str()))
+}
+
+fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// `response` compressed for `accept_encoding` according to `settings`.
+pub fn compress_with(
+    response: Response,
+    accept_encoding: &str,
+    settings: &CompressionSettings,
+) -> Response {
+    if !settings.enabled
+        || response.stream.is_some()
+        || matches!(response.status, 101 | 204 | 206 | 304 | 416)
+        || header(&response, "Content-Range").is_some()
+        || header(&response, "Content-Encoding").is_some()
+        || !compressible(header(&response, "Content-Type").unwrap_or(""))
+    {
+        return response;
+    }
+    let len = match &response.binary {
+        Some(bytes) => bytes.len(),
+        None => response.body.len(),
+    };
+    if len < settings.min_bytes {
+        return response;
+    }
+    // From here the encoding depends on the request, even when none is chosen
+    let mut response = response.append_header("Vary", "Accept-Encoding");
+    let Some(encoding) = negotiate(accept_encoding) else {
+        return response;
+    };
+    let body: &[u8] = match &response.binary {
+        Some(bytes) => bytes,
+        None => response.body.as_bytes(),
+    };
+    let Ok(compressed) = encoding.encode(body) else {
+        return response;
+    };
+    response
+        .headers
+        .retain(|k, _| !k.eq_ignore_ascii_case("Content-Length"));
+    response.body.clear();
+    response.binary = Some(compressed);
+    response.headers.insert(
+        "Content-Encoding".to_string(),
+        encoding.as_str().to_string(),
+    );
+    for (name, value) in response.headers.iter_mut() {
+        if name.eq_ignore_ascii_case("ETag") && !value.starts_with("W/") {
+            *value = format!("W/{}", value);
+        }
+    }
+    response
+}
+
+/// `response` compressed with the configured settings (done when responding).
+pub fn compress(response: Response, accept_encoding: &str) -> Response {
+    let settings = SETTINGS.read().unwrap().clone();
+    compress_with(response, accept_encoding, &settings)
+}
@@ -0,0 +1,171 @@
+//! Cobalto contract tests
+//!
+//! Pact-like consumer contracts between Cobalto services. A consumer records
+//! the interactions it relies on by replaying them against a provider router
+//! in-process (`ContractRecorder`), the resulting JSON file is shared with the
+//! provider team, and `verify` replays it against the provider's router to
+//! catch breaking changes before deployment.
+//!
+//! JSON bodies match leniently: every field present in the contract must be
+//! present with the same value, extra fields in the actual response are fine.
+
+use crate::router::Router;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// The request side of an interaction.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContractRequest {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+}
+
+/// The expected response of an interaction.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContractResponse {
+    pub status: u16,
+    /// JSON bodies are stored as JSON, anything else as a string
+    pub body: Value,
+}
+
+/// One request/response pair the consumer depends on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub description: String,
+    pub request: ContractRequest,
+    pub response: ContractResponse,
+}
+
+/// A consumer/provider contract file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Contract {
+    pub consumer: String,
+    pub provider: String,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Contract {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(std::io::Error::other)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+fn body_value(body: &str) -> Value {
+    serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string()))
+}
+
+/// Records interactions by dispatching them to a provider router.
+pub struct ContractRecorder<'a> {
+    router: &'a Router,
+    contract: Contract,
+}
+
+impl<'a> ContractRecorder<'a> {
+    pub fn new(consumer: &str, provider: &str, router: &'a Router) -> Self {
+        ContractRecorder {
+            router,
+            contract: Contract {
+                consumer: consumer.to_string(),
+                provider: provider.to_string(),
+                interactions: Vec::new(),
+            },
+        }
+    }
+
+    /// Dispatch a request and record the provider's answer as the expectation.
+    pub async fn record(&mut self, description: &str, method: &str, path: &str, body: &str) {
+        let resp = self.router.dispatch(method, path, body).await;
+        self.contract.interactions.push(Interaction {
+            description: description.to_string(),
+            request: ContractRequest {
+                method: method.to_string(),
+                path: path.to_string(),
+                body: body.to_string(),
+            },
+            response: ContractResponse {
+                status: resp.status,
+                body: body_value(&resp.body),
+            },
+        });
+    }
+
+    /// The contract recorded so far.
+    pub fn finish(self) -> Contract {
+        self.contract
+    }
+}
+
+/// An interaction the provider no longer honours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContractMismatch {
+    pub description: String,
+    pub reason: String,
+}
+
+/// Whether `actual` satisfies `expected` (objects match as subsets).
+fn matches(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(exp), Value::Object(act)) => exp
+            .iter()
+            .all(|(k, v)| act.get(k).is_some_and(|a| matches(v, a))),
+        (Value::Array(exp), Value::Array(act)) => {
+            exp.len() == act.len() && exp.iter().zip(act).all(|(e, a)| matches(e, a))
+        }
+        _ => expected == actual,
+    }
+}
+
+/// Replay every interaction of `contract` against the provider router.
+pub async fn verify(router: &Router, contract: &Contract) -> Vec<ContractMismatch> {
+    let mut mismatches = Vec::new();
+    for interaction in &contract.interactions {
+        let req = &interaction.request;
+        let resp = router.dispatch(&req.method, &req.path, &req.body).await;
+        let expected = &interaction.response;
+        let reason = if resp.status != expected.status {
+            Some(format!(
+                "expected status {}, got {}",
+                expected.status, resp.status
+            ))
+        } else if !matches(&expected.body, &body_value(&resp.body)) {
+            Some(format!("body does not match: got {}", resp.body))
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            mismatches.push(ContractMismatch {
+                description: interaction.description.clone(),
+                reason,
+            });
+        }
+    }
+    mismatches
+}
+
+/// Verify a contract file and print the outcome (the `cobalto contract verify` output).
+///
+/// Returns whether the provider satisfies the contract.
+pub async fn verify_file(router: &Router, path: impl AsRef<Path>) -> std::io::Result<bool> {
+    let contract = Contract::load(path)?;
+    let mismatches = verify(router, &contract).await;
+    println!(
+        "Contract {} -> {}: {} interactions, {} failing",
+        contract.consumer,
+        contract.provider,
+        contract.interactions.len(),
+        mismatches.len()
+    );
+    for m in &mismatches {
+        println!("  {}: {}", m.description, m.reason);
+    }
+    Ok(mismatches.is_empty())
+}
@@ -0,0 +1,255 @@
+//! Cobalto cookies
+//!
+//! Read the request's cookies with `req.cookies()` / `req.cookie(name)` and set
+//! them on a response with `set_cookie`:
+//!
+//! ```ignore
+//! let theme = req.cookie("theme").unwrap_or_else(|| "light".into());
+//! Response::html(page).set_cookie(
+//!     Cookie::build("theme", "dark")
+//!         .http_only(true)
+//!         .max_age(Duration::from_secs(30 * 24 * 60 * 60)),
+//! )
+//! ```
+//!
+//! Values are percent-encoded on the way out and decoded on the way in, so any
+//! string round-trips. Several cookies on one response share the `Set-Cookie`
+//! header entry, one per line; the server sends each as its own header.
+
+use crate::router::{Request, Response, percent_decode};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The `SameSite` attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross-site requests too; browsers require `Secure` with it.
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// A cookie to send with a response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<Duration>,
+    pub expires: Option<DateTime<Utc>>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+}
+
+/// Whether `name` is an RFC 6265 cookie name (an HTTP token).
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b))
+}
+
+/// Whether `value` can be a `Path` or `Domain` attribute: no `;` or control
+/// characters that would end the attribute or the header.
+fn is_valid_attribute(value: &str) -> bool {
+    !value.contains(';') && !value.chars().any(char::is_control)
+}
+
+impl Cookie {
+    /// A cookie for the whole site (`Path=/`) with no other attributes.
+    ///
+    /// # Panics
+    ///
+    /// If `name` is not a valid cookie name (empty, or with separators such
+    /// as `;`, `=`, spaces or line breaks).
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
+        let name = name.into();
+        assert!(is_valid_name(&name), "invalid cookie name {:?}", name);
+        Cookie {
+            name,
+            value: value.into(),
+            path: Some("/".to_string()),
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Start building a cookie; same as `new`, reads better before builder calls.
+    pub fn build<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
+        Self::new(name, value)
+    }
+
+    /// A cookie that makes the browser delete `name`.
+    pub fn removal<N: Into<String>>(name: N) -> Self {
+        Cookie::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(DateTime::<Utc>::UNIX_EPOCH)
+    }
+
+    /// Builder for the path the cookie is sent for
+    ///
+    /// # Panics
+    ///
+    /// If `path` contains `;` or control characters.
+    pub fn path<S: Into<String>>(mut self, path: S) -> Self {
+        let path = path.into();
+        assert!(is_valid_attribute(&path), "invalid cookie path {:?}", path);
+        self.path = Some(path);
+        self
+    }
+
+    /// Builder for the domain the cookie is sent to
+    ///
+    /// # Panics
+    ///
+    /// If `domain` contains `;` or control characters.
+    pub fn domain<S: Into<String>>(mut self, domain: S) -> Self {
+        let domain = domain.into();
+        assert!(
+            is_valid_attribute(&domain),
+            "invalid cookie domain {:?}",
+            domain
+        );
+        self.domain = Some(domain);
+        self
+    }
+
+    /// Builder for the lifetime from now
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Builder for an absolute expiry
+    pub fn expires(mut self, at: DateTime<Utc>) -> Self {
+        self.expires = Some(at);
+        self
+    }
+
+    /// Builder for HTTPS-only cookies
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Builder for cookies hidden from JavaScript
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Builder for the `SameSite` attribute
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// The `Set-Cookie` header value.
+    pub fn to_header(&self) -> String {
+        let mut header = format!("{}={}", self.name, encode(&self.value));
+        if let Some(path) = &self.path {
+            header.push_str(&format!("; Path={}", path));
+        }
+        if let Some(domain) = &self.domain {
+            header.push_str(&format!("; Domain={}", domain));
+        }
+        if let Some(max_age) = self.max_age {
+            header.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+        if let Some(expires) = self.expires {
+            header.push_str(&format!(
+                "; Expires={}",
+                expires.format("%a, %d %b %Y %H:%M:%S GMT")
+            ));
+        }
+        if self.secure {
+            header.push_str("; Secure");
+        }
+        if self.http_only {
+            header.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = self.same_site {
+            header.push_str(&format!("; SameSite={}", same_site.as_str()));
+        }
+        header
+    }
+}
+
+/// Cookies of a `Cookie` request header, values decoded. Later duplicates are
+/// ignored, as browsers send the most specific cookie first.
+pub fn parse_cookies(header: &str) -> HashMap<String, String> {
+    let mut cookies = HashMap::new();
+    for pair in header.split(';') {
+        let Some((name, value)) = pair.trim().split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        // `+` is literal in cookies, unlike in form encoding
+        cookies
+            .entry(name.trim().to_string())
+            .or_insert_with(|| percent_decode(&value.replace('+', "%2B")));
+    }
+    cookies
+}
+
+/// Percent-encode everything outside RFC 6265's cookie-octet, plus `%`.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            0x21 | 0x23..=0x24 | 0x26..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+impl Request {
+    /// Cookies sent with the request.
+    pub fn cookies(&self) -> HashMap<String, String> {
+        self.header("cookie").map(parse_cookies).unwrap_or_default()
+    }
+
+    /// Value of one request cookie.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+}
+
+impl Response {
+    /// Builder adding a `Set-Cookie` header
+    pub fn set_cookie(self, cookie: Cookie) -> Self {
+        self.append_header("Set-Cookie", &cookie.to_header())
+    }
+
+    /// Builder telling the browser to delete a cookie
+    pub fn remove_cookie(self, name: &str) -> Self {
+        self.set_cookie(Cookie::removal(name))
+    }
+
+    /// `Set-Cookie` values of this response, in the order they were added.
+    pub fn cookies(&self) -> Vec<&str> {
+        self.headers
+            .get("Set-Cookie")
+            .map(|v| v.lines().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,279 @@
+//! Cobalto DataTable component
+//!
+//! Pairs a `QuerySet` with column definitions and renders a sortable,
+//! paginated and filterable HTML table; filtering, sorting and paging run in
+//! the database. State travels in query parameters (`sort`, `dir`, `page`,
+//! `q`), and every control carries `hx-get` attributes so HTMX can reload
+//! just the table partial.
+
+use crate::orm::{FieldType, Model, QuerySet, text_of};
+use crate::router::percent_encode;
+use crate::template::escape_html;
+use sqlx::any::AnyRow;
+use std::collections::HashMap;
+
+/// A row as displayed by the table: column key → cell text.
+pub type Row = HashMap<String, String>;
+
+/// One table column.
+#[derive(Clone, Debug)]
+pub struct Column {
+    pub key: String,
+    pub label: String,
+    pub sortable: bool,
+}
+
+impl Column {
+    pub fn new(key: &str, label: &str) -> Self {
+        Column {
+            key: key.to_string(),
+            label: label.to_string(),
+            sortable: true,
+        }
+    }
+
+    /// Builder for disabling sorting on this column
+    pub fn unsortable(mut self) -> Self {
+        self.sortable = false;
+        self
+    }
+}
+
+/// The rows of a single page plus the table state that produced them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TablePage {
+    pub rows: Vec<Row>,
+    pub page: usize,
+    pub pages: usize,
+    pub total: usize,
+    pub sort: Option<String>,
+    pub descending: bool,
+    pub filter: String,
+}
+
+/// Table definition, reusable across requests.
+#[derive(Clone, Debug)]
+pub struct DataTable {
+    pub id: String,
+    /// Endpoint returning the table partial (used by HTMX reloads).
+    pub url: String,
+    pub columns: Vec<Column>,
+    pub per_page: usize,
+    /// Columns matched by the filter box; `None` for the text columns
+    pub search_fields: Option<Vec<String>>,
+    pub default_order: Option<String>,
+}
+
+impl DataTable {
+    pub fn new(id: &str, url: &str, columns: Vec<Column>) -> Self {
+        DataTable {
+            id: id.to_string(),
+            url: url.to_string(),
+            columns,
+            per_page: 25,
+            search_fields: None,
+            default_order: None,
+        }
+    }
+
+    /// Builder for the page size
+    pub fn per_page(mut self, per_page: usize) -> Self {
+        self.per_page = per_page.max(1);
+        self
+    }
+
+    /// Builder for the columns matched by the filter box (text columns by
+    /// default)
+    pub fn search_fields(mut self, columns: &[&str]) -> Self {
+        self.search_fields = Some(columns.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Builder for the order used when no column is sorted, e.g. `"-id"`
+    pub fn default_order(mut self, field: &str) -> Self {
+        self.default_order = Some(field.to_string());
+        self
+    }
+
+    /// Apply filter, sort and paging from the query parameters to `objects`.
+    ///
+    /// Counts the filtered rows and returns the table state (with no rows)
+    /// along with the queryset of the requested page, left for the caller to
+    /// fetch.
+    pub async fn query<M: Model>(
+        &self,
+        objects: QuerySet<M>,
+        query: &HashMap<String, String>,
+    ) -> Result<(TablePage, QuerySet<M>), sqlx::Error> {
+        let filter = query.get("q").map(|q| q.trim()).unwrap_or("").to_string();
+        let mut objects = objects;
+        if !filter.is_empty() {
+            let lookups: Vec<String> = match &self.search_fields {
+                Some(fields) => fields.iter().map(|f| format!("{}__icontains", f)).collect(),
+                None => objects
+                    .columns()
+                    .iter()
+                    .filter(|f| {
+                        f.field_type == FieldType::Text
+                            && self.columns.iter().any(|c| c.key == f.name)
+                    })
+                    .map(|f| format!("{}__icontains", f.name))
+                    .collect(),
+            };
+            let lookups: Vec<&str> = lookups.iter().map(|l| l.as_str()).collect();
+            objects = objects.filter_any(&lookups, filter.as_str());
+        }
+
+        let sort = query
+            .get("sort")
+            .filter(|key| self.columns.iter().any(|c| c.sortable && &c.key == *key))
+            .cloned();
+        let descending = query.get("dir").map(|d| d == "desc").unwrap_or(false);
+        objects = match (&sort, &self.default_order) {
+            (Some(key), _) if descending => objects.order_by(&format!("-{}", key)),
+            (Some(key), _) => objects.order_by(key),
+            (None, Some(order)) => objects.order_by(order),
+            (None, None) => objects,
+        };
+
+        let total = objects.count().await?.max(0) as usize;
+        let pages = total.div_ceil(self.per_page).max(1);
+        let page = query
+            .get("page")
+            .and_then(|p| p.parse::<usize>().ok())
+            .unwrap_or(1)
+            .clamp(1, pages);
+        let objects = objects
+            .limit(self.per_page as i64)
+            .offset(((page - 1) * self.per_page) as i64);
+        let state = TablePage {
+            rows: Vec::new(),
+            page,
+            pages,
+            total,
+            sort,
+            descending,
+            filter,
+        };
+        Ok((state, objects))
+    }
+
+    /// Fetch the page of `objects` selected by the query parameters, with
+    /// each column's value as cell text.
+    pub async fn page<M>(
+        &self,
+        objects: QuerySet<M>,
+        query: &HashMap<String, String>,
+    ) -> Result<TablePage, sqlx::Error>
+    where
+        M: Model + for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+    {
+        let (mut page, objects) = self.query(objects, query).await?;
+        page.rows = objects
+            .all()
+            .await?
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .map(|c| (c.key.clone(), text_of(&row.value_of(&c.key))))
+                    .collect()
+            })
+            .collect();
+        Ok(page)
+    }
+
+    fn link(&self, page: usize, sort: Option<&str>, descending: bool, filter: &str) -> String {
+        let mut url = format!("{}?page={}", self.url, page);
+        if let Some(sort) = sort {
+            url.push_str(&format!(
+                "&sort={}&dir={}",
+                sort,
+                if descending { "desc" } else { "asc" }
+            ));
+        }
+        if !filter.is_empty() {
+            url.push_str(&format!("&q={}", percent_encode(filter)));
+        }
+        url
+    }
+
+    fn htmx_attrs(&self, url: &str) -> String {
+        format!(
+            r##"href="{url}" hx-get="{url}" hx-target="#{id}" hx-swap="outerHTML""##,
+            url = escape_html(url),
+            id = self.id
+        )
+    }
+
+    /// Render the table partial for one page.
+    pub fn render(&self, page: &TablePage) -> String {
+        let mut html = format!(r#"<div id="{}" class="cobalto-datatable">"#, self.id);
+        html.push_str(&format!(
+            r##"<form hx-get="{url}" hx-target="#{id}" hx-swap="outerHTML" hx-trigger="input changed delay:300ms from:input" action="{url}">
+<input type="search" name="q" value="{q}" placeholder="Filter…"></form>"##,
+            url = escape_html(&self.url),
+            id = self.id,
+            q = escape_html(&page.filter)
+        ));
+
+        html.push_str("<table><thead><tr>");
+        for col in &self.columns {
+            if col.sortable {
+                let active = page.sort.as_deref() == Some(col.key.as_str());
+                let descending = active && !page.descending;
+                let arrow = match (active, page.descending) {
+                    (true, false) => " ▲",
+                    (true, true) => " ▼",
+                    _ => "",
+                };
+                let url = self.link(1, Some(&col.key), descending, &page.filter);
+                html.push_str(&format!(
+                    "<th><a {}>{}{}</a></th>",
+                    self.htmx_attrs(&url),
+                    escape_html(&col.label),
+                    arrow
+                ));
+            } else {
+                html.push_str(&format!("<th>{}</th>", escape_html(&col.label)));
+            }
+        }
+        html.push_str("</tr></thead><tbody>");
+        for row in &page.rows {
+            html.push_str("<tr>");
+            for col in &self.columns {
+                html.push_str(&format!(
+                    "<td>{}</td>",
+                    escape_html(row.get(&col.key).map(|s| s.as_str()).unwrap_or(""))
+                ));
+            }
+            html.push_str("</tr>");
+        }
+        html.push_str("</tbody></table>");
+
+        html.push_str(&format!(
+            r#"<nav class="cobalto-datatable-pager"><span>Page {} of {} ({} rows)</span>"#,
+            page.page, page.pages, page.total
+        ));
+        if page.page > 1 {
+            let url = self.link(
+                page.page - 1,
+                page.sort.as_deref(),
+                page.descending,
+                &page.filter,
+            );
+            html.push_str(&format!(" <a {}>Previous</a>", self.htmx_attrs(&url)));
+        }
+        if page.page < page.pages {
+            let url = self.link(
+                page.page + 1,
+                page.sort.as_deref(),
+                page.descending,
+                &page.filter,
+            );
+            html.push_str(&format!(" <a {}>Next</a>", self.htmx_attrs(&url)));
+        }
+        html.push_str("</nav></div>");
+        html
+    }
+}
@@ -0,0 +1,308 @@
+//! Cobalto debug error page
+//!
+//! With `debug = true`, a panicking handler, or a 5xx answer to a browser,
+//! is shown as a page with the error, its backtrace, the matched route, the
+//! request's headers and parameters, the loaded settings and the slow queries
+//! captured by `Db::with_slow_query_log`. Settings whose names look secret are
+//! masked, and so are the credential headers. Never enable `debug` in
+//! production.
+
+use crate::error_pages::PanicReport;
+use crate::router::RequestScope;
+use crate::settings::Settings;
+use crate::template::escape_html;
+
+/// Setting names containing one of these are shown masked.
+const SENSITIVE: &[&str] = &[
+    "secret",
+    "password",
+    "passwd",
+    "token",
+    "key",
+    "database_url",
+    "dsn",
+];
+
+/// Request headers carrying credentials, shown masked.
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization"];
+
+/// The settings as `(name, value)` rows, sorted, secrets masked.
+pub fn settings_rows(settings: &Settings) -> Vec<(String, String)> {
+    let mut rows = vec![
+        ("debug".to_string(), settings.debug.to_string()),
+        ("host".to_string(), settings.host.clone()),
+        ("port".to_string(), settings.port.to_string()),
+        ("ws_port".to_string(), settings.ws_port.to_string()),
+        (
+            "workers".to_string(),
+            settings
+                .workers
+                .map(|w| w.to_string())
+                .unwrap_or_else(|| "auto".to_string()),
+        ),
+        (
+            "server.keep_alive".to_string(),
+            settings
+                .server
+                .keep_alive
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "default".to_string()),
+        ),
+        (
+            "server.client_request_timeout".to_string(),
+            settings
+                .server
+                .client_request_timeout
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "default".to_string()),
+        ),
+        (
+            "server.max_connections".to_string(),
+            settings
+                .server
+                .max_connections
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "default".to_string()),
+        ),
+        (
+            "server.http2".to_string(),
+            settings.server.http2.to_string(),
+        ),
+        ("template.dir".to_string(), settings.template.dir.clone()),
+        (
+            "template.dirs".to_string(),
+            settings.template.dirs.join(", "),
+        ),
+        (
+            "template.debug".to_string(),
+            settings.template.debug.to_string(),
+        ),
+        ("static_dir".to_string(), settings.static_dir.clone()),
+        ("static_url".to_string(), settings.static_url.clone()),
+        (
+            "max_upload_bytes".to_string(),
+            settings.max_upload_bytes.to_string(),
+        ),
+        (
+            "max_json_bytes".to_string(),
+            settings.max_json_bytes.to_string(),
+        ),
+        ("strict_json".to_string(), settings.strict_json.to_string()),
+        (
+            "trusted_proxies".to_string(),
+            settings.trusted_proxies.join(", "),
+        ),
+        ("tls".to_string(), settings.tls.enabled().to_string()),
+        (
+            "monitoring.healthz".to_string(),
+            settings.monitoring.healthz.to_string(),
+        ),
+        (
+            "monitoring.metrics".to_string(),
+            settings.monitoring.metrics.to_string(),
+        ),
+        (
+            "compression.enabled".to_string(),
+            settings.compression.enabled.to_string(),
+        ),
+        (
+            "compression.min_bytes".to_string(),
+            settings.compression.min_bytes.to_string(),
+        ),
+        (
+            "mail.backend".to_string(),
+            format!("{:?}", settings.mail.backend).to_ascii_lowercase(),
+        ),
+        ("mail.from".to_string(), settings.mail.from.clone()),
+        (
+            "i18n.default_locale".to_string(),
+            settings.i18n.default_locale.clone(),
+        ),
+        ("i18n.dir".to_string(), settings.i18n.dir.clone()),
+        ("mail.host".to_string(), settings.mail.host.clone()),
+        ("mail.port".to_string(), settings.mail.port.to_string()),
+        (
+            "mail.password".to_string(),
+            settings.mail.password.clone().unwrap_or_default(),
+        ),
+    ];
+    rows.extend(
+        settings
+            .body_limits
+            .iter()
+            .map(|(name, limit)| (format!("body_limits.{}", name), limit.to_string())),
+    );
+    rows.extend(settings.other.iter().map(|(k, v)| (k.clone(), v.clone())));
+    for (name, value) in rows.iter_mut() {
+        let lower = name.to_ascii_lowercase();
+        if SENSITIVE.iter().any(|s| lower.contains(s)) {
+            *value = "********".to_string();
+        }
+    }
+    rows.sort();
+    rows
+}
+
+fn table<'a, I>(rows: I) -> String
+where
+    I: IntoIterator<Item = (&'a String, &'a String)>,
+{
+    let mut rows: Vec<_> = rows.into_iter().collect();
+    rows.sort();
+    if rows.is_empty() {
+        return "<p><i>none</i></p>".to_string();
+    }
+    let body: String = rows
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "<tr><th>{}</th><td><code>{}</code></td></tr>",
+                escape_html(k),
+                escape_html(v)
+            )
+        })
+        .collect();
+    format!("<table>{}</table>", body)
+}
+
+/// The captured slow queries, most recent first, with their plan warnings.
+fn slow_queries() -> String {
+    let queries = crate::orm::slow_queries();
+    if queries.is_empty() {
+        return "<p><i>none</i></p>".to_string();
+    }
+    let items: String = queries
+        .iter()
+        .rev()
+        .map(|query| {
+            let warnings: String = query
+                .plan
+                .iter()
+                .flat_map(|plan| &plan.warnings)
+                .map(|w| format!("<li>{}</li>", escape_html(w)))
+                .collect();
+            format!(
+                "<li><code>{}</code> {}ms{}</li>",
+                escape_html(&query.sql),
+                query.duration.as_millis(),
+                if warnings.is_empty() {
+                    String::new()
+                } else {
+                    format!("<ul>{}</ul>", warnings)
+                }
+            )
+        })
+        .collect();
+    format!("<ol>{}</ol>", items)
+}
+
+/// The process allocation totals, when the `alloc-stats` feature is on.
+#[cfg(feature = "alloc-stats")]
+fn allocations() -> String {
+    let totals = crate::alloc_stats::totals();
+    let rows = [
+        ("allocations".to_string(), totals.allocations.to_string()),
+        ("bytes".to_string(), totals.bytes.to_string()),
+    ];
+    format!(
+        "<section><h2>Allocations</h2>{}</section>\n",
+        table(rows.iter().map(|(k, v)| (k, v)))
+    )
+}
+
+#[cfg(not(feature = "alloc-stats"))]
+fn allocations() -> String {
+    String::new()
+}
+
+/// The debug page for `report`, raised while handling `scope`.
+pub fn render(
+    title: &str,
+    report: &PanicReport,
+    scope: &RequestScope,
+    settings: &[(String, String)],
+) -> String {
+    let location = report
+        .location
+        .as_deref()
+        .map(|l| {
+            format!(
+                "<p class=\"location\">at <code>{}</code></p>",
+                escape_html(l)
+            )
+        })
+        .unwrap_or_default();
+    let backtrace = if report.backtrace.is_empty() {
+        "<p><i>not available</i></p>".to_string()
+    } else {
+        format!("<pre>{}</pre>", escape_html(&report.backtrace))
+    };
+    let headers: Vec<(String, String)> = scope
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS
+                .iter()
+                .any(|h| name.eq_ignore_ascii_case(h))
+            {
+                "********".to_string()
+            } else {
+                value.clone()
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    let route = [
+        ("method".to_string(), scope.method.clone()),
+        ("path".to_string(), scope.path.clone()),
+        ("route".to_string(), scope.route.clone()),
+        ("request_id".to_string(), scope.request_id.clone()),
+    ];
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title} at {path}</title>
+<style>
+body {{ font-family: sans-serif; margin: 0; }}
+header {{ background: #ffc; border-bottom: 1px solid #ddd; padding: 1em 2em; }}
+section {{ padding: 0.5em 2em; border-bottom: 1px solid #eee; }}
+h1 {{ margin: 0 0 0.3em; }}
+.message {{ font-size: 1.2em; white-space: pre-wrap; }}
+table {{ border-collapse: collapse; }}
+th {{ text-align: right; padding: 0.2em 1em 0.2em 0; vertical-align: top; color: #666; }}
+pre {{ background: #f6f6f6; padding: 1em; overflow: auto; }}
+</style>
+</head>
+<body>
+<header>
+<h1>{title}</h1>
+<p class="message">{message}</p>
+{location}
+</header>
+<section><h2>Request</h2>{route}</section>
+<section><h2>Path parameters</h2>{params}</section>
+<section><h2>Query parameters</h2>{query}</section>
+<section><h2>Headers</h2>{headers}</section>
+<section><h2>Backtrace</h2>{backtrace}</section>
+<section><h2>Slow queries</h2>{slow_queries}</section>
+{allocations}<section><h2>Settings</h2>{settings}</section>
+<section><p>You're seeing this page because <code>debug = true</code>.</p></section>
+</body>
+</html>
+"#,
+        title = escape_html(title),
+        path = escape_html(&scope.path),
+        message = escape_html(&report.message),
+        location = location,
+        route = table(route.iter().map(|(k, v)| (k, v))),
+        params = table(&scope.params),
+        query = table(&scope.query),
+        headers = table(headers.iter().map(|(k, v)| (k, v))),
+        backtrace = backtrace,
+        slow_queries = slow_queries(),
+        allocations = allocations(),
+        settings = table(settings.iter().map(|(k, v)| (k, v))),
+    )
+}
@@ -0,0 +1,263 @@
+//! Cobalto error pages
+//!
+//! Errors the router answers itself (no matching route, ...) get a page from,
+//! in order of preference:
+//!
+//! 1. a handler registered with `router.error_handler(404, |info| ...)`;
+//! 2. the `<status>.html` template (`templates/404.html`, `templates/500.html`)
+//!    in the template directory, rendered with `status`, `method`, `path` and,
+//!    when `debug` is on, `error`;
+//! 3. the built-in page.
+//!
+//! A panicking handler or middleware is caught and logged, and the client gets
+//! a 500 page instead of a dropped connection. In debug mode browsers get the
+//! debug page (see `debug_page`) for panics and for 5xx responses.
+
+use crate::router::{RequestScope, Response};
+use crate::settings::Settings;
+use crate::template::{TemplateEngine, TemplateValue};
+use futures::FutureExt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Once};
+
+/// What went wrong, for error handlers and error templates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ErrorInfo {
+    pub status: u16,
+    pub method: String,
+    pub path: String,
+    /// Error details; only set when `debug` is on.
+    pub detail: Option<String>,
+}
+
+impl ErrorInfo {
+    /// Template context for the error page.
+    pub fn context(&self) -> HashMap<String, TemplateValue> {
+        let mut context = HashMap::new();
+        context.insert(
+            "status".to_string(),
+            TemplateValue::Number(self.status as f64),
+        );
+        context.insert(
+            "method".to_string(),
+            TemplateValue::String(self.method.clone()),
+        );
+        context.insert("path".to_string(), TemplateValue::String(self.path.clone()));
+        if let Some(detail) = &self.detail {
+            context.insert("error".to_string(), TemplateValue::String(detail.clone()));
+        }
+        context
+    }
+}
+
+/// Builds the response for an error status.
+pub type ErrorHandler = Arc<dyn Fn(&ErrorInfo) -> Response + Send + Sync>;
+
+/// Custom error handlers and the template directory to look for pages in.
+#[derive(Clone, Default)]
+pub struct ErrorPages {
+    handlers: HashMap<u16, ErrorHandler>,
+    template_dir: String,
+    debug: bool,
+    /// Settings shown on the debug page
+    settings: Vec<(String, String)>,
+}
+
+impl ErrorPages {
+    pub fn new(template_dir: &str, debug: bool) -> Self {
+        ErrorPages {
+            template_dir: template_dir.to_string(),
+            debug,
+            ..Default::default()
+        }
+    }
+
+    /// Take the template directory, debug flag and debug-page settings from
+    /// `settings`, keeping the handlers.
+    pub fn configure(&mut self, settings: &Settings) {
+        self.template_dir = settings.template.dir.clone();
+        self.debug = settings.debug;
+        self.settings = crate::debug_page::settings_rows(settings);
+    }
+
+    pub fn set_handler(&mut self, status: u16, handler: ErrorHandler) {
+        self.handlers.insert(status, handler);
+    }
+
+    /// Whether error details may be shown.
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    /// The custom page for `info.status`; `None` to use the built-in one.
+    ///
+    /// `detail` is dropped from `info` unless `debug` is on.
+    pub fn render(&self, info: &ErrorInfo) -> Option<Response> {
+        let info = ErrorInfo {
+            detail: info.detail.clone().filter(|_| self.debug),
+            ..info.clone()
+        };
+        if let Some(handler) = self.handlers.get(&info.status) {
+            return Some(handler(&info).with_status(info.status));
+        }
+        let name = format!("{}.html", info.status);
+        if !std::path::Path::new(&self.template_dir)
+            .join(&name)
+            .is_file()
+        {
+            return None;
+        }
+        let page = TemplateEngine::new(&self.template_dir).render(&name, &info.context());
+        Some(page.with_status(info.status))
+    }
+}
+
+/// A panic caught while handling a request.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PanicReport {
+    pub message: String,
+    /// `file:line:column` of the panic.
+    pub location: Option<String>,
+    pub backtrace: String,
+}
+
+impl std::fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "panicked at {}: {}", location, self.message),
+            None => write!(f, "panicked: {}", self.message),
+        }
+    }
+}
+
+thread_local! {
+    /// Filled by the panic hook, taken right after `catch_unwind` on the same thread
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+/// Set once a router in debug mode handles a request: backtraces are then
+/// always captured for the debug page
+static FORCE_BACKTRACE: AtomicBool = AtomicBool::new(false);
+
+/// Record location and backtrace of every panic, then run the previous hook.
+///
+/// Capturing a backtrace is slow, so outside debug mode it follows
+/// `RUST_BACKTRACE` (`Backtrace::capture`) and is empty when that is unset.
+fn install_panic_hook(debug: bool) {
+    if debug {
+        FORCE_BACKTRACE.store(true, Ordering::Relaxed);
+    }
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let report = PanicReport {
+                message: panic_message(info.payload()),
+                location: info.location().map(|l| l.to_string()),
+                backtrace: backtrace(),
+            };
+            LAST_PANIC.with(|last| *last.borrow_mut() = Some(report));
+            previous(info);
+        }));
+    });
+}
+
+fn backtrace() -> String {
+    use std::backtrace::{Backtrace, BacktraceStatus};
+    let backtrace = if FORCE_BACKTRACE.load(Ordering::Relaxed) {
+        Backtrace::force_capture()
+    } else {
+        Backtrace::capture()
+    };
+    match backtrace.status() {
+        BacktraceStatus::Captured => backtrace.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+impl ErrorPages {
+    /// Run `response` for the request in `scope`, turning a panic into a
+    /// logged 500. In debug mode, a 5xx answer to a browser is replaced by the
+    /// debug page.
+    pub(crate) async fn guard<F>(&self, scope: &RequestScope, response: F) -> Response
+    where
+        F: Future<Output = Response>,
+    {
+        install_panic_hook(self.debug);
+        let payload = match AssertUnwindSafe(response).catch_unwind().await {
+            Ok(response) => return self.debug_server_error(scope, response),
+            Err(payload) => payload,
+        };
+        let report = LAST_PANIC
+            .with(|last| last.borrow_mut().take())
+            .unwrap_or_else(|| PanicReport {
+                message: panic_message(&*payload),
+                ..Default::default()
+            });
+        crate::logging::log(
+            crate::logging::LogRecord::new(log::Level::Error, report.to_string())
+                .field("method", scope.method.as_str())
+                .field("path", scope.path.as_str()),
+        );
+        self.server_error(scope, &report)
+    }
+
+    /// The 500 page for a caught panic.
+    pub fn server_error(&self, scope: &RequestScope, report: &PanicReport) -> Response {
+        let info = ErrorInfo {
+            status: 500,
+            method: scope.method.clone(),
+            path: scope.path.clone(),
+            detail: Some(report.to_string()),
+        };
+        if let Some(page) = self.render(&info) {
+            return page;
+        }
+        if !self.debug {
+            return Response::html("Internal Server Error").with_status(500);
+        }
+        Response::html(crate::debug_page::render(
+            "Panic",
+            report,
+            scope,
+            &self.settings,
+        ))
+        .with_status(500)
+    }
+
+    /// `response`, or the debug page if it is a 5xx for a browser in debug mode.
+    fn debug_server_error(&self, scope: &RequestScope, response: Response) -> Response {
+        let wants_html = scope
+            .headers
+            .get("accept")
+            .is_some_and(|accept| accept.contains("text/html"));
+        if !self.debug || response.status < 500 || !wants_html {
+            return response;
+        }
+        let report = PanicReport {
+            message: response.body.clone(),
+            ..Default::default()
+        };
+        Response::html(crate::debug_page::render(
+            &format!("Handler returned {}", response.status),
+            &report,
+            scope,
+            &self.settings,
+        ))
+        .with_status(response.status)
+    }
+}
@@ -0,0 +1,204 @@
+//! Cobalto extractors
+//!
+//! Handlers wrapped with `extract` take typed arguments instead of a bare
+//! `Request`; each one is pulled out of the request before the handler runs,
+//! and a failure answers with a JSON error (400 for malformed input, 422 for
+//! well-formed input of the wrong shape) without calling the handler:
+//!
+//! ```ignore
+//! async fn update_user(Path(id): Path<i64>, Json(user): Json<UpdateUser>, db: State<Db>) -> Response {
+//!     ...
+//! }
+//!
+//! router.add_route("PUT", "/users/:id", extract(update_user), "update_user");
+//! ```
+//!
+//! Implement `FromRequest` for your own types to use them as arguments.
+
+use crate::router::{Handler, IntoResponse, Request, Response};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Why an argument could not be extracted; answers with `status` and a JSON error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rejection {
+    pub status: u16,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn new<S: Into<String>>(status: u16, message: S) -> Self {
+        Rejection {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        Response::json(serde_json::json!({"error": self.message})).with_status(self.status)
+    }
+}
+
+/// A handler argument built from the request.
+pub trait FromRequest: Sized {
+    fn from_request(req: &Request) -> Result<Self, Rejection>;
+}
+
+impl FromRequest for Request {
+    fn from_request(req: &Request) -> Result<Self, Rejection> {
+        Ok(req.clone())
+    }
+}
+
+/// Optional arguments never reject.
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(req: &Request) -> Result<Self, Rejection> {
+        Ok(T::from_request(req).ok())
+    }
+}
+
+/// JSON body, parsed with `Request::json`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(req: &Request) -> Result<Self, Rejection> {
+        req.json()
+            .map(Json)
+            .map_err(|e| Rejection::new(e.status(), e.to_string()))
+    }
+}
+
+/// Path parameters: one value (`Path<i64>`), or a struct with a field per
+/// parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(req: &Request) -> Result<Self, Rejection> {
+        if req.params.len() == 1 {
+            let value = req.params.values().next().unwrap();
+            // As a string first, then as a literal so numbers and booleans parse
+            let single = serde_json::from_value(serde_json::Value::String(value.clone()))
+                .or_else(|_| serde_json::from_str(value));
+            if let Ok(value) = single {
+                return Ok(Path(value));
+            }
+        }
+        let encoded = serde_urlencoded::to_string(&req.params)
+            .map_err(|e| Rejection::new(400, e.to_string()))?;
+        serde_urlencoded::from_str(&encoded)
+            .map(Path)
+            .map_err(|e| Rejection::new(400, format!("invalid path parameters: {}", e)))
+    }
+}
+
+/// Query string (see `Request::query_as`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(req: &Request) -> Result<Self, Rejection> {
+        req.query_as()
+            .map(Query)
+            .map_err(|e| Rejection::new(400, format!("invalid query string: {}", e)))
+    }
+}
+
+/// URL-encoded form body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Form<T> {
+    fn from_request(req: &Request) -> Result<Self, Rejection> {
+        serde_urlencoded::from_str(&req.body)
+            .map(Form)
+            .map_err(|e| Rejection::new(422, format!("invalid form body: {}", e)))
+    }
+}
+
+/// A value registered with `Router::manage`; a missing one is a 500.
+#[derive(Clone, Debug)]
+pub struct State<T>(pub T);
+
+impl<T> std::ops::Deref for State<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> FromRequest for State<T> {
+    fn from_request(req: &Request) -> Result<Self, Rejection> {
+        req.state().map(State).ok_or_else(|| {
+            Rejection::new(
+                500,
+                format!(
+                    "{} is not managed by the router",
+                    std::any::type_name::<T>()
+                ),
+            )
+        })
+    }
+}
+
+/// Handler functions whose arguments all implement `FromRequest`.
+pub trait ExtractHandler<Args>: Send + Sync + 'static {
+    fn call(self: Arc<Self>, req: Request) -> Pin<Box<dyn Future<Output = Response> + Send>>;
+}
+
+macro_rules! impl_extract_handler {
+    ($($arg:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<F, Fut, R, $($arg),+> ExtractHandler<($($arg,)+)> for F
+        where
+            F: Fn($($arg),+) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = R> + Send + 'static,
+            R: IntoResponse,
+            $($arg: FromRequest + Send + 'static,)+
+        {
+            fn call(self: Arc<Self>, req: Request) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+                // Extract inside the future, where the application state is in scope
+                Box::pin(async move {
+                    $(
+                        let $arg = match $arg::from_request(&req) {
+                            Ok(value) => value,
+                            Err(rejection) => return rejection.into_response(),
+                        };
+                    )+
+                    (*self)($($arg),+).await.into_response()
+                })
+            }
+        }
+    };
+}
+
+impl_extract_handler!(A);
+impl_extract_handler!(A, B);
+impl_extract_handler!(A, B, C);
+impl_extract_handler!(A, B, C, D);
+impl_extract_handler!(A, B, C, D, E);
+impl_extract_handler!(A, B, C, D, E, G);
+
+/// Wrap a handler taking extractors into a route `Handler`, like `handler` does
+/// for `Fn(Request)`.
+pub fn extract<F, Args>(f: F) -> Handler
+where
+    F: ExtractHandler<Args>,
+{
+    let f = Arc::new(f);
+    Arc::new(move |req| ExtractHandler::<Args>::call(f.clone(), req))
+}
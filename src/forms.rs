@@ -0,0 +1,191 @@
+//! Cobalto forms
+//!
+//! Field metadata used to render model forms (and, later, the admin) without
+//! hand-written HTML. `#[derive(Model)]` fills it from field attributes such as
+//! `#[cobalto(label = "E-mail", help = "...", widget = "textarea")]`; it can
+//! also be implemented by hand through the `FormFields` trait.
+
+use crate::template::escape_html;
+use std::collections::HashMap;
+
+/// How a field is rendered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Widget {
+    Text,
+    Textarea,
+    Email,
+    Password,
+    Number,
+    Checkbox,
+    Date,
+    Hidden,
+    Select(Vec<(String, String)>),
+}
+
+impl Widget {
+    /// Parse the `widget = "..."` attribute value.
+    pub fn from_attr(name: &str) -> Option<Self> {
+        Some(match name {
+            "text" => Widget::Text,
+            "textarea" => Widget::Textarea,
+            "email" => Widget::Email,
+            "password" => Widget::Password,
+            "number" => Widget::Number,
+            "checkbox" => Widget::Checkbox,
+            "date" => Widget::Date,
+            "hidden" => Widget::Hidden,
+            _ => return None,
+        })
+    }
+}
+
+/// Presentation metadata for one form field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormField {
+    pub name: String,
+    pub label: String,
+    pub help_text: Option<String>,
+    pub widget: Widget,
+    pub required: bool,
+}
+
+impl FormField {
+    /// A required text field labelled from its name (`first_name` → "First name").
+    pub fn new(name: &str) -> Self {
+        FormField {
+            name: name.to_string(),
+            label: humanize(name),
+            help_text: None,
+            widget: Widget::Text,
+            required: true,
+        }
+    }
+
+    /// Builder for a custom label
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    /// Builder for help text shown under the input
+    pub fn help(mut self, help: &str) -> Self {
+        self.help_text = Some(help.to_string());
+        self
+    }
+
+    /// Builder for the widget
+    pub fn widget(mut self, widget: Widget) -> Self {
+        self.widget = widget;
+        self
+    }
+
+    /// Builder for optional fields
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    /// Render the label, input and help text of the field.
+    pub fn render(&self, value: Option<&str>, error: Option<&str>) -> String {
+        let value = escape_html(value.unwrap_or(""));
+        let required = if self.required { " required" } else { "" };
+        let input = match &self.widget {
+            Widget::Textarea => format!(
+                r#"<textarea id="id_{n}" name="{n}"{r}>{v}</textarea>"#,
+                n = self.name,
+                r = required,
+                v = value
+            ),
+            Widget::Checkbox => format!(
+                r#"<input type="checkbox" id="id_{n}" name="{n}" value="true"{c}>"#,
+                n = self.name,
+                c = if value == "true" { " checked" } else { "" }
+            ),
+            Widget::Select(options) => {
+                let opts: String = options
+                    .iter()
+                    .map(|(val, text)| {
+                        format!(
+                            r#"<option value="{}"{}>{}</option>"#,
+                            escape_html(val),
+                            if escape_html(val) == value {
+                                " selected"
+                            } else {
+                                ""
+                            },
+                            escape_html(text)
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"<select id="id_{n}" name="{n}"{r}>{o}</select>"#,
+                    n = self.name,
+                    r = required,
+                    o = opts
+                )
+            }
+            other => format!(
+                r#"<input type="{t}" id="id_{n}" name="{n}" value="{v}"{r}>"#,
+                t = match other {
+                    Widget::Email => "email",
+                    Widget::Password => "password",
+                    Widget::Number => "number",
+                    Widget::Date => "date",
+                    Widget::Hidden => "hidden",
+                    _ => "text",
+                },
+                n = self.name,
+                v = value,
+                r = required
+            ),
+        };
+        if self.widget == Widget::Hidden {
+            return input;
+        }
+        let mut html = format!(
+            r#"<div class="field"><label for="id_{}">{}</label>{}"#,
+            self.name,
+            escape_html(&self.label),
+            input
+        );
+        if let Some(help) = &self.help_text {
+            html.push_str(&format!(r#"<small class="help">{}</small>"#, escape_html(help)));
+        }
+        if let Some(error) = error {
+            html.push_str(&format!(r#"<span class="error">{}</span>"#, escape_html(error)));
+        }
+        html.push_str("</div>");
+        html
+    }
+}
+
+/// Implemented (usually derived) by types that can be edited through a form.
+pub trait FormFields {
+    fn form_fields() -> Vec<FormField>;
+}
+
+/// Render every field of `T` with the given values and errors.
+pub fn render_form<T: FormFields>(
+    values: &HashMap<String, String>,
+    errors: &HashMap<String, String>,
+) -> String {
+    T::form_fields()
+        .iter()
+        .map(|f| {
+            f.render(
+                values.get(&f.name).map(|s| s.as_str()),
+                errors.get(&f.name).map(|s| s.as_str()),
+            )
+        })
+        .collect()
+}
+
+/// `first_name` → `First name`
+pub fn humanize(name: &str) -> String {
+    let spaced = name.replace('_', " ");
+    let mut chars = spaced.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,371 @@
+//! Cobalto internationalization
+//!
+//! Translations live in gettext `.po` files, one per locale, in the directory
+//! set by `i18n.dir` (`locale/it.po`, `locale/pt-BR.po`), loaded by
+//! `Router::new`. Handlers translate with `gettext`/`ngettext`, templates with
+//! `{% trans %}` and `{% blocktrans %}`:
+//!
+//! ```text
+//! <h1>{% trans "Welcome" %}</h1>
+//! {% blocktrans %}Hello {{ user.name }}{% endblocktrans %}
+//! {% blocktrans count n=cart.items %}One item{% plural %}{{ n }} items{% endblocktrans %}
+//! ```
+//!
+//! The `msgid` of a `blocktrans` is its body with variables written as
+//! `{{ name }}`; translations use the same placeholders.
+//!
+//! The locale of a request is, in order: the one set with `with_locale`, the
+//! `locale` session key, the best `Accept-Language` match among the loaded
+//! catalogs, and `i18n.default_locale`. Untranslated messages fall back to the
+//! catalog of the base language (`pt` for `pt-BR`), then to the `msgid`.
+//! Plurals use the `n == 1` / `n != 1` rule.
+
+use crate::router::current_request;
+use crate::template::{TagArgs, TemplateValue, escape_html};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+
+/// I18n settings: `[i18n]` in the settings file, `COBALTO_I18N__DEFAULT_LOCALE`, ...
+#[derive(Clone, Debug)]
+pub struct I18nSettings {
+    /// Locale used when nothing better is known.
+    pub default_locale: String,
+    /// Directory holding the `<locale>.po` catalogs.
+    pub dir: String,
+}
+
+impl Default for I18nSettings {
+    fn default() -> Self {
+        I18nSettings {
+            default_locale: "en".to_string(),
+            dir: "locale".to_string(),
+        }
+    }
+}
+
+/// A `.po` file that could not be read.
+#[derive(Debug)]
+pub enum CatalogError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl std::fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatalogError::Io(e) => write!(f, "cannot read catalog: {}", e),
+            CatalogError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+/// Translations of one locale: `msgid` to its singular and plural forms.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Catalog {
+    messages: HashMap<String, Vec<String>>,
+}
+
+/// Separator gettext puts between `msgctxt` and `msgid`.
+const CONTEXT_SEPARATOR: char = '\u{4}';
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the text of a `.po` file.
+    pub fn parse_po(source: &str) -> Result<Catalog, CatalogError> {
+        #[derive(Default)]
+        struct Entry {
+            context: Option<String>,
+            id: Option<String>,
+            forms: Vec<String>,
+        }
+        enum Field {
+            Context,
+            Id,
+            Plural,
+            Form(usize),
+        }
+
+        let mut catalog = Catalog::new();
+        let mut entry = Entry::default();
+        let mut field: Option<Field> = None;
+        let mut flush = |entry: &mut Entry| {
+            let entry = std::mem::take(entry);
+            if let Some(id) = entry.id
+                && !id.is_empty()
+                && entry.forms.iter().any(|f| !f.is_empty())
+            {
+                let key = match entry.context {
+                    Some(context) => format!("{}{}{}", context, CONTEXT_SEPARATOR, id),
+                    None => id,
+                };
+                catalog.messages.insert(key, entry.forms);
+            }
+        };
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            let error = |message: &str| CatalogError::Parse {
+                line: number + 1,
+                message: message.to_string(),
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = match line.split_once(char::is_whitespace) {
+                Some((keyword, rest)) if !line.starts_with('"') => (keyword, rest.trim()),
+                _ => ("", line),
+            };
+            let value = unquote(rest).ok_or_else(|| error("expected a quoted string"))?;
+            match keyword {
+                "msgctxt" => {
+                    flush(&mut entry);
+                    entry.context = Some(value);
+                    field = Some(Field::Context);
+                }
+                "msgid" => {
+                    if entry.id.is_some() {
+                        flush(&mut entry);
+                    }
+                    entry.id = Some(value);
+                    field = Some(Field::Id);
+                }
+                "msgid_plural" => field = Some(Field::Plural),
+                "msgstr" => {
+                    entry.forms = vec![value];
+                    field = Some(Field::Form(0));
+                }
+                _ if keyword.starts_with("msgstr[") => {
+                    let index: usize = keyword["msgstr[".len()..]
+                        .trim_end_matches(']')
+                        .parse()
+                        .map_err(|_| error("invalid plural index"))?;
+                    if entry.forms.len() <= index {
+                        entry.forms.resize(index + 1, String::new());
+                    }
+                    entry.forms[index] = value;
+                    field = Some(Field::Form(index));
+                }
+                // A continuation line of the previous string
+                "" => match field {
+                    Some(Field::Context) => entry.context.get_or_insert_default().push_str(&value),
+                    Some(Field::Id) => entry.id.get_or_insert_default().push_str(&value),
+                    Some(Field::Plural) => {}
+                    Some(Field::Form(index)) => entry.forms[index].push_str(&value),
+                    None => return Err(error("string outside of an entry")),
+                },
+                other => return Err(error(&format!("unknown keyword `{}`", other))),
+            }
+        }
+        flush(&mut entry);
+        Ok(catalog)
+    }
+
+    /// Read and parse a `.po` file.
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Catalog, CatalogError> {
+        Catalog::parse_po(&std::fs::read_to_string(path).map_err(CatalogError::Io)?)
+    }
+
+    /// Add a translation, e.g. for catalogs built in code.
+    pub fn insert(&mut self, msgid: &str, msgstr: &str) {
+        self.messages
+            .insert(msgid.to_string(), vec![msgstr.to_string()]);
+    }
+
+    /// The translation of `msgid`, in the plural form for `n`.
+    pub fn translate(&self, msgid: &str, n: Option<u64>) -> Option<&str> {
+        let forms = self.messages.get(msgid)?;
+        let index = match n {
+            Some(1) | None => 0,
+            Some(_) => 1,
+        };
+        forms
+            .get(index)
+            .or(forms.first())
+            .map(String::as_str)
+            .filter(|s| !s.is_empty())
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// A quoted `.po` string with its escapes resolved.
+fn unquote(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            other => out.push(other),
+        }
+    }
+    Some(out)
+}
+
+/// `pt_BR`, `PT-br` -> `pt-br`.
+fn normalize(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+struct Registry {
+    catalogs: HashMap<String, Catalog>,
+    default_locale: String,
+}
+
+static REGISTRY: Lazy<RwLock<Registry>> = Lazy::new(|| {
+    RwLock::new(Registry {
+        catalogs: HashMap::new(),
+        default_locale: "en".to_string(),
+    })
+});
+
+tokio::task_local! {
+    static LOCALE: String;
+}
+
+/// Use `settings` and load every `<locale>.po` of its directory (done by
+/// `Router::new`). Broken catalogs are logged and skipped.
+pub fn configure(settings: &I18nSettings) {
+    REGISTRY.write().unwrap().default_locale = normalize(&settings.default_locale);
+    let Ok(entries) = std::fs::read_dir(&settings.dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.extension().is_none_or(|e| e != "po") {
+            continue;
+        }
+        let Some(locale) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        match Catalog::from_file(&path) {
+            Ok(catalog) => add_catalog(locale, catalog),
+            Err(e) => log::warn!("skipping catalog {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Install the translations for `locale`, replacing any previous ones.
+pub fn add_catalog(locale: &str, catalog: Catalog) {
+    REGISTRY
+        .write()
+        .unwrap()
+        .catalogs
+        .insert(normalize(locale), catalog);
+}
+
+/// Locales with a catalog, sorted.
+pub fn available_locales() -> Vec<String> {
+    let mut locales: Vec<String> = REGISTRY.read().unwrap().catalogs.keys().cloned().collect();
+    locales.sort();
+    locales
+}
+
+/// The best of `available` for an `Accept-Language` header, by quality;
+/// `fr-CH` also matches `fr`.
+pub fn negotiate(accept_language: &str, available: &[String]) -> Option<String> {
+    let mut wanted: Vec<(String, f32)> = accept_language
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let tag = normalize(params.next()?);
+            let quality = params
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    wanted.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let available: Vec<String> = available.iter().map(|l| normalize(l)).collect();
+    wanted.iter().find_map(|(tag, _)| {
+        let base = tag.split('-').next().unwrap_or(tag);
+        available
+            .iter()
+            .find(|l| *l == tag)
+            .or_else(|| available.iter().find(|l| *l == base))
+            .cloned()
+    })
+}
+
+/// Run `fut` with `locale` as the current locale, e.g. to render an email in
+/// the recipient's language.
+pub async fn with_locale<F: Future>(locale: &str, fut: F) -> F::Output {
+    LOCALE.scope(normalize(locale), fut).await
+}
+
+/// `with_locale` for synchronous code.
+pub(crate) fn with_locale_sync<R>(locale: &str, f: impl FnOnce() -> R) -> R {
+    LOCALE.sync_scope(normalize(locale), f)
+}
+
+/// The locale messages are translated to right now.
+pub fn current_locale() -> String {
+    if let Ok(locale) = LOCALE.try_with(|l| l.clone()) {
+        return locale;
+    }
+    if let Some(locale) = crate::session::current_session().and_then(|s| s.get("locale")) {
+        return normalize(&locale);
+    }
+    if let Some(header) =
+        current_request().and_then(|scope| scope.headers.get("accept-language").cloned())
+        && let Some(locale) = negotiate(&header, &available_locales())
+    {
+        return locale;
+    }
+    REGISTRY.read().unwrap().default_locale.clone()
+}
+
+/// `msgid` translated to `locale`, in the plural form for `n`.
+fn lookup(locale: &str, msgid: &str, n: Option<u64>) -> Option<String> {
+    let registry = REGISTRY.read().unwrap();
+    let locale = normalize(locale);
+    let base = locale.split('-').next().unwrap_or(&locale);
+    [locale.as_str(), base]
+        .iter()
+        .filter_map(|l| registry.catalogs.get(*l))
+        .find_map(|c| c.translate(msgid, n))
+        .map(str::to_string)
+}
+
+/// `msgid` in the current locale, or `msgid` itself when untranslated.
+pub fn gettext(msgid: &str) -> String {
+    lookup(&current_locale(), msgid, None).unwrap_or_else(|| msgid.to_string())
+}
+
+/// `singular` or `plural` for `n`, in the current locale.
+pub fn ngettext(singular: &str, plural: &str, n: u64) -> String {
+    lookup(&current_locale(), singular, Some(n))
+        .unwrap_or_else(|| if n == 1 { singular } else { plural }.to_string())
+}
+
+/// `msgid` in `context` (`msgctxt` in the catalog), for words that translate
+/// differently depending on use.
+pub fn pgettext(context: &str, msgid: &str) -> String {
+    let key = format!("{}{}{}", context, CONTEXT_SEPARATOR, msgid);
+    lookup(&current_locale(), &key, None).unwrap_or_else(|| msgid.to_string())
+}
+
+/// `{% trans "Welcome" %}`: the translated, escaped string.
+pub fn trans_tag(args: &TagArgs, context: &HashMap<String, TemplateValue>) -> String {
+    let Some(msgid) = args.positional.first() else {
+        return String::new();
+    };
+    escape_html(&gettext(&TagArgs::resolve(msgid, context)))
+}
@@ -0,0 +1,199 @@
+//! Cobalto ID generation
+//!
+//! Application-side primary keys for `#[cobalto(primary_key, auto)]` fields
+//! (`Field::auto_id`), filled in by `Model::save` on insert,
+//! for when database autoincrement is not an option (sharding, publicly
+//! exposed IDs). The default strategy is UUIDv7 (time-ordered, RFC 9562);
+//! `snowflake` yields ordered 64-bit integers made of a millisecond timestamp,
+//! a node id and a per-millisecond sequence. Timestamps come from
+//! `clock::now()`, so frozen time gives predictable prefixes in tests.
+//!
+//! Configured through `Settings.other`: `id_strategy = "uuid7" | "snowflake"`
+//! and `id_node` (0–1023) for snowflakes.
+
+use crate::clock;
+use crate::settings::Settings;
+use once_cell::sync::Lazy;
+use std::fmt;
+use std::sync::{Mutex, RwLock};
+
+/// Snowflake epoch: 2024-01-01T00:00:00Z, in Unix milliseconds.
+pub const SNOWFLAKE_EPOCH_MS: i64 = 1_704_067_200_000;
+const NODE_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+const MAX_SEQUENCE: u16 = (1 << SEQUENCE_BITS) - 1;
+
+/// How new IDs are generated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdStrategy {
+    #[default]
+    UuidV7,
+    Snowflake {
+        node: u16,
+    },
+}
+
+impl IdStrategy {
+    /// Read `id_strategy` / `id_node` from settings.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        match settings.other.get("id_strategy")?.as_str() {
+            "uuid7" | "uuidv7" => Some(IdStrategy::UuidV7),
+            "snowflake" => {
+                let node = settings
+                    .other
+                    .get("id_node")
+                    .and_then(|n| n.parse::<u16>().ok())
+                    .unwrap_or(0);
+                Some(IdStrategy::Snowflake {
+                    node: node & ((1 << NODE_BITS) - 1),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A freshly generated ID.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GeneratedId {
+    Uuid(String),
+    Snowflake(i64),
+}
+
+impl fmt::Display for GeneratedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratedId::Uuid(s) => write!(f, "{}", s),
+            GeneratedId::Snowflake(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+fn random_u64() -> u64 {
+    rand::random()
+}
+
+/// Monotonic generator state: last timestamp and sequence within it.
+#[derive(Default)]
+struct Sequence {
+    last_ms: i64,
+    counter: u16,
+}
+
+impl Sequence {
+    /// Next (timestamp, counter) pair, never going backwards; when the counter
+    /// overflows the timestamp is advanced by one millisecond.
+    fn next(&mut self, seed: impl FnOnce() -> u16) -> (i64, u16) {
+        let now = clock::now().timestamp_millis();
+        if now > self.last_ms {
+            self.last_ms = now;
+            self.counter = seed();
+        } else if self.counter >= MAX_SEQUENCE {
+            self.last_ms += 1;
+            self.counter = seed();
+        } else {
+            self.counter += 1;
+        }
+        (self.last_ms, self.counter)
+    }
+}
+
+/// Thread-safe ID generator.
+#[derive(Default)]
+pub struct IdGenerator {
+    pub strategy: IdStrategy,
+    uuid_seq: Mutex<Sequence>,
+    snowflake_seq: Mutex<Sequence>,
+}
+
+impl IdGenerator {
+    pub fn new(strategy: IdStrategy) -> Self {
+        IdGenerator {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    /// Next ID according to the configured strategy.
+    pub fn next(&self) -> GeneratedId {
+        match self.strategy {
+            IdStrategy::UuidV7 => GeneratedId::Uuid(self.uuid_v7()),
+            IdStrategy::Snowflake { .. } => GeneratedId::Snowflake(self.snowflake()),
+        }
+    }
+
+    /// A UUIDv7 string; the 12-bit `rand_a` field is a counter so IDs created
+    /// in the same millisecond still sort in creation order.
+    pub fn uuid_v7(&self) -> String {
+        // Counter starts in the lower half so there is room to increment
+        let (ms, counter) = self
+            .uuid_seq
+            .lock()
+            .unwrap()
+            .next(|| (random_u64() & 0x7ff) as u16);
+        let rand_b = random_u64() & 0x3fff_ffff_ffff_ffff;
+        let ms = ms as u64 & 0xffff_ffff_ffff;
+        let hi = (ms << 16) | (0x7 << 12) | counter as u64;
+        let lo = (0b10 << 62) | rand_b;
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            hi >> 32,
+            (hi >> 16) & 0xffff,
+            hi & 0xffff,
+            lo >> 48,
+            lo & 0xffff_ffff_ffff
+        )
+    }
+
+    /// A snowflake: 41-bit milliseconds since `SNOWFLAKE_EPOCH_MS`, 10-bit
+    /// node, 12-bit sequence.
+    pub fn snowflake(&self) -> i64 {
+        let node = match self.strategy {
+            IdStrategy::Snowflake { node } => node as i64,
+            IdStrategy::UuidV7 => 0,
+        };
+        let (ms, seq) = self.snowflake_seq.lock().unwrap().next(|| 0);
+        ((ms - SNOWFLAKE_EPOCH_MS) << (NODE_BITS + SEQUENCE_BITS))
+            | (node << SEQUENCE_BITS)
+            | seq as i64
+    }
+}
+
+/// Split a snowflake into (Unix milliseconds, node, sequence).
+pub fn snowflake_parts(id: i64) -> (i64, u16, u16) {
+    let ms = (id >> (NODE_BITS + SEQUENCE_BITS)) + SNOWFLAKE_EPOCH_MS;
+    let node = ((id >> SEQUENCE_BITS) & ((1 << NODE_BITS) - 1)) as u16;
+    let seq = (id & MAX_SEQUENCE as i64) as u16;
+    (ms, node, seq)
+}
+
+static GENERATOR: Lazy<RwLock<IdGenerator>> = Lazy::new(|| RwLock::new(IdGenerator::default()));
+
+/// Install the global strategy, normally from settings.
+pub fn set_id_strategy(strategy: IdStrategy) {
+    *GENERATOR.write().unwrap() = IdGenerator::new(strategy);
+}
+
+/// Next ID from the global generator.
+pub fn next_id() -> GeneratedId {
+    GENERATOR.read().unwrap().next()
+}
+
+/// Field types that can be filled by `#[cobalto(primary_key, auto)]`.
+pub trait AutoId {
+    fn auto_id() -> Self;
+}
+
+impl AutoId for String {
+    /// The global strategy's ID, rendered as a string.
+    fn auto_id() -> Self {
+        next_id().to_string()
+    }
+}
+
+impl AutoId for i64 {
+    /// Always a snowflake, since a UUID does not fit in 64 bits.
+    fn auto_id() -> Self {
+        GENERATOR.read().unwrap().snowflake()
+    }
+}
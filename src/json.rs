@@ -0,0 +1,146 @@
+//! Cobalto JSON bodies
+//!
+//! Field names are left to serde: a type sent to (or read from) a JavaScript
+//! frontend declares its case with `#[serde(rename_all = "camelCase")]`, and
+//! `#[serde(alias = "...")]` accepts a second spelling on input. Keys are
+//! never rewritten after serialization, so map keys and explicit renames come
+//! out as written.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! #[serde(rename_all = "camelCase")]
+//! struct Profile {
+//!     user_name: String,            // "userName"
+//!     scores: HashMap<String, u32>, // keys kept as they are
+//! }
+//! ```
+//!
+//! Request bodies larger than the `max_json_bytes` setting are refused with a
+//! 413, and with `strict_json` on a body whose content type is not JSON gets a
+//! 415.
+
+use crate::router::{IntoResponse, Response};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static MAX_JSON_BYTES: AtomicUsize = AtomicUsize::new(1024 * 1024);
+static STRICT_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Set the JSON body size limit and content-type strictness (done by
+/// `Router::new` from the settings).
+pub fn set_json_limits(max_bytes: usize, strict: bool) {
+    MAX_JSON_BYTES.store(max_bytes, Ordering::Relaxed);
+    STRICT_JSON.store(strict, Ordering::Relaxed);
+}
+
+/// Why a request body could not be read as JSON.
+#[derive(Debug)]
+pub enum JsonError {
+    /// The body is larger than `max_json_bytes`.
+    TooLarge { limit: usize },
+    /// `strict_json` is on and the content type is not JSON.
+    UnsupportedMediaType(String),
+    /// Malformed JSON, or JSON of the wrong shape.
+    Invalid(serde_json::Error),
+}
+
+impl JsonError {
+    /// 413, 415, 400 for malformed JSON and 422 for the wrong shape.
+    pub fn status(&self) -> u16 {
+        match self {
+            JsonError::TooLarge { .. } => 413,
+            JsonError::UnsupportedMediaType(_) => 415,
+            JsonError::Invalid(e) => match e.classify() {
+                serde_json::error::Category::Data => 422,
+                _ => 400,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::TooLarge { limit } => {
+                write!(f, "JSON body larger than {} bytes", limit)
+            }
+            JsonError::UnsupportedMediaType(content_type) if content_type.is_empty() => {
+                write!(f, "expected content type application/json")
+            }
+            JsonError::UnsupportedMediaType(content_type) => {
+                write!(
+                    f,
+                    "expected content type application/json, got {}",
+                    content_type
+                )
+            }
+            JsonError::Invalid(e) => write!(f, "invalid JSON body: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(e: serde_json::Error) -> Self {
+        JsonError::Invalid(e)
+    }
+}
+
+impl IntoResponse for JsonError {
+    fn into_response(self) -> Response {
+        Response::json(serde_json::json!({"error": self.to_string()})).with_status(self.status())
+    }
+}
+
+/// `application/json` or any `+json` type, parameters ignored.
+fn is_json_content_type(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+/// Parse a request body sent with `content_type`, applying the configured
+/// size limit and, when `strict_json` is on, the content-type check.
+pub fn parse_body<T: serde::de::DeserializeOwned>(
+    body: &str,
+    content_type: Option<&str>,
+) -> Result<T, JsonError> {
+    parse_body_with(
+        body,
+        content_type,
+        MAX_JSON_BYTES.load(Ordering::Relaxed),
+        STRICT_JSON.load(Ordering::Relaxed),
+    )
+}
+
+/// `parse_body` with an explicit limit and strictness.
+pub fn parse_body_with<T: serde::de::DeserializeOwned>(
+    body: &str,
+    content_type: Option<&str>,
+    max_bytes: usize,
+    strict: bool,
+) -> Result<T, JsonError> {
+    check_body(body, content_type, max_bytes, strict)?;
+    Ok(serde_json::from_str(body)?)
+}
+
+fn check_body(
+    body: &str,
+    content_type: Option<&str>,
+    max_bytes: usize,
+    strict: bool,
+) -> Result<(), JsonError> {
+    if body.len() > max_bytes {
+        return Err(JsonError::TooLarge { limit: max_bytes });
+    }
+    if strict && !content_type.is_some_and(is_json_content_type) {
+        return Err(JsonError::UnsupportedMediaType(
+            content_type.unwrap_or_default().to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -1,3 +1,61 @@
+pub mod admin;
+#[cfg(feature = "alloc-stats")]
+pub mod alloc_stats;
+pub mod app;
+pub mod cache;
+pub mod channels;
+pub mod coalesce;
+pub mod compress;
+pub mod contract;
+pub mod cookie;
+pub mod clock;
+pub mod datatable;
+pub mod debug_page;
+pub mod error_pages;
+pub mod extract;
+pub mod forms;
+pub mod i18n;
+pub mod ids;
+pub mod json;
+pub mod linkcheck;
+pub mod logging;
+pub mod mail;
+pub mod manage;
+pub mod metrics;
+pub mod minify;
+#[cfg(feature = "mirror")]
+pub mod mirror;
+pub mod multipart;
+pub mod obfuscate;
 pub mod orm;
+pub mod paginator;
+#[cfg(feature = "payments")]
+pub mod payments;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod plugins;
+pub mod progress;
+pub mod proxy;
+pub mod pubsub;
+pub mod qr;
+pub mod quota;
+pub mod reload;
+#[cfg(feature = "html-rewrite")]
+pub mod rewrite;
+pub mod route_tree;
 pub mod router;
+pub mod scaffold;
+pub mod session;
 pub mod settings;
+pub mod signals;
+pub mod sse;
+pub mod state;
+pub mod staticfiles;
+pub mod supervisor;
+pub mod tasks;
+pub mod template;
+pub mod test;
+pub mod throttle;
+pub mod tls;
+pub mod websocket;
+pub mod wizard;
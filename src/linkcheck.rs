@@ -0,0 +1,132 @@
+//! Cobalto link checker
+//!
+//! Crawls the application in-process (through `Router::dispatch`), following
+//! internal links found in rendered HTML, and reports pages answering with an
+//! error status and redirect loops. Meant to run before releases of
+//! content-heavy sites.
+
+use crate::router::Router;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{HashSet, VecDeque};
+
+static LINK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)\b(?:href|src)\s*=\s*["']([^"'#]*)"#).unwrap());
+
+/// Maximum redirects followed from a single link.
+const MAX_REDIRECTS: usize = 10;
+
+/// A problem found while crawling.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkIssue {
+    /// A page answered with 4xx/5xx: (url, status, page linking to it).
+    Broken {
+        url: String,
+        status: u16,
+        referrer: Option<String>,
+    },
+    /// Following redirects came back to an already visited URL.
+    RedirectLoop { chain: Vec<String> },
+}
+
+/// Result of a crawl.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkReport {
+    pub checked: Vec<String>,
+    pub issues: Vec<LinkIssue>,
+}
+
+impl LinkReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Print a human readable summary (the `cobalto linkcheck` output).
+    pub fn print(&self) {
+        println!("Checked {} URLs", self.checked.len());
+        for issue in &self.issues {
+            match issue {
+                LinkIssue::Broken {
+                    url,
+                    status,
+                    referrer,
+                } => println!(
+                    "  {} {} (linked from {})",
+                    status,
+                    url,
+                    referrer.as_deref().unwrap_or("<start>")
+                ),
+                LinkIssue::RedirectLoop { chain } => {
+                    println!("  redirect loop: {}", chain.join(" -> "))
+                }
+            }
+        }
+    }
+}
+
+/// Extract internal (same-site, absolute-path) links from an HTML page.
+pub fn extract_links(html: &str) -> Vec<String> {
+    LINK_RE
+        .captures_iter(html)
+        .filter_map(|c| c.get(1))
+        .map(|m| m.as_str().trim().to_string())
+        .filter(|href| href.starts_with('/') && !href.starts_with("//"))
+        .collect()
+}
+
+/// Crawl from `start` (or from every parameterless GET route when empty).
+pub async fn linkcheck(router: &Router, start: &[&str]) -> LinkReport {
+    let mut queue: VecDeque<(String, Option<String>)> = if start.is_empty() {
+        router
+            .routes
+            .iter()
+            .filter(|r| r.method == "GET" && !r.path.contains(':'))
+            .map(|r| (r.path.clone(), None))
+            .collect()
+    } else {
+        start.iter().map(|s| (s.to_string(), None)).collect()
+    };
+    let mut seen: HashSet<String> = queue.iter().map(|(u, _)| u.clone()).collect();
+    let mut report = LinkReport::default();
+
+    while let Some((url, referrer)) = queue.pop_front() {
+        report.checked.push(url.clone());
+        let mut chain = vec![url.clone()];
+        let mut resp = router.dispatch("GET", &url, "").await;
+        // Follow redirects, detecting loops
+        while (300..400).contains(&resp.status) {
+            let location = resp
+                .headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case("location"))
+                .map(|(_, v)| v.clone());
+            let Some(location) = location.filter(|l| l.starts_with('/')) else {
+                break;
+            };
+            if chain.contains(&location) || chain.len() > MAX_REDIRECTS {
+                chain.push(location);
+                report.issues.push(LinkIssue::RedirectLoop {
+                    chain: chain.clone(),
+                });
+                break;
+            }
+            chain.push(location.clone());
+            resp = router.dispatch("GET", &location, "").await;
+        }
+        if resp.status >= 400 {
+            report.issues.push(LinkIssue::Broken {
+                url: chain.last().cloned().unwrap_or(url),
+                status: resp.status,
+                referrer,
+            });
+            continue;
+        }
+        let page = chain.last().cloned().unwrap_or_default();
+        for link in extract_links(&resp.body) {
+            if seen.insert(link.clone()) {
+                queue.push_back((link, Some(page.clone())));
+            }
+        }
+    }
+    report
+}
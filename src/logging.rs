@@ -0,0 +1,235 @@
+//! Cobalto logging
+//!
+//! Request lines and other framework events go through one global logger,
+//! configured from `Settings.log` by `Router::new`: a minimum level, a `text`
+//! or `json` format, and a sink (stdout by default; `tracing_sink()` forwards
+//! to `tracing`, or install any closure with `set_sink`). Middleware and
+//! handlers attach fields to the current request's log line with `add_field`:
+//!
+//! ```ignore
+//! router.add_middleware(Arc::new(|ctx| {
+//!     cobalto::logging::add_field("request_id", new_request_id());
+//!     None
+//! }));
+//! ```
+
+use log::{Level, LevelFilter};
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// How records are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `[2025-01-01 12:00:00] INFO GET /users 200 duration_ms=3 ip=127.0.0.1`
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format `{}`", s)),
+        }
+    }
+}
+
+/// One log event.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub time: chrono::DateTime<chrono::Local>,
+    pub level: Level,
+    pub message: String,
+    /// Structured fields in insertion order.
+    pub fields: Vec<(String, Value)>,
+}
+
+impl LogRecord {
+    pub fn new<S: Into<String>>(level: Level, message: S) -> Self {
+        LogRecord {
+            time: chrono::Local::now(),
+            level,
+            message: message.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Builder adding a field
+    pub fn field<K: Into<String>, V: Into<Value>>(mut self, key: K, value: V) -> Self {
+        self.fields.push((key.into(), value.into()));
+        self
+    }
+
+    /// The record as a single line in `format`.
+    pub fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => {
+                let mut line = format!(
+                    "[{}] {} {}",
+                    self.time.format("%Y-%m-%d %H:%M:%S"),
+                    self.level,
+                    self.message
+                );
+                for (key, value) in &self.fields {
+                    match value {
+                        Value::String(s) if !s.contains(char::is_whitespace) => {
+                            line.push_str(&format!(" {}={}", key, s))
+                        }
+                        other => line.push_str(&format!(" {}={}", key, other)),
+                    }
+                }
+                line
+            }
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("time".into(), self.time.to_rfc3339().into());
+                object.insert("level".into(), self.level.as_str().into());
+                object.insert("message".into(), self.message.clone().into());
+                for (key, value) in &self.fields {
+                    object.insert(key.clone(), value.clone());
+                }
+                Value::Object(object).to_string()
+            }
+        }
+    }
+}
+
+/// Receives each record that passes the level filter, with its formatted line.
+pub type LogSink = Arc<dyn Fn(&LogRecord, &str) + Send + Sync>;
+
+struct Logger {
+    level: LevelFilter,
+    format: LogFormat,
+    sink: LogSink,
+}
+
+static LOGGER: Lazy<RwLock<Logger>> = Lazy::new(|| {
+    RwLock::new(Logger {
+        level: LevelFilter::Info,
+        format: LogFormat::Text,
+        sink: stdout_sink(),
+    })
+});
+
+/// Log settings: `[log]` in the settings file, `COBALTO_LOG__LEVEL`, ...
+#[derive(Clone, Debug)]
+pub struct LogSettings {
+    pub level: LevelFilter,
+    pub format: LogFormat,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            level: LevelFilter::Info,
+            format: LogFormat::Text,
+        }
+    }
+}
+
+/// Apply level and format (done by `Router::new`); the sink is kept.
+pub fn configure(settings: &LogSettings) {
+    let mut logger = LOGGER.write().unwrap();
+    logger.level = settings.level;
+    logger.format = settings.format;
+}
+
+/// Replace where records go.
+pub fn set_sink<F>(sink: F)
+where
+    F: Fn(&LogRecord, &str) + Send + Sync + 'static,
+{
+    LOGGER.write().unwrap().sink = Arc::new(sink);
+}
+
+/// Print formatted lines to stdout (the default).
+pub fn stdout_sink() -> LogSink {
+    Arc::new(|_, line| println!("{}", line))
+}
+
+/// Emit records as `tracing` events under the `cobalto` target, fields
+/// included as a JSON `fields` value; the format setting is ignored.
+pub fn tracing_sink() -> LogSink {
+    Arc::new(|record, _| {
+        let fields = Value::Object(record.fields.iter().cloned().collect()).to_string();
+        let message = record.message.as_str();
+        match record.level {
+            Level::Error => tracing::error!(target: "cobalto", fields = %fields, "{}", message),
+            Level::Warn => tracing::warn!(target: "cobalto", fields = %fields, "{}", message),
+            Level::Info => tracing::info!(target: "cobalto", fields = %fields, "{}", message),
+            Level::Debug => tracing::debug!(target: "cobalto", fields = %fields, "{}", message),
+            Level::Trace => tracing::trace!(target: "cobalto", fields = %fields, "{}", message),
+        }
+    })
+}
+
+/// Whether records at `level` are written.
+pub fn enabled(level: Level) -> bool {
+    level <= LOGGER.read().unwrap().level
+}
+
+/// Write `record`, adding the fields collected for the current request.
+pub fn log(mut record: LogRecord) {
+    let (format, sink) = {
+        let logger = LOGGER.read().unwrap();
+        if record.level > logger.level {
+            return;
+        }
+        (logger.format, logger.sink.clone())
+    };
+    if let Ok(fields) = REQUEST_FIELDS.try_with(|f| f.lock().unwrap().clone()) {
+        record.fields.extend(fields);
+    }
+    let line = record.format(format);
+    sink(&record, &line);
+}
+
+tokio::task_local! {
+    static REQUEST_FIELDS: Arc<Mutex<Vec<(String, Value)>>>;
+}
+
+/// Attach a field to log records of the current request; a no-op outside one.
+pub fn add_field<K: Into<String>, V: Into<Value>>(key: K, value: V) {
+    let (key, value) = (key.into(), value.into());
+    let _ = REQUEST_FIELDS.try_with(|fields| {
+        let mut fields = fields.lock().unwrap();
+        fields.retain(|(k, _)| *k != key);
+        fields.push((key, value));
+    });
+}
+
+/// Run `fut` with its own set of request log fields.
+pub async fn scope<F: std::future::Future>(fut: F) -> F::Output {
+    REQUEST_FIELDS
+        .scope(Arc::new(Mutex::new(Vec::new())), fut)
+        .await
+}
+
+/// Log a served request: `GET /users 200 duration_ms=3 ip=...`.
+///
+/// Server errors log at `Error`, client errors at `Warn`, the rest at `Info`.
+pub fn log_request(method: &str, path: &str, status: u16, duration_ms: Option<u128>, ip: &str) {
+    let level = match status {
+        500.. => Level::Error,
+        400..=499 => Level::Warn,
+        _ => Level::Info,
+    };
+    let mut record = LogRecord::new(level, format!("{} {} {}", method, path, status));
+    if LOGGER.read().unwrap().format == LogFormat::Json {
+        // Already readable in the text message; separate keys for JSON consumers
+        record = record
+            .field("method", method)
+            .field("path", path)
+            .field("status", status);
+    }
+    if let Some(ms) = duration_ms {
+        record = record.field("duration_ms", ms as u64);
+    }
+    log(record.field("ip", ip))
+}
@@ -0,0 +1,398 @@
+//! Cobalto mail
+//!
+//! ```ignore
+//! let mut ctx = HashMap::new();
+//! ctx.insert("name".to_string(), TemplateValue::String(user.name.clone()));
+//! mail::send(
+//!     Message::new()
+//!         .to(&user.email)
+//!         .subject("Welcome!")
+//!         .body_template("emails/welcome.html", ctx),
+//! )
+//! .await?;
+//! ```
+//!
+//! Where mail goes is set by `[mail]` in the settings file (applied by
+//! `Router::new`):
+//!
+//! - `backend = "console"` (the default) prints each message to stdout;
+//! - `backend = "file"` writes one `.eml` file per message to `mail.file_dir`;
+//! - `backend = "memory"` keeps messages in `outbox()`, for tests;
+//! - `backend = "smtp"` sends through `mail.host`/`mail.port`, with STARTTLS
+//!   unless `mail.starttls = false` and login when `mail.username` is set.
+//!
+//! `send_later` hands the message to a background task and only logs failures.
+
+use crate::logging::LogRecord;
+use crate::settings::Settings;
+use crate::template::{TemplateEngine, TemplateValue};
+use lettre::message::header::ContentType;
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use log::Level;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Where messages are delivered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MailBackend {
+    #[default]
+    Console,
+    File,
+    Memory,
+    Smtp,
+}
+
+impl std::str::FromStr for MailBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "console" => Ok(MailBackend::Console),
+            "file" => Ok(MailBackend::File),
+            "memory" => Ok(MailBackend::Memory),
+            "smtp" => Ok(MailBackend::Smtp),
+            _ => Err(format!("unknown mail backend `{}`", s)),
+        }
+    }
+}
+
+/// Mail settings: `[mail]` in the settings file, `COBALTO_MAIL__HOST`, ...
+#[derive(Clone, Debug)]
+pub struct MailSettings {
+    pub backend: MailBackend,
+    /// Sender used when a message has no `from`.
+    pub from: String,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Upgrade the SMTP connection with STARTTLS.
+    pub starttls: bool,
+    /// Directory the `file` backend writes to.
+    pub file_dir: String,
+}
+
+impl Default for MailSettings {
+    fn default() -> Self {
+        MailSettings {
+            backend: MailBackend::Console,
+            from: "webmaster@localhost".to_string(),
+            host: "localhost".to_string(),
+            port: 587,
+            username: None,
+            password: None,
+            starttls: true,
+            file_dir: "mail".to_string(),
+        }
+    }
+}
+
+/// Why a message could not be sent.
+#[derive(Debug)]
+pub enum MailError {
+    /// An address that doesn't parse, or no recipients at all.
+    Address(String),
+    /// The body template is missing.
+    Template(String),
+    Smtp(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::Address(reason) => write!(f, "invalid address: {}", reason),
+            MailError::Template(name) => write!(f, "mail template '{}' not found", name),
+            MailError::Smtp(reason) => write!(f, "SMTP error: {}", reason),
+            MailError::Io(e) => write!(f, "cannot write mail: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+impl From<std::io::Error> for MailError {
+    fn from(e: std::io::Error) -> Self {
+        MailError::Io(e)
+    }
+}
+
+/// An email being built. Addresses are `user@example.com` or
+/// `Name <user@example.com>`.
+#[derive(Clone, Default)]
+pub struct Message {
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    /// Plain-text body.
+    pub text: Option<String>,
+    /// HTML body; sent along with `text` when both are set.
+    pub html: Option<String>,
+    /// Template rendered into the body when the message is sent.
+    pub template: Option<(String, HashMap<String, TemplateValue>)>,
+}
+
+impl Message {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder for the sender, instead of `mail.from`
+    pub fn from(mut self, address: &str) -> Self {
+        self.from = Some(address.to_string());
+        self
+    }
+
+    /// Builder adding a recipient
+    pub fn to(mut self, address: &str) -> Self {
+        self.to.push(address.to_string());
+        self
+    }
+
+    /// Builder adding a carbon-copy recipient
+    pub fn cc(mut self, address: &str) -> Self {
+        self.cc.push(address.to_string());
+        self
+    }
+
+    /// Builder adding a blind-copy recipient
+    pub fn bcc(mut self, address: &str) -> Self {
+        self.bcc.push(address.to_string());
+        self
+    }
+
+    /// Builder for the Reply-To address
+    pub fn reply_to(mut self, address: &str) -> Self {
+        self.reply_to = Some(address.to_string());
+        self
+    }
+
+    /// Builder for the subject
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.subject = subject.to_string();
+        self
+    }
+
+    /// Builder for the plain-text body
+    pub fn body(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    /// Builder for the HTML body
+    pub fn html(mut self, html: &str) -> Self {
+        self.html = Some(html.to_string());
+        self
+    }
+
+    /// Builder rendering template `name` from the template directory as the
+    /// body: HTML for `.html` templates, plain text otherwise
+    pub fn body_template(mut self, name: &str, context: HashMap<String, TemplateValue>) -> Self {
+        self.template = Some((name.to_string(), context));
+        self
+    }
+
+    /// Every recipient: `to`, `cc` and `bcc`.
+    pub fn recipients(&self) -> impl Iterator<Item = &String> {
+        self.to.iter().chain(&self.cc).chain(&self.bcc)
+    }
+}
+
+/// Delivers messages according to `MailSettings`; cheap to clone.
+#[derive(Clone, Default)]
+pub struct Mailer {
+    settings: MailSettings,
+    template_dir: String,
+    outbox: Arc<Mutex<Vec<Message>>>,
+}
+
+static MAILER: Lazy<RwLock<Mailer>> = Lazy::new(|| RwLock::new(Mailer::default()));
+static FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Use the mail settings and template directory of `settings` (done by
+/// `Router::new`).
+pub fn configure(settings: &Settings) {
+    *MAILER.write().unwrap() = Mailer::new(settings.mail.clone(), &settings.template.dir);
+}
+
+/// The configured mailer.
+pub fn mailer() -> Mailer {
+    MAILER.read().unwrap().clone()
+}
+
+/// Send `message` with the configured mailer.
+pub async fn send(message: Message) -> Result<(), MailError> {
+    mailer().send(message).await
+}
+
+/// Send `message` in the background; failures are logged.
+pub fn send_later(message: Message) -> tokio::task::JoinHandle<()> {
+    let mailer = mailer();
+    tokio::spawn(async move {
+        let subject = message.subject.clone();
+        if let Err(e) = mailer.send(message).await {
+            crate::logging::log(
+                LogRecord::new(Level::Error, format!("sending mail failed: {}", e))
+                    .field("subject", subject),
+            );
+        }
+    })
+}
+
+/// Messages sent with the `memory` backend of the configured mailer.
+pub fn outbox() -> Vec<Message> {
+    mailer().outbox()
+}
+
+impl Mailer {
+    pub fn new(settings: MailSettings, template_dir: &str) -> Self {
+        Mailer {
+            settings,
+            template_dir: template_dir.to_string(),
+            outbox: Arc::default(),
+        }
+    }
+
+    pub fn settings(&self) -> &MailSettings {
+        &self.settings
+    }
+
+    /// Messages sent so far with the `memory` backend.
+    pub fn outbox(&self) -> Vec<Message> {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    /// Empty the `memory` outbox.
+    pub fn clear_outbox(&self) {
+        self.outbox.lock().unwrap().clear();
+    }
+
+    /// `message` with its template rendered and the default sender filled in.
+    pub fn prepare(&self, mut message: Message) -> Result<Message, MailError> {
+        if let Some((name, context)) = message.template.take() {
+            if !std::path::Path::new(&self.template_dir)
+                .join(&name)
+                .is_file()
+            {
+                return Err(MailError::Template(name));
+            }
+            let body = TemplateEngine::new(&self.template_dir)
+                .render(&name, &context)
+                .body;
+            if name.ends_with(".html") || name.ends_with(".htm") {
+                message.html = Some(body);
+            } else {
+                message.text = Some(body);
+            }
+        }
+        if message.from.is_none() {
+            message.from = Some(self.settings.from.clone());
+        }
+        if message.recipients().next().is_none() {
+            return Err(MailError::Address("message has no recipients".to_string()));
+        }
+        Ok(message)
+    }
+
+    /// The message as RFC 5322 text, as the file and console backends write it.
+    pub fn format(&self, message: Message) -> Result<String, MailError> {
+        let email = build(&self.prepare(message)?)?;
+        Ok(String::from_utf8_lossy(&email.formatted()).into_owned())
+    }
+
+    /// Deliver `message` through the configured backend.
+    pub async fn send(&self, message: Message) -> Result<(), MailError> {
+        let message = self.prepare(message)?;
+        match self.settings.backend {
+            MailBackend::Memory => {
+                build(&message)?;
+                self.outbox.lock().unwrap().push(message);
+            }
+            MailBackend::Console => {
+                let email = build(&message)?;
+                println!("{}", String::from_utf8_lossy(&email.formatted()));
+                println!("{}", "-".repeat(79));
+            }
+            MailBackend::File => {
+                let email = build(&message)?;
+                tokio::fs::create_dir_all(&self.settings.file_dir).await?;
+                let name = format!(
+                    "{}-{}.eml",
+                    crate::clock::now().format("%Y%m%d-%H%M%S"),
+                    FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+                );
+                let path = std::path::Path::new(&self.settings.file_dir).join(name);
+                tokio::fs::write(path, email.formatted()).await?;
+            }
+            MailBackend::Smtp => {
+                let email = build(&message)?;
+                self.transport()?
+                    .send(email)
+                    .await
+                    .map_err(|e| MailError::Smtp(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, MailError> {
+        let settings = &self.settings;
+        let mut builder = if settings.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+                .map_err(|e| MailError::Smtp(e.to_string()))?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+        }
+        .port(settings.port);
+        if let Some(username) = &settings.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                settings.password.clone().unwrap_or_default(),
+            ));
+        }
+        Ok(builder.build())
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, MailError> {
+    address
+        .parse()
+        .map_err(|e| MailError::Address(format!("{:?}: {}", address, e)))
+}
+
+/// The lettre message for a prepared `message`.
+fn build(message: &Message) -> Result<lettre::Message, MailError> {
+    let mut builder = lettre::Message::builder()
+        .from(mailbox(message.from.as_deref().unwrap_or_default())?)
+        .subject(message.subject.clone());
+    for address in &message.to {
+        builder = builder.to(mailbox(address)?);
+    }
+    for address in &message.cc {
+        builder = builder.cc(mailbox(address)?);
+    }
+    for address in &message.bcc {
+        builder = builder.bcc(mailbox(address)?);
+    }
+    if let Some(address) = &message.reply_to {
+        builder = builder.reply_to(mailbox(address)?);
+    }
+    let result = match (&message.text, &message.html) {
+        (Some(text), Some(html)) => builder.multipart(MultiPart::alternative_plain_html(
+            text.clone(),
+            html.clone(),
+        )),
+        (None, Some(html)) => builder.singlepart(SinglePart::html(html.clone())),
+        (text, None) => builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(text.clone().unwrap_or_default()),
+    };
+    result.map_err(|e| MailError::Address(e.to_string()))
+}
@@ -0,0 +1,532 @@
+//! Cobalto management commands
+//!
+//! `manage::run` turns a project's `main` into a command line, like Django's
+//! `manage.py`:
+//!
+//! ```ignore
+//! #[actix_web::main]
+//! async fn main() -> std::process::ExitCode {
+//!     let settings = Settings::load("settings.toml").expect("invalid settings");
+//!     cobalto::manage::run(settings, |settings| {
+//!         let mut router = Router::new(settings);
+//!         router.mount(blog::app());
+//!         router
+//!     })
+//!     .await
+//! }
+//! ```
+//!
+//! - `runserver [host:port] [--set key=value]...` serves (the default command);
+//! - `start [--all | --web | --worker | --scheduler]...` runs the server, the
+//!   task queue worker and the scheduler under one supervisor, or a subset of
+//!   them (the worker stays with the roles that enqueue, see `tasks`);
+//! - `migrate` creates the tables of the mounted apps and enabled plugins and
+//!   applies pending migrations;
+//! - `routes` prints the route table;
+//! - `middleware --route /path` prints the middleware chain of the routes
+//!   matching a path, in execution order;
+//! - `linkcheck [/path]...` crawls the site in-process and fails on broken
+//!   links and redirect loops;
+//! - `contract verify <file>` replays a consumer contract against the router
+//!   and fails if the provider no longer honours it;
+//! - `shell` reads SQL statements and requests (`GET /path`) with the
+//!   database connected.
+
+use crate::orm::{Backend, Db};
+use crate::router::Router;
+use crate::settings::{Settings, SettingsError};
+use crate::supervisor::{Role, Supervisor, parse_roles};
+use crate::tasks::{Scheduler, TaskQueue};
+use sqlx::any::AnyRow;
+use sqlx::{Column, Row};
+use std::fmt;
+use std::io::Write;
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+pub const USAGE: &str = "\
+Usage: <command> [options]
+
+Commands:
+  runserver [host:port] [--set key=value]...   serve the application (default)
+  start [--all|--web|--worker|--scheduler]...  run the server and background roles under a supervisor
+  migrate                                      create tables and apply pending migrations
+  routes                                       print the route table
+  middleware --route /path                     print the middleware chain of a route in execution order
+  linkcheck [/path]...                         crawl from the given paths and report broken links
+  contract verify <file>                       check the router against a consumer contract
+  shell                                        SQL and request prompt with the database connected";
+
+/// A parsed command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    RunServer {
+        /// `host:port`, or just a port
+        addr: Option<String>,
+        /// Settings keys as accepted by `Settings::set`
+        overrides: Vec<(String, String)>,
+    },
+    Start {
+        roles: Vec<Role>,
+    },
+    Migrate,
+    Routes,
+    Middleware {
+        /// Request path or route pattern
+        route: String,
+    },
+    LinkCheck {
+        /// Where the crawl starts; every parameterless GET route when empty
+        start: Vec<String>,
+    },
+    VerifyContract {
+        path: String,
+    },
+    Shell,
+    Help,
+}
+
+/// Why a command failed.
+#[derive(Debug)]
+pub enum ManageError {
+    Usage(String),
+    Settings(SettingsError),
+    Db(sqlx::Error),
+    Io(std::io::Error),
+    /// A check ran and found problems (already printed)
+    Failed(String),
+}
+
+impl fmt::Display for ManageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManageError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            ManageError::Settings(e) => write!(f, "{}", e),
+            ManageError::Db(e) => write!(f, "database error: {}", e),
+            ManageError::Io(e) => write!(f, "{}", e),
+            ManageError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ManageError {}
+
+impl From<SettingsError> for ManageError {
+    fn from(e: SettingsError) -> Self {
+        ManageError::Settings(e)
+    }
+}
+
+impl From<sqlx::Error> for ManageError {
+    fn from(e: sqlx::Error) -> Self {
+        ManageError::Db(e)
+    }
+}
+
+impl From<std::io::Error> for ManageError {
+    fn from(e: std::io::Error) -> Self {
+        ManageError::Io(e)
+    }
+}
+
+/// Parse the arguments following the program name.
+pub fn parse_args<I, S>(args: I) -> Result<Command, ManageError>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let args: Vec<String> = args.into_iter().map(Into::into).collect();
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(Command::RunServer {
+            addr: None,
+            overrides: Vec::new(),
+        });
+    };
+    let no_options = |command: Command| match rest {
+        [] => Ok(command),
+        [extra, ..] => Err(ManageError::Usage(format!(
+            "unexpected argument '{}'",
+            extra
+        ))),
+    };
+    match command.as_str() {
+        "runserver" => {
+            let mut addr = None;
+            let mut overrides = Vec::new();
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                if arg == "--set" {
+                    let pair = rest
+                        .next()
+                        .ok_or_else(|| ManageError::Usage("--set needs key=value".into()))?;
+                    let (key, value) = pair.split_once('=').ok_or_else(|| {
+                        ManageError::Usage(format!("expected key=value, got '{}'", pair))
+                    })?;
+                    overrides.push((key.trim().to_string(), value.trim().to_string()));
+                } else if addr.is_none() && !arg.starts_with('-') {
+                    addr = Some(arg.clone());
+                } else {
+                    return Err(ManageError::Usage(format!("unexpected argument '{}'", arg)));
+                }
+            }
+            Ok(Command::RunServer { addr, overrides })
+        }
+        "start" => parse_roles(rest)
+            .map(|roles| Command::Start { roles })
+            .map_err(ManageError::Usage),
+        "migrate" => no_options(Command::Migrate),
+        "routes" => no_options(Command::Routes),
+        "middleware" => match rest {
+            [flag, route] if flag == "--route" && route.starts_with('/') => {
+                Ok(Command::Middleware {
+                    route: route.clone(),
+                })
+            }
+            _ => Err(ManageError::Usage(
+                "usage: middleware --route /path".into(),
+            )),
+        },
+        "linkcheck" => match rest.iter().find(|arg| !arg.starts_with('/')) {
+            Some(arg) => Err(ManageError::Usage(format!(
+                "expected a path starting with '/', got '{}'",
+                arg
+            ))),
+            None => Ok(Command::LinkCheck {
+                start: rest.to_vec(),
+            }),
+        },
+        "contract" => match rest {
+            [verb, path] if verb == "verify" => Ok(Command::VerifyContract { path: path.clone() }),
+            _ => Err(ManageError::Usage("usage: contract verify <file>".into())),
+        },
+        "shell" => no_options(Command::Shell),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        other => Err(ManageError::Usage(format!("unknown command '{}'", other))),
+    }
+}
+
+/// Apply `runserver`'s address and `--set` overrides to `settings`.
+pub fn apply_overrides(
+    settings: &mut Settings,
+    addr: Option<&str>,
+    overrides: &[(String, String)],
+) -> Result<(), SettingsError> {
+    if let Some(addr) = addr {
+        match addr.rsplit_once(':') {
+            Some((host, port)) => {
+                if !host.is_empty() {
+                    settings.set("host", host)?;
+                }
+                settings.set("port", port)?;
+            }
+            None => settings.set("port", addr)?,
+        }
+    }
+    for (key, value) in overrides {
+        settings.set(key, value)?;
+    }
+    settings.validate()
+}
+
+/// The route table printed by `routes`.
+pub fn routes_table(router: &Router) -> String {
+    let routes = router.list_routes();
+    let width = routes.iter().map(|(m, _)| m.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (method, path) in routes {
+        out.push_str(&format!("{:<width$}  {}\n", method, path));
+    }
+    out
+}
+
+/// The middleware chains printed by `middleware`, one block per route
+/// matching `path`; empty when none does.
+pub fn middleware_table(router: &Router, path: &str) -> String {
+    let mut out = String::new();
+    for route in router.matching_routes(path) {
+        out.push_str(&format!("{} {}\n", route.method, route.path));
+        for (i, step) in router.middleware_chain(route).iter().enumerate() {
+            out.push_str(&format!("  {:>2}. {}\n", i + 1, step));
+        }
+    }
+    out
+}
+
+/// Create the tables of the mounted apps and enabled plugins, then apply
+/// pending migrations. Returns the applied `app.migration` names.
+pub async fn migrate(router: &Router, db: &Db) -> Result<Vec<String>, sqlx::Error> {
+    crate::plugins::migrate_plugins(db, &router.settings).await?;
+    router.migrate(db).await
+}
+
+/// Text of column `i`, whatever its storage type.
+fn cell(row: &AnyRow, i: usize) -> String {
+    if let Ok(v) = row.try_get::<Option<i64>, _>(i) {
+        return v.map(|v| v.to_string()).unwrap_or_else(|| "NULL".into());
+    }
+    if let Ok(v) = row.try_get::<Option<f64>, _>(i) {
+        return v.map(|v| v.to_string()).unwrap_or_else(|| "NULL".into());
+    }
+    if let Ok(v) = row.try_get::<Option<String>, _>(i) {
+        return v.unwrap_or_else(|| "NULL".into());
+    }
+    if let Ok(v) = row.try_get::<Option<bool>, _>(i) {
+        return v.map(|v| v.to_string()).unwrap_or_else(|| "NULL".into());
+    }
+    match row.try_get::<Option<Vec<u8>>, _>(i) {
+        Ok(Some(v)) => format!("<{} bytes>", v.len()),
+        _ => "NULL".into(),
+    }
+}
+
+/// Rows of a query as tab-separated lines under a header.
+fn format_rows(rows: &[AnyRow]) -> String {
+    let Some(first) = rows.first() else {
+        return "(no rows)\n".to_string();
+    };
+    let header: Vec<&str> = first.columns().iter().map(|c| c.name()).collect();
+    let mut out = header.join("\t") + "\n";
+    for row in rows {
+        let cells: Vec<String> = (0..row.len()).map(|i| cell(row, i)).collect();
+        out.push_str(&cells.join("\t"));
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "({} row{})\n",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" }
+    ));
+    out
+}
+
+const SHELL_HELP: &str = "\
+  <SQL statement>           run it; queries print their rows
+  GET|POST|... /path [body] dispatch a request through the router
+  .routes                   print the route table
+  .tables                   list the database tables
+  .quit                     leave the shell
+";
+
+/// Answer one shell line; `None` for `.quit`.
+async fn shell_line(router: &Router, db: &Db, line: &str) -> Option<String> {
+    let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let sql = match word {
+        ".quit" | ".exit" => return None,
+        ".help" => return Some(SHELL_HELP.to_string()),
+        ".routes" => return Some(routes_table(router)),
+        ".tables" => match db.backend() {
+            Backend::Sqlite => {
+                "SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name".to_string()
+            }
+            Backend::Postgres => "SELECT table_name FROM information_schema.tables \
+                                  WHERE table_schema = current_schema() ORDER BY table_name"
+                .to_string(),
+            Backend::MySql => "SELECT table_name FROM information_schema.tables \
+                               WHERE table_schema = DATABASE() ORDER BY table_name"
+                .to_string(),
+        },
+        "GET" | "HEAD" | "POST" | "PUT" | "PATCH" | "DELETE" | "OPTIONS" => {
+            let rest = rest.trim_start();
+            let (path, body) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if !path.starts_with('/') {
+                return Some(format!("usage: {} /path [body]\n", word));
+            }
+            let response = router.dispatch(word, path, body.trim()).await;
+            let body = match &response.binary {
+                Some(bytes) => format!("<{} bytes>", bytes.len()),
+                None => response.body.clone(),
+            };
+            return Some(format!("{}\n{}\n", response.status, body));
+        }
+        _ => line.to_string(),
+    };
+    let keyword = sql
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let result = if matches!(
+        keyword.as_str(),
+        "select" | "with" | "pragma" | "explain" | "show" | "values"
+    ) {
+        sqlx::query(&sql)
+            .fetch_all(&db.pool)
+            .await
+            .map(|rows| format_rows(&rows))
+    } else {
+        db.execute(&sql)
+            .await
+            .map(|n| format!("{} row{} affected\n", n, if n == 1 { "" } else { "s" }))
+    };
+    Some(result.unwrap_or_else(|e| format!("error: {}\n", e)))
+}
+
+/// Run the shell over `input`, writing prompts and answers to `output`.
+pub async fn shell<R, W>(router: &Router, db: &Db, input: R, output: &mut W) -> std::io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: Write,
+{
+    writeln!(output, "Cobalto shell. Type .help for help.")?;
+    let mut lines = input.lines();
+    loop {
+        write!(output, "cobalto> ")?;
+        output.flush()?;
+        let Some(line) = lines.next_line().await? else {
+            writeln!(output)?;
+            return Ok(());
+        };
+        let line = line.trim().trim_end_matches(';').trim();
+        if line.is_empty() {
+            continue;
+        }
+        match shell_line(router, db, line).await {
+            Some(answer) => write!(output, "{}", answer)?,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Supervisor running the `roles` of `router`; the worker and scheduler
+/// roles are skipped with a warning when no task queue or scheduler is
+/// registered.
+///
+/// The task queue is in-process, so with one registered the worker must run
+/// next to the web or scheduler roles that fill it, and they next to it.
+fn supervisor(router: &Arc<Router>, roles: &[Role]) -> Result<Supervisor, ManageError> {
+    let warn = |message: &str| {
+        crate::logging::log(crate::logging::LogRecord::new(log::Level::Warn, message))
+    };
+    let mut supervisor = Supervisor::new();
+    supervisor.shutdown_timeout =
+        std::time::Duration::from_secs(router.settings.get_or("shutdown_timeout", 30));
+    let web = router.clone();
+    supervisor.add_role(Role::Web, move |shutdown| async move {
+        web.serve_until(shutdown.requested()).await
+    });
+    match router.managed::<TaskQueue>() {
+        Some(queue) => {
+            let worker = roles.contains(&Role::Worker);
+            let producers = roles.iter().any(|role| *role != Role::Worker);
+            if worker != producers {
+                return Err(ManageError::Failed(
+                    "the task queue is in-process: start --worker together with --web or \
+                     --scheduler"
+                        .to_string(),
+                ));
+            }
+            supervisor.add_role(Role::Worker, move |shutdown| {
+                queue.run_until(shutdown.requested())
+            })
+        }
+        None if roles.contains(&Role::Worker) => {
+            warn("no task queue registered, skipping the worker role")
+        }
+        None => {}
+    }
+    match router.managed::<Scheduler>() {
+        Some(scheduler) => supervisor.add_role(Role::Scheduler, move |shutdown| {
+            scheduler.run_until(shutdown.requested())
+        }),
+        None if roles.contains(&Role::Scheduler) => {
+            warn("no scheduler registered, skipping the scheduler role")
+        }
+        None => {}
+    }
+    Ok(supervisor)
+}
+
+/// Run `command` against the router `build` makes from `settings`.
+pub async fn execute<F>(
+    command: Command,
+    mut settings: Settings,
+    build: F,
+) -> Result<(), ManageError>
+where
+    F: FnOnce(Settings) -> Router,
+{
+    match command {
+        Command::Help => println!("{}", USAGE),
+        Command::RunServer { addr, overrides } => {
+            apply_overrides(&mut settings, addr.as_deref(), &overrides)?;
+            build(settings).run().await?;
+        }
+        Command::Start { roles } => {
+            let router = Arc::new(build(settings));
+            router.settings.validate()?;
+            let supervisor = supervisor(&router, &roles)?;
+            router.run_startup_hooks().await;
+            let result = supervisor.run(&roles).await;
+            router.run_shutdown_hooks().await;
+            result?;
+        }
+        Command::Routes => print!("{}", routes_table(&build(settings))),
+        Command::Middleware { route } => {
+            let table = middleware_table(&build(settings), &route);
+            if table.is_empty() {
+                return Err(ManageError::Failed(format!("no route matches {}", route)));
+            }
+            print!("{}", table);
+        }
+        Command::Migrate => {
+            let router = build(settings);
+            let db = Db::from_settings(&router.settings).await?;
+            let applied = migrate(&router, &db).await?;
+            if applied.is_empty() {
+                println!("No migrations to apply.");
+            }
+            for name in applied {
+                println!("  applied {}", name);
+            }
+        }
+        Command::LinkCheck { start } => {
+            let router = build(settings);
+            let start: Vec<&str> = start.iter().map(String::as_str).collect();
+            let report = crate::linkcheck::linkcheck(&router, &start).await;
+            report.print();
+            if !report.is_ok() {
+                return Err(ManageError::Failed(format!(
+                    "{} link problem{} found",
+                    report.issues.len(),
+                    if report.issues.len() == 1 { "" } else { "s" }
+                )));
+            }
+        }
+        Command::VerifyContract { path } => {
+            let router = build(settings);
+            if !crate::contract::verify_file(&router, &path).await? {
+                return Err(ManageError::Failed(format!("contract {} is broken", path)));
+            }
+        }
+        Command::Shell => {
+            let router = build(settings);
+            let db = Db::from_settings(&router.settings).await?;
+            let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+            shell(&router, &db, stdin, &mut std::io::stdout()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse the process arguments and run the command; the exit code is 2 for
+/// usage errors and 1 for failures.
+pub async fn run<F>(settings: Settings, build: F) -> ExitCode
+where
+    F: FnOnce(Settings) -> Router,
+{
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    match execute(command, settings, build).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
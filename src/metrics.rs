@@ -0,0 +1,240 @@
+//! Cobalto health check and metrics
+//!
+//! `Router::run` serves, when enabled in `[monitoring]`:
+//!
+//! - `/healthz`: `200 {"status": "ok"}`, or a 503 when the managed `Db` (see
+//!   `Router::manage`) does not answer `SELECT 1`;
+//! - `/metrics`: request counts, latency histograms per route, the number
+//!   of in-flight requests and the counters other modules register with
+//!   `register_counter`, in the Prometheus text format.
+//!
+//! Requests are labelled with their route pattern (`/users/:id`), never the
+//! raw path, so the number of series stays bounded; unmatched requests share
+//! the `<unmatched>` route.
+
+use crate::orm::Db;
+use crate::router::Response;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Monitoring settings: `[monitoring]` in the settings file,
+/// `COBALTO_MONITORING__METRICS`, ...
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitoringSettings {
+    pub healthz: bool,
+    pub healthz_path: String,
+    /// Off by default: the endpoint is public unless guarded by a proxy.
+    pub metrics: bool,
+    pub metrics_path: String,
+}
+
+impl Default for MonitoringSettings {
+    fn default() -> Self {
+        MonitoringSettings {
+            healthz: true,
+            healthz_path: "/healthz".to_string(),
+            metrics: false,
+            metrics_path: "/metrics".to_string(),
+        }
+    }
+}
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+pub const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route label of requests no route matched.
+pub const UNMATCHED: &str = "<unmatched>";
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Non-cumulative count per bucket, plus one for `+Inf`
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// (method, route, status) -> count
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// (method, route) -> latencies
+    durations: BTreeMap<(String, String), Histogram>,
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(Registry::default()));
+static IN_FLIGHT: AtomicI64 = AtomicI64::new(0);
+
+/// Reads the current value of a registered counter.
+pub type CounterFn = Arc<dyn Fn() -> u64 + Send + Sync>;
+
+/// name -> (help, readers summed when rendered)
+static COUNTERS: Lazy<Mutex<BTreeMap<String, (String, Vec<CounterFn>)>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Export a counter kept elsewhere (e.g. by a middleware) on `/metrics`.
+/// Counters registered under the same name are summed.
+pub fn register_counter<F>(name: &str, help: &str, read: F)
+where
+    F: Fn() -> u64 + Send + Sync + 'static,
+{
+    let mut counters = COUNTERS.lock().unwrap();
+    let (existing_help, readers) = counters
+        .entry(name.to_string())
+        .or_insert_with(|| (help.to_string(), Vec::new()));
+    *existing_help = help.to_string();
+    readers.push(Arc::new(read));
+}
+
+/// Count a served request (done by the router).
+pub fn record(method: &str, route: &str, status: u16, duration: Duration) {
+    let route = if route.is_empty() { UNMATCHED } else { route };
+    let seconds = duration.as_secs_f64();
+    let mut registry = REGISTRY.lock().unwrap();
+    *registry
+        .requests
+        .entry((method.to_string(), route.to_string(), status))
+        .or_default() += 1;
+    let histogram = registry
+        .durations
+        .entry((method.to_string(), route.to_string()))
+        .or_default();
+    if histogram.counts.is_empty() {
+        histogram.counts = vec![0; BUCKETS.len() + 1];
+    }
+    let bucket = BUCKETS
+        .iter()
+        .position(|&le| seconds <= le)
+        .unwrap_or(BUCKETS.len());
+    histogram.counts[bucket] += 1;
+    histogram.sum += seconds;
+    histogram.count += 1;
+}
+
+/// Marks a request as in flight until dropped.
+pub struct InFlight(());
+
+impl InFlight {
+    pub fn start() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        InFlight(())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Requests being handled right now.
+pub fn in_flight() -> i64 {
+    IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+/// Forget every recorded request.
+pub fn reset() {
+    *REGISTRY.lock().unwrap() = Registry::default();
+}
+
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Everything recorded so far, in the Prometheus text format.
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+    out.push_str("# HELP cobalto_requests_total Requests served.\n");
+    out.push_str("# TYPE cobalto_requests_total counter\n");
+    for ((method, route, status), count) in &registry.requests {
+        let _ = writeln!(
+            out,
+            "cobalto_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+            label(method),
+            label(route),
+            status,
+            count
+        );
+    }
+    out.push_str("# HELP cobalto_request_duration_seconds Time spent handling requests.\n");
+    out.push_str("# TYPE cobalto_request_duration_seconds histogram\n");
+    for ((method, route), histogram) in &registry.durations {
+        let labels = format!("method=\"{}\",route=\"{}\"", label(method), label(route));
+        let mut cumulative = 0;
+        for (i, count) in histogram.counts.iter().enumerate() {
+            cumulative += count;
+            let le = BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |le| le.to_string());
+            let _ = writeln!(
+                out,
+                "cobalto_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels, le, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "cobalto_request_duration_seconds_sum{{{}}} {}",
+            labels, histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "cobalto_request_duration_seconds_count{{{}}} {}",
+            labels, histogram.count
+        );
+    }
+    out.push_str("# HELP cobalto_requests_in_flight Requests being handled.\n");
+    out.push_str("# TYPE cobalto_requests_in_flight gauge\n");
+    let _ = writeln!(out, "cobalto_requests_in_flight {}", in_flight());
+    drop(registry);
+    let counters: Vec<(String, String, Vec<CounterFn>)> = COUNTERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, (help, readers))| (name.clone(), help.clone(), readers.clone()))
+        .collect();
+    for (name, help, readers) in counters {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let total: u64 = readers.iter().map(|read| read()).sum();
+        let _ = writeln!(out, "{} {}", name, total);
+    }
+    out
+}
+
+/// The `/metrics` response.
+pub fn metrics_response() -> Response {
+    let mut response = Response::html(render());
+    response.headers.insert(
+        "Content-Type".to_string(),
+        "text/plain; version=0.0.4; charset=utf-8".to_string(),
+    );
+    response
+}
+
+/// The `/healthz` response, checking `db` when there is one.
+pub async fn health(db: Option<&Db>) -> Response {
+    let Some(db) = db else {
+        return Response::json(serde_json::json!({ "status": "ok" }));
+    };
+    match sqlx::query("SELECT 1").execute(&db.pool).await {
+        Ok(_) => Response::json(serde_json::json!({ "status": "ok", "database": "ok" })),
+        Err(e) => {
+            log::warn!("health check failed: {e}");
+            Response::json(serde_json::json!({
+                "status": "error",
+                "database": e.to_string(),
+            }))
+            .with_status(503)
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Cobalto HTML minification
+//!
+//! Opt-in wrapper that collapses whitespace and strips comments from
+//! `text/html` responses. Runs of whitespace become a single space, since
+//! even between tags a space can be rendered (`<b>a</b> <i>b</i>`). Content
+//! of `<pre>`, `<textarea>`, `<script>` and `<style>` elements and quoted
+//! attribute values are left untouched. Byte savings are exported to
+//! `/metrics` as `cobalto_minify_bytes_in_total` and
+//! `cobalto_minify_bytes_out_total`.
+
+use crate::router::{Handler, Response};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Once};
+
+const PRESERVED: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+/// Byte counters shared by every `MinifyHtml::new()` policy.
+static BYTES_IN: Lazy<Arc<AtomicU64>> = Lazy::new(Arc::default);
+static BYTES_OUT: Lazy<Arc<AtomicU64>> = Lazy::new(Arc::default);
+
+/// Minifies an HTML document.
+pub fn minify_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    let mut pending_space = false;
+    while !rest.is_empty() {
+        // Drop comments (conditional comments included)
+        if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+        // Copy preserved elements verbatim
+        if let Some(tag) = PRESERVED.iter().find(|t| opens_tag(rest, t)) {
+            let close = format!("</{}", tag);
+            let end = find_ci(rest, &close)
+                .map(|i| i + rest[i..].find('>').map(|j| j + 1).unwrap_or(rest.len() - i))
+                .unwrap_or(rest.len());
+            if pending_space && !out.is_empty() {
+                out.push(' ');
+            }
+            pending_space = false;
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            pending_space = true;
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        if pending_space && !out.is_empty() {
+            out.push(' ');
+        }
+        pending_space = false;
+        if c == '<' && rest[1..].starts_with(|n: char| n.is_ascii_alphabetic() || n == '/') {
+            rest = &rest[copy_tag(rest, &mut out)..];
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Copy the tag `html` starts with, collapsing whitespace between attributes
+/// but not inside quoted values; returns the bytes consumed.
+fn copy_tag(html: &str, out: &mut String) -> usize {
+    let mut quote: Option<char> = None;
+    let mut pending_space = false;
+    for (i, c) in html.char_indices() {
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None if c.is_whitespace() => pending_space = true,
+            None => {
+                if pending_space && c != '>' && !(c == '/' && html[i + 1..].starts_with('>')) {
+                    out.push(' ');
+                }
+                pending_space = false;
+                out.push(c);
+                match c {
+                    '"' | '\'' => quote = Some(c),
+                    '>' => return i + 1,
+                    _ => {}
+                }
+            }
+        }
+    }
+    html.len()
+}
+
+fn opens_tag(s: &str, tag: &str) -> bool {
+    let n = tag.len() + 1;
+    s.len() > n
+        && s.as_bytes()[0] == b'<'
+        && s.is_char_boundary(n)
+        && s[1..n].eq_ignore_ascii_case(tag)
+        && matches!(s.as_bytes()[n], b'>' | b' ' | b'\t' | b'\n' | b'\r' | b'/')
+}
+
+/// Byte offset of the ASCII `needle` in `haystack`, ignoring ASCII case.
+fn find_ci(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Minification policy wrapping route handlers.
+#[derive(Clone, Default)]
+pub struct MinifyHtml {
+    /// Critical CSS inlined as a `<style>` block right before `</head>`.
+    pub critical_css: Option<String>,
+    pub bytes_in: Arc<AtomicU64>,
+    pub bytes_out: Arc<AtomicU64>,
+}
+
+impl MinifyHtml {
+    /// A policy counting into the process-wide byte counters exported to
+    /// `/metrics` (registered by the first call).
+    pub fn new() -> Self {
+        static REGISTERED: Once = Once::new();
+        REGISTERED.call_once(|| {
+            crate::metrics::register_counter(
+                "cobalto_minify_bytes_in_total",
+                "HTML bytes before minification.",
+                || BYTES_IN.load(Ordering::Relaxed),
+            );
+            crate::metrics::register_counter(
+                "cobalto_minify_bytes_out_total",
+                "HTML bytes after minification.",
+                || BYTES_OUT.load(Ordering::Relaxed),
+            );
+        });
+        MinifyHtml {
+            critical_css: None,
+            bytes_in: BYTES_IN.clone(),
+            bytes_out: BYTES_OUT.clone(),
+        }
+    }
+
+    /// Builder for inlining critical CSS into every page
+    pub fn with_critical_css<S: Into<String>>(mut self, css: S) -> Self {
+        self.critical_css = Some(css.into());
+        self
+    }
+
+    /// Minify a response if it is HTML; other responses are returned as-is.
+    pub fn process(&self, mut resp: Response) -> Response {
+        let is_html = resp
+            .headers
+            .iter()
+            .any(|(k, v)| k.eq_ignore_ascii_case("content-type") && v.starts_with("text/html"));
+        if !is_html {
+            return resp;
+        }
+        let mut body = minify_html(&resp.body);
+        if let Some(css) = &self.critical_css {
+            if let Some(i) = find_ci(&body, "</head>") {
+                body.insert_str(i, &format!("<style>{}</style>", css));
+            }
+        }
+        self.bytes_in
+            .fetch_add(resp.body.len() as u64, Ordering::Relaxed);
+        self.bytes_out
+            .fetch_add(body.len() as u64, Ordering::Relaxed);
+        resp.body = body;
+        resp
+    }
+
+    /// Wrap a handler so that its HTML output is minified.
+    pub fn wrap(&self, handler: Handler) -> Handler {
+        let minify = self.clone();
+        Arc::new(move |req| {
+            let minify = minify.clone();
+            let handler = handler.clone();
+            Box::pin(async move { minify.process(handler(req).await) })
+        })
+    }
+}
@@ -0,0 +1,226 @@
+//! Cobalto traffic mirroring (feature `mirror`)
+//!
+//! Shadow traffic for testing a new version against production load: a
+//! sampled share of the requests reaching a wrapped handler is replayed in the
+//! background against a secondary host. The client always gets the primary
+//! response, whatever the secondary does; status or body differences are
+//! logged as divergences and counted.
+//!
+//! Only `GET` and `HEAD` are mirrored unless `Mirror::methods` opts other
+//! methods in: replaying a `POST` repeats its side effects on the secondary.
+//! Request headers are forwarded, except the hop-by-hop ones.
+
+use crate::router::{Handler, Response, current_request, percent_encode};
+use actix_web::web::Bytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters describing the mirrored traffic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MirrorStats {
+    pub mirrored: u64,
+    pub diverged: u64,
+    pub failed: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    seen: AtomicU64,
+    mirrored: AtomicU64,
+    diverged: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Mirroring policy towards one secondary host.
+#[derive(Clone)]
+pub struct Mirror {
+    pub secondary: String,
+    pub percent: u8,
+    /// Methods replayed, uppercase
+    pub methods: Vec<String>,
+    client: reqwest::Client,
+    counters: Arc<Counters>,
+}
+
+impl Mirror {
+    /// Mirror every request to `secondary` (e.g. `http://10.0.0.5:8000`).
+    pub fn new(secondary: &str) -> Self {
+        Mirror {
+            secondary: secondary.trim_end_matches('/').to_string(),
+            percent: 100,
+            methods: vec!["GET".to_string(), "HEAD".to_string()],
+            client: reqwest::Client::new(),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Builder for the sampled share of requests, in percent
+    pub fn sample(mut self, percent: u8) -> Self {
+        self.percent = percent.min(100);
+        self
+    }
+
+    /// Builder replacing the mirrored methods (`GET` and `HEAD` by default),
+    /// e.g. `.methods(&["GET", "HEAD", "POST"])` for a secondary whose writes
+    /// are discarded
+    pub fn methods(mut self, methods: &[&str]) -> Self {
+        self.methods = methods.iter().map(|m| m.to_ascii_uppercase()).collect();
+        self
+    }
+
+    /// Whether requests with `method` are mirrored.
+    pub fn mirrors_method(&self, method: &str) -> bool {
+        self.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+
+    pub fn stats(&self) -> MirrorStats {
+        MirrorStats {
+            mirrored: self.counters.mirrored.load(Ordering::Relaxed),
+            diverged: self.counters.diverged.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether the next request is sampled; spreads picks evenly (10% picks
+    /// one request in ten rather than the first ten of every hundred).
+    pub fn should_mirror(&self) -> bool {
+        let n = self.counters.seen.fetch_add(1, Ordering::Relaxed);
+        let pct = self.percent as u64;
+        (n + 1) * pct / 100 > n * pct / 100
+    }
+
+    /// Wrap a handler so that sampled requests are mirrored after it answers.
+    pub fn wrap(&self, handler: Handler) -> Handler {
+        let mirror = self.clone();
+        Arc::new(move |req| {
+            let mirror = mirror.clone();
+            let handler = handler.clone();
+            Box::pin(async move {
+                let scope = current_request();
+                let resp = handler(req).await;
+                if let Some(scope) = scope
+                    && mirror.mirrors_method(&scope.method)
+                    && mirror.should_mirror()
+                {
+                    let url = format!(
+                        "{}{}{}",
+                        mirror.secondary,
+                        scope.path,
+                        encode_query(&scope.query)
+                    );
+                    let primary = resp.clone();
+                    tokio::spawn(async move {
+                        let headers = forwarded_headers(&scope.headers);
+                        mirror
+                            .replay(&scope.method, &url, headers, scope.raw_body, primary)
+                            .await
+                    });
+                }
+                resp
+            })
+        })
+    }
+
+    async fn replay(
+        &self,
+        method: &str,
+        url: &str,
+        headers: Vec<(String, String)>,
+        body: Bytes,
+        primary: Response,
+    ) {
+        self.counters.mirrored.fetch_add(1, Ordering::Relaxed);
+        let method = reqwest::Method::from_bytes(method.as_bytes()).unwrap_or(reqwest::Method::GET);
+        let mut request = self.client.request(method, url).body(body);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let result = request.send().await;
+        let shadow = match result {
+            Ok(r) => {
+                let status = r.status().as_u16();
+                r.text().await.map(|body| (status, body))
+            }
+            Err(e) => Err(e),
+        };
+        match shadow {
+            Ok((status, body)) => {
+                if let Some(diff) = divergence(&primary, status, &body) {
+                    self.counters.diverged.fetch_add(1, Ordering::Relaxed);
+                    log::warn!("mirror divergence on {}: {}", url, diff);
+                }
+            }
+            Err(e) => {
+                self.counters.failed.fetch_add(1, Ordering::Relaxed);
+                log::warn!("mirror request to {} failed: {}", url, e);
+            }
+        }
+    }
+}
+
+/// Describe how a shadow response differs from the primary one, if it does.
+pub fn divergence(primary: &Response, status: u16, body: &str) -> Option<String> {
+    if primary.status != status {
+        return Some(format!("status {} != {}", primary.status, status));
+    }
+    let primary_body = match &primary.binary {
+        Some(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        None => primary.body.clone(),
+    };
+    if primary_body != body {
+        return Some(format!(
+            "body differs ({} vs {} bytes)",
+            primary_body.len(),
+            body.len()
+        ));
+    }
+    None
+}
+
+/// Headers that only concern one connection (RFC 9110 §7.6.1), plus those
+/// the client recomputes for the secondary.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+];
+
+/// The request headers to send to the secondary: all but the hop-by-hop ones
+/// and those listed in `Connection`.
+pub fn forwarded_headers(headers: &HashMap<String, String>) -> Vec<(String, String)> {
+    let listed: Vec<String> = headers
+        .get("connection")
+        .map(|v| v.split(',').map(|h| h.trim().to_ascii_lowercase()).collect())
+        .unwrap_or_default();
+    let mut forwarded: Vec<(String, String)> = headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.to_ascii_lowercase();
+            !HOP_BY_HOP.contains(&name.as_str()) && !listed.contains(&name)
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    forwarded.sort();
+    forwarded
+}
+
+fn encode_query(query: &HashMap<String, String>) -> String {
+    if query.is_empty() {
+        return String::new();
+    }
+    let mut pairs: Vec<String> = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v)))
+        .collect();
+    pairs.sort();
+    format!("?{}", pairs.join("&"))
+}
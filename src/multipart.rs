@@ -0,0 +1,419 @@
+//! Cobalto multipart uploads
+//!
+//! `req.multipart().await` parses a `multipart/form-data` body into text fields
+//! and uploaded files. Small files stay in memory; anything above
+//! `MEMORY_THRESHOLD` is spooled, as it is parsed, to a private temporary file
+//! that is removed when the `UploadedFile` is dropped. Bodies larger than
+//! `Settings.max_upload_bytes` are refused (actix already enforces the same
+//! limit while reading).
+
+use crate::router::{IntoResponse, Request, Response, current_request};
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::AsyncWriteExt;
+
+/// Files up to this size are kept in memory.
+pub const MEMORY_THRESHOLD: usize = 1024 * 1024;
+
+static MAX_UPLOAD_BYTES: AtomicUsize = AtomicUsize::new(10 * 1024 * 1024);
+
+/// Set the upload size limit (done by `Router::new` from the settings).
+pub fn set_max_upload_bytes(limit: usize) {
+    MAX_UPLOAD_BYTES.store(limit, Ordering::Relaxed);
+}
+
+pub fn max_upload_bytes() -> usize {
+    MAX_UPLOAD_BYTES.load(Ordering::Relaxed)
+}
+
+/// Why a body could not be read as multipart.
+#[derive(Debug)]
+pub enum MultipartError {
+    NotMultipart,
+    MissingBoundary,
+    Malformed(String),
+    TooLarge { limit: usize },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultipartError::NotMultipart => write!(f, "request is not multipart/form-data"),
+            MultipartError::MissingBoundary => write!(f, "multipart boundary missing"),
+            MultipartError::Malformed(why) => write!(f, "malformed multipart body: {}", why),
+            MultipartError::TooLarge { limit } => {
+                write!(f, "upload exceeds the limit of {} bytes", limit)
+            }
+            MultipartError::Io(e) => write!(f, "cannot store upload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {}
+
+impl IntoResponse for MultipartError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            MultipartError::TooLarge { .. } => 413,
+            MultipartError::Io(_) => 500,
+            _ => 400,
+        };
+        Response::json(serde_json::json!({"error": self.to_string()})).with_status(status)
+    }
+}
+
+/// Where an uploaded file's content lives.
+#[derive(Debug)]
+enum Storage {
+    Memory(Bytes),
+    Temp(PathBuf),
+}
+
+/// A file part of a multipart body.
+#[derive(Debug)]
+pub struct UploadedFile {
+    pub field: String,
+    /// As sent by the client; don't use it as a path without sanitizing.
+    pub filename: String,
+    pub content_type: String,
+    pub size: usize,
+    storage: Storage,
+}
+
+impl UploadedFile {
+    /// Whether the content was spooled to a temporary file.
+    pub fn is_temp_file(&self) -> bool {
+        matches!(self.storage, Storage::Temp(_))
+    }
+
+    /// The whole content.
+    pub async fn bytes(&self) -> Result<Bytes, std::io::Error> {
+        match &self.storage {
+            Storage::Memory(bytes) => Ok(bytes.clone()),
+            Storage::Temp(path) => tokio::fs::read(path).await.map(Bytes::from),
+        }
+    }
+
+    /// Copy the content to `dest`.
+    pub async fn save_to<P: AsRef<Path>>(&self, dest: P) -> Result<(), std::io::Error> {
+        match &self.storage {
+            Storage::Memory(bytes) => tokio::fs::write(dest, bytes).await,
+            Storage::Temp(path) => tokio::fs::copy(path, dest).await.map(|_| ()),
+        }
+    }
+}
+
+impl Drop for UploadedFile {
+    fn drop(&mut self) {
+        if let Storage::Temp(path) = &self.storage {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Parsed `multipart/form-data` body.
+#[derive(Debug, Default)]
+pub struct Multipart {
+    pub fields: HashMap<String, String>,
+    pub files: Vec<UploadedFile>,
+}
+
+impl Multipart {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|s| s.as_str())
+    }
+
+    /// First file uploaded under `name`.
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.iter().find(|f| f.field == name)
+    }
+}
+
+impl Request {
+    /// Parse the current request's `multipart/form-data` body.
+    pub async fn multipart(&self) -> Result<Multipart, MultipartError> {
+        let scope = current_request().unwrap_or_default();
+        let content_type = scope
+            .headers
+            .get("content-type")
+            .ok_or(MultipartError::NotMultipart)?;
+        parse(content_type, scope.raw_body.clone(), max_upload_bytes()).await
+    }
+}
+
+/// The `boundary` parameter of a multipart content type.
+pub fn boundary(content_type: &str) -> Result<String, MultipartError> {
+    let mut parts = content_type.split(';');
+    let mime = parts.next().unwrap_or("").trim();
+    if !mime.eq_ignore_ascii_case("multipart/form-data") {
+        return Err(MultipartError::NotMultipart);
+    }
+    parts
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(k, _)| k.eq_ignore_ascii_case("boundary"))
+        .map(|(_, v)| v.trim_matches('"').to_string())
+        .filter(|b| !b.is_empty())
+        .ok_or(MultipartError::MissingBoundary)
+}
+
+/// Parse `body` sent with `content_type`, refusing bodies over `limit` bytes.
+/// The buffer is shared, not copied.
+pub async fn parse(
+    content_type: &str,
+    body: Bytes,
+    limit: usize,
+) -> Result<Multipart, MultipartError> {
+    if body.len() > limit {
+        return Err(MultipartError::TooLarge { limit });
+    }
+    let chunk = Ok::<_, std::io::Error>(body);
+    parse_stream(content_type, futures::stream::iter([chunk]), limit).await
+}
+
+/// Parse a body arriving as a stream of chunks, refusing it once it passes
+/// `limit` bytes. File parts go to disk as they arrive once over
+/// `MEMORY_THRESHOLD`, so only about one chunk per part is held in memory.
+pub async fn parse_stream<S, E>(
+    content_type: &str,
+    body: S,
+    limit: usize,
+) -> Result<Multipart, MultipartError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: std::fmt::Display,
+{
+    let delimiter = format!("--{}", boundary(content_type)?).into_bytes();
+    let mut closing = b"\r\n".to_vec();
+    closing.extend_from_slice(&delimiter);
+    let mut body = std::pin::pin!(body);
+    let mut form = Multipart::default();
+    let mut buf: Vec<u8> = Vec::new();
+    let mut received = 0;
+    let mut state = State::Preamble;
+    loop {
+        // Consume as much of `buf` as the current state can
+        let progressed = match &mut state {
+            State::Preamble => match find(&buf, &delimiter) {
+                Some(at) => {
+                    buf.drain(..at + delimiter.len());
+                    state = State::Boundary;
+                    true
+                }
+                None => false,
+            },
+            State::Boundary if buf.len() >= 2 => {
+                if buf.starts_with(b"--") {
+                    return Ok(form);
+                }
+                if !buf.starts_with(b"\r\n") {
+                    return Err(MultipartError::Malformed(
+                        "boundary not followed by CRLF".into(),
+                    ));
+                }
+                buf.drain(..2);
+                state = State::Headers;
+                true
+            }
+            State::Boundary => false,
+            State::Headers => match find(&buf, b"\r\n\r\n") {
+                Some(end) => {
+                    let headers = parse_headers(&String::from_utf8_lossy(&buf[..end]));
+                    buf.drain(..end + 4);
+                    state = State::Content(Part::new(&headers));
+                    true
+                }
+                None => false,
+            },
+            State::Content(part) => match find(&buf, &closing) {
+                Some(end) => {
+                    part.write(&buf[..end]).await?;
+                    buf.drain(..end + closing.len());
+                    let State::Content(part) = std::mem::replace(&mut state, State::Boundary)
+                    else {
+                        unreachable!()
+                    };
+                    part.finish(&mut form).await?;
+                    true
+                }
+                None => {
+                    // Keep what could be the start of a split boundary
+                    let safe = buf.len().saturating_sub(closing.len() - 1);
+                    part.write(&buf[..safe]).await?;
+                    buf.drain(..safe);
+                    false
+                }
+            },
+        };
+        if progressed {
+            continue;
+        }
+        match body.next().await {
+            Some(Ok(chunk)) => {
+                received += chunk.len();
+                if received > limit {
+                    return Err(MultipartError::TooLarge { limit });
+                }
+                buf.extend_from_slice(&chunk);
+            }
+            Some(Err(e)) => return Err(MultipartError::Malformed(e.to_string())),
+            None => {
+                return Err(MultipartError::Malformed(
+                    match state {
+                        State::Preamble => "no opening boundary",
+                        State::Headers => "unterminated part headers",
+                        _ => "missing closing boundary",
+                    }
+                    .into(),
+                ));
+            }
+        }
+    }
+}
+
+/// Where `parse_stream` is in the body.
+enum State {
+    Preamble,
+    /// Right after a delimiter: `--` ends the body, CRLF starts a part
+    Boundary,
+    Headers,
+    Content(Part),
+}
+
+/// A part being received.
+struct Part {
+    name: Option<String>,
+    filename: Option<String>,
+    content_type: String,
+    size: usize,
+    memory: Vec<u8>,
+    /// Spool file of a file part past `MEMORY_THRESHOLD`
+    temp: Option<(PathBuf, tokio::fs::File)>,
+}
+
+impl Part {
+    fn new(headers: &HashMap<String, String>) -> Self {
+        let disposition = headers
+            .get("content-disposition")
+            .map(String::as_str)
+            .unwrap_or("");
+        let mut params = disposition_params(disposition);
+        Part {
+            name: params.remove("name"),
+            filename: params.remove("filename"),
+            content_type: headers
+                .get("content-type")
+                .cloned()
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            size: 0,
+            memory: Vec::new(),
+            temp: None,
+        }
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), MultipartError> {
+        self.size += data.len();
+        if self.temp.is_none()
+            && self.filename.is_some()
+            && self.memory.len() + data.len() > MEMORY_THRESHOLD
+        {
+            let (path, mut file) = create_temp_file().await.map_err(MultipartError::Io)?;
+            let spooled = file.write_all(&self.memory).await;
+            // Registered first so a failed write still removes the file
+            self.temp = Some((path, file));
+            spooled.map_err(MultipartError::Io)?;
+            self.memory = Vec::new();
+        }
+        match &mut self.temp {
+            Some((_, file)) => file.write_all(data).await.map_err(MultipartError::Io),
+            None => {
+                self.memory.extend_from_slice(data);
+                Ok(())
+            }
+        }
+    }
+
+    async fn finish(mut self, form: &mut Multipart) -> Result<(), MultipartError> {
+        let Some(name) = self.name.take() else {
+            return Ok(());
+        };
+        let Some(filename) = self.filename.take() else {
+            form.fields
+                .insert(name, String::from_utf8_lossy(&self.memory).into_owned());
+            return Ok(());
+        };
+        if let Some((_, file)) = &mut self.temp {
+            file.flush().await.map_err(MultipartError::Io)?;
+        }
+        let storage = match self.temp.take() {
+            Some((path, _)) => Storage::Temp(path),
+            None => Storage::Memory(Bytes::from(std::mem::take(&mut self.memory))),
+        };
+        form.files.push(UploadedFile {
+            field: name,
+            filename,
+            content_type: std::mem::take(&mut self.content_type),
+            size: self.size,
+            storage,
+        });
+        Ok(())
+    }
+}
+
+/// Remove the spool file of a part abandoned midway (malformed or too large).
+impl Drop for Part {
+    fn drop(&mut self) {
+        if let Some((path, _)) = &self.temp {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A new file in the temp directory with an unguessable name, created
+/// exclusively (never following an existing file or symlink) and readable
+/// by the owner only.
+async fn create_temp_file() -> std::io::Result<(PathBuf, tokio::fs::File)> {
+    loop {
+        let path =
+            std::env::temp_dir().join(format!("cobalto-upload-{:032x}", rand::random::<u128>()));
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(&path).await {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn parse_headers(block: &str) -> HashMap<String, String> {
+    block
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect()
+}
+
+/// `form-data; name="avatar"; filename="me.png"` → {name, filename}
+fn disposition_params(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .skip(1)
+        .filter_map(|p| p.trim().split_once('='))
+        .map(|(k, v)| {
+            (
+                k.trim().to_ascii_lowercase(),
+                v.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,138 @@
+//! Cobalto ID obfuscation
+//!
+//! Turns sequential integer IDs into short, non-sequential public strings and
+//! back, keyed from the application secret key. Route patterns opt in per
+//! parameter with `/orders/:id|sqid`: the router decodes the segment before
+//! the handler runs (the handler sees the plain number) and rejects values that
+//! do not decode. Serializers opt in with `#[serde(with = "cobalto::obfuscate::sqid")]`.
+//!
+//! There is no default key: without a `secret_key` setting, registering a
+//! `|sqid` route panics and the serde adapter fails.
+
+use std::sync::RwLock;
+
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+/// Odd multiplier (bijective mod 2^64) and its modular inverse.
+const MUL: u64 = 0x9E37_79B9_7F4A_7C15;
+const MUL_INV: u64 = 0xF1DE_83E1_9937_733D;
+
+/// Reversible encoder for integer IDs.
+#[derive(Clone, Debug)]
+pub struct IdCodec {
+    alphabet: Vec<u8>,
+    k1: u64,
+    k2: u64,
+}
+
+impl IdCodec {
+    /// Build a codec whose output depends on `secret`.
+    pub fn new(secret: &str) -> Self {
+        // FNV-1a over the secret seeds both the keys and the alphabet shuffle
+        let mut seed: u64 = 0xcbf2_9ce4_8422_2325;
+        for b in secret.bytes() {
+            seed ^= b as u64;
+            seed = seed.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+        let mut state = seed;
+        let mut next = || {
+            // splitmix64
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let k1 = next();
+        let k2 = next();
+        let mut alphabet = ALPHABET.to_vec();
+        for i in (1..alphabet.len()).rev() {
+            let j = (next() % (i as u64 + 1)) as usize;
+            alphabet.swap(i, j);
+        }
+        IdCodec { alphabet, k1, k2 }
+    }
+
+    /// Encode an ID into its public form.
+    pub fn encode(&self, id: u64) -> String {
+        let base = self.alphabet.len() as u64;
+        let mut n = (id ^ self.k1).wrapping_mul(MUL) ^ self.k2;
+        let mut out = Vec::new();
+        loop {
+            out.push(self.alphabet[(n % base) as usize]);
+            n /= base;
+            if n == 0 {
+                break;
+            }
+        }
+        String::from_utf8(out).unwrap_or_default()
+    }
+
+    /// Decode a public ID; `None` if it was not produced by this codec.
+    pub fn decode(&self, s: &str) -> Option<u64> {
+        if s.is_empty() {
+            return None;
+        }
+        let base = self.alphabet.len() as u64;
+        let mut n: u64 = 0;
+        for b in s.bytes().rev() {
+            let digit = self.alphabet.iter().position(|&a| a == b)? as u64;
+            n = n.checked_mul(base)?.checked_add(digit)?;
+        }
+        let id = (n ^ self.k2).wrapping_mul(MUL_INV) ^ self.k1;
+        // Reject non-canonical spellings (e.g. trailing zero digits)
+        (self.encode(id) == s).then_some(id)
+    }
+}
+
+/// The global codec; unset until a non-empty secret key is installed.
+static CODEC: RwLock<Option<IdCodec>> = RwLock::new(None);
+
+/// Install the global codec, normally from the application secret key. An
+/// empty secret is refused with a warning and leaves the codec unset.
+pub fn set_secret_key(secret: &str) {
+    if secret.is_empty() {
+        log::warn!("obfuscate: empty secret_key ignored, obfuscated IDs are disabled");
+        return;
+    }
+    *CODEC.write().unwrap() = Some(IdCodec::new(secret));
+}
+
+/// Whether a secret key has been installed.
+pub fn is_configured() -> bool {
+    CODEC.read().unwrap().is_some()
+}
+
+/// Encode an ID with the global codec; `None` without a secret key.
+pub fn encode_id(id: u64) -> Option<String> {
+    let codec = CODEC.read().unwrap();
+    if codec.is_none() {
+        log::error!("obfuscate: no secret_key configured, cannot encode IDs");
+    }
+    codec.as_ref().map(|c| c.encode(id))
+}
+
+/// Decode an ID with the global codec; `None` if invalid or without a secret
+/// key.
+pub fn decode_id(s: &str) -> Option<u64> {
+    let codec = CODEC.read().unwrap();
+    if codec.is_none() {
+        log::error!("obfuscate: no secret_key configured, cannot decode IDs");
+    }
+    codec.as_ref()?.decode(s)
+}
+
+/// Serde adapter exposing integer IDs in obfuscated form.
+pub mod sqid {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = super::encode_id(*id)
+            .ok_or_else(|| serde::ser::Error::custom("no secret_key configured"))?;
+        serializer.serialize_str(&encoded)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        super::decode_id(&s).ok_or_else(|| serde::de::Error::custom("invalid id"))
+    }
+}
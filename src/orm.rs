@@ -1,7 +1,1992 @@
 // cobalto/src/orm.rs
 
+use crate::signals::Signal;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sqlx::any::{AnyPool, AnyPoolOptions, AnyRow};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
 /// The core trait marking a struct as a Cobalto Model.
 /// Can be derived or implemented for table mapping, migrations, etc.
 pub trait Model: Sized + Send + Sync + 'static {
     fn table_name() -> &'static str;
+
+    /// Indexes declared on the model with `#[cobalto(index(...))]`.
+    fn indexes() -> Vec<Index> {
+        Vec::new()
+    }
+
+    /// Columns, with the options given by `#[cobalto(...)]` field attributes.
+    fn fields() -> Vec<Field> {
+        Vec::new()
+    }
+
+    /// Foreign keys declared by the model's fields, with their `on_delete`.
+    fn foreign_keys() -> Vec<ForeignKey> {
+        Self::fields()
+            .iter()
+            .filter_map(|f| {
+                let related = f.related?;
+                Some(ForeignKey {
+                    table: Self::table_name().to_string(),
+                    column: f.name.clone(),
+                    references: related.table.to_string(),
+                    references_column: (related.primary_key)(),
+                    on_delete: related.on_delete,
+                })
+            })
+            .collect()
+    }
+
+    /// Primary key column: the field marked `primary_key`, else `id`.
+    fn primary_key() -> String {
+        Self::fields()
+            .into_iter()
+            .find(|f| f.primary_key)
+            .map(|f| f.name)
+            .unwrap_or_else(|| "id".to_string())
+    }
+
+    /// Soft-deleted models keep deleted rows with `deleted_at` set; queries
+    /// skip them. On by default for models with a `deleted_at` field.
+    fn soft_delete() -> bool {
+        Self::fields().iter().any(|f| f.name == "deleted_at")
+    }
+
+    /// Start a query over the model's table: `User::objects(&db).filter("age__gte", 18)`.
+    /// Soft-deleted rows are left out, see `QuerySet::with_deleted`.
+    fn objects(db: &Db) -> QuerySet<Self> {
+        QuerySet::new(db.clone())
+    }
+
+    /// Column values in `fields()` order (generated by the derive).
+    fn values(&self) -> Vec<SqlValue> {
+        Vec::new()
+    }
+
+    /// Store the key the database assigned on insert.
+    fn set_primary_key(&mut self, _id: i64) {}
+
+    /// Store a generated key (see `Field::auto_id`); text keys need the
+    /// derive's override, integers go through `set_primary_key`.
+    fn set_primary_key_value(&mut self, value: SqlValue) {
+        if let SqlValue::Int(id) = value {
+            self.set_primary_key(id);
+        }
+    }
+
+    /// Value of the primary key column.
+    fn primary_key_value(&self) -> SqlValue {
+        self.value_of(&Self::primary_key())
+    }
+
+    /// Value of a column, `Null` when it isn't a declared field.
+    fn value_of(&self, column: &str) -> SqlValue {
+        Self::fields()
+            .iter()
+            .position(|f| f.name == column)
+            .and_then(|i| self.values().into_iter().nth(i))
+            .unwrap_or(SqlValue::Null)
+    }
+
+    /// The row a foreign key points at; the derive generates `post.author(&db)`
+    /// from an `author_id` field.
+    fn related<R: Model>(
+        &self,
+        db: &Db,
+        column: &str,
+    ) -> impl Future<Output = Result<Option<R>, sqlx::Error>> + Send
+    where
+        R: for<'r> sqlx::FromRow<'r, AnyRow> + Unpin,
+    {
+        let key = self.value_of(column);
+        let query = R::objects(db).filter(&R::primary_key(), key.clone());
+        async move {
+            if key == SqlValue::Null {
+                return Ok(None);
+            }
+            query.first().await
+        }
+    }
+
+    /// Rows of `R` whose foreign key points at this row; the derive generates
+    /// `user.post_set(&db)` for each model referencing `User`.
+    fn related_set<R: Model>(&self, db: &Db) -> QuerySet<R> {
+        let query = R::objects(db);
+        match R::fields()
+            .into_iter()
+            .find(|f| f.related.is_some_and(|r| r.table == Self::table_name()))
+        {
+            Some(fk) => query.filter(&fk.name, self.primary_key_value()),
+            None => query.invalid(&format!("{}_id", Self::table_name())),
+        }
+    }
+
+    /// Fetch a row by primary key.
+    fn get<V: Into<SqlValue>>(
+        db: &Db,
+        pk: V,
+    ) -> impl Future<Output = Result<Option<Self>, sqlx::Error>> + Send
+    where
+        Self: for<'r> sqlx::FromRow<'r, AnyRow> + Unpin,
+    {
+        let query = Self::objects(db).filter(&Self::primary_key(), pk);
+        async move { query.first().await }
+    }
+
+    /// Fetch every row.
+    fn all(db: &Db) -> impl Future<Output = Result<Vec<Self>, sqlx::Error>> + Send
+    where
+        Self: for<'r> sqlx::FromRow<'r, AnyRow> + Unpin,
+    {
+        Self::objects(db).all()
+    }
+
+    /// Insert the row, or update it when it already has a key. Integer primary
+    /// keys left at 0 are assigned by the database and stored back; `auto_id`
+    /// keys left empty get an ID from `ids::next_id` before the insert.
+    ///
+    /// Sends the `PreSave` and `PostSave` signals.
+    fn save(&mut self, db: &Db) -> impl Future<Output = Result<(), sqlx::Error>> + Send {
+        async move {
+            crate::signals::send(Signal::PreSave, &*self, false);
+            let fields = Self::fields();
+            let unset = |value: &SqlValue| match value {
+                SqlValue::Null | SqlValue::Int(0) => true,
+                SqlValue::Text(s) => s.is_empty(),
+                _ => false,
+            };
+            let mut generated = false;
+            if let Some(field) = fields.iter().find(|f| f.primary_key && f.auto_id)
+                && unset(&self.primary_key_value())
+            {
+                use crate::ids::AutoId;
+                self.set_primary_key_value(match field.field_type {
+                    FieldType::Integer => SqlValue::Int(i64::auto_id()),
+                    _ => SqlValue::Text(String::auto_id()),
+                });
+                generated = true;
+            }
+            let values = self.values();
+            let pk_value = self.primary_key_value();
+            let auto = fields.iter().any(|f| f.is_auto());
+            if !generated && !(auto && unset(&pk_value)) {
+                let mut params: Vec<SqlValue> = fields
+                    .iter()
+                    .zip(values.iter())
+                    .filter(|(f, _)| !f.primary_key)
+                    .map(|(_, v)| v.clone())
+                    .collect();
+                params.push(pk_value);
+                if db.execute_with(&update_sql::<Self>(), params).await? > 0 {
+                    crate::signals::send(Signal::PostSave, &*self, false);
+                    return Ok(());
+                }
+            }
+            let params = fields
+                .iter()
+                .zip(values)
+                .filter(|(f, _)| !f.is_auto())
+                .map(|(_, v)| v)
+                .collect();
+            let id = db
+                .insert_returning(&insert_sql::<Self>(), params, &Self::primary_key())
+                .await?;
+            if auto {
+                self.set_primary_key(id);
+            }
+            crate::signals::send(Signal::PostSave, &*self, true);
+            Ok(())
+        }
+    }
+
+    /// Delete the row by primary key, returning whether it existed. Rows
+    /// referencing it are handled by the `on_delete` of their relation (see
+    /// `register_relations`), and soft-deleted models only get `deleted_at` set.
+    ///
+    /// Sends `PreDelete`, and `PostDelete` if a row was deleted.
+    fn delete(&self, db: &Db) -> impl Future<Output = Result<bool, DeleteError>> + Send {
+        let pk_value = self.primary_key_value();
+        async move {
+            crate::signals::send(Signal::PreDelete, self, false);
+            let deleted = db
+                .delete_with_relations(
+                    Self::table_name(),
+                    &Self::primary_key(),
+                    pk_value,
+                    &registered_relations(),
+                    Self::soft_delete(),
+                )
+                .await?;
+            if deleted {
+                crate::signals::send(Signal::PostDelete, self, false);
+            }
+            Ok(deleted)
+        }
+    }
+}
+
+/// SQL dialect targeted by generated DDL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+    MySql,
+}
+
+impl Backend {
+    /// Dialect of a database URL: `postgres://`, `mysql://`, anything else SQLite.
+    pub fn from_url(url: &str) -> Self {
+        let scheme = url.split_once("://").map(|(s, _)| s).unwrap_or("");
+        match scheme.to_ascii_lowercase().as_str() {
+            "postgres" | "postgresql" => Backend::Postgres,
+            "mysql" | "mariadb" => Backend::MySql,
+            _ => Backend::Sqlite,
+        }
+    }
+
+    /// Rewrite `?` placeholders for the backend (`$1`, `$2`, ... on Postgres),
+    /// leaving quoted strings and identifiers alone.
+    pub fn placeholders(self, sql: &str) -> String {
+        if self != Backend::Postgres {
+            return sql.to_string();
+        }
+        let mut out = String::with_capacity(sql.len() + 8);
+        let mut quote: Option<char> = None;
+        let mut n = 0;
+        for c in sql.chars() {
+            match (quote, c) {
+                (None, '?') => {
+                    n += 1;
+                    out.push_str(&format!("${}", n));
+                    continue;
+                }
+                (None, '\'' | '"') => quote = Some(c),
+                (Some(q), c) if c == q => quote = None,
+                _ => {}
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    /// `ESCAPE` clause for `LIKE` patterns built with a backslash escape.
+    pub(crate) fn like_escape(self) -> &'static str {
+        match self {
+            // Backslash is itself an escape character in MySQL string literals
+            Backend::MySql => " ESCAPE '\\\\'",
+            _ => " ESCAPE '\\'",
+        }
+    }
+}
+
+/// A declarative index: `index(fields(email), unique, where = "deleted_at IS NULL")`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub fields: Vec<String>,
+    pub unique: bool,
+    pub where_clause: Option<String>,
+}
+
+impl Index {
+    /// Index named `<table>_<fields>_idx`.
+    pub fn new(table: &str, fields: &[&str]) -> Self {
+        Index {
+            name: format!("{}_{}_idx", table, fields.join("_")),
+            table: table.to_string(),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            unique: false,
+            where_clause: None,
+        }
+    }
+
+    /// Builder for unique indexes
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    /// Builder for partial indexes
+    pub fn filter(mut self, where_clause: &str) -> Self {
+        self.where_clause = Some(where_clause.to_string());
+        self
+    }
+
+    /// `CREATE INDEX` statement for the backend.
+    ///
+    /// Postgres builds the index `CONCURRENTLY` so writes aren't blocked (the
+    /// migration must then run outside a transaction); MySQL has no partial
+    /// indexes, so the `where` clause is dropped and an online build requested;
+    /// it has no `IF NOT EXISTS` either, which `Db::create_indexes` makes up for.
+    pub fn create_sql(&self, backend: Backend) -> String {
+        let unique = if self.unique { "UNIQUE " } else { "" };
+        let fields = self.fields.join(", ");
+        let filter = match (&self.where_clause, backend) {
+            (Some(w), Backend::Sqlite | Backend::Postgres) => format!(" WHERE {}", w),
+            _ => String::new(),
+        };
+        match backend {
+            Backend::Sqlite => format!(
+                "CREATE {}INDEX IF NOT EXISTS {} ON {} ({}){}",
+                unique, self.name, self.table, fields, filter
+            ),
+            Backend::Postgres => format!(
+                "CREATE {}INDEX CONCURRENTLY IF NOT EXISTS {} ON {} ({}){}",
+                unique, self.name, self.table, fields, filter
+            ),
+            Backend::MySql => format!(
+                "CREATE {}INDEX {} ON {} ({}) ALGORITHM=INPLACE LOCK=NONE",
+                unique, self.name, self.table, fields
+            ),
+        }
+    }
+
+    /// `DROP INDEX` statement for the backend.
+    pub fn drop_sql(&self, backend: Backend) -> String {
+        match backend {
+            Backend::Sqlite => format!("DROP INDEX IF EXISTS {}", self.name),
+            Backend::Postgres => format!("DROP INDEX CONCURRENTLY IF EXISTS {}", self.name),
+            Backend::MySql => format!("DROP INDEX {} ON {}", self.name, self.table),
+        }
+    }
+}
+
+/// Result of comparing declared indexes with the database.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexReport {
+    /// Declared but absent from the database.
+    pub missing: Vec<String>,
+    /// Present on a checked table but not declared (candidates for removal).
+    pub unused: Vec<String>,
+}
+
+/// What happens to referencing rows when the referenced row is deleted.
+///
+/// Set on FK fields with `#[cobalto(on_delete = "cascade" | "set_null" | "restrict")]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnDelete {
+    Cascade,
+    SetNull,
+    #[default]
+    Restrict,
+}
+
+impl OnDelete {
+    /// Parse the `on_delete = "..."` attribute value.
+    pub fn from_attr(value: &str) -> Option<Self> {
+        match value {
+            "cascade" => Some(OnDelete::Cascade),
+            "set_null" => Some(OnDelete::SetNull),
+            "restrict" => Some(OnDelete::Restrict),
+            _ => None,
+        }
+    }
+
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            OnDelete::Cascade => "CASCADE",
+            OnDelete::SetNull => "SET NULL",
+            OnDelete::Restrict => "RESTRICT",
+        }
+    }
+}
+
+/// A foreign key from `table.column` to `references(references_column)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    pub table: String,
+    pub column: String,
+    pub references: String,
+    pub references_column: String,
+    pub on_delete: OnDelete,
+}
+
+impl ForeignKey {
+    pub fn new(table: &str, column: &str, references: &str, on_delete: OnDelete) -> Self {
+        ForeignKey {
+            table: table.to_string(),
+            column: column.to_string(),
+            references: references.to_string(),
+            references_column: "id".to_string(),
+            on_delete,
+        }
+    }
+
+    /// Constraint clause for `CREATE TABLE`.
+    pub fn ddl(&self) -> String {
+        format!(
+            "FOREIGN KEY ({}) REFERENCES {}({}) ON DELETE {}",
+            self.column,
+            self.references,
+            self.references_column,
+            self.on_delete.as_sql()
+        )
+    }
+}
+
+static RELATIONS: Lazy<RwLock<Vec<ForeignKey>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Make foreign keys known to `Model::delete`, which applies their
+/// `on_delete` to the rows referencing a deleted row.
+pub fn register_relations(foreign_keys: &[ForeignKey]) {
+    let mut relations = RELATIONS.write().unwrap();
+    for fk in foreign_keys {
+        if !relations.contains(fk) {
+            relations.push(fk.clone());
+        }
+    }
+}
+
+/// Foreign keys registered with `register_relations`.
+pub fn registered_relations() -> Vec<ForeignKey> {
+    RELATIONS.read().unwrap().clone()
+}
+
+/// Storage type of a model field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    Integer,
+    Float,
+    Text,
+    Boolean,
+    DateTime,
+    Blob,
+}
+
+impl FieldType {
+    /// Column type for the backend; `max_length` turns text into `VARCHAR(n)`.
+    pub fn sql(&self, backend: Backend, max_length: Option<u32>) -> String {
+        match (self, backend, max_length) {
+            (FieldType::Integer, Backend::Sqlite, _) => "INTEGER".into(),
+            (FieldType::Integer, _, _) => "BIGINT".into(),
+            (FieldType::Float, Backend::Sqlite, _) => "REAL".into(),
+            (FieldType::Float, _, _) => "DOUBLE PRECISION".into(),
+            (FieldType::Text, Backend::Sqlite, _) => "TEXT".into(),
+            (FieldType::Text, _, Some(n)) => format!("VARCHAR({})", n),
+            (FieldType::Text, _, None) => "TEXT".into(),
+            (FieldType::Boolean, _, _) => "BOOLEAN".into(),
+            (FieldType::DateTime, Backend::Sqlite, _) => "TEXT".into(),
+            (FieldType::DateTime, Backend::Postgres, _) => "TIMESTAMPTZ".into(),
+            (FieldType::DateTime, Backend::MySql, _) => "DATETIME".into(),
+            (FieldType::Blob, Backend::Postgres, _) => "BYTEA".into(),
+            (FieldType::Blob, _, _) => "BLOB".into(),
+        }
+    }
+}
+
+/// The model a foreign key points at, resolved lazily so related lookups can
+/// walk into its fields.
+#[derive(Clone, Copy)]
+pub struct Related {
+    pub table: &'static str,
+    pub primary_key: fn() -> String,
+    pub fields: fn() -> Vec<Field>,
+    /// What deleting the target does to the referencing row.
+    pub on_delete: OnDelete,
+}
+
+impl Related {
+    pub fn of<R: Model>() -> Self {
+        Related {
+            table: R::table_name(),
+            primary_key: R::primary_key,
+            fields: R::fields,
+            on_delete: OnDelete::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Related {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Related")
+            .field("table", &self.table)
+            .field("on_delete", &self.on_delete)
+            .finish()
+    }
+}
+
+impl PartialEq for Related {
+    fn eq(&self, other: &Self) -> bool {
+        self.table == other.table && self.on_delete == other.on_delete
+    }
+}
+
+/// A model column: `#[cobalto(primary_key)]`, `#[cobalto(max_length = 255)]`,
+/// `#[cobalto(default = "now()")]`, `#[cobalto(unique)]`,
+/// `#[cobalto(foreign_key = "User", on_delete = "cascade")]`, `Option<T>` for nullable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub field_type: FieldType,
+    pub primary_key: bool,
+    pub max_length: Option<u32>,
+    pub default: Option<String>,
+    pub unique: bool,
+    pub nullable: bool,
+    /// Key generated by the application (`#[cobalto(primary_key, auto)]`), see `crate::ids`.
+    pub auto_id: bool,
+    /// Target model of a `#[cobalto(foreign_key = "User")]` field.
+    pub related: Option<Related>,
+}
+
+impl Field {
+    pub fn new(name: &str, field_type: FieldType) -> Self {
+        Field {
+            name: name.to_string(),
+            field_type,
+            primary_key: false,
+            max_length: None,
+            default: None,
+            unique: false,
+            nullable: false,
+            auto_id: false,
+            related: None,
+        }
+    }
+
+    /// Builder for the primary key
+    pub fn primary_key(mut self) -> Self {
+        self.primary_key = true;
+        self
+    }
+
+    /// Builder for a maximum text length (`VARCHAR(n)`, a `CHECK` on SQLite)
+    pub fn max_length(mut self, n: u32) -> Self {
+        self.max_length = Some(n);
+        self
+    }
+
+    /// Builder for a default: a SQL literal, or `now()` for the current time
+    pub fn default(mut self, expr: &str) -> Self {
+        self.default = Some(expr.to_string());
+        self
+    }
+
+    /// Builder for unique columns
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    /// Builder for nullable columns
+    pub fn nullable(mut self) -> Self {
+        self.nullable = true;
+        self
+    }
+
+    /// Builder for a primary key generated by the application (UUIDv7 or
+    /// snowflake, see `crate::ids`) instead of the database
+    pub fn auto_id(mut self) -> Self {
+        self.auto_id = true;
+        self
+    }
+
+    /// Builder for a foreign key to `R`
+    pub fn foreign_key<R: Model>(mut self) -> Self {
+        self.related = Some(Related::of::<R>());
+        self
+    }
+
+    /// Builder for the `on_delete` of a foreign key (`restrict` by default)
+    pub fn on_delete(mut self, on_delete: OnDelete) -> Self {
+        if let Some(related) = &mut self.related {
+            related.on_delete = on_delete;
+        }
+        self
+    }
+
+    /// Name of the relation a foreign key column stands for: `author` for
+    /// `author_id`, else the column name.
+    pub fn relation_name(&self) -> Option<&str> {
+        self.related?;
+        Some(self.name.strip_suffix("_id").unwrap_or(&self.name))
+    }
+
+    /// Integer primary keys are assigned by the database, unless `auto_id`.
+    pub fn is_auto(&self) -> bool {
+        self.primary_key && self.field_type == FieldType::Integer && !self.auto_id
+    }
+
+    /// The default as a SQL expression (`now()` becomes `CURRENT_TIMESTAMP`).
+    pub fn default_sql(&self) -> Option<String> {
+        let expr = self.default.as_deref()?;
+        Some(match expr.to_ascii_lowercase().as_str() {
+            "now()" => "CURRENT_TIMESTAMP".to_string(),
+            _ => expr.to_string(),
+        })
+    }
+
+    /// Column definition for `CREATE TABLE`.
+    pub fn ddl(&self, backend: Backend) -> String {
+        let mut ddl = format!(
+            "{} {}",
+            self.name,
+            self.field_type.sql(backend, self.max_length)
+        );
+        if self.primary_key {
+            ddl.push_str(" PRIMARY KEY");
+            if self.is_auto() {
+                ddl.push_str(match backend {
+                    Backend::Sqlite => " AUTOINCREMENT",
+                    Backend::Postgres => " GENERATED BY DEFAULT AS IDENTITY",
+                    Backend::MySql => " AUTO_INCREMENT",
+                });
+            }
+        } else if !self.nullable {
+            ddl.push_str(" NOT NULL");
+        }
+        if self.unique && !self.primary_key {
+            ddl.push_str(" UNIQUE");
+        }
+        if let Some(default) = self.default_sql() {
+            ddl.push_str(&format!(" DEFAULT {}", wrap_default(&default, backend)));
+        }
+        if let (Some(n), Backend::Sqlite, FieldType::Text) =
+            (self.max_length, backend, self.field_type)
+        {
+            ddl.push_str(&format!(" CHECK (length({}) <= {})", self.name, n));
+        }
+        ddl
+    }
+}
+
+/// SQLite and MySQL want non-literal defaults in parentheses.
+fn wrap_default(expr: &str, backend: Backend) -> String {
+    let literal = expr.starts_with('\'')
+        || expr.parse::<f64>().is_ok()
+        || matches!(
+            expr.to_ascii_uppercase().as_str(),
+            "NULL" | "TRUE" | "FALSE" | "CURRENT_TIMESTAMP"
+        );
+    if literal || backend == Backend::Postgres {
+        expr.to_string()
+    } else {
+        format!("({})", expr)
+    }
+}
+
+/// `CREATE TABLE` for a model, from its declared fields and foreign keys
+/// (`Model::foreign_keys`), plus `foreign_keys` on columns not declared as one.
+pub fn create_table_sql<M: Model>(backend: Backend, foreign_keys: &[ForeignKey]) -> String {
+    let mut columns: Vec<String> = M::fields().iter().map(|f| f.ddl(backend)).collect();
+    let declared = M::foreign_keys();
+    let extra = foreign_keys
+        .iter()
+        .filter(|fk| fk.table == M::table_name())
+        .filter(|fk| !declared.iter().any(|k| k.column == fk.column));
+    columns.extend(declared.iter().chain(extra).map(|fk| fk.ddl()));
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} ({})",
+        M::table_name(),
+        columns.join(", ")
+    )
+}
+
+/// Fields written by `INSERT`, i.e. all but database-assigned primary keys.
+pub fn insert_fields<M: Model>() -> Vec<Field> {
+    M::fields().into_iter().filter(|f| !f.is_auto()).collect()
+}
+
+/// `INSERT` binding one value per `insert_fields`; a NULL bound to a field
+/// with a default gets the default.
+pub fn insert_sql<M: Model>() -> String {
+    insert_sql_for(M::table_name(), &M::fields())
+}
+
+/// `insert_sql` for a table known only at runtime (e.g. in the admin).
+pub fn insert_sql_for(table: &str, fields: &[Field]) -> String {
+    let fields: Vec<&Field> = fields.iter().filter(|f| !f.is_auto()).collect();
+    let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+    let values: Vec<String> = fields
+        .iter()
+        .map(|f| match f.default_sql() {
+            Some(default) => format!("COALESCE(?, {})", default),
+            None => "?".to_string(),
+        })
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table,
+        names.join(", "),
+        values.join(", ")
+    )
+}
+
+/// `UPDATE` by primary key, binding every non-key field then the key.
+pub fn update_sql<M: Model>() -> String {
+    update_sql_for(M::table_name(), &M::fields(), &M::primary_key())
+}
+
+/// `update_sql` for a table known only at runtime.
+pub fn update_sql_for(table: &str, fields: &[Field], primary_key: &str) -> String {
+    let sets: Vec<String> = fields
+        .iter()
+        .filter(|f| !f.primary_key)
+        .map(|f| format!("{} = ?", f.name))
+        .collect();
+    format!(
+        "UPDATE {} SET {} WHERE {} = ?",
+        table,
+        sets.join(", "),
+        primary_key
+    )
+}
+
+/// Why a delete was refused.
+#[derive(Debug)]
+pub enum DeleteError {
+    /// A `restrict` relation still has referencing rows: (table, count).
+    Restricted(String, i64),
+    Db(sqlx::Error),
+}
+
+impl std::fmt::Display for DeleteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeleteError::Restricted(table, n) => {
+                write!(
+                    f,
+                    "Cannot delete: {} rows in '{}' still reference it",
+                    n, table
+                )
+            }
+            DeleteError::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DeleteError {}
+
+impl From<sqlx::Error> for DeleteError {
+    fn from(e: sqlx::Error) -> Self {
+        DeleteError::Db(e)
+    }
+}
+
+/// SQLite tuning applied to every pooled connection.
+///
+/// The defaults target small production deployments: WAL journaling so
+/// readers don't block the writer, a busy timeout instead of immediate
+/// `database is locked` errors, enforced foreign keys, and a single-writer
+/// queue serializing writes issued by concurrent actix workers.
+#[derive(Clone, Debug)]
+pub struct SqliteOptions {
+    pub wal: bool,
+    pub busy_timeout: Duration,
+    pub foreign_keys: bool,
+    pub single_writer: bool,
+    pub max_connections: u32,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        SqliteOptions {
+            wal: true,
+            busy_timeout: Duration::from_secs(5),
+            foreign_keys: true,
+            single_writer: true,
+            max_connections: 8,
+        }
+    }
+}
+
+/// Tables with at least this many rows are "large" for plan heuristics.
+pub const LARGE_TABLE_ROWS: i64 = 1000;
+
+/// `EXPLAIN QUERY PLAN` output with heuristic warnings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryPlan {
+    pub sql: String,
+    pub steps: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// A query slower than the configured threshold, with its plan.
+#[derive(Clone, Debug)]
+pub struct SlowQuery {
+    pub sql: String,
+    pub duration: Duration,
+    pub plan: Option<QueryPlan>,
+}
+
+/// Number of slow queries kept; older ones are forgotten.
+pub const SLOW_QUERY_LOG_SIZE: usize = 100;
+
+static SLOW_QUERIES: Lazy<RwLock<VecDeque<SlowQuery>>> =
+    Lazy::new(|| RwLock::new(VecDeque::with_capacity(SLOW_QUERY_LOG_SIZE)));
+
+/// The last `SLOW_QUERY_LOG_SIZE` slow queries, oldest first (listed on the
+/// debug page).
+pub fn slow_queries() -> Vec<SlowQuery> {
+    SLOW_QUERIES.read().unwrap().iter().cloned().collect()
+}
+
+/// Forget captured slow queries.
+pub fn clear_slow_queries() {
+    SLOW_QUERIES.write().unwrap().clear();
+}
+
+static FILTER_COLUMN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?:\bWHERE\b|\bAND\b|\bOR\b)\s+(?:\w+\.)?(\w+)\s*(?:=|<|>|!=|\bIN\b|\bLIKE\b|\bIS\b|\bBETWEEN\b)")
+        .unwrap()
+});
+
+/// Table scanned without an index by a plan step (`SCAN posts`, `SCAN TABLE posts`).
+fn scanned_table(step: &str) -> Option<&str> {
+    let rest = step.strip_prefix("SCAN ")?;
+    if rest.contains(" USING ") {
+        return None;
+    }
+    let rest = rest.strip_prefix("TABLE ").unwrap_or(rest);
+    let table = rest.split_whitespace().next()?;
+    (!matches!(table, "CONSTANT" | "SUBQUERY")).then_some(table)
+}
+
+/// Database handle wrapping a connection pool; the backend follows the URL.
+#[derive(Clone)]
+pub struct Db {
+    pub pool: AnyPool,
+    backend: Backend,
+    writer: Option<Arc<tokio::sync::Mutex<()>>>,
+    slow_query_threshold: Option<Duration>,
+}
+
+impl Db {
+    /// Connect with the default production-friendly options.
+    ///
+    /// `postgres://` and `mysql://` URLs need the `postgres` / `mysql` crate
+    /// features; anything else is a SQLite path or `sqlite:` URL.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        Self::connect_with(url, SqliteOptions::default()).await
+    }
+
+    /// Connect to the `database_url` setting (SQLite `cobalto.db` when unset).
+    pub async fn from_settings(settings: &crate::settings::Settings) -> Result<Self, sqlx::Error> {
+        Self::connect(settings.get_str("database_url").unwrap_or("cobalto.db")).await
+    }
+
+    /// Connect with explicit options. Only `max_connections` applies to
+    /// Postgres and MySQL; the rest are SQLite tuning.
+    pub async fn connect_with(url: &str, options: SqliteOptions) -> Result<Self, sqlx::Error> {
+        sqlx::any::install_default_drivers();
+        let backend = Backend::from_url(url);
+        if backend != Backend::Sqlite {
+            let pool = AnyPoolOptions::new()
+                .max_connections(options.max_connections.max(1))
+                .connect(url)
+                .await?;
+            return Ok(Db {
+                pool,
+                backend,
+                writer: None,
+                slow_query_threshold: None,
+            });
+        }
+
+        let in_memory = url.contains(":memory:");
+        let mut url = if url.starts_with("sqlite:") {
+            url.to_string()
+        } else {
+            format!("sqlite:{}", url)
+        };
+        // Create the file if missing
+        if !in_memory && !url.contains("mode=") {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str("mode=rwc");
+        }
+        let mut pragmas = vec![
+            format!("PRAGMA busy_timeout = {}", options.busy_timeout.as_millis()),
+            format!(
+                "PRAGMA foreign_keys = {}",
+                if options.foreign_keys { "ON" } else { "OFF" }
+            ),
+        ];
+        // WAL is meaningless for in-memory databases
+        if options.wal && !in_memory {
+            pragmas.push("PRAGMA journal_mode = WAL".to_string());
+            pragmas.push("PRAGMA synchronous = NORMAL".to_string());
+        }
+        let pragmas = Arc::new(pragmas);
+        // Every in-memory connection is a separate database, so keep just one
+        let max_connections = if in_memory {
+            1
+        } else {
+            options.max_connections.max(1)
+        };
+        let pool = AnyPoolOptions::new()
+            .max_connections(max_connections)
+            .after_connect(move |conn, _| {
+                let pragmas = pragmas.clone();
+                Box::pin(async move {
+                    for pragma in pragmas.iter() {
+                        sqlx::query(pragma).execute(&mut *conn).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect(&url)
+            .await?;
+        Ok(Db {
+            pool,
+            backend,
+            writer: options
+                .single_writer
+                .then(|| Arc::new(tokio::sync::Mutex::new(()))),
+            slow_query_threshold: None,
+        })
+    }
+
+    /// SQL dialect of the connected database.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Builder enabling slow query capture (with their plans), for debug mode
+    pub fn with_slow_query_log(mut self, threshold: Duration) -> Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    async fn record_timing(&self, sql: &str, duration: Duration) {
+        let Some(threshold) = self.slow_query_threshold else {
+            return;
+        };
+        if duration < threshold {
+            return;
+        }
+        let plan = self.explain(sql).await.ok();
+        log::warn!("slow query ({}ms): {}", duration.as_millis(), sql);
+        let mut captured = SLOW_QUERIES.write().unwrap();
+        if captured.len() == SLOW_QUERY_LOG_SIZE {
+            captured.pop_front();
+        }
+        captured.push_back(SlowQuery {
+            sql: sql.to_string(),
+            duration,
+            plan,
+        });
+    }
+
+    /// Run `EXPLAIN QUERY PLAN` and flag full scans of large tables and
+    /// filter columns without an index. SQLite only: other backends get an
+    /// empty plan.
+    pub async fn explain(&self, sql: &str) -> Result<QueryPlan, sqlx::Error> {
+        if self.backend != Backend::Sqlite {
+            return Ok(QueryPlan {
+                sql: sql.to_string(),
+                ..Default::default()
+            });
+        }
+        let rows: Vec<(i64, i64, i64, String)> =
+            sqlx::query_as(&format!("EXPLAIN QUERY PLAN {}", sql))
+                .fetch_all(&self.pool)
+                .await?;
+        let steps: Vec<String> = rows.into_iter().map(|(_, _, _, detail)| detail).collect();
+        let mut filter_columns: Vec<String> = Vec::new();
+        for caps in FILTER_COLUMN_RE.captures_iter(sql) {
+            if !filter_columns.contains(&caps[1].to_string()) {
+                filter_columns.push(caps[1].to_string());
+            }
+        }
+        let mut warnings = Vec::new();
+        for step in &steps {
+            let Some(table) = scanned_table(step) else {
+                continue;
+            };
+            let (count,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {}", table))
+                .fetch_one(&self.pool)
+                .await
+                .unwrap_or((0,));
+            if count >= LARGE_TABLE_ROWS {
+                warnings.push(format!(
+                    "sequential scan on large table '{}' ({} rows)",
+                    table, count
+                ));
+            }
+            for column in &filter_columns {
+                warnings.push(format!(
+                    "filter on '{}' scans '{}' without an index; consider: {}",
+                    column,
+                    table,
+                    Index::new(table, &[column.as_str()]).create_sql(Backend::Sqlite)
+                ));
+            }
+        }
+        Ok(QueryPlan {
+            sql: sql.to_string(),
+            steps,
+            warnings,
+        })
+    }
+
+    /// Execute a statement, going through the single-writer queue if enabled.
+    pub async fn execute(&self, sql: &str) -> Result<u64, sqlx::Error> {
+        let guard = match &self.writer {
+            Some(writer) => Some(writer.lock().await),
+            None => None,
+        };
+        let started = Instant::now();
+        let result = sqlx::query(sql).execute(&self.pool).await?;
+        // The plan of a slow query is looked up without blocking writers
+        drop(guard);
+        self.record_timing(sql, started.elapsed()).await;
+        Ok(result.rows_affected())
+    }
+
+    /// Create every declared index that doesn't exist yet.
+    pub async fn create_indexes(&self, indexes: &[Index]) -> Result<(), sqlx::Error> {
+        // MySQL has no `CREATE INDEX IF NOT EXISTS`: look the indexes up first
+        let existing = match self.backend {
+            Backend::MySql => self.existing_indexes().await?,
+            Backend::Sqlite | Backend::Postgres => Vec::new(),
+        };
+        for index in indexes {
+            if existing
+                .iter()
+                .any(|(name, table)| *name == index.name && *table == index.table)
+            {
+                continue;
+            }
+            self.execute(&index.create_sql(self.backend)).await?;
+        }
+        Ok(())
+    }
+
+    /// `(name, table)` of the indexes in the database, primary keys aside.
+    async fn existing_indexes(&self) -> Result<Vec<(String, String)>, sqlx::Error> {
+        self.fetch_all(match self.backend {
+            Backend::Sqlite => {
+                "SELECT name, tbl_name FROM sqlite_master \
+                 WHERE type = 'index' AND name NOT LIKE 'sqlite_autoindex_%'"
+            }
+            Backend::Postgres => {
+                "SELECT indexname, tablename FROM pg_indexes \
+                 WHERE schemaname = current_schema() AND indexname NOT LIKE '%_pkey'"
+            }
+            Backend::MySql => {
+                "SELECT DISTINCT index_name, table_name FROM information_schema.statistics \
+                 WHERE table_schema = DATABASE() AND index_name <> 'PRIMARY'"
+            }
+        })
+        .await
+    }
+
+    /// Compare declared indexes with the ones present in the database.
+    pub async fn check_indexes(&self, declared: &[Index]) -> Result<IndexReport, sqlx::Error> {
+        let existing = self.existing_indexes().await?;
+        let mut report = IndexReport::default();
+        for index in declared {
+            if !existing.iter().any(|(name, _)| *name == index.name) {
+                report.missing.push(index.name.clone());
+            }
+        }
+        for (name, table) in existing {
+            let checked = declared.iter().any(|i| i.table == table);
+            if checked && !declared.iter().any(|i| i.name == name) {
+                report.unused.push(name);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Fetch all rows of a query into `T`.
+    pub async fn fetch_all<T>(&self, sql: &str) -> Result<Vec<T>, sqlx::Error>
+    where
+        T: for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+    {
+        let started = Instant::now();
+        let rows = sqlx::query_as::<_, T>(sql).fetch_all(&self.pool).await?;
+        self.record_timing(sql, started.elapsed()).await;
+        Ok(rows)
+    }
+}
+
+/// A value bound to a query placeholder.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlValue {
+    Null,
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Bool(bool),
+    /// Only meaningful for `__in` lookups
+    List(Vec<SqlValue>),
+}
+
+impl From<i64> for SqlValue {
+    fn from(v: i64) -> Self {
+        SqlValue::Int(v)
+    }
+}
+
+impl From<i32> for SqlValue {
+    fn from(v: i32) -> Self {
+        SqlValue::Int(v as i64)
+    }
+}
+
+impl From<u32> for SqlValue {
+    fn from(v: u32) -> Self {
+        SqlValue::Int(v as i64)
+    }
+}
+
+impl From<f64> for SqlValue {
+    fn from(v: f64) -> Self {
+        SqlValue::Float(v)
+    }
+}
+
+impl From<bool> for SqlValue {
+    fn from(v: bool) -> Self {
+        SqlValue::Bool(v)
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(v: &str) -> Self {
+        SqlValue::Text(v.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(v: String) -> Self {
+        SqlValue::Text(v)
+    }
+}
+
+impl<T: Into<SqlValue>> From<Option<T>> for SqlValue {
+    fn from(v: Option<T>) -> Self {
+        v.map(Into::into).unwrap_or(SqlValue::Null)
+    }
+}
+
+impl<T: Into<SqlValue>> From<Vec<T>> for SqlValue {
+    fn from(v: Vec<T>) -> Self {
+        SqlValue::List(v.into_iter().map(Into::into).collect())
+    }
+}
+
+macro_rules! bind_values {
+    ($query:expr, $values:expr) => {{
+        let mut query = $query;
+        for value in $values {
+            query = match value {
+                SqlValue::Null => query.bind(None::<String>),
+                SqlValue::Int(v) => query.bind(v),
+                SqlValue::Float(v) => query.bind(v),
+                SqlValue::Text(v) => query.bind(v),
+                SqlValue::Bool(v) => query.bind(v),
+                // Lists are expanded into scalar placeholders when filtering
+                SqlValue::List(_) => query,
+            };
+        }
+        query
+    }};
+}
+
+impl Db {
+    /// Execute a statement with bound `?` parameters, through the single-writer queue.
+    pub async fn execute_with(&self, sql: &str, params: Vec<SqlValue>) -> Result<u64, sqlx::Error> {
+        let guard = match &self.writer {
+            Some(writer) => Some(writer.lock().await),
+            None => None,
+        };
+        let started = Instant::now();
+        let result = bind_values!(sqlx::query(&self.backend.placeholders(sql)), params)
+            .execute(&self.pool)
+            .await?;
+        drop(guard);
+        self.record_timing(sql, started.elapsed()).await;
+        Ok(result.rows_affected())
+    }
+
+    /// Execute `statements` with their bound `?` parameters in one
+    /// transaction, through the single-writer queue: either all of them
+    /// apply or none does.
+    pub async fn execute_atomic(
+        &self,
+        statements: &[(&str, Vec<SqlValue>)],
+    ) -> Result<(), sqlx::Error> {
+        let _guard = match &self.writer {
+            Some(writer) => Some(writer.lock().await),
+            None => None,
+        };
+        let mut tx = self.pool.begin().await?;
+        for (sql, params) in statements {
+            bind_values!(sqlx::query(&self.backend.placeholders(sql)), params.clone())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Like `execute_with`, returning the `id` of the inserted row.
+    pub async fn insert_with(&self, sql: &str, params: Vec<SqlValue>) -> Result<i64, sqlx::Error> {
+        self.insert_returning(sql, params, "id").await
+    }
+
+    /// Run an `INSERT` and return the generated `primary_key`: through
+    /// `RETURNING` on Postgres, the driver's last insert id elsewhere.
+    pub async fn insert_returning(
+        &self,
+        sql: &str,
+        params: Vec<SqlValue>,
+        primary_key: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let guard = match &self.writer {
+            Some(writer) => Some(writer.lock().await),
+            None => None,
+        };
+        let started = Instant::now();
+        let id = if self.backend == Backend::Postgres {
+            let sql = self
+                .backend
+                .placeholders(&format!("{} RETURNING {}", sql, primary_key));
+            bind_values!(sqlx::query_scalar::<_, i64>(&sql), params)
+                .fetch_one(&self.pool)
+                .await?
+        } else {
+            bind_values!(sqlx::query(sql), params)
+                .execute(&self.pool)
+                .await?
+                .last_insert_id()
+                .unwrap_or_default()
+        };
+        drop(guard);
+        self.record_timing(sql, started.elapsed()).await;
+        Ok(id)
+    }
+
+    /// Delete (or soft-delete) the row of `table` whose `primary_key` is `key`,
+    /// returning whether it existed. The `on_delete` behaviour of every relation
+    /// is applied in the ORM itself, down the whole relation graph, so the
+    /// outcome doesn't depend on the database enforcing constraints. Relations
+    /// leading back to a table already on the path are left to the database.
+    ///
+    /// With `soft_delete` the row gets `deleted_at` set instead of being removed,
+    /// and `cascade` relations are soft-deleted the same way.
+    pub async fn delete_with_relations(
+        &self,
+        table: &str,
+        primary_key: &str,
+        key: SqlValue,
+        relations: &[ForeignKey],
+        soft_delete: bool,
+    ) -> Result<bool, DeleteError> {
+        let condition = format!("{} = ?", primary_key);
+        let mut steps = Vec::new();
+        plan_delete(
+            table,
+            &condition,
+            relations,
+            &mut vec![table.to_string()],
+            &mut steps,
+        );
+        let _guard = match &self.writer {
+            Some(writer) => Some(writer.lock().await),
+            None => None,
+        };
+        let live = if soft_delete {
+            " AND deleted_at IS NULL"
+        } else {
+            ""
+        };
+        let mut tx = self.pool.begin().await?;
+        // Every condition binds the key once, at its innermost subquery
+        for step in steps {
+            match step {
+                DeleteStep::Restrict { table, condition } => {
+                    let sql = self.backend.placeholders(&format!(
+                        "SELECT COUNT(*) FROM {} WHERE {}{}",
+                        table, condition, live
+                    ));
+                    let count = bind_values!(sqlx::query_scalar::<_, i64>(&sql), [key.clone()])
+                        .fetch_one(&mut *tx)
+                        .await?;
+                    if count > 0 {
+                        return Err(DeleteError::Restricted(table, count));
+                    }
+                }
+                DeleteStep::SetNull {
+                    table,
+                    column,
+                    condition,
+                } => {
+                    let sql = self.backend.placeholders(&format!(
+                        "UPDATE {} SET {} = NULL WHERE {}",
+                        table, column, condition
+                    ));
+                    bind_values!(sqlx::query(&sql), [key.clone()])
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                DeleteStep::Delete { table, condition } => {
+                    let sql = self
+                        .backend
+                        .placeholders(&delete_sql(&table, &condition, soft_delete));
+                    bind_values!(sqlx::query(&sql), [key.clone()])
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+        }
+        let sql = self
+            .backend
+            .placeholders(&delete_sql(table, &condition, soft_delete));
+        let deleted = bind_values!(sqlx::query(&sql), [key])
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+}
+
+/// One statement of a delete following `on_delete` relations; conditions
+/// select the affected rows through subqueries up to the deleted row.
+enum DeleteStep {
+    Restrict {
+        table: String,
+        condition: String,
+    },
+    SetNull {
+        table: String,
+        column: String,
+        condition: String,
+    },
+    Delete {
+        table: String,
+        condition: String,
+    },
+}
+
+/// Steps deleting the rows of `table` matching `condition`, children first.
+fn plan_delete(
+    table: &str,
+    condition: &str,
+    relations: &[ForeignKey],
+    path: &mut Vec<String>,
+    steps: &mut Vec<DeleteStep>,
+) {
+    for fk in relations.iter().filter(|fk| fk.references == table) {
+        let referencing = format!(
+            "{} IN (SELECT {} FROM {} WHERE {})",
+            fk.column, fk.references_column, table, condition
+        );
+        match fk.on_delete {
+            OnDelete::Restrict => steps.push(DeleteStep::Restrict {
+                table: fk.table.clone(),
+                condition: referencing,
+            }),
+            OnDelete::SetNull => steps.push(DeleteStep::SetNull {
+                table: fk.table.clone(),
+                column: fk.column.clone(),
+                condition: referencing,
+            }),
+            OnDelete::Cascade if path.contains(&fk.table) => {}
+            OnDelete::Cascade => {
+                path.push(fk.table.clone());
+                plan_delete(&fk.table, &referencing, relations, path, steps);
+                path.pop();
+                steps.push(DeleteStep::Delete {
+                    table: fk.table.clone(),
+                    condition: referencing,
+                });
+            }
+        }
+    }
+}
+
+/// `DELETE`, or the `deleted_at` update of a soft delete, of matching rows.
+fn delete_sql(table: &str, condition: &str, soft_delete: bool) -> String {
+    if soft_delete {
+        format!(
+            "UPDATE {} SET deleted_at = CURRENT_TIMESTAMP WHERE {} AND deleted_at IS NULL",
+            table, condition
+        )
+    } else {
+        format!("DELETE FROM {} WHERE {}", table, condition)
+    }
+}
+
+static FIELD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+/// Escape `%`, `_` and `\` for a `LIKE ... ESCAPE '\'` pattern.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Escape `*`, `?` and `[` for a `GLOB` pattern.
+fn escape_glob(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '*' | '?' | '[' => format!("[{}]", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Map key matching rows on a column value.
+fn key_of(value: &SqlValue) -> String {
+    format!("{:?}", value)
+}
+
+pub(crate) fn text_of(value: &SqlValue) -> String {
+    match value {
+        SqlValue::Text(s) => s.clone(),
+        SqlValue::Int(i) => i.to_string(),
+        SqlValue::Float(f) => f.to_string(),
+        SqlValue::Bool(b) => b.to_string(),
+        SqlValue::Null | SqlValue::List(_) => String::new(),
+    }
+}
+
+/// Operators accepted after the last `__` of a lookup.
+const LOOKUP_OPS: &[&str] = &[
+    "exact",
+    "iexact",
+    "contains",
+    "icontains",
+    "startswith",
+    "istartswith",
+    "endswith",
+    "iendswith",
+    "gt",
+    "gte",
+    "lt",
+    "lte",
+    "ne",
+    "in",
+    "isnull",
+];
+
+/// The foreign key field called `name`, or `name_id`, and its target.
+fn relation<'a>(fields: &'a [Field], name: &str) -> Option<(&'a Field, Related)> {
+    fields.iter().find_map(|f| {
+        let related = f.related?;
+        (f.name == name || f.relation_name() == Some(name)).then_some((f, related))
+    })
+}
+
+/// SQL condition for one lookup against a table with `fields`, pushing its
+/// parameters. `Err` carries the offending field name.
+fn lookup_sql(
+    fields: &[Field],
+    backend: Backend,
+    lookup: &str,
+    value: SqlValue,
+    params: &mut Vec<SqlValue>,
+) -> Result<String, String> {
+    // `author__username`: a lookup on the related table, as a subquery
+    let nested = lookup
+        .split_once("__")
+        .filter(|(_, rest)| !LOOKUP_OPS.contains(rest))
+        .and_then(|(head, rest)| Some((relation(fields, head)?, rest)));
+    if let Some(((field, related), rest)) = nested {
+        let inner = lookup_sql(&(related.fields)(), backend, rest, value, params)?;
+        return Ok(format!(
+            "{} IN (SELECT {} FROM {} WHERE {})",
+            field.name,
+            (related.primary_key)(),
+            related.table,
+            inner
+        ));
+    }
+    let (field, op) = lookup.rsplit_once("__").unwrap_or((lookup, "exact"));
+    if !FIELD_RE.is_match(field) {
+        return Err(field.to_string());
+    }
+    // `author` filters on the `author_id` column
+    let field = relation(fields, field)
+        .map(|(f, _)| f.name.as_str())
+        .unwrap_or(field);
+    fn bind(v: SqlValue, params: &mut Vec<SqlValue>) -> &'static str {
+        params.push(v);
+        "?"
+    }
+    let like = |field: &str, pattern: String, params: &mut Vec<SqlValue>, operator: &str| {
+        format!(
+            "{} {} {}{}",
+            field,
+            operator,
+            bind(pattern.into(), params),
+            backend.like_escape()
+        )
+    };
+    let ilike = if backend == Backend::Postgres {
+        "ILIKE"
+    } else {
+        "LIKE"
+    };
+    let sql = match op {
+        "exact" if value == SqlValue::Null => format!("{} IS NULL", field),
+        "exact" => format!("{} = {}", field, bind(value, params)),
+        "ne" if value == SqlValue::Null => format!("{} IS NOT NULL", field),
+        "ne" => format!("{} != {}", field, bind(value, params)),
+        "iexact" => format!("LOWER({}) = LOWER({})", field, bind(value, params)),
+        "gt" => format!("{} > {}", field, bind(value, params)),
+        "gte" => format!("{} >= {}", field, bind(value, params)),
+        "lt" => format!("{} < {}", field, bind(value, params)),
+        "lte" => format!("{} <= {}", field, bind(value, params)),
+        "contains" if backend == Backend::Postgres => {
+            format!("strpos({}, {}) > 0", field, bind(value, params))
+        }
+        "contains" => format!("instr({}, {}) > 0", field, bind(value, params)),
+        "icontains" => {
+            let pattern = format!("%{}%", escape_like(&text_of(&value)));
+            like(field, pattern, params, ilike)
+        }
+        // SQLite's LIKE ignores case, so it uses GLOB; MySQL needs BINARY
+        "startswith" | "endswith" if backend != Backend::Sqlite => {
+            let text = escape_like(&text_of(&value));
+            let pattern = if op == "startswith" {
+                format!("{}%", text)
+            } else {
+                format!("%{}", text)
+            };
+            let operator = if backend == Backend::MySql {
+                "LIKE BINARY"
+            } else {
+                "LIKE"
+            };
+            like(field, pattern, params, operator)
+        }
+        "startswith" => {
+            let pattern = format!("{}*", escape_glob(&text_of(&value)));
+            format!("{} GLOB {}", field, bind(pattern.into(), params))
+        }
+        "istartswith" => {
+            let pattern = format!("{}%", escape_like(&text_of(&value)));
+            like(field, pattern, params, ilike)
+        }
+        "endswith" => {
+            let pattern = format!("*{}", escape_glob(&text_of(&value)));
+            format!("{} GLOB {}", field, bind(pattern.into(), params))
+        }
+        "iendswith" => {
+            let pattern = format!("%{}", escape_like(&text_of(&value)));
+            like(field, pattern, params, ilike)
+        }
+        "isnull" => {
+            let null = matches!(value, SqlValue::Bool(true) | SqlValue::Int(1));
+            format!("{} IS {}NULL", field, if null { "" } else { "NOT " })
+        }
+        "in" => {
+            let items = match value {
+                SqlValue::List(items) => items,
+                other => vec![other],
+            };
+            if items.is_empty() {
+                "0 = 1".to_string()
+            } else {
+                let marks: Vec<&str> = items.into_iter().map(|v| bind(v, params)).collect();
+                format!("{} IN ({})", field, marks.join(", "))
+            }
+        }
+        _ => {
+            // Not a known operator: the whole lookup is a field name
+            return lookup_sql(
+                fields,
+                backend,
+                &format!("{}__exact", lookup),
+                value,
+                params,
+            );
+        }
+    };
+    Ok(sql)
+}
+
+/// A lazily built, chainable query over a model's table.
+///
+/// Lookups follow Django's `field__op` convention: `exact` (default),
+/// `iexact`, `contains`, `icontains`, `startswith`, `istartswith`,
+/// `endswith`, `iendswith`, `gt`, `gte`, `lt`, `lte`, `ne`, `in`, `isnull`.
+/// Every value is bound as a parameter; field names are validated. Lookups
+/// follow foreign keys (`author__username`), and `select_related` /
+/// `prefetch_related` load related rows without a query per row.
+pub struct QuerySet<M: Model> {
+    db: Db,
+    /// `M`'s table and fields, or those given to `QuerySet::for_table`
+    table: String,
+    fields: Arc<Vec<Field>>,
+    conditions: Vec<String>,
+    params: Vec<SqlValue>,
+    order: Vec<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    error: Option<String>,
+    /// Skip soft-deleted rows
+    live_only: bool,
+    _model: std::marker::PhantomData<M>,
+}
+
+impl<M: Model> Clone for QuerySet<M> {
+    fn clone(&self) -> Self {
+        QuerySet {
+            db: self.db.clone(),
+            table: self.table.clone(),
+            fields: self.fields.clone(),
+            conditions: self.conditions.clone(),
+            params: self.params.clone(),
+            order: self.order.clone(),
+            limit: self.limit,
+            offset: self.offset,
+            error: self.error.clone(),
+            live_only: self.live_only,
+            _model: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<M: Model> QuerySet<M> {
+    pub fn new(db: Db) -> Self {
+        QuerySet {
+            db,
+            table: M::table_name().to_string(),
+            fields: Arc::new(M::fields()),
+            conditions: Vec::new(),
+            params: Vec::new(),
+            order: Vec::new(),
+            limit: None,
+            offset: None,
+            error: None,
+            live_only: M::soft_delete(),
+            _model: std::marker::PhantomData,
+        }
+    }
+
+    /// Columns of the queried table.
+    pub(crate) fn columns(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Fail the query on execution, naming the missing column.
+    fn invalid(mut self, field: &str) -> Self {
+        self.error.get_or_insert_with(|| field.to_string());
+        self
+    }
+
+    fn check_field(&mut self, field: &str) -> bool {
+        if FIELD_RE.is_match(field) {
+            return true;
+        }
+        self.error.get_or_insert_with(|| field.to_string());
+        false
+    }
+
+    /// SQL condition for one lookup, pushing its parameters.
+    fn condition(&mut self, lookup: &str, value: SqlValue) -> Option<String> {
+        match lookup_sql(
+            &self.fields,
+            self.db.backend,
+            lookup,
+            value,
+            &mut self.params,
+        ) {
+            Ok(sql) => Some(sql),
+            Err(field) => {
+                self.error.get_or_insert(field);
+                None
+            }
+        }
+    }
+
+    /// Keep rows matching the lookup
+    pub fn filter<V: Into<SqlValue>>(mut self, lookup: &str, value: V) -> Self {
+        if let Some(sql) = self.condition(lookup, value.into()) {
+            self.conditions.push(sql);
+        }
+        self
+    }
+
+    /// Drop rows matching the lookup
+    pub fn exclude<V: Into<SqlValue>>(mut self, lookup: &str, value: V) -> Self {
+        if let Some(sql) = self.condition(lookup, value.into()) {
+            self.conditions.push(format!("NOT ({})", sql));
+        }
+        self
+    }
+
+    /// Keep rows matching any of the lookups, each against `value`:
+    /// `.filter_any(&["name__icontains", "email__icontains"], "bob")`
+    pub fn filter_any<V: Into<SqlValue>>(mut self, lookups: &[&str], value: V) -> Self {
+        let value = value.into();
+        let conditions: Vec<String> = lookups
+            .iter()
+            .filter_map(|lookup| self.condition(lookup, value.clone()))
+            .collect();
+        if !conditions.is_empty() {
+            self.conditions.push(format!("({})", conditions.join(" OR ")));
+        }
+        self
+    }
+
+    /// Order by a field; prefix with `-` for descending
+    pub fn order_by(mut self, field: &str) -> Self {
+        let (name, dir) = match field.strip_prefix('-') {
+            Some(name) => (name, "DESC"),
+            None => (field, "ASC"),
+        };
+        if self.check_field(name) {
+            self.order.push(format!("{} {}", name, dir));
+        }
+        self
+    }
+
+    /// Builder for the maximum number of rows
+    pub fn limit(mut self, n: i64) -> Self {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Builder for the number of rows to skip
+    pub fn offset(mut self, n: i64) -> Self {
+        self.offset = Some(n);
+        self
+    }
+
+    /// Include soft-deleted rows
+    pub fn with_deleted(mut self) -> Self {
+        self.live_only = false;
+        self
+    }
+
+    fn where_clause(&self) -> String {
+        let mut conditions = self.conditions.clone();
+        if self.live_only {
+            conditions.insert(0, "deleted_at IS NULL".to_string());
+        }
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        }
+    }
+
+    /// The `SELECT` statement and its parameters, with `?` placeholders.
+    pub fn to_sql(&self) -> (String, Vec<SqlValue>) {
+        let mut sql = format!("SELECT * FROM {}{}", self.table, self.where_clause());
+        if !self.order.is_empty() {
+            sql.push_str(&format!(" ORDER BY {}", self.order.join(", ")));
+        }
+        match (self.limit, self.offset) {
+            (Some(l), Some(o)) => sql.push_str(&format!(" LIMIT {} OFFSET {}", l, o)),
+            (Some(l), None) => sql.push_str(&format!(" LIMIT {}", l)),
+            (None, Some(o)) => sql.push_str(&match self.db.backend {
+                Backend::Sqlite => format!(" LIMIT -1 OFFSET {}", o),
+                Backend::Postgres => format!(" OFFSET {}", o),
+                Backend::MySql => format!(" LIMIT 18446744073709551615 OFFSET {}", o),
+            }),
+            (None, None) => {}
+        }
+        (sql, self.params.clone())
+    }
+
+    fn check(&self) -> Result<(), sqlx::Error> {
+        match &self.error {
+            Some(field) => Err(sqlx::Error::ColumnNotFound(field.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Fetch every matching row.
+    pub async fn all(self) -> Result<Vec<M>, sqlx::Error>
+    where
+        M: for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+    {
+        self.check()?;
+        let (sql, params) = self.to_sql();
+        let sql = self.db.backend.placeholders(&sql);
+        let started = Instant::now();
+        let rows = bind_values!(sqlx::query_as::<_, M>(&sql), params)
+            .fetch_all(&self.db.pool)
+            .await?;
+        self.db.record_timing(&sql, started.elapsed()).await;
+        Ok(rows)
+    }
+
+    /// Fetch every matching row undecoded, e.g. for a `QuerySet::for_table`.
+    pub async fn rows(self) -> Result<Vec<AnyRow>, sqlx::Error> {
+        self.check()?;
+        let (sql, params) = self.to_sql();
+        let sql = self.db.backend.placeholders(&sql);
+        let started = Instant::now();
+        let rows = bind_values!(sqlx::query(&sql), params)
+            .fetch_all(&self.db.pool)
+            .await?;
+        self.db.record_timing(&sql, started.elapsed()).await;
+        Ok(rows)
+    }
+
+    /// Fetch the first matching row.
+    pub async fn first(self) -> Result<Option<M>, sqlx::Error>
+    where
+        M: for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+    {
+        Ok(self.limit(1).all().await?.into_iter().next())
+    }
+
+    /// Count matching rows (ignores ordering and slicing).
+    pub async fn count(&self) -> Result<i64, sqlx::Error> {
+        self.check()?;
+        let sql = self.db.backend.placeholders(&format!(
+            "SELECT COUNT(*) FROM {}{}",
+            self.table,
+            self.where_clause()
+        ));
+        bind_values!(sqlx::query_scalar::<_, i64>(&sql), self.params.clone())
+            .fetch_one(&self.db.pool)
+            .await
+    }
+
+    /// Whether any row matches.
+    pub async fn exists(&self) -> Result<bool, sqlx::Error> {
+        Ok(self.count().await? > 0)
+    }
+
+    /// Delete matching rows, through the single-writer queue if enabled.
+    pub async fn delete(self) -> Result<u64, sqlx::Error> {
+        self.check()?;
+        let _guard = match &self.db.writer {
+            Some(writer) => Some(writer.lock().await),
+            None => None,
+        };
+        let sql = self.db.backend.placeholders(&format!(
+            "DELETE FROM {}{}",
+            self.table,
+            self.where_clause()
+        ));
+        let result = bind_values!(sqlx::query(&sql), self.params)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Fetch rows together with the row each one's foreign key `name` points
+    /// at, all loaded by one JOIN instead of a query per row.
+    pub async fn select_related<R>(self, name: &str) -> Result<Vec<(M, Option<R>)>, sqlx::Error>
+    where
+        M: for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+        R: Model + for<'r> sqlx::FromRow<'r, AnyRow> + Clone + Send + Unpin,
+    {
+        self.check()?;
+        let fk = match relation(&M::fields(), name) {
+            Some((field, related)) if related.table == R::table_name() => field.name.clone(),
+            _ => return Err(sqlx::Error::ColumnNotFound(name.to_string())),
+        };
+        let pk = R::primary_key();
+        let (inner, params) = self.to_sql();
+        let sql = self.db.backend.placeholders(&format!(
+            "SELECT r.* FROM ({}) m JOIN {} r ON r.{} = m.{}",
+            inner,
+            R::table_name(),
+            pk,
+            fk
+        ));
+        let started = Instant::now();
+        let related = bind_values!(sqlx::query_as::<_, R>(&sql), params)
+            .fetch_all(&self.db.pool)
+            .await?;
+        self.db.record_timing(&sql, started.elapsed()).await;
+        let by_key: HashMap<String, R> = related
+            .into_iter()
+            .map(|r| (key_of(&r.value_of(&pk)), r))
+            .collect();
+        let rows = self.all().await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let related = by_key.get(&key_of(&row.value_of(&fk))).cloned();
+                (row, related)
+            })
+            .collect())
+    }
+
+    /// Fetch rows together with the `R` rows referencing each of them, all
+    /// loaded by one batched `IN` query.
+    pub async fn prefetch_related<R>(self) -> Result<Vec<(M, Vec<R>)>, sqlx::Error>
+    where
+        M: for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+        R: Model + for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+    {
+        let fk = R::fields()
+            .into_iter()
+            .find(|f| f.related.is_some_and(|r| r.table == M::table_name()))
+            .map(|f| f.name)
+            .ok_or_else(|| sqlx::Error::ColumnNotFound(format!("{}_id", M::table_name())))?;
+        let db = self.db.clone();
+        let rows = self.all().await?;
+        let pk = M::primary_key();
+        let keys: Vec<SqlValue> = rows.iter().map(|row| row.value_of(&pk)).collect();
+        let mut groups: HashMap<String, Vec<R>> = HashMap::new();
+        if !keys.is_empty() {
+            let related = R::objects(&db)
+                .filter(&format!("{}__in", fk), SqlValue::List(keys))
+                .all()
+                .await?;
+            for r in related {
+                groups.entry(key_of(&r.value_of(&fk))).or_default().push(r);
+            }
+        }
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let related = groups
+                    .remove(&key_of(&row.value_of(&pk)))
+                    .unwrap_or_default();
+                (row, related)
+            })
+            .collect())
+    }
+
+    /// Query plan of the `SELECT` (see `Db::explain`).
+    pub async fn explain(&self) -> Result<QueryPlan, sqlx::Error> {
+        self.check()?;
+        self.db.explain(&self.to_sql().0).await
+    }
+}
+
+/// Stands for a table known only at runtime, see `QuerySet::for_table`.
+pub struct AnyTable;
+
+impl Model for AnyTable {
+    fn table_name() -> &'static str {
+        ""
+    }
+}
+
+impl QuerySet<AnyTable> {
+    /// Query over a table known only at runtime (e.g. in the admin); fetch
+    /// with `rows`.
+    pub fn for_table(db: &Db, table: &str, fields: &[Field]) -> Self {
+        let mut query = QuerySet::new(db.clone());
+        query.table = table.to_string();
+        query.fields = Arc::new(fields.to_vec());
+        query.live_only = fields.iter().any(|f| f.name == "deleted_at");
+        query
+    }
+}
+
+/// Awaiting a queryset fetches every matching row: `user.post_set(&db).await`.
+impl<M> std::future::IntoFuture for QuerySet<M>
+where
+    M: Model + for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+{
+    type Output = Result<Vec<M>, sqlx::Error>;
+    type IntoFuture = futures::future::BoxFuture<'static, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.all())
+    }
 }
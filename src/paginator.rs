@@ -0,0 +1,162 @@
+//! Cobalto pagination
+//!
+//! Splits a `QuerySet` into pages so list views never do OFFSET math by hand:
+//!
+//! ```ignore
+//! let page = Paginator::new(Post::objects(&db).order_by("-id"), 20)
+//!     .page_param(req.query.get("page").map(String::as_str))
+//!     .await?;
+//! context.insert("page".into(), page.context());
+//! ```
+//!
+//! and in the template:
+//!
+//! ```text
+//! {% for post in page.items %}...{% endfor %}
+//! {% if page.has_previous %}<a href="?page={{ page.previous }}">Newer</a>{% endif %}
+//! Page {{ page.number }} of {{ page.total_pages }}
+//! ```
+
+use crate::orm::{Model, QuerySet};
+use crate::template::TemplateValue;
+use sqlx::any::AnyRow;
+use std::collections::HashMap;
+
+/// Pages over a query; out-of-range page numbers are clamped to the valid range.
+pub struct Paginator<M: Model> {
+    queryset: QuerySet<M>,
+    per_page: usize,
+}
+
+impl<M: Model> Paginator<M> {
+    pub fn new(queryset: QuerySet<M>, per_page: usize) -> Self {
+        Paginator {
+            queryset,
+            per_page: per_page.max(1),
+        }
+    }
+
+    /// Fetch page `number`, counting from 1.
+    pub async fn page(&self, number: usize) -> Result<Page<M>, sqlx::Error>
+    where
+        M: for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+    {
+        let total_items = self.queryset.count().await?.max(0) as usize;
+        let total_pages = total_items.div_ceil(self.per_page).max(1);
+        let number = number.clamp(1, total_pages);
+        let items = self
+            .queryset
+            .clone()
+            .limit(self.per_page as i64)
+            .offset(((number - 1) * self.per_page) as i64)
+            .all()
+            .await?;
+        Ok(Page {
+            items,
+            number,
+            per_page: self.per_page,
+            total_items,
+            total_pages,
+        })
+    }
+
+    /// Fetch the page named by a raw `?page=` value; missing or invalid means 1.
+    pub async fn page_param(&self, raw: Option<&str>) -> Result<Page<M>, sqlx::Error>
+    where
+        M: for<'r> sqlx::FromRow<'r, AnyRow> + Send + Unpin,
+    {
+        let number = raw.and_then(|p| p.trim().parse().ok()).unwrap_or(1);
+        self.page(number).await
+    }
+}
+
+/// One page of results.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page<M> {
+    pub items: Vec<M>,
+    /// 1-based page number.
+    pub number: usize,
+    pub per_page: usize,
+    pub total_items: usize,
+    pub total_pages: usize,
+}
+
+impl<M> Page<M> {
+    pub fn has_next(&self) -> bool {
+        self.number < self.total_pages
+    }
+
+    pub fn has_previous(&self) -> bool {
+        self.number > 1
+    }
+
+    pub fn next_page_number(&self) -> Option<usize> {
+        self.has_next().then_some(self.number + 1)
+    }
+
+    pub fn previous_page_number(&self) -> Option<usize> {
+        self.has_previous().then_some(self.number - 1)
+    }
+
+    /// 1-based index of the first item on the page (0 when empty).
+    pub fn start_index(&self) -> usize {
+        if self.items.is_empty() {
+            0
+        } else {
+            (self.number - 1) * self.per_page + 1
+        }
+    }
+
+    /// 1-based index of the last item on the page.
+    pub fn end_index(&self) -> usize {
+        (self.number - 1) * self.per_page + self.items.len()
+    }
+
+    /// Template context with items serialized as they would be by serde.
+    pub fn context(&self) -> TemplateValue
+    where
+        M: serde::Serialize,
+    {
+        self.context_with(TemplateValue::from_serialize)
+    }
+
+    /// Template context with `item` converting each row: `items`, `number`,
+    /// `total_pages`, `total_items`, `has_next`, `has_previous`, `next`,
+    /// `previous`, `start_index`, `end_index` and `page_range`.
+    pub fn context_with<F>(&self, item: F) -> TemplateValue
+    where
+        F: Fn(&M) -> TemplateValue,
+    {
+        let number = |n: usize| TemplateValue::Number(n as f64);
+        let optional = |n: Option<usize>| {
+            n.map(number)
+                .unwrap_or_else(|| TemplateValue::String(String::new()))
+        };
+        let mut context = HashMap::new();
+        context.insert(
+            "items".to_string(),
+            TemplateValue::List(self.items.iter().map(item).collect()),
+        );
+        context.insert("number".to_string(), number(self.number));
+        context.insert("per_page".to_string(), number(self.per_page));
+        context.insert("total_pages".to_string(), number(self.total_pages));
+        context.insert("total_items".to_string(), number(self.total_items));
+        context.insert("has_next".to_string(), TemplateValue::Bool(self.has_next()));
+        context.insert(
+            "has_previous".to_string(),
+            TemplateValue::Bool(self.has_previous()),
+        );
+        context.insert("next".to_string(), optional(self.next_page_number()));
+        context.insert(
+            "previous".to_string(),
+            optional(self.previous_page_number()),
+        );
+        context.insert("start_index".to_string(), number(self.start_index()));
+        context.insert("end_index".to_string(), number(self.end_index()));
+        context.insert(
+            "page_range".to_string(),
+            TemplateValue::List((1..=self.total_pages).map(number).collect()),
+        );
+        TemplateValue::Object(context)
+    }
+}
@@ -0,0 +1,395 @@
+//! Cobalto payments app (feature `payments`)
+//!
+//! Stripe helpers: Checkout Session creation, signed webhook verification, a
+//! `Payment` model and a `{% buy_button price_id %}` template tag. Keys come
+//! from `Settings.other` (`stripe_secret_key`, `stripe_webhook_secret`), and
+//! fulfillment registered with `on_fulfilled` runs on the task queue.
+
+use crate::clock;
+use crate::orm::{Db, Field, FieldType, Model, SqlValue};
+use crate::router::{IntoResponse, Request, Response};
+use crate::settings::Settings;
+use crate::tasks::TaskQueue;
+use crate::template::{TagArgs, TemplateValue, escape_html};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+const STRIPE_API: &str = "https://api.stripe.com/v1";
+/// Maximum accepted age of a webhook signature, in seconds.
+pub const WEBHOOK_TOLERANCE_SECS: i64 = 300;
+
+/// A payment as persisted by the app.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Payment {
+    pub id: i64,
+    pub session_id: String,
+    pub customer_email: Option<String>,
+    pub amount_total: i64,
+    pub currency: String,
+    pub status: String,
+}
+
+impl Model for Payment {
+    fn table_name() -> &'static str {
+        "payment"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("session_id", FieldType::Text).unique(),
+            Field::new("customer_email", FieldType::Text).nullable(),
+            Field::new("amount_total", FieldType::Integer),
+            Field::new("currency", FieldType::Text),
+            Field::new("status", FieldType::Text),
+        ]
+    }
+
+    fn values(&self) -> Vec<SqlValue> {
+        vec![
+            self.id.into(),
+            self.session_id.clone().into(),
+            self.customer_email.clone().into(),
+            self.amount_total.into(),
+            self.currency.clone().into(),
+            self.status.clone().into(),
+        ]
+    }
+
+    fn set_primary_key(&mut self, id: i64) {
+        self.id = id;
+    }
+}
+
+impl Payment {
+    /// DDL for the payments table.
+    pub const CREATE_TABLE: &'static str = "CREATE TABLE IF NOT EXISTS payment (\
+        id INTEGER PRIMARY KEY AUTOINCREMENT, \
+        session_id TEXT NOT NULL UNIQUE, \
+        customer_email TEXT, \
+        amount_total INTEGER NOT NULL, \
+        currency TEXT NOT NULL, \
+        status TEXT NOT NULL)";
+}
+
+/// Stripe credentials read from settings.
+#[derive(Clone, Debug)]
+pub struct StripeConfig {
+    pub secret_key: String,
+    pub webhook_secret: String,
+}
+
+impl StripeConfig {
+    /// `None` unless both keys are set and non-empty: with an empty webhook
+    /// secret anyone could sign events.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        let key = |name: &str| {
+            settings
+                .other
+                .get(name)
+                .filter(|value| !value.is_empty())
+                .cloned()
+        };
+        Some(StripeConfig {
+            secret_key: key("stripe_secret_key")?,
+            webhook_secret: key("stripe_webhook_secret")?,
+        })
+    }
+}
+
+/// Parameters of a Checkout Session.
+#[derive(Clone, Debug)]
+pub struct CheckoutRequest {
+    pub price_id: String,
+    pub quantity: u32,
+    pub success_url: String,
+    pub cancel_url: String,
+    pub customer_email: Option<String>,
+    /// Sent as `Idempotency-Key`, so retrying with the same key (e.g. the
+    /// order id) returns the session already created instead of a new one.
+    /// A random key is used when unset.
+    pub idempotency_key: Option<String>,
+}
+
+impl CheckoutRequest {
+    /// Form-encoded body expected by `POST /v1/checkout/sessions`.
+    pub fn form_params(&self) -> Vec<(String, String)> {
+        let mut params = vec![
+            ("mode".to_string(), "payment".to_string()),
+            ("line_items[0][price]".to_string(), self.price_id.clone()),
+            (
+                "line_items[0][quantity]".to_string(),
+                self.quantity.to_string(),
+            ),
+            ("success_url".to_string(), self.success_url.clone()),
+            ("cancel_url".to_string(), self.cancel_url.clone()),
+        ];
+        if let Some(email) = &self.customer_email {
+            params.push(("customer_email".to_string(), email.clone()));
+        }
+        params
+    }
+}
+
+/// The fields of a Checkout Session the app cares about.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CheckoutSession {
+    pub id: String,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub amount_total: Option<i64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub customer_email: Option<String>,
+    #[serde(default)]
+    pub payment_status: Option<String>,
+}
+
+/// Create a Checkout Session; redirect the customer to the returned `url`.
+pub async fn create_checkout_session(
+    config: &StripeConfig,
+    request: &CheckoutRequest,
+) -> Result<CheckoutSession, reqwest::Error> {
+    let idempotency_key = request
+        .idempotency_key
+        .clone()
+        .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+    reqwest::Client::new()
+        .post(format!("{}/checkout/sessions", STRIPE_API))
+        .basic_auth(&config.secret_key, Some(""))
+        .header("Idempotency-Key", idempotency_key)
+        .form(&request.form_params())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// Why a webhook was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub enum WebhookError {
+    MissingSignature,
+    InvalidSignature,
+    Expired,
+    InvalidPayload,
+}
+
+fn mac(secret: &str, timestamp: i64, payload: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    mac
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Compute the `v1` signature Stripe sends for `payload` at `timestamp`.
+pub fn sign_payload(secret: &str, timestamp: i64, payload: &str) -> String {
+    to_hex(&mac(secret, timestamp, payload).finalize().into_bytes())
+}
+
+/// Verify a `Stripe-Signature` header (`t=...,v1=...`) and parse the event.
+///
+/// Nothing verifies against an empty secret.
+pub fn verify_webhook(
+    secret: &str,
+    signature_header: &str,
+    payload: &str,
+) -> Result<serde_json::Value, WebhookError> {
+    if secret.is_empty() {
+        return Err(WebhookError::InvalidSignature);
+    }
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in signature_header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", t)) => timestamp = t.parse::<i64>().ok(),
+            Some(("v1", sig)) => signatures.push(sig),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.ok_or(WebhookError::MissingSignature)?;
+    if signatures.is_empty() {
+        return Err(WebhookError::MissingSignature);
+    }
+    // `verify_slice` compares in constant time
+    let valid = signatures.iter().any(|sig| {
+        from_hex(sig).is_some_and(|sig| mac(secret, timestamp, payload).verify_slice(&sig).is_ok())
+    });
+    if !valid {
+        return Err(WebhookError::InvalidSignature);
+    }
+    if (clock::now().timestamp() - timestamp).abs() > WEBHOOK_TOLERANCE_SECS {
+        return Err(WebhookError::Expired);
+    }
+    serde_json::from_str(payload).map_err(|_| WebhookError::InvalidPayload)
+}
+
+/// Fulfillment task queued for every newly paid checkout.
+pub type FulfillmentHandler =
+    Arc<dyn Fn(Payment) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// Webhook endpoint state.
+#[derive(Clone)]
+pub struct StripeWebhook {
+    pub config: StripeConfig,
+    db: Db,
+    queue: TaskQueue,
+    fulfilled: Option<FulfillmentHandler>,
+}
+
+impl StripeWebhook {
+    /// Webhook storing payments in `db` and fulfilling them on `queue`.
+    ///
+    /// `None` without a webhook secret, so a misconfigured app refuses to
+    /// serve the endpoint rather than accept forged events.
+    pub fn new(config: StripeConfig, db: Db, queue: TaskQueue) -> Option<Self> {
+        if config.webhook_secret.is_empty() {
+            return None;
+        }
+        Some(StripeWebhook {
+            config,
+            db,
+            queue,
+            fulfilled: None,
+        })
+    }
+
+    /// Register the fulfillment task (e.g. deliver the order).
+    pub fn on_fulfilled<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Payment) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.fulfilled = Some(Arc::new(move |payment| Box::pin(f(payment))));
+        self
+    }
+
+    /// Handle a webhook request given its `Stripe-Signature` header.
+    ///
+    /// Stripe retries deliveries, so the payment is upserted on its
+    /// `session_id` and fulfillment is queued only when the row becomes
+    /// `paid`; a retry of an already paid session changes nothing.
+    pub async fn handle(&self, req: &Request, signature_header: &str) -> Response {
+        let event = match verify_webhook(&self.config.webhook_secret, signature_header, &req.body) {
+            Ok(event) => event,
+            Err(e) => {
+                return Response::json(serde_json::json!({"error": format!("{:?}", e)}))
+                    .with_status(400);
+            }
+        };
+        if event["type"] == "checkout.session.completed" {
+            let object = &event["data"]["object"];
+            if let Ok(session) = serde_json::from_value::<CheckoutSession>(object.clone()) {
+                let payment = Payment {
+                    id: 0,
+                    session_id: session.id,
+                    customer_email: session.customer_email,
+                    amount_total: session.amount_total.unwrap_or(0),
+                    currency: session.currency.unwrap_or_default(),
+                    status: session.payment_status.unwrap_or_else(|| "paid".to_string()),
+                };
+                let newly_paid = match self.record(payment).await {
+                    Ok(newly_paid) => newly_paid,
+                    // Answer an error so Stripe retries the delivery
+                    Err(e) => return e.into_response(),
+                };
+                if let (Some(payment), Some(f)) = (newly_paid, &self.fulfilled)
+                    && self
+                        .queue
+                        .enqueue("stripe_fulfillment", f(payment))
+                        .await
+                        .is_err()
+                {
+                    return Response::json(serde_json::json!({"error": "Task queue closed"}))
+                        .with_status(503);
+                }
+            }
+        }
+        Response::json(serde_json::json!({"received": true}))
+    }
+
+    /// Upsert `payment` by `session_id`, returning the stored row if it just
+    /// became `paid`.
+    async fn record(&self, mut payment: Payment) -> Result<Option<Payment>, sqlx::Error> {
+        let paid = payment.status == "paid";
+        match payment.save(&self.db).await {
+            Ok(()) => return Ok(paid.then_some(payment)),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {}
+            Err(e) => return Err(e),
+        }
+        // Already recorded: only an unpaid row may change, so concurrent
+        // retries can't both see the transition
+        let updated = self
+            .db
+            .execute_with(
+                "UPDATE payment SET customer_email = ?, amount_total = ?, currency = ?, status = ? \
+                 WHERE session_id = ? AND status <> 'paid'",
+                vec![
+                    payment.customer_email.clone().into(),
+                    payment.amount_total.into(),
+                    payment.currency.clone().into(),
+                    payment.status.clone().into(),
+                    payment.session_id.clone().into(),
+                ],
+            )
+            .await?;
+        if !(paid && updated > 0) {
+            return Ok(None);
+        }
+        Payment::objects(&self.db)
+            .filter("session_id", payment.session_id)
+            .first()
+            .await
+    }
+}
+
+/// Renderer behind `{% buy_button price_id label="Buy" url="/checkout" %}`.
+///
+/// Renders a form posting `price_id` to the app's checkout endpoint.
+pub fn buy_button_tag(args: &TagArgs, context: &HashMap<String, TemplateValue>) -> String {
+    let price = match args.positional.first() {
+        Some(raw) => TagArgs::resolve(raw, context),
+        None => return String::new(),
+    };
+    let label = args
+        .named
+        .get("label")
+        .map(|l| TagArgs::resolve(l, context))
+        .unwrap_or_else(|| "Buy now".to_string());
+    let url = args
+        .named
+        .get("url")
+        .map(|u| TagArgs::resolve(u, context))
+        .unwrap_or_else(|| "/checkout".to_string());
+    format!(
+        r#"<form method="post" action="{}" class="cobalto-buy-button"><input type="hidden" name="price_id" value="{}"><button type="submit">{}</button></form>"#,
+        escape_html(&url),
+        escape_html(&price),
+        escape_html(&label)
+    )
+}
+
+/// Register the payments template tags.
+pub fn register_tags() {
+    crate::template::register_tag("buy_button", buy_button_tag);
+}
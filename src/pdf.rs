@@ -0,0 +1,148 @@
+//! Cobalto PDF generation (feature `pdf`)
+//!
+//! Renders a template to HTML with a `TemplateEngine` and converts it to PDF
+//! through a headless renderer, streaming the PDF out as the converter writes
+//! it. The converter binary defaults to `wkhtmltopdf` and can be changed with
+//! the `COBALTO_PDF_BIN` environment variable; it must read HTML on stdin and
+//! write the PDF to stdout (`<bin> - -`).
+//!
+//! ```ignore
+//! async fn invoice(req: Request) -> Response {
+//!     Response::pdf_from_template(&req.templates(), "invoice.html", &context).await
+//! }
+//! ```
+
+use crate::router::{BodyStream, Response};
+use crate::template::{TemplateEngine, TemplateValue};
+use actix_web::web::Bytes;
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdout, Command};
+
+/// Size of the chunks read from the converter.
+const PDF_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Converts an HTML document to a PDF body streamed from the configured
+/// backend.
+///
+/// The HTML is written to the converter while its output is read, so large
+/// documents cannot deadlock on full pipes. Fails if the converter cannot be
+/// started or exits without output; a failure after the first bytes ends the
+/// stream with an error.
+pub async fn html_to_pdf(html: String) -> std::io::Result<BodyStream> {
+    let bin = std::env::var("COBALTO_PDF_BIN").unwrap_or_else(|_| "wkhtmltopdf".to_string());
+    let mut child = Command::new(bin)
+        .args(["--quiet", "-", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    tokio::spawn(async move {
+        // Closing stdin (on drop) tells the converter the document is complete
+        let _ = stdin.write_all(html.as_bytes()).await;
+    });
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let first = read_chunk(&mut stdout).await?;
+    if first.is_empty() {
+        exit_status(&mut child).await?;
+        return Err(std::io::Error::other("PDF backend wrote nothing"));
+    }
+    let chunks = futures::stream::unfold(
+        (Some(first), Some((child, stdout))),
+        |(pending, running)| async move {
+            if let Some(chunk) = pending {
+                return Some((Ok(chunk), (None, running)));
+            }
+            let (mut child, mut stdout) = running?;
+            match read_chunk(&mut stdout).await {
+                Ok(chunk) if !chunk.is_empty() => Some((Ok(chunk), (None, Some((child, stdout))))),
+                Ok(_) => match exit_status(&mut child).await {
+                    Ok(()) => None,
+                    Err(e) => Some((Err(e), (None, None))),
+                },
+                Err(e) => Some((Err(e), (None, None))),
+            }
+        },
+    );
+    Ok(BodyStream::new(chunks))
+}
+
+/// Next chunk of converter output, empty at the end.
+async fn read_chunk(stdout: &mut ChildStdout) -> std::io::Result<Bytes> {
+    let mut buf = vec![0u8; PDF_CHUNK_SIZE];
+    let n = stdout.read(&mut buf).await?;
+    buf.truncate(n);
+    Ok(Bytes::from(buf))
+}
+
+async fn exit_status(child: &mut Child) -> std::io::Result<()> {
+    let status = child.wait().await?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "PDF backend exited with {}",
+            status
+        )))
+    }
+}
+
+impl Response {
+    /// PDF response rendered from a template by `engine`, served inline as
+    /// `<name>.pdf`
+    pub async fn pdf_from_template(
+        engine: &TemplateEngine,
+        template_name: &str,
+        context: &HashMap<String, TemplateValue>,
+    ) -> Self {
+        let rendered = engine.render(template_name, context);
+        if rendered.status != 200 {
+            return rendered;
+        }
+        match html_to_pdf(rendered.body).await {
+            Ok(body) => {
+                let stem = template_name
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(template_name)
+                    .trim_end_matches(".html");
+                Response::pdf_stream(body, &format!("{}.pdf", stem))
+            }
+            Err(e) => Response::html(format!("PDF generation failed: {}", e)).with_status(500),
+        }
+    }
+
+    /// PDF response from raw bytes
+    pub fn pdf(bytes: Vec<u8>, filename: &str) -> Self {
+        let mut resp = Self::pdf_headers(filename)
+            .add_header("Content-Length".to_string(), bytes.len().to_string());
+        resp.binary = Some(bytes);
+        resp
+    }
+
+    /// PDF response streamed from `body`
+    pub fn pdf_stream(body: BodyStream, filename: &str) -> Self {
+        let mut resp = Self::pdf_headers(filename);
+        resp.stream = Some(body);
+        resp
+    }
+
+    fn pdf_headers(filename: &str) -> Self {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "application/pdf".to_string());
+        headers.insert(
+            "Content-Disposition".to_string(),
+            format!("inline; filename=\"{}\"", filename),
+        );
+        Self {
+            status: 200,
+            body: String::new(),
+            headers,
+            binary: None,
+            stream: None,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Cobalto plugins
+//!
+//! Third-party crates extend an application by implementing `CobaltoPlugin`
+//! and registering it with `register_plugin!`. Registration happens at link
+//! time (through `inventory`), so adding the crate to Cargo.toml is enough to
+//! make the plugin available; it is enabled by listing its name in the
+//! `plugins` setting (comma separated). `Router::new` lets enabled plugins
+//! adjust the settings before applying them, and `Router::install_plugins`
+//! installs the rest.
+//!
+//! ```ignore
+//! struct Blog;
+//! impl CobaltoPlugin for Blog {
+//!     fn name(&self) -> &'static str { "blog" }
+//!     fn routes(&self, router: &mut Router) { /* ... */ }
+//! }
+//! cobalto::register_plugin!(Blog);
+//! ```
+
+use crate::orm::{Db, Index, Model};
+use crate::router::{Handler, Router};
+use crate::settings::Settings;
+
+#[doc(hidden)]
+pub use inventory;
+
+/// DDL and indexes of a model contributed by a plugin.
+#[derive(Clone, Debug)]
+pub struct ModelSchema {
+    pub table: &'static str,
+    pub create_sql: &'static str,
+    pub indexes: Vec<Index>,
+}
+
+impl ModelSchema {
+    /// Schema of model `M` created by `create_sql`.
+    pub fn of<M: Model>(create_sql: &'static str) -> Self {
+        ModelSchema {
+            table: M::table_name(),
+            create_sql,
+            indexes: M::indexes(),
+        }
+    }
+}
+
+/// Extension points of a plugin; every hook is optional.
+pub trait CobaltoPlugin: Send + Sync {
+    /// Name used in the `plugins` setting.
+    fn name(&self) -> &'static str;
+
+    /// Adjust settings; runs in `Router::new`, before they take effect.
+    fn configure(&self, _settings: &mut Settings) {}
+
+    /// Register the plugin's routes.
+    fn routes(&self, _router: &mut Router) {}
+
+    /// Middleware applied to every route handler when it is dispatched,
+    /// including routes added after `install_plugins`.
+    fn wrap(&self, handler: Handler) -> Handler {
+        handler
+    }
+
+    /// Register template tags and context processors.
+    fn template_tags(&self) {}
+
+    /// Models whose tables the plugin needs.
+    fn models(&self) -> Vec<ModelSchema> {
+        Vec::new()
+    }
+}
+
+/// Link-time registration record collected by `inventory`.
+pub struct PluginRegistration {
+    pub plugin: &'static dyn CobaltoPlugin,
+}
+
+inventory::collect!(PluginRegistration);
+
+/// Register a plugin value (a `static`-promotable expression, usually a unit struct).
+#[macro_export]
+macro_rules! register_plugin {
+    ($plugin:expr) => {
+        $crate::plugins::inventory::submit! {
+            $crate::plugins::PluginRegistration { plugin: &$plugin }
+        }
+    };
+}
+
+/// Every plugin linked into the binary.
+pub fn available_plugins() -> Vec<&'static dyn CobaltoPlugin> {
+    inventory::iter::<PluginRegistration>
+        .into_iter()
+        .map(|r| r.plugin)
+        .collect()
+}
+
+/// Plugins listed in the `plugins` setting, in the listed order.
+pub fn enabled_plugins(settings: &Settings) -> Vec<&'static dyn CobaltoPlugin> {
+    let available = available_plugins();
+    settings
+        .other
+        .get("plugins")
+        .map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .filter_map(|name| {
+                    let found = available.iter().find(|p| p.name() == name).copied();
+                    if found.is_none() {
+                        log::warn!("plugin '{}' is enabled but not linked", name);
+                    }
+                    found
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Let the enabled plugins adjust `settings` (done by `Router::new`).
+pub fn configure_plugins(settings: &mut Settings) {
+    for plugin in enabled_plugins(settings) {
+        plugin.configure(settings);
+    }
+}
+
+impl Router {
+    /// Install the enabled plugins: template tags, routes, then middleware
+    /// over every handler (see `Router::wrap_handlers`). Returns the installed
+    /// plugin names.
+    pub fn install_plugins(&mut self) -> Vec<&'static str> {
+        let plugins = enabled_plugins(&self.settings);
+        for plugin in &plugins {
+            plugin.template_tags();
+            plugin.routes(self);
+        }
+        for plugin in &plugins {
+            let plugin = *plugin;
+            self.wrap_handlers(move |handler| plugin.wrap(handler));
+        }
+        plugins.iter().map(|p| p.name()).collect()
+    }
+}
+
+/// Create the tables and indexes of the enabled plugins' models.
+pub async fn migrate_plugins(db: &Db, settings: &Settings) -> Result<(), sqlx::Error> {
+    for plugin in enabled_plugins(settings) {
+        for model in plugin.models() {
+            db.execute(model.create_sql).await?;
+            db.create_indexes(&model.indexes).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//! Cobalto task progress reporting
+//!
+//! Long-running tasks report `(percent, message)` updates through a `Progress`
+//! handle. The latest update is kept in the cache under `progress:<task_id>`
+//! for polling, and every update is published on the `progress:<task_id>`
+//! pub/sub topic so SSE or websocket handlers can stream it to the browser.
+//! The `{% progress_bar %}` template tag renders a ready-made bar listening on
+//! an SSE endpoint.
+//!
+//! ```ignore
+//! let progress = tracker
+//!     .enqueue(&queue, &job_id, |progress| async move {
+//!         for (i, row) in rows.iter().enumerate() {
+//!             import(row).await;
+//!             progress.update((i * 100 / rows.len()) as u8, "Importing");
+//!         }
+//!     })
+//!     .await?;
+//! ```
+//!
+//! Finished tasks stay readable for `finished_ttl` (5 minutes by default) and
+//! their topic is dropped, ending the subscribers' streams; tasks that stop
+//! reporting expire after `ttl` (an hour).
+
+use crate::cache::{Cache, default_cache};
+use crate::pubsub::{PubSub, Subscription};
+use crate::tasks::{QueueClosed, TaskQueue};
+use crate::template::{TagArgs, TemplateValue, escape_html};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+/// How long the last update of a running task is kept.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long a finished task stays readable.
+pub const DEFAULT_FINISHED_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// A single progress report.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProgressUpdate {
+    pub task_id: String,
+    pub percent: u8,
+    pub message: String,
+    pub done: bool,
+}
+
+impl ProgressUpdate {
+    /// Formats the update as a Server-Sent Events frame.
+    pub fn to_sse(&self) -> String {
+        crate::sse::Event::json(self).event("progress").to_string()
+    }
+}
+
+/// Stores the latest progress of every task and fans updates out.
+#[derive(Clone)]
+pub struct ProgressTracker {
+    cache: Cache,
+    hub: PubSub,
+    ttl: Duration,
+    finished_ttl: Duration,
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        Self::new(PubSub::new())
+    }
+}
+
+impl ProgressTracker {
+    /// Tracker publishing on `hub` and storing in the default cache.
+    pub fn new(hub: PubSub) -> Self {
+        ProgressTracker {
+            cache: default_cache(),
+            hub,
+            ttl: DEFAULT_TTL,
+            finished_ttl: DEFAULT_FINISHED_TTL,
+        }
+    }
+
+    /// Builder for the cache holding the latest updates
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Builder for how long running and finished tasks are kept
+    pub fn ttl(mut self, running: Duration, finished: Duration) -> Self {
+        self.ttl = running;
+        self.finished_ttl = finished;
+        self
+    }
+
+    /// Cache key and pub/sub topic of a task.
+    fn key(task_id: &str) -> String {
+        format!("progress:{}", task_id)
+    }
+
+    /// Start tracking a task and return the handle the task reports through.
+    pub fn start(&self, task_id: &str) -> Progress {
+        let progress = Progress {
+            task_id: task_id.to_string(),
+            tracker: self.clone(),
+        };
+        progress.update(0, "Queued");
+        progress
+    }
+
+    /// Start tracking `task_id` and run `task` on `queue` with its handle;
+    /// the task is marked finished when `task` returns without doing so.
+    pub async fn enqueue<F, Fut>(
+        &self,
+        queue: &TaskQueue,
+        task_id: &str,
+        task: F,
+    ) -> Result<Progress, QueueClosed>
+    where
+        F: FnOnce(Progress) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let progress = self.start(task_id);
+        let handle = progress.clone();
+        let name = format!("progress:{}", task_id);
+        queue
+            .enqueue(&name, async move {
+                task(handle.clone()).await;
+                if !handle.is_finished() {
+                    handle.finish("Done");
+                }
+            })
+            .await?;
+        Ok(progress)
+    }
+
+    fn record(&self, update: ProgressUpdate) {
+        let key = Self::key(&update.task_id);
+        let Ok(json) = serde_json::to_string(&update) else {
+            return;
+        };
+        let ttl = if update.done {
+            self.finished_ttl
+        } else {
+            self.ttl
+        };
+        self.cache.set(&key, json.clone(), Some(ttl));
+        self.hub.publish(&key, json);
+        if update.done {
+            // Subscribers read the final update, then their stream ends
+            self.hub.remove(&key);
+        }
+    }
+
+    /// Latest known progress of a task (for polling endpoints).
+    pub fn get(&self, task_id: &str) -> Option<ProgressUpdate> {
+        serde_json::from_str(&self.cache.get(&Self::key(task_id))?).ok()
+    }
+
+    /// Subscribe to the JSON-encoded updates of a task.
+    pub fn subscribe(&self, task_id: &str) -> Subscription {
+        self.hub.subscribe(&Self::key(task_id))
+    }
+
+    /// Forget a task before its entry expires.
+    pub fn clear(&self, task_id: &str) {
+        let key = Self::key(task_id);
+        self.cache.delete(&key);
+        self.hub.remove(&key);
+    }
+}
+
+/// Handle held by a running task.
+#[derive(Clone)]
+pub struct Progress {
+    pub task_id: String,
+    tracker: ProgressTracker,
+}
+
+impl Progress {
+    /// Report progress; `percent` is clamped to 100.
+    pub fn update(&self, percent: u8, message: &str) {
+        self.tracker.record(ProgressUpdate {
+            task_id: self.task_id.clone(),
+            percent: percent.min(100),
+            message: message.to_string(),
+            done: false,
+        });
+    }
+
+    /// Whether the task has been marked finished (or has expired).
+    pub fn is_finished(&self) -> bool {
+        self.tracker.get(&self.task_id).is_none_or(|update| update.done)
+    }
+
+    /// Mark the task as finished.
+    pub fn finish(&self, message: &str) {
+        self.tracker.record(ProgressUpdate {
+            task_id: self.task_id.clone(),
+            percent: 100,
+            message: message.to_string(),
+            done: true,
+        });
+    }
+}
+
+/// Renderer behind the built-in `{% progress_bar task_id url="/progress" %}` tag.
+///
+/// The bar listens on `<url>/<task_id>` for SSE `progress` events.
+pub fn progress_bar_tag(args: &TagArgs, context: &HashMap<String, TemplateValue>) -> String {
+    let task_id = match args.positional.first() {
+        Some(raw) => TagArgs::resolve(raw, context),
+        None => return String::new(),
+    };
+    let url = args
+        .named
+        .get("url")
+        .map(|u| TagArgs::resolve(u, context))
+        .unwrap_or_else(|| "/progress".to_string());
+    let element_id = format!("progress-{}", task_id);
+    let source = format!(
+        "{}/{}",
+        url.trim_end_matches('/'),
+        crate::router::percent_encode(&task_id)
+    );
+    format!(
+        r#"<div class="cobalto-progress" id="{id_attr}">
+<progress max="100" value="0"></progress> <span class="cobalto-progress-message"></span>
+<script>
+(function() {{
+  var root = document.getElementById({id_js});
+  var source = new EventSource({source_js});
+  source.addEventListener("progress", function(e) {{
+    var p = JSON.parse(e.data);
+    root.querySelector("progress").value = p.percent;
+    root.querySelector(".cobalto-progress-message").textContent = p.message;
+    if (p.done) source.close();
+  }});
+}})();
+</script>
+</div>"#,
+        id_attr = escape_html(&element_id),
+        id_js = script_string(&element_id),
+        source_js = script_string(&source),
+    )
+}
+
+/// `s` as a JavaScript string literal that cannot close the `<script>` element.
+fn script_string(s: &str) -> String {
+    serde_json::to_string(s)
+        .unwrap_or_default()
+        .replace('<', "\\u003c")
+}
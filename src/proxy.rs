@@ -0,0 +1,216 @@
+//! Cobalto reverse proxy awareness
+//!
+//! Behind nginx or a load balancer the peer address is the proxy's, and the
+//! client's address, scheme and host arrive in `X-Forwarded-For`,
+//! `X-Forwarded-Proto` and `X-Forwarded-Host`. Anyone can send those headers,
+//! so they are only honored when the peer is listed in the `trusted_proxies`
+//! setting:
+//!
+//! ```toml
+//! trusted_proxies = ["127.0.0.1", "10.0.0.0/8"]
+//! ```
+//!
+//! Handlers read the result with `req.client_ip()`, `req.scheme()`,
+//! `req.host()` and `req.absolute_url(path)`; session cookies are marked
+//! `Secure` on HTTPS requests.
+
+use crate::router::{Request, current_request};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// An address or network a forwarded header is trusted from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ProxyNet {
+    Any,
+    Net(IpAddr, u8),
+}
+
+impl ProxyNet {
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim();
+        if entry == "*" {
+            return Some(ProxyNet::Any);
+        }
+        let (addr, prefix) = match entry.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().ok()?)),
+            None => (entry, None),
+        };
+        let addr: IpAddr = addr.parse().ok()?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        match prefix {
+            Some(prefix) if prefix > bits => None,
+            prefix => Some(ProxyNet::Net(addr, prefix.unwrap_or(bits))),
+        }
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let (net, prefix) = match *self {
+            ProxyNet::Any => return true,
+            ProxyNet::Net(net, prefix) => (net, prefix),
+        };
+        match (net, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether `entry` is a valid `trusted_proxies` item: an address, a network
+/// (`10.0.0.0/8`) or `*` for any peer.
+pub fn is_valid_proxy(entry: &str) -> bool {
+    ProxyNet::parse(entry).is_some()
+}
+
+/// The peers whose forwarded headers are honored.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    nets: Vec<ProxyNet>,
+}
+
+/// Client address, scheme and host of a request, after forwarded headers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Forwarded {
+    /// Empty when unknown
+    pub client_ip: String,
+    /// `http` or `https`
+    pub scheme: String,
+    /// As requested, with any port; empty when the request had no `Host`
+    pub host: String,
+}
+
+impl TrustedProxies {
+    /// Proxies from the `trusted_proxies` entries; invalid ones are skipped
+    /// (`Settings::set` rejects them).
+    pub fn new<S: AsRef<str>>(entries: &[S]) -> Self {
+        TrustedProxies {
+            nets: entries
+                .iter()
+                .filter_map(|e| ProxyNet::parse(e.as_ref()))
+                .collect(),
+        }
+    }
+
+    pub fn from_settings(settings: &crate::settings::Settings) -> Self {
+        TrustedProxies::new(&settings.trusted_proxies)
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|net| net.contains(ip))
+    }
+
+    /// Client address: from a trusted peer, the right-most `X-Forwarded-For`
+    /// entry that is not itself a trusted proxy; otherwise the peer.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<String> {
+        let peer = peer?;
+        if !self.is_trusted(peer) {
+            return Some(peer.to_string());
+        }
+        let hops = hops(forwarded_for);
+        let client = self.client_hop(&hops).map(|i| hops[i]);
+        Some(client.map_or_else(|| peer.to_string(), str::to_string))
+    }
+
+    /// Index of the client in the `X-Forwarded-For` hops of a trusted peer.
+    fn client_hop(&self, hops: &[&str]) -> Option<usize> {
+        hops.iter()
+            .rposition(|hop| !hop.parse().is_ok_and(|ip| self.is_trusted(ip)))
+            .or((!hops.is_empty()).then_some(0))
+    }
+
+    /// Resolve a request from `peer` with lowercased `headers`, received over
+    /// TLS or not.
+    pub fn resolve(
+        &self,
+        peer: Option<IpAddr>,
+        headers: &HashMap<String, String>,
+        tls: bool,
+    ) -> Forwarded {
+        let trusted = peer.is_some_and(|ip| self.is_trusted(ip));
+        // Proxies chaining the headers append, so values left of what the
+        // trusted proxies added came from the client: take the one added by
+        // the proxy the client connected to, as many hops from the right as
+        // `X-Forwarded-For` went through trusted proxies.
+        let forwarded_for = hops(headers.get("x-forwarded-for").map(String::as_str));
+        let trusted_hops = self
+            .client_hop(&forwarded_for)
+            .map_or(1, |client| forwarded_for.len() - client);
+        let forwarded = |name: &str| {
+            let values = hops(headers.get(name).filter(|_| trusted).map(String::as_str));
+            values
+                .get(values.len().saturating_sub(trusted_hops))
+                .map(|v| v.to_string())
+        };
+        let scheme = forwarded("x-forwarded-proto")
+            .map(|proto| proto.to_ascii_lowercase())
+            .filter(|proto| proto == "http" || proto == "https")
+            .unwrap_or_else(|| if tls { "https" } else { "http" }.to_string());
+        let host = forwarded("x-forwarded-host")
+            .or_else(|| headers.get("host").cloned())
+            .unwrap_or_default();
+        Forwarded {
+            client_ip: self
+                .client_ip(peer, headers.get("x-forwarded-for").map(String::as_str))
+                .unwrap_or_default(),
+            scheme,
+            host,
+        }
+    }
+}
+
+/// The non-empty, comma-separated entries of a forwarded header.
+fn hops(value: Option<&str>) -> Vec<&str> {
+    value
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .collect()
+}
+
+impl Request {
+    /// The client's address, through trusted proxies (`None` outside a
+    /// request or when unknown).
+    pub fn client_ip(&self) -> Option<String> {
+        current_request()
+            .map(|scope| scope.forwarded.client_ip)
+            .filter(|ip| !ip.is_empty())
+    }
+
+    /// `http` or `https`, as the client sees it.
+    pub fn scheme(&self) -> String {
+        current_request()
+            .map(|scope| scope.forwarded.scheme)
+            .filter(|scheme| !scheme.is_empty())
+            .unwrap_or_else(|| "http".to_string())
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.scheme() == "https"
+    }
+
+    /// The host the client asked for, with any port.
+    pub fn host(&self) -> String {
+        current_request()
+            .map(|scope| scope.forwarded.host)
+            .unwrap_or_default()
+    }
+
+    /// `path` as an absolute URL on the requested scheme and host:
+    /// `req.absolute_url(&reverse("post", &[("slug", "hi")]).unwrap())`.
+    ///
+    /// `path` is returned as is when the host is unknown.
+    pub fn absolute_url(&self, path: &str) -> String {
+        let host = self.host();
+        if host.is_empty() {
+            return path.to_string();
+        }
+        format!("{}://{}{}", self.scheme(), host, path)
+    }
+}
@@ -0,0 +1,178 @@
+//! Cobalto in-memory Pub/Sub hub
+//!
+//! A concurrent-safe broadcast hub keyed by topic, meant for SSE and websocket
+//! handlers in single-process deployments. Every topic is backed by a
+//! `tokio::sync::broadcast` channel with its own capacity and backpressure policy.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+/// What happens when a topic buffer is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Overwrite the oldest buffered message; slow subscribers lag.
+    DropOldest,
+    /// Refuse the new message while the buffer is full.
+    DropNewest,
+}
+
+/// Per-topic channel configuration.
+#[derive(Clone, Copy, Debug)]
+pub struct TopicConfig {
+    pub capacity: usize,
+    pub backpressure: Backpressure,
+}
+
+impl Default for TopicConfig {
+    fn default() -> Self {
+        TopicConfig {
+            capacity: 128,
+            backpressure: Backpressure::DropOldest,
+        }
+    }
+}
+
+/// Counters kept for each topic.
+#[derive(Default, Debug)]
+pub struct TopicStats {
+    pub published: AtomicU64,
+    pub dropped: AtomicU64,
+    pub lagged: AtomicU64,
+}
+
+struct Topic {
+    sender: broadcast::Sender<String>,
+    config: TopicConfig,
+    stats: Arc<TopicStats>,
+}
+
+/// Broadcast hub shared between handlers (cheap to clone).
+#[derive(Clone, Default)]
+pub struct PubSub {
+    topics: Arc<RwLock<HashMap<String, Topic>>>,
+    defaults: TopicConfig,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hub whose implicitly created topics use `defaults`.
+    pub fn with_defaults(defaults: TopicConfig) -> Self {
+        PubSub {
+            topics: Arc::default(),
+            defaults,
+        }
+    }
+
+    /// Configure a topic explicitly. Existing subscribers of the topic are dropped.
+    pub fn configure(&self, topic: &str, config: TopicConfig) {
+        let (sender, _) = broadcast::channel(config.capacity.max(1));
+        self.topics.write().unwrap().insert(
+            topic.to_string(),
+            Topic {
+                sender,
+                config,
+                stats: Arc::default(),
+            },
+        );
+    }
+
+    fn ensure(&self, topic: &str) -> (broadcast::Sender<String>, TopicConfig, Arc<TopicStats>) {
+        if let Some(t) = self.topics.read().unwrap().get(topic) {
+            return (t.sender.clone(), t.config, t.stats.clone());
+        }
+        let mut topics = self.topics.write().unwrap();
+        let t = topics.entry(topic.to_string()).or_insert_with(|| Topic {
+            sender: broadcast::channel(self.defaults.capacity.max(1)).0,
+            config: self.defaults,
+            stats: Arc::default(),
+        });
+        (t.sender.clone(), t.config, t.stats.clone())
+    }
+
+    /// Publish a message. Returns `false` when the message was dropped.
+    pub fn publish<M: Into<String>>(&self, topic: &str, message: M) -> bool {
+        let (sender, config, stats) = self.ensure(topic);
+        if config.backpressure == Backpressure::DropNewest && sender.len() >= config.capacity {
+            stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        stats.published.fetch_add(1, Ordering::Relaxed);
+        // No subscribers is not an error for a fire-and-forget hub
+        let _ = sender.send(message.into());
+        true
+    }
+
+    /// Subscribe to a topic, creating it with the hub defaults if needed.
+    pub fn subscribe(&self, topic: &str) -> Subscription {
+        let (sender, _, stats) = self.ensure(topic);
+        Subscription {
+            receiver: sender.subscribe(),
+            stats,
+        }
+    }
+
+    /// Drop a topic; its subscribers see the end of the stream.
+    pub fn remove(&self, topic: &str) {
+        self.topics.write().unwrap().remove(topic);
+    }
+
+    /// Snapshot of (published, dropped, lagged) counters for a topic.
+    pub fn stats(&self, topic: &str) -> Option<(u64, u64, u64)> {
+        self.topics.read().unwrap().get(topic).map(|t| {
+            (
+                t.stats.published.load(Ordering::Relaxed),
+                t.stats.dropped.load(Ordering::Relaxed),
+                t.stats.lagged.load(Ordering::Relaxed),
+            )
+        })
+    }
+
+    /// Number of live subscribers on a topic.
+    pub fn subscriber_count(&self, topic: &str) -> usize {
+        self.topics
+            .read()
+            .unwrap()
+            .get(topic)
+            .map(|t| t.sender.receiver_count())
+            .unwrap_or(0)
+    }
+}
+
+/// A subscriber handle; lagged messages are counted and skipped transparently.
+pub struct Subscription {
+    receiver: broadcast::Receiver<String>,
+    stats: Arc<TopicStats>,
+}
+
+impl Subscription {
+    /// Wait for the next message. Returns `None` once the topic is closed.
+    pub async fn recv(&mut self) -> Option<String> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(msg) => return Some(msg),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    self.stats.lagged.fetch_add(n, Ordering::Relaxed);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Non-blocking receive.
+    pub fn try_recv(&mut self) -> Option<String> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(msg) => return Some(msg),
+                Err(broadcast::error::TryRecvError::Lagged(n)) => {
+                    self.stats.lagged.fetch_add(n, Ordering::Relaxed);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
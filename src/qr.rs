@@ -0,0 +1,36 @@
+//! Cobalto QR code template tag
+//!
+//! `{% qrcode url size=200 %}` renders the value of `url` (a context variable
+//! or a quoted literal) as an inline SVG QR code, `size` pixels wide.
+
+use crate::template::{TagArgs, TemplateValue};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use std::collections::HashMap;
+
+/// Default edge length of the generated SVG, in pixels.
+pub const DEFAULT_SIZE: u32 = 200;
+
+/// Encodes `data` as an SVG QR code of at least `size`×`size` pixels.
+pub fn qrcode_svg(data: &str, size: u32) -> Option<String> {
+    let code = QrCode::new(data.as_bytes()).ok()?;
+    Some(
+        code.render::<svg::Color>()
+            .min_dimensions(size, size)
+            .build(),
+    )
+}
+
+/// Renderer behind the built-in `qrcode` tag.
+pub fn qrcode_tag(args: &TagArgs, context: &HashMap<String, TemplateValue>) -> String {
+    let data = match args.positional.first() {
+        Some(raw) => TagArgs::resolve(raw, context),
+        None => return String::new(),
+    };
+    let size = args
+        .named
+        .get("size")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_SIZE);
+    qrcode_svg(&data, size).unwrap_or_default()
+}
@@ -0,0 +1,379 @@
+//! Cobalto tenant quotas
+//!
+//! Soft per-tenant resource limits: requests per day, storage bytes and rows
+//! per model. The tenant is resolved from the request (subdomain, API key,
+//! ...) by a closure and requests are counted by `Quotas::wrap`.
+//!
+//! Row and storage counters are seeded from the database at startup
+//! (`seed_rows`, `seed_storage`) and then follow the model signals: once
+//! `track_rows` / `track_storage` is called, every `save` inserting a row and
+//! every `delete` adjusts them. Code about to insert checks the limit with
+//! `check_rows` / `check_storage`; bytes stored outside the models (uploaded
+//! files) are counted with `add_storage`. `usage` exposes the counters for
+//! billing pages.
+//!
+//! ```ignore
+//! quotas.seed_rows::<Note>(&db, "tenant").await?;
+//! quotas.track_rows::<Note, _>(|note| Some(note.tenant.clone()));
+//! ```
+
+use crate::clock;
+use crate::orm::{Backend, Db, Model};
+use crate::router::{Handler, Request, Response};
+use crate::signals::{self, Signal};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Limits of one tenant; `None` means unlimited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuotaLimits {
+    pub requests_per_day: Option<u64>,
+    pub storage_bytes: Option<u64>,
+    pub rows_per_model: HashMap<String, u64>,
+}
+
+impl QuotaLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder for the daily request limit
+    pub fn requests_per_day(mut self, limit: u64) -> Self {
+        self.requests_per_day = Some(limit);
+        self
+    }
+
+    /// Builder for the storage limit
+    pub fn storage_bytes(mut self, limit: u64) -> Self {
+        self.storage_bytes = Some(limit);
+        self
+    }
+
+    /// Builder for the row limit of model `M`
+    pub fn rows<M: Model>(mut self, limit: u64) -> Self {
+        self.rows_per_model
+            .insert(M::table_name().to_string(), limit);
+        self
+    }
+}
+
+/// Usage counters of one tenant.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub day: Option<NaiveDate>,
+    pub requests_today: u64,
+    pub storage_bytes: u64,
+    pub rows: HashMap<String, u64>,
+}
+
+/// Returned when an operation would exceed a tenant's quota.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    pub tenant: String,
+    pub resource: String,
+    pub limit: u64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tenant '{}' exceeded its {} quota ({})",
+            self.tenant, self.resource, self.limit
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// Resolves the tenant of a request.
+pub type TenantResolver = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// Quota registry shared by middleware and data-writing code (cheap to clone).
+#[derive(Clone)]
+pub struct Quotas {
+    resolve: TenantResolver,
+    defaults: QuotaLimits,
+    limits: Arc<Mutex<HashMap<String, QuotaLimits>>>,
+    usage: Arc<Mutex<HashMap<String, Usage>>>,
+}
+
+impl Quotas {
+    pub fn new<F>(resolve: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        Quotas {
+            resolve: Arc::new(resolve),
+            defaults: QuotaLimits::default(),
+            limits: Arc::default(),
+            usage: Arc::default(),
+        }
+    }
+
+    /// Builder for the limits of tenants without an explicit plan
+    pub fn with_defaults(mut self, limits: QuotaLimits) -> Self {
+        self.defaults = limits;
+        self
+    }
+
+    /// Set the limits of a tenant (e.g. from its billing plan).
+    pub fn set_limits(&self, tenant: &str, limits: QuotaLimits) {
+        self.limits
+            .lock()
+            .unwrap()
+            .insert(tenant.to_string(), limits);
+    }
+
+    /// Effective limits of a tenant.
+    pub fn limits(&self, tenant: &str) -> QuotaLimits {
+        self.limits
+            .lock()
+            .unwrap()
+            .get(tenant)
+            .cloned()
+            .unwrap_or_else(|| self.defaults.clone())
+    }
+
+    /// Current usage of a tenant.
+    pub fn usage(&self, tenant: &str) -> Usage {
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(tenant.to_string()).or_default();
+        roll_day(entry);
+        entry.clone()
+    }
+
+    fn exceeded(tenant: &str, resource: &str, limit: u64) -> QuotaExceeded {
+        QuotaExceeded {
+            tenant: tenant.to_string(),
+            resource: resource.to_string(),
+            limit,
+        }
+    }
+
+    /// Count one request for `tenant`.
+    pub fn hit_request(&self, tenant: &str) -> Result<(), QuotaExceeded> {
+        let limit = self.limits(tenant).requests_per_day;
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(tenant.to_string()).or_default();
+        roll_day(entry);
+        if let Some(limit) = limit
+            && entry.requests_today >= limit
+        {
+            return Err(Self::exceeded(tenant, "requests_per_day", limit));
+        }
+        entry.requests_today += 1;
+        Ok(())
+    }
+
+    /// Adjust stored bytes; growth past the limit is refused, shrinking never is.
+    pub fn add_storage(&self, tenant: &str, delta: i64) -> Result<(), QuotaExceeded> {
+        let limit = self.limits(tenant).storage_bytes;
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(tenant.to_string()).or_default();
+        let new_total = entry.storage_bytes.saturating_add_signed(delta);
+        if let Some(limit) = limit
+            && delta > 0
+            && new_total > limit
+        {
+            return Err(Self::exceeded(tenant, "storage_bytes", limit));
+        }
+        entry.storage_bytes = new_total;
+        Ok(())
+    }
+
+    /// Check that `tenant` may store `bytes` more.
+    pub fn check_storage(&self, tenant: &str, bytes: u64) -> Result<(), QuotaExceeded> {
+        let Some(limit) = self.limits(tenant).storage_bytes else {
+            return Ok(());
+        };
+        if self.usage(tenant).storage_bytes.saturating_add(bytes) > limit {
+            return Err(Self::exceeded(tenant, "storage_bytes", limit));
+        }
+        Ok(())
+    }
+
+    /// Check that `tenant` may insert `rows` more rows of model `M`.
+    pub fn check_rows<M: Model>(&self, tenant: &str, rows: u64) -> Result<(), QuotaExceeded> {
+        let table = M::table_name();
+        let Some(limit) = self.limits(tenant).rows_per_model.get(table).copied() else {
+            return Ok(());
+        };
+        let count = self.usage(tenant).rows.get(table).copied().unwrap_or(0);
+        if count.saturating_add(rows) > limit {
+            return Err(Self::exceeded(tenant, &format!("rows:{}", table), limit));
+        }
+        Ok(())
+    }
+
+    fn adjust_rows(&self, tenant: &str, table: &str, delta: i64) {
+        let mut usage = self.usage.lock().unwrap();
+        let count = usage
+            .entry(tenant.to_string())
+            .or_default()
+            .rows
+            .entry(table.to_string())
+            .or_default();
+        *count = count.saturating_add_signed(delta);
+    }
+
+    fn adjust_storage(&self, tenant: &str, delta: i64) {
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(tenant.to_string()).or_default();
+        entry.storage_bytes = entry.storage_bytes.saturating_add_signed(delta);
+    }
+
+    /// Count the live rows of model `M` per tenant (`SELECT tenant_column,
+    /// COUNT(*) ... GROUP BY tenant_column`), replacing the row counters of
+    /// `M`. Run at startup, before `track_rows`.
+    pub async fn seed_rows<M: Model>(
+        &self,
+        db: &Db,
+        tenant_column: &str,
+    ) -> Result<(), sqlx::Error> {
+        let totals = grouped_totals::<M>(db, tenant_column, "COUNT(*)").await?;
+        let table = M::table_name();
+        let mut usage = self.usage.lock().unwrap();
+        for entry in usage.values_mut() {
+            entry.rows.remove(table);
+        }
+        for (tenant, count) in totals {
+            usage
+                .entry(tenant)
+                .or_default()
+                .rows
+                .insert(table.to_string(), count.max(0) as u64);
+        }
+        Ok(())
+    }
+
+    /// Add the bytes stored in `size_column` of model `M` to each tenant's
+    /// storage counter. Run once per model at startup, before `track_storage`.
+    pub async fn seed_storage<M: Model>(
+        &self,
+        db: &Db,
+        tenant_column: &str,
+        size_column: &str,
+    ) -> Result<(), sqlx::Error> {
+        let sum = format!("COALESCE(SUM({}), 0)", size_column);
+        let totals = grouped_totals::<M>(db, tenant_column, &sum).await?;
+        for (tenant, bytes) in totals {
+            self.adjust_storage(&tenant, bytes);
+        }
+        Ok(())
+    }
+
+    /// Keep the row counters of model `M` current: `PostSave` of a new row
+    /// adds one to its tenant, `PostDelete` removes one. Rows for which
+    /// `tenant_of` returns `None` are not counted.
+    pub fn track_rows<M, F>(&self, tenant_of: F)
+    where
+        M: Model,
+        F: Fn(&M) -> Option<String> + Send + Sync + 'static,
+    {
+        let tenant_of = Arc::new(tenant_of);
+        let (quotas, tenant) = (self.clone(), tenant_of.clone());
+        signals::connect::<M, _>(Signal::PostSave, move |row, event| {
+            if event.created
+                && let Some(tenant) = tenant(row)
+            {
+                quotas.adjust_rows(&tenant, M::table_name(), 1);
+            }
+        });
+        let quotas = self.clone();
+        signals::connect::<M, _>(Signal::PostDelete, move |row, _| {
+            if let Some(tenant) = tenant_of(row) {
+                quotas.adjust_rows(&tenant, M::table_name(), -1);
+            }
+        });
+    }
+
+    /// Keep the storage counters current for model `M`, whose rows weigh
+    /// `size_of` bytes: added when a row is inserted, released when it is
+    /// deleted. Size changes of updated rows go through `add_storage`.
+    pub fn track_storage<M, F, S>(&self, tenant_of: F, size_of: S)
+    where
+        M: Model,
+        F: Fn(&M) -> Option<String> + Send + Sync + 'static,
+        S: Fn(&M) -> u64 + Send + Sync + 'static,
+    {
+        let tracked = Arc::new((tenant_of, size_of));
+        let (quotas, insert) = (self.clone(), tracked.clone());
+        signals::connect::<M, _>(Signal::PostSave, move |row, event| {
+            let (tenant_of, size_of) = &*insert;
+            if event.created
+                && let Some(tenant) = tenant_of(row)
+            {
+                quotas.adjust_storage(&tenant, size_of(row) as i64);
+            }
+        });
+        let quotas = self.clone();
+        signals::connect::<M, _>(Signal::PostDelete, move |row, _| {
+            let (tenant_of, size_of) = &*tracked;
+            if let Some(tenant) = tenant_of(row) {
+                quotas.adjust_storage(&tenant, -(size_of(row) as i64));
+            }
+        });
+    }
+
+    /// Wrap a handler so that each request counts against its tenant's daily quota.
+    ///
+    /// Requests without a resolvable tenant pass through uncounted.
+    pub fn wrap(&self, handler: Handler) -> Handler {
+        let quotas = self.clone();
+        Arc::new(move |req| {
+            let quotas = quotas.clone();
+            let handler = handler.clone();
+            Box::pin(async move {
+                let Some(tenant) = (quotas.resolve)(&req) else {
+                    return handler(req).await;
+                };
+                match quotas.hit_request(&tenant) {
+                    Ok(()) => handler(req).await,
+                    Err(e) => {
+                        Response::json(serde_json::json!({"error": e.to_string()})).with_status(429)
+                    }
+                }
+            })
+        })
+    }
+}
+
+/// Reset the daily request counter when the day changes.
+fn roll_day(usage: &mut Usage) {
+    let today = clock::now().date_naive();
+    if usage.day != Some(today) {
+        usage.day = Some(today);
+        usage.requests_today = 0;
+    }
+}
+
+/// `SELECT tenant_column, aggregate FROM M GROUP BY tenant_column` over the
+/// live rows, tenants as text.
+async fn grouped_totals<M: Model>(
+    db: &Db,
+    tenant_column: &str,
+    aggregate: &str,
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let (text, integer) = match db.backend() {
+        Backend::MySql => ("CHAR", "SIGNED"),
+        Backend::Sqlite | Backend::Postgres => ("TEXT", "BIGINT"),
+    };
+    let live = if M::soft_delete() {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    };
+    let sql = format!(
+        "SELECT CAST({col} AS {text}), CAST({aggregate} AS {integer}) FROM {table} \
+         WHERE {col} IS NOT NULL{live} GROUP BY {col}",
+        col = tenant_column,
+        table = M::table_name(),
+    );
+    sqlx::query_as::<_, (String, i64)>(&sql)
+        .fetch_all(&db.pool)
+        .await
+}
@@ -0,0 +1,151 @@
+//! Cobalto development reloading
+//!
+//! With `settings.debug` on, `Router::run()` starts a `Watcher` over the
+//! template, static and source directories. A template or static change drops
+//! the parsed-template cache so the next render picks it up; a source change is
+//! reported, since the binary has to be rebuilt (run under `cargo watch -x run`
+//! to restart automatically). Either way the reload version is bumped, and the
+//! script injected into HTML responses polls `RELOAD_PATH` and refreshes the
+//! page when the version moves.
+
+use crate::router::Response;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Polled by the live-reload script; answers with the current version.
+pub const RELOAD_PATH: &str = "/__cobalto/reload";
+
+static VERSION: AtomicU64 = AtomicU64::new(0);
+
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>(function(){var v=null;setInterval(function(){fetch("/__cobalto/reload").then(function(r){return r.text()}).then(function(t){if(v!==null&&t!==v){location.reload()}v=t}).catch(function(){})},1000)})();</script>"#;
+
+/// Changes since the last reload; bumped by the watcher.
+pub fn version() -> u64 {
+    VERSION.load(Ordering::Relaxed)
+}
+
+/// Insert the live-reload script before `</body>` of an HTML response.
+pub fn inject_script(mut response: Response) -> Response {
+    let is_html = response
+        .headers
+        .iter()
+        .any(|(k, v)| k.eq_ignore_ascii_case("content-type") && v.starts_with("text/html"));
+    if !is_html || response.binary.is_some() || response.stream.is_some() {
+        return response;
+    }
+    if let Some(at) = response.body.to_ascii_lowercase().rfind("</body>") {
+        response.body.insert_str(at, LIVE_RELOAD_SCRIPT);
+    }
+    response
+}
+
+/// What kind of files changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Templates or static assets: picked up on the next render.
+    Assets,
+    /// Rust sources: needs a rebuild and restart.
+    Source,
+}
+
+/// Polls directories for modified, added or removed files.
+pub struct Watcher {
+    asset_dirs: Vec<PathBuf>,
+    source_dirs: Vec<PathBuf>,
+    interval: Duration,
+    assets: u64,
+    sources: u64,
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        Watcher {
+            asset_dirs: Vec::new(),
+            source_dirs: Vec::new(),
+            interval: Duration::from_millis(500),
+            assets: fingerprint(&[]),
+            sources: fingerprint(&[]),
+        }
+    }
+
+    /// Builder adding a template or static directory
+    pub fn assets<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.asset_dirs.push(dir.into());
+        self.assets = fingerprint(&self.asset_dirs);
+        self
+    }
+
+    /// Builder adding a source directory
+    pub fn source<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.source_dirs.push(dir.into());
+        self.sources = fingerprint(&self.source_dirs);
+        self
+    }
+
+    /// Builder for the polling interval
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Look for changes since the last check, acting on them.
+    pub fn check(&mut self) -> Option<Change> {
+        let sources = fingerprint(&self.source_dirs);
+        let assets = fingerprint(&self.asset_dirs);
+        let change = if sources != self.sources {
+            Some(Change::Source)
+        } else if assets != self.assets {
+            Some(Change::Assets)
+        } else {
+            None
+        };
+        self.sources = sources;
+        self.assets = assets;
+        match change {
+            Some(Change::Assets) => crate::template::clear_template_cache(),
+            Some(Change::Source) => {
+                println!("Source files changed: rebuild and restart to apply them")
+            }
+            None => return None,
+        }
+        VERSION.fetch_add(1, Ordering::Relaxed);
+        change
+    }
+
+    /// Keep checking in the background until the runtime shuts down.
+    pub fn spawn(mut self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                self.check();
+            }
+        })
+    }
+}
+
+/// Hash of every file's path, size and modification time under `dirs`.
+fn fingerprint(dirs: &[PathBuf]) -> u64 {
+    let mut entries: Vec<(PathBuf, u64, Option<std::time::SystemTime>)> = dirs
+        .iter()
+        .flat_map(|dir| walkdir::WalkDir::new(dir).into_iter().flatten())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            Some((entry.into_path(), meta.len(), meta.modified().ok()))
+        })
+        .collect();
+    entries.sort();
+    let mut hasher = DefaultHasher::new();
+    entries.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,280 @@
+//! Cobalto HTML rewriting (feature `html-rewrite`)
+//!
+//! A post-processing stage over HTML responses built on `lol_html`: element
+//! handlers are registered by CSS selector and applied to the page as it goes
+//! out (streamed bodies chunk by chunk), so cross-cutting changes (analytics
+//! snippets, CDN image URLs) need no template edits. Non-HTML and binary
+//! responses pass through.
+//!
+//! With `csp_nonces`, every request gets a fresh nonce: it is stored as a
+//! `CspNonce` request extension, exposed to templates as `{{ csp_nonce }}`,
+//! sent in the `Content-Security-Policy` header and added to the scripts the
+//! rewriter injects itself. Scripts of the page only run if the template
+//! marks them: `<script nonce="{{ csp_nonce }}">`.
+
+use crate::router::{BodyStream, Middleware, PostMiddleware, RequestContext, Response, Router};
+use actix_web::web::Bytes;
+use futures::StreamExt;
+use lol_html::html_content::{ContentType, Element};
+use lol_html::{
+    HtmlRewriter as Rewriter, OutputSink, RewriteStrSettings, Selector, Settings, element,
+    rewrite_str,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Once};
+
+/// Callback run on every element matching a selector.
+pub type ElementHandler = Arc<dyn Fn(&mut Element<'_, '_>) + Send + Sync>;
+
+/// Policy sent by `csp_nonces` unless another is given; `{nonce}` is
+/// replaced by the request's nonce.
+pub const DEFAULT_CSP: &str =
+    "script-src 'nonce-{nonce}' 'strict-dynamic'; object-src 'none'; base-uri 'none'";
+
+/// Chunks buffered on each side of a streamed rewrite.
+const STREAM_BUFFER_CHUNKS: usize = 4;
+
+/// The CSP nonce of the current request: `req.extension::<CspNonce>()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CspNonce(pub String);
+
+impl CspNonce {
+    /// A fresh random nonce (128 bits, hex).
+    pub fn generate() -> Self {
+        CspNonce(format!("{:032x}", rand::random::<u128>()))
+    }
+}
+
+/// A set of element handlers applied to HTML responses.
+#[derive(Clone, Default)]
+pub struct HtmlRewriter {
+    handlers: Vec<(String, ElementHandler)>,
+    /// Appended to `<body>`; their scripts get the request nonce
+    body_snippets: Vec<String>,
+    /// Policy sent with nonces, when enabled
+    csp: Option<String>,
+}
+
+impl HtmlRewriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for elements matching `selector`.
+    ///
+    /// Fails if the selector is not supported by `lol_html`.
+    pub fn on<F>(mut self, selector: &str, f: F) -> Result<Self, lol_html::errors::SelectorError>
+    where
+        F: Fn(&mut Element<'_, '_>) + Send + Sync + 'static,
+    {
+        selector.parse::<Selector>()?;
+        self.handlers.push((selector.to_string(), Arc::new(f)));
+        Ok(self)
+    }
+
+    /// Append an HTML snippet (e.g. analytics) at the end of `<body>`. Its
+    /// scripts are trusted: they get the request's CSP nonce.
+    pub fn append_to_body(mut self, snippet: &str) -> Self {
+        self.body_snippets.push(snippet.to_string());
+        self
+    }
+
+    /// Generate a nonce per request and send `policy` (see `DEFAULT_CSP`) as
+    /// the `Content-Security-Policy` of HTML responses.
+    pub fn csp_nonces(mut self, policy: &str) -> Self {
+        self.csp = Some(policy.to_string());
+        self
+    }
+
+    /// Prefix root-relative image URLs with a CDN origin.
+    pub fn cdn_images(self, origin: &str) -> Self {
+        let origin = origin.trim_end_matches('/').to_string();
+        self.on("img[src]", move |el| {
+            if let Some(src) = el.get_attribute("src")
+                && src.starts_with('/')
+                && !src.starts_with("//")
+            {
+                let _ = el.set_attribute("src", &format!("{}{}", origin, src));
+            }
+        })
+        .expect("valid selector")
+    }
+
+    /// A streaming rewriter writing to `output`.
+    fn rewriter<'h, O: OutputSink>(&'h self, nonce: Option<&'h str>, output: O) -> Rewriter<'h, O> {
+        let mut handlers: Vec<_> = self
+            .handlers
+            .iter()
+            .map(|(selector, f)| {
+                element!(selector.as_str(), move |el| {
+                    f(el);
+                    Ok(())
+                })
+            })
+            .collect();
+        if !self.body_snippets.is_empty() {
+            handlers.push(element!("body", move |el| {
+                for snippet in &self.body_snippets {
+                    el.append(&trust_scripts(snippet, nonce), ContentType::Html);
+                }
+                Ok(())
+            }));
+        }
+        Rewriter::new(
+            Settings {
+                element_content_handlers: handlers,
+                ..Settings::new()
+            },
+            output,
+        )
+    }
+
+    /// Rewrite an HTML document, giving injected scripts `nonce`; returns it
+    /// unchanged if rewriting fails.
+    pub fn process(&self, html: &str, nonce: Option<&str>) -> String {
+        let mut out = Vec::with_capacity(html.len());
+        let mut rewriter = self.rewriter(nonce, |chunk: &[u8]| out.extend_from_slice(chunk));
+        let result = rewriter
+            .write(html.as_bytes())
+            .and_then(|()| rewriter.end());
+        if result.is_err() {
+            return html.to_string();
+        }
+        String::from_utf8(out).unwrap_or_else(|_| html.to_string())
+    }
+
+    /// Rewrite a streamed body as its chunks arrive. `lol_html` keeps its
+    /// state on a blocking thread, fed and drained through bounded channels.
+    fn process_stream(&self, body: BodyStream, nonce: Option<String>) -> BodyStream {
+        let Some(mut source) = body.take() else {
+            return body;
+        };
+        let (input_tx, mut input_rx) = tokio::sync::mpsc::channel(STREAM_BUFFER_CHUNKS);
+        let (output_tx, output_rx) = tokio::sync::mpsc::channel(STREAM_BUFFER_CHUNKS);
+        tokio::spawn(async move {
+            while let Some(chunk) = source.next().await {
+                if input_tx.send(chunk).await.is_err() {
+                    return;
+                }
+            }
+        });
+        let rewriter = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let buffer = Rc::new(RefCell::new(Vec::new()));
+            let sink = buffer.clone();
+            let mut html = rewriter.rewriter(nonce.as_deref(), move |chunk: &[u8]| {
+                sink.borrow_mut().extend_from_slice(chunk)
+            });
+            // Send what was rewritten so far; `false` once the client is gone
+            let flush = || {
+                let chunk = std::mem::take(&mut *buffer.borrow_mut());
+                chunk.is_empty() || output_tx.blocking_send(Ok(Bytes::from(chunk))).is_ok()
+            };
+            while let Some(chunk) = input_rx.blocking_recv() {
+                let written = chunk.and_then(|chunk: Bytes| {
+                    html.write(&chunk)
+                        .map_err(|e| std::io::Error::other(e.to_string()))
+                });
+                if let Err(e) = written {
+                    let _ = output_tx.blocking_send(Err(e));
+                    return;
+                }
+                if !flush() {
+                    return;
+                }
+            }
+            if let Err(e) = html.end() {
+                let _ = output_tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+                return;
+            }
+            flush();
+        });
+        let chunks = futures::stream::unfold(output_rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        });
+        BodyStream::new(chunks)
+    }
+
+    /// Rewrite `resp` if it is HTML, with `nonce` for injected scripts and
+    /// the CSP header; other responses are returned as-is.
+    pub fn rewrite(&self, mut resp: Response, nonce: Option<String>) -> Response {
+        let is_html = resp
+            .headers
+            .iter()
+            .any(|(k, v)| k.eq_ignore_ascii_case("content-type") && v.contains("text/html"));
+        if !is_html || resp.binary.is_some() {
+            return resp;
+        }
+        if let (Some(policy), Some(nonce)) = (&self.csp, &nonce) {
+            resp.headers.insert(
+                "Content-Security-Policy".to_string(),
+                policy.replace("{nonce}", nonce),
+            );
+        }
+        match resp.stream.take() {
+            Some(stream) => resp.stream = Some(self.process_stream(stream, nonce)),
+            None => resp.body = self.process(&resp.body, nonce.as_deref()),
+        }
+        resp
+    }
+
+    /// Middleware giving each request its `CspNonce` extension (when
+    /// `csp_nonces` is on).
+    pub fn middleware(&self) -> Middleware {
+        let nonces = self.csp.is_some();
+        Arc::new(move |ctx: &mut RequestContext| {
+            if nonces {
+                ctx.extensions.insert(CspNonce::generate());
+            }
+            None
+        })
+    }
+
+    /// Post-middleware rewriting the HTML responses.
+    pub fn post_middleware(&self) -> PostMiddleware {
+        let rewriter = self.clone();
+        Arc::new(move |ctx: &RequestContext, resp: Response| {
+            let nonce = ctx.extensions.get::<CspNonce>().map(|n| n.0);
+            rewriter.rewrite(resp, nonce)
+        })
+    }
+
+    /// Rewrite every response of `router` and expose `{{ csp_nonce }}` to
+    /// templates.
+    pub fn install(&self, router: &mut Router) {
+        static CONTEXT_PROCESSOR: Once = Once::new();
+        CONTEXT_PROCESSOR.call_once(|| {
+            crate::template::register_context_processor(|context| {
+                let nonce = crate::router::current_request()
+                    .and_then(|scope| scope.extensions.get::<CspNonce>());
+                if let Some(CspNonce(nonce)) = nonce {
+                    context.insert(
+                        "csp_nonce".to_string(),
+                        crate::template::TemplateValue::String(nonce),
+                    );
+                }
+            });
+        });
+        router.add_middleware(self.middleware());
+        router.add_post_middleware(self.post_middleware());
+    }
+}
+
+/// `snippet` with `nonce` set on its scripts.
+fn trust_scripts(snippet: &str, nonce: Option<&str>) -> String {
+    let Some(nonce) = nonce else {
+        return snippet.to_string();
+    };
+    rewrite_str(
+        snippet,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("script", |el| {
+                el.set_attribute("nonce", nonce)?;
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    )
+    .unwrap_or_else(|_| snippet.to_string())
+}
@@ -0,0 +1,359 @@
+//! Cobalto route matching
+//!
+//! Route patterns are compiled into a tree of path segments when the route
+//! table is built, so a lookup walks the request path once instead of testing
+//! every route. At each segment literals are tried first, then typed
+//! (`:id<i64>`, `:id|sqid`) parameters, then plain `:name` parameters and
+//! finally a `*name` catch-all, backtracking when a branch dead-ends.
+//!
+//! Two routes conflict when they share a method and their patterns have the
+//! same shape, e.g. `GET /users/:id` and `GET /users/:pk`: only the first could
+//! ever match, so `insert` refuses the second.
+
+use crate::router::{percent_decode_path, segment_matches_type};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Why a route could not be added to a `RouteTree`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouteError {
+    /// Same method and pattern shape as a route added before.
+    Conflict {
+        method: String,
+        pattern: String,
+        existing: String,
+    },
+    /// The pattern itself is malformed.
+    Invalid {
+        pattern: String,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::Conflict {
+                method,
+                pattern,
+                existing,
+            } => write!(
+                f,
+                "route {} {} conflicts with {} {}",
+                method, pattern, method, existing
+            ),
+            RouteError::Invalid { pattern, reason } => {
+                write!(f, "invalid route pattern '{}': {}", pattern, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+/// Routes compiled for lookup by method and path.
+pub struct RouteTree<T> {
+    root: Node<T>,
+}
+
+impl<T> Default for RouteTree<T> {
+    fn default() -> Self {
+        RouteTree { root: Node::new() }
+    }
+}
+
+struct Node<T> {
+    literals: HashMap<String, Node<T>>,
+    /// Typed parameters come before untyped ones
+    params: Vec<(ParamKind, Node<T>)>,
+    /// Routes ending at this node
+    endpoints: Vec<Endpoint<T>>,
+    /// Routes ending in a `*name` segment after this node
+    catch_all: Vec<Endpoint<T>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ParamKind {
+    ty: Option<String>,
+    sqid: bool,
+}
+
+impl ParamKind {
+    fn is_typed(&self) -> bool {
+        self.ty.is_some() || self.sqid
+    }
+
+    /// The parameter value for a path segment, percent-decoded, if the
+    /// segment fits.
+    fn capture(&self, segment: &str) -> Option<String> {
+        let segment = percent_decode_path(segment);
+        let segment = segment.as_str();
+        if let Some(ty) = &self.ty
+            && !segment_matches_type(ty, segment)
+        {
+            return None;
+        }
+        if self.sqid {
+            return crate::obfuscate::decode_id(segment).map(|id| id.to_string());
+        }
+        Some(segment.to_string())
+    }
+}
+
+struct Endpoint<T> {
+    method: String,
+    pattern: String,
+    /// Parameter names in path order, the catch-all last
+    names: Vec<String>,
+    value: T,
+}
+
+enum Segment<'a> {
+    Literal(&'a str),
+    Param {
+        name: &'a str,
+        kind: ParamKind,
+        optional: bool,
+    },
+    CatchAll(&'a str),
+}
+
+fn parse_segment(segment: &str) -> Segment<'_> {
+    if let Some(name) = segment.strip_prefix('*') {
+        return Segment::CatchAll(name);
+    }
+    let Some(spec) = segment.strip_prefix(':') else {
+        return Segment::Literal(segment);
+    };
+    let optional = spec.ends_with('?');
+    let spec = spec.trim_end_matches('?');
+    let (spec, ty) = match spec.split_once('<') {
+        Some((name, ty)) => (name, ty.strip_suffix('>')),
+        None => (spec, None),
+    };
+    let (name, constraint) = spec.split_once('|').unwrap_or((spec, ""));
+    Segment::Param {
+        name,
+        kind: ParamKind {
+            ty: ty.map(str::to_string),
+            sqid: constraint == "sqid",
+        },
+        optional,
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    match path.trim_matches('/') {
+        "" => Vec::new(),
+        trimmed => trimmed.split('/').collect(),
+    }
+}
+
+impl<T> Node<T> {
+    fn new() -> Self {
+        Node {
+            literals: HashMap::new(),
+            params: Vec::new(),
+            endpoints: Vec::new(),
+            catch_all: Vec::new(),
+        }
+    }
+
+    /// The node reached by `segments`, created as needed.
+    fn descend(&mut self, segments: &[Segment]) -> &mut Node<T> {
+        let Some((first, rest)) = segments.split_first() else {
+            return self;
+        };
+        let child = match first {
+            Segment::Literal(literal) => self
+                .literals
+                .entry(literal.to_string())
+                .or_insert_with(Node::new),
+            Segment::Param { kind, .. } => {
+                let index = match self.params.iter().position(|(k, _)| k == kind) {
+                    Some(index) => index,
+                    None => {
+                        let index = if kind.is_typed() {
+                            self.params.iter().take_while(|(k, _)| k.is_typed()).count()
+                        } else {
+                            self.params.len()
+                        };
+                        self.params.insert(index, (kind.clone(), Node::new()));
+                        index
+                    }
+                };
+                &mut self.params[index].1
+            }
+            Segment::CatchAll(_) => unreachable!("catch-all segments end the pattern"),
+        };
+        child.descend(rest)
+    }
+
+    /// Visit the endpoints matching `segments`, most specific first, until
+    /// `visit` returns true.
+    fn walk<'a>(
+        &'a self,
+        segments: &[&str],
+        captured: &mut Vec<String>,
+        visit: &mut dyn FnMut(&'a Endpoint<T>, &[String]) -> bool,
+    ) -> bool {
+        match segments.split_first() {
+            Some((first, rest)) => {
+                if let Some(child) = self.literals.get(*first)
+                    && child.walk(rest, captured, visit)
+                {
+                    return true;
+                }
+                for (kind, child) in &self.params {
+                    let Some(value) = kind.capture(first) else {
+                        continue;
+                    };
+                    captured.push(value);
+                    let stop = child.walk(rest, captured, visit);
+                    captured.pop();
+                    if stop {
+                        return true;
+                    }
+                }
+            }
+            None => {
+                if self.endpoints.iter().any(|e| visit(e, captured)) {
+                    return true;
+                }
+            }
+        }
+        if self.catch_all.is_empty() {
+            return false;
+        }
+        let rest: Vec<String> = segments.iter().map(|s| percent_decode_path(s)).collect();
+        captured.push(rest.join("/"));
+        let stop = self.catch_all.iter().any(|e| visit(e, captured));
+        captured.pop();
+        stop
+    }
+}
+
+impl<T> Endpoint<T> {
+    fn params(&self, values: &[String]) -> HashMap<String, String> {
+        self.names
+            .iter()
+            .cloned()
+            .zip(values.iter().cloned())
+            .collect()
+    }
+}
+
+impl<T: Clone> RouteTree<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a route. A pattern ending in `:name?` segments is added once per
+    /// number of optional segments present.
+    pub fn insert(&mut self, method: &str, pattern: &str, value: T) -> Result<(), RouteError> {
+        let invalid = |reason| RouteError::Invalid {
+            pattern: pattern.to_string(),
+            reason,
+        };
+        let segments: Vec<Segment> = split_path(pattern).into_iter().map(parse_segment).collect();
+        let catch_all = match segments.last() {
+            Some(Segment::CatchAll(name)) => Some(name.to_string()),
+            _ => None,
+        };
+        let fixed = &segments[..segments.len() - catch_all.is_some() as usize];
+        if fixed.iter().any(|s| matches!(s, Segment::CatchAll(_))) {
+            return Err(invalid("a *catch-all must be the last segment"));
+        }
+        let required = fixed
+            .iter()
+            .position(|s| matches!(s, Segment::Param { optional: true, .. }))
+            .unwrap_or(fixed.len());
+        if fixed[required..]
+            .iter()
+            .any(|s| !matches!(s, Segment::Param { optional: true, .. }))
+        {
+            return Err(invalid("only trailing segments can be optional"));
+        }
+        if catch_all.is_some() && required < fixed.len() {
+            return Err(invalid("a *catch-all cannot follow optional segments"));
+        }
+
+        let names: Vec<String> = fixed
+            .iter()
+            .filter_map(|s| match s {
+                Segment::Param { name, .. } => Some(name.to_string()),
+                _ => None,
+            })
+            .collect();
+        let literal_count = |len: usize| {
+            fixed[..len]
+                .iter()
+                .filter(|s| matches!(s, Segment::Literal(_)))
+                .count()
+        };
+        // Check every variant before adding any, so a conflict adds nothing
+        for pass in 0..2 {
+            for len in required..=fixed.len() {
+                let node = self.root.descend(&fixed[..len]);
+                let endpoints = match catch_all {
+                    Some(_) => &mut node.catch_all,
+                    None => &mut node.endpoints,
+                };
+                if pass == 0 {
+                    if let Some(existing) = endpoints
+                        .iter()
+                        .find(|e| e.method.eq_ignore_ascii_case(method))
+                    {
+                        return Err(RouteError::Conflict {
+                            method: method.to_ascii_uppercase(),
+                            pattern: pattern.to_string(),
+                            existing: existing.pattern.clone(),
+                        });
+                    }
+                    continue;
+                }
+                let mut names = names[..len - literal_count(len)].to_vec();
+                names.extend(catch_all.clone());
+                endpoints.push(Endpoint {
+                    method: method.to_ascii_uppercase(),
+                    pattern: pattern.to_string(),
+                    names,
+                    value: value.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> RouteTree<T> {
+    /// The route for `method` and `path`, with its path parameters.
+    pub fn find(&self, method: &str, path: &str) -> Option<(&T, HashMap<String, String>)> {
+        let mut found = None;
+        self.root.walk(
+            &split_path(path),
+            &mut Vec::new(),
+            &mut |endpoint, values| {
+                if !endpoint.method.eq_ignore_ascii_case(method) {
+                    return false;
+                }
+                found = Some((&endpoint.value, endpoint.params(values)));
+                true
+            },
+        );
+        found
+    }
+
+    /// Methods of every route matching `path`, for 405-style answers.
+    pub fn allowed_methods(&self, path: &str) -> Vec<String> {
+        let mut methods: Vec<String> = Vec::new();
+        self.root
+            .walk(&split_path(path), &mut Vec::new(), &mut |endpoint, _| {
+                if !methods.contains(&endpoint.method) {
+                    methods.push(endpoint.method.clone());
+                }
+                false
+            });
+        methods
+    }
+}
@@ -1,36 +1,166 @@
+use crate::error_pages::{ErrorInfo, ErrorPages};
+use crate::route_tree::RouteTree;
+use crate::session::Sessions;
 use crate::settings::Settings;
+use crate::state::AppState;
+use crate::template::{TagArgs, TemplateValue, escape_html};
+use crate::websocket::{WebSocket, WsContext, WsHandler, WsRoute};
+use actix_web::web::Bytes;
 use actix_web::{HttpRequest, HttpResponse, Responder, body::BoxBody};
+use futures::{Stream, StreamExt};
 use serde::Serialize;
 use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 
+#[derive(Clone, Debug, Default)]
 pub struct Request {
+    /// HTTP method, uppercase (`GET`)
+    pub method: String,
+    /// Request path, without the query string
+    pub path: String,
     pub params: HashMap<String, String>,
+    /// Decoded query string parameters (last value wins for repeated keys)
+    pub query: HashMap<String, String>,
+    pub headers: Headers,
+    /// Body as text; empty for multipart uploads, read with `multipart()`
     pub body: String,
+    /// From the client's `X-Request-Id`, or generated; echoed on the response
+    pub request_id: String,
+    /// Values registered with `Router::manage`, see `Request::state`
+    pub state: AppState,
+    /// Values middleware set for this request only, see `Request::extension`
+    pub extensions: AppState,
 }
 
 impl Request {
-    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
-        serde_json::from_str(&self.body)
+    /// Deserialize the JSON body
+    ///
+    /// Fails with a 413 over `max_json_bytes`, and with a 415 when
+    /// `strict_json` is on and the content type is not JSON.
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, crate::json::JsonError> {
+        crate::json::parse_body(&self.body, self.header("content-type"))
+    }
+
+    /// Deserialize the query string into a serde struct (`?page=2&sort=name`)
+    pub fn query_as<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<T, serde_urlencoded::de::Error> {
+        let encoded = serde_urlencoded::to_string(&self.query)
+            .map_err(|e| serde::de::Error::custom(e.to_string()))?;
+        serde_urlencoded::from_str(&encoded)
+    }
+
+    /// Parse a path parameter into any `FromStr` type
+    pub fn param<T: std::str::FromStr>(&self, name: &str) -> Result<T, ParamError> {
+        let value = self
+            .params
+            .get(name)
+            .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+        value.parse().map_err(|_| ParamError::Invalid {
+            name: name.to_string(),
+            value: value.clone(),
+            expected: std::any::type_name::<T>(),
+        })
     }
 }
 
+/// A path parameter that is missing or does not parse; answers 400.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParamError {
+    Missing(String),
+    Invalid {
+        name: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl std::fmt::Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamError::Missing(name) => write!(f, "missing parameter '{}'", name),
+            ParamError::Invalid {
+                name,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid parameter '{}': '{}' is not a valid {}",
+                name, value, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+#[derive(Clone)]
 pub struct Response {
     pub status: u16,
     pub body: String,
     pub headers: HashMap<String, String>,
+    /// Raw bytes sent instead of `body` (PDFs, images, downloads).
+    pub binary: Option<Vec<u8>>,
+    /// Chunks streamed instead of `body` (large files, generated downloads).
+    pub stream: Option<BodyStream>,
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// A streamed response body; clones share it and it can be consumed once.
+#[derive(Clone)]
+pub struct BodyStream(Arc<std::sync::Mutex<Option<ByteStream>>>);
+
+impl BodyStream {
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, std::io::Error>> + Send + 'static,
+    {
+        BodyStream(Arc::new(std::sync::Mutex::new(Some(Box::pin(stream)))))
+    }
+
+    /// The stream, unless it was already taken.
+    pub fn take(&self) -> Option<ByteStream> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// Size of the chunks `Response::file` reads.
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+enum FileChunks {
+    Closed(std::path::PathBuf, u64, Option<u64>),
+    /// Open file and the bytes left to send, `None` for up to the end
+    Open(tokio::fs::File, Option<u64>),
+    Done,
 }
 
 impl Responder for Response {
     type Body = BoxBody;
-    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let accept_encoding = req
+            .headers()
+            .get(actix_web::http::header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        let this = crate::compress::compress(self, accept_encoding);
         let mut res =
-            HttpResponse::build(actix_web::http::StatusCode::from_u16(self.status).unwrap());
-        for (k, v) in self.headers {
-            res.append_header((k, v));
+            HttpResponse::build(actix_web::http::StatusCode::from_u16(this.status).unwrap());
+        for (k, v) in this.headers {
+            // Repeated headers (`Set-Cookie`) are kept one per line
+            for value in v.lines() {
+                res.append_header((k.as_str(), value));
+            }
+        }
+        if let Some(stream) = this.stream.and_then(|s| s.take()) {
+            return res.streaming(stream);
+        }
+        match this.binary {
+            Some(bytes) => res.body(bytes),
+            None => res.body(this.body),
         }
-        res.body(self.body)
     }
 }
 
@@ -46,10 +176,13 @@ impl Response {
             status: 200,
             body: body.into(),
             headers,
+            binary: None,
+            stream: None,
         }
     }
 
-    /// JSON response with status 200 and JSON content type
+    /// JSON response with status 200 and JSON content type; field names are
+    /// those of `T`'s `Serialize` impl (see `crate::json`)
     pub fn json<T: Serialize>(body: T) -> Self {
         let body = serde_json::to_string(&body)
             .unwrap_or_else(|_| "{\"error\": \"Failed to serialize body\"}".to_string());
@@ -62,6 +195,8 @@ impl Response {
             status: 200,
             body,
             headers,
+            binary: None,
+            stream: None,
         }
     }
 
@@ -71,16 +206,388 @@ impl Response {
         self
     }
 
+    /// `302 Found` redirect to `url`
+    pub fn redirect(url: &str) -> Self {
+        Response::html("")
+            .with_status(302)
+            .add_header("Location", url)
+    }
+
+    /// `301 Moved Permanently` redirect to `url`
+    pub fn redirect_permanent(url: &str) -> Self {
+        Response::html("")
+            .with_status(301)
+            .add_header("Location", url)
+    }
+
+    /// Body streamed from `stream` as it is produced
+    pub fn stream<S>(stream: S) -> Self
+    where
+        S: Stream<Item = Bytes> + Send + 'static,
+    {
+        let mut resp = Response::html("").add_header("Content-Type", "application/octet-stream");
+        resp.stream = Some(BodyStream::new(stream.map(Ok)));
+        resp
+    }
+
+    /// File streamed from disk in chunks, with the content type guessed from
+    /// its extension; 404 when it isn't a readable file.
+    pub fn file<P: AsRef<std::path::Path>>(path: P) -> Self {
+        Self::file_range(path, 0, None)
+    }
+
+    /// Like `file`, streaming `len` bytes (or up to the end) from offset `start`.
+    pub fn file_range<P: AsRef<std::path::Path>>(path: P, start: u64, len: Option<u64>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        if !path.is_file() {
+            return Response::html("Not found").with_status(404);
+        }
+        let content_type = crate::staticfiles::content_type(&path.to_string_lossy());
+        let state = FileChunks::Closed(path, start, len);
+        let chunks = futures::stream::unfold(state, |state| async move {
+            let (mut file, remaining) = match state {
+                FileChunks::Closed(path, start, len) => {
+                    let opened = async {
+                        let mut file = tokio::fs::File::open(&path).await?;
+                        tokio::io::AsyncSeekExt::seek(&mut file, std::io::SeekFrom::Start(start))
+                            .await?;
+                        Ok::<_, std::io::Error>(file)
+                    };
+                    match opened.await {
+                        Ok(file) => (file, len),
+                        Err(e) => return Some((Err(e), FileChunks::Done)),
+                    }
+                }
+                FileChunks::Open(file, remaining) => (file, remaining),
+                FileChunks::Done => return None,
+            };
+            let size = remaining.map_or(FILE_CHUNK_SIZE, |n| n.min(FILE_CHUNK_SIZE as u64) as usize);
+            if size == 0 {
+                return None;
+            }
+            let mut buf = vec![0u8; size];
+            match tokio::io::AsyncReadExt::read(&mut file, &mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    let remaining = remaining.map(|r| r - n as u64);
+                    Some((Ok(Bytes::from(buf)), FileChunks::Open(file, remaining)))
+                }
+                Err(e) => Some((Err(e), FileChunks::Done)),
+            }
+        });
+        let mut resp = Response::html("").add_header("Content-Type", content_type);
+        resp.stream = Some(BodyStream::new(chunks));
+        resp
+    }
+
+    /// The full body, draining the stream if there is one (mainly for tests).
+    pub async fn body_bytes(self) -> Result<Vec<u8>, std::io::Error> {
+        if let Some(mut stream) = self.stream.and_then(|s| s.take()) {
+            let mut out = Vec::new();
+            while let Some(chunk) = stream.next().await {
+                out.extend_from_slice(&chunk?);
+            }
+            return Ok(out);
+        }
+        Ok(self.binary.unwrap_or_else(|| self.body.into_bytes()))
+    }
+
     /// Builder for adding or overwriting a header
     pub fn add_header<S: Into<String>>(mut self, key: S, val: S) -> Self {
         self.headers.insert(key.into(), val.into());
         self
     }
+
+    /// Builder adding a header value, keeping any earlier values of it
+    pub fn append_header(mut self, key: &str, val: &str) -> Self {
+        self.headers
+            .entry(key.to_string())
+            .and_modify(|existing| {
+                existing.push('\n');
+                existing.push_str(val);
+            })
+            .or_insert_with(|| val.to_string());
+        self
+    }
+}
+
+/// Method, path, route and query parameters of the request being handled, readable anywhere
+/// inside the handler future (e.g. by template context processors).
+#[derive(Clone, Debug, Default)]
+pub struct RequestScope {
+    pub method: String,
+    pub path: String,
+    /// Pattern of the matched route, e.g. `/users/:id`.
+    pub route: String,
+    pub params: HashMap<String, String>,
+    pub query: HashMap<String, String>,
+    /// Request headers, names lowercased.
+    pub headers: HashMap<String, String>,
+    /// The body as received, for binary payloads such as uploads.
+    pub raw_body: Bytes,
+    /// From the client's `X-Request-Id`, or generated; echoed on the response.
+    pub request_id: String,
+    /// Client address, scheme and host, through trusted proxies.
+    pub forwarded: crate::proxy::Forwarded,
+    /// Values middleware set for this request (e.g. the CSP nonce).
+    pub extensions: AppState,
+}
+
+impl RequestScope {
+    /// Scope of an incoming request before routing (no route or params yet);
+    /// `headers` have lowercase names.
+    fn incoming(
+        method: &str,
+        path: &str,
+        query: &str,
+        headers: HashMap<String, String>,
+        body: Bytes,
+        forwarded: crate::proxy::Forwarded,
+    ) -> Self {
+        RequestScope {
+            method: method.to_ascii_uppercase(),
+            path: path.to_string(),
+            route: String::new(),
+            params: HashMap::new(),
+            query: parse_urlencoded(query),
+            request_id: request_id_from(headers.get("x-request-id").map(String::as_str)),
+            headers,
+            raw_body: body,
+            forwarded,
+            extensions: AppState::new(),
+        }
+    }
+}
+
+/// Run `handler` for a matched request, with the handler's `Request` built
+/// from `scope` and the session named by its cookie. Shared by the server
+/// and `Router::dispatch_with_headers`.
+async fn dispatch_matched(handler: Handler, scope: RequestScope, pipeline: &Pipeline) -> Response {
+    let request = Request {
+        method: scope.method.clone(),
+        path: scope.path.clone(),
+        params: scope.params.clone(),
+        query: scope.query.clone(),
+        headers: Headers(scope.headers.clone()),
+        body: body_text(&scope.headers, &scope.raw_body),
+        request_id: scope.request_id.clone(),
+        state: pipeline.state.clone(),
+        extensions: AppState::new(),
+    };
+    let cookie_header = scope.headers.get("cookie").cloned();
+    call_with_middleware(handler, request, scope, pipeline, cookie_header.as_deref()).await
+}
+
+/// `Request::body` for a raw body: empty for multipart uploads, which are
+/// only read from `RequestScope::raw_body` and so are never copied.
+fn body_text(headers: &HashMap<String, String>, body: &[u8]) -> String {
+    let multipart = headers
+        .get("content-type")
+        .is_some_and(|ct| ct.to_ascii_lowercase().starts_with("multipart/"));
+    if multipart {
+        String::new()
+    } else {
+        String::from_utf8_lossy(body).into_owned()
+    }
+}
+
+/// Header carrying the request ID in both directions.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The client's request ID if it is a sane token (1 to 200 visible ASCII
+/// characters), otherwise a new random one.
+pub fn request_id_from(header: Option<&str>) -> String {
+    match header.map(str::trim) {
+        Some(id)
+            if !id.is_empty() && id.len() <= 200 && id.bytes().all(|b| b.is_ascii_graphic()) =>
+        {
+            id.to_string()
+        }
+        _ => format!("{:032x}", rand::random::<u128>()),
+    }
+}
+
+impl Request {
+    /// ID of the request being handled, for log correlation and tracing.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// HTTP method, uppercase (`GET`).
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Request path, without the query string.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Request headers, looked up case-insensitively.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// One request header: `req.header("Authorization")`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// A value a middleware stored in `RequestContext::extensions` for this
+    /// request, e.g. `req.extension::<CspNonce>()`.
+    pub fn extension<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.extensions.get::<T>()
+    }
+}
+
+/// Request headers by lowercased name; repeated headers are joined with
+/// `", "` (`"; "` for `Cookie`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Headers(HashMap<String, String>);
+
+/// Headers from a `name -> value` map, names in any case.
+impl From<HashMap<String, String>> for Headers {
+    fn from(headers: HashMap<String, String>) -> Self {
+        Headers(
+            headers
+                .into_iter()
+                .map(|(name, value)| (name.to_ascii_lowercase(), value))
+                .collect(),
+        )
+    }
+}
+
+impl Headers {
+    /// Value of header `name`, in any case.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// `(name, value)` pairs, names lowercased.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+tokio::task_local! {
+    static REQUEST_SCOPE: RequestScope;
+}
+
+/// The scope of the request currently being handled, if any.
+pub fn current_request() -> Option<RequestScope> {
+    REQUEST_SCOPE.try_with(|scope| scope.clone()).ok()
+}
+
+/// Run `fut` with `scope` as the current request (used by the router and tests).
+pub async fn with_request_scope<F: Future>(scope: RequestScope, fut: F) -> F::Output {
+    REQUEST_SCOPE.scope(scope, fut).await
+}
+
+/// Run `f` with `scope` as the current request, outside async code
+/// (streamed template rendering).
+pub(crate) fn with_request_scope_sync<R>(scope: RequestScope, f: impl FnOnce() -> R) -> R {
+    REQUEST_SCOPE.sync_scope(scope, f)
 }
 
 /// Handler type—expand as needed for params/state later!
-pub type Handler =
-    Arc<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync>;
+pub type Handler = Arc<HandlerFn>;
+
+type HandlerFn = dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync;
+
+/// Values a handler may return: responses, models (as JSON) and `Result`s of them.
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::html(self)
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        Response::html(self)
+    }
+}
+
+impl<T: crate::orm::Model + Serialize> IntoResponse for T {
+    fn into_response(self) -> Response {
+        Response::json(self)
+    }
+}
+
+impl<T: crate::orm::Model + Serialize> IntoResponse for Vec<T> {
+    fn into_response(self) -> Response {
+        Response::json(self)
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(err) => err.into_response(),
+        }
+    }
+}
+
+impl IntoResponse for ParamError {
+    fn into_response(self) -> Response {
+        Response::json(serde_json::json!({"error": self.to_string()})).with_status(400)
+    }
+}
+
+impl IntoResponse for sqlx::Error {
+    /// Missing rows become 404s; any other database error is logged and
+    /// answered with a generic 500, so that SQL and schema details stay out of
+    /// the response.
+    fn into_response(self) -> Response {
+        match self {
+            sqlx::Error::RowNotFound => {
+                Response::json(serde_json::json!({"error": "Not found"})).with_status(404)
+            }
+            other => {
+                log::error!("database error: {}", other);
+                Response::json(serde_json::json!({"error": "Internal server error"}))
+                    .with_status(500)
+            }
+        }
+    }
+}
+
+/// Turn an async fn returning any `IntoResponse` into a `Handler`.
+pub fn handler<F, Fut, R>(f: F) -> Handler
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = R> + Send + 'static,
+    R: IntoResponse,
+{
+    let f = Arc::new(f);
+    Arc::new(move |req| {
+        let f = f.clone();
+        Box::pin(async move { f(req).await.into_response() })
+    })
+}
 
 #[derive(Clone)]
 pub struct Route {
@@ -88,32 +595,946 @@ pub struct Route {
     pub path: String,
     pub handler: Handler,
     pub handler_name: String,
+    /// Name for reversing with `Router::url_for` and `{% url %}`.
+    pub name: Option<String>,
+    /// Layers added by the enclosing route groups, outermost first.
+    pub groups: Vec<GroupLayers>,
+}
+
+/// How many middleware a route group wraps around its routes, for
+/// `Router::middleware_chain`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GroupLayers {
+    pub prefix: String,
+    pub middlewares: usize,
+    pub post_middlewares: usize,
+    pub wrappers: usize,
+}
+
+impl Route {
+    /// Name the route: `router.add_route(...).name("user-detail")`.
+    pub fn name(&mut self, name: &str) -> &mut Self {
+        self.name = Some(name.to_string());
+        self
+    }
+}
+
+/// Route names to path patterns of one router, managed in its state so that
+/// `reverse` and `{% url %}` resolve against the router handling the request
+/// (cheap to clone, clones share the table).
+#[derive(Clone, Default)]
+pub struct RouteNames {
+    names: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl RouteNames {
+    /// Path pattern of the route named `name`.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.names.read().unwrap().get(name).cloned()
+    }
+
+    /// Replace the table with the names of `routes`.
+    fn update(&self, routes: &[Route]) {
+        let names = routes
+            .iter()
+            .filter_map(|r| Some((r.name.clone()?, r.path.clone())))
+            .collect();
+        *self.names.write().unwrap() = names;
+    }
+}
+
+/// Path of the route named `name` with its parameters filled in, e.g.
+/// `reverse("user-detail", &[("id", "42")])` → `/users/42`, looked up in the
+/// router handling the current request.
+///
+/// `None` outside a request, if no route has that name or a parameter is missing.
+pub fn reverse(name: &str, params: &[(&str, &str)]) -> Option<String> {
+    let pattern = crate::state::current_state()?
+        .get::<RouteNames>()?
+        .get(name)?;
+    build_path(&pattern, params)
+}
+
+/// Fill the `:name` and `*name` segments of `pattern`; `|sqid` segments are
+/// encoded and missing `:name?` segments are left out.
+fn build_path(pattern: &str, params: &[(&str, &str)]) -> Option<String> {
+    let param = |name: &str| params.iter().find(|(k, _)| *k == name).map(|(_, v)| *v);
+    let mut segments = Vec::new();
+    for segment in pattern.split('/') {
+        if let Some(name) = segment.strip_prefix('*') {
+            let rest: Vec<_> = param(name)?.split('/').map(percent_encode).collect();
+            segments.push(rest.join("/"));
+        } else if let Some(spec) = segment.strip_prefix(':') {
+            let optional = spec.ends_with('?');
+            let spec = spec.trim_end_matches('?');
+            let spec = spec.split('<').next().unwrap_or(spec);
+            let (name, constraint) = spec.split_once('|').unwrap_or((spec, ""));
+            let Some(value) = param(name) else {
+                if optional {
+                    continue;
+                }
+                return None;
+            };
+            segments.push(match constraint {
+                "sqid" => crate::obfuscate::encode_id(value.parse().ok()?)?,
+                _ => percent_encode(value),
+            });
+        } else {
+            segments.push(segment.to_string());
+        }
+    }
+    Some(segments.join("/"))
+}
+
+/// `{% url "user-detail" id=user.id %}`: the reversed path, empty if unknown.
+pub fn url_tag(args: &TagArgs, context: &HashMap<String, TemplateValue>) -> String {
+    let Some(name) = args.positional.first() else {
+        return String::new();
+    };
+    let name = TagArgs::resolve(name, context);
+    let values: Vec<(String, String)> = args
+        .named
+        .iter()
+        .map(|(k, v)| (k.clone(), TagArgs::resolve(v, context)))
+        .collect();
+    let params: Vec<(&str, &str)> = values
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    reverse(&name, &params).unwrap_or_default()
+}
+
+/// What middleware sees of the request being handled.
+#[derive(Clone, Debug, Default)]
+pub struct RequestContext {
+    pub path: String,
+    pub params: HashMap<String, String>,
+    pub is_authenticated: bool,
+    pub start_time: Option<std::time::Instant>,
+    /// Per-request values by type, handed to the handler as `Request::extensions`.
+    pub extensions: AppState,
+}
+
+/// Runs before the handler; returning a response short-circuits the request.
+///
+/// Changes made to `ctx.params` and `ctx.extensions` are visible to the handler.
+pub type Middleware = Arc<dyn Fn(&mut RequestContext) -> Option<Response> + Send + Sync>;
+
+/// Runs after the handler (or a short-circuiting middleware) and may rewrite the response.
+pub type PostMiddleware = Arc<dyn Fn(&RequestContext, Response) -> Response + Send + Sync>;
+
+/// Everything wrapped around a handler: middleware, sessions, application
+/// state and the error pages.
+#[derive(Clone)]
+struct Pipeline {
+    middlewares: Arc<Vec<Middleware>>,
+    post_middlewares: Arc<Vec<PostMiddleware>>,
+    sessions: Option<Sessions>,
+    state: AppState,
+    error_pages: ErrorPages,
+    wrappers: Arc<Vec<HandlerWrapper>>,
+}
+
+/// Run `handler` inside the request scope, surrounded by the middleware chain and,
+/// when enabled, the session named by `cookie_header`.
+async fn call_with_middleware(
+    handler: Handler,
+    request: Request,
+    scope: RequestScope,
+    pipeline: &Pipeline,
+    cookie_header: Option<&str>,
+) -> Response {
+    let request_scope = scope.clone();
+    crate::logging::add_field("request_id", scope.request_id.as_str());
+    let handler = pipeline
+        .wrappers
+        .iter()
+        .fold(handler, |handler, wrap| wrap(handler));
+    let inner = pipeline.error_pages.guard(
+        &request_scope,
+        run_pipeline(
+            handler,
+            request,
+            scope,
+            &pipeline.middlewares,
+            &pipeline.post_middlewares,
+        ),
+    );
+    // In the request scope so the session cookie can follow the scheme
+    let response = with_request_scope(request_scope.clone(), async {
+        match &pipeline.sessions {
+            Some(sessions) => sessions.scope(cookie_header, inner).await,
+            None => inner.await,
+        }
+    });
+    let mut response = crate::state::with_state(pipeline.state.clone(), response).await;
+    if !response.headers.contains_key(REQUEST_ID_HEADER) {
+        response
+            .headers
+            .insert(REQUEST_ID_HEADER.to_string(), request_scope.request_id);
+    }
+    response
+}
+
+async fn run_pipeline(
+    handler: Handler,
+    mut request: Request,
+    mut scope: RequestScope,
+    middlewares: &[Middleware],
+    post_middlewares: &[PostMiddleware],
+) -> Response {
+    let mut ctx = RequestContext {
+        path: scope.path.clone(),
+        params: request.params.clone(),
+        is_authenticated: false,
+        start_time: Some(std::time::Instant::now()),
+        extensions: request.extensions.clone(),
+    };
+    let response = match middlewares.iter().find_map(|mw| mw(&mut ctx)) {
+        Some(resp) => resp,
+        None => {
+            request.params = ctx.params.clone();
+            request.extensions = ctx.extensions.clone();
+            scope.extensions = ctx.extensions.clone();
+            REQUEST_SCOPE.scope(scope, handler(request)).await
+        }
+    };
+    post_middlewares
+        .iter()
+        .fold(response, |resp, pmw| pmw(&ctx, resp))
+}
+
+/// Wrap `handler` in a route-level middleware chain (used by route groups).
+///
+/// Runs inside the request scope, so `RequestContext::path` is the request path.
+pub fn with_middleware(
+    handler: Handler,
+    middlewares: Vec<Middleware>,
+    post_middlewares: Vec<PostMiddleware>,
+) -> Handler {
+    let middlewares = Arc::new(middlewares);
+    let post_middlewares = Arc::new(post_middlewares);
+    Arc::new(move |mut request| {
+        let handler = handler.clone();
+        let middlewares = middlewares.clone();
+        let post_middlewares = post_middlewares.clone();
+        Box::pin(async move {
+            let scope = current_request();
+            let mut ctx = RequestContext {
+                path: scope.as_ref().map(|s| s.path.clone()).unwrap_or_default(),
+                params: request.params.clone(),
+                is_authenticated: false,
+                start_time: Some(std::time::Instant::now()),
+                extensions: request.extensions.clone(),
+            };
+            let response = match middlewares.iter().find_map(|mw| mw(&mut ctx)) {
+                Some(resp) => resp,
+                None => {
+                    request.params = ctx.params.clone();
+                    request.extensions = ctx.extensions.clone();
+                    match scope {
+                        Some(mut scope) => {
+                            scope.extensions = ctx.extensions.clone();
+                            REQUEST_SCOPE.scope(scope, handler(request)).await
+                        }
+                        None => handler(request).await,
+                    }
+                }
+            };
+            post_middlewares
+                .iter()
+                .fold(response, |resp, pmw| pmw(&ctx, resp))
+        })
+    })
+}
+
+/// Routes sharing a path prefix and a middleware stack, see `Router::group`.
+pub struct RouteGroup {
+    prefix: String,
+    middlewares: Vec<Middleware>,
+    post_middlewares: Vec<PostMiddleware>,
+    wrappers: Vec<HandlerWrapper>,
+    routes: Vec<Route>,
+}
+
+/// Decorates a route handler, see `RouteGroup::wrap_handlers`.
+pub type HandlerWrapper = Arc<dyn Fn(Handler) -> Handler + Send + Sync>;
+
+impl RouteGroup {
+    pub(crate) fn new(prefix: &str) -> Self {
+        RouteGroup {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            middlewares: Vec::new(),
+            post_middlewares: Vec::new(),
+            wrappers: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Middleware run for every route of the group, after the router-wide chain.
+    pub fn add_middleware(&mut self, middleware: Middleware) {
+        self.middlewares.push(middleware);
+    }
+
+    pub fn add_post_middleware(&mut self, middleware: PostMiddleware) {
+        self.post_middlewares.push(middleware);
+    }
+
+    /// Decorate the handler of every route of the group (e.g. `Throttle::attach`),
+    /// inside the group middleware.
+    pub fn wrap_handlers<F>(&mut self, wrapper: F)
+    where
+        F: Fn(Handler) -> Handler + Send + Sync + 'static,
+    {
+        self.wrappers.push(Arc::new(wrapper));
+    }
+
+    /// Register a route relative to the group prefix.
+    pub fn add_route(
+        &mut self,
+        method: &str,
+        path: &str,
+        handler: Handler,
+        handler_name: &str,
+    ) -> &mut Route {
+        self.routes.push(Route {
+            method: method.to_string(),
+            path: join_paths(&self.prefix, path),
+            handler,
+            handler_name: handler_name.to_string(),
+            name: None,
+            groups: Vec::new(),
+        });
+        self.routes.last_mut().unwrap()
+    }
+
+    pub fn get<F, Fut, R>(&mut self, path: &str, f: F) -> &mut Route
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.add_route("GET", path, handler(f), std::any::type_name::<F>())
+    }
+
+    pub fn post<F, Fut, R>(&mut self, path: &str, f: F) -> &mut Route
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.add_route("POST", path, handler(f), std::any::type_name::<F>())
+    }
+
+    pub fn put<F, Fut, R>(&mut self, path: &str, f: F) -> &mut Route
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.add_route("PUT", path, handler(f), std::any::type_name::<F>())
+    }
+
+    pub fn patch<F, Fut, R>(&mut self, path: &str, f: F) -> &mut Route
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.add_route("PATCH", path, handler(f), std::any::type_name::<F>())
+    }
+
+    pub fn delete<F, Fut, R>(&mut self, path: &str, f: F) -> &mut Route
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        R: IntoResponse,
+    {
+        self.add_route("DELETE", path, handler(f), std::any::type_name::<F>())
+    }
+
+    /// Nested group; its middleware runs inside this group's.
+    pub fn group<F: FnOnce(&mut RouteGroup)>(&mut self, prefix: &str, f: F) {
+        let prefix = join_paths(&self.prefix, prefix);
+        let mut group = RouteGroup::new(&prefix);
+        f(&mut group);
+        self.routes.extend(group.into_routes());
+    }
+
+    /// The group's routes with their handlers wrapped in the group middleware.
+    pub(crate) fn into_routes(self) -> Vec<Route> {
+        let layers = GroupLayers {
+            prefix: self.prefix.clone(),
+            middlewares: self.middlewares.len(),
+            post_middlewares: self.post_middlewares.len(),
+            wrappers: self.wrappers.len(),
+        };
+        let routes = self.routes.into_iter().map(|mut route| {
+            if layers.middlewares + layers.post_middlewares + layers.wrappers > 0 {
+                route.groups.insert(0, layers.clone());
+            }
+            Route {
+                handler: self
+                    .wrappers
+                    .iter()
+                    .fold(route.handler, |handler, wrap| wrap(handler)),
+                ..route
+            }
+        });
+        if self.middlewares.is_empty() && self.post_middlewares.is_empty() {
+            return routes.collect();
+        }
+        routes
+            .map(|route| Route {
+                handler: with_middleware(
+                    route.handler,
+                    self.middlewares.clone(),
+                    self.post_middlewares.clone(),
+                ),
+                ..route
+            })
+            .collect()
+    }
+}
+
+/// Client address: from `X-Forwarded-For` when the peer is a trusted proxy,
+/// else the peer address.
+fn client_ip(req: &HttpRequest, proxies: &crate::proxy::TrustedProxies) -> String {
+    proxies
+        .client_ip(
+            req.peer_addr().map(|a| a.ip()),
+            req.headers()
+                .get("x-forwarded-for")
+                .and_then(|hv| hv.to_str().ok()),
+        )
+        .unwrap_or_else(|| "<unknown>".to_string())
+}
+
+/// Headers of `req` by lowercased name, repeated ones joined (HTTP/2 sends
+/// each cookie as its own `Cookie` header).
+fn header_map(req: &HttpRequest) -> HashMap<String, String> {
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for (name, value) in req.headers().iter() {
+        let Ok(value) = value.to_str() else { continue };
+        let separator = if name == actix_web::http::header::COOKIE {
+            "; "
+        } else {
+            ", "
+        };
+        headers
+            .entry(name.as_str().to_string())
+            .and_modify(|joined| {
+                joined.push_str(separator);
+                joined.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    headers
+}
+
+/// `/api` + `/users` → `/api/users`; `/api` + `/` → `/api`.
+fn join_paths(prefix: &str, path: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    let path = path.trim_start_matches('/');
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => format!("/{}", path),
+        (false, true) => prefix.to_string(),
+        (false, false) => format!("{}/{}", prefix, path),
+    }
+}
+
+/// The `body_limits` entry for `route`, by route name, then handler name.
+fn body_limit(settings: &Settings, route: &Route) -> Option<usize> {
+    route
+        .name
+        .as_ref()
+        .and_then(|name| settings.body_limits.get(name))
+        .or_else(|| settings.body_limits.get(&route.handler_name))
+        .copied()
+}
+
+/// 413 for a body over a route's limit.
+fn body_too_large(limit: usize) -> Response {
+    Response::json(serde_json::json!({
+        "error": format!("request body larger than {} bytes", limit)
+    }))
+    .with_status(413)
+}
+
+/// Startup task run after the server binds and before `/readyz` reports ready.
+pub type WarmupTask = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// An immutable snapshot of the routes served by a running server.
+pub struct RouteTable {
+    pub routes: Vec<Route>,
+    /// Path pattern -> allowed methods, for the method-mismatch page
+    pub paths: Vec<(String, Vec<String>)>,
+    tree: RouteTree<usize>,
+}
+
+/// Compile `routes` for lookup, indexed by position. A route conflicting with
+/// an earlier one is left out with a warning.
+fn compile_routes(routes: &[Route]) -> RouteTree<usize> {
+    let mut tree = RouteTree::new();
+    for (index, route) in routes.iter().enumerate() {
+        if let Err(e) = tree.insert(&route.method, &route.path, index) {
+            crate::logging::log(crate::logging::LogRecord::new(
+                log::Level::Warn,
+                e.to_string(),
+            ));
+        }
+    }
+    tree
+}
+
+impl RouteTable {
+    pub fn new(routes: Vec<Route>) -> Self {
+        let mut paths: Vec<(String, Vec<String>)> = Vec::new();
+        for route in &routes {
+            match paths.iter_mut().find(|(p, _)| *p == route.path) {
+                Some((_, methods)) => methods.push(route.method.clone()),
+                None => paths.push((route.path.clone(), vec![route.method.clone()])),
+            }
+        }
+        let tree = compile_routes(&routes);
+        RouteTable {
+            routes,
+            paths,
+            tree,
+        }
+    }
+
+    /// The route matching `method` and `path`, with its path parameters.
+    pub fn find(&self, method: &str, path: &str) -> Option<(&Route, HashMap<String, String>)> {
+        let (index, params) = self.tree.find(method, path)?;
+        Some((&self.routes[*index], params))
+    }
+
+    /// Methods of the routes matching `path`; empty if none does.
+    pub fn allowed_methods(&self, path: &str) -> Vec<String> {
+        self.tree.allowed_methods(path)
+    }
+}
+
+/// Handle swapping the route table of a running server (cheap to clone).
+#[derive(Clone)]
+pub struct RouteSwapper {
+    table: Arc<RwLock<Arc<RouteTable>>>,
+    names: RouteNames,
+}
+
+impl Default for RouteSwapper {
+    fn default() -> Self {
+        RouteSwapper {
+            table: Arc::new(RwLock::new(Arc::new(RouteTable::new(Vec::new())))),
+            names: RouteNames::default(),
+        }
+    }
+}
+
+impl RouteSwapper {
+    /// The table new requests are resolved against.
+    pub fn current(&self) -> Arc<RouteTable> {
+        self.table.read().unwrap().clone()
+    }
+
+    /// Atomically install a new table; requests already running finish on the old one.
+    pub fn replace(&self, routes: Vec<Route>) {
+        self.names.update(&routes);
+        let table = Arc::new(RouteTable::new(routes));
+        *self.table.write().unwrap() = table;
+    }
+}
+
+/// Header carrying the token of `reload_routes_handler`.
+pub const RELOAD_TOKEN_HEADER: &str = "X-Reload-Token";
+
+/// Admin endpoint swapping in the routes returned by `rebuild`.
+///
+/// Only answers `POST` requests carrying the token in the `X-Reload-Token`
+/// header. The endpoint keeps itself in the rebuilt table: when `rebuild`
+/// leaves its route out, it is added back under the same method and pattern.
+pub fn reload_routes_handler<F>(swapper: RouteSwapper, token: &str, rebuild: F) -> Handler
+where
+    F: Fn() -> Vec<Route> + Send + Sync + 'static,
+{
+    let token = token.to_string();
+    let rebuild = Arc::new(rebuild);
+    // The handler re-registers itself; a weak reference avoids a cycle
+    let this: Arc<std::sync::OnceLock<std::sync::Weak<HandlerFn>>> = Arc::default();
+    let handler: Handler = Arc::new({
+        let this = this.clone();
+        move |_req| {
+            let swapper = swapper.clone();
+            let token = token.clone();
+            let rebuild = rebuild.clone();
+            let this = this.get().and_then(std::sync::Weak::upgrade);
+            Box::pin(async move {
+                let scope = current_request().unwrap_or_default();
+                if scope.method != "POST" {
+                    return Response::json(serde_json::json!({"error": "Method not allowed"}))
+                        .with_status(405)
+                        .add_header("Allow".to_string(), "POST".to_string());
+                }
+                let given = scope
+                    .headers
+                    .get(&RELOAD_TOKEN_HEADER.to_ascii_lowercase())
+                    .cloned()
+                    .unwrap_or_default();
+                let valid = !token.is_empty() && constant_time_eq(&given, &token);
+                if !valid {
+                    return Response::json(serde_json::json!({"error": "Forbidden"}))
+                        .with_status(403);
+                }
+                let mut routes = rebuild();
+                if let Some(this) = this
+                    && !routes
+                        .iter()
+                        .any(|r| r.method == scope.method && r.path == scope.route)
+                {
+                    routes.push(Route {
+                        method: scope.method.clone(),
+                        path: scope.route.clone(),
+                        handler: this,
+                        handler_name: "reload_routes".to_string(),
+                        name: None,
+                        groups: Vec::new(),
+                    });
+                }
+                let count = routes.len();
+                swapper.replace(routes);
+                Response::json(serde_json::json!({"routes": count}))
+            })
+        }
+    });
+    let _ = this.set(Arc::downgrade(&handler));
+    handler
 }
 
 /// The Cobalto router is just a list of registered routes for now.
 pub struct Router {
     pub routes: Vec<Route>,
     pub settings: Settings,
+    pub middlewares: Vec<Middleware>,
+    pub post_middlewares: Vec<PostMiddleware>,
+    pub ws_routes: Vec<WsRoute>,
+    sessions: Option<Sessions>,
+    state: AppState,
+    warmups: Vec<(String, WarmupTask)>,
+    startup_hooks: Vec<WarmupTask>,
+    shutdown_hooks: Vec<WarmupTask>,
+    ready: Arc<AtomicBool>,
+    /// Set once shutdown starts; `/readyz` answers 503 from then on
+    draining: Arc<AtomicBool>,
+    live: RouteSwapper,
+    /// `routes` compiled for `dispatch`, by index
+    tree: RouteTree<usize>,
+    error_pages: ErrorPages,
+    /// Applied to the matched handler on every request, see `wrap_handlers`
+    wrappers: Vec<HandlerWrapper>,
+    /// Apps added with `mount`
+    pub(crate) apps: Vec<crate::app::App>,
 }
 
 impl Router {
-    pub fn new(settings: Settings) -> Self {
+    /// Router for `settings`, after the enabled plugins have adjusted them
+    /// (`CobaltoPlugin::configure`).
+    pub fn new(mut settings: Settings) -> Self {
+        crate::plugins::configure_plugins(&mut settings);
+        if let Some(secret) = settings.other.get("secret_key") {
+            crate::obfuscate::set_secret_key(secret);
+        }
+        crate::staticfiles::set_static_url(&settings.static_url);
+        crate::template::configure(&settings);
+        crate::logging::configure(&settings.log);
+        crate::mail::configure(&settings);
+        crate::i18n::configure(&settings.i18n);
+        crate::compress::configure(&settings.compression);
+        crate::multipart::set_max_upload_bytes(settings.max_upload_bytes);
+        crate::json::set_json_limits(settings.max_json_bytes, settings.strict_json);
+        if let Some(strategy) = crate::ids::IdStrategy::from_settings(&settings) {
+            crate::ids::set_id_strategy(strategy);
+        }
+        let live = RouteSwapper::default();
+        let mut state = AppState::new();
+        state.insert(crate::template::TemplateEngine::from_settings(&settings));
+        state.insert(live.names.clone());
         Router {
             routes: Vec::new(),
             settings,
+            middlewares: Vec::new(),
+            post_middlewares: Vec::new(),
+            ws_routes: Vec::new(),
+            sessions: None,
+            state,
+            warmups: Vec::new(),
+            startup_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            ready: Arc::new(AtomicBool::new(false)),
+            draining: Arc::new(AtomicBool::new(false)),
+            live,
+            tree: RouteTree::new(),
+            error_pages: ErrorPages::default(),
+            wrappers: Vec::new(),
+            apps: Vec::new(),
+        }
+    }
+
+    /// Decorate the handler of every request when it is dispatched, whenever
+    /// its route was added; the first wrapper registered is the innermost.
+    pub fn wrap_handlers<F>(&mut self, wrapper: F)
+    where
+        F: Fn(Handler) -> Handler + Send + Sync + 'static,
+    {
+        self.wrappers.push(Arc::new(wrapper));
+    }
+
+    /// Answer `status` errors raised by the router (404, 500) with `f`
+    /// instead of the `<status>.html` template or the built-in page.
+    pub fn error_handler<F>(&mut self, status: u16, f: F)
+    where
+        F: Fn(&ErrorInfo) -> Response + Send + Sync + 'static,
+    {
+        self.error_pages.set_handler(status, Arc::new(f));
+    }
+
+    /// Load a session for every request and save it back when it changes.
+    pub fn use_sessions(&mut self, sessions: Sessions) {
+        self.sessions = Some(sessions);
+    }
+
+    /// Share `value` with every handler, retrieved by type with `req.state::<T>()`.
+    pub fn manage<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.state.insert(value);
+    }
+
+    /// Clone of the value of type `T` shared with `manage`, if any.
+    pub fn managed<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.state.get::<T>()
+    }
+
+    fn pipeline(&self) -> Pipeline {
+        let mut error_pages = self.error_pages.clone();
+        error_pages.configure(&self.settings);
+        Pipeline {
+            middlewares: Arc::new(self.middlewares.clone()),
+            post_middlewares: Arc::new(self.post_middlewares.clone()),
+            sessions: self.sessions.clone(),
+            state: self.state.clone(),
+            error_pages,
+            wrappers: Arc::new(self.wrappers.clone()),
+        }
+    }
+
+    /// Replace every route, including on the running server.
+    pub fn replace_routes(&mut self, new_routes: Vec<Route>) {
+        self.live.replace(new_routes.clone());
+        self.tree = compile_routes(&new_routes);
+        self.routes = new_routes;
+    }
+
+    /// Handle for swapping routes while `run` is serving (plugins, dev reload).
+    pub fn route_swapper(&self) -> RouteSwapper {
+        self.live.clone()
+    }
+
+    /// Register a warm-up task (template precompile, cache priming, ...).
+    ///
+    /// Tasks run in registration order once the server is bound; `/readyz`
+    /// answers 503 until all of them have finished.
+    pub fn warmup<F, Fut>(&mut self, name: &str, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.warmups
+            .push((name.to_string(), Arc::new(move || Box::pin(task()))));
+    }
+
+    /// Run every warm-up task, mark the router ready and return per-task timings.
+    pub async fn run_warmups(&self) -> Vec<(String, std::time::Duration)> {
+        let mut timings = Vec::new();
+        for (name, task) in &self.warmups {
+            let t0 = std::time::Instant::now();
+            task().await;
+            timings.push((name.clone(), t0.elapsed()));
+        }
+        self.ready.store(true, Ordering::SeqCst);
+        timings
+    }
+
+    /// The startup banner: registered routes, warm-up timings and the address.
+    fn print_banner(&self, bind_addr: &str, warmups: &[(String, std::time::Duration)]) {
+        println!("╭──────────────────── Registered Routes ────────────────────╮");
+        for route in &self.routes {
+            println!(
+                "│   {:<6}  {}  (fn: {})",
+                route.method, route.path, route.handler_name
+            );
+        }
+        for route in &self.ws_routes {
+            println!("│   {:<6}  {}", "WS", route.path_pattern);
+        }
+        if !warmups.is_empty() {
+            println!("├───────────────────────── Warm-up ─────────────────────────┤");
+            for (name, elapsed) in warmups {
+                println!("│   {:<40}  {:>6}ms", name, elapsed.as_millis());
+            }
         }
+        println!("╰───────────────────────────────────────────────────────────╯");
+        let scheme = if self.settings.tls.enabled() {
+            "https"
+        } else {
+            "http"
+        };
+        println!("Cobalto router serving on {}://{}", scheme, bind_addr);
+    }
+
+    /// Register a task run by `run` before the server binds (migrations,
+    /// connecting pools, ...), in registration order.
+    pub fn on_startup<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.startup_hooks.push(Arc::new(move || Box::pin(hook())));
+    }
+
+    /// Register a task run by `run` once the server has stopped and in-flight
+    /// requests have drained (flushing caches, closing pools, ...).
+    pub fn on_shutdown<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks.push(Arc::new(move || Box::pin(hook())));
+    }
+
+    /// Run the startup hooks in registration order.
+    pub async fn run_startup_hooks(&self) {
+        for hook in &self.startup_hooks {
+            hook().await;
+        }
+    }
+
+    /// Run the shutdown hooks in registration order.
+    pub async fn run_shutdown_hooks(&self) {
+        for hook in &self.shutdown_hooks {
+            hook().await;
+        }
+    }
+
+    /// Whether warm-up has completed and shutdown hasn't started.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst) && !self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Register a middleware run before every handler, in registration order.
+    pub fn add_middleware(&mut self, middleware: Middleware) {
+        self.middlewares.push(middleware);
+    }
+
+    /// Register a middleware run on every response, in registration order.
+    pub fn add_post_middleware(&mut self, middleware: PostMiddleware) {
+        self.post_middlewares.push(middleware);
     }
 
     /// Register a route.
-    pub fn add_route(&mut self, method: &str, path: &str, handler: Handler, handler_name: &str) {
-        self.routes.push(Route {
+    ///
+    /// # Panics
+    ///
+    /// If the pattern is malformed, an earlier route has the same method and
+    /// pattern shape (`/users/:id` vs `/users/:pk`), or it has a `|sqid`
+    /// segment and no `secret_key` is configured.
+    pub fn add_route(
+        &mut self,
+        method: &str,
+        path: &str,
+        handler: Handler,
+        handler_name: &str,
+    ) -> &mut Route {
+        self.push_route(Route {
             method: method.to_string(),
             path: path.to_string(),
             handler,
             handler_name: handler_name.to_string(),
+            name: None,
+            groups: Vec::new(),
+        })
+    }
+
+    pub(crate) fn push_route(&mut self, route: Route) -> &mut Route {
+        if route.path.contains("|sqid") && !crate::obfuscate::is_configured() {
+            panic!(
+                "route {} uses |sqid but no secret_key is configured",
+                route.path
+            );
+        }
+        if let Err(e) = self
+            .tree
+            .insert(&route.method, &route.path, self.routes.len())
+        {
+            panic!("{}", e);
+        }
+        self.routes.push(route);
+        self.routes.last_mut().unwrap()
+    }
+
+    /// Path of the route named `name`: `router.url_for("user-detail", &[("id", "42")])`.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let route = self
+            .routes
+            .iter()
+            .find(|r| r.name.as_deref() == Some(name))?;
+        build_path(&route.path, params)
+    }
+
+    /// Register routes under a shared prefix and middleware stack:
+    /// `router.group("/api/v1", |g| { g.get("/users", list_users); })`.
+    ///
+    /// Panics on conflicting routes, as `add_route` does.
+    pub fn group<F: FnOnce(&mut RouteGroup)>(&mut self, prefix: &str, f: F) {
+        let mut group = RouteGroup::new(prefix);
+        f(&mut group);
+        for route in group.into_routes() {
+            self.push_route(route);
+        }
+    }
+
+    /// Register a WebSocket route; path parameters work as for HTTP routes.
+    pub fn add_websocket(&mut self, path: &str, handler: WsHandler) {
+        self.ws_routes.push(WsRoute {
+            path_pattern: path.to_string(),
+            handler,
         });
     }
 
+    /// Context for a WebSocket request, if `path` (with optional query) matches a route.
+    fn ws_match(&self, path: &str) -> Option<(WsHandler, WsContext)> {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        self.ws_routes.iter().find_map(|route| {
+            let params = extract_path_params(&route.path_pattern, path)?;
+            Some((
+                route.handler.clone(),
+                WsContext {
+                    path: path.to_string(),
+                    params,
+                    query: parse_urlencoded(query),
+                    state: self.state.clone(),
+                },
+            ))
+        })
+    }
+
+    /// Run the WebSocket handler for `path` against an in-memory client, returning
+    /// the client end (`None` if no WebSocket route matches). Useful in tests.
+    pub fn connect_websocket(&self, path: &str) -> Option<WebSocket> {
+        let (handler, ctx) = self.ws_match(path)?;
+        let (server, client) = WebSocket::pair();
+        tokio::spawn(handler(ctx, server));
+        Some(client)
+    }
+
     /// List all registered routes as (method, path) strings.
     pub fn list_routes(&self) -> Vec<(String, String)> {
         self.routes
@@ -122,140 +1543,412 @@ impl Router {
             .collect()
     }
 
-    pub async fn run(&self) -> std::io::Result<()> {
-        let bind_addr = format!("{}:{}", self.settings.host, self.settings.port);
-        let app_state = self.settings.clone();
-        let routes = self.routes.clone();
+    /// The routes matching `path` (a request path or a route pattern).
+    pub fn matching_routes(&self, path: &str) -> Vec<&Route> {
+        self.routes
+            .iter()
+            .filter(|r| r.path == path || extract_path_params(&r.path, path).is_some())
+            .collect()
+    }
 
-        // Log all registered routes at startup
-        println!("╭──────────────────── Registered Routes ────────────────────╮");
-        for route in &self.routes {
-            println!(
-                "│   {:<6}  {}  (fn: {})",
-                route.method, route.path, route.handler_name
+    /// The layers a request to `route` runs through, in order: router-wide
+    /// middleware, router-wide handler wrappers (last registered outermost),
+    /// then each group's middleware and wrappers from the outermost group in,
+    /// the handler, and the post-middleware on the way back out.
+    pub fn middleware_chain(&self, route: &Route) -> Vec<String> {
+        let mut chain: Vec<String> = (1..=self.middlewares.len())
+            .map(|i| format!("pre      global middleware {}", i))
+            .collect();
+        chain.extend(
+            (1..=self.wrappers.len())
+                .rev()
+                .map(|i| format!("wrap     global wrapper {}", i)),
+        );
+        for group in &route.groups {
+            chain.extend(
+                (1..=group.middlewares)
+                    .map(|i| format!("pre      group {} middleware {}", group.prefix, i)),
+            );
+            chain.extend(
+                (1..=group.wrappers)
+                    .rev()
+                    .map(|i| format!("wrap     group {} wrapper {}", group.prefix, i)),
             );
         }
-        println!("╰───────────────────────────────────────────────────────────╯");
-        println!("Cobalto router serving on http://{}", bind_addr);
+        chain.push(format!("handler  {}", route.handler_name));
+        for group in route.groups.iter().rev() {
+            chain.extend(
+                (1..=group.post_middlewares)
+                    .map(|i| format!("post     group {} post-middleware {}", group.prefix, i)),
+            );
+        }
+        chain.extend(
+            (1..=self.post_middlewares.len())
+                .map(|i| format!("post     global post-middleware {}", i)),
+        );
+        chain
+    }
 
-        actix_web::HttpServer::new(move || {
+    /// Dispatch a request in-process, without a server (used by tooling and tests).
+    ///
+    /// `path` may carry a query string. Unmatched requests get a plain 404.
+    pub async fn dispatch(&self, method: &str, path: &str, body: &str) -> Response {
+        self.dispatch_with_headers(
+            method,
+            path,
+            HashMap::new(),
+            Bytes::copy_from_slice(body.as_bytes()),
+        )
+        .await
+    }
+
+    /// `dispatch` with request headers and a raw body; header names are
+    /// matched case-insensitively and a `cookie` header selects the session.
+    pub async fn dispatch_with_headers(
+        &self,
+        method: &str,
+        path: &str,
+        headers: HashMap<String, String>,
+        body: Bytes,
+    ) -> Response {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        // The server adds the allowed methods and route suggestions; tooling
+        // only needs the status and the configured error page
+        let Some((index, params)) = self.tree.find(method, path) else {
+            let info = ErrorInfo {
+                status: 404,
+                method: method.to_ascii_uppercase(),
+                path: path.to_string(),
+                detail: Some(format!("No route matches {} {}", method, path)),
+            };
+            return self
+                .pipeline()
+                .error_pages
+                .render(&info)
+                .unwrap_or_else(|| Response::html("Not found").with_status(404));
+        };
+        let route = &self.routes[*index];
+        self.live.names.update(&self.routes);
+        if let Some(limit) = body_limit(&self.settings, route)
+            && body.len() > limit
+        {
+            return body_too_large(limit);
+        }
+        let headers: HashMap<String, String> = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect();
+        // No peer address: forwarded headers are never trusted here
+        let forwarded = crate::proxy::TrustedProxies::default().resolve(None, &headers, false);
+        let scope = RequestScope {
+            route: route.path.clone(),
+            params,
+            ..RequestScope::incoming(method, path, query, headers, body, forwarded)
+        };
+        dispatch_matched(route.handler.clone(), scope, &self.pipeline()).await
+    }
+
+    /// Serve until SIGINT or SIGTERM, then stop accepting connections, let
+    /// in-flight requests finish (up to the `shutdown_timeout` setting, 30s by
+    /// default) and run the shutdown hooks.
+    ///
+    /// The registered scheduler and task queue worker start once the startup
+    /// hooks have run, in this process.
+    pub async fn run(&self) -> std::io::Result<()> {
+        self.settings.validate().map_err(std::io::Error::other)?;
+        self.run_startup_hooks().await;
+        self.start_background();
+        let result = self.serve().await;
+        self.run_shutdown_hooks().await;
+        result
+    }
+
+    /// Serve HTTP only until SIGINT or SIGTERM: no startup or shutdown
+    /// hooks, no background roles.
+    pub async fn serve(&self) -> std::io::Result<()> {
+        self.serve_until(shutdown_signal()).await
+    }
+
+    /// `serve` until `shutdown` resolves (the web role of `manage start`),
+    /// then drain in-flight requests like `serve` does on a signal.
+    pub async fn serve_until<F>(&self, shutdown: F) -> std::io::Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let bind_addr = format!("{}:{}", self.settings.host, self.settings.port);
+        // Shared by every actix worker
+        let app_state = actix_web::web::Data::new(self.settings.clone());
+        self.live.replace(self.routes.clone());
+        let live = self.live.clone();
+
+        let ready = self.ready.clone();
+        let draining = self.draining.clone();
+        let mut pipeline = self.pipeline();
+        let debug = self.settings.debug;
+        if debug {
+            // Development: re-render on template changes and refresh the browser
+            crate::reload::Watcher::new()
+                .assets(&self.settings.template.dir)
+                .assets(&self.settings.static_dir)
+                .source("src")
+                .spawn();
+            let mut post_middlewares = (*pipeline.post_middlewares).clone();
+            post_middlewares.push(Arc::new(|_: &RequestContext, response: Response| {
+                crate::reload::inject_script(response)
+            }));
+            pipeline.post_middlewares = Arc::new(post_middlewares);
+            println!("Live reload enabled, watching templates, static files and src/");
+        }
+        let ws_routes = self.ws_routes.clone();
+        let max_upload_bytes = self.settings.max_upload_bytes;
+        let state = self.state.clone();
+        let statics = crate::staticfiles::StaticFiles::from_settings(&self.settings);
+        let monitoring = self.settings.monitoring.clone();
+        let db = self.state.get::<crate::orm::Db>();
+        let proxies = crate::proxy::TrustedProxies::from_settings(&self.settings);
+        let mut server = actix_web::HttpServer::new(move || {
             // Create App with app_data up front
-            let app = actix_web::App::new().app_data(actix_web::web::Data::new(app_state.clone()));
+            let app = actix_web::App::new()
+                .app_data(app_state.clone())
+                .app_data(actix_web::web::PayloadConfig::new(max_upload_bytes));
 
-            // Fold over all routes, chaining .route calls
-            let route_paths: Vec<(String, Vec<String>)> = routes
-                .iter()
-                .fold(HashMap::new(), |mut map, route| {
-                    map.entry(route.path.clone())
-                        .or_insert(vec![])
-                        .push(route.method.clone());
-                    map
-                })
-                .into_iter()
-                .collect();
+            // Readiness probe, healthy once warm-up tasks have run
+            let app = app.route(
+                "/readyz",
+                actix_web::web::get().to({
+                    let ready = ready.clone();
+                    let draining = draining.clone();
+                    move || {
+                        let ready =
+                            ready.load(Ordering::SeqCst) && !draining.load(Ordering::SeqCst);
+                        async move {
+                            if ready {
+                                HttpResponse::Ok().body("ready")
+                            } else {
+                                HttpResponse::ServiceUnavailable().body("warming up")
+                            }
+                        }
+                    }
+                }),
+            );
 
-            let app = routes
-                .iter()
-                .fold(app, |app, route| {
-                    let path_pattern = route.path.clone();
-                    let handler = route.handler.clone();
-                    let method = route.method.clone();
-
-                    app.route(
-                        "/{tail:.*}",
-                        actix_web::web::route()
-                            .guard(actix_web::guard::fn_guard({
-                                let pattern = path_pattern.clone();
-                                let method = method.clone();
-                                move |ctx| {
-                                    // Check HTTP method and path pattern
-                                    let req = ctx.head();
-                                    let req_method = req.method.as_str();
-                                    let req_path = req.uri.path();
-                                    req_method == method
-                                        && extract_path_params(&pattern, req_path).is_some()
-                                }
-                            }))
-                            .to({
-                                let path_pattern = path_pattern.clone();
-                                let handler = handler.clone();
-                                move |req: HttpRequest, body: actix_web::web::Bytes| {
-                                    let path_pattern = path_pattern.clone();
-                                    let handler = handler.clone();
-                                    async move {
-                                        let params = extract_path_params(&path_pattern, req.path())
-                                            .unwrap_or_default();
-                                        let body_str =
-                                            String::from_utf8(body.to_vec()).unwrap_or_default();
-                                        let request = Request {
-                                            params: params.clone(),
-                                            body: body_str,
-                                        };
+            // Liveness probe, checking the managed database if any
+            let app = if monitoring.healthz {
+                app.route(
+                    &monitoring.healthz_path,
+                    actix_web::web::get().to({
+                        let db = db.clone();
+                        move |req: HttpRequest| {
+                            let db = db.clone();
+                            async move { crate::metrics::health(db.as_ref()).await.respond_to(&req) }
+                        }
+                    }),
+                )
+            } else {
+                app
+            };
 
-                                        let t0 = std::time::Instant::now();
-                                        let response = (handler)(request).await;
-                                        let elapsed = t0.elapsed().as_millis();
-
-                                        let now = chrono::Local::now();
-                                        let ip = req
-                                            .headers()
-                                            .get("x-forwarded-for")
-                                            .and_then(|hv| hv.to_str().ok())
-                                            .map(|s| {
-                                                s.split(',').next().unwrap_or(s).trim().to_string()
-                                            })
-                                            .or_else(|| req.peer_addr().map(|a| a.ip().to_string()))
-                                            .unwrap_or_else(|| "<unknown>".to_string());
-                                        println!(
-                                            "[{}] {} {} {} [{}ms, {}]",
-                                            now.format("%Y-%m-%d %H:%M:%S"),
-                                            req.method(),
-                                            req.path(),
-                                            response.status,
-                                            elapsed,
-                                            ip,
-                                        );
-                                        response
-                                    }
-                                }
-                            }),
-                    )
-                })
-                .default_service(actix_web::web::to({
-                    let route_paths = route_paths.clone();
+            // Prometheus scrape endpoint
+            let app = if monitoring.metrics {
+                app.route(
+                    &monitoring.metrics_path,
+                    actix_web::web::get().to(|req: HttpRequest| async move {
+                        crate::metrics::metrics_response().respond_to(&req)
+                    }),
+                )
+            } else {
+                app
+            };
+
+            // Version polled by the live-reload script in debug mode
+            let app = if debug {
+                app.route(
+                    crate::reload::RELOAD_PATH,
+                    actix_web::web::get().to(|| async { crate::reload::version().to_string() }),
+                )
+            } else {
+                app
+            };
+
+            // Files under static_dir, served at static_url
+            let app = app.route(
+                &statics.route_pattern(),
+                actix_web::web::get().to({
+                    let statics = statics.clone();
                     move |req: HttpRequest| {
-                        let route_paths = route_paths.clone();
+                        let statics = statics.clone();
                         async move {
+                            let header = |name: &str| {
+                                req.headers().get(name).and_then(|hv| hv.to_str().ok())
+                            };
+                            statics
+                                .serve(
+                                    req.match_info().get("tail").unwrap_or(""),
+                                    header("if-none-match"),
+                                    header("range"),
+                                )
+                                .await
+                                .respond_to(&req)
+                        }
+                    }
+                }),
+            );
+
+            // WebSocket upgrades, pumped into the handler's message channel
+            let app = ws_routes.iter().fold(app, |app, route| {
+                let route = route.clone();
+                let state = state.clone();
+                app.route(
+                    &crate::websocket::actix_pattern(&route.path_pattern),
+                    actix_web::web::get().to(
+                        move |req: HttpRequest, payload: actix_web::web::Payload| {
+                            let route = route.clone();
+                            let state = state.clone();
+                            async move {
+                                let params = extract_path_params(&route.path_pattern, req.path())
+                                    .ok_or_else(|| {
+                                    actix_web::error::ErrorNotFound("Not found")
+                                })?;
+                                let (response, session, stream) = actix_ws::handle(&req, payload)?;
+                                let ctx = WsContext {
+                                    path: req.path().to_string(),
+                                    params,
+                                    query: parse_urlencoded(req.query_string()),
+                                    state,
+                                };
+                                let (server, client) = WebSocket::pair();
+                                actix_web::rt::spawn((route.handler)(ctx, server));
+                                actix_web::rt::spawn(crate::websocket::pump(
+                                    session, stream, client,
+                                ));
+                                Ok::<_, actix_web::Error>(response)
+                            }
+                        },
+                    ),
+                )
+            });
+
+            // Every request is resolved against the live route table; the
+            // snapshot keeps in-flight requests on the table they started with
+            let app = app.default_service(actix_web::web::to({
+                let live = live.clone();
+                let pipeline = pipeline.clone();
+                let proxies = proxies.clone();
+                move |req: HttpRequest, body: actix_web::web::Bytes| {
+                    let table = live.current();
+                    let pipeline = pipeline.clone();
+                    let proxies = proxies.clone();
+                    async move {
+                        let found = table
+                            .find(req.method().as_str(), req.path())
+                            .map(|(route, params)| {
+                                let limit = req
+                                    .app_data::<actix_web::web::Data<Settings>>()
+                                    .and_then(|settings| body_limit(settings, route));
+                                (route.handler.clone(), route.path.clone(), limit, params)
+                            });
+                        if let Some((handler, route, limit, params)) = found {
+                            if let Some(limit) = limit
+                                && body.len() > limit
+                            {
+                                return body_too_large(limit).respond_to(&req);
+                            }
+                            let headers = header_map(&req);
+                            let forwarded = proxies.resolve(
+                                req.peer_addr().map(|a| a.ip()),
+                                &headers,
+                                req.app_config().secure(),
+                            );
+                            let scope = RequestScope {
+                                route: route.clone(),
+                                params,
+                                ..RequestScope::incoming(
+                                    req.method().as_str(),
+                                    req.path(),
+                                    req.query_string(),
+                                    headers,
+                                    body,
+                                    forwarded,
+                                )
+                            };
+
+                            let ip = client_ip(&req, &proxies);
+                            let response = crate::logging::scope(async {
+                                let _in_flight = crate::metrics::InFlight::start();
+                                let t0 = std::time::Instant::now();
+                                let response = dispatch_matched(handler, scope, &pipeline).await;
+                                let elapsed = t0.elapsed();
+                                crate::metrics::record(
+                                    req.method().as_str(),
+                                    &route,
+                                    response.status,
+                                    elapsed,
+                                );
+                                crate::logging::log_request(
+                                    req.method().as_str(),
+                                    req.path(),
+                                    response.status,
+                                    Some(elapsed.as_millis()),
+                                    &ip,
+                                );
+                                response
+                            })
+                            .await;
+                            response.respond_to(&req)
+                        } else {
+                            let route_paths = &table.paths;
+                            let allowed_methods = table.allowed_methods(req.path());
+                            let debug = req
+                                .app_data::<actix_web::web::Data<Settings>>()
+                                .map(|s| s.debug)
+                                .unwrap_or(false);
                             let req_path = req.path();
                             let req_method = req.method().as_str().to_string();
-                            // Find if path matches any known route (regardless of method)
-                            let matched = route_paths
-                                .iter()
-                                .find(|(path, _)| {
-                                    // Use extract_path_params logic for pattern match
-                                    extract_path_params(path, req_path).is_some()
-                                });
-
-                            let ip = req
+
+                            let ip = client_ip(&req, &proxies);
+                            crate::metrics::record(
+                                req.method().as_str(),
+                                crate::metrics::UNMATCHED,
+                                404,
+                                std::time::Duration::ZERO,
+                            );
+                            crate::logging::log_request(
+                                req.method().as_str(),
+                                req.path(),
+                                404,
+                                None,
+                                &ip,
+                            );
+
+                            // Custom error page, unless the client wants JSON
+                            let wants_json = req
                                 .headers()
-                                .get("x-forwarded-for")
-                                .and_then(|hv| hv.to_str().ok())
-                                .map(|s| {
-                                    s.split(',').next().unwrap_or(s).trim().to_string()
-                                })
-                                .or_else(|| req.peer_addr().map(|a| a.ip().to_string()))
-                                .unwrap_or_else(|| "<unknown>".to_string());
-                            let now = chrono::Local::now();
-
-                            if let Some((_, allowed_methods)) = matched {
+                                .get("accept")
+                                .and_then(|h| h.to_str().ok())
+                                .is_some_and(|a| a.contains("application/json"));
+                            let detail = if allowed_methods.is_empty() {
+                                format!("No route matches {} {}", req_method, req_path)
+                            } else {
+                                format!(
+                                    "Method {} not allowed; allowed methods: {}",
+                                    req_method,
+                                    allowed_methods.join(", ")
+                                )
+                            };
+                            let info = ErrorInfo {
+                                status: 404,
+                                method: req_method.clone(),
+                                path: req_path.to_string(),
+                                detail: Some(detail),
+                            };
+                            if !wants_json
+                                && let Some(page) = pipeline.error_pages.render(&info)
+                            {
+                                return page.respond_to(&req);
+                            }
+
+                            if !allowed_methods.is_empty() {
                                 // Path matches but method does not
-                                println!(
-                                    "[{}] {} {} 404 [{}]",
-                                    now.format("%Y-%m-%d %H:%M:%S"),
-                                    req.method(),
-                                    req.path(),
-                                    ip,
-                                );
                                 let accept = req
                                     .headers()
                                     .get("accept")
@@ -287,59 +1980,339 @@ impl Router {
                                     .get("accept")
                                     .and_then(|h| h.to_str().ok())
                                     .unwrap_or("");
-                                println!(
-                                    "[{}] {} {} 404 [{}]",
-                                    now.format("%Y-%m-%d %H:%M:%S"),
-                                    req.method(),
-                                    req.path(),
-                                    ip,
-                                );
+                                // In debug mode, point at near-miss routes and the route table
+                                let patterns: Vec<&str> =
+                                    route_paths.iter().map(|(p, _)| p.as_str()).collect();
+                                let suggestions = if debug {
+                                    suggest_routes(&patterns, req_path, 5)
+                                } else {
+                                    Vec::new()
+                                };
                                 if accept.contains("application/json") {
-                                    HttpResponse::NotFound()
-                                        .content_type("application/json; charset=utf-8")
-                                        .body(r#"{"error":"Not found","status":404}"#)
+                                    if debug {
+                                        HttpResponse::NotFound()
+                                            .content_type("application/json; charset=utf-8")
+                                            .body(
+                                                serde_json::json!({
+                                                    "error": "Not found",
+                                                    "status": 404,
+                                                    "suggestions": suggestions,
+                                                })
+                                                .to_string(),
+                                            )
+                                    } else {
+                                        HttpResponse::NotFound()
+                                            .content_type("application/json; charset=utf-8")
+                                            .body(r#"{"error":"Not found","status":404}"#)
+                                    }
                                 } else {
+                                    let debug_html = if debug {
+                                        let list = |items: &[&str]| {
+                                            items
+                                                .iter()
+                                                .map(|p| {
+                                                    format!("<li><code>{}</code></li>", escape_html(p))
+                                                })
+                                                .collect::<String>()
+                                        };
+                                        let suggested: Vec<&str> =
+                                            suggestions.iter().map(|s| s.as_str()).collect();
+                                        format!(
+                                            r#"<div style="text-align:left;display:inline-block">
+                                            <p>No route matches <code>{}</code></p>
+                                            <h3>Did you mean:</h3><ul>{}</ul>
+                                            <h3>Registered routes:</h3><ul>{}</ul>
+                                            </div>"#,
+                                            escape_html(req_path),
+                                            list(&suggested),
+                                            list(&patterns)
+                                        )
+                                    } else {
+                                        String::new()
+                                    };
                                     HttpResponse::NotFound()
                                         .content_type("text/html; charset=utf-8")
-                                        .body(
+                                        .body(format!(
                                             r#"<!DOCTYPE html>
                                             <html lang="en">
                                             <head><meta charset="utf-8"><title>404 Not Found</title></head>
                                             <body style="font-family:sans-serif;text-align:center;margin-top:10vh">
                                             <h1 style="font-size:4rem;margin-bottom:0.5em">404</h1>
                                             <p style="font-size:1.5rem;margin-bottom:2em">Page not found</p>
+                                            {}
                                             </body>
                                             </html>
-                                            "#
-                                        )
+                                            "#,
+                                            debug_html
+                                        ))
                                 }
                             }
                         }
                     }
-                }));
+                }
+            }));
             app
-        })
-        .bind(bind_addr)?
-        .run()
-        .await
+        });
+        if let Some(workers) = self.settings.workers {
+            server = server.workers(workers);
+        }
+        let tuning = &self.settings.server;
+        if let Some(secs) = tuning.keep_alive {
+            server = server.keep_alive(match secs {
+                0 => actix_web::http::KeepAlive::Disabled,
+                secs => std::time::Duration::from_secs(secs).into(),
+            });
+        }
+        if let Some(secs) = tuning.client_request_timeout {
+            server = server.client_request_timeout(std::time::Duration::from_secs(secs));
+        }
+        if let Some(max) = tuning.max_connections {
+            server = server.max_connections(max);
+        }
+        // Signals are handled below so shutdown hooks run after draining
+        server = server
+            .disable_signals()
+            .shutdown_timeout(self.settings.get_or("shutdown_timeout", 30));
+        let tls = &self.settings.tls;
+        let server = match (&tls.cert, &tls.key) {
+            (Some(cert), Some(key)) => {
+                let config = crate::tls::load_rustls_config(cert, key)?;
+                if let Some(redirect_port) = tls.redirect_port {
+                    let redirect = crate::tls::redirect_server(
+                        &self.settings.host,
+                        redirect_port,
+                        self.settings.port,
+                    )?;
+                    tokio::spawn(redirect);
+                    println!(
+                        "Redirecting http://{}:{} to HTTPS",
+                        self.settings.host, redirect_port
+                    );
+                }
+                server.bind_rustls_0_23(&bind_addr, config)?.run()
+            }
+            _ if tuning.http2 => server.bind_auto_h2c(&bind_addr)?.run(),
+            _ => server.bind(&bind_addr)?.run(),
+        };
+        let handle = server.handle();
+        let draining = self.draining.clone();
+        tokio::spawn(async move {
+            shutdown.await;
+            println!("Shutting down, waiting for in-flight requests...");
+            draining.store(true, Ordering::SeqCst);
+            handle.stop(true).await;
+        });
+        let warmup = async {
+            let timings = self.run_warmups().await;
+            self.print_banner(&bind_addr, &timings);
+        };
+        let (result, _) = tokio::join!(server, warmup);
+        result
     }
 }
 
-fn extract_path_params(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
-    let pattern_parts: Vec<_> = pattern.trim_matches('/').split('/').collect();
-    let path_parts: Vec<_> = path.trim_matches('/').split('/').collect();
-    if pattern_parts.len() != path_parts.len() {
-        return None;
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+pub(crate) async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
     }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Match `path` against a route `pattern`, returning its parameters.
+///
+/// Besides literal and `:name` segments, a pattern may end with `:name?`
+/// segments, which can be left out of the path, or with a `*name` catch-all,
+/// which captures the rest of the path (possibly empty) joined by `/`.
+/// Parameters are percent-decoded segment by segment.
+fn extract_path_params(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let split = |s: &str| match s.trim_matches('/') {
+        "" => Vec::new(),
+        trimmed => trimmed.split('/').map(str::to_string).collect::<Vec<_>>(),
+    };
+    let pattern_parts = split(pattern);
+    let path_parts = split(path);
+    let decoded: Vec<String> = path_parts.iter().map(|s| percent_decode_path(s)).collect();
     let mut params = HashMap::new();
-    for (p, actual) in pattern_parts.iter().zip(path_parts.iter()) {
-        if p.starts_with(':') {
-            params.insert(p[1..].to_string(), actual.to_string());
-        } else if *p != *actual {
+    for (i, p) in pattern_parts.iter().enumerate() {
+        if let Some(name) = p.strip_prefix('*') {
+            let rest = decoded.get(i..).unwrap_or_default().join("/");
+            params.insert(name.to_string(), rest);
+            return Some(params);
+        }
+        let Some(actual) = path_parts.get(i) else {
+            if p.starts_with(':') && p.ends_with('?') {
+                continue;
+            }
             return None;
+        };
+        if let Some(spec) = p.strip_prefix(':') {
+            let spec = spec.trim_end_matches('?');
+            // `:id<i64>` only matches segments parsing as the type
+            let (spec, segment_type) = match spec.split_once('<') {
+                Some((name, ty)) => (name, ty.strip_suffix('>')),
+                None => (spec, None),
+            };
+            let value = &decoded[i];
+            if let Some(ty) = segment_type
+                && !segment_matches_type(ty, value)
+            {
+                return None;
+            }
+            let (name, constraint) = spec.split_once('|').unwrap_or((spec, ""));
+            let value = match constraint {
+                "sqid" => crate::obfuscate::decode_id(value)?.to_string(),
+                _ => value.clone(),
+            };
+            params.insert(name.to_string(), value);
+        } else if p != actual {
+            return None;
+        }
+    }
+    (path_parts.len() <= pattern_parts.len()).then_some(params)
+}
+
+/// Whether a path segment is a valid value of a `:name<type>` segment type.
+///
+/// Unknown types accept any value.
+pub(crate) fn segment_matches_type(ty: &str, value: &str) -> bool {
+    match ty {
+        "i8" => value.parse::<i8>().is_ok(),
+        "i16" => value.parse::<i16>().is_ok(),
+        "i32" => value.parse::<i32>().is_ok(),
+        "i64" | "int" => value.parse::<i64>().is_ok(),
+        "u8" => value.parse::<u8>().is_ok(),
+        "u16" => value.parse::<u16>().is_ok(),
+        "u32" => value.parse::<u32>().is_ok(),
+        "u64" => value.parse::<u64>().is_ok(),
+        "usize" => value.parse::<usize>().is_ok(),
+        "f32" | "f64" | "float" => value.parse::<f64>().is_ok(),
+        "bool" => value.parse::<bool>().is_ok(),
+        "uuid" => {
+            let groups: Vec<_> = value.split('-').map(str::len).collect();
+            groups == [8, 4, 4, 4, 12] && value.chars().all(|c| c == '-' || c.is_ascii_hexdigit())
+        }
+        _ => true,
+    }
+}
+
+/// Returns up to `max` route patterns that nearly match `path`, closest first.
+///
+/// Distance is the Levenshtein distance summed over path segments; `:param`
+/// segments match anything and each missing or extra segment costs 3.
+pub fn suggest_routes(patterns: &[&str], path: &str, max: usize) -> Vec<String> {
+    let path_parts: Vec<_> = path.trim_matches('/').split('/').collect();
+    let mut scored: Vec<(usize, &str)> = patterns
+        .iter()
+        .filter_map(|pattern| {
+            let pattern_parts: Vec<_> = pattern.trim_matches('/').split('/').collect();
+            let mut distance = 3 * pattern_parts.len().abs_diff(path_parts.len());
+            for (p, actual) in pattern_parts.iter().zip(path_parts.iter()) {
+                if !p.starts_with(':') && !p.starts_with('*') {
+                    distance += levenshtein(p, actual);
+                }
+            }
+            (distance > 0 && distance <= 3).then_some((distance, *pattern))
+        })
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(max)
+        .map(|(_, p)| p.to_string())
+        .collect()
+}
+
+/// Classic edit distance between two strings (by chars).
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Parses an `application/x-www-form-urlencoded` string into key/value pairs.
+pub fn parse_urlencoded(input: &str) -> HashMap<String, String> {
+    input
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect()
+}
+
+/// Encodes every byte but RFC 3986 unreserved characters as `%XX`, for a
+/// path segment or query component.
+pub fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Whether two secrets are equal, comparing every byte whatever the first
+/// difference, so that the time taken does not reveal how much matched.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Decodes `%XX` escapes and `+` as space; invalid escapes are kept verbatim.
+pub fn percent_decode(input: &str) -> String {
+    decode_escapes(input, true)
+}
+
+/// Decodes the `%XX` escapes of a path segment, where `+` is a plain `+`.
+pub fn percent_decode_path(input: &str) -> String {
+    decode_escapes(input, false)
+}
+
+fn decode_escapes(input: &str, plus_as_space: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if plus_as_space => out.push(b' '),
+            // `from_str_radix` alone would also take a sign, as in `%+1`
+            b'%' if i + 2 < bytes.len()
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit() =>
+            {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                out.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            b => out.push(b),
         }
+        i += 1;
     }
-    Some(params)
+    String::from_utf8_lossy(&out).into_owned()
 }
 
 #[macro_export]
@@ -349,7 +2322,7 @@ macro_rules! route {
             $router.add_route(
                 stringify!($method),
                 $path,
-                Arc::new(|req| Box::pin($handler(req))),
+                $crate::router::handler($handler),
                 stringify!($handler)
             );
         )*
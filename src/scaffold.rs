@@ -0,0 +1,888 @@
+//! Cobalto project scaffolding
+//!
+//! The file trees written by `cobalto-admin startproject` and `startapp`
+//! (the `cli` feature builds the binary):
+//!
+//! ```text
+//! cobalto-admin startproject mysite      # mysite/Cargo.toml, settings.toml, src/, templates/, static/
+//! cd mysite && cobalto-admin startapp blog   # src/blog/{mod,models,handlers}.rs, src/blog/templates/blog/
+//! ```
+//!
+//! `startproject <name> --example blog` writes a complete site instead:
+//! posts with a migration, a password login, the admin, templates, new posts
+//! pushed over a WebSocket, and a `tests/` suite driving it all through
+//! `cobalto::test::Client`, so `cargo test` in the generated project also
+//! exercises the framework end to end.
+//!
+//! Generated models implement `orm::Model` by hand, so the projects build
+//! with the `cobalto` crate alone. Existing files are never overwritten.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Why a project or app could not be created.
+#[derive(Debug)]
+pub enum ScaffoldError {
+    /// Not usable as a crate or module name
+    InvalidName(String),
+    /// The project directory or app module already exists
+    Exists(PathBuf),
+    /// `startapp` outside a project (no `Cargo.toml` and `src/`)
+    NotAProject(PathBuf),
+    /// Not one of `EXAMPLES`
+    UnknownExample(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ScaffoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaffoldError::InvalidName(name) => write!(
+                f,
+                "'{}' is not a valid name: use lowercase letters, digits and underscores, starting with a letter",
+                name
+            ),
+            ScaffoldError::Exists(path) => write!(f, "{} already exists", path.display()),
+            ScaffoldError::NotAProject(path) => write!(
+                f,
+                "{} is not a Cobalto project (no Cargo.toml and src/)",
+                path.display()
+            ),
+            ScaffoldError::UnknownExample(name) => write!(
+                f,
+                "unknown example '{}' (available: {})",
+                name,
+                EXAMPLES.join(", ")
+            ),
+            ScaffoldError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScaffoldError {}
+
+impl From<std::io::Error> for ScaffoldError {
+    fn from(e: std::io::Error) -> Self {
+        ScaffoldError::Io(e)
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "cobalto", "test", "tests",
+];
+
+/// Whether `name` works as both a crate and a module name.
+pub fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
+
+const PROJECT_CARGO: &str = r#"[package]
+name = "$project"
+version = "0.1.0"
+edition = "2024"
+
+[dependencies]
+cobalto = "0.1"
+actix-web = "4"
+sqlx = { version = "0.8", features = ["any", "sqlite", "runtime-tokio-native-tls"] }
+"#;
+
+const PROJECT_SETTINGS: &str = r#"# Settings of $project. Every key can be overridden by a COBALTO_* environment
+# variable, e.g. COBALTO_PORT=9000 or COBALTO_TEMPLATE__DEBUG=false.
+debug = true
+host = "127.0.0.1"
+port = 8000
+static_dir = "static"
+static_url = "/static/"
+database_url = "$project.db"
+# Behind nginx, trust its X-Forwarded-* headers:
+# trusted_proxies = ["127.0.0.1"]
+
+[template]
+dir = "templates"
+debug = true
+
+[log]
+level = "info"
+"#;
+
+const PROJECT_GITIGNORE: &str = "/target\n*.db\n";
+
+const PROJECT_MAIN: &str = r#"mod handlers;
+mod models;
+
+use cobalto::router::Router;
+use cobalto::settings::Settings;
+use std::process::ExitCode;
+
+/// `cargo run -- runserver`, `migrate`, `routes` or `shell`.
+#[actix_web::main]
+async fn main() -> ExitCode {
+    let settings = match Settings::load("settings.toml") {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    cobalto::manage::run(settings, |settings| {
+        let mut router = Router::new(settings);
+        router.group("", |g| {
+            g.get("/", handlers::index).name("index");
+        });
+        // Apps created with `cobalto-admin startapp`:
+        // router.mount(blog::app());
+        router
+    })
+    .await
+}
+"#;
+
+const PROJECT_MODELS: &str = r#"use cobalto::orm::{Field, FieldType, Model, SqlValue};
+
+/// An example model: `Note::objects(&db).all().await`.
+#[derive(Debug, sqlx::FromRow)]
+pub struct Note {
+    pub id: i64,
+    pub title: String,
+    pub body: String,
+}
+
+impl Model for Note {
+    fn table_name() -> &'static str {
+        "note"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("title", FieldType::Text).max_length(200),
+            Field::new("body", FieldType::Text),
+        ]
+    }
+
+    fn values(&self) -> Vec<SqlValue> {
+        vec![self.id.into(), self.title.clone().into(), self.body.clone().into()]
+    }
+
+    fn set_primary_key(&mut self, id: i64) {
+        self.id = id;
+    }
+}
+"#;
+
+const PROJECT_HANDLERS: &str = r#"use cobalto::router::{Request, Response};
+use cobalto::template::TemplateValue;
+use std::collections::HashMap;
+
+pub async fn index(req: Request) -> Response {
+    let context = HashMap::from([(
+        "project".to_string(),
+        TemplateValue::String("$project".to_string()),
+    )]);
+    req.templates().render("index.html", &context)
+}
+"#;
+
+const PROJECT_BASE_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>{% block title %}$project{% endblock %}</title>
+  <link rel="stylesheet" href="{% static "style.css" %}">
+</head>
+<body>
+  {% block content %}{% endblock %}
+</body>
+</html>
+"#;
+
+const PROJECT_INDEX_HTML: &str = r#"{% extends "base.html" %}
+
+{% block content %}
+<h1>Welcome to {{ project }}</h1>
+<p>Edit <code>templates/index.html</code> to change this page.</p>
+{% endblock %}
+"#;
+
+const PROJECT_STYLE: &str = "body {\n  font-family: sans-serif;\n  margin: 2rem;\n}\n";
+
+const APP_MOD: &str = r#"pub mod handlers;
+pub mod models;
+
+use cobalto::app::App;
+
+/// The $app app: `router.mount($app::app())`.
+pub fn app() -> App {
+    let mut app = App::new("$app", "/$app");
+    app.routes(|g| {
+        g.get("/", handlers::index).name("index");
+    })
+    .templates("src/$app/templates")
+    .model::<models::$model>();
+    app
+}
+"#;
+
+const APP_MODELS: &str = r#"use cobalto::orm::{Field, FieldType, Model, SqlValue};
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct $model {
+    pub id: i64,
+    pub name: String,
+}
+
+impl Model for $model {
+    fn table_name() -> &'static str {
+        "$app_item"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("name", FieldType::Text).max_length(200),
+        ]
+    }
+
+    fn values(&self) -> Vec<SqlValue> {
+        vec![self.id.into(), self.name.clone().into()]
+    }
+
+    fn set_primary_key(&mut self, id: i64) {
+        self.id = id;
+    }
+}
+"#;
+
+const APP_HANDLERS: &str = r#"use cobalto::router::{Request, Response};
+use std::collections::HashMap;
+
+pub async fn index(req: Request) -> Response {
+    req.templates().render("$app/index.html", &HashMap::new())
+}
+"#;
+
+const APP_INDEX_HTML: &str = r#"{% extends "base.html" %}
+
+{% block content %}
+<h1>$app</h1>
+{% endblock %}
+"#;
+
+/// Example projects accepted by `start_example`.
+pub const EXAMPLES: &[&str] = &["blog"];
+
+const BLOG_CARGO: &str = r#"[package]
+name = "$project"
+version = "0.1.0"
+edition = "2024"
+
+[dependencies]
+cobalto = "0.1"
+actix-web = "4"
+serde = { version = "1", features = ["derive"] }
+serde_json = "1"
+sqlx = { version = "0.8", features = ["any", "sqlite", "runtime-tokio-native-tls"] }
+
+[dev-dependencies]
+tokio = { version = "1", features = ["macros", "rt-multi-thread"] }
+"#;
+
+const BLOG_SETTINGS: &str = r#"# Settings of $project. Every key can be overridden by a COBALTO_* environment
+# variable, e.g. COBALTO_PORT=9000 or COBALTO_ADMIN_PASSWORD=...
+debug = true
+host = "127.0.0.1"
+port = 8000
+static_dir = "static"
+static_url = "/static/"
+database_url = "$project.db"
+# Password of the admin account; logins are disabled while it is empty
+admin_password = ""
+
+[template]
+dir = "templates"
+debug = true
+
+[log]
+level = "info"
+"#;
+
+const BLOG_MAIN: &str = r#"use cobalto::orm::Db;
+use cobalto::settings::Settings;
+use std::process::ExitCode;
+
+/// `cargo run -- runserver`, `migrate`, `routes` or `shell`.
+#[actix_web::main]
+async fn main() -> ExitCode {
+    let settings = match Settings::load("settings.toml") {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let db = match Db::from_settings(&settings).await {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("database error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    cobalto::manage::run(settings, |settings| $project::router(settings, db)).await
+}
+"#;
+
+const BLOG_LIB: &str = r#"pub mod accounts;
+pub mod blog;
+
+use cobalto::admin::Admin;
+use cobalto::orm::Db;
+use cobalto::router::Router;
+use cobalto::session::Sessions;
+use cobalto::settings::Settings;
+
+/// The whole site; `main` and the tests build it the same way.
+pub fn router(settings: Settings, db: Db) -> Router {
+    let password = settings.get_or("admin_password", String::new());
+    let mut router = Router::new(settings);
+    router.manage(db);
+    router.manage(accounts::AdminPassword(password));
+    router.use_sessions(Sessions::memory());
+    router.group("", accounts::routes);
+    router.mount(blog::app());
+    router.add_websocket("/ws/posts", blog::notifications::socket());
+    // Staff sessions (see `accounts::login`) may use the admin
+    Admin::new("/admin")
+        .register::<blog::models::Post>()
+        .mount(&mut router);
+    router
+}
+"#;
+
+const BLOG_ACCOUNTS: &str = r#"use cobalto::router::{
+    Request, Response, RouteGroup, constant_time_eq, parse_urlencoded,
+};
+use cobalto::template::TemplateValue;
+use std::collections::HashMap;
+
+/// Password of the admin account, from the `admin_password` setting; empty
+/// disables logins.
+#[derive(Clone)]
+pub struct AdminPassword(pub String);
+
+pub fn routes(g: &mut RouteGroup) {
+    g.get("/login", login_form).name("login");
+    g.post("/login", login);
+    g.post("/logout", logout).name("logout");
+}
+
+/// Whether the request comes from the logged-in admin.
+pub fn is_logged_in(req: &Request) -> bool {
+    req.session().and_then(|s| s.get("user")).is_some()
+}
+
+pub async fn login_form(req: Request) -> Response {
+    render_login(&req, "")
+}
+
+pub async fn login(req: Request) -> Response {
+    let form = parse_urlencoded(&req.body);
+    let expected = req.state::<AdminPassword>().map(|p| p.0).unwrap_or_default();
+    let given = form.get("password").map(String::as_str).unwrap_or("");
+    // Compared in constant time, so response times don't leak the password
+    if expected.is_empty() || !constant_time_eq(given, &expected) {
+        return render_login(&req, "Wrong password.").with_status(401);
+    }
+    if let Some(session) = req.session() {
+        session.cycle_id();
+        session.set("user", "admin");
+        session.set("is_staff", "true");
+    }
+    Response::redirect("/")
+}
+
+pub async fn logout(req: Request) -> Response {
+    if let Some(session) = req.session() {
+        session.destroy();
+    }
+    Response::redirect("/")
+}
+
+fn render_login(req: &Request, error: &str) -> Response {
+    let context = HashMap::from([(
+        "error".to_string(),
+        TemplateValue::String(error.to_string()),
+    )]);
+    req.templates().render("login.html", &context)
+}
+"#;
+
+const BLOG_MOD: &str = r#"pub mod handlers;
+pub mod models;
+pub mod notifications;
+
+use cobalto::app::App;
+
+/// The blog: posts listed at `/`, written by the logged-in admin.
+pub fn app() -> App {
+    notifications::connect();
+    let mut app = App::new("blog", "/");
+    app.routes(|g| {
+        g.get("/", handlers::index).name("index");
+        g.get("/new", handlers::new_post).name("new");
+        g.post("/new", handlers::create_post);
+        g.get("/posts/:id<i64>", handlers::post).name("post");
+    })
+    .templates("src/blog/templates")
+    .model::<models::Post>()
+    .migration(
+        "0001_welcome_post",
+        "INSERT INTO post (title, body) VALUES ('Welcome', 'The first post of $project.')",
+    );
+    app
+}
+"#;
+
+const BLOG_MODELS: &str = r#"use cobalto::orm::{Field, FieldType, Model, SqlValue};
+use serde::Serialize;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Post {
+    pub id: i64,
+    pub title: String,
+    pub body: String,
+}
+
+impl Model for Post {
+    fn table_name() -> &'static str {
+        "post"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("title", FieldType::Text).max_length(200),
+            Field::new("body", FieldType::Text),
+        ]
+    }
+
+    fn values(&self) -> Vec<SqlValue> {
+        vec![self.id.into(), self.title.clone().into(), self.body.clone().into()]
+    }
+
+    fn set_primary_key(&mut self, id: i64) {
+        self.id = id;
+    }
+}
+"#;
+
+const BLOG_HANDLERS: &str = r#"use super::models::Post;
+use crate::accounts::is_logged_in;
+use cobalto::orm::{Db, Model};
+use cobalto::router::{Request, Response, parse_urlencoded, reverse};
+use cobalto::template::TemplateValue;
+use std::collections::HashMap;
+
+fn db(req: &Request) -> Db {
+    req.state::<Db>().expect("the router manages a Db")
+}
+
+pub async fn index(req: Request) -> Result<Response, sqlx::Error> {
+    let posts = Post::objects(&db(&req)).order_by("-id").all().await?;
+    let context = HashMap::from([
+        ("posts".to_string(), TemplateValue::from_serialize(&posts)),
+        (
+            "logged_in".to_string(),
+            TemplateValue::Bool(is_logged_in(&req)),
+        ),
+    ]);
+    Ok(req.templates().render("blog/index.html", &context))
+}
+
+pub async fn post(req: Request) -> Result<Response, sqlx::Error> {
+    // The route only matches integer ids
+    let id: i64 = req.param("id").unwrap_or_default();
+    let Some(post) = Post::get(&db(&req), id).await? else {
+        return Ok(Response::html("No such post").with_status(404));
+    };
+    let context = HashMap::from([("post".to_string(), TemplateValue::from_serialize(&post))]);
+    Ok(req.templates().render("blog/post.html", &context))
+}
+
+pub async fn new_post(req: Request) -> Response {
+    if !is_logged_in(&req) {
+        return Response::redirect("/login");
+    }
+    render_form(&req, "", "", "")
+}
+
+pub async fn create_post(req: Request) -> Result<Response, sqlx::Error> {
+    if !is_logged_in(&req) {
+        return Ok(Response::redirect("/login"));
+    }
+    let form = parse_urlencoded(&req.body);
+    let field = |name: &str| form.get(name).map(|v| v.trim().to_string()).unwrap_or_default();
+    let (title, body) = (field("title"), field("body"));
+    if title.is_empty() || title.len() > 200 {
+        return Ok(render_form(&req, &title, &body, "A title of 1 to 200 characters is required.")
+            .with_status(400));
+    }
+    let mut post = Post { id: 0, title, body };
+    post.save(&db(&req)).await?;
+    let url = reverse("blog:post", &[("id", &post.id.to_string())]).unwrap_or_default();
+    Ok(Response::redirect(&url))
+}
+
+fn render_form(req: &Request, title: &str, body: &str, error: &str) -> Response {
+    let context = HashMap::from([
+        ("title".to_string(), TemplateValue::String(title.to_string())),
+        ("body".to_string(), TemplateValue::String(body.to_string())),
+        ("error".to_string(), TemplateValue::String(error.to_string())),
+    ]);
+    req.templates().render("blog/new.html", &context)
+}
+"#;
+
+const BLOG_NOTIFICATIONS: &str = r#"use super::models::Post;
+use cobalto::channels;
+use cobalto::signals::{self, Signal};
+use cobalto::websocket::{WebSocket, WsContext, WsHandler};
+use std::sync::{Arc, Once};
+
+/// Channel group told about every new post.
+pub const GROUP: &str = "posts";
+
+/// Announce new posts to `GROUP` as JSON (`{"id": 1, "title": "..."}`).
+pub fn connect() {
+    static CONNECTED: Once = Once::new();
+    CONNECTED.call_once(|| {
+        signals::connect::<Post, _>(Signal::PostSave, |post, event| {
+            if event.created {
+                let message = serde_json::json!({"id": post.id, "title": post.title});
+                channels::group(GROUP).broadcast(message.to_string());
+            }
+        });
+    });
+}
+
+/// `/ws/posts`: pushes the posts published while the page is open.
+pub fn socket() -> WsHandler {
+    Arc::new(|_ctx: WsContext, ws: WebSocket| {
+        Box::pin(async move {
+            let mut connection = channels::connect();
+            connection.subscribe(GROUP);
+            while let Some(message) = connection.recv().await {
+                if ws.send_text(message).await.is_err() {
+                    break;
+                }
+            }
+        })
+    })
+}
+"#;
+
+const BLOG_BASE_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>{% block title %}$project{% endblock %}</title>
+  <link rel="stylesheet" href="{% static "style.css" %}">
+</head>
+<body>
+  <nav>
+    <a href="{% url "blog:index" %}">$project</a>
+    <a href="{% url "login" %}">Log in</a>
+    <form method="post" action="{% url "logout" %}"><button>Log out</button></form>
+  </nav>
+  <p id="news" hidden></p>
+  {% block content %}{% endblock %}
+  <script>
+    const socket = new WebSocket((location.protocol === "https:" ? "wss://" : "ws://") + location.host + "/ws/posts");
+    socket.onmessage = (event) => {
+      const post = JSON.parse(event.data);
+      const news = document.getElementById("news");
+      news.textContent = "New post: " + post.title;
+      news.hidden = false;
+    };
+  </script>
+</body>
+</html>
+"#;
+
+const BLOG_LOGIN_HTML: &str = r#"{% extends "base.html" %}
+
+{% block content %}
+<h1>Log in</h1>
+{% if error %}<p class="error">{{ error }}</p>{% endif %}
+<form method="post">
+  <input type="password" name="password" placeholder="Password" autofocus>
+  <button type="submit">Log in</button>
+</form>
+{% endblock %}
+"#;
+
+const BLOG_INDEX_HTML: &str = r#"{% extends "base.html" %}
+
+{% block content %}
+<h1>Posts</h1>
+{% if logged_in %}<p><a href="{% url "blog:new" %}">Write a post</a> · <a href="/admin/">Admin</a></p>{% endif %}
+<ul>
+{% for post in posts %}
+  <li><a href="{% url "blog:post" id=post.id %}">{{ post.title }}</a></li>
+{% endfor %}
+</ul>
+{% endblock %}
+"#;
+
+const BLOG_POST_HTML: &str = r#"{% extends "base.html" %}
+
+{% block title %}{{ post.title }}{% endblock %}
+
+{% block content %}
+<article>
+  <h1>{{ post.title }}</h1>
+  <p>{{ post.body }}</p>
+</article>
+<p><a href="{% url "blog:index" %}">All posts</a></p>
+{% endblock %}
+"#;
+
+const BLOG_NEW_HTML: &str = r#"{% extends "base.html" %}
+
+{% block content %}
+<h1>New post</h1>
+{% if error %}<p class="error">{{ error }}</p>{% endif %}
+<form method="post">
+  <input name="title" value="{{ title }}" placeholder="Title" maxlength="200">
+  <textarea name="body" placeholder="Write something">{{ body }}</textarea>
+  <button type="submit">Publish</button>
+</form>
+{% endblock %}
+"#;
+
+const BLOG_TESTS: &str = r#"use cobalto::channels;
+use cobalto::orm::{Db, Model};
+use cobalto::settings::Settings;
+use cobalto::test::Client;
+use cobalto::websocket::WsMessage;
+use $project::blog::models::Post;
+
+async fn client() -> (Client, Db) {
+    let mut settings = Settings::load("settings.toml").unwrap();
+    settings.set("admin_password", "secret").unwrap();
+    let db = Db::connect(":memory:").await.unwrap();
+    let router = $project::router(settings, db.clone());
+    router.migrate(&db).await.unwrap();
+    (Client::new(router), db)
+}
+
+#[tokio::test]
+async fn test_home_lists_the_welcome_post() {
+    let (client, _db) = client().await;
+    let response = client.get("/").send().await;
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains("Welcome"));
+    assert!(client.get("/posts/1").send().await.body.contains("The first post"));
+    assert_eq!(client.get("/posts/99").send().await.status, 404);
+}
+
+#[tokio::test]
+async fn test_writing_needs_a_login() {
+    let (client, _db) = client().await;
+    let response = client.get("/new").send().await;
+    assert_eq!(response.headers["Location"], "/login");
+
+    let wrong = client.post("/login").form(&[("password", "nope")]).send().await;
+    assert_eq!(wrong.status, 401);
+    let login = client.post("/login").form(&[("password", "secret")]).send().await;
+    assert_eq!(login.status, 302);
+
+    let invalid = client.post("/new").form(&[("title", ""), ("body", "x")]).send().await;
+    assert_eq!(invalid.status, 400);
+    let created = client
+        .post("/new")
+        .form(&[("title", "Second"), ("body", "More news")])
+        .send()
+        .await;
+    assert_eq!(created.headers["Location"], "/posts/2");
+    assert!(client.get("/").send().await.body.contains("Second"));
+
+    client.post("/logout").send().await;
+    assert_eq!(client.get("/new").send().await.status, 302);
+}
+
+#[tokio::test]
+async fn test_admin_is_for_the_logged_in_admin() {
+    let (client, _db) = client().await;
+    assert_eq!(client.get("/admin/").send().await.status, 403);
+    client.post("/login").form(&[("password", "secret")]).send().await;
+    let list = client.get("/admin/post").send().await;
+    assert_eq!(list.status, 200);
+    assert!(list.body.contains("Welcome"));
+}
+
+#[tokio::test]
+async fn test_new_posts_are_pushed_to_websockets() {
+    let (client, db) = client().await;
+    let mut socket = client.router().connect_websocket("/ws/posts").unwrap();
+    while channels::group("posts").size() == 0 {
+        tokio::task::yield_now().await;
+    }
+    let mut post = Post {
+        id: 0,
+        title: "Live".into(),
+        body: String::new(),
+    };
+    post.save(&db).await.unwrap();
+    // Posts created by the other tests are announced too
+    loop {
+        match socket.recv().await {
+            Some(WsMessage::Text(message)) if message.contains("\"title\":\"Live\"") => break,
+            Some(_) => continue,
+            None => panic!("socket closed before the post was announced"),
+        }
+    }
+}
+"#;
+
+/// `snake_case` to `CamelCase`.
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Write `files` (relative to `root`, with `$` placeholders filled in).
+fn write_files(
+    root: &Path,
+    files: &[(&str, &str)],
+    vars: &[(&str, &str)],
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let mut written = Vec::new();
+    for (relative, content) in files {
+        let path = root.join(relative);
+        if path.exists() {
+            return Err(ScaffoldError::Exists(path));
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut content = content.to_string();
+        for (name, value) in vars {
+            content = content.replace(name, value);
+        }
+        std::fs::write(&path, content)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Create project `name` in a new directory under `parent`. Returns the
+/// written files.
+pub fn start_project(name: &str, parent: &Path) -> Result<Vec<PathBuf>, ScaffoldError> {
+    if !valid_name(name) {
+        return Err(ScaffoldError::InvalidName(name.to_string()));
+    }
+    let root = parent.join(name);
+    if root.exists() {
+        return Err(ScaffoldError::Exists(root));
+    }
+    write_files(
+        &root,
+        &[
+            ("Cargo.toml", PROJECT_CARGO),
+            ("settings.toml", PROJECT_SETTINGS),
+            (".gitignore", PROJECT_GITIGNORE),
+            ("src/main.rs", PROJECT_MAIN),
+            ("src/models.rs", PROJECT_MODELS),
+            ("src/handlers.rs", PROJECT_HANDLERS),
+            ("templates/base.html", PROJECT_BASE_HTML),
+            ("templates/index.html", PROJECT_INDEX_HTML),
+            ("static/style.css", PROJECT_STYLE),
+        ],
+        &[("$project", name)],
+    )
+}
+
+/// Create project `name` from example `example` (see `EXAMPLES`) in a new
+/// directory under `parent`. Returns the written files.
+pub fn start_example(
+    example: &str,
+    name: &str,
+    parent: &Path,
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    if !EXAMPLES.contains(&example) {
+        return Err(ScaffoldError::UnknownExample(example.to_string()));
+    }
+    if !valid_name(name) {
+        return Err(ScaffoldError::InvalidName(name.to_string()));
+    }
+    let root = parent.join(name);
+    if root.exists() {
+        return Err(ScaffoldError::Exists(root));
+    }
+    write_files(
+        &root,
+        &[
+            ("Cargo.toml", BLOG_CARGO),
+            ("settings.toml", BLOG_SETTINGS),
+            (".gitignore", PROJECT_GITIGNORE),
+            ("src/main.rs", BLOG_MAIN),
+            ("src/lib.rs", BLOG_LIB),
+            ("src/accounts.rs", BLOG_ACCOUNTS),
+            ("src/blog/mod.rs", BLOG_MOD),
+            ("src/blog/models.rs", BLOG_MODELS),
+            ("src/blog/handlers.rs", BLOG_HANDLERS),
+            ("src/blog/notifications.rs", BLOG_NOTIFICATIONS),
+            ("src/blog/templates/blog/index.html", BLOG_INDEX_HTML),
+            ("src/blog/templates/blog/post.html", BLOG_POST_HTML),
+            ("src/blog/templates/blog/new.html", BLOG_NEW_HTML),
+            ("templates/base.html", BLOG_BASE_HTML),
+            ("templates/login.html", BLOG_LOGIN_HTML),
+            ("static/style.css", PROJECT_STYLE),
+            ("tests/blog_tests.rs", BLOG_TESTS),
+        ],
+        &[("$project", name)],
+    )
+}
+
+/// Add app `name` to the project at `project`: a module in `src/<name>/`
+/// with its own templates, to be declared in `main.rs` and mounted. Returns
+/// the written files.
+pub fn start_app(name: &str, project: &Path) -> Result<Vec<PathBuf>, ScaffoldError> {
+    if !valid_name(name) {
+        return Err(ScaffoldError::InvalidName(name.to_string()));
+    }
+    if !project.join("Cargo.toml").is_file() || !project.join("src").is_dir() {
+        return Err(ScaffoldError::NotAProject(project.to_path_buf()));
+    }
+    let root = project.join("src").join(name);
+    if root.exists() || project.join("src").join(format!("{}.rs", name)).exists() {
+        return Err(ScaffoldError::Exists(root));
+    }
+    let model = format!("{}Item", camel_case(name));
+    let templates = format!("templates/{}/index.html", name);
+    write_files(
+        &root,
+        &[
+            ("mod.rs", APP_MOD),
+            ("models.rs", APP_MODELS),
+            ("handlers.rs", APP_HANDLERS),
+            (&templates, APP_INDEX_HTML),
+        ],
+        &[("$model", &model), ("$app", name)],
+    )
+}
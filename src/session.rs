@@ -0,0 +1,403 @@
+//! Cobalto sessions
+//!
+//! Server-side sessions keyed by a random id carried in a cookie. Handlers read
+//! and write the current session through `req.session()`; the router loads it
+//! before the middleware chain runs and, when it was modified, saves it back to
+//! the configured `SessionStore` and issues the `Set-Cookie` header.
+
+use crate::clock;
+use crate::orm::{Backend, Db, SqlValue};
+use crate::router::{Request, Response};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Backend holding session data between requests.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Data for a live session, `None` when unknown or expired.
+    async fn load(&self, id: &str) -> Option<HashMap<String, String>>;
+    /// Create or overwrite a session, expiring `ttl` from now.
+    async fn save(&self, id: &str, data: &HashMap<String, String>, ttl: Duration);
+    /// Forget a session.
+    async fn destroy(&self, id: &str);
+}
+
+/// Process-local store, fine for development and single-instance deployments.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    sessions: Arc<Mutex<HashMap<String, (HashMap<String, String>, i64)>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load(&self, id: &str) -> Option<HashMap<String, String>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(id) {
+            Some((data, expires_at)) if *expires_at > clock::now().timestamp() => {
+                Some(data.clone())
+            }
+            Some(_) => {
+                sessions.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn save(&self, id: &str, data: &HashMap<String, String>, ttl: Duration) {
+        let expires_at = clock::now().timestamp() + ttl.as_secs() as i64;
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), (data.clone(), expires_at));
+    }
+
+    async fn destroy(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+/// Store backed by the `cobalto_sessions` table of a `Db`.
+#[derive(Clone)]
+pub struct DbStore {
+    db: Db,
+}
+
+impl DbStore {
+    pub fn new(db: Db) -> Self {
+        DbStore { db }
+    }
+
+    /// Create the sessions table if it doesn't exist yet.
+    pub async fn create_table(&self) -> Result<(), sqlx::Error> {
+        self.db
+            .execute(
+                "CREATE TABLE IF NOT EXISTS cobalto_sessions (\
+                 id VARCHAR(128) PRIMARY KEY, data TEXT NOT NULL, expires_at BIGINT NOT NULL)",
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Delete expired sessions, returning how many were removed.
+    pub async fn purge_expired(&self) -> Result<u64, sqlx::Error> {
+        self.db
+            .execute_with(
+                "DELETE FROM cobalto_sessions WHERE expires_at <= ?",
+                vec![SqlValue::Int(clock::now().timestamp())],
+            )
+            .await
+    }
+}
+
+#[async_trait]
+impl SessionStore for DbStore {
+    async fn load(&self, id: &str) -> Option<HashMap<String, String>> {
+        let sql = self
+            .db
+            .backend()
+            .placeholders("SELECT data FROM cobalto_sessions WHERE id = ? AND expires_at > ?");
+        let row: Option<(String,)> = sqlx::query_as(&sql)
+            .bind(id)
+            .bind(clock::now().timestamp())
+            .fetch_optional(&self.db.pool)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("session load failed: {e}");
+                None
+            });
+        row.and_then(|(data,)| serde_json::from_str(&data).ok())
+    }
+
+    async fn save(&self, id: &str, data: &HashMap<String, String>, ttl: Duration) {
+        let expires_at = clock::now().timestamp() + ttl.as_secs() as i64;
+        let data = serde_json::to_string(data).unwrap_or_else(|_| "{}".to_string());
+        let sql = match self.db.backend() {
+            Backend::MySql => {
+                "INSERT INTO cobalto_sessions (id, data, expires_at) VALUES (?, ?, ?) \
+                 ON DUPLICATE KEY UPDATE data = VALUES(data), expires_at = VALUES(expires_at)"
+            }
+            _ => {
+                "INSERT INTO cobalto_sessions (id, data, expires_at) VALUES (?, ?, ?) \
+                 ON CONFLICT(id) DO UPDATE SET data = excluded.data, expires_at = excluded.expires_at"
+            }
+        };
+        if let Err(e) = self
+            .db
+            .execute_with(sql, vec![id.into(), data.into(), SqlValue::Int(expires_at)])
+            .await
+        {
+            log::warn!("session save failed: {e}");
+        }
+    }
+
+    async fn destroy(&self, id: &str) {
+        if let Err(e) = self
+            .db
+            .execute_with("DELETE FROM cobalto_sessions WHERE id = ?", vec![id.into()])
+            .await
+        {
+            log::warn!("session delete failed: {e}");
+        }
+    }
+}
+
+#[derive(Default)]
+struct SessionState {
+    id: Option<String>,
+    previous_id: Option<String>,
+    values: HashMap<String, String>,
+    modified: bool,
+    destroyed: bool,
+}
+
+/// The session of the current request (cheap to clone, clones share state).
+#[derive(Clone, Default)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl Session {
+    /// A fresh, empty session without an id.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn existing(id: String, values: HashMap<String, String>) -> Self {
+        Session {
+            state: Arc::new(Mutex::new(SessionState {
+                id: Some(id),
+                values,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Session id, `None` until the session is first saved.
+    pub fn id(&self) -> Option<String> {
+        self.state.lock().unwrap().id.clone()
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.state.lock().unwrap().values.get(key).cloned()
+    }
+
+    pub fn set<K: Into<String>, V: Into<String>>(&self, key: K, value: V) {
+        let mut state = self.state.lock().unwrap();
+        state.values.insert(key.into(), value.into());
+        state.modified = true;
+    }
+
+    /// Remove a key, returning its old value.
+    pub fn delete(&self, key: &str) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let old = state.values.remove(key);
+        state.modified |= old.is_some();
+        old
+    }
+
+    /// Snapshot of every key.
+    pub fn to_map(&self) -> HashMap<String, String> {
+        self.state.lock().unwrap().values.clone()
+    }
+
+    /// Replace every key with `values`.
+    pub fn replace(&self, values: HashMap<String, String>) {
+        let mut state = self.state.lock().unwrap();
+        state.values = values;
+        state.modified = true;
+    }
+
+    /// Drop all data and delete the session from the store (logout).
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.values.clear();
+        state.destroyed = true;
+    }
+
+    /// Keep the data under a new id (call on login to prevent session fixation).
+    pub fn cycle_id(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.previous_id.is_none() {
+            state.previous_id = state.id.take();
+        }
+        state.id = None;
+        state.modified = true;
+    }
+
+    pub fn is_modified(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.modified || state.destroyed
+    }
+}
+
+/// Session cookie settings.
+#[derive(Clone, Debug)]
+pub struct SessionConfig {
+    pub cookie_name: String,
+    pub ttl: Duration,
+    pub secure: bool,
+    pub same_site: String,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            cookie_name: "cobalto_session".to_string(),
+            ttl: Duration::from_secs(14 * 24 * 60 * 60),
+            secure: false,
+            same_site: "Lax".to_string(),
+        }
+    }
+}
+
+/// Loads and saves sessions for the router (see `Router::use_sessions`).
+#[derive(Clone)]
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    config: SessionConfig,
+}
+
+impl Sessions {
+    pub fn new<S: SessionStore + 'static>(store: S) -> Self {
+        Sessions {
+            store: Arc::new(store),
+            config: SessionConfig::default(),
+        }
+    }
+
+    /// Sessions kept in a `MemoryStore`.
+    pub fn memory() -> Self {
+        Self::new(MemoryStore::new())
+    }
+
+    pub fn with_config(mut self, config: SessionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Session named by the cookie in a `Cookie` header, or a fresh one.
+    pub async fn load(&self, cookie_header: Option<&str>) -> Session {
+        let id = cookie_header.and_then(|h| cookie_value(h, &self.config.cookie_name));
+        if let Some(id) = id {
+            if let Some(values) = self.store.load(&id).await {
+                return Session::existing(id, values);
+            }
+        }
+        Session::new()
+    }
+
+    /// Persist a modified session, returning the `Set-Cookie` value to send.
+    pub async fn commit(&self, session: &Session) -> Option<String> {
+        let (id, values, previous, destroyed) = {
+            let mut state = session.state.lock().unwrap();
+            if !state.modified && !state.destroyed {
+                return None;
+            }
+            if state.destroyed {
+                (
+                    state.id.take(),
+                    HashMap::new(),
+                    state.previous_id.take(),
+                    true,
+                )
+            } else {
+                let id = state.id.get_or_insert_with(new_session_id).clone();
+                state.modified = false;
+                (
+                    Some(id),
+                    state.values.clone(),
+                    state.previous_id.take(),
+                    false,
+                )
+            }
+        };
+        if let Some(previous) = previous {
+            self.store.destroy(&previous).await;
+        }
+        if destroyed {
+            if let Some(id) = id {
+                self.store.destroy(&id).await;
+            }
+            return Some(self.cookie("", Duration::ZERO));
+        }
+        let id = id?;
+        self.store.save(&id, &values, self.config.ttl).await;
+        Some(self.cookie(&id, self.config.ttl))
+    }
+
+    fn cookie(&self, value: &str, max_age: Duration) -> String {
+        let mut cookie = format!(
+            "{}={}; Path=/; HttpOnly; SameSite={}; Max-Age={}",
+            self.config.cookie_name,
+            value,
+            self.config.same_site,
+            max_age.as_secs()
+        );
+        let https = crate::router::current_request().is_some_and(|r| r.forwarded.scheme == "https");
+        if self.config.secure || https {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    /// Run `fut` with the session from `cookie_header` as the current one,
+    /// adding `Set-Cookie` to its response when the session changed.
+    pub async fn scope<F>(&self, cookie_header: Option<&str>, fut: F) -> Response
+    where
+        F: Future<Output = Response>,
+    {
+        let session = self.load(cookie_header).await;
+        let response = CURRENT_SESSION.scope(session.clone(), fut).await;
+        match self.commit(&session).await {
+            Some(cookie) => response.append_header("Set-Cookie", &cookie),
+            None => response,
+        }
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_SESSION: Session;
+}
+
+/// The session of the request currently being handled, if sessions are enabled.
+pub fn current_session() -> Option<Session> {
+    CURRENT_SESSION.try_with(|session| session.clone()).ok()
+}
+
+/// Run `f` with `session` as the current session, outside async code.
+pub(crate) fn with_session_sync<R>(session: Session, f: impl FnOnce() -> R) -> R {
+    CURRENT_SESSION.sync_scope(session, f)
+}
+
+impl Request {
+    /// The session of this request, if the router has sessions enabled.
+    pub fn session(&self) -> Option<Session> {
+        current_session()
+    }
+}
+
+fn cookie_value(header: &str, name: &str) -> Option<String> {
+    header.split(';').find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+        (key == name && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+fn new_session_id() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
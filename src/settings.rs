@@ -1,17 +1,490 @@
+use crate::compress::CompressionSettings;
+use crate::i18n::I18nSettings;
+use crate::logging::LogSettings;
+use crate::mail::MailSettings;
+use crate::metrics::MonitoringSettings;
 use std::collections::HashMap;
 
 #[derive(Clone, Debug)]
 pub struct TemplateSettings {
     pub dir: String,
+    /// More directories searched, in order, for templates missing from `dir`
+    /// (`dirs = ["shared/templates"]`, or comma separated in the environment).
+    pub dirs: Vec<String>,
+    /// Skip the parsed-template cache and render undefined variables and
+    /// unknown tags as visible errors (see `TemplateEngine::strict`).
     pub debug: bool,
 }
 
+/// HTTPS: `[tls]` in the settings file, `COBALTO_TLS__CERT`, ...
+#[derive(Clone, Debug, Default)]
+pub struct TlsSettings {
+    /// PEM certificate chain; serving uses TLS when this and `key` are set.
+    pub cert: Option<String>,
+    /// PEM private key.
+    pub key: Option<String>,
+    /// Plain-HTTP port answering every request with a redirect to HTTPS.
+    pub redirect_port: Option<u16>,
+}
+
+impl TlsSettings {
+    pub fn enabled(&self) -> bool {
+        self.cert.is_some() && self.key.is_some()
+    }
+}
+
+/// HTTP server tuning: `[server]` in the settings file, `COBALTO_SERVER__KEEP_ALIVE`, ...
+///
+/// Unset values keep actix's defaults.
+#[derive(Clone, Debug, Default)]
+pub struct ServerSettings {
+    /// Seconds an idle connection is kept open; `0` disables keep-alive.
+    pub keep_alive: Option<u64>,
+    /// Seconds a client has to send the request head; `0` disables the limit.
+    pub client_request_timeout: Option<u64>,
+    /// Concurrent connections per worker.
+    pub max_connections: Option<usize>,
+    /// Accept HTTP/2 without TLS (h2c with prior knowledge). Over TLS,
+    /// HTTP/2 is always negotiated.
+    pub http2: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct Settings {
     pub debug: bool,
     pub host: String,
     pub port: u16,
     pub ws_port: u16,
+    /// Number of actix worker threads; `None` uses one per CPU core.
+    pub workers: Option<usize>,
+    /// Keep-alive, timeouts and connection limits, applied by `Router::run()`.
+    pub server: ServerSettings,
     pub template: TemplateSettings,
+    /// Log level and format, applied by `Router::new`.
+    pub log: LogSettings,
+    pub tls: TlsSettings,
+    /// Outgoing mail, applied by `Router::new`.
+    pub mail: MailSettings,
+    /// Default locale and catalog directory, applied by `Router::new`.
+    pub i18n: I18nSettings,
+    /// Response compression, applied by `Router::new`.
+    pub compression: CompressionSettings,
+    /// The `/healthz` and `/metrics` endpoints.
+    pub monitoring: MonitoringSettings,
+    /// Directory served at `static_url` by `Router::run()`.
+    pub static_dir: String,
+    /// URL prefix for static files, also used by the `{% static %}` tag.
+    pub static_url: String,
+    /// Largest accepted request body, uploads included, in bytes.
+    pub max_upload_bytes: usize,
+    /// Largest body `req.json()` parses, in bytes; larger ones are a 413.
+    pub max_json_bytes: usize,
+    /// Make `req.json()` answer 415 unless the content type is JSON.
+    pub strict_json: bool,
+    /// Body limits for single routes, by route name or handler name
+    /// (`[body_limits]` in the settings file).
+    pub body_limits: HashMap<String, usize>,
+    /// Proxies whose `X-Forwarded-*` headers are honored: addresses,
+    /// networks (`10.0.0.0/8`) or `*` (see `crate::proxy`).
+    pub trusted_proxies: Vec<String>,
     pub other: HashMap<String, String>, // Manteniamo eventuali future impostazioni
 }
+
+impl Default for TemplateSettings {
+    fn default() -> Self {
+        TemplateSettings {
+            dir: "templates".to_string(),
+            dirs: Vec::new(),
+            debug: false,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            debug: false,
+            host: "127.0.0.1".to_string(),
+            port: 8000,
+            ws_port: 8001,
+            workers: None,
+            server: ServerSettings::default(),
+            template: TemplateSettings::default(),
+            log: LogSettings::default(),
+            tls: TlsSettings::default(),
+            mail: MailSettings::default(),
+            i18n: I18nSettings::default(),
+            compression: CompressionSettings::default(),
+            monitoring: MonitoringSettings::default(),
+            static_dir: "static".to_string(),
+            static_url: "/static/".to_string(),
+            max_upload_bytes: 10 * 1024 * 1024,
+            max_json_bytes: 1024 * 1024,
+            strict_json: false,
+            body_limits: HashMap::new(),
+            trusted_proxies: Vec::new(),
+            other: HashMap::new(),
+        }
+    }
+}
+
+/// Prefix of environment variables read by `Settings::from_env`.
+pub const ENV_PREFIX: &str = "COBALTO_";
+
+/// Why settings could not be loaded.
+#[derive(Debug)]
+pub enum SettingsError {
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    Parse {
+        path: String,
+        message: String,
+    },
+    Invalid {
+        key: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::Io { path, source } => write!(f, "cannot read {}: {}", path, source),
+            SettingsError::Parse { path, message } => {
+                write!(f, "invalid TOML in {}: {}", path, message)
+            }
+            SettingsError::Invalid { key, value, reason } => {
+                write!(f, "invalid setting `{}` = {:?}: {}", key, value, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+fn invalid(key: &str, value: &str, reason: &str) -> SettingsError {
+    SettingsError::Invalid {
+        key: key.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, SettingsError> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(invalid(key, value, "expected true or false")),
+    }
+}
+
+/// A TOML array as flattened by `flatten_toml`, or a comma separated list.
+fn parse_list(value: &str) -> Vec<String> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value);
+    value
+        .split(',')
+        .map(|item| item.trim().trim_matches('"').trim())
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_seconds(key: &str, value: &str) -> Result<u64, SettingsError> {
+    value
+        .parse()
+        .map_err(|_| invalid(key, value, "expected a number of seconds"))
+}
+
+fn parse_port(key: &str, value: &str) -> Result<u16, SettingsError> {
+    match value.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(invalid(
+            key,
+            value,
+            "expected a port number between 1 and 65535",
+        )),
+    }
+}
+
+/// Flattens TOML tables into dotted keys with string values.
+fn flatten_toml(prefix: &str, table: &toml::Table, out: &mut Vec<(String, String)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            toml::Value::Table(inner) => flatten_toml(&key, inner, out),
+            toml::Value::String(s) => out.push((key, s.clone())),
+            other => out.push((key, other.to_string())),
+        }
+    }
+}
+
+impl Settings {
+    /// Defaults overridden by a TOML file.
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, SettingsError> {
+        Settings::default().merge_file(path)
+    }
+
+    /// Defaults overridden by `COBALTO_*` environment variables.
+    pub fn from_env() -> Result<Self, SettingsError> {
+        Settings::default().merge_env()
+    }
+
+    /// Defaults, then `path` if it exists, then the environment, validated.
+    /// Assign fields afterwards for explicit overrides.
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self, SettingsError> {
+        let mut settings = Settings::default();
+        if path.as_ref().exists() {
+            settings = settings.merge_file(path)?;
+        }
+        let settings = settings.merge_env()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Apply the keys of a TOML file: known fields (with `[server]`, `[template]`, `[log]`,
+    /// `[tls]`, `[mail]`, `[i18n]`, `[compression]` and `[monitoring]` tables), everything else into `other` under dotted keys (keys of an
+    /// `[other]` table keep their plain names).
+    pub fn merge_file<P: AsRef<std::path::Path>>(mut self, path: P) -> Result<Self, SettingsError> {
+        let display = path.as_ref().display().to_string();
+        let source = std::fs::read_to_string(&path).map_err(|source| SettingsError::Io {
+            path: display.clone(),
+            source,
+        })?;
+        let table: toml::Table =
+            source
+                .parse()
+                .map_err(|e: toml::de::Error| SettingsError::Parse {
+                    path: display,
+                    message: e.message().to_string(),
+                })?;
+        let mut pairs = Vec::new();
+        flatten_toml("", &table, &mut pairs);
+        for (key, value) in pairs {
+            self.set(&key, &value)?;
+        }
+        Ok(self)
+    }
+
+    /// Apply `COBALTO_*` environment variables (`COBALTO_PORT`, `COBALTO_TEMPLATE__DIR`).
+    pub fn merge_env(self) -> Result<Self, SettingsError> {
+        self.merge_vars(std::env::vars())
+    }
+
+    /// Apply `COBALTO_*` variables from `vars`; `__` separates nested keys.
+    pub fn merge_vars<I: IntoIterator<Item = (String, String)>>(
+        mut self,
+        vars: I,
+    ) -> Result<Self, SettingsError> {
+        for (name, value) in vars {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                let key = key.to_ascii_lowercase().replace("__", ".");
+                self.set(&key, &value)?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Set one setting from its string form, parsing typed fields.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        match key {
+            "debug" => self.debug = parse_bool(key, value)?,
+            "host" => self.host = value.to_string(),
+            "port" => self.port = parse_port(key, value)?,
+            "ws_port" => self.ws_port = parse_port(key, value)?,
+            "workers" | "server.workers" => {
+                self.workers = match value.parse::<usize>() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return Err(invalid(key, value, "expected a positive number of workers")),
+                }
+            }
+            "server.keep_alive" => self.server.keep_alive = Some(parse_seconds(key, value)?),
+            "server.client_request_timeout" => {
+                self.server.client_request_timeout = Some(parse_seconds(key, value)?)
+            }
+            "server.max_connections" => {
+                self.server.max_connections = match value.parse::<usize>() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => {
+                        return Err(invalid(
+                            key,
+                            value,
+                            "expected a positive number of connections",
+                        ));
+                    }
+                }
+            }
+            "server.http2" => self.server.http2 = parse_bool(key, value)?,
+            "static_dir" => self.static_dir = value.to_string(),
+            "static_url" => self.static_url = value.to_string(),
+            "max_upload_bytes" => {
+                self.max_upload_bytes = value
+                    .parse()
+                    .map_err(|_| invalid(key, value, "expected a size in bytes"))?
+            }
+            "max_json_bytes" => {
+                self.max_json_bytes = value
+                    .parse()
+                    .map_err(|_| invalid(key, value, "expected a size in bytes"))?
+            }
+            "strict_json" => self.strict_json = parse_bool(key, value)?,
+            "trusted_proxies" => {
+                let proxies = parse_list(value);
+                if let Some(bad) = proxies.iter().find(|p| !crate::proxy::is_valid_proxy(p)) {
+                    return Err(invalid(key, bad, "expected an IP address, a network or *"));
+                }
+                self.trusted_proxies = proxies;
+            }
+            "template.dir" => self.template.dir = value.to_string(),
+            "template.dirs" => self.template.dirs = parse_list(value),
+            "template.debug" => self.template.debug = parse_bool(key, value)?,
+            "log.level" => {
+                self.log.level = value.parse().map_err(|_| {
+                    invalid(
+                        key,
+                        value,
+                        "expected off, error, warn, info, debug or trace",
+                    )
+                })?
+            }
+            "log.format" => {
+                self.log.format = value
+                    .parse()
+                    .map_err(|_| invalid(key, value, "expected text or json"))?
+            }
+            "tls.cert" => self.tls.cert = Some(value.to_string()),
+            "tls.key" => self.tls.key = Some(value.to_string()),
+            "tls.redirect_port" => self.tls.redirect_port = Some(parse_port(key, value)?),
+            "mail.backend" => {
+                self.mail.backend = value
+                    .parse()
+                    .map_err(|_| invalid(key, value, "expected console, file, memory or smtp"))?
+            }
+            "mail.from" => self.mail.from = value.to_string(),
+            "mail.host" => self.mail.host = value.to_string(),
+            "mail.port" => self.mail.port = parse_port(key, value)?,
+            "mail.username" => self.mail.username = Some(value.to_string()),
+            "mail.password" => self.mail.password = Some(value.to_string()),
+            "mail.starttls" => self.mail.starttls = parse_bool(key, value)?,
+            "mail.file_dir" => self.mail.file_dir = value.to_string(),
+            "i18n.default_locale" => self.i18n.default_locale = value.to_string(),
+            "i18n.dir" => self.i18n.dir = value.to_string(),
+            "compression.enabled" => self.compression.enabled = parse_bool(key, value)?,
+            "monitoring.healthz" => self.monitoring.healthz = parse_bool(key, value)?,
+            "monitoring.healthz_path" => self.monitoring.healthz_path = value.to_string(),
+            "monitoring.metrics" => self.monitoring.metrics = parse_bool(key, value)?,
+            "monitoring.metrics_path" => self.monitoring.metrics_path = value.to_string(),
+            "compression.min_bytes" => {
+                self.compression.min_bytes = value
+                    .parse()
+                    .map_err(|_| invalid(key, value, "expected a size in bytes"))?
+            }
+            _ if key.starts_with("body_limits.") => {
+                let limit = value
+                    .parse()
+                    .map_err(|_| invalid(key, value, "expected a size in bytes"))?;
+                self.body_limits
+                    .insert(key["body_limits.".len()..].to_string(), limit);
+            }
+            _ => {
+                let key = key.strip_prefix("other.").unwrap_or(key);
+                self.other.insert(key.to_string(), value.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check values that can't be caught while parsing.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.host.trim().is_empty() {
+            return Err(invalid("host", &self.host, "must not be empty"));
+        }
+        if self.port == 0 {
+            return Err(invalid(
+                "port",
+                "0",
+                "expected a port number between 1 and 65535",
+            ));
+        }
+        if self.workers == Some(0) {
+            return Err(invalid(
+                "workers",
+                "0",
+                "expected a positive number of workers",
+            ));
+        }
+        if !self.static_url.starts_with('/') && !self.static_url.contains("://") {
+            return Err(invalid(
+                "static_url",
+                &self.static_url,
+                "must start with `/` or be an absolute URL",
+            ));
+        }
+        for (key, path) in [
+            ("monitoring.healthz_path", &self.monitoring.healthz_path),
+            ("monitoring.metrics_path", &self.monitoring.metrics_path),
+        ] {
+            if !path.starts_with('/') {
+                return Err(invalid(key, path, "must start with `/`"));
+            }
+        }
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            let (key, value) = match &self.tls.cert {
+                Some(cert) => ("tls.cert", cert.as_str()),
+                None => ("tls.key", self.tls.key.as_deref().unwrap_or_default()),
+            };
+            return Err(invalid(
+                key,
+                value,
+                "tls.cert and tls.key must be set together",
+            ));
+        }
+        if self.tls.redirect_port.is_some_and(|p| p == self.port) {
+            return Err(invalid(
+                "tls.redirect_port",
+                &self.port.to_string(),
+                "must differ from port",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Raw value of an `other` setting.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.other.get(key).map(|s| s.as_str())
+    }
+
+    /// Typed value of an `other` setting; `Ok(None)` when unset.
+    pub fn get<T: std::str::FromStr>(&self, key: &str) -> Result<Option<T>, SettingsError> {
+        match self.other.get(key) {
+            Some(value) => value.parse().map(Some).map_err(|_| {
+                invalid(
+                    key,
+                    value,
+                    &format!("expected {}", std::any::type_name::<T>()),
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Boolean `other` setting, accepting `true/false`, `yes/no`, `on/off`, `1/0`.
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, SettingsError> {
+        self.other.get(key).map(|v| parse_bool(key, v)).transpose()
+    }
+
+    /// Typed `other` setting, or `default` when unset or invalid.
+    pub fn get_or<T: std::str::FromStr>(&self, key: &str, default: T) -> T {
+        self.get(key).ok().flatten().unwrap_or(default)
+    }
+}
@@ -0,0 +1,101 @@
+//! Cobalto model signals
+//!
+//! Receivers subscribe to a model's lifecycle without touching the model:
+//!
+//! ```ignore
+//! signals::connect::<Post, _>(Signal::PostSave, |post, event| {
+//!     if event.created {
+//!         audit::record("post created", post.id);
+//!     }
+//!     cache::default_cache().invalidate_tag("posts");
+//! });
+//! ```
+//!
+//! `Model::save` sends `PreSave` and `PostSave`, `Model::delete` sends
+//! `PreDelete` and, when a row was removed, `PostDelete`. Bulk operations such
+//! as `QuerySet::delete` send nothing. Receivers run synchronously, in
+//! connection order; spawn a task for slow work.
+
+use crate::orm::Model;
+use once_cell::sync::Lazy;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// A point in a model's lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Signal {
+    PreSave,
+    PostSave,
+    PreDelete,
+    PostDelete,
+}
+
+/// What a receiver is told besides the instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub signal: Signal,
+    /// `PostSave` only: the row was inserted rather than updated.
+    pub created: bool,
+}
+
+/// Handle for `disconnect`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReceiverId(u64);
+
+type Receiver<M> = Arc<dyn Fn(&M, &Event) + Send + Sync>;
+
+/// Receivers by model type and signal; each stored as `Receiver<M>`.
+type Registry = HashMap<(TypeId, Signal), Vec<(ReceiverId, Arc<dyn Any + Send + Sync>)>>;
+
+static RECEIVERS: Lazy<RwLock<Registry>> = Lazy::new(|| RwLock::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Call `f` whenever `signal` is sent for an `M`.
+pub fn connect<M, F>(signal: Signal, f: F) -> ReceiverId
+where
+    M: Model,
+    F: Fn(&M, &Event) + Send + Sync + 'static,
+{
+    let id = ReceiverId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let receiver: Receiver<M> = Arc::new(f);
+    RECEIVERS
+        .write()
+        .unwrap()
+        .entry((TypeId::of::<M>(), signal))
+        .or_default()
+        .push((id, Arc::new(receiver)));
+    id
+}
+
+/// Remove a receiver; returns whether it was connected.
+pub fn disconnect(id: ReceiverId) -> bool {
+    let mut receivers = RECEIVERS.write().unwrap();
+    let mut found = false;
+    for list in receivers.values_mut() {
+        let before = list.len();
+        list.retain(|(rid, _)| *rid != id);
+        found |= list.len() != before;
+    }
+    found
+}
+
+/// Run the receivers of `signal` for `instance` (done by the ORM).
+pub fn send<M: Model>(signal: Signal, instance: &M, created: bool) {
+    // Clone the list so receivers may connect or disconnect
+    let receivers: Vec<Receiver<M>> = RECEIVERS
+        .read()
+        .unwrap()
+        .get(&(TypeId::of::<M>(), signal))
+        .map(|list| {
+            list.iter()
+                .filter_map(|(_, r)| r.downcast_ref::<Receiver<M>>().cloned())
+                .collect()
+        })
+        .unwrap_or_default();
+    let event = Event { signal, created };
+    for receiver in receivers {
+        receiver(instance, &event);
+    }
+}
@@ -0,0 +1,133 @@
+//! Cobalto Server-Sent Events
+//!
+//! `Response::sse(events)` streams `Event`s as `text/event-stream`, with a
+//! comment line sent every `KEEP_ALIVE` while no event is ready so proxies
+//! and browsers keep the connection open:
+//!
+//! ```ignore
+//! async fn ticks(_req: Request) -> Response {
+//!     let events = futures::stream::iter(1..=3)
+//!         .map(|n| Event::data(n.to_string()).event("tick").id(n.to_string()));
+//!     Response::sse(events)
+//! }
+//! ```
+//!
+//! In the browser, `new EventSource("/ticks").addEventListener("tick", ...)`.
+
+use crate::pubsub::Subscription;
+use crate::router::{BodyStream, Response};
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use std::fmt;
+use std::time::Duration;
+
+/// Interval of the keep-alive comments sent by `Response::sse`.
+pub const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// One server-sent event.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    pub event: Option<String>,
+    pub data: String,
+    pub id: Option<String>,
+    pub retry: Option<Duration>,
+}
+
+impl Event {
+    /// Unnamed event (a `message` in the browser) carrying `data`
+    pub fn data<S: Into<String>>(data: S) -> Self {
+        Event {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Event carrying `value` as JSON
+    pub fn json<T: serde::Serialize>(value: &T) -> Self {
+        Event::data(serde_json::to_string(value).unwrap_or_default())
+    }
+
+    /// Builder for the event name, for `addEventListener(name, ...)`
+    pub fn event(mut self, name: &str) -> Self {
+        self.event = Some(name.to_string());
+        self
+    }
+
+    /// Builder for the id the browser sends back as `Last-Event-ID` when
+    /// reconnecting
+    pub fn id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Builder for how long the browser waits before reconnecting
+    pub fn retry(mut self, after: Duration) -> Self {
+        self.retry = Some(after);
+        self
+    }
+}
+
+/// The event's wire format, ending with the blank line that dispatches it.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Line breaks would end a field early
+        let clean = |s: &str| s.replace(['\r', '\n'], " ");
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", clean(event))?;
+        }
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", clean(id))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        for line in self.data.split('\n') {
+            writeln!(f, "data: {}", line.strip_suffix('\r').unwrap_or(line))?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Messages of a pub/sub subscription as events named `event`, until the
+/// topic closes.
+pub fn subscription_events(subscription: Subscription, event: &str) -> impl Stream<Item = Event> {
+    let event = event.to_string();
+    futures::stream::unfold(subscription, move |mut subscription| {
+        let event = event.clone();
+        async move {
+            let message = subscription.recv().await?;
+            Some((Event::data(message).event(&event), subscription))
+        }
+    })
+}
+
+impl Response {
+    /// `events` streamed as `text/event-stream`, with keep-alive comments
+    /// every `KEEP_ALIVE`
+    pub fn sse<S>(events: S) -> Self
+    where
+        S: Stream<Item = Event> + Send + 'static,
+    {
+        Response::sse_with_keep_alive(events, KEEP_ALIVE)
+    }
+
+    /// `Response::sse` with keep-alive comments every `interval`
+    pub fn sse_with_keep_alive<S>(events: S, interval: Duration) -> Self
+    where
+        S: Stream<Item = Event> + Send + 'static,
+    {
+        let frames = futures::stream::unfold(Box::pin(events), move |mut events| async move {
+            let frame = tokio::select! {
+                event = events.next() => event?.to_string(),
+                _ = tokio::time::sleep(interval) => ": ping\n\n".to_string(),
+            };
+            Some((Ok::<_, std::io::Error>(Bytes::from(frame)), events))
+        });
+        let mut resp = Response::html("")
+            .add_header("Content-Type", "text/event-stream")
+            .add_header("Cache-Control", "no-cache")
+            .add_header("X-Accel-Buffering", "no");
+        resp.stream = Some(BodyStream::new(frames));
+        resp
+    }
+}
@@ -0,0 +1,87 @@
+//! Cobalto application state
+//!
+//! Values registered on the router with `Router::manage` (typically the `Db`
+//! pool) are shared by every request and looked up by type from handlers:
+//!
+//! ```ignore
+//! router.manage(Db::connect("app.db").await?);
+//! // in a handler
+//! let db = req.state::<Db>().expect("Db is managed");
+//! ```
+
+use crate::router::Request;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Type-keyed map of shared values (cheap to clone).
+#[derive(Clone, Default)]
+pub struct AppState {
+    values: Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `value`, replacing any previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        Arc::make_mut(&mut self.values).insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Shared handle to the value of type `T`, if registered.
+    pub fn get_arc<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|value| value.downcast::<T>().ok())
+    }
+
+    /// Clone of the value of type `T`, if registered.
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+            .cloned()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl std::fmt::Debug for AppState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AppState")
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
+
+tokio::task_local! {
+    static APP_STATE: AppState;
+}
+
+/// Run `fut` with `state` as the application state (used by the router and tests).
+pub async fn with_state<F: Future>(state: AppState, fut: F) -> F::Output {
+    APP_STATE.scope(state, fut).await
+}
+
+/// `with_state` for synchronous code.
+pub(crate) fn with_state_sync<R>(state: AppState, f: impl FnOnce() -> R) -> R {
+    APP_STATE.sync_scope(state, f)
+}
+
+/// The application state of the request currently being handled.
+pub fn current_state() -> Option<AppState> {
+    APP_STATE.try_with(|state| state.clone()).ok()
+}
+
+impl Request {
+    /// A value registered with `Router::manage`, e.g. `req.state::<Db>()`.
+    pub fn state<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.state.get::<T>()
+    }
+}
@@ -0,0 +1,193 @@
+//! Cobalto static files
+//!
+//! `Router::run()` serves everything under `Settings.static_dir` at
+//! `Settings.static_url`, with content types guessed from the extension, an
+//! `ETag` for conditional requests and single `Range` requests for media.
+//! Files are streamed from disk, ranges by seeking to their start.
+//! Templates link to those files with `{% static "css/app.css" %}`.
+
+use crate::router::Response;
+use crate::settings::Settings;
+use crate::template::{TagArgs, TemplateValue, escape_html};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+static STATIC_URL: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new("/static/".to_string()));
+
+/// Set the URL prefix used by the `static` tag (done by `Router::new`).
+pub fn set_static_url(url: &str) {
+    *STATIC_URL.write().unwrap() = normalize_url(url);
+}
+
+/// The URL prefix static files are served under, always ending in `/`.
+pub fn static_url() -> String {
+    STATIC_URL.read().unwrap().clone()
+}
+
+fn normalize_url(url: &str) -> String {
+    format!("/{}/", url.trim_matches('/')).replace("//", "/")
+}
+
+/// Renderer behind the built-in `static` tag.
+pub fn static_tag(args: &TagArgs, context: &HashMap<String, TemplateValue>) -> String {
+    match args.positional.first() {
+        Some(raw) => {
+            let path = TagArgs::resolve(raw, context);
+            escape_html(&format!("{}{}", static_url(), path.trim_start_matches('/')))
+        }
+        None => String::new(),
+    }
+}
+
+/// Content type for a file name, by extension.
+pub fn content_type(path: &str) -> &'static str {
+    let ext = path.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Serves files from a directory.
+#[derive(Clone, Debug)]
+pub struct StaticFiles {
+    pub dir: PathBuf,
+    pub url: String,
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(dir: P, url: &str) -> Self {
+        StaticFiles {
+            dir: dir.into(),
+            url: normalize_url(url),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::new(&settings.static_dir, &settings.static_url)
+    }
+
+    /// Actix route pattern matching every file under `url`.
+    pub fn route_pattern(&self) -> String {
+        format!("{}{{tail:.*}}", self.url)
+    }
+
+    /// File for a request path relative to `url`, rejecting anything escaping `dir`.
+    fn resolve(&self, rel: &str) -> Option<PathBuf> {
+        let rel = Path::new(rel.trim_start_matches('/'));
+        if rel.as_os_str().is_empty()
+            || !rel.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        Some(self.dir.join(rel))
+    }
+
+    /// Response for `rel`, honouring `If-None-Match` and `Range` header values.
+    pub async fn serve(
+        &self,
+        rel: &str,
+        if_none_match: Option<&str>,
+        range: Option<&str>,
+    ) -> Response {
+        let not_found = || Response::html("Not found").with_status(404);
+        let Some(path) = self.resolve(rel) else {
+            return not_found();
+        };
+        let meta = match tokio::fs::metadata(&path).await {
+            Ok(meta) if meta.is_file() => meta,
+            _ => return not_found(),
+        };
+        // Modification time and size, so the file needn't be read for a tag
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let total = meta.len();
+        let etag = format!("\"{:x}-{:x}\"", modified.as_micros(), total);
+        let with_headers = |resp: Response| {
+            resp.add_header("Content-Type", content_type(rel))
+                .add_header("ETag", etag.as_str())
+                .add_header("Accept-Ranges", "bytes")
+        };
+
+        if if_none_match.is_some_and(|v| etag_matches(v, &etag)) {
+            return with_headers(Response::html("").with_status(304));
+        }
+
+        if let Some(range) = range {
+            return match parse_range(range, total) {
+                Some((start, end)) => {
+                    with_headers(Response::file_range(&path, start, Some(end - start + 1)))
+                        .with_status(206)
+                        .add_header(
+                            "Content-Range".to_string(),
+                            format!("bytes {start}-{end}/{total}"),
+                        )
+                }
+                None => with_headers(Response::html("").with_status(416))
+                    .add_header("Content-Range".to_string(), format!("bytes */{total}")),
+            };
+        }
+
+        with_headers(Response::file(&path))
+    }
+}
+
+/// Whether an `If-None-Match` value matches `etag`, compared weakly: a
+/// compressed response turned our tag into `W/"..."`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Parses a single `bytes=` range into inclusive offsets within `total`.
+fn parse_range(header: &str, total: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || total == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let len: u64 = suffix.parse().ok()?;
+            if len == 0 {
+                return None;
+            }
+            (total.saturating_sub(len), total - 1)
+        }
+        (start, "") => (start.parse().ok()?, total - 1),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.min(total - 1),
+        ),
+    };
+    (start <= end && start < total).then_some((start, end))
+}
@@ -0,0 +1,188 @@
+//! Cobalto process supervisor
+//!
+//! Runs the HTTP server, task workers and scheduler together in one process
+//! (`cobalto start --all`), or any subset of them for scaled deployments
+//! (`cobalto start --web`, `--worker`, `--scheduler`). All roles share a single
+//! shutdown: SIGINT, SIGTERM or the exit of any role asks the others to stop
+//! through their `Shutdown`, and they get `shutdown_timeout` to finish what
+//! they are doing before being aborted.
+
+use crate::logging::LogRecord;
+use log::Level;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// A process role managed by the supervisor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    Web,
+    Worker,
+    Scheduler,
+}
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Web => "web",
+            Role::Worker => "worker",
+            Role::Scheduler => "scheduler",
+        }
+    }
+}
+
+/// Parse `start` command-line flags into the set of roles to run.
+///
+/// `--all` (or no flags) selects every role.
+pub fn parse_roles<I, S>(args: I) -> Result<Vec<Role>, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut roles = Vec::new();
+    for arg in args {
+        let role = match arg.as_ref() {
+            "start" => continue,
+            "--all" => return Ok(vec![Role::Web, Role::Worker, Role::Scheduler]),
+            "--web" => Role::Web,
+            "--worker" => Role::Worker,
+            "--scheduler" => Role::Scheduler,
+            other => return Err(format!("Unknown flag '{}'", other)),
+        };
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    }
+    if roles.is_empty() {
+        roles = vec![Role::Web, Role::Worker, Role::Scheduler];
+    }
+    Ok(roles)
+}
+
+/// Future returned by a role entrypoint.
+pub type RoleFuture = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>;
+
+/// Factory producing a role's future when it is started.
+pub type RoleFactory = Box<dyn FnOnce(Shutdown) -> RoleFuture + Send>;
+
+/// Given to every role: resolves once the supervisor shuts down, after
+/// which the role should stop taking work and return.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Wait until shutdown is requested.
+    pub async fn requested(mut self) {
+        // The sender lives as long as the supervisor runs
+        let _ = self.0.wait_for(|stop| *stop).await;
+    }
+}
+
+/// Supervises the registered roles in a single process tree.
+pub struct Supervisor {
+    pub roles: Vec<(Role, RoleFactory)>,
+    /// How long roles get to return once shutdown is requested
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor {
+            roles: Vec::new(),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+fn log(level: Level, message: String, role: Option<Role>) {
+    let record = LogRecord::new(level, message);
+    crate::logging::log(match role {
+        Some(role) => record.field("role", role.name()),
+        None => record,
+    });
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the entrypoint for a role; it is told when to stop through
+    /// its `Shutdown`.
+    pub fn add_role<F, Fut>(&mut self, role: Role, f: F)
+    where
+        F: FnOnce(Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        self.roles
+            .push((role, Box::new(move |shutdown| Box::pin(f(shutdown)))));
+    }
+
+    /// Run only the `selected` roles until SIGINT, SIGTERM or until one of
+    /// them exits, then stop the others gracefully.
+    pub async fn run(self, selected: &[Role]) -> std::io::Result<()> {
+        let (stop, stopped) = watch::channel(false);
+        let mut set = tokio::task::JoinSet::new();
+        for (role, factory) in self.roles {
+            if !selected.contains(&role) {
+                continue;
+            }
+            log(Level::Info, format!("starting {}", role.name()), Some(role));
+            let fut = factory(Shutdown(stopped.clone()));
+            set.spawn(async move { (role, fut.await) });
+        }
+        if set.is_empty() {
+            return Ok(());
+        }
+
+        let result = tokio::select! {
+            _ = crate::router::shutdown_signal() => {
+                log(Level::Info, "shutdown requested".to_string(), None);
+                Ok(())
+            }
+            Some(joined) = set.join_next() => match joined {
+                Ok((role, res)) => {
+                    log(
+                        Level::Info,
+                        format!("{} exited, stopping remaining roles", role.name()),
+                        Some(role),
+                    );
+                    res
+                }
+                Err(e) => Err(std::io::Error::other(e)),
+            },
+        };
+        let _ = stop.send(true);
+        let drained = tokio::time::timeout(self.shutdown_timeout, async {
+            let mut result = Ok(());
+            while let Some(joined) = set.join_next().await {
+                let (role, res) = match joined {
+                    Ok((role, res)) => (Some(role), res),
+                    Err(e) => (None, Err(std::io::Error::other(e))),
+                };
+                if let Err(e) = res {
+                    log(Level::Warn, format!("role failed while stopping: {}", e), role);
+                    result = result.and(Err(e));
+                }
+            }
+            result
+        })
+        .await;
+        match drained {
+            Ok(drained) => result.and(drained),
+            Err(_) => {
+                log(
+                    Level::Warn,
+                    format!(
+                        "roles still running after {}s, aborting them",
+                        self.shutdown_timeout.as_secs()
+                    ),
+                    None,
+                );
+                set.shutdown().await;
+                result
+            }
+        }
+    }
+}
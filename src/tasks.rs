@@ -0,0 +1,530 @@
+//! Cobalto scheduled tasks
+//!
+//! Periodic jobs that run inside the server process, no external cron needed:
+//!
+//! ```ignore
+//! let mut scheduler = Scheduler::new();
+//! scheduler.every("purge_sessions", Duration::from_secs(15 * 60), || async {
+//!     purge_expired_sessions().await;
+//! });
+//! scheduler.cron("nightly_report", "30 2 * * *", || async {
+//!     send_report().await;
+//! })?;
+//! router.schedule(scheduler);
+//! ```
+//!
+//! Cron expressions have the usual five fields (minute, hour, day of month,
+//! month, day of week) with `*`, lists, ranges and `/step`, plus `@hourly`,
+//! `@daily`, `@weekly`, `@monthly` and `@yearly`; they are evaluated in UTC.
+//!
+//! A job never overlaps itself: if it is still running when it is due again,
+//! that run is skipped with a warning. Every run is logged with its duration,
+//! and a panicking job is logged without stopping the scheduler.
+//!
+//! One-off background work goes through a `TaskQueue` instead, so handlers
+//! answer without waiting for it:
+//!
+//! ```ignore
+//! let queue = TaskQueue::default();
+//! router.task_queue(queue.clone());
+//! // in a handler
+//! queue.enqueue("welcome_email", async move { send_welcome(user).await; }).await?;
+//! ```
+//!
+//! `Router::run` starts the scheduler and a queue worker next to the server;
+//! `manage start --web`, `--worker` and `--scheduler` pick the roles a
+//! process runs. A `TaskQueue` lives in the memory of its process, so the
+//! worker has to run in the same process as the roles that enqueue (`start`
+//! refuses to split them when a queue is registered); only the scheduler can
+//! be moved to a process of its own.
+
+use crate::clock;
+use crate::logging::LogRecord;
+use crate::router::Router;
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, TimeZone, Timelike, Utc};
+use futures::FutureExt;
+use log::Level;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Body of a scheduled job.
+pub type Job = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// A cron expression that could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronError(pub String);
+
+impl std::fmt::Display for CronError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid cron expression: {}", self.0)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// Allowed values of one cron field, as a bit set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Written as `*`, which matters for the day-of-month/day-of-week rule
+    any: bool,
+}
+
+impl Field {
+    fn parse(spec: &str, min: u32, max: u32) -> Result<Field, CronError> {
+        let invalid = || CronError(format!("'{}' (allowed {}-{})", spec, min, max));
+        let mut bits = 0u64;
+        for part in spec.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((a, b)) => (
+                        a.parse().map_err(|_| invalid())?,
+                        b.parse().map_err(|_| invalid())?,
+                    ),
+                    // `5/15` means from 5 to the end in steps of 15
+                    None => {
+                        let start = range.parse().map_err(|_| invalid())?;
+                        (start, if part.contains('/') { max } else { start })
+                    }
+                },
+            };
+            if step == 0 || start < min || end > max || start > end {
+                return Err(invalid());
+            }
+            for value in (start..=end).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(Field {
+            bits,
+            any: spec == "*",
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+/// A parsed five-field cron expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronExpr {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl FromStr for CronExpr {
+    type Err = CronError;
+
+    fn from_str(expr: &str) -> Result<Self, CronError> {
+        let expr = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(CronError(format!(
+                "'{}' (expected 5 fields, got {})",
+                expr,
+                fields.len()
+            )));
+        };
+        let mut weekday = Field::parse(weekday, 0, 7)?;
+        // Sunday is both 0 and 7
+        if weekday.matches(7) {
+            weekday.bits |= 1;
+        }
+        Ok(CronExpr {
+            minute: Field::parse(minute, 0, 59)?,
+            hour: Field::parse(hour, 0, 23)?,
+            day: Field::parse(day, 1, 31)?,
+            month: Field::parse(month, 1, 12)?,
+            weekday,
+        })
+    }
+}
+
+impl CronExpr {
+    /// Whether the expression fires on `date`. As in cron, when both day
+    /// fields are restricted either one matching is enough.
+    fn day_matches(&self, date: &DateTime<Utc>) -> bool {
+        let day = self.day.matches(date.day());
+        let weekday = self.weekday.matches(date.weekday().num_days_from_sunday());
+        match (self.day.any, self.weekday.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// First time strictly after `after` at which the expression fires.
+    ///
+    /// `None` if it never does (e.g. `0 0 31 2 *`).
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let mut t = start;
+        while t.year() <= start.year() + 5 {
+            if !self.month.matches(t.month()) {
+                let (year, month) = match t.month() {
+                    12 => (t.year() + 1, 1),
+                    m => (t.year(), m + 1),
+                };
+                t = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.day_matches(&t) {
+                t = Utc
+                    .with_ymd_and_hms(t.year(), t.month(), t.day(), 0, 0, 0)
+                    .single()?
+                    + ChronoDuration::days(1);
+            } else if !self.hour.matches(t.hour()) {
+                t = t.with_minute(0)? + ChronoDuration::hours(1);
+            } else if !self.minute.matches(t.minute()) {
+                t += ChronoDuration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+/// When a job runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every interval, starting one interval after the scheduler starts.
+    Every(Duration),
+    Cron(CronExpr),
+}
+
+impl Schedule {
+    /// The next run after `now`.
+    pub fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(interval) => Some(now + ChronoDuration::from_std(*interval).ok()?),
+            Schedule::Cron(expr) => expr.next_after(now),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ScheduledJob {
+    name: String,
+    schedule: Schedule,
+    job: Job,
+    running: Arc<AtomicBool>,
+}
+
+/// Registered jobs; cheap to clone.
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    jobs: Vec<ScheduledJob>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add<F, Fut>(&mut self, name: &str, schedule: Schedule, f: F) -> &mut Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.jobs.push(ScheduledJob {
+            name: name.to_string(),
+            schedule,
+            job: Arc::new(move || Box::pin(f())),
+            running: Arc::new(AtomicBool::new(false)),
+        });
+        self
+    }
+
+    /// Run `f` every `interval`.
+    pub fn every<F, Fut>(&mut self, name: &str, interval: Duration, f: F) -> &mut Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.add(name, Schedule::Every(interval), f)
+    }
+
+    /// Run `f` on a cron schedule (`"*/5 * * * *"`, `"@daily"`).
+    pub fn cron<F, Fut>(&mut self, name: &str, expr: &str, f: F) -> Result<&mut Self, CronError>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let expr = expr.parse()?;
+        Ok(self.add(name, Schedule::Cron(expr), f))
+    }
+
+    /// Names of the registered jobs.
+    pub fn jobs(&self) -> Vec<&str> {
+        self.jobs.iter().map(|j| j.name.as_str()).collect()
+    }
+
+    /// Start job `name` now in the background.
+    ///
+    /// `None` if there is no such job or its previous run is still going.
+    pub fn trigger(&self, name: &str) -> Option<tokio::task::JoinHandle<()>> {
+        let job = self.jobs.iter().find(|j| j.name == name)?;
+        spawn_run(job)
+    }
+
+    /// Run the jobs on their schedules until the process exits.
+    pub async fn run(self) -> std::io::Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Run the jobs on their schedules until `shutdown` resolves, then wait
+    /// for the runs in progress; this is the body of the supervisor's
+    /// scheduler role.
+    pub async fn run_until<F: Future<Output = ()>>(self, shutdown: F) -> std::io::Result<()> {
+        let mut next: Vec<Option<DateTime<Utc>>> = self
+            .jobs
+            .iter()
+            .map(|j| j.schedule.next_after(clock::now()))
+            .collect();
+        let mut shutdown = std::pin::pin!(shutdown);
+        let mut running = Vec::new();
+        loop {
+            let Some(due) = next.iter().flatten().min().copied() else {
+                // Nothing will ever be due
+                break;
+            };
+            let wait = (due - clock::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = tokio::time::sleep(wait) => {}
+            }
+            running.retain(|run: &tokio::task::JoinHandle<()>| !run.is_finished());
+            let now = clock::now();
+            for (job, next_run) in self.jobs.iter().zip(next.iter_mut()) {
+                if next_run.is_some_and(|t| t <= now) {
+                    running.extend(spawn_run(job));
+                    *next_run = job.schedule.next_after(now);
+                }
+            }
+        }
+        for run in running {
+            let _ = run.await;
+        }
+        Ok(())
+    }
+
+    /// Run the scheduler in a background task.
+    pub fn start(self) -> tokio::task::JoinHandle<std::io::Result<()>> {
+        tokio::spawn(self.run())
+    }
+}
+
+/// Spawn one run of `job`, unless the previous one is still going.
+fn spawn_run(job: &ScheduledJob) -> Option<tokio::task::JoinHandle<()>> {
+    if job.running.swap(true, Ordering::SeqCst) {
+        crate::logging::log(
+            LogRecord::new(
+                Level::Warn,
+                format!("task {} skipped: previous run still in progress", job.name),
+            )
+            .field("task", job.name.as_str()),
+        );
+        return None;
+    }
+    let job = job.clone();
+    Some(tokio::spawn(async move {
+        run_logged(&job.name, (job.job)()).await;
+        job.running.store(false, Ordering::SeqCst);
+    }))
+}
+
+/// Await `task`, logging its duration, or that it panicked.
+async fn run_logged(name: &str, task: Pin<Box<dyn Future<Output = ()> + Send>>) {
+    let started = std::time::Instant::now();
+    let outcome = AssertUnwindSafe(task).catch_unwind().await;
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let record = match outcome {
+        Ok(()) => LogRecord::new(Level::Info, format!("task {} finished", name)),
+        Err(_) => LogRecord::new(Level::Error, format!("task {} panicked", name)),
+    };
+    crate::logging::log(
+        record
+            .field("task", name)
+            .field("duration_ms", duration_ms),
+    );
+}
+
+/// A task waiting in a `TaskQueue`.
+struct QueuedTask {
+    name: String,
+    task: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+/// The queue no longer accepts tasks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueClosed;
+
+impl std::fmt::Display for QueueClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "task queue closed")
+    }
+}
+
+impl std::error::Error for QueueClosed {}
+
+/// Tasks a worker runs at the same time unless `TaskQueue::concurrency`
+/// says otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 16;
+
+/// Bounded queue of background tasks run by a worker (cheap to clone).
+///
+/// `enqueue` waits while the queue is full, so a burst of requests slows
+/// down instead of piling up tasks without limit. A worker runs up to
+/// `concurrency` tasks at a time and leaves the others queued, logged like
+/// scheduled jobs.
+#[derive(Clone)]
+pub struct TaskQueue {
+    sender: mpsc::Sender<QueuedTask>,
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<QueuedTask>>>,
+    concurrency: usize,
+}
+
+impl Default for TaskQueue {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl TaskQueue {
+    /// Queue holding at most `capacity` tasks not yet picked up.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        TaskQueue {
+            sender,
+            receiver: Arc::new(tokio::sync::Mutex::new(receiver)),
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Let each worker run up to `tasks` tasks at the same time.
+    pub fn concurrency(mut self, tasks: usize) -> Self {
+        self.concurrency = tasks.max(1);
+        self
+    }
+
+    /// Queue `task` under `name`, waiting while the queue is full.
+    pub async fn enqueue<Fut>(&self, name: &str, task: Fut) -> Result<(), QueueClosed>
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.sender
+            .send(QueuedTask {
+                name: name.to_string(),
+                task: Box::pin(task),
+            })
+            .await
+            .map_err(|_| QueueClosed)
+    }
+
+    /// Number of tasks waiting for a worker.
+    pub fn pending(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+
+    /// Run the tasks already queued, one after the other, and return how many
+    /// ran (tests and one-shot commands).
+    pub async fn run_pending(&self) -> usize {
+        let mut ran = 0;
+        loop {
+            let queued = self.receiver.lock().await.try_recv();
+            let Ok(queued) = queued else {
+                return ran;
+            };
+            run_logged(&queued.name, queued.task).await;
+            ran += 1;
+        }
+    }
+
+    /// Pick up tasks as they are queued until the process exits. Several
+    /// workers may share a queue.
+    pub async fn run(self) -> std::io::Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Pick up tasks until `shutdown` resolves, then wait for the running
+    /// ones to finish; this is the body of the supervisor's worker role.
+    /// Tasks still queued stay in the queue.
+    pub async fn run_until<F: Future<Output = ()>>(self, shutdown: F) -> std::io::Result<()> {
+        let slots = Arc::new(tokio::sync::Semaphore::new(self.concurrency));
+        let mut shutdown = std::pin::pin!(shutdown);
+        loop {
+            // Wait for a free slot first, so that busy workers leave the
+            // tasks in the queue and `enqueue` pushes back
+            let next = async {
+                let slot = slots.clone().acquire_owned().await.expect("never closed");
+                let queued = self.receiver.lock().await.recv().await;
+                queued.map(|queued| (slot, queued))
+            };
+            tokio::select! {
+                _ = &mut shutdown => break,
+                next = next => {
+                    // Only once every handle, this one included, is gone
+                    let Some((slot, queued)) = next else {
+                        break;
+                    };
+                    tokio::spawn(async move {
+                        run_logged(&queued.name, queued.task).await;
+                        drop(slot);
+                    });
+                }
+            }
+        }
+        let _ = slots.acquire_many(self.concurrency as u32).await;
+        Ok(())
+    }
+
+    /// Run a worker in a background task.
+    pub fn start(self) -> tokio::task::JoinHandle<std::io::Result<()>> {
+        tokio::spawn(self.run())
+    }
+}
+
+impl Router {
+    /// Register `scheduler`; `run` starts it with the server, once the
+    /// startup hooks have run.
+    pub fn schedule(&mut self, scheduler: Scheduler) {
+        self.manage(scheduler);
+    }
+
+    /// Share `queue` with handlers (`req.state::<TaskQueue>()`); `run` starts
+    /// a worker for it with the server, once the startup hooks have run.
+    pub fn task_queue(&mut self, queue: TaskQueue) {
+        self.manage(queue);
+    }
+
+    /// Start the registered scheduler and task queue worker in background
+    /// tasks.
+    pub(crate) fn start_background(&self) {
+        if let Some(scheduler) = self.managed::<Scheduler>() {
+            scheduler.start();
+        }
+        if let Some(queue) = self.managed::<TaskQueue>() {
+            queue.start();
+        }
+    }
+}
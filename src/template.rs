@@ -3,21 +3,27 @@
 //! This module implements a Django-inspired template engine for Rust, now with Tailwind integration.
 //!
 //! Workflow:
-//! 1. `render_template` loads the child template.
-//! 2. `tokenize_template` splits content into Text, Variable, and Tag tokens.
+//! 1. `render_template` loads the child template; parsed templates are cached and re-parsed when the file's mtime or size changes (see `set_template_cache`).
+//! 2. `tokenize_template` splits content into Text, Variable, and Tag tokens, dropping `{# comments #}` and keeping `{% verbatim %}` sections as text.
 //! 3. `parse_tokens` and `parse_nodes` build an AST of `Node`.
-//! 4. Child `Block` definitions and `Extends` tag are collected.
-//! 5. `merge_blocks` merges child blocks into the base template, replacing all matching blocks by name (supports multiple occurrences).
-//! 6. `render_nodes` walks the merged AST and outputs HTML, resolving variables, `if` conditions, `for` loops, and Tailwind imports via `{% tailwind %}`.
+//! 4. The `Extends` chain is followed up to the root template and each level's `Block` definitions are collected.
+//! 5. `merge_blocks` merges each level's blocks into the template it extends, from the root down, replacing all matching blocks by name (supports multiple occurrences); `{{ block.super }}` in an overriding block renders the block it replaces.
+//! 6. `render_nodes` walks the merged AST and outputs HTML, resolving variables, `if`/`elif` conditions (see `evaluate_condition`), `for` loops over lists and objects (with `key, value` unpacking and a `forloop` counter object), and Tailwind imports via `{% tailwind %}`. Variable output is HTML-escaped unless marked `|safe` or inside `{% autoescape off %}`.
+//! 7. Custom tags registered with `register_tag` (e.g. the built-in `{% qrcode %}`, `{% static %}`, `{% url %}` and `{% trans %}`) render through the tag registry.
+//!
+//! `stream_template` renders the same way but sends the HTML in chunks as it is produced.
 //!
 //! Runtime logging is controlled via `set_display_logs`.
 
+use actix_web::web::Bytes;
 use log::debug;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 
 use crate::router::Response;
 
@@ -38,6 +44,232 @@ macro_rules! tdebug {
     }
 }
 
+/// Whether parsed templates are cached (off while `template.debug` is set)
+static CACHE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Parsed templates by (path, delimiters), with the file stamp they were parsed from
+static COMPILED: Lazy<RwLock<HashMap<(String, Delimiters), CompiledTemplate>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+struct CompiledTemplate {
+    stamp: FileStamp,
+    nodes: Arc<Vec<Node>>,
+}
+
+/// Modification time and size: a changed file differs in at least one.
+type FileStamp = (Option<std::time::SystemTime>, u64);
+
+/// Enable or disable the parsed-template cache (done by `Router::new` from
+/// `template.debug`); disabling it also empties it
+pub fn set_template_cache(enabled: bool) {
+    CACHE_ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        clear_template_cache();
+    }
+}
+
+/// Drop every parsed template, forcing the next render to re-read from disk
+pub fn clear_template_cache() {
+    COMPILED.write().unwrap().clear();
+}
+
+/// Whether new engines are strict (set by `Router::new` from `template.debug`)
+static STRICT_DEFAULT: AtomicBool = AtomicBool::new(false);
+
+/// Make engines created from now on strict or not (see `TemplateEngine::strict`)
+pub fn set_strict_default(enabled: bool) {
+    STRICT_DEFAULT.store(enabled, Ordering::Relaxed);
+}
+
+thread_local! {
+    /// Set while an engine parses, so unknown tags are kept for strict mode
+    static KEEP_UNKNOWN: Cell<bool> = const { Cell::new(false) };
+    /// Name and path of the files a strict engine is rendering on this thread
+    static STRICT_SOURCES: RefCell<Option<Vec<(String, String)>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with strict rendering reporting errors in `sources`
+fn with_strict_sources<R>(sources: Option<Vec<(String, String)>>, f: impl FnOnce() -> R) -> R {
+    let previous = STRICT_SOURCES.with(|s| s.replace(sources));
+    let result = f();
+    STRICT_SOURCES.with(|s| *s.borrow_mut() = previous);
+    result
+}
+
+/// Visible marker for an undefined variable or unknown tag, naming the
+/// first line of the rendered files that mentions it; `None` unless a
+/// strict engine is rendering
+fn strict_error(kind: &str, what: &str) -> Option<String> {
+    let location = STRICT_SOURCES.with(|sources| {
+        let sources = sources.borrow();
+        let sources = sources.as_ref()?;
+        Some(sources.iter().find_map(|(name, path)| {
+            let source = std::fs::read_to_string(path).ok()?;
+            let line = source.lines().position(|l| l.contains(what))?;
+            Some(format!(" at {}:{}", name, line + 1))
+        }))
+    })?;
+    let location = location.unwrap_or_default();
+    log::warn!("template: {} '{}'{}", kind, what, location);
+    Some(format!(
+        "<span class=\"cobalto-template-error\" style=\"color:#b00020;background:#fde7e9\">[{} '{}'{}]</span>",
+        kind,
+        escape_html(what),
+        escape_html(&location)
+    ))
+}
+
+/// Directories searched, in order, for templates missing from an engine's own
+static TEMPLATE_DIRS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Search `dir` for templates not found in an engine's directory (done by
+/// `Router::mount` for app template directories)
+pub fn add_template_dir(dir: &str) {
+    let mut dirs = TEMPLATE_DIRS.write().unwrap();
+    if !dirs.iter().any(|d| d == dir) {
+        dirs.push(dir.to_string());
+    }
+}
+
+/// A custom tag renderer: receives the tag arguments and the render context
+pub type TagFn = Arc<dyn Fn(&TagArgs, &HashMap<String, TemplateValue>) -> String + Send + Sync>;
+
+/// Registry of custom `{% name ... %}` tags, pre-populated with the built-in ones
+static CUSTOM_TAGS: Lazy<RwLock<HashMap<String, TagFn>>> = Lazy::new(|| {
+    let mut tags: HashMap<String, TagFn> = HashMap::new();
+    tags.insert("qrcode".to_string(), Arc::new(crate::qr::qrcode_tag));
+    tags.insert(
+        "progress_bar".to_string(),
+        Arc::new(crate::progress::progress_bar_tag),
+    );
+    tags.insert(
+        "static".to_string(),
+        Arc::new(crate::staticfiles::static_tag),
+    );
+    tags.insert("url".to_string(), Arc::new(crate::router::url_tag));
+    tags.insert("trans".to_string(), Arc::new(crate::i18n::trans_tag));
+    RwLock::new(tags)
+});
+
+/// Register a custom tag usable as `{% name arg key=value %}`
+pub fn register_tag<F>(name: &str, f: F)
+where
+    F: Fn(&TagArgs, &HashMap<String, TemplateValue>) -> String + Send + Sync + 'static,
+{
+    CUSTOM_TAGS
+        .write()
+        .unwrap()
+        .insert(name.to_string(), Arc::new(f));
+}
+
+fn is_custom_tag(name: &str) -> bool {
+    CUSTOM_TAGS.read().unwrap().contains_key(name)
+}
+
+/// A context processor adds values to every context passed to `render_template`
+pub type ContextProcessor = Arc<dyn Fn(&mut HashMap<String, TemplateValue>) + Send + Sync>;
+
+/// Registered context processors, starting with the built-in request processor
+static CONTEXT_PROCESSORS: Lazy<RwLock<Vec<ContextProcessor>>> =
+    Lazy::new(|| RwLock::new(vec![Arc::new(request_context_processor)]));
+
+/// Register a context processor applied on every template render
+pub fn register_context_processor<F>(f: F)
+where
+    F: Fn(&mut HashMap<String, TemplateValue>) + Send + Sync + 'static,
+{
+    CONTEXT_PROCESSORS.write().unwrap().push(Arc::new(f));
+}
+
+/// Returns a copy of `context` extended by every registered context processor
+pub fn apply_context_processors(
+    context: &HashMap<String, TemplateValue>,
+) -> HashMap<String, TemplateValue> {
+    let mut processed = context.clone();
+    let processors = CONTEXT_PROCESSORS.read().unwrap().clone();
+    for processor in processors {
+        processor(&mut processed);
+    }
+    processed
+}
+
+/// Exposes the current route and query parameters as `request.params` / `request.query`
+pub fn request_context_processor(context: &mut HashMap<String, TemplateValue>) {
+    if context.contains_key("request") {
+        return;
+    }
+    if let Some(scope) = crate::router::current_request() {
+        let to_object = |map: HashMap<String, String>| {
+            TemplateValue::Object(
+                map.into_iter()
+                    .map(|(k, v)| (k, TemplateValue::String(v)))
+                    .collect(),
+            )
+        };
+        let mut request = HashMap::new();
+        request.insert("params".to_string(), to_object(scope.params));
+        request.insert("query".to_string(), to_object(scope.query));
+        context.insert("request".to_string(), TemplateValue::Object(request));
+    }
+}
+
+/// Parsed arguments of a custom tag: positional values and `key=value` pairs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagArgs {
+    pub positional: Vec<String>,
+    pub named: HashMap<String, String>,
+}
+
+impl TagArgs {
+    /// Splits `a "b c" key=value` into positional and named arguments
+    pub fn parse(input: &str) -> Self {
+        let mut args = TagArgs::default();
+        let mut current = String::new();
+        let mut in_quotes = false;
+        let mut parts = Vec::new();
+        for c in input.chars() {
+            match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    current.push(c);
+                }
+                c if c.is_whitespace() && !in_quotes => {
+                    if !current.is_empty() {
+                        parts.push(std::mem::take(&mut current));
+                    }
+                }
+                c => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            parts.push(current);
+        }
+        for part in parts {
+            match part.split_once('=') {
+                Some((k, v)) if !part.starts_with('"') => {
+                    args.named.insert(k.to_string(), v.to_string());
+                }
+                _ => args.positional.push(part),
+            }
+        }
+        args
+    }
+
+    /// Resolves an argument: quoted strings are literals, numbers stay as-is,
+    /// anything else is looked up as a context variable.
+    pub fn resolve(raw: &str, context: &HashMap<String, TemplateValue>) -> String {
+        if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+            return raw[1..raw.len() - 1].to_string();
+        }
+        if raw.parse::<f64>().is_ok() {
+            return raw.to_string();
+        }
+        resolve_variable(raw, context)
+            .map(|v| v.as_string())
+            .unwrap_or_default()
+    }
+}
+
 /// Supported value types for template context
 #[derive(Clone)]
 pub enum TemplateValue {
@@ -60,6 +292,36 @@ impl TemplateValue {
     }
 }
 
+impl TemplateValue {
+    /// Convert any serializable value (e.g. a model) into a context value.
+    ///
+    /// Structs become objects, sequences lists, and `null` an empty string.
+    pub fn from_serialize<T: serde::Serialize>(value: &T) -> Self {
+        serde_json::to_value(value)
+            .map(TemplateValue::from)
+            .unwrap_or_else(|_| TemplateValue::String(String::new()))
+    }
+}
+
+impl From<serde_json::Value> for TemplateValue {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => TemplateValue::String(String::new()),
+            serde_json::Value::Bool(b) => TemplateValue::Bool(b),
+            serde_json::Value::Number(n) => TemplateValue::Number(n.as_f64().unwrap_or(0.0)),
+            serde_json::Value::String(s) => TemplateValue::String(s),
+            serde_json::Value::Array(items) => {
+                TemplateValue::List(items.into_iter().map(TemplateValue::from).collect())
+            }
+            serde_json::Value::Object(map) => TemplateValue::Object(
+                map.into_iter()
+                    .map(|(k, v)| (k, TemplateValue::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
 impl fmt::Display for TemplateValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.as_string())
@@ -85,6 +347,7 @@ pub enum Node {
         else_body: Vec<Node>,
     },
     For {
+        /// One name, or comma separated names unpacking each item
         var_name: String,
         list_name: String,
         body: Vec<Node>,
@@ -95,38 +358,134 @@ pub enum Node {
     },
     Extends(String), // {% extends "base.html" %}
     Tailwind,        // {% tailwind %}
+    Autoescape {
+        enabled: bool,
+        body: Vec<Node>,
+    }, // {% autoescape off %}...{% endautoescape %}
+    Custom {
+        name: String,
+        args: TagArgs,
+    }, // {% name arg key=value %} from the tag registry
+    Cache {
+        ttl: String,
+        name: String,
+        vary_on: Vec<String>,
+        body: Vec<Node>,
+    }, // {% cache 300 "sidebar" user.id %}...{% endcache %}
+    BlockTrans {
+        singular: String,
+        plural: Option<String>,
+        count: Option<(String, String)>,
+    }, // {% blocktrans count n=items %}...{% plural %}...{% endblocktrans %}
+    With {
+        bindings: Vec<(String, String)>,
+        body: Vec<Node>,
+    }, // {% with total=items|length %}...{% endwith %}
+    Set {
+        name: String,
+        expr: String,
+    }, // {% set total = items|length %}, until the end of the enclosing body
+    Unknown(String), // {% misspelled %}, kept by engines for strict mode
+}
+
+/// Template delimiters, configurable per engine instance
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Delimiters {
+    pub variable: (String, String),
+    pub tag: (String, String),
 }
 
+impl Default for Delimiters {
+    fn default() -> Self {
+        Delimiters {
+            variable: ("{{".to_string(), "}}".to_string()),
+            tag: ("{%".to_string(), "%}".to_string()),
+        }
+    }
+}
+
+impl Delimiters {
+    /// Custom delimiters, e.g. `Delimiters::new(("[[", "]]"), ("[%", "%]"))`
+    pub fn new(variable: (&str, &str), tag: (&str, &str)) -> Self {
+        Delimiters {
+            variable: (variable.0.to_string(), variable.1.to_string()),
+            tag: (tag.0.to_string(), tag.1.to_string()),
+        }
+    }
+
+    /// Variables, tags and `{# comments #}`
+    fn regex(&self) -> Regex {
+        Regex::new(&format!(
+            r"(?s)({}.*?{}|{}.*?{}|{}.*?{})",
+            regex::escape(&self.variable.0),
+            regex::escape(&self.variable.1),
+            regex::escape(&self.tag.0),
+            regex::escape(&self.tag.1),
+            regex::escape(COMMENT.0),
+            regex::escape(COMMENT.1),
+        ))
+        .unwrap()
+    }
+}
+
+/// Delimiters of single-line comments, whatever the engine's delimiters
+const COMMENT: (&str, &str) = ("{#", "#}");
+
 /// Tokenizes the template content into a Vec<Token>
 pub fn tokenize_template(content: &str) -> Vec<Token> {
+    tokenize_with(content, &Delimiters::default())
+}
+
+/// Tokenizes the template content using the given delimiters
+pub fn tokenize_with(content: &str, delimiters: &Delimiters) -> Vec<Token> {
     let mut tokens = Vec::new();
-    let re = Regex::new(r"(?s)(\{\{.*?\}\}|\{%.*?%\})").unwrap();
+    let re = delimiters.regex();
+    let (var_open, var_close) = &delimiters.variable;
+    let (tag_open, tag_close) = &delimiters.tag;
+    let tag_inner = |m: &str| {
+        m[tag_open.len()..m.len() - tag_close.len()]
+            .trim()
+            .to_string()
+    };
     let mut last_end = 0;
-    for mat in re.find_iter(content) {
+    while let Some(mat) = re.find_at(content, last_end) {
         let start = mat.start();
         let end = mat.end();
         if start > last_end {
             tokens.push(Token::Text(content[last_end..start].to_string()));
         }
+        last_end = end;
         let m = mat.as_str().trim();
-        if m.starts_with("{{") {
-            let inner = m
-                .trim_start_matches("{{")
-                .trim_end_matches("}}")
+        if m.starts_with(COMMENT.0)
+            && !m.starts_with(var_open.as_str())
+            && !m.starts_with(tag_open.as_str())
+        {
+            continue;
+        }
+        if m.starts_with(var_open.as_str()) {
+            let inner = m[var_open.len()..m.len() - var_close.len()]
                 .trim()
                 .to_string();
             tdebug!("tokenize: Variable '{{ {{ {} }} }}'", inner);
             tokens.push(Token::Variable(inner));
-        } else {
-            let inner = m
-                .trim_start_matches("{% ")
-                .trim_end_matches(" %}")
-                .trim()
-                .to_string();
-            tdebug!("tokenize: Tag '{{% {} %}}'", inner);
-            tokens.push(Token::Tag(inner));
+            continue;
         }
-        last_end = end;
+        let inner = tag_inner(m);
+        if inner == "verbatim" || inner.starts_with("verbatim ") {
+            // Everything up to the matching endverbatim is literal text
+            let end_tag = format!("end{}", inner);
+            let close = re.find_iter(&content[end..]).find(|m| {
+                m.as_str().starts_with(tag_open.as_str()) && tag_inner(m.as_str()) == end_tag
+            });
+            let text_end = close.map_or(content.len(), |m| end + m.start());
+            if text_end > end {
+                tokens.push(Token::Text(content[end..text_end].to_string()));
+            }
+            last_end = close.map_or(content.len(), |m| end + m.end());
+            continue;
+        }
+        tdebug!("tokenize: Tag '{{% {} %}}'", inner);
+        tokens.push(Token::Tag(inner));
     }
     if last_end < content.len() {
         tokens.push(Token::Text(content[last_end..].to_string()));
@@ -155,9 +514,20 @@ fn parse_nodes(tokens: &[Token], idx: &mut usize, end_tags: &[&str]) -> Vec<Node
             }
             Token::Tag(tag) => {
                 let t = tag.trim();
-                if end_tags.contains(&t) {
+                let tag_name = t.split_whitespace().next().unwrap_or("");
+                if end_tags.contains(&t) || end_tags.contains(&tag_name) {
                     break;
                 }
+                // Skip comment/endcomment with everything inside
+                if tag_name == "comment" {
+                    while let Some(token) = tokens.get(*idx) {
+                        *idx += 1;
+                        if matches!(token, Token::Tag(t) if t.trim() == "endcomment") {
+                            break;
+                        }
+                    }
+                    continue;
+                }
                 // Handle extends
                 if let Some(rest) = t.strip_prefix("extends ") {
                     nodes.push(Node::Extends(rest.trim_matches('"').to_string()));
@@ -175,37 +545,79 @@ fn parse_nodes(tokens: &[Token], idx: &mut usize, end_tags: &[&str]) -> Vec<Node
                     });
                     continue;
                 }
-                // Handle if/else/endif
-                if let Some(cond) = t.strip_prefix("if ") {
+                // Handle autoescape on/off
+                if let Some(mode) = t.strip_prefix("autoescape ") {
                     *idx += 1;
-                    let then_body = parse_nodes(tokens, idx, &["else", "endif"]);
-                    let mut else_body = Vec::new();
-                    if *idx < tokens.len() {
-                        if let Token::Tag(tt) = &tokens[*idx] {
-                            if tt.trim() == "else" {
-                                *idx += 1;
-                                else_body = parse_nodes(tokens, idx, &["endif"]);
-                            }
-                        }
-                    }
-                    *idx += 1; // skip endif
-                    nodes.push(Node::If {
-                        condition: cond.to_string(),
-                        then_body,
-                        else_body,
+                    let body = parse_nodes(tokens, idx, &["endautoescape"]);
+                    *idx += 1; // skip endautoescape
+                    nodes.push(Node::Autoescape {
+                        enabled: mode.trim() != "off",
+                        body,
+                    });
+                    continue;
+                }
+                // Handle cache/endcache
+                if let Some(rest) = t.strip_prefix("cache ") {
+                    let mut args = TagArgs::parse(rest).positional.into_iter();
+                    *idx += 1;
+                    let body = parse_nodes(tokens, idx, &["endcache"]);
+                    *idx += 1; // skip endcache
+                    nodes.push(Node::Cache {
+                        ttl: args.next().unwrap_or_default(),
+                        name: args.next().unwrap_or_default(),
+                        vary_on: args.collect(),
+                        body,
+                    });
+                    continue;
+                }
+                // Handle with/endwith
+                if let Some(rest) = t.strip_prefix("with ") {
+                    let mut bindings: Vec<(String, String)> =
+                        TagArgs::parse(rest).named.into_iter().collect();
+                    bindings.sort();
+                    *idx += 1;
+                    let body = parse_nodes(tokens, idx, &["endwith"]);
+                    *idx += 1; // skip endwith
+                    nodes.push(Node::With { bindings, body });
+                    continue;
+                }
+                // Handle set
+                if let Some((name, expr)) = t.strip_prefix("set ").and_then(|r| r.split_once('=')) {
+                    nodes.push(Node::Set {
+                        name: name.trim().to_string(),
+                        expr: expr.trim().to_string(),
                     });
+                    *idx += 1;
+                    continue;
+                }
+                // Handle blocktrans/plural/endblocktrans
+                if tag_name == "blocktrans" {
+                    *idx += 1;
+                    nodes.push(parse_blocktrans(&t["blocktrans".len()..], tokens, idx));
+                    continue;
+                }
+                // Handle if/elif/else/endif
+                if let Some(cond) = t.strip_prefix("if ") {
+                    *idx += 1;
+                    nodes.push(parse_if(cond, tokens, idx));
                     continue;
                 }
                 // Handle for/endfor
-                if let Some(rest) = t.strip_prefix("for ") {
-                    let parts: Vec<&str> = rest.split_whitespace().collect();
-                    if parts.len() == 3 && parts[1] == "in" {
+                if let Some(rest) = t.strip_prefix("for ")
+                    && let Some((names, list)) = rest.rsplit_once(" in ")
+                {
+                    let names: Vec<&str> = names.split(',').map(str::trim).collect();
+                    let list = list.trim();
+                    if names.iter().all(|n| !n.is_empty() && !n.contains(' '))
+                        && !list.is_empty()
+                        && !list.contains(' ')
+                    {
                         *idx += 1;
                         let body = parse_nodes(tokens, idx, &["endfor"]);
                         *idx += 1; // skip endfor
                         nodes.push(Node::For {
-                            var_name: parts[0].to_string(),
-                            list_name: parts[2].to_string(),
+                            var_name: names.join(","),
+                            list_name: list.to_string(),
                             body,
                         });
                         continue;
@@ -217,7 +629,20 @@ fn parse_nodes(tokens: &[Token], idx: &mut usize, end_tags: &[&str]) -> Vec<Node
                     *idx += 1;
                     continue;
                 }
-                // Unknown tag: skip
+                // Handle registered custom tags
+                let (name, rest) = t.split_once(' ').unwrap_or((t, ""));
+                if is_custom_tag(name) {
+                    nodes.push(Node::Custom {
+                        name: name.to_string(),
+                        args: TagArgs::parse(rest),
+                    });
+                    *idx += 1;
+                    continue;
+                }
+                // Unknown tag: skip, or keep for strict rendering
+                if KEEP_UNKNOWN.with(Cell::get) {
+                    nodes.push(Node::Unknown(t.to_string()));
+                }
                 *idx += 1;
             }
         }
@@ -225,6 +650,62 @@ fn parse_nodes(tokens: &[Token], idx: &mut usize, end_tags: &[&str]) -> Vec<Node
     nodes
 }
 
+/// Collects the body of a `blocktrans` whose tag has been consumed into its
+/// message ids, with variables written as `{{ name }}`.
+fn parse_blocktrans(args: &str, tokens: &[Token], idx: &mut usize) -> Node {
+    let count = args
+        .trim()
+        .strip_prefix("count ")
+        .and_then(|rest| rest.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()));
+    let mut singular = String::new();
+    let mut plural: Option<String> = None;
+    while let Some(token) = tokens.get(*idx) {
+        *idx += 1;
+        match token {
+            Token::Text(text) => plural.as_mut().unwrap_or(&mut singular).push_str(text),
+            Token::Variable(var) => {
+                let target = plural.as_mut().unwrap_or(&mut singular);
+                target.push_str("{{ ");
+                target.push_str(var);
+                target.push_str(" }}");
+            }
+            Token::Tag(tag) => match tag.trim() {
+                "plural" => plural = Some(String::new()),
+                "endblocktrans" => break,
+                _ => {}
+            },
+        }
+    }
+    Node::BlockTrans {
+        singular,
+        plural,
+        count,
+    }
+}
+
+/// Parses the branches of an `if` whose tag has been consumed; an `elif`
+/// becomes a nested `If` in the else branch.
+fn parse_if(condition: &str, tokens: &[Token], idx: &mut usize) -> Node {
+    let then_body = parse_nodes(tokens, idx, &["elif", "else", "endif"]);
+    let mut else_body = Vec::new();
+    if let Some(Token::Tag(tt)) = tokens.get(*idx) {
+        let tt = tt.trim();
+        *idx += 1;
+        if let Some(cond) = tt.strip_prefix("elif ") {
+            else_body.push(parse_if(cond, tokens, idx));
+        } else if tt == "else" {
+            else_body = parse_nodes(tokens, idx, &["endif"]);
+            *idx += 1; // skip endif
+        }
+    }
+    Node::If {
+        condition: condition.trim().to_string(),
+        then_body,
+        else_body,
+    }
+}
+
 /// Resolves a dotted variable path 'a.b.c' within the context
 fn resolve_variable<'a>(
     name: &str,
@@ -243,6 +724,392 @@ fn resolve_variable<'a>(
     current
 }
 
+/// Splits `value|filter:"a|b"|other` at the pipes outside quotes.
+fn split_filters(expr: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in expr.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('|', None) => {
+                parts.push(expr[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(expr[start..].trim());
+    parts
+}
+
+/// A quoted string, number, `true`, `false`, `None` or dotted variable.
+fn literal_or_variable(
+    token: &str,
+    context: &HashMap<String, TemplateValue>,
+) -> Option<TemplateValue> {
+    if let Some(quote) = token.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        let inner = token.strip_prefix(quote)?.strip_suffix(quote)?;
+        return Some(TemplateValue::String(inner.to_string()));
+    }
+    match token {
+        "true" | "True" => Some(TemplateValue::Bool(true)),
+        "false" | "False" => Some(TemplateValue::Bool(false)),
+        "None" | "none" => None,
+        _ => match token.parse::<f64>() {
+            Ok(n) => Some(TemplateValue::Number(n)),
+            Err(_) => resolve_variable(token, context).cloned(),
+        },
+    }
+}
+
+/// Value of `expr`: a literal or variable followed by filters, e.g.
+/// `items|length` or `user.name|default:"anonymous"|upper`.
+///
+/// Filters: `length`, `upper`, `lower`, `first`, `last`, `join:", "` and
+/// `default:value`; `safe` and `escape` only matter when rendering.
+pub fn evaluate_value(
+    expr: &str,
+    context: &HashMap<String, TemplateValue>,
+) -> Option<TemplateValue> {
+    let parts = split_filters(expr);
+    let mut value = literal_or_variable(parts[0], context);
+    for filter in &parts[1..] {
+        let (name, arg) = match filter.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (*filter, None),
+        };
+        value = match (name, value) {
+            ("safe" | "escape", value) => value,
+            ("length", value) => Some(TemplateValue::Number(match &value {
+                Some(TemplateValue::List(items)) => items.len(),
+                Some(TemplateValue::Object(map)) => map.len(),
+                Some(TemplateValue::String(s)) => s.chars().count(),
+                _ => 0,
+            } as f64)),
+            ("upper", Some(v)) => Some(TemplateValue::String(v.as_string().to_uppercase())),
+            ("lower", Some(v)) => Some(TemplateValue::String(v.as_string().to_lowercase())),
+            ("first", Some(TemplateValue::List(items))) => items.into_iter().next(),
+            ("last", Some(TemplateValue::List(items))) => items.into_iter().last(),
+            ("first", Some(TemplateValue::String(s))) => s
+                .chars()
+                .next()
+                .map(|c| TemplateValue::String(c.to_string())),
+            ("last", Some(TemplateValue::String(s))) => s
+                .chars()
+                .last()
+                .map(|c| TemplateValue::String(c.to_string())),
+            ("join", Some(TemplateValue::List(items))) => {
+                let sep = arg.and_then(|a| literal_or_variable(a, context));
+                let sep = sep.map(|s| s.as_string()).unwrap_or_default();
+                let items: Vec<String> = items.iter().map(TemplateValue::as_string).collect();
+                Some(TemplateValue::String(items.join(&sep)))
+            }
+            ("default", value) if !truthy(&value) => {
+                arg.and_then(|a| literal_or_variable(a, context))
+            }
+            ("default", value) => value,
+            ("upper" | "lower" | "first" | "last" | "join", value) => value,
+            (other, value) => {
+                tdebug!("Unknown filter '{}'", other);
+                value
+            }
+        };
+    }
+    value
+}
+
+/// Evaluates an `if` condition such as `user.age >= 18 and not banned`.
+///
+/// Supports `or`, `and`, `not`, parentheses, the comparisons `==`, `!=`, `<`,
+/// `>`, `<=`, `>=`, `in` and `not in`, string and number literals, `true`,
+/// `false` and `None`. Values are truthy Django-style: non-empty strings,
+/// lists and objects, non-zero numbers and `true`; undefined variables are
+/// falsy. A malformed condition is false.
+pub fn evaluate_condition(condition: &str, context: &HashMap<String, TemplateValue>) -> bool {
+    let tokens = expr_tokens(condition);
+    let mut parser = ExprParser {
+        tokens: &tokens,
+        pos: 0,
+        context,
+    };
+    match parser.or_expr() {
+        Some(value) if parser.pos == tokens.len() => truthy(&value),
+        _ => {
+            tdebug!("if: cannot evaluate condition '{}'", condition);
+            false
+        }
+    }
+}
+
+fn expr_tokens(expr: &str) -> Vec<String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            tokens.push(chars[start..i].iter().collect());
+        } else if "=!<>".contains(c) {
+            if chars.get(i + 1) == Some(&'=') {
+                tokens.push(format!("{}=", c));
+                i += 2;
+            } else {
+                tokens.push(c.to_string());
+                i += 1;
+            }
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            i += 1;
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() && !"=!<>()\"'".contains(chars[i]) {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        }
+    }
+    tokens
+}
+
+/// Recursive-descent evaluator over `expr_tokens`; `None` means undefined.
+struct ExprParser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    context: &'a HashMap<String, TemplateValue>,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or_expr(&mut self) -> Option<Option<TemplateValue>> {
+        let mut value = self.and_expr()?;
+        while self.eat("or") {
+            let rhs = self.and_expr()?;
+            value = Some(TemplateValue::Bool(truthy(&value) || truthy(&rhs)));
+        }
+        Some(value)
+    }
+
+    fn and_expr(&mut self) -> Option<Option<TemplateValue>> {
+        let mut value = self.not_expr()?;
+        while self.eat("and") {
+            let rhs = self.not_expr()?;
+            value = Some(TemplateValue::Bool(truthy(&value) && truthy(&rhs)));
+        }
+        Some(value)
+    }
+
+    fn not_expr(&mut self) -> Option<Option<TemplateValue>> {
+        if self.eat("not") {
+            let value = self.not_expr()?;
+            return Some(Some(TemplateValue::Bool(!truthy(&value))));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Option<Option<TemplateValue>> {
+        let lhs = self.operand()?;
+        let op = match self.peek() {
+            Some(op @ ("==" | "!=" | "<" | ">" | "<=" | ">=" | "in")) => op.to_string(),
+            Some("not") if self.tokens.get(self.pos + 1).map(|t| t.as_str()) == Some("in") => {
+                self.pos += 1;
+                "not in".to_string()
+            }
+            _ => return Some(lhs),
+        };
+        self.pos += 1;
+        let rhs = self.operand()?;
+        let result = match op.as_str() {
+            "==" => values_equal(&lhs, &rhs),
+            "!=" => !values_equal(&lhs, &rhs),
+            "in" => contains(&rhs, &lhs),
+            "not in" => !contains(&rhs, &lhs),
+            _ => match (lhs, rhs) {
+                (Some(TemplateValue::Number(a)), Some(TemplateValue::Number(b))) => {
+                    compare(op.as_str(), a.partial_cmp(&b))
+                }
+                (Some(TemplateValue::String(a)), Some(TemplateValue::String(b))) => {
+                    compare(op.as_str(), Some(a.cmp(&b)))
+                }
+                _ => false,
+            },
+        };
+        Some(Some(TemplateValue::Bool(result)))
+    }
+
+    fn operand(&mut self) -> Option<Option<TemplateValue>> {
+        let token = self.peek()?.to_string();
+        self.pos += 1;
+        if token == "(" {
+            let value = self.or_expr()?;
+            return self.eat(")").then_some(value);
+        }
+        if let Some(quote) = token.chars().next().filter(|c| *c == '"' || *c == '\'') {
+            let inner = token.strip_prefix(quote)?.strip_suffix(quote)?;
+            return Some(Some(TemplateValue::String(inner.to_string())));
+        }
+        Some(match token.as_str() {
+            "true" | "True" => Some(TemplateValue::Bool(true)),
+            "false" | "False" => Some(TemplateValue::Bool(false)),
+            "None" | "none" => None,
+            ")" | "==" | "!=" | "<" | ">" | "<=" | ">=" | "=" | "!" | "and" | "or" => return None,
+            _ => match token.parse::<f64>() {
+                Ok(n) => Some(TemplateValue::Number(n)),
+                Err(_) => evaluate_value(&token, self.context),
+            },
+        })
+    }
+}
+
+fn truthy(value: &Option<TemplateValue>) -> bool {
+    match value {
+        None => false,
+        Some(TemplateValue::Bool(b)) => *b,
+        Some(TemplateValue::Number(n)) => *n != 0.0,
+        Some(TemplateValue::String(s)) => !s.is_empty(),
+        Some(TemplateValue::List(items)) => !items.is_empty(),
+        Some(TemplateValue::Object(map)) => !map.is_empty(),
+    }
+}
+
+fn values_equal(a: &Option<TemplateValue>, b: &Option<TemplateValue>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(TemplateValue::Number(x)), Some(TemplateValue::Number(y))) => x == y,
+        (Some(TemplateValue::String(x)), Some(TemplateValue::String(y))) => x == y,
+        (Some(TemplateValue::Bool(x)), Some(TemplateValue::Bool(y))) => x == y,
+        (Some(TemplateValue::List(x)), Some(TemplateValue::List(y))) => {
+            x.len() == y.len()
+                && x.iter()
+                    .zip(y)
+                    .all(|(x, y)| values_equal(&Some(x.clone()), &Some(y.clone())))
+        }
+        _ => false,
+    }
+}
+
+fn contains(haystack: &Option<TemplateValue>, needle: &Option<TemplateValue>) -> bool {
+    match (haystack, needle) {
+        (Some(TemplateValue::List(items)), _) => items
+            .iter()
+            .any(|item| values_equal(&Some(item.clone()), needle)),
+        (Some(TemplateValue::String(s)), Some(TemplateValue::String(sub))) => {
+            s.contains(sub.as_str())
+        }
+        (Some(TemplateValue::Object(map)), Some(TemplateValue::String(key))) => {
+            map.contains_key(key)
+        }
+        _ => false,
+    }
+}
+
+fn compare(op: &str, ordering: Option<std::cmp::Ordering>) -> bool {
+    use std::cmp::Ordering::*;
+    matches!(
+        (op, ordering),
+        ("<", Some(Less))
+            | (">", Some(Greater))
+            | ("<=", Some(Less | Equal))
+            | (">=", Some(Greater | Equal))
+    )
+}
+
+/// Every block defined in `nodes`, nested ones included
+fn collect_blocks(nodes: &[Node], blocks: &mut HashMap<String, Vec<Node>>) {
+    for node in nodes {
+        match node {
+            Node::Block { name, body } => {
+                blocks.insert(name.clone(), body.clone());
+                collect_blocks(body, blocks);
+            }
+            Node::If {
+                then_body,
+                else_body,
+                ..
+            } => {
+                collect_blocks(then_body, blocks);
+                collect_blocks(else_body, blocks);
+            }
+            Node::For { body, .. }
+            | Node::Autoescape { body, .. }
+            | Node::Cache { body, .. }
+            | Node::With { body, .. } => collect_blocks(body, blocks),
+            _ => {}
+        }
+    }
+}
+
+/// `body` of an overriding block with each `{{ block.super }}` replaced by
+/// `parent`, the body it overrides. Nested blocks keep their own.
+fn substitute_super(body: &[Node], parent: &[Node]) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    for node in body {
+        match node {
+            Node::Variable(v) if v.trim() == "block.super" => nodes.extend_from_slice(parent),
+            Node::If {
+                condition,
+                then_body,
+                else_body,
+            } => nodes.push(Node::If {
+                condition: condition.clone(),
+                then_body: substitute_super(then_body, parent),
+                else_body: substitute_super(else_body, parent),
+            }),
+            Node::For {
+                var_name,
+                list_name,
+                body,
+            } => nodes.push(Node::For {
+                var_name: var_name.clone(),
+                list_name: list_name.clone(),
+                body: substitute_super(body, parent),
+            }),
+            Node::Autoescape { enabled, body } => nodes.push(Node::Autoescape {
+                enabled: *enabled,
+                body: substitute_super(body, parent),
+            }),
+            Node::Cache {
+                ttl,
+                name,
+                vary_on,
+                body,
+            } => nodes.push(Node::Cache {
+                ttl: ttl.clone(),
+                name: name.clone(),
+                vary_on: vary_on.clone(),
+                body: substitute_super(body, parent),
+            }),
+            Node::With { bindings, body } => nodes.push(Node::With {
+                bindings: bindings.clone(),
+                body: substitute_super(body, parent),
+            }),
+            other => nodes.push(other.clone()),
+        }
+    }
+    nodes
+}
+
 /// Merges child blocks into base AST by matching block names
 fn merge_blocks(nodes: &[Node], child_blocks: &HashMap<String, Vec<Node>>) -> Vec<Node> {
     nodes
@@ -252,7 +1119,7 @@ fn merge_blocks(nodes: &[Node], child_blocks: &HashMap<String, Vec<Node>>) -> Ve
                 if let Some(child) = child_blocks.get(name) {
                     Node::Block {
                         name: name.clone(),
-                        body: child.clone(),
+                        body: substitute_super(child, &merge_blocks(body, child_blocks)),
                     }
                 } else {
                     Node::Block {
@@ -279,23 +1146,143 @@ fn merge_blocks(nodes: &[Node], child_blocks: &HashMap<String, Vec<Node>>) -> Ve
                 list_name: list_name.clone(),
                 body: merge_blocks(body, child_blocks),
             },
+            Node::Autoescape { enabled, body } => Node::Autoescape {
+                enabled: *enabled,
+                body: merge_blocks(body, child_blocks),
+            },
+            Node::Cache {
+                ttl,
+                name,
+                vary_on,
+                body,
+            } => Node::Cache {
+                ttl: ttl.clone(),
+                name: name.clone(),
+                vary_on: vary_on.clone(),
+                body: merge_blocks(body, child_blocks),
+            },
+            Node::BlockTrans { .. } => node.clone(),
+            Node::With { bindings, body } => Node::With {
+                bindings: bindings.clone(),
+                body: merge_blocks(body, child_blocks),
+            },
+            Node::Set { .. } | Node::Unknown(_) => node.clone(),
             Node::Text(t) => Node::Text(t.clone()),
             Node::Variable(v) => Node::Variable(v.clone()),
             Node::Extends(e) => Node::Extends(e.clone()),
             Node::Tailwind => Node::Tailwind,
+            Node::Custom { name, args } => Node::Custom {
+                name: name.clone(),
+                args: args.clone(),
+            },
         })
         .collect()
 }
 
+/// Items `{% for %}` iterates over `value`: a list's items, or an object's
+/// keys in order (`key, value` pairs when `pairs`)
+fn loop_items(value: TemplateValue, pairs: bool) -> Vec<TemplateValue> {
+    match value {
+        TemplateValue::List(items) => items,
+        TemplateValue::Object(map) => {
+            let mut entries: Vec<(String, TemplateValue)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    if pairs {
+                        TemplateValue::List(vec![TemplateValue::String(key), value])
+                    } else {
+                        TemplateValue::String(key)
+                    }
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The `forloop` object visible inside `{% for %}`, Django-style.
+fn forloop(index: usize, length: usize) -> TemplateValue {
+    TemplateValue::Object(HashMap::from([
+        (
+            "counter".to_string(),
+            TemplateValue::Number((index + 1) as f64),
+        ),
+        ("counter0".to_string(), TemplateValue::Number(index as f64)),
+        ("first".to_string(), TemplateValue::Bool(index == 0)),
+        ("last".to_string(), TemplateValue::Bool(index + 1 == length)),
+        ("length".to_string(), TemplateValue::Number(length as f64)),
+    ]))
+}
+
 /// Renders the AST into HTML string using the context
+///
+/// Variable output is HTML-escaped unless it goes through the `safe` filter
+/// (`{{ html|safe }}`) or sits inside `{% autoescape off %}`. Custom tags are
+/// trusted and never escaped.
 pub fn render_nodes(nodes: &[Node], context: &HashMap<String, TemplateValue>) -> String {
+    render_escaped(nodes, context, true)
+}
+
+fn render_escaped(
+    nodes: &[Node],
+    context: &HashMap<String, TemplateValue>,
+    autoescape: bool,
+) -> String {
     let mut out = String::new();
-    for node in nodes {
+    render_to(nodes, context, autoescape, &mut out);
+    out
+}
+
+/// Where rendered HTML goes: a `String`, or the chunks of a streamed response
+trait Output {
+    fn push_str(&mut self, s: &str);
+
+    /// Whether the output is no longer read (a streaming client went away)
+    fn closed(&self) -> bool {
+        false
+    }
+}
+
+impl Output for String {
+    fn push_str(&mut self, s: &str) {
+        String::push_str(self, s);
+    }
+}
+
+fn render_to(
+    nodes: &[Node],
+    context: &HashMap<String, TemplateValue>,
+    autoescape: bool,
+    out: &mut dyn Output,
+) {
+    for (i, node) in nodes.iter().enumerate() {
+        if out.closed() {
+            return;
+        }
         match node {
             Node::Text(t) => out.push_str(t),
-            Node::Variable(name) => {
-                if let Some(val) = resolve_variable(name, context) {
-                    out.push_str(&val.as_string());
+            Node::Variable(expr) => {
+                let mut escape = autoescape;
+                for filter in split_filters(expr).into_iter().skip(1) {
+                    match filter {
+                        "safe" => escape = false,
+                        "escape" => escape = true,
+                        _ => {}
+                    }
+                }
+                if let Some(val) = evaluate_value(expr, context) {
+                    let text = val.as_string();
+                    if escape {
+                        out.push_str(&escape_html(&text));
+                    } else {
+                        out.push_str(&text);
+                    }
+                } else if let Some(error) =
+                    strict_error("undefined variable", split_filters(expr)[0])
+                {
+                    out.push_str(&error);
                 }
             }
             Node::If {
@@ -303,10 +1290,10 @@ pub fn render_nodes(nodes: &[Node], context: &HashMap<String, TemplateValue>) ->
                 then_body,
                 else_body,
             } => {
-                if let Some(TemplateValue::Bool(true)) = resolve_variable(condition, context) {
-                    out.push_str(&render_nodes(then_body, context));
+                if evaluate_condition(condition, context) {
+                    render_to(then_body, context, autoescape, out);
                 } else {
-                    out.push_str(&render_nodes(else_body, context));
+                    render_to(else_body, context, autoescape, out);
                 }
             }
             Node::For {
@@ -314,38 +1301,336 @@ pub fn render_nodes(nodes: &[Node], context: &HashMap<String, TemplateValue>) ->
                 list_name,
                 body,
             } => {
-                if let Some(TemplateValue::List(items)) =
-                    resolve_variable(list_name, context).cloned()
-                {
-                    for item in items {
-                        let mut local = context.clone();
-                        local.insert(var_name.clone(), item);
-                        out.push_str(&render_nodes(body, &local));
+                // `dict.items` iterates `dict` unless it has an `items` key
+                let value = resolve_variable(list_name, context).or_else(|| {
+                    list_name
+                        .strip_suffix(".items")
+                        .and_then(|name| resolve_variable(name, context))
+                });
+                let names: Vec<&str> = var_name.split(',').collect();
+                let items = value
+                    .cloned()
+                    .map(|value| loop_items(value, names.len() > 1))
+                    .unwrap_or_default();
+                let length = items.len();
+                for (i, item) in items.into_iter().enumerate() {
+                    let mut local = context.clone();
+                    match (names.as_slice(), item) {
+                        ([name], item) => {
+                            local.insert(name.to_string(), item);
+                        }
+                        (names, TemplateValue::List(parts)) => {
+                            let mut parts = parts.into_iter();
+                            for name in names {
+                                match parts.next() {
+                                    Some(part) => local.insert(name.to_string(), part),
+                                    None => local.remove(*name),
+                                };
+                            }
+                        }
+                        (names, item) => {
+                            local.insert(names[0].to_string(), item);
+                            for name in &names[1..] {
+                                local.remove(*name);
+                            }
+                        }
                     }
+                    local.insert("forloop".to_string(), forloop(i, length));
+                    render_to(body, &local, autoescape, out);
                 }
             }
-            Node::Block { body, .. } => {
-                out.push_str(&render_nodes(body, context));
+            Node::Block { body, .. } => render_to(body, context, autoescape, out),
+            Node::With { bindings, body } => {
+                let mut local = context.clone();
+                for (name, expr) in bindings {
+                    match evaluate_value(expr, context) {
+                        Some(value) => local.insert(name.clone(), value),
+                        None => local.remove(name),
+                    };
+                }
+                render_to(body, &local, autoescape, out);
             }
+            Node::Set { name, expr } => {
+                let mut local = context.clone();
+                match evaluate_value(expr, context) {
+                    Some(value) => local.insert(name.clone(), value),
+                    None => local.remove(name),
+                };
+                render_to(&nodes[i + 1..], &local, autoescape, out);
+                return;
+            }
+            Node::Autoescape { enabled, body } => render_to(body, context, *enabled, out),
             Node::Extends(_) => {}
+            Node::Unknown(tag) => {
+                if let Some(error) = strict_error("unknown tag", tag) {
+                    out.push_str(&error);
+                }
+            }
             Node::Tailwind => {
                 tdebug!("Inserting Tailwind CDN link");
                 out.push_str(r#"<script src="https://cdn.tailwindcss.com"></script>"#);
             }
+            Node::Custom { name, args } => {
+                let tag = CUSTOM_TAGS.read().unwrap().get(name).cloned();
+                if let Some(tag) = tag {
+                    out.push_str(&tag(args, context));
+                }
+            }
+            Node::Cache {
+                ttl,
+                name,
+                vary_on,
+                body,
+            } => {
+                out.push_str(&render_fragment(
+                    ttl, name, vary_on, body, context, autoescape,
+                ));
+            }
+            Node::BlockTrans {
+                singular,
+                plural,
+                count,
+            } => {
+                out.push_str(&render_blocktrans(
+                    singular, plural, count, context, autoescape,
+                ));
+            }
+        }
+    }
+}
+
+/// `{{ name }}` placeholders in a translated `blocktrans` message
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*(.*?)\s*\}\}").unwrap());
+
+/// Translates a `blocktrans` message and fills in its variables.
+fn render_blocktrans(
+    singular: &str,
+    plural: &Option<String>,
+    count: &Option<(String, String)>,
+    context: &HashMap<String, TemplateValue>,
+    autoescape: bool,
+) -> String {
+    let mut local;
+    let mut context = context;
+    let message = match (plural, count) {
+        (Some(plural), Some((name, value))) => {
+            let n = TagArgs::resolve(value, context)
+                .parse::<f64>()
+                .unwrap_or(0.0);
+            local = context.clone();
+            local.insert(name.clone(), TemplateValue::Number(n));
+            context = &local;
+            crate::i18n::ngettext(singular, plural, n.max(0.0) as u64)
+        }
+        _ => crate::i18n::gettext(singular),
+    };
+    PLACEHOLDER
+        .replace_all(&message, |caps: &regex::Captures| {
+            render_escaped(&[Node::Variable(caps[1].to_string())], context, autoescape)
+        })
+        .into_owned()
+}
+
+/// Cache key of a `{% cache %}` fragment rendered with `vary_on` values.
+pub fn fragment_cache_key(name: &str, vary_on: &[&str]) -> String {
+    use sha2::{Digest, Sha256};
+    let mut canonical = String::new();
+    for value in vary_on {
+        canonical.push_str(&value.len().to_string());
+        canonical.push(':');
+        canonical.push_str(value);
+    }
+    let hash: String = Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("fragment:{}:{}", name, hash)
+}
+
+/// Renders `{% cache ttl "name" vary... %}` through the default cache.
+///
+/// The TTL is in seconds (a number or a variable), `None` keeps the fragment
+/// until invalidated. Entries are tagged `fragment:<name>`, so
+/// `default_cache().invalidate_tag("fragment:sidebar")` drops every variant.
+/// A TTL that isn't a number renders the body uncached.
+fn render_fragment(
+    ttl: &str,
+    name: &str,
+    vary_on: &[String],
+    body: &[Node],
+    context: &HashMap<String, TemplateValue>,
+    autoescape: bool,
+) -> String {
+    let ttl = match TagArgs::resolve(ttl, context).as_str() {
+        "None" => None,
+        seconds => match seconds.parse::<f64>() {
+            Ok(seconds) if seconds > 0.0 => Some(std::time::Duration::from_secs_f64(seconds)),
+            _ => return render_escaped(body, context, autoescape),
+        },
+    };
+    let name = TagArgs::resolve(name, context);
+    let values: Vec<String> = vary_on
+        .iter()
+        .map(|v| TagArgs::resolve(v, context))
+        .collect();
+    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+    let key = fragment_cache_key(&name, &values);
+    let cache = crate::cache::default_cache();
+    if let Some(html) = cache.get(&key) {
+        tdebug!("cache tag: hit {}", key);
+        return html;
+    }
+    let html = render_escaped(body, context, autoescape);
+    cache.set_tagged(&key, html.clone(), ttl, &[&format!("fragment:{}", name)]);
+    html
+}
+
+/// Escapes `& < > " '` for safe inclusion in HTML text and attributes
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#x27;"),
+            _ => out.push(c),
         }
     }
     out
 }
 
-/// Main entry: loads child template, merges with base, and renders HTML
-pub fn render_template(template_name: &str, context: &HashMap<String, TemplateValue>) -> Response {
-    // Load child template
-    let child_path = format!("templates/{}", template_name);
-    let child = match std::fs::read_to_string(&child_path) {
-        Ok(c) => c,
-        Err(_) => {
-            return Response {
-                status_code: 404,
+/// A template engine instance: where templates live and how they are delimited
+///
+/// `Router::new` builds one from the settings (`TemplateEngine::from_settings`)
+/// and manages it, so handlers get it with `req.templates()`.
+#[derive(Debug, Clone)]
+pub struct TemplateEngine {
+    pub dir: String,
+    /// Directories searched, in order, for templates missing from `dir`
+    pub search_paths: Vec<String>,
+    pub delimiters: Delimiters,
+    /// Render undefined variables and unknown tags as visible errors
+    pub strict: bool,
+}
+
+impl Default for TemplateEngine {
+    fn default() -> Self {
+        TemplateEngine {
+            dir: "templates".to_string(),
+            search_paths: Vec::new(),
+            delimiters: Delimiters::default(),
+            strict: STRICT_DEFAULT.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl TemplateEngine {
+    pub fn new(dir: &str) -> Self {
+        TemplateEngine {
+            dir: dir.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Engine for `template.dir` and `template.dirs`, strict while
+    /// `template.debug` is set
+    pub fn from_settings(settings: &crate::settings::Settings) -> Self {
+        TemplateEngine {
+            dir: settings.template.dir.clone(),
+            search_paths: settings.template.dirs.clone(),
+            strict: settings.template.debug,
+            ..Default::default()
+        }
+    }
+
+    /// Builder for one more directory searched after the others
+    pub fn search_path(mut self, dir: &str) -> Self {
+        self.search_paths
+            .push(dir.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Builder for alternate delimiters (e.g. to generate files containing `{{ }}`)
+    pub fn with_delimiters(mut self, delimiters: Delimiters) -> Self {
+        self.delimiters = delimiters;
+        self
+    }
+
+    /// Builder for strict rendering: an undefined variable or unknown tag
+    /// renders as a visible error naming it and the line that mentions it
+    /// (`[undefined variable 'user.nmae' at profile.html:12]`) and is logged.
+    /// On by default while `template.debug` is set.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    fn parse(&self, content: &str) -> Vec<Node> {
+        let tokens = tokenize_with(content, &self.delimiters);
+        KEEP_UNKNOWN.with(|keep| keep.set(true));
+        let nodes = parse_tokens(&tokens);
+        KEEP_UNKNOWN.with(|keep| keep.set(false));
+        nodes
+    }
+
+    /// File of template `name`: in the engine's directory, else in the first
+    /// search path or added template directory that has it
+    fn path_of(&self, name: &str) -> String {
+        let own = format!("{}/{}", self.dir, name);
+        if std::path::Path::new(&own).is_file() {
+            return own;
+        }
+        let added = TEMPLATE_DIRS.read().unwrap().clone();
+        self.search_paths
+            .iter()
+            .chain(&added)
+            .map(|dir| format!("{}/{}", dir, name))
+            .find(|path| std::path::Path::new(path).is_file())
+            .unwrap_or(own)
+    }
+
+    /// Parsed template `name`, reusing the cached parse while the file is unchanged
+    fn load(&self, name: &str) -> Option<Arc<Vec<Node>>> {
+        let path = self.path_of(name);
+        if !CACHE_ENABLED.load(Ordering::Relaxed) {
+            let content = std::fs::read_to_string(&path).ok()?;
+            return Some(Arc::new(self.parse(&content)));
+        }
+        let meta = std::fs::metadata(&path).ok()?;
+        let stamp = (meta.modified().ok(), meta.len());
+        let key = (path, self.delimiters.clone());
+        if let Some(cached) = COMPILED.read().unwrap().get(&key) {
+            if cached.stamp == stamp {
+                return Some(cached.nodes.clone());
+            }
+        }
+        tdebug!("Parsing template '{}'", key.0);
+        let content = std::fs::read_to_string(&key.0).ok()?;
+        let nodes = Arc::new(self.parse(&content));
+        COMPILED.write().unwrap().insert(
+            key,
+            CompiledTemplate {
+                stamp,
+                nodes: nodes.clone(),
+            },
+        );
+        Some(nodes)
+    }
+
+    /// Template `name` merged into the template it extends, to be rendered
+    /// with `context` (context processors already applied); a 404 response
+    /// when it is missing
+    fn prepare(
+        &self,
+        template_name: &str,
+        context: HashMap<String, TemplateValue>,
+    ) -> Result<Prepared, Response> {
+        // Load child template
+        let Some(child_nodes) = self.load(template_name) else {
+            return Err(Response {
+                status: 404,
                 body: format!("Template '{}' not found", template_name),
                 headers: [(
                     "Content-Type".to_string(),
@@ -354,49 +1639,407 @@ pub fn render_template(template_name: &str, context: &HashMap<String, TemplateVa
                 .iter()
                 .cloned()
                 .collect(),
+                binary: None,
+                stream: None,
+            });
+        };
+
+        tdebug!("Child AST: {:?}", child_nodes);
+
+        // Follow the extends chain up to the root template, collecting the
+        // blocks of each level (child first)
+        let mut sources = vec![(template_name.to_string(), self.path_of(template_name))];
+        let mut levels = Vec::new();
+        let mut nodes = child_nodes;
+        while let Some(base) = nodes.iter().find_map(|node| match node {
+            Node::Extends(b) => Some(b.clone()),
+            _ => None,
+        }) {
+            if sources.iter().any(|(name, _)| *name == base) {
+                log::warn!(
+                    "template '{}' extends itself through '{}'",
+                    base,
+                    template_name
+                );
+                break;
+            }
+            let mut blocks = HashMap::new();
+            collect_blocks(&nodes, &mut blocks);
+            levels.push(blocks);
+            sources.push((base.clone(), self.path_of(&base)));
+            nodes = self.load(&base).unwrap_or_else(|| {
+                Arc::new(vec![Node::Text(format!("Template '{}' not found", base))])
+            });
+            tdebug!("Base AST: {:?}", nodes);
+        }
+
+        // Merge each level into the one it extends, from the root down
+        let merged = levels.iter().rev().fold(nodes.to_vec(), |merged, blocks| {
+            merge_blocks(&merged, blocks)
+        });
+        tdebug!("Merged AST: {:?}", merged);
+        Ok(Prepared {
+            nodes: merged,
+            context,
+            sources: self.strict.then_some(sources),
+        })
+    }
+
+    /// Loads child template, merges with base, and renders HTML
+    pub fn render(
+        &self,
+        template_name: &str,
+        context: &HashMap<String, TemplateValue>,
+    ) -> Response {
+        self.render_processed(template_name, apply_context_processors(context))
+    }
+
+    /// `render` with a context the processors have already been applied to
+    fn render_processed(
+        &self,
+        template_name: &str,
+        context: HashMap<String, TemplateValue>,
+    ) -> Response {
+        let Prepared {
+            nodes,
+            context,
+            sources,
+        } = match self.prepare(template_name, context) {
+            Ok(prepared) => prepared,
+            Err(not_found) => return not_found,
+        };
+        Response {
+            status: 200,
+            body: with_strict_sources(sources, || render_nodes(&nodes, &context)),
+            headers: [(
+                "Content-Type".to_string(),
+                "text/html; charset=utf-8".to_string(),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+            binary: None,
+            stream: None,
+        }
+    }
+
+    /// Like `render`, but the body is streamed while it renders, in chunks of
+    /// about `STREAM_CHUNK_BYTES`: the first bytes go out before a long loop
+    /// finishes and the page is never held in memory whole.
+    ///
+    /// Rendering runs on a blocking thread, with the current request, session,
+    /// locale and app state, and stops when the client goes away. Call it from
+    /// a handler (it needs the Tokio runtime).
+    pub fn render_stream(
+        &self,
+        template_name: &str,
+        context: &HashMap<String, TemplateValue>,
+    ) -> Response {
+        let Prepared {
+            nodes,
+            context,
+            sources,
+        } = match self.prepare(template_name, apply_context_processors(context)) {
+            Ok(prepared) => prepared,
+            Err(not_found) => return not_found,
+        };
+        let request = crate::router::current_request();
+        let session = crate::session::current_session();
+        let state = crate::state::current_state();
+        let locale = crate::i18n::current_locale();
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER_CHUNKS);
+
+        tokio::task::spawn_blocking(move || {
+            let render = move || {
+                let mut out = ChunkedOutput {
+                    buf: String::new(),
+                    tx,
+                    closed: false,
+                };
+                with_strict_sources(sources, || render_to(&nodes, &context, true, &mut out));
+                out.flush();
             };
+            let render = move || crate::i18n::with_locale_sync(&locale, render);
+            let render = move || match state {
+                Some(state) => crate::state::with_state_sync(state, render),
+                None => render(),
+            };
+            let render = move || match session {
+                Some(session) => crate::session::with_session_sync(session, render),
+                None => render(),
+            };
+            match request {
+                Some(request) => crate::router::with_request_scope_sync(request, render),
+                None => render(),
+            }
+        });
+
+        let chunks = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        });
+        Response::stream(chunks).add_header("Content-Type", "text/html; charset=utf-8")
+    }
+}
+
+/// A template ready to render
+struct Prepared {
+    nodes: Vec<Node>,
+    context: HashMap<String, TemplateValue>,
+    /// Name and path of the rendered files, when the engine is strict
+    sources: Option<Vec<(String, String)>>,
+}
+
+/// Size at which `render_stream` sends the HTML rendered so far.
+pub const STREAM_CHUNK_BYTES: usize = 8 * 1024;
+
+/// Chunks rendered ahead of a slow client before rendering waits.
+const STREAM_BUFFER_CHUNKS: usize = 4;
+
+/// `Output` sending chunks of `STREAM_CHUNK_BYTES` to a streamed response
+struct ChunkedOutput {
+    buf: String,
+    tx: tokio::sync::mpsc::Sender<Bytes>,
+    closed: bool,
+}
+
+impl ChunkedOutput {
+    fn flush(&mut self) {
+        if self.buf.is_empty() || self.closed {
+            return;
         }
-    };
-    let child_nodes = parse_tokens(&tokenize_template(&child));
-    tdebug!("Child AST: {:?}", child_nodes);
+        let chunk = Bytes::from(std::mem::take(&mut self.buf));
+        // The receiver is gone once the client disconnects
+        self.closed = self.tx.blocking_send(chunk).is_err();
+    }
+}
 
-    // Collect child blocks and detect base
-    let mut child_blocks = HashMap::new();
-    let mut base_t: Option<String> = None;
-    for node in &child_nodes {
-        if let Node::Extends(b) = node {
-            base_t = Some(b.clone());
+impl Output for ChunkedOutput {
+    fn push_str(&mut self, s: &str) {
+        self.buf.push_str(s);
+        if self.buf.len() >= STREAM_CHUNK_BYTES {
+            self.flush();
         }
-        if let Node::Block { name, body } = node {
-            child_blocks.insert(name.clone(), body.clone());
+    }
+
+    fn closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Which templates extend which, as found under an engine's directory
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependencyGraph {
+    /// Template name -> templates it directly depends on
+    pub edges: HashMap<String, Vec<String>>,
+}
+
+impl DependencyGraph {
+    /// Templates `name` directly depends on.
+    pub fn dependencies(&self, name: &str) -> Vec<String> {
+        self.edges.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Every template that depends on `name`, directly or transitively (sorted).
+    pub fn dependents(&self, name: &str) -> Vec<String> {
+        let mut found: Vec<String> = Vec::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(current) = pending.pop() {
+            for (child, parents) in &self.edges {
+                if parents.contains(&current) && !found.contains(child) && child != name {
+                    found.push(child.clone());
+                    pending.push(child.clone());
+                }
+            }
         }
+        found.sort();
+        found
     }
+}
 
-    // If extends, load base, merge and render
-    let html: String;
-    if let Some(base) = base_t {
-        let base_content = std::fs::read_to_string(format!("templates/{}", base))
-            .unwrap_or(format!("Template '{}' not found", base));
-        let base_nodes = parse_tokens(&tokenize_template(&base_content));
-        tdebug!("Base AST: {:?}", base_nodes);
-        let merged = merge_blocks(&base_nodes, &child_blocks);
-        tdebug!("Merged AST: {:?}", merged);
-        html = render_nodes(&merged, context);
-    } else {
-        // Otherwise, merge child blocks and render directly
-        let merged = merge_blocks(&child_nodes, &child_blocks);
-        html = render_nodes(&merged, context)
-    }
-
-    Response {
-        status_code: 200,
-        body: html.to_string(),
-        headers: [(
-            "Content-Type".to_string(),
-            "text/html; charset=utf-8".to_string(),
-        )]
+impl TemplateEngine {
+    /// Scan the template directory and map each template to the ones it extends.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        let root = std::path::Path::new(&self.dir);
+        let names = walkdir::WalkDir::new(root)
+            .into_iter()
+            .flatten()
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let rel = entry.path().strip_prefix(root).ok()?;
+                Some(rel.to_string_lossy().replace('\\', "/"))
+            });
+
+        let mut graph = DependencyGraph::default();
+        for name in names {
+            let Ok(content) = std::fs::read_to_string(root.join(&name)) else {
+                continue;
+            };
+            let parents: Vec<String> = self
+                .parse(&content)
+                .into_iter()
+                .filter_map(|node| match node {
+                    Node::Extends(base) => Some(base),
+                    _ => None,
+                })
+                .collect();
+            graph.edges.insert(name, parents);
+        }
+        graph
+    }
+
+    /// Drop cached renders of `name` and of every template depending on it.
+    ///
+    /// Returns how many cache entries were removed.
+    pub fn invalidate(&self, name: &str) -> usize {
+        let cache = crate::cache::default_cache();
+        std::iter::once(name.to_string())
+            .chain(self.dependency_graph().dependents(name))
+            .map(|t| cache.invalidate_tag(&format!("template:{}", t)))
+            .sum()
+    }
+}
+
+/// Apply `settings.template` (done by `Router::new`): parse caching and
+/// strict mode
+pub fn configure(settings: &crate::settings::Settings) {
+    set_template_cache(!settings.template.debug);
+    set_strict_default(settings.template.debug);
+}
+
+/// The engine managed by the router handling the current request (built from
+/// its settings), else `TemplateEngine::default()`
+pub fn default_engine() -> TemplateEngine {
+    crate::state::current_state()
+        .and_then(|state| state.get::<TemplateEngine>())
+        .unwrap_or_default()
+}
+
+impl crate::router::Request {
+    /// The router's template engine: `req.templates().render("index.html", &ctx)`.
+    pub fn templates(&self) -> TemplateEngine {
+        self.state::<TemplateEngine>().unwrap_or_default()
+    }
+}
+
+/// Main entry: renders a template with the default engine
+pub fn render_template(template_name: &str, context: &HashMap<String, TemplateValue>) -> Response {
+    default_engine().render(template_name, context)
+}
+
+/// `render_template`, streaming the body as it renders (see
+/// `TemplateEngine::render_stream`)
+pub fn stream_template(template_name: &str, context: &HashMap<String, TemplateValue>) -> Response {
+    default_engine().render_stream(template_name, context)
+}
+
+/// Stable SHA-256 of a context (object keys sorted), used as a cache key
+pub fn context_hash(context: &HashMap<String, TemplateValue>) -> String {
+    fn write(value: &TemplateValue, out: &mut String) {
+        match value {
+            TemplateValue::String(s) => {
+                out.push('s');
+                out.push_str(&s.len().to_string());
+                out.push(':');
+                out.push_str(s);
+            }
+            TemplateValue::Bool(b) => out.push_str(if *b { "T" } else { "F" }),
+            TemplateValue::Number(n) => {
+                out.push('n');
+                out.push_str(&n.to_string());
+                out.push(';');
+            }
+            TemplateValue::List(items) => {
+                out.push('[');
+                for item in items {
+                    write(item, out);
+                }
+                out.push(']');
+            }
+            TemplateValue::Object(map) => write_map(map, out),
+        }
+    }
+    fn write_map(map: &HashMap<String, TemplateValue>, out: &mut String) {
+        let mut keys: Vec<&String> = map.keys().collect();
+        keys.sort();
+        out.push('{');
+        for key in keys {
+            out.push_str(&key.len().to_string());
+            out.push(':');
+            out.push_str(key);
+            write(&map[key], out);
+        }
+        out.push('}');
+    }
+
+    use sha2::{Digest, Sha256};
+    let mut canonical = String::new();
+    write_map(context, &mut canonical);
+    Sha256::digest(canonical.as_bytes())
         .iter()
-        .cloned()
-        .collect(),
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Renders a template through the default cache, keyed by the resolved context hash.
+///
+/// Entries are tagged `template:<name>` and expire after `ttl`.
+pub fn render_cached(
+    template_name: &str,
+    context: &HashMap<String, TemplateValue>,
+    ttl: std::time::Duration,
+) -> Response {
+    render_cached_tagged(template_name, context, ttl, &[])
+}
+
+/// Like `render_cached`, with extra surrogate keys (e.g. `model:post`) so that
+/// the page can be dropped with `Cache::invalidate_tag`; see
+/// `invalidate_cached_pages` to do it whenever a model changes.
+pub fn render_cached_tagged(
+    template_name: &str,
+    context: &HashMap<String, TemplateValue>,
+    ttl: std::time::Duration,
+    surrogate_keys: &[&str],
+) -> Response {
+    let cache = crate::cache::default_cache();
+    let resolved = apply_context_processors(context);
+    let key = format!("page:{}:{}", template_name, context_hash(&resolved));
+    if let Some(html) = cache.get(&key) {
+        tdebug!("render_cached: hit {}", key);
+        return Response::html(html);
+    }
+    // The key already covers the processed context: render it as is rather
+    // than running the processors a second time
+    let response = default_engine().render_processed(template_name, resolved);
+    if response.status == 200 {
+        let template_tag = format!("template:{}", template_name);
+        let mut tags = vec![template_tag.as_str()];
+        tags.extend_from_slice(surrogate_keys);
+        cache.set_tagged(&key, response.body.clone(), Some(ttl), &tags);
+    }
+    response
+}
+
+/// Surrogate key of the cached pages showing `M` rows: `model:<table>`
+pub fn model_tag<M: crate::orm::Model>() -> String {
+    format!("model:{}", M::table_name())
+}
+
+/// Invalidate the cached pages tagged `model_tag::<M>()` whenever an `M` is
+/// saved or deleted. The signal receivers are connected once per model, so
+/// calling it again is harmless.
+pub fn invalidate_cached_pages<M: crate::orm::Model>() {
+    static CONNECTED: Lazy<std::sync::Mutex<std::collections::HashSet<std::any::TypeId>>> =
+        Lazy::new(Default::default);
+    if !CONNECTED
+        .lock()
+        .unwrap()
+        .insert(std::any::TypeId::of::<M>())
+    {
+        return;
+    }
+    for signal in [crate::signals::Signal::PostSave, crate::signals::Signal::PostDelete] {
+        crate::signals::connect::<M, _>(signal, |_, _| {
+            crate::cache::default_cache().invalidate_tag(&model_tag::<M>());
+        });
     }
 }
@@ -0,0 +1,159 @@
+//! Cobalto testing utilities
+//!
+//! `Client` sends requests straight to a `Router`, without binding a socket:
+//!
+//! ```ignore
+//! let client = Client::new(router);
+//! let response = client
+//!     .get("/users/1")
+//!     .header("Accept", "application/json")
+//!     .send()
+//!     .await;
+//! assert_eq!(response.status, 200);
+//! ```
+//!
+//! Cookies set by responses are kept and sent with later requests, so session
+//! logins carry over like in a browser.
+
+pub use crate::clock::{FreezeGuard, freeze_time};
+
+use crate::router::{Response, Router};
+use actix_web::web::Bytes;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// In-process client for a configured `Router`.
+pub struct Client {
+    router: Router,
+    cookies: Mutex<HashMap<String, String>>,
+}
+
+impl Client {
+    pub fn new(router: Router) -> Self {
+        Client {
+            router,
+            cookies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The router requests are sent to.
+    pub fn router(&self) -> &Router {
+        &self.router
+    }
+
+    /// Start a request; `path` may carry a query string.
+    pub fn request(&self, method: &str, path: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: self,
+            method: method.to_ascii_uppercase(),
+            path: path.to_string(),
+            headers: HashMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest<'_> {
+        self.request("GET", path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest<'_> {
+        self.request("POST", path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest<'_> {
+        self.request("PUT", path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest<'_> {
+        self.request("PATCH", path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest<'_> {
+        self.request("DELETE", path)
+    }
+
+    /// Value of a cookie the client holds.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.lock().unwrap().get(name).cloned()
+    }
+
+    /// Forget every cookie, e.g. to log out.
+    pub fn clear_cookies(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Keep the cookies a response sets; expired ones are dropped.
+    fn store_cookies(&self, response: &Response) {
+        let mut jar = self.cookies.lock().unwrap();
+        for header in response.cookies() {
+            let mut parts = header.split(';');
+            let Some((name, value)) = parts.next().and_then(|p| p.trim().split_once('=')) else {
+                continue;
+            };
+            let expired = parts.any(|attr| attr.trim().eq_ignore_ascii_case("max-age=0"));
+            if expired {
+                jar.remove(name);
+            } else {
+                jar.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+}
+
+/// A request being built by a `Client`.
+pub struct TestRequest<'a> {
+    client: &'a Client,
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Bytes,
+}
+
+impl TestRequest<'_> {
+    /// Builder for a request header
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .insert(name.to_ascii_lowercase(), value.to_string());
+        self
+    }
+
+    /// Builder for the raw body
+    pub fn body<B: Into<Bytes>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Builder for a JSON body, with its content type
+    pub fn json<T: Serialize>(self, value: &T) -> Self {
+        let body = serde_json::to_vec(value).expect("JSON body must serialize");
+        self.header("content-type", "application/json").body(body)
+    }
+
+    /// Builder for a urlencoded form body, with its content type
+    pub fn form<T: Serialize>(self, value: &T) -> Self {
+        let body = serde_urlencoded::to_string(value).expect("form body must serialize");
+        self.header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    /// Dispatch the request and return the handler's response.
+    pub async fn send(mut self) -> Response {
+        let jar = self.client.cookies.lock().unwrap().clone();
+        if !jar.is_empty() && !self.headers.contains_key("cookie") {
+            let header = jar
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join("; ");
+            self.headers.insert("cookie".to_string(), header);
+        }
+        let response = self
+            .client
+            .router
+            .dispatch_with_headers(&self.method, &self.path, self.headers, self.body)
+            .await;
+        self.client.store_cookies(&response);
+        response
+    }
+}
@@ -0,0 +1,203 @@
+//! Cobalto request throttling
+//!
+//! Named rate-limit scopes (e.g. `burst`, `sustained`) counted per identity —
+//! a user id or API key resolved from the request — instead of per IP.
+//! A `Throttle` wraps route handlers (or a whole route group, see `attach`),
+//! rejects over-quota requests with 429 and reports the tightest scope through
+//! `X-RateLimit-*` headers.
+
+use crate::clock;
+use crate::router::{Handler, Request, Response, RouteGroup};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A named fixed-window limit: `limit` requests per `window`.
+#[derive(Clone, Debug)]
+pub struct ThrottleScope {
+    pub name: String,
+    pub limit: u32,
+    pub window: Duration,
+}
+
+impl ThrottleScope {
+    pub fn new(name: &str, limit: u32, window: Duration) -> Self {
+        ThrottleScope {
+            name: name.to_string(),
+            limit,
+            window,
+        }
+    }
+
+    /// Short window for absorbing spikes (`burst`).
+    pub fn burst(limit: u32) -> Self {
+        Self::new("burst", limit, Duration::from_secs(60))
+    }
+
+    /// Long window for steady usage (`sustained`).
+    pub fn sustained(limit: u32) -> Self {
+        Self::new("sustained", limit, Duration::from_secs(24 * 60 * 60))
+    }
+}
+
+/// Resolves the throttling identity (user id, API key) of a request.
+pub type IdentityResolver = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// Outcome of a quota check for the tightest scope.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThrottleDecision {
+    pub allowed: bool,
+    pub scope: String,
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset_secs: u64,
+}
+
+/// Open windows by (scope, identity): start and hits so far.
+#[derive(Default)]
+struct Counters {
+    windows: HashMap<(String, String), (DateTime<Utc>, u32)>,
+    pruned_at: Option<DateTime<Utc>>,
+}
+
+/// Throttling policy shared by every route it wraps.
+#[derive(Clone)]
+pub struct Throttle {
+    pub scopes: Vec<ThrottleScope>,
+    identify: IdentityResolver,
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl Throttle {
+    pub fn new<F>(scopes: Vec<ThrottleScope>, identify: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        Throttle {
+            scopes,
+            identify: Arc::new(identify),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Check `identity` against every scope and return the tightest result.
+    ///
+    /// The hit is only counted when all scopes allow it, so rejected requests
+    /// do not eat into the quota of the other scopes.
+    pub fn check(&self, identity: &str) -> Option<ThrottleDecision> {
+        let now = clock::now();
+        let mut counters = self.counters.lock().unwrap();
+        self.prune(&mut counters, now);
+        let windows: Vec<(DateTime<Utc>, u32)> = self
+            .scopes
+            .iter()
+            .map(|scope| {
+                match counters.windows.get(&(scope.name.clone(), identity.to_string())) {
+                    Some(&(start, hits)) if elapsed(start, now) < scope.window => (start, hits),
+                    _ => (now, 0),
+                }
+            })
+            .collect();
+        let allowed = self
+            .scopes
+            .iter()
+            .zip(&windows)
+            .all(|(scope, &(_, hits))| hits < scope.limit);
+        let mut tightest: Option<ThrottleDecision> = None;
+        for (scope, &(start, hits)) in self.scopes.iter().zip(&windows) {
+            let hits = if allowed { hits + 1 } else { hits };
+            if allowed {
+                counters
+                    .windows
+                    .insert((scope.name.clone(), identity.to_string()), (start, hits));
+            }
+            let decision = ThrottleDecision {
+                allowed: allowed || hits < scope.limit,
+                scope: scope.name.clone(),
+                limit: scope.limit,
+                remaining: scope.limit.saturating_sub(hits),
+                reset_secs: scope.window.saturating_sub(elapsed(start, now)).as_secs(),
+            };
+            let tighter = match &tightest {
+                None => true,
+                Some(t) => (!decision.allowed && t.allowed) || decision.remaining < t.remaining,
+            };
+            if tighter {
+                tightest = Some(decision);
+            }
+        }
+        tightest
+    }
+
+    /// Number of open windows currently tracked.
+    pub fn tracked_windows(&self) -> usize {
+        self.counters.lock().unwrap().windows.len()
+    }
+
+    /// Drop expired windows, at most once per shortest scope window.
+    fn prune(&self, counters: &mut Counters, now: DateTime<Utc>) {
+        let Some(interval) = self.scopes.iter().map(|s| s.window).min() else {
+            return;
+        };
+        if counters
+            .pruned_at
+            .is_some_and(|at| elapsed(at, now) < interval)
+        {
+            return;
+        }
+        counters.pruned_at = Some(now);
+        let windows: HashMap<&str, Duration> = self
+            .scopes
+            .iter()
+            .map(|s| (s.name.as_str(), s.window))
+            .collect();
+        counters.windows.retain(|(scope, _), (start, _)| {
+            windows
+                .get(scope.as_str())
+                .is_some_and(|window| elapsed(*start, now) < *window)
+        });
+    }
+
+    /// Wrap a handler so that it is throttled by this policy.
+    ///
+    /// Requests without a resolvable identity pass through unthrottled.
+    pub fn wrap(&self, handler: Handler) -> Handler {
+        let throttle = self.clone();
+        Arc::new(move |req| {
+            let throttle = throttle.clone();
+            let handler = handler.clone();
+            Box::pin(async move {
+                let decision = (throttle.identify)(&req).and_then(|id| throttle.check(&id));
+                match decision {
+                    Some(d) if !d.allowed => {
+                        with_rate_headers(Response::html("Too Many Requests").with_status(429), &d)
+                            .add_header("Retry-After".to_string(), d.reset_secs.to_string())
+                    }
+                    Some(d) => with_rate_headers(handler(req).await, &d),
+                    None => handler(req).await,
+                }
+            })
+        })
+    }
+
+    /// Throttle every route of `group` with this policy.
+    ///
+    /// `router.group("/api", |g| { throttle.attach(g); g.get("/users", list_users); })`
+    pub fn attach(&self, group: &mut RouteGroup) {
+        let throttle = self.clone();
+        group.wrap_handlers(move |handler| throttle.wrap(handler));
+    }
+}
+
+fn elapsed(since: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    (now - since).to_std().unwrap_or_default()
+}
+
+/// Adds `X-RateLimit-*` headers describing a decision.
+pub fn with_rate_headers(resp: Response, d: &ThrottleDecision) -> Response {
+    resp.add_header("X-RateLimit-Limit".to_string(), d.limit.to_string())
+        .add_header("X-RateLimit-Remaining".to_string(), d.remaining.to_string())
+        .add_header("X-RateLimit-Reset".to_string(), d.reset_secs.to_string())
+        .add_header("X-RateLimit-Scope".to_string(), d.scope.clone())
+}
@@ -0,0 +1,105 @@
+//! Cobalto HTTPS
+//!
+//! With `tls.cert` and `tls.key` set, `Router::run()` serves HTTPS through
+//! rustls (HTTP/2 negotiated by ALPN). `tls.redirect_port` additionally starts
+//! a plain-HTTP listener that answers every request with a permanent redirect
+//! to the HTTPS URL:
+//!
+//! ```toml
+//! port = 443
+//!
+//! [tls]
+//! cert = "/etc/letsencrypt/live/example.com/fullchain.pem"
+//! key = "/etc/letsencrypt/live/example.com/privkey.pem"
+//! redirect_port = 80
+//! ```
+
+use actix_web::{HttpRequest, HttpResponse};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fs::File;
+use std::io::{self, BufReader};
+
+/// Server configuration from a PEM certificate chain and private key.
+pub fn load_rustls_config(cert_path: &str, key_path: &str) -> io::Result<rustls::ServerConfig> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+    };
+    let certs: Vec<CertificateDer<'static>> =
+        rustls_pemfile::certs(&mut open(cert_path)?).collect::<Result<_, _>>()?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: no certificate found", cert_path),
+        ));
+    }
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut open(key_path)?)?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: no private key found", key_path),
+            )
+        })?;
+    let mut config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// HTTPS URL for a request that arrived as `host` (possibly with a port) and
+/// `path_and_query`, on `https_port`.
+pub fn https_url(host: &str, path_and_query: &str, https_port: u16) -> String {
+    // Strip the plain-HTTP port, keeping bracketed IPv6 addresses whole
+    let hostname = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    let path = if path_and_query.is_empty() {
+        "/"
+    } else {
+        path_and_query
+    };
+    match https_port {
+        443 => format!("https://{}{}", hostname, path),
+        port => format!("https://{}:{}{}", hostname, port, path),
+    }
+}
+
+/// Plain-HTTP server redirecting everything on `host:redirect_port` to HTTPS.
+pub fn redirect_server(
+    host: &str,
+    redirect_port: u16,
+    https_port: u16,
+) -> io::Result<actix_web::dev::Server> {
+    let fallback_host = host.to_string();
+    let server = actix_web::HttpServer::new(move || {
+        let fallback_host = fallback_host.clone();
+        actix_web::App::new().default_service(actix_web::web::to(move |req: HttpRequest| {
+            let host = req
+                .headers()
+                .get("host")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or(&fallback_host)
+                .to_string();
+            let path = req
+                .uri()
+                .path_and_query()
+                .map(|pq| pq.as_str().to_string())
+                .unwrap_or_default();
+            let location = https_url(&host, &path, https_port);
+            async move {
+                HttpResponse::PermanentRedirect()
+                    .insert_header(("Location", location))
+                    .finish()
+            }
+        }))
+    })
+    .workers(1)
+    .disable_signals()
+    .bind((host, redirect_port))?
+    .run();
+    Ok(server)
+}
@@ -0,0 +1,188 @@
+//! Cobalto WebSocket routes
+//!
+//! `router.add_websocket("/ws/chat/:room", handler)` upgrades matching requests
+//! and runs `handler` with a `WsContext` (path and query parameters, app state)
+//! and a `WebSocket`, a plain message channel to the client: `recv()` yields
+//! incoming messages until the client goes away and `send()` writes back.
+//! The socket itself is pumped by the router, so handlers are ordinary `Send`
+//! futures and can be tested against `Router::connect_websocket`.
+
+use crate::state::AppState;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Messages buffered per direction before `send` waits.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// A WebSocket message as seen by handlers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WsMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+/// The other side has gone away.
+#[derive(Debug)]
+pub struct WsClosed;
+
+impl std::fmt::Display for WsClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "websocket closed")
+    }
+}
+
+impl std::error::Error for WsClosed {}
+
+/// Cloneable sending half, for writing from other tasks (e.g. a pubsub listener).
+#[derive(Clone)]
+pub struct WsSender {
+    tx: mpsc::Sender<WsMessage>,
+}
+
+impl WsSender {
+    pub async fn send(&self, msg: WsMessage) -> Result<(), WsClosed> {
+        self.tx.send(msg).await.map_err(|_| WsClosed)
+    }
+
+    pub async fn send_text<S: Into<String>>(&self, text: S) -> Result<(), WsClosed> {
+        self.send(WsMessage::Text(text.into())).await
+    }
+}
+
+/// One end of a WebSocket connection.
+pub struct WebSocket {
+    rx: mpsc::Receiver<WsMessage>,
+    sender: WsSender,
+}
+
+impl WebSocket {
+    /// Two connected in-memory ends: what one sends the other receives.
+    pub fn pair() -> (WebSocket, WebSocket) {
+        let (a_tx, a_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (b_tx, b_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        (
+            WebSocket {
+                rx: a_rx,
+                sender: WsSender { tx: b_tx },
+            },
+            WebSocket {
+                rx: b_rx,
+                sender: WsSender { tx: a_tx },
+            },
+        )
+    }
+
+    /// Next message; `None` once the peer has closed the connection.
+    pub async fn recv(&mut self) -> Option<WsMessage> {
+        match self.rx.recv().await {
+            Some(WsMessage::Close) | None => None,
+            Some(msg) => Some(msg),
+        }
+    }
+
+    pub async fn send(&self, msg: WsMessage) -> Result<(), WsClosed> {
+        self.sender.send(msg).await
+    }
+
+    pub async fn send_text<S: Into<String>>(&self, text: S) -> Result<(), WsClosed> {
+        self.sender.send_text(text).await
+    }
+
+    /// A sending handle usable while this end is busy in `recv`.
+    pub fn sender(&self) -> WsSender {
+        self.sender.clone()
+    }
+
+    /// Close the connection from this side.
+    pub async fn close(self) {
+        let _ = self.sender.send(WsMessage::Close).await;
+    }
+}
+
+/// What a WebSocket handler knows about the connection.
+#[derive(Clone, Default)]
+pub struct WsContext {
+    pub path: String,
+    pub params: HashMap<String, String>,
+    pub query: HashMap<String, String>,
+    pub(crate) state: AppState,
+}
+
+impl WsContext {
+    /// A value registered with `Router::manage`.
+    pub fn state<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.state.get::<T>()
+    }
+
+    /// Parse a path parameter, like `Request::param`.
+    pub fn param<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.params.get(name)?.parse().ok()
+    }
+}
+
+/// WebSocket handler: runs for the lifetime of the connection.
+pub type WsHandler = Arc<
+    dyn Fn(WsContext, WebSocket) -> Pin<Box<dyn std::future::Future<Output = ()> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// A registered WebSocket route.
+#[derive(Clone)]
+pub struct WsRoute {
+    pub path_pattern: String,
+    pub handler: WsHandler,
+}
+
+/// Actix resource pattern for a Cobalto path (`/ws/:room<i64>` → `/ws/{room}`).
+pub(crate) fn actix_pattern(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => {
+                let name = name.split(['<', '|']).next().unwrap_or(name);
+                format!("{{{}}}", name)
+            }
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Relay between an upgraded actix connection and the handler's `WebSocket`.
+pub(crate) async fn pump(
+    mut session: actix_ws::Session,
+    mut stream: actix_ws::MessageStream,
+    mut client: WebSocket,
+) {
+    use actix_ws::Message;
+    loop {
+        tokio::select! {
+            incoming = stream.recv() => {
+                let forwarded = match incoming {
+                    Some(Ok(Message::Text(text))) => client.send(WsMessage::Text(text.to_string())).await,
+                    Some(Ok(Message::Binary(bytes))) => client.send(WsMessage::Binary(bytes.to_vec())).await,
+                    Some(Ok(Message::Ping(bytes))) => session.pong(&bytes).await.map_err(|_| WsClosed),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => Err(WsClosed),
+                    Some(Ok(_)) => Ok(()),
+                };
+                if forwarded.is_err() {
+                    break;
+                }
+            }
+            outgoing = client.recv() => {
+                let sent = match outgoing {
+                    Some(WsMessage::Text(text)) => session.text(text).await,
+                    Some(WsMessage::Binary(bytes)) => session.binary(bytes).await,
+                    Some(WsMessage::Close) | None => break,
+                };
+                if sent.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = session.close(None).await;
+}
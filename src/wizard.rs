@@ -0,0 +1,201 @@
+//! Cobalto Form Wizard
+//!
+//! Chains several forms across requests. Each step has its own template and
+//! validator; submitted data is persisted between requests in the session
+//! under `wizard:<id>`, and the `done` callback receives every step's data once
+//! the last step validates.
+//!
+//! Navigation is driven by the `wizard_action` form field: `back` returns to
+//! the previous step, anything else submits the current one.
+
+use crate::router::{Request, Response, parse_urlencoded};
+use crate::session::Session;
+use crate::template::{TemplateEngine, TemplateValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Submitted form data for a single step.
+pub type FormData = HashMap<String, String>;
+
+/// Validator returning per-field error messages on failure.
+pub type StepValidator =
+    Arc<dyn Fn(&FormData) -> Result<(), HashMap<String, String>> + Send + Sync>;
+
+/// Final callback receiving the data of all steps in order.
+pub type DoneHandler = Arc<dyn Fn(&[FormData]) -> Response + Send + Sync>;
+
+/// One page of the wizard.
+#[derive(Clone)]
+pub struct WizardStep {
+    pub name: String,
+    pub template: String,
+    pub validator: Option<StepValidator>,
+}
+
+impl WizardStep {
+    pub fn new(name: &str, template: &str) -> Self {
+        WizardStep {
+            name: name.to_string(),
+            template: template.to_string(),
+            validator: None,
+        }
+    }
+
+    /// Builder for attaching a validator to the step
+    pub fn validate<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&FormData) -> Result<(), HashMap<String, String>> + Send + Sync + 'static,
+    {
+        self.validator = Some(Arc::new(f));
+        self
+    }
+}
+
+/// Progress persisted between requests.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct WizardState {
+    pub current: usize,
+    pub data: Vec<FormData>,
+}
+
+/// A multi-step form.
+pub struct FormWizard {
+    pub id: String,
+    pub steps: Vec<WizardStep>,
+    pub done: DoneHandler,
+}
+
+impl FormWizard {
+    pub fn new<F>(id: &str, steps: Vec<WizardStep>, done: F) -> Self
+    where
+        F: Fn(&[FormData]) -> Response + Send + Sync + 'static,
+    {
+        FormWizard {
+            id: id.to_string(),
+            steps,
+            done: Arc::new(done),
+        }
+    }
+
+    fn session_key(&self) -> String {
+        format!("wizard:{}", self.id)
+    }
+
+    /// Load the wizard state from the session (fresh state when absent, or
+    /// when it points past the last step, e.g. after steps were removed).
+    pub fn load_state(&self, session: &Session) -> WizardState {
+        session
+            .get(&self.session_key())
+            .and_then(|raw| serde_json::from_str::<WizardState>(&raw).ok())
+            .filter(|state| state.current < self.steps.len())
+            .unwrap_or_default()
+    }
+
+    /// Store the wizard state into the session.
+    pub fn save_state(&self, session: &Session, state: &WizardState) {
+        if let Ok(raw) = serde_json::to_string(state) {
+            session.set(self.session_key(), raw);
+        }
+    }
+
+    /// Render the current step (GET requests).
+    pub fn render(&self, req: &Request, session: &Session) -> Response {
+        let state = self.load_state(session);
+        self.render_step(&req.templates(), &state, &HashMap::new())
+    }
+
+    /// Handle a submitted step (POST requests), updating the session.
+    pub fn handle(&self, req: &Request, session: &Session) -> Response {
+        let templates = req.templates();
+        let mut state = self.load_state(session);
+        let mut form = parse_urlencoded(&req.body);
+        let action = form.remove("wizard_action").unwrap_or_default();
+
+        if action == "back" {
+            state.current = state.current.saturating_sub(1);
+            self.save_state(session, &state);
+            return self.render_step(&templates, &state, &HashMap::new());
+        }
+
+        let step = match self.steps.get(state.current) {
+            Some(step) => step,
+            None => return Response::html("Wizard has no steps").with_status(500),
+        };
+        if let Some(validator) = &step.validator {
+            if let Err(errors) = validator(&form) {
+                return self
+                    .render_step_with(&templates, &state, &form, &errors)
+                    .with_status(422);
+            }
+        }
+
+        if state.data.len() <= state.current {
+            state.data.resize(state.current + 1, FormData::new());
+        }
+        state.data[state.current] = form;
+
+        if state.current + 1 >= self.steps.len() {
+            session.delete(&self.session_key());
+            return (self.done)(&state.data);
+        }
+        state.current += 1;
+        self.save_state(session, &state);
+        self.render_step(&templates, &state, &HashMap::new())
+    }
+
+    fn render_step(
+        &self,
+        templates: &TemplateEngine,
+        state: &WizardState,
+        errors: &HashMap<String, String>,
+    ) -> Response {
+        let previous = state.data.get(state.current).cloned().unwrap_or_default();
+        self.render_step_with(templates, state, &previous, errors)
+    }
+
+    fn render_step_with(
+        &self,
+        templates: &TemplateEngine,
+        state: &WizardState,
+        form: &FormData,
+        errors: &HashMap<String, String>,
+    ) -> Response {
+        let step = match self.steps.get(state.current) {
+            Some(step) => step,
+            None => return Response::html("Wizard has no steps").with_status(500),
+        };
+        let to_object = |map: &HashMap<String, String>| {
+            TemplateValue::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), TemplateValue::String(v.clone())))
+                    .collect(),
+            )
+        };
+        let mut wizard = HashMap::new();
+        wizard.insert("id".to_string(), TemplateValue::String(self.id.clone()));
+        wizard.insert("step".to_string(), TemplateValue::String(step.name.clone()));
+        wizard.insert(
+            "step_number".to_string(),
+            TemplateValue::Number((state.current + 1) as f64),
+        );
+        wizard.insert(
+            "step_count".to_string(),
+            TemplateValue::Number(self.steps.len() as f64),
+        );
+        wizard.insert(
+            "has_prev".to_string(),
+            TemplateValue::Bool(state.current > 0),
+        );
+        wizard.insert(
+            "is_last".to_string(),
+            TemplateValue::Bool(state.current + 1 == self.steps.len()),
+        );
+
+        let mut context = HashMap::new();
+        context.insert("wizard".to_string(), TemplateValue::Object(wizard));
+        context.insert("form".to_string(), to_object(form));
+        context.insert("errors".to_string(), to_object(errors));
+        templates.render(&step.template, &context)
+    }
+}
@@ -0,0 +1,131 @@
+use cobalto::admin::Admin;
+use cobalto::orm::{Backend, Db, Field, FieldType, Model, create_table_sql};
+use cobalto::router::Router;
+use cobalto::settings::Settings;
+
+struct Post;
+
+impl Model for Post {
+    fn table_name() -> &'static str {
+        "post"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("title", FieldType::Text).max_length(20),
+            Field::new("views", FieldType::Integer).default("0"),
+        ]
+    }
+}
+
+async fn admin_router(per_page: usize) -> (Router, Db) {
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Post>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    let mut router = Router::new(Settings::default());
+    router.manage(db.clone());
+    Admin::new("/admin")
+        .register::<Post>()
+        .per_page(per_page)
+        .guard(|_| true)
+        .mount(&mut router);
+    (router, db)
+}
+
+#[tokio::test]
+async fn test_admin_create_edit_delete() {
+    let (router, db) = admin_router(25).await;
+
+    let index = router.dispatch("GET", "/admin", "").await;
+    assert_eq!(index.status, 200);
+    assert!(index.body.contains(r#"<a href="/admin/post">Post</a>"#));
+    assert!(
+        router
+            .dispatch("GET", "/admin/post/new", "")
+            .await
+            .body
+            .contains(r#"name="title""#)
+    );
+
+    let created = router
+        .dispatch("POST", "/admin/post/new", "title=Hello+admin&views=")
+        .await;
+    assert_eq!(created.status, 302);
+    let (title, views): (String, i64) = sqlx::query_as("SELECT title, views FROM post")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!((title.as_str(), views), ("Hello admin", 0));
+
+    let edit = router.dispatch("GET", "/admin/post/1", "").await;
+    assert!(edit.body.contains("Hello admin"));
+    let invalid = router
+        .dispatch("POST", "/admin/post/1", "title=&views=abc")
+        .await;
+    assert_eq!(invalid.status, 400);
+    assert!(invalid.body.contains("Enter a whole number."));
+    let updated = router
+        .dispatch("POST", "/admin/post/1", "title=Renamed&views=7")
+        .await;
+    assert_eq!(updated.status, 302);
+    assert!(
+        router
+            .dispatch("GET", "/admin/post", "")
+            .await
+            .body
+            .contains("Renamed")
+    );
+
+    assert_eq!(
+        router
+            .dispatch("POST", "/admin/post/1/delete", "")
+            .await
+            .status,
+        302
+    );
+    assert_eq!(
+        router.dispatch("GET", "/admin/post/1", "").await.status,
+        404
+    );
+    assert_eq!(
+        router.dispatch("GET", "/admin/missing", "").await.status,
+        404
+    );
+}
+
+#[tokio::test]
+async fn test_admin_list_search_and_pagination() {
+    let (router, db) = admin_router(2).await;
+    for title in ["apple", "banana", "cherry", "<b>apricot</b>"] {
+        sqlx::query("INSERT INTO post (title, views) VALUES (?, 1)")
+            .bind(title)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+    }
+
+    let first = router.dispatch("GET", "/admin/post", "").await.body;
+    assert!(first.contains("Page 1 of 2"));
+    assert!(first.contains("&lt;b&gt;apricot&lt;/b&gt;"));
+    assert!(!first.contains("banana"));
+    assert!(first.contains(r#"href="/admin/post?page=2""#));
+
+    let second = router.dispatch("GET", "/admin/post?page=2", "").await.body;
+    assert!(second.contains("banana") && second.contains("apple"));
+
+    let found = router.dispatch("GET", "/admin/post?q=ap", "").await.body;
+    assert!(found.contains("2 rows"));
+    assert!(found.contains("apple") && !found.contains("cherry"));
+}
+
+#[tokio::test]
+async fn test_admin_default_guard_requires_staff_session() {
+    let db = Db::connect(":memory:").await.unwrap();
+    let mut router = Router::new(Settings::default());
+    router.manage(db);
+    Admin::new("/admin").register::<Post>().mount(&mut router);
+
+    assert_eq!(router.dispatch("GET", "/admin", "").await.status, 403);
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "alloc-stats")]
+
+use cobalto::alloc_stats::{self, CountingAllocator};
+use cobalto::router::{Handler, Request, Response};
+use cobalto::settings::Settings;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+#[tokio::test]
+async fn test_measure_counts_future_allocations() {
+    let (len, stats) = alloc_stats::measure(async {
+        let v: Vec<u8> = Vec::with_capacity(4096);
+        v.capacity()
+    })
+    .await;
+    assert_eq!(len, 4096);
+    assert!(stats.allocations >= 1);
+    assert!(stats.bytes >= 4096);
+    assert!(alloc_stats::totals().bytes >= stats.bytes);
+}
+
+#[tokio::test]
+async fn test_wrap_adds_alloc_headers_in_debug() {
+    let handler: Handler = Arc::new(|_| Box::pin(async { Response::html("x".repeat(100)) }));
+    let settings = Settings {
+        debug: true,
+        ..Settings::default()
+    };
+    let resp = alloc_stats::wrap(handler.clone(), &settings)(Request {
+        params: HashMap::new(),
+        body: String::new(),
+        query: HashMap::new(),
+        ..Default::default()
+    })
+    .await;
+    let bytes: u64 = resp.headers["X-Alloc-Bytes"].parse().unwrap();
+    assert!(bytes >= 100);
+
+    let settings = Settings {
+        debug: false,
+        ..Settings::default()
+    };
+    let resp = alloc_stats::wrap(handler, &settings)(Request::default()).await;
+    assert!(!resp.headers.contains_key("X-Alloc-Count"));
+    assert!(!resp.headers.contains_key("X-Alloc-Bytes"));
+
+    let metrics = cobalto::metrics::render();
+    assert!(metrics.contains("cobalto_alloc_allocations_total"));
+    assert!(metrics.contains("cobalto_alloc_bytes_total"));
+}
+
+#[test]
+fn test_register_metrics_once() {
+    alloc_stats::register_metrics();
+    alloc_stats::register_metrics();
+    let metrics = cobalto::metrics::render();
+    let exported: u64 = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("cobalto_alloc_bytes_total "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    // Registered twice, the totals would be summed twice
+    assert!(exported <= alloc_stats::totals().bytes);
+}
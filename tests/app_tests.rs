@@ -0,0 +1,127 @@
+use cobalto::app::*;
+use cobalto::orm::{Db, Field, FieldType, Model};
+use cobalto::router::{Response, Router, reverse};
+use cobalto::settings::Settings;
+use cobalto::template::TemplateEngine;
+use std::collections::HashMap;
+
+struct Post;
+
+impl Model for Post {
+    fn table_name() -> &'static str {
+        "app_post"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("title", FieldType::Text),
+        ]
+    }
+}
+
+fn blog() -> App {
+    let mut blog = App::new("blog", "/blog/");
+    blog.routes(|g| {
+        g.get("/", |_| async { Response::html("posts") })
+            .name("index");
+        g.get("/:slug", |_| async { Response::html("post") })
+            .name("post");
+    })
+    .model::<Post>()
+    .migration(
+        "0002_summary",
+        "ALTER TABLE app_post ADD COLUMN summary TEXT",
+    );
+    blog
+}
+
+#[tokio::test]
+async fn test_mounted_app_routes_are_prefixed_and_namespaced() {
+    let mut router = Router::new(Settings::default());
+    router.mount(blog());
+    let mut shop = App::new("shop", "/shop");
+    shop.routes(|g| {
+        g.get("/", |_| async { Response::html("products") })
+            .name("index");
+        g.get("/links", |_| async {
+            Response::html(format!(
+                "{:?} {:?} {:?}",
+                reverse("blog:post", &[("slug", "hello")]),
+                reverse("shop:index", &[]),
+                reverse("index", &[]),
+            ))
+        });
+    });
+    router.mount(shop);
+
+    assert_eq!(router.apps(), vec!["blog", "shop"]);
+    assert_eq!(router.dispatch("GET", "/blog/hello", "").await.body, "post");
+    assert_eq!(router.dispatch("GET", "/shop", "").await.body, "products");
+    assert_eq!(
+        router.dispatch("GET", "/shop/links", "").await.body,
+        r#"Some("/blog/hello") Some("/shop") None"#
+    );
+    assert_eq!(router.url_for("blog:index", &[]).as_deref(), Some("/blog"));
+}
+
+#[tokio::test]
+async fn test_migrate_creates_tables_and_applies_migrations_once() {
+    let mut router = Router::new(Settings::default());
+    router.mount(blog());
+    let db = Db::connect(":memory:").await.unwrap();
+
+    let applied = router.migrate(&db).await.unwrap();
+    assert_eq!(applied, vec!["blog.0002_summary"]);
+    db.execute("INSERT INTO app_post (id, title, summary) VALUES (1, 'a', 'b')")
+        .await
+        .unwrap();
+
+    // Already applied: a second run is a no-op
+    assert!(router.migrate(&db).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_failed_migration_record_rolls_the_migration_back() {
+    let mut router = Router::new(Settings::default());
+    router.mount(blog());
+    let db = Db::connect(":memory:").await.unwrap();
+    // A migrations table refusing the record of `0002_summary`
+    db.execute(
+        "CREATE TABLE cobalto_migrations (app VARCHAR(100) NOT NULL, \
+         name VARCHAR(200) NOT NULL CHECK (name <> '0002_summary'), \
+         applied_at VARCHAR(40) NOT NULL, PRIMARY KEY (app, name))",
+    )
+    .await
+    .unwrap();
+
+    assert!(router.migrate(&db).await.is_err());
+    // The column was not added, so the migration can run again later
+    assert!(
+        db.execute("INSERT INTO app_post (id, title, summary) VALUES (1, 'a', 'b')")
+            .await
+            .is_err()
+    );
+}
+
+#[test]
+fn test_app_templates_are_searched_after_the_project_dir() {
+    let root = std::env::temp_dir().join(format!("cobalto_app_{}", std::process::id()));
+    let project = root.join("templates");
+    let app_dir = root.join("blog_templates");
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::create_dir_all(&app_dir).unwrap();
+    std::fs::write(project.join("shared.html"), "project").unwrap();
+    std::fs::write(app_dir.join("shared.html"), "app").unwrap();
+    std::fs::write(app_dir.join("blog_list.html"), "blog list").unwrap();
+
+    let mut app = App::new("blog_templates", "/b");
+    app.templates(app_dir.to_str().unwrap());
+    Router::new(Settings::default()).mount(app);
+
+    let engine = TemplateEngine::new(project.to_str().unwrap());
+    let context = HashMap::new();
+    assert_eq!(engine.render("blog_list.html", &context).body, "blog list");
+    assert_eq!(engine.render("shared.html", &context).body, "project");
+    std::fs::remove_dir_all(&root).unwrap();
+}
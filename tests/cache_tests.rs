@@ -0,0 +1,157 @@
+use chrono::{TimeZone, Utc};
+use cobalto::cache::*;
+use cobalto::test::freeze_time;
+use std::time::Duration;
+
+#[test]
+fn test_ttl_expiry_with_frozen_clock() {
+    let frozen = freeze_time(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap());
+    let cache = Cache::new();
+    cache.set("k", "v".into(), Some(Duration::from_secs(10)));
+    assert_eq!(cache.get("k").as_deref(), Some("v"));
+    frozen.advance(chrono::Duration::seconds(11));
+    assert_eq!(cache.get("k"), None);
+
+    // Expired entries are purged on the next write, capacity or not
+    cache.set("other", "w".into(), None);
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_surrogate_key_invalidation() {
+    let cache = Cache::new();
+    cache.set_tagged("a", "1".into(), None, &["model:post"]);
+    cache.set_tagged("b", "2".into(), None, &["model:post", "model:user"]);
+    cache.set("c", "3".into(), None);
+    assert_eq!(cache.invalidate_tag("model:post"), 2);
+    assert_eq!(cache.get("a"), None);
+    assert_eq!(cache.get("b"), None);
+    assert_eq!(cache.get("c").as_deref(), Some("3"));
+    assert_eq!(cache.invalidate_tag("model:user"), 0);
+}
+
+#[test]
+fn test_lru_eviction_and_get_or_set() {
+    let cache = Cache::with_capacity(2);
+    cache.set("a", "1".into(), None);
+    cache.set("b", "2".into(), None);
+    // Reading `a` makes `b` the least recently used entry
+    assert_eq!(cache.get("a").as_deref(), Some("1"));
+    cache.set("c", "3".into(), None);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get("b"), None);
+    assert_eq!(cache.get("a").as_deref(), Some("1"));
+
+    assert_eq!(cache.get_or_set("c", None, || "computed".into()), "3");
+    assert_eq!(
+        cache.get_or_set("d", None, || "computed".into()),
+        "computed"
+    );
+    assert_eq!(cache.get("d").as_deref(), Some("computed"));
+}
+
+#[tokio::test]
+async fn test_cache_page_serves_cached_responses() {
+    use cobalto::router::{Request, Response, Router, handler};
+    use cobalto::settings::Settings;
+    use cobalto::test::Client;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let store = Arc::new(Cache::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mut router = Router::new(Settings::default());
+    router.add_route(
+        "GET",
+        "/report",
+        cache_page_with(
+            store.clone(),
+            Duration::from_secs(60),
+            handler(move |req: Request| {
+                let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    let page = req.query.get("page").cloned().unwrap_or_default();
+                    Response::html(format!("run {} page {}", n, page))
+                }
+            }),
+        ),
+        "report",
+    );
+    let client = Client::new(router);
+
+    assert_eq!(client.get("/report").send().await.body, "run 1 page ");
+    assert_eq!(client.get("/report").send().await.body, "run 1 page ");
+    assert_eq!(
+        client.get("/report?page=2").send().await.body,
+        "run 2 page 2"
+    );
+    let authorized = client
+        .get("/report")
+        .header("Authorization", "Bearer x")
+        .send()
+        .await;
+    assert_eq!(authorized.body, "run 3 page ");
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    assert_eq!(
+        cached(&*store, "answer", None, || async { "42".to_string() }).await,
+        "42"
+    );
+    assert_eq!(store.get("answer").as_deref(), Some("42"));
+}
+
+#[tokio::test]
+async fn test_cache_page_respects_credentials_cache_control_and_vary() {
+    use cobalto::router::{Request, Response, Router, handler};
+    use cobalto::settings::Settings;
+    use cobalto::test::Client;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut router = Router::new(Settings::default());
+    let page = |path: &str, name: &str, header: (&'static str, &'static str)| {
+        let counter = calls.clone();
+        let store = Arc::new(Cache::new());
+        (
+            path.to_string(),
+            cache_page_with(
+                store,
+                Duration::from_secs(60),
+                handler(move |req: Request| {
+                    let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    async move {
+                        let lang = req.header("accept-language").unwrap_or("-").to_string();
+                        Response::html(format!("run {} {}", n, lang)).add_header(header.0, header.1)
+                    }
+                }),
+            ),
+            name.to_string(),
+        )
+    };
+    for (path, cached, name) in [
+        page(
+            "/private",
+            "private",
+            ("Cache-Control", "private, max-age=60"),
+        ),
+        page("/localized", "localized", ("Vary", "Accept-Language")),
+    ] {
+        router.add_route("GET", &path, cached, &name);
+    }
+    let client = Client::new(router);
+
+    assert_eq!(client.get("/private").send().await.body, "run 1 -");
+    assert_eq!(client.get("/private").send().await.body, "run 2 -");
+
+    let get = |lang: &'static str| client.get("/localized").header("Accept-Language", lang);
+    assert_eq!(get("fr").send().await.body, "run 3 fr");
+    assert_eq!(get("de").send().await.body, "run 4 de");
+    assert_eq!(get("fr").send().await.body, "run 3 fr");
+
+    // A request with cookies may get a personalized page
+    let with_cookie = get("fr").header("Cookie", "sid=1").send().await;
+    assert_eq!(with_cookie.body, "run 5 fr");
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+}
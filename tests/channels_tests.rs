@@ -0,0 +1,127 @@
+use cobalto::channels::*;
+use cobalto::pubsub::PubSub;
+
+fn user(name: &str) -> ChannelContext {
+    ChannelContext {
+        user: Some(name.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_pattern_policies_on_join_and_publish() {
+    let mut channels = Channels::new(PubSub::new());
+    channels
+        .authorize("room:*", |ctx, room| ctx.user.is_some() && room != "staff")
+        .authorize_publish("room:announcements", |ctx, _| {
+            ctx.user.as_deref() == Some("admin")
+        });
+
+    let anon = ChannelContext::default();
+    assert!(channels.join(&user("ann"), "room:lobby").is_ok());
+    assert!(channels.join(&anon, "room:lobby").is_err());
+    assert!(channels.join(&user("ann"), "room:staff").is_err());
+
+    // Joining announcements is fine, publishing requires admin
+    assert!(channels.join(&user("ann"), "room:announcements").is_ok());
+    let err = channels
+        .publish(&user("ann"), "room:announcements", "hi")
+        .unwrap_err();
+    assert_eq!(err.action, ChannelAction::Publish);
+    assert!(
+        channels
+            .publish(&user("admin"), "room:announcements", "hi")
+            .is_ok()
+    );
+}
+
+#[test]
+fn test_unmatched_channels_default_deny() {
+    let mut channels = Channels::new(PubSub::new());
+    assert!(channels.join(&user("ann"), "misc").is_err());
+    channels.allow_unmatched = true;
+    assert!(channels.join(&user("ann"), "misc").is_ok());
+}
+
+#[tokio::test]
+async fn test_group_broadcast_subscribe_and_unsubscribe() {
+    let mut ann = connect();
+    let mut bob = connect();
+    ann.subscribe("room:42");
+    bob.subscribe("room:42");
+    bob.subscribe("room:7");
+    assert_eq!(group("room:42").size(), 2);
+
+    assert_eq!(group("room:42").broadcast("hello"), 2);
+    assert_eq!(ann.recv().await.as_deref(), Some("hello"));
+    assert_eq!(bob.recv().await.as_deref(), Some("hello"));
+
+    bob.unsubscribe("room:42");
+    assert_eq!(group("room:42").broadcast("again"), 1);
+    assert_eq!(ann.try_recv().as_deref(), Some("again"));
+    assert_eq!(bob.try_recv(), None);
+
+    // Dropping a connection leaves its groups
+    drop(bob);
+    assert_eq!(group("room:7").size(), 0);
+    assert!(layer().send(ann.name(), "direct".to_string()));
+    assert_eq!(ann.try_recv().as_deref(), Some("direct"));
+}
+
+#[test]
+fn test_join_group_is_authorized() {
+    let layer: std::sync::Arc<dyn ChannelLayer> = std::sync::Arc::new(InMemoryLayer::new());
+    let mut channels = Channels::new(PubSub::new());
+    channels.authorize("room:*", |ctx, _| ctx.user.is_some());
+
+    let mut conn = Connection::open(layer.clone());
+    assert!(
+        channels
+            .join_group(&ChannelContext::default(), &mut conn, "room:1")
+            .is_err()
+    );
+    assert!(
+        channels
+            .join_group(&user("ann"), &mut conn, "room:1")
+            .is_ok()
+    );
+    assert_eq!(conn.groups(), ["room:1"]);
+    assert_eq!(layer.group_size("room:1"), 1);
+}
+
+#[tokio::test]
+async fn test_connection_queues_are_bounded() {
+    use cobalto::pubsub::{Backpressure, TopicConfig};
+    use std::sync::Arc;
+
+    let refusing: Arc<dyn ChannelLayer> = Arc::new(InMemoryLayer::with_config(TopicConfig {
+        capacity: 2,
+        backpressure: Backpressure::DropNewest,
+    }));
+    let mut conn = Connection::open(refusing.clone());
+    conn.subscribe("room:1");
+    let delivered: Vec<usize> = (1..=3)
+        .map(|n| refusing.group_send("room:1", n.to_string()))
+        .collect();
+    assert_eq!(delivered, [1, 1, 0]);
+    assert!(!refusing.send(conn.name(), "direct".to_string()));
+    assert_eq!(conn.recv().await.as_deref(), Some("1"));
+    assert_eq!(conn.recv().await.as_deref(), Some("2"));
+    assert_eq!(conn.try_recv(), None);
+
+    let lagging: Arc<dyn ChannelLayer> = Arc::new(InMemoryLayer::with_config(TopicConfig {
+        capacity: 2,
+        backpressure: Backpressure::DropOldest,
+    }));
+    let mut conn = Connection::open(lagging.clone());
+    conn.subscribe("room:1");
+    for n in 1..=3 {
+        assert_eq!(lagging.group_send("room:1", n.to_string()), 1);
+    }
+    assert_eq!(conn.recv().await.as_deref(), Some("2"));
+    assert_eq!(conn.recv().await.as_deref(), Some("3"));
+
+    let name = conn.name().to_string();
+    drop(conn);
+    assert!(!lagging.send(&name, "gone".to_string()));
+}
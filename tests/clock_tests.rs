@@ -0,0 +1,26 @@
+use chrono::{Duration, TimeZone, Utc};
+use cobalto::clock;
+use cobalto::test::freeze_time;
+use cobalto::throttle::{Throttle, ThrottleScope};
+
+#[test]
+fn test_freeze_and_advance() {
+    let at = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    {
+        let frozen = freeze_time(at);
+        assert_eq!(clock::now(), at);
+        frozen.advance(Duration::minutes(5));
+        assert_eq!(clock::now(), at + Duration::minutes(5));
+    }
+    assert_ne!(clock::now(), at + Duration::minutes(5));
+}
+
+#[test]
+fn test_throttle_window_follows_frozen_clock() {
+    let frozen = freeze_time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+    let t = Throttle::new(vec![ThrottleScope::burst(1)], |_| None);
+    assert!(t.check("k").unwrap().allowed);
+    assert!(!t.check("k").unwrap().allowed);
+    frozen.advance(Duration::seconds(61));
+    assert!(t.check("k").unwrap().allowed);
+}
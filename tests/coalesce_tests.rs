@@ -0,0 +1,90 @@
+use cobalto::coalesce::Coalesce;
+use cobalto::router::{Handler, Request, Response};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn slow_counter(calls: Arc<AtomicUsize>) -> Handler {
+    Arc::new(move |req: Request| {
+        let calls = calls.clone();
+        Box::pin(async move {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Response::html(format!("{}:{}", req.params["id"], n))
+        })
+    })
+}
+
+fn request(id: &str) -> Request {
+    Request {
+        method: "GET".to_string(),
+        params: HashMap::from([("id".to_string(), id.to_string())]),
+        body: String::new(),
+        query: HashMap::new(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_identical_requests_share_one_execution() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let handler = Coalesce::new().wrap(slow_counter(calls.clone()));
+    let (a, b, c) = tokio::join!(
+        handler(request("1")),
+        handler(request("1")),
+        handler(request("2"))
+    );
+    assert_eq!(a.body, "1:1");
+    assert_eq!(b.body, "1:1");
+    assert!(c.body.starts_with("2:"));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Once finished, the next request runs the handler again
+    handler(request("1")).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_requests_without_key_are_not_coalesced() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let handler = Coalesce::new()
+        .with_key(|_| None)
+        .wrap(slow_counter(calls.clone()));
+    tokio::join!(handler(request("1")), handler(request("1")));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_users_and_writes_never_share_responses() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let handler = Coalesce::new().wrap(slow_counter(calls.clone()));
+    let as_user = |cookie: &str| Request {
+        headers: HashMap::from([("Cookie".to_string(), cookie.to_string())]).into(),
+        ..request("1")
+    };
+    let post = || Request {
+        method: "POST".to_string(),
+        ..request("1")
+    };
+    tokio::join!(handler(as_user("sid=a")), handler(as_user("sid=b")));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    tokio::join!(handler(post()), handler(post()));
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    // A response setting a cookie is recomputed for each waiter
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = slow_counter(calls.clone());
+    let login: Handler = Arc::new(move |req| {
+        let counter = counter.clone();
+        Box::pin(async move {
+            let resp = counter(req).await;
+            let body = resp.body.clone();
+            resp.set_cookie(cobalto::cookie::Cookie::new("sid", body))
+        })
+    });
+    let handler = Coalesce::new().wrap(login);
+    let (a, b) = tokio::join!(handler(request("1")), handler(request("1")));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_ne!(a.body, b.body);
+}
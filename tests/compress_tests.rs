@@ -0,0 +1,121 @@
+use cobalto::compress::*;
+use cobalto::router::Response;
+use cobalto::settings::Settings;
+use std::io::Read;
+
+#[test]
+fn test_negotiate_accept_encoding() {
+    assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+    assert_eq!(negotiate("gzip, deflate"), Some(Encoding::Gzip));
+    assert_eq!(negotiate("deflate, gzip;q=0.5"), Some(Encoding::Deflate));
+    assert_eq!(negotiate("br;q=0.5, *;q=0.8"), Some(Encoding::Gzip));
+    assert_eq!(negotiate("gzip;q=0, deflate;q=0"), None);
+    assert_eq!(negotiate("br;q=0, gzip;q=0, *"), Some(Encoding::Deflate));
+    assert_eq!(negotiate("br;q=0, gzip;q=0, deflate;q=0, *"), None);
+    assert_eq!(negotiate("*;q=0.5, deflate"), Some(Encoding::Deflate));
+    assert_eq!(negotiate("identity"), None);
+    assert_eq!(negotiate(""), None);
+}
+
+#[test]
+fn test_compress_large_text_bodies_only() {
+    let settings = CompressionSettings {
+        enabled: true,
+        min_bytes: 100,
+    };
+    let page = "<p>hello</p>".repeat(50);
+
+    let response = compress_with(Response::html(page.clone()), "gzip", &settings);
+    assert_eq!(
+        response.headers.get("Content-Encoding").map(String::as_str),
+        Some("gzip")
+    );
+    assert_eq!(
+        response.headers.get("Vary").map(String::as_str),
+        Some("Accept-Encoding")
+    );
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(response.binary.as_deref().unwrap())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, page);
+
+    // Too small, not accepted, already compressed, or disabled: untouched
+    let small = compress_with(Response::html("<p>hi</p>"), "gzip", &settings);
+    assert!(small.binary.is_none());
+    let identity = compress_with(Response::html(page.clone()), "identity", &settings);
+    assert_eq!(identity.body, page);
+    assert_eq!(identity.headers["Vary"], "Accept-Encoding");
+    assert!(!small.headers.contains_key("Vary"));
+    let mut png = Response::html(page.clone());
+    png.headers
+        .insert("Content-Type".to_string(), "image/png".to_string());
+    let png = compress_with(png, "gzip", &settings);
+    assert!(png.binary.is_none());
+    let disabled = compress_with(
+        Response::html(page.clone()),
+        "gzip",
+        &CompressionSettings {
+            enabled: false,
+            ..settings
+        },
+    );
+    assert!(disabled.binary.is_none());
+
+    // Partial responses are left alone; a strong ETag is weakened
+    let partial = compress_with(
+        Response::html(page.clone()).with_status(206),
+        "gzip",
+        &settings,
+    );
+    assert!(partial.binary.is_none());
+    let tagged = compress_with(
+        Response::html(page.clone()).add_header("ETag", "\"v1\""),
+        "gzip",
+        &settings,
+    );
+    assert_eq!(tagged.headers["ETag"], "W/\"v1\"");
+}
+
+#[test]
+fn test_deflate_is_zlib_and_brotli_round_trips() {
+    let settings = CompressionSettings {
+        enabled: true,
+        min_bytes: 100,
+    };
+    let page = "<p>hello</p>".repeat(50);
+
+    let deflated = compress_with(Response::html(page.clone()), "deflate", &settings);
+    assert_eq!(deflated.headers["Content-Encoding"], "deflate");
+    let mut decoded = String::new();
+    flate2::read::ZlibDecoder::new(deflated.binary.as_deref().unwrap())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, page);
+
+    let brotli = compress_with(Response::html(page.clone()), "br", &settings);
+    assert_eq!(brotli.headers["Content-Encoding"], "br");
+    let mut decoded = String::new();
+    brotli::Decompressor::new(brotli.binary.as_deref().unwrap(), 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, page);
+}
+
+#[test]
+fn test_compression_settings_parse() {
+    let settings = Settings::default()
+        .merge_vars([
+            (
+                "COBALTO_COMPRESSION__MIN_BYTES".to_string(),
+                "2048".to_string(),
+            ),
+            (
+                "COBALTO_COMPRESSION__ENABLED".to_string(),
+                "off".to_string(),
+            ),
+        ])
+        .unwrap();
+    assert_eq!(settings.compression.min_bytes, 2048);
+    assert!(!settings.compression.enabled);
+}
@@ -0,0 +1,72 @@
+use cobalto::contract::*;
+use cobalto::router::{Handler, Response, Router};
+use cobalto::settings::Settings;
+use serde_json::json;
+use std::sync::Arc;
+
+fn json_page(value: serde_json::Value) -> Handler {
+    Arc::new(move |_req| {
+        let value = value.clone();
+        Box::pin(async move { Response::json(value) })
+    })
+}
+
+#[tokio::test]
+async fn test_record_and_verify_contract() {
+    let mut provider = Router::new(Settings::default());
+    provider.add_route(
+        "GET",
+        "/users/:id",
+        json_page(json!({"id": 1, "name": "Ada"})),
+        "user",
+    );
+    let mut recorder = ContractRecorder::new("billing", "accounts", &provider);
+    recorder.record("fetch a user", "GET", "/users/1", "").await;
+    recorder.record("missing page", "GET", "/nope", "").await;
+    let contract = recorder.finish();
+    assert_eq!(
+        contract.interactions[0].response.body,
+        json!({"id": 1, "name": "Ada"})
+    );
+    assert_eq!(contract.interactions[1].response.status, 404);
+
+    let path = std::env::temp_dir().join("cobalto_contract_test.json");
+    contract.save(&path).unwrap();
+    assert_eq!(Contract::load(&path).unwrap(), contract);
+    std::fs::remove_file(&path).unwrap();
+
+    // Extra fields on the provider side keep the contract satisfied
+    let mut evolved = Router::new(Settings::default());
+    evolved.add_route(
+        "GET",
+        "/users/:id",
+        json_page(json!({"id": 1, "name": "Ada", "email": "ada@example.com"})),
+        "user",
+    );
+    assert!(verify(&evolved, &contract).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_verify_reports_breaking_changes() {
+    let mut provider = Router::new(Settings::default());
+    provider.add_route("GET", "/users/:id", json_page(json!({"id": 1})), "user");
+    let contract = Contract {
+        consumer: "billing".into(),
+        provider: "accounts".into(),
+        interactions: vec![Interaction {
+            description: "fetch a user".into(),
+            request: ContractRequest {
+                method: "GET".into(),
+                path: "/users/1".into(),
+                body: String::new(),
+            },
+            response: ContractResponse {
+                status: 200,
+                body: json!({"id": 1, "name": "Ada"}),
+            },
+        }],
+    };
+    let mismatches = verify(&provider, &contract).await;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].description, "fetch a user");
+}
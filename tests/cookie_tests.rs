@@ -0,0 +1,60 @@
+use cobalto::cookie::{Cookie, SameSite, parse_cookies};
+use cobalto::router::{Request, Response};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[test]
+fn test_cookie_header_attributes_and_encoding() {
+    let cookie = Cookie::build("cart", "a b;c%+")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::from_secs(3600));
+    assert_eq!(
+        cookie.to_header(),
+        "cart=a%20b%3Bc%25+; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Strict"
+    );
+    assert_eq!(
+        Cookie::removal("cart").to_header(),
+        "cart=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+    );
+
+    let parsed = parse_cookies("cart=a%20b%3Bc%25+; theme=\"dark\"; cart=older");
+    assert_eq!(parsed["cart"], "a b;c%+");
+    assert_eq!(parsed["theme"], "dark");
+}
+
+#[test]
+fn test_response_keeps_every_set_cookie() {
+    let response = Response::html("ok")
+        .set_cookie(Cookie::new("a", "1"))
+        .set_cookie(Cookie::new("b", "2"))
+        .remove_cookie("c");
+    assert_eq!(
+        response.cookies(),
+        vec![
+            "a=1; Path=/",
+            "b=2; Path=/",
+            "c=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        ]
+    );
+}
+
+#[test]
+fn test_request_cookies_come_from_the_cookie_header() {
+    let req = Request {
+        headers: HashMap::from([("Cookie".to_string(), "theme=dark; lang=fr".to_string())]).into(),
+        ..Default::default()
+    };
+    assert_eq!(req.cookie("theme").as_deref(), Some("dark"));
+    assert_eq!(req.cookie("nope"), None);
+    assert!(Request::default().cookies().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "invalid cookie domain")]
+fn test_cookie_attributes_reject_header_injection() {
+    assert!(std::panic::catch_unwind(|| Cookie::new("a;b", "1")).is_err());
+    assert!(std::panic::catch_unwind(|| Cookie::new("a", "1").path("/\r\nX-Evil: 1")).is_err());
+    Cookie::new("a", "1").domain("example.com; Secure");
+}
@@ -0,0 +1,121 @@
+use cobalto::datatable::*;
+use cobalto::orm::{Backend, Db, Field, FieldType, Model, SqlValue, create_table_sql};
+use std::collections::HashMap;
+
+#[derive(Debug, sqlx::FromRow)]
+struct Person {
+    id: i64,
+    name: String,
+    age: i64,
+}
+
+impl Model for Person {
+    fn table_name() -> &'static str {
+        "person"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("name", FieldType::Text),
+            Field::new("age", FieldType::Integer),
+        ]
+    }
+
+    fn values(&self) -> Vec<SqlValue> {
+        vec![self.id.into(), self.name.clone().into(), self.age.into()]
+    }
+}
+
+async fn people() -> Db {
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Person>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    db.execute("INSERT INTO person (name, age) VALUES ('Carol', 9), ('alice', 10), ('Bob', 2)")
+        .await
+        .unwrap();
+    db
+}
+
+fn table() -> DataTable {
+    DataTable::new(
+        "people",
+        "/people/table",
+        vec![Column::new("name", "Name"), Column::new("age", "Age")],
+    )
+    .per_page(2)
+}
+
+fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_sort_and_paging_in_the_database() {
+    let db = people().await;
+    let page = table()
+        .page(Person::objects(&db), &query(&[("sort", "age"), ("dir", "desc")]))
+        .await
+        .unwrap();
+    assert_eq!((page.total, page.pages, page.page), (3, 2, 1));
+    assert_eq!(page.rows[0]["name"], "alice");
+    assert_eq!(page.rows[1]["name"], "Carol");
+    assert_eq!(page.rows[1]["age"], "9");
+
+    let page = table()
+        .page(Person::objects(&db), &query(&[("sort", "age"), ("page", "9")]))
+        .await
+        .unwrap();
+    assert_eq!(page.page, 2);
+    assert_eq!(page.rows.len(), 1);
+    assert_eq!(page.rows[0]["name"], "alice");
+
+    // Unknown sort keys fall back to the default order
+    let (state, objects) = table()
+        .default_order("-id")
+        .query(Person::objects(&db), &query(&[("sort", "id; DROP TABLE person")]))
+        .await
+        .unwrap();
+    assert_eq!(state.sort, None);
+    let names: Vec<String> = objects
+        .all()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+    assert_eq!(names, vec!["Bob", "alice"]);
+}
+
+#[tokio::test]
+async fn test_filter_and_render() {
+    let db = people().await;
+    let t = table();
+    let page = t
+        .page(Person::objects(&db), &query(&[("q", "BO")]))
+        .await
+        .unwrap();
+    assert_eq!(page.total, 1);
+    let html = t.render(&page);
+    assert!(html.contains("<td>Bob</td>"));
+    assert!(html.contains(r##"hx-target="#people""##));
+    assert!(html.contains("/people/table?page=1&amp;sort=name&amp;dir=asc&amp;q=BO"));
+
+    // Only text columns are searched unless search fields are given
+    let page = t
+        .page(Person::objects(&db), &query(&[("q", "10")]))
+        .await
+        .unwrap();
+    assert_eq!(page.total, 0);
+    let page = t
+        .clone()
+        .search_fields(&["name", "age"])
+        .page(Person::objects(&db), &query(&[("q", "10")]))
+        .await
+        .unwrap();
+    assert_eq!(page.rows[0]["name"], "alice");
+}
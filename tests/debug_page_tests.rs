@@ -0,0 +1,84 @@
+use cobalto::debug_page::settings_rows;
+use cobalto::orm::Db;
+use cobalto::router::{Request, Response, Router, handler};
+use cobalto::settings::Settings;
+use cobalto::test::Client;
+
+#[test]
+fn test_settings_rows_mask_secrets() {
+    let mut settings = Settings::default();
+    settings.set("secret_key", "hunter2").unwrap();
+    settings.set("site_name", "Demo").unwrap();
+    let rows = settings_rows(&settings);
+    let value = |name: &str| {
+        rows.iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+    assert_eq!(value("secret_key"), Some("********"));
+    assert_eq!(value("site_name"), Some("Demo"));
+    assert_eq!(value("debug"), Some("false"));
+}
+
+#[tokio::test]
+async fn test_debug_page_for_panics_and_server_errors() {
+    let mut settings = Settings::default();
+    settings.debug = true;
+    settings.set("secret_key", "hunter2").unwrap();
+    let mut router = Router::new(settings);
+    router.add_route(
+        "GET",
+        "/orders/:id",
+        handler(|_req: Request| async move {
+            let orders: Vec<u32> = Vec::new();
+            Response::html(orders[0].to_string())
+        }),
+        "order",
+    );
+    router.add_route(
+        "GET",
+        "/broken",
+        handler(|_req: Request| async move { Response::html("database is down").with_status(503) }),
+        "broken",
+    );
+    let client = Client::new(router);
+    let db = Db::connect(":memory:")
+        .await
+        .unwrap()
+        .with_slow_query_log(std::time::Duration::ZERO);
+    db.execute("CREATE TABLE slow_marker (id INTEGER)").await.unwrap();
+
+    let page = client
+        .get("/orders/7?tab=items")
+        .header("X-Trace", "abc")
+        .header("Cookie", "sessionid=s3cr3t-session")
+        .header("Authorization", "Bearer s3cr3t-token")
+        .send()
+        .await;
+    assert_eq!(page.status, 500);
+    for expected in [
+        "index out of bounds",
+        "/orders/:id",
+        "tab",
+        "x-trace",
+        "Backtrace",
+        "********",
+        "CREATE TABLE slow_marker",
+    ] {
+        assert!(page.body.contains(expected), "missing {}", expected);
+    }
+    assert!(!page.body.contains("hunter2"));
+    assert!(!page.body.contains("s3cr3t-session") && !page.body.contains("s3cr3t-token"));
+
+    // Returned errors get the page only when a browser asks
+    let api = client.get("/broken").send().await;
+    assert_eq!(api.body, "database is down");
+    let browser = client
+        .get("/broken")
+        .header("Accept", "text/html")
+        .send()
+        .await;
+    assert_eq!(browser.status, 503);
+    assert!(browser.body.contains("Handler returned 503"));
+    assert!(browser.body.contains("database is down"));
+}
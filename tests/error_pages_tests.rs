@@ -0,0 +1,77 @@
+use cobalto::error_pages::{ErrorInfo, ErrorPages};
+use cobalto::router::{Response, Router};
+use cobalto::settings::Settings;
+
+#[tokio::test]
+async fn test_custom_error_handler_answers_unmatched_requests() {
+    let mut router = Router::new(Settings::default());
+    router.error_handler(404, |info: &ErrorInfo| {
+        Response::html(format!("no {} here", info.path))
+    });
+    let response = router.dispatch("GET", "/nowhere?x=1", "").await;
+    assert_eq!(response.status, 404);
+    assert_eq!(response.body, "no /nowhere here");
+}
+
+#[test]
+fn test_error_templates_get_details_only_in_debug() {
+    let dir = std::env::temp_dir().join("cobalto_error_pages");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("404.html"),
+        "{{ status }} {{ method }} {{ path }}|{{ error }}",
+    )
+    .unwrap();
+    let info = ErrorInfo {
+        status: 404,
+        method: "GET".to_string(),
+        path: "/missing".to_string(),
+        detail: Some("No route matches GET /missing".to_string()),
+    };
+    let dir = dir.to_str().unwrap();
+
+    let debug = ErrorPages::new(dir, true).render(&info).unwrap();
+    assert_eq!(debug.status, 404);
+    assert_eq!(
+        debug.body.trim(),
+        "404 GET /missing|No route matches GET /missing"
+    );
+    let quiet = ErrorPages::new(dir, false).render(&info).unwrap();
+    assert_eq!(quiet.body.trim(), "404 GET /missing|");
+    // No 500.html: the built-in page is used
+    let info = ErrorInfo {
+        status: 500,
+        ..info
+    };
+    assert!(ErrorPages::new(dir, true).render(&info).is_none());
+}
+
+#[tokio::test]
+async fn test_handler_panics_become_500_responses() {
+    use cobalto::router::{Request, handler};
+
+    fn app(debug: bool) -> Router {
+        let mut settings = Settings::default();
+        settings.debug = debug;
+        let mut router = Router::new(settings);
+        router.add_route(
+            "GET",
+            "/boom",
+            handler(|_req: Request| async move {
+                let status: Option<u16> = None;
+                Response::html("unreachable").with_status(status.expect("no status code"))
+            }),
+            "boom",
+        );
+        router
+    }
+
+    let quiet = app(false).dispatch("GET", "/boom", "").await;
+    assert_eq!(quiet.status, 500);
+    assert_eq!(quiet.body, "Internal Server Error");
+
+    let debug = app(true).dispatch("GET", "/boom", "").await;
+    assert_eq!(debug.status, 500);
+    assert!(debug.body.contains("no status code"));
+    assert!(debug.body.contains("error_pages_tests.rs"));
+}
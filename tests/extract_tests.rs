@@ -0,0 +1,104 @@
+use cobalto::extract::{Form, Json, Path, Query, State, extract};
+use cobalto::router::{Response, Router};
+use cobalto::settings::Settings;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct CreateUser {
+    name: String,
+    age: u32,
+}
+
+#[derive(Clone)]
+struct Greeting(&'static str);
+
+async fn create_user(
+    Path(team): Path<i64>,
+    Json(user): Json<CreateUser>,
+    greeting: State<Greeting>,
+) -> Response {
+    Response::html(format!(
+        "{} {} ({}) in team {}",
+        greeting.0, user.name, user.age, team
+    ))
+}
+
+#[tokio::test]
+async fn test_extractors_feed_handler_arguments() {
+    let mut router = Router::new(Settings::default());
+    router.manage(Greeting("Welcome"));
+    router.add_route(
+        "POST",
+        "/teams/:id/users",
+        extract(create_user),
+        "create_user",
+    );
+
+    let ok = router
+        .dispatch("POST", "/teams/7/users", r#"{"name": "Ada", "age": 36}"#)
+        .await;
+    assert_eq!(ok.status, 200);
+    assert_eq!(ok.body, "Welcome Ada (36) in team 7");
+
+    // Malformed JSON is a 400, well-formed JSON of the wrong shape a 422
+    let syntax = router.dispatch("POST", "/teams/7/users", "{").await;
+    assert_eq!(syntax.status, 400);
+    assert!(syntax.body.contains("invalid JSON body"));
+    let shape = router
+        .dispatch("POST", "/teams/7/users", r#"{"name": "Ada"}"#)
+        .await;
+    assert_eq!(shape.status, 422);
+
+    let bad_path = router
+        .dispatch("POST", "/teams/x/users", r#"{"name": "Ada", "age": 36}"#)
+        .await;
+    assert_eq!(bad_path.status, 400);
+}
+
+#[derive(Deserialize)]
+struct Search {
+    q: String,
+    page: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    username: String,
+}
+
+#[tokio::test]
+async fn test_query_form_and_missing_state() {
+    let mut router = Router::new(Settings::default());
+    router.add_route(
+        "GET",
+        "/search",
+        extract(|Query(search): Query<Search>| async move {
+            Response::html(format!("{}:{}", search.q, search.page.unwrap_or(1)))
+        }),
+        "search",
+    );
+    router.add_route(
+        "POST",
+        "/login",
+        extract(|Form(login): Form<Login>| async move { Response::html(login.username) }),
+        "login",
+    );
+    router.add_route(
+        "GET",
+        "/greet",
+        extract(|greeting: State<Greeting>| async move { Response::html(greeting.0) }),
+        "greet",
+    );
+
+    assert_eq!(
+        router.dispatch("GET", "/search?q=rust", "").await.body,
+        "rust:1"
+    );
+    assert_eq!(router.dispatch("GET", "/search", "").await.status, 400);
+    assert_eq!(
+        router.dispatch("POST", "/login", "username=ada").await.body,
+        "ada"
+    );
+    assert_eq!(router.dispatch("POST", "/login", "").await.status, 422);
+    assert_eq!(router.dispatch("GET", "/greet", "").await.status, 500);
+}
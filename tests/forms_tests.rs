@@ -0,0 +1,43 @@
+use cobalto::forms::*;
+use std::collections::HashMap;
+
+struct Contact;
+
+impl FormFields for Contact {
+    fn form_fields() -> Vec<FormField> {
+        vec![
+            FormField::new("email")
+                .label("E-mail")
+                .widget(Widget::Email)
+                .help("We never share it"),
+            FormField::new("message")
+                .widget(Widget::Textarea)
+                .optional(),
+        ]
+    }
+}
+
+#[test]
+fn test_humanize_default_label() {
+    assert_eq!(FormField::new("first_name").label, "First name");
+    assert_eq!(Widget::from_attr("textarea"), Some(Widget::Textarea));
+    assert_eq!(Widget::from_attr("nope"), None);
+}
+
+#[test]
+fn test_render_form_with_values_and_errors() {
+    let values = HashMap::from([("message".to_string(), "<hi>".to_string())]);
+    let errors = HashMap::from([("email".to_string(), "Required".to_string())]);
+    let html = render_form::<Contact>(&values, &errors);
+    assert!(html.contains(r#"<label for="id_email">E-mail</label>"#));
+    assert!(html.contains(r#"type="email""#));
+    assert!(html.contains(r#"<small class="help">We never share it</small>"#));
+    assert!(html.contains(r#"<span class="error">Required</span>"#));
+    assert!(html.contains(r#"<textarea id="id_message" name="message">&lt;hi&gt;</textarea>"#));
+}
+
+#[test]
+fn test_render_escapes_single_quotes() {
+    let html = FormField::new("name").render(Some("' onfocus='alert(1)"), None);
+    assert!(html.contains("value=\"&#x27; onfocus=&#x27;alert(1)\""));
+}
@@ -0,0 +1,126 @@
+use cobalto::i18n::*;
+use cobalto::router::{RequestScope, with_request_scope};
+use cobalto::template::{TemplateValue, parse_tokens, render_nodes, tokenize_template};
+use std::collections::HashMap;
+
+const ITALIAN: &str = r#"
+# Header
+msgid ""
+msgstr ""
+"Content-Type: text/plain; charset=UTF-8\n"
+
+msgid "Welcome"
+msgstr "Benvenuto"
+
+#, fuzzy
+msgid "Hello {{ user.name }}"
+msgstr "Ciao "
+"{{ user.name }}"
+
+msgid "One item"
+msgid_plural "{{ n }} items"
+msgstr[0] "Un articolo"
+msgstr[1] "{{ n }} articoli"
+
+msgctxt "month"
+msgid "May"
+msgstr "Maggio"
+
+msgid "Untranslated"
+msgstr ""
+"#;
+
+#[test]
+fn test_parse_po_and_negotiate() {
+    let catalog = Catalog::parse_po(ITALIAN).unwrap();
+    assert_eq!(catalog.len(), 4);
+    assert_eq!(catalog.translate("Welcome", None), Some("Benvenuto"));
+    assert_eq!(
+        catalog.translate("Hello {{ user.name }}", None),
+        Some("Ciao {{ user.name }}")
+    );
+    assert_eq!(
+        catalog.translate("One item", Some(3)),
+        Some("{{ n }} articoli")
+    );
+    assert_eq!(catalog.translate("Untranslated", None), None);
+    assert!(matches!(
+        Catalog::parse_po("msgid Welcome"),
+        Err(CatalogError::Parse { line: 1, .. })
+    ));
+
+    let available = vec!["en".to_string(), "fr".to_string(), "pt-BR".to_string()];
+    assert_eq!(
+        negotiate("fr-CH, fr;q=0.9, en;q=0.8", &available).as_deref(),
+        Some("fr")
+    );
+    assert_eq!(
+        negotiate("de, pt_br;q=0.5, en;q=0.4", &available).as_deref(),
+        Some("pt-br")
+    );
+    assert_eq!(
+        negotiate("en;q=0.1, fr;q=0.7", &available).as_deref(),
+        Some("fr")
+    );
+    assert_eq!(negotiate("de, ja;q=0", &available), None);
+}
+
+#[tokio::test]
+async fn test_gettext_follows_request_locale() {
+    add_catalog("it", Catalog::parse_po(ITALIAN).unwrap());
+
+    let scope = RequestScope {
+        headers: HashMap::from([(
+            "accept-language".to_string(),
+            "it-IT,it;q=0.9,en;q=0.5".to_string(),
+        )]),
+        ..Default::default()
+    };
+    with_request_scope(scope, async {
+        assert_eq!(current_locale(), "it");
+        assert_eq!(gettext("Welcome"), "Benvenuto");
+        assert_eq!(gettext("Goodbye"), "Goodbye");
+        assert_eq!(ngettext("One item", "{{ n }} items", 1), "Un articolo");
+        assert_eq!(pgettext("month", "May"), "Maggio");
+        assert_eq!(gettext("May"), "May");
+    })
+    .await;
+
+    // An explicit locale wins; regional variants fall back to the base language
+    with_locale("it-CH", async {
+        assert_eq!(gettext("Welcome"), "Benvenuto");
+    })
+    .await;
+    with_locale("xx", async {
+        assert_eq!(gettext("Welcome"), "Welcome");
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_trans_and_blocktrans_tags() {
+    add_catalog("it", Catalog::parse_po(ITALIAN).unwrap());
+    let nodes = parse_tokens(&tokenize_template(
+        "<h1>{% trans \"Welcome\" %}</h1>\
+         <p>{% blocktrans %}Hello {{ user.name }}{% endblocktrans %}</p>\
+         <p>{% blocktrans count n=items %}One item{% plural %}{{ n }} items{% endblocktrans %}</p>",
+    ));
+    let context = |items: f64| {
+        let user = HashMap::from([("name".to_string(), TemplateValue::String("<Ada>".into()))]);
+        HashMap::from([
+            ("user".to_string(), TemplateValue::Object(user)),
+            ("items".to_string(), TemplateValue::Number(items)),
+        ])
+    };
+
+    let html = with_locale("it", async { render_nodes(&nodes, &context(3.0)) }).await;
+    assert_eq!(
+        html,
+        "<h1>Benvenuto</h1><p>Ciao &lt;Ada&gt;</p><p>3 articoli</p>"
+    );
+    let html = with_locale("en", async { render_nodes(&nodes, &context(1.0)) }).await;
+    assert_eq!(
+        html,
+        "<h1>Welcome</h1><p>Hello &lt;Ada&gt;</p><p>One item</p>"
+    );
+}
@@ -0,0 +1,91 @@
+use chrono::{TimeZone, Utc};
+use cobalto::ids::*;
+use cobalto::test::freeze_time;
+
+#[test]
+fn test_uuid_v7_format_and_order() {
+    let _frozen = freeze_time(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap());
+    let generator = IdGenerator::new(IdStrategy::UuidV7);
+    let ids: Vec<String> = (0..100).map(|_| generator.uuid_v7()).collect();
+    for id in &ids {
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "7");
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+    }
+    // Same frozen millisecond: the counter keeps them ordered
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(sorted, ids);
+    let ms = Utc
+        .with_ymd_and_hms(2024, 5, 1, 0, 0, 0)
+        .unwrap()
+        .timestamp_millis();
+    assert!(ids[0].replace('-', "").starts_with(&format!("{:012x}", ms)));
+}
+
+#[test]
+fn test_snowflake_layout_and_monotonicity() {
+    let at = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let _frozen = freeze_time(at);
+    let generator = IdGenerator::new(IdStrategy::Snowflake { node: 7 });
+    let a = generator.snowflake();
+    let b = generator.snowflake();
+    assert!(b > a);
+    assert_eq!(snowflake_parts(a), (at.timestamp_millis(), 7, 0));
+    assert_eq!(snowflake_parts(b), (at.timestamp_millis(), 7, 1));
+    assert!(matches!(generator.next(), GeneratedId::Snowflake(_)));
+}
+
+#[tokio::test]
+async fn test_save_fills_auto_id_keys() {
+    use cobalto::orm::{Backend, Db, Field, FieldType, Model, SqlValue, create_table_sql};
+
+    #[derive(Debug, sqlx::FromRow)]
+    struct Ticket {
+        id: String,
+        subject: String,
+    }
+
+    impl Model for Ticket {
+        fn table_name() -> &'static str {
+            "ticket"
+        }
+
+        fn fields() -> Vec<Field> {
+            vec![
+                Field::new("id", FieldType::Text).primary_key().auto_id(),
+                Field::new("subject", FieldType::Text),
+            ]
+        }
+
+        fn values(&self) -> Vec<SqlValue> {
+            vec![self.id.clone().into(), self.subject.clone().into()]
+        }
+
+        fn set_primary_key_value(&mut self, value: SqlValue) {
+            if let SqlValue::Text(id) = value {
+                self.id = id;
+            }
+        }
+    }
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Ticket>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    let mut ticket = Ticket {
+        id: String::new(),
+        subject: "printer".into(),
+    };
+    ticket.save(&db).await.unwrap();
+    assert_eq!(ticket.id.len(), 36);
+    let stored = Ticket::get(&db, ticket.id.as_str()).await.unwrap().unwrap();
+    assert_eq!(stored.subject, "printer");
+
+    // Saving again updates the same row
+    ticket.subject = "scanner".into();
+    let id = ticket.id.clone();
+    ticket.save(&db).await.unwrap();
+    assert_eq!(ticket.id, id);
+    assert_eq!(Ticket::objects(&db).count().await.unwrap(), 1);
+}
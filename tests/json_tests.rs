@@ -0,0 +1,82 @@
+use cobalto::json::*;
+use cobalto::router::{Request, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Profile {
+    user_name: String,
+    last_login_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct CamelProfile {
+    #[serde(alias = "user_name")]
+    user_name: String,
+    #[serde(rename = "seen_at")]
+    last_login_at: Option<String>,
+    scores: HashMap<String, u32>,
+}
+
+#[test]
+fn test_json_case_comes_from_the_type() {
+    let body = vec![CamelProfile {
+        user_name: "ann".into(),
+        last_login_at: None,
+        scores: HashMap::from([("math_score".to_string(), 9)]),
+    }];
+    let resp = Response::json(body);
+    assert_eq!(
+        resp.body,
+        r#"[{"userName":"ann","seen_at":null,"scores":{"math_score":9}}]"#
+    );
+
+    let snake = Response::json(Profile {
+        user_name: "ann".into(),
+        last_login_at: None,
+    });
+    assert_eq!(snake.body, r#"{"user_name":"ann","last_login_at":null}"#);
+}
+
+#[test]
+fn test_request_json_accepts_aliases() {
+    for body in [
+        r#"{"userName":"ann","seen_at":null,"scores":{"mathScore":9}}"#,
+        r#"{"user_name":"ann","seen_at":null,"scores":{"mathScore":9}}"#,
+    ] {
+        let req = Request {
+            params: HashMap::new(),
+            body: body.to_string(),
+            query: HashMap::new(),
+            ..Default::default()
+        };
+        let p: CamelProfile = req.json().unwrap();
+        assert_eq!(p.user_name, "ann");
+        assert_eq!(p.scores["mathScore"], 9);
+    }
+}
+
+#[test]
+fn test_json_body_limits_and_content_type() {
+    let body = r#"{"user_name":"ann","last_login_at":null}"#;
+    let parse = |content_type, max_bytes, strict| {
+        parse_body_with::<Profile>(body, content_type, max_bytes, strict)
+    };
+    assert!(parse(None, 1024, false).is_ok());
+    assert!(parse(Some("application/json; charset=utf-8"), 1024, true).is_ok());
+    assert!(parse(Some("application/vnd.api+json"), 1024, true).is_ok());
+
+    let too_large = parse(Some("application/json"), 10, false).unwrap_err();
+    assert_eq!(too_large.status(), 413);
+    let wrong_type = parse(Some("text/plain"), 1024, true).unwrap_err();
+    assert_eq!(wrong_type.status(), 415);
+    assert_eq!(
+        wrong_type.to_string(),
+        "expected content type application/json, got text/plain"
+    );
+    assert_eq!(parse(None, 1024, true).unwrap_err().status(), 415);
+
+    let shape = parse_body_with::<Profile>(r#"{"user_name":1}"#, None, 1024, false);
+    assert_eq!(shape.unwrap_err().status(), 422);
+}
@@ -0,0 +1,49 @@
+use cobalto::linkcheck::*;
+use cobalto::router::{Handler, Response, Router};
+use cobalto::settings::Settings;
+use std::sync::Arc;
+
+fn page(html: &'static str) -> Handler {
+    Arc::new(move |_req| Box::pin(async move { Response::html(html) }))
+}
+
+fn redirect(to: &'static str) -> Handler {
+    Arc::new(move |_req| {
+        Box::pin(async move {
+            Response::html("")
+                .with_status(302)
+                .add_header("Location", to)
+        })
+    })
+}
+
+#[test]
+fn test_extract_internal_links_only() {
+    let html = r#"<a href="/about">a</a><a href="https://x.com">b</a><img src="/logo.png"><a href="//cdn">c</a><a href="/faq#top">d</a>"#;
+    assert_eq!(extract_links(html), vec!["/about", "/logo.png", "/faq"]);
+}
+
+#[tokio::test]
+async fn test_linkcheck_reports_broken_links_and_loops() {
+    let mut router = Router::new(Settings::default());
+    router.add_route(
+        "GET",
+        "/",
+        page(r#"<a href="/ok">ok</a> <a href="/missing">x</a> <a href="/loop-a">loop</a>"#),
+        "index",
+    );
+    router.add_route("GET", "/ok", page("fine"), "ok");
+    router.add_route("GET", "/loop-a", redirect("/loop-b"), "loop_a");
+    router.add_route("GET", "/loop-b", redirect("/loop-a"), "loop_b");
+
+    let report = linkcheck(&router, &["/"]).await;
+    assert_eq!(report.checked.len(), 4);
+    assert!(report.issues.contains(&LinkIssue::Broken {
+        url: "/missing".into(),
+        status: 404,
+        referrer: Some("/".into()),
+    }));
+    assert!(report.issues.contains(&LinkIssue::RedirectLoop {
+        chain: vec!["/loop-a".into(), "/loop-b".into(), "/loop-a".into()],
+    }));
+}
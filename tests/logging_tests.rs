@@ -0,0 +1,57 @@
+use cobalto::logging::{LogFormat, LogRecord, add_field, log_request, scope, set_sink};
+use cobalto::settings::Settings;
+use log::Level;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_record_formats() {
+    let record = LogRecord::new(Level::Warn, "GET /x 404")
+        .field("ip", "127.0.0.1")
+        .field("user", "Ada Lovelace");
+    let text = record.format(LogFormat::Text);
+    assert!(text.ends_with(r#"WARN GET /x 404 ip=127.0.0.1 user="Ada Lovelace""#));
+
+    let json: serde_json::Value = serde_json::from_str(&record.format(LogFormat::Json)).unwrap();
+    assert_eq!(json["level"], "WARN");
+    assert_eq!(json["message"], "GET /x 404");
+    assert_eq!(json["ip"], "127.0.0.1");
+}
+
+#[test]
+fn test_log_settings_parse() {
+    let settings = Settings::default()
+        .merge_vars([
+            ("COBALTO_LOG__LEVEL".to_string(), "debug".to_string()),
+            ("COBALTO_LOG__FORMAT".to_string(), "JSON".to_string()),
+        ])
+        .unwrap();
+    assert_eq!(settings.log.level, log::LevelFilter::Debug);
+    assert_eq!(settings.log.format, LogFormat::Json);
+    assert!(
+        Settings::default()
+            .merge_vars([("COBALTO_LOG__LEVEL".to_string(), "loud".to_string())])
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_request_fields_reach_the_sink() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let captured = lines.clone();
+    set_sink(move |_, line| captured.lock().unwrap().push(line.to_string()));
+
+    scope(async {
+        add_field("request_id", "abc123");
+        log_request("GET", "/logged", 200, Some(3), "10.0.0.1");
+    })
+    .await;
+    // Outside a request scope fields are dropped
+    add_field("request_id", "ignored");
+    log_request("GET", "/unscoped", 200, None, "10.0.0.1");
+
+    let lines = lines.lock().unwrap();
+    let logged = lines.iter().find(|l| l.contains("/logged")).unwrap();
+    assert!(logged.contains("duration_ms=3 ip=10.0.0.1 request_id=abc123"));
+    let unscoped = lines.iter().find(|l| l.contains("/unscoped")).unwrap();
+    assert!(!unscoped.contains("request_id"));
+}
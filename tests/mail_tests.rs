@@ -0,0 +1,110 @@
+use cobalto::mail::{MailBackend, MailError, MailSettings, Mailer, Message};
+use cobalto::settings::Settings;
+use cobalto::template::TemplateValue;
+use std::collections::HashMap;
+
+fn mailer(backend: MailBackend, dir: &std::path::Path) -> Mailer {
+    let settings = MailSettings {
+        backend,
+        from: "Site <noreply@example.com>".into(),
+        file_dir: dir.join("outbox").to_string_lossy().into_owned(),
+        ..Default::default()
+    };
+    Mailer::new(settings, &dir.to_string_lossy())
+}
+
+#[tokio::test]
+async fn test_memory_backend_renders_templates() {
+    let dir = std::env::temp_dir().join("cobalto_mail_memory");
+    std::fs::create_dir_all(dir.join("emails")).unwrap();
+    std::fs::write(dir.join("emails/welcome.html"), "<p>Hello {{ name }}</p>").unwrap();
+    let mailer = mailer(MailBackend::Memory, &dir);
+
+    let mut ctx = HashMap::new();
+    ctx.insert("name".to_string(), TemplateValue::String("Ada".into()));
+    mailer
+        .send(
+            Message::new()
+                .to("ada@example.com")
+                .subject("Welcome")
+                .body_template("emails/welcome.html", ctx),
+        )
+        .await
+        .unwrap();
+
+    let sent = mailer.outbox();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].from.as_deref(), Some("Site <noreply@example.com>"));
+    assert_eq!(sent[0].html.as_deref(), Some("<p>Hello Ada</p>"));
+
+    let missing = mailer
+        .send(
+            Message::new()
+                .to("ada@example.com")
+                .body_template("emails/nope.html", HashMap::new()),
+        )
+        .await;
+    assert!(matches!(missing, Err(MailError::Template(_))));
+    assert!(matches!(
+        mailer.send(Message::new().subject("nobody")).await,
+        Err(MailError::Address(_))
+    ));
+    assert!(matches!(
+        mailer.send(Message::new().to("not an address")).await,
+        Err(MailError::Address(_))
+    ));
+    assert_eq!(mailer.outbox().len(), 1);
+}
+
+#[tokio::test]
+async fn test_file_backend_writes_eml() {
+    let dir = std::env::temp_dir().join(format!("cobalto_mail_file_{}", std::process::id()));
+    let mailer = mailer(MailBackend::File, &dir);
+    mailer
+        .send(
+            Message::new()
+                .to("bob@example.com")
+                .cc("carol@example.com")
+                .subject("Report")
+                .body("All good."),
+        )
+        .await
+        .unwrap();
+
+    let files: Vec<_> = std::fs::read_dir(dir.join("outbox"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].extension().is_some_and(|e| e == "eml"));
+    let content = std::fs::read_to_string(&files[0]).unwrap();
+    assert!(content.contains("To: bob@example.com"));
+    assert!(content.contains("Cc: carol@example.com"));
+    assert!(content.contains("Subject: Report"));
+    assert!(content.contains("All good."));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_mail_settings_parse() {
+    let settings = Settings::default()
+        .merge_vars([
+            ("COBALTO_MAIL__BACKEND".to_string(), "SMTP".to_string()),
+            (
+                "COBALTO_MAIL__HOST".to_string(),
+                "smtp.example.com".to_string(),
+            ),
+            ("COBALTO_MAIL__PORT".to_string(), "465".to_string()),
+            ("COBALTO_MAIL__STARTTLS".to_string(), "no".to_string()),
+        ])
+        .unwrap();
+    assert_eq!(settings.mail.backend, MailBackend::Smtp);
+    assert_eq!(settings.mail.host, "smtp.example.com");
+    assert_eq!(settings.mail.port, 465);
+    assert!(!settings.mail.starttls);
+    assert!(
+        Settings::default()
+            .merge_vars([("COBALTO_MAIL__BACKEND".to_string(), "pigeon".to_string())])
+            .is_err()
+    );
+}
@@ -0,0 +1,259 @@
+use cobalto::app::App;
+use cobalto::manage::*;
+use cobalto::orm::Db;
+use cobalto::router::{Response, Router};
+use cobalto::settings::Settings;
+use cobalto::supervisor::Role;
+use cobalto::tasks::{Scheduler, TaskQueue};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn test_parse_args_and_runserver_overrides() {
+    assert_eq!(
+        parse_args(Vec::<String>::new()).unwrap(),
+        Command::RunServer {
+            addr: None,
+            overrides: vec![]
+        }
+    );
+    assert_eq!(parse_args(["migrate"]).unwrap(), Command::Migrate);
+    assert_eq!(
+        parse_args(["start", "--all"]).unwrap(),
+        Command::Start {
+            roles: vec![Role::Web, Role::Worker, Role::Scheduler]
+        }
+    );
+    assert_eq!(
+        parse_args(["start", "--worker"]).unwrap(),
+        Command::Start {
+            roles: vec![Role::Worker]
+        }
+    );
+    assert!(matches!(
+        parse_args(["start", "--bogus"]),
+        Err(ManageError::Usage(_))
+    ));
+    assert!(matches!(
+        parse_args(["routes", "x"]),
+        Err(ManageError::Usage(_))
+    ));
+    assert!(matches!(parse_args(["serve"]), Err(ManageError::Usage(_))));
+    assert_eq!(
+        parse_args(["middleware", "--route", "/notes"]).unwrap(),
+        Command::Middleware {
+            route: "/notes".into()
+        }
+    );
+    assert!(matches!(
+        parse_args(["middleware", "/notes"]),
+        Err(ManageError::Usage(_))
+    ));
+    assert_eq!(
+        parse_args(["linkcheck", "/", "/docs"]).unwrap(),
+        Command::LinkCheck {
+            start: vec!["/".into(), "/docs".into()]
+        }
+    );
+    assert!(matches!(
+        parse_args(["linkcheck", "docs"]),
+        Err(ManageError::Usage(_))
+    ));
+    assert_eq!(
+        parse_args(["contract", "verify", "web.json"]).unwrap(),
+        Command::VerifyContract {
+            path: "web.json".into()
+        }
+    );
+    assert!(matches!(
+        parse_args(["contract", "web.json"]),
+        Err(ManageError::Usage(_))
+    ));
+
+    let command = parse_args([
+        "runserver",
+        "0.0.0.0:9000",
+        "--set",
+        "debug=true",
+        "--set",
+        "log.level=debug",
+    ])
+    .unwrap();
+    let Command::RunServer { addr, overrides } = command else {
+        panic!("expected runserver");
+    };
+    let mut settings = Settings::default();
+    apply_overrides(&mut settings, addr.as_deref(), &overrides).unwrap();
+    assert_eq!(settings.host, "0.0.0.0");
+    assert_eq!(settings.port, 9000);
+    assert!(settings.debug);
+
+    let mut settings = Settings::default();
+    apply_overrides(&mut settings, Some("8080"), &[]).unwrap();
+    assert_eq!((settings.host.as_str(), settings.port), ("127.0.0.1", 8080));
+    assert!(apply_overrides(&mut settings, Some("localhost:http"), &[]).is_err());
+}
+
+fn router() -> Router {
+    let mut router = Router::new(Settings::default());
+    let mut notes = App::new("notes", "/notes");
+    notes
+        .routes(|g| {
+            g.get("/", |_| async { Response::html("all notes") });
+            g.post("/", |_| async {
+                Response::html("created").with_status(201)
+            });
+        })
+        .migration(
+            "0001_initial",
+            "CREATE TABLE note (id INTEGER PRIMARY KEY, title TEXT)",
+        );
+    router.mount(notes);
+    router
+}
+
+#[tokio::test]
+async fn test_routes_and_migrate() {
+    let router = router();
+    assert_eq!(routes_table(&router), "GET   /notes\nPOST  /notes\n");
+    assert!(middleware_table(&router, "/notes").starts_with("GET /notes\n   1. handler  "));
+    assert!(middleware_table(&router, "/missing").is_empty());
+
+    let db = Db::connect(":memory:").await.unwrap();
+    assert_eq!(
+        migrate(&router, &db).await.unwrap(),
+        vec!["notes.0001_initial"]
+    );
+    assert!(migrate(&router, &db).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_shell_runs_sql_and_requests() {
+    let router = router();
+    let db = Db::connect(":memory:").await.unwrap();
+    migrate(&router, &db).await.unwrap();
+
+    let input = "INSERT INTO note (id, title) VALUES (1, 'first');\n\
+                 SELECT id, title FROM note\n\
+                 SELECT * FROM missing\n\
+                 GET /notes\n\
+                 .quit\n\
+                 SELECT 1\n";
+    let mut output = Vec::new();
+    shell(&router, &db, input.as_bytes(), &mut output)
+        .await
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("1 row affected"));
+    assert!(output.contains("id\ttitle\n1\tfirst\n(1 row)"));
+    assert!(output.contains("error: "));
+    assert!(output.contains("200\nall notes"));
+    // Nothing after .quit runs
+    assert_eq!(output.matches("cobalto> ").count(), 5);
+}
+
+#[tokio::test]
+async fn test_linkcheck_fails_on_broken_links() {
+    let ok = execute(
+        Command::LinkCheck {
+            start: vec!["/notes".into()],
+        },
+        Settings::default(),
+        |_| router(),
+    )
+    .await;
+    assert!(ok.is_ok());
+    let broken = execute(
+        Command::LinkCheck {
+            start: vec!["/missing".into()],
+        },
+        Settings::default(),
+        |_| router(),
+    )
+    .await;
+    assert!(matches!(broken, Err(ManageError::Failed(_))));
+}
+
+#[tokio::test]
+async fn test_contract_verify_command() {
+    let contract = cobalto::contract::Contract {
+        consumer: "web".into(),
+        provider: "notes".into(),
+        interactions: vec![cobalto::contract::Interaction {
+            description: "list notes".into(),
+            request: cobalto::contract::ContractRequest {
+                method: "GET".into(),
+                path: "/notes".into(),
+                body: String::new(),
+            },
+            response: cobalto::contract::ContractResponse {
+                status: 200,
+                body: "all notes".into(),
+            },
+        }],
+    };
+    let path = std::env::temp_dir().join("cobalto_manage_contract.json");
+    contract.save(&path).unwrap();
+    let command = Command::VerifyContract {
+        path: path.display().to_string(),
+    };
+    assert!(
+        execute(command.clone(), Settings::default(), |_| router())
+            .await
+            .is_ok()
+    );
+    let changed = execute(command, Settings::default(), |_| {
+        Router::new(Settings::default())
+    })
+    .await;
+    assert!(matches!(changed, Err(ManageError::Failed(_))));
+    std::fs::remove_file(&path).unwrap();
+
+    let missing = Command::VerifyContract {
+        path: "/nonexistent/contract.json".into(),
+    };
+    assert!(matches!(
+        execute(missing, Settings::default(), |_| router()).await,
+        Err(ManageError::Io(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_start_runs_the_selected_background_roles() {
+    let hooks = Arc::new(AtomicUsize::new(0));
+    let (started, stopped) = (hooks.clone(), hooks.clone());
+    let command = Command::Start {
+        roles: vec![Role::Worker, Role::Scheduler],
+    };
+    // No queue is registered and the scheduler has no jobs: both roles end
+    // at once, and the server never starts
+    let result = execute(command, Settings::default(), move |_| {
+        let mut router = router();
+        router.schedule(Scheduler::new());
+        router.on_startup(move || {
+            started.fetch_add(1, Ordering::SeqCst);
+            async {}
+        });
+        router.on_shutdown(move || {
+            stopped.fetch_add(1, Ordering::SeqCst);
+            async {}
+        });
+        router
+    })
+    .await;
+    assert!(result.is_ok());
+    assert_eq!(hooks.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_start_keeps_the_worker_with_the_roles_that_enqueue() {
+    for roles in [vec![Role::Web], vec![Role::Worker]] {
+        let result = execute(Command::Start { roles }, Settings::default(), |_| {
+            let mut router = router();
+            router.task_queue(TaskQueue::default());
+            router
+        })
+        .await;
+        assert!(matches!(result, Err(ManageError::Failed(_))));
+    }
+}
@@ -0,0 +1,79 @@
+use cobalto::metrics::*;
+use cobalto::orm::Db;
+use cobalto::settings::Settings;
+use std::time::Duration;
+
+#[test]
+fn test_render_prometheus_text() {
+    record("GET", "/metrics_test/:id", 200, Duration::from_millis(30));
+    record("GET", "/metrics_test/:id", 200, Duration::from_millis(300));
+    record("GET", "/metrics_test/:id", 404, Duration::from_millis(2));
+    record("POST", "", 404, Duration::ZERO);
+    let guard = InFlight::start();
+
+    let text = render();
+    assert!(text.contains(
+        "cobalto_requests_total{method=\"GET\",route=\"/metrics_test/:id\",status=\"200\"} 2\n"
+    ));
+    assert!(
+        text.contains(
+            "cobalto_requests_total{method=\"POST\",route=\"<unmatched>\",status=\"404\"}"
+        )
+    );
+    let labels = "method=\"GET\",route=\"/metrics_test/:id\"";
+    assert!(text.contains(&format!(
+        "cobalto_request_duration_seconds_bucket{{{},le=\"0.005\"}} 1\n",
+        labels
+    )));
+    assert!(text.contains(&format!(
+        "cobalto_request_duration_seconds_bucket{{{},le=\"0.05\"}} 2\n",
+        labels
+    )));
+    assert!(text.contains(&format!(
+        "cobalto_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 3\n",
+        labels
+    )));
+    assert!(text.contains(&format!(
+        "cobalto_request_duration_seconds_count{{{}}} 3\n",
+        labels
+    )));
+    assert!(text.contains("# TYPE cobalto_requests_in_flight gauge"));
+    assert!(in_flight() >= 1);
+    drop(guard);
+}
+
+#[tokio::test]
+async fn test_health_checks_database() {
+    assert_eq!(health(None).await.status, 200);
+    let db = Db::connect(":memory:").await.unwrap();
+    let response = health(Some(&db)).await;
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains("\"database\":\"ok\""));
+
+    db.pool.close().await;
+    assert_eq!(health(Some(&db)).await.status, 503);
+}
+
+#[test]
+fn test_monitoring_settings() {
+    let settings = Settings::default()
+        .merge_vars([
+            (
+                "COBALTO_MONITORING__METRICS".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "COBALTO_MONITORING__HEALTHZ_PATH".to_string(),
+                "/health".to_string(),
+            ),
+        ])
+        .unwrap();
+    assert!(settings.monitoring.metrics);
+    assert!(settings.monitoring.healthz);
+    assert_eq!(settings.monitoring.healthz_path, "/health");
+    assert!(settings.validate().is_ok());
+
+    let mut bad = settings.clone();
+    bad.monitoring.metrics_path = "metrics".into();
+    assert!(bad.validate().is_err());
+}
@@ -0,0 +1,62 @@
+use cobalto::minify::*;
+use cobalto::router::Response;
+
+#[test]
+fn test_minify_collapses_whitespace_and_comments() {
+    let html = "<html>\n  <body>\n    <!-- note -->\n    <p>Hello   <b>world</b> !</p>\n  </body>\n</html>";
+    assert_eq!(
+        minify_html(html),
+        "<html> <body> <p>Hello <b>world</b> !</p> </body> </html>"
+    );
+}
+
+#[test]
+fn test_minify_preserves_pre_and_textarea() {
+    let html = "<div>\n  <pre>  a\n   b  </pre>\n  <textarea>x  y</textarea>\n</div>";
+    assert_eq!(
+        minify_html(html),
+        "<div> <pre>  a\n   b  </pre> <textarea>x  y</textarea> </div>"
+    );
+    assert_eq!(
+        minify_html("<SCRIPT>if (a  <  b) {}</Script>\n  <p>x</p>"),
+        "<SCRIPT>if (a  <  b) {}</Script> <p>x</p>"
+    );
+}
+
+#[test]
+fn test_minify_keeps_spaces_between_inline_tags_and_quoted_values() {
+    assert_eq!(
+        minify_html("<b>a</b>\n   <i>b</i>"),
+        "<b>a</b> <i>b</i>"
+    );
+    assert_eq!(
+        minify_html("<input   value=\"a   b\"\n   title='x  <y>'  />"),
+        "<input value=\"a   b\" title='x  <y>'/>"
+    );
+}
+
+#[test]
+fn test_minify_savings_exported_to_metrics() {
+    let m = MinifyHtml::new();
+    m.process(Response::html("<p>    x    </p>"));
+    let metrics = cobalto::metrics::render();
+    assert!(metrics.contains("# TYPE cobalto_minify_bytes_in_total counter"));
+    assert!(metrics.contains("cobalto_minify_bytes_out_total "));
+
+    // Every policy counts into the same, once-registered counters
+    let other = MinifyHtml::new();
+    assert!(std::sync::Arc::ptr_eq(&m.bytes_in, &other.bytes_in));
+}
+
+#[test]
+fn test_process_only_html_and_inlines_css() {
+    let m = MinifyHtml::new().with_critical_css("body{margin:0}");
+    let resp = m.process(Response::html("<head>\n</head>  <p>x</p>"));
+    assert_eq!(
+        resp.body,
+        "<head> <style>body{margin:0}</style></head> <p>x</p>"
+    );
+
+    let json = m.process(Response::json(vec!["  a  "]));
+    assert_eq!(json.body, r#"["  a  "]"#);
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "mirror")]
+
+use cobalto::mirror::{Mirror, divergence, forwarded_headers};
+use cobalto::router::Response;
+
+#[test]
+fn test_sampling_spreads_evenly() {
+    let mirror = Mirror::new("http://127.0.0.1:9").sample(10);
+    let picks: Vec<bool> = (0..20).map(|_| mirror.should_mirror()).collect();
+    assert_eq!(picks.iter().filter(|p| **p).count(), 2);
+    assert!(picks[9] && picks[19]);
+    assert!(!Mirror::new("http://x").sample(0).should_mirror());
+}
+
+#[test]
+fn test_divergence_detection() {
+    let primary = Response::html("hello");
+    assert_eq!(divergence(&primary, 200, "hello"), None);
+    assert!(
+        divergence(&primary, 500, "hello")
+            .unwrap()
+            .contains("status")
+    );
+    assert!(divergence(&primary, 200, "hi").unwrap().contains("body"));
+}
+
+#[test]
+fn test_safe_methods_only_by_default() {
+    let mirror = Mirror::new("http://x");
+    assert!(mirror.mirrors_method("GET") && mirror.mirrors_method("head"));
+    assert!(!mirror.mirrors_method("POST"));
+    assert!(mirror.methods(&["get", "post"]).mirrors_method("POST"));
+}
+
+#[test]
+fn test_hop_by_hop_headers_are_not_forwarded() {
+    let headers = std::collections::HashMap::from(
+        [
+            ("accept", "text/html"),
+            ("authorization", "Bearer t"),
+            ("connection", "keep-alive, x-trace"),
+            ("keep-alive", "timeout=5"),
+            ("x-trace", "1"),
+            ("transfer-encoding", "chunked"),
+            ("host", "example.com"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string())),
+    );
+    let forwarded = forwarded_headers(&headers);
+    let names: Vec<&str> = forwarded.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(names, ["accept", "authorization"]);
+}
@@ -0,0 +1,109 @@
+use actix_web::web::Bytes;
+use cobalto::multipart::{MEMORY_THRESHOLD, MultipartError, parse};
+use cobalto::router::{Request, RequestScope, with_request_scope};
+use std::collections::HashMap;
+
+const CONTENT_TYPE: &str = "multipart/form-data; boundary=XyZ";
+
+fn body(file: &[u8]) -> Vec<u8> {
+    let mut body = b"--XyZ\r\n\
+Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+Holiday\r\n\
+--XyZ\r\n\
+Content-Disposition: form-data; name=\"photo\"; filename=\"beach.png\"\r\n\
+Content-Type: image/png\r\n\r\n"
+        .to_vec();
+    body.extend_from_slice(file);
+    body.extend_from_slice(b"\r\n--XyZ--\r\n");
+    body
+}
+
+#[tokio::test]
+async fn test_request_multipart_fields_and_files() {
+    let scope = RequestScope {
+        headers: HashMap::from([("content-type".to_string(), CONTENT_TYPE.to_string())]),
+        raw_body: body(&[0, 159, 146, 150]).into(),
+        ..Default::default()
+    };
+    let form = with_request_scope(scope, async { Request::default().multipart().await })
+        .await
+        .unwrap();
+    assert_eq!(form.field("title"), Some("Holiday"));
+    let photo = form.file("photo").unwrap();
+    assert_eq!(photo.filename, "beach.png");
+    assert_eq!(photo.content_type, "image/png");
+    assert_eq!(photo.bytes().await.unwrap().as_ref(), &[0, 159, 146, 150]);
+    assert!(!photo.is_temp_file());
+}
+
+#[tokio::test]
+async fn test_large_files_spool_to_disk_and_limits_apply() {
+    let big = vec![7u8; MEMORY_THRESHOLD + 1];
+    let form = parse(CONTENT_TYPE, body(&big).into(), usize::MAX).await.unwrap();
+    let photo = form.file("photo").unwrap();
+    assert!(photo.is_temp_file());
+    assert_eq!(photo.size, big.len());
+    assert_eq!(photo.bytes().await.unwrap().len(), big.len());
+
+    assert!(matches!(
+        parse(CONTENT_TYPE, body(b"x").into(), 10).await,
+        Err(MultipartError::TooLarge { limit: 10 })
+    ));
+    assert!(matches!(
+        parse("application/json", Bytes::from_static(b"{}"), 10).await,
+        Err(MultipartError::NotMultipart)
+    ));
+}
+
+#[tokio::test]
+async fn test_streamed_body_split_across_chunks() {
+    use cobalto::multipart::parse_stream;
+    let big = vec![9u8; MEMORY_THRESHOLD * 2];
+    let body = body(&big);
+    // Small chunks split the boundaries and the part headers
+    let chunks: Vec<Result<_, std::io::Error>> = body
+        .chunks(4093)
+        .map(|c| Ok(Bytes::copy_from_slice(c)))
+        .collect();
+    let form = parse_stream(CONTENT_TYPE, futures::stream::iter(chunks), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(form.field("title"), Some("Holiday"));
+    let photo = form.file("photo").unwrap();
+    assert!(photo.is_temp_file());
+    assert_eq!(photo.size, big.len());
+    assert_eq!(photo.bytes().await.unwrap().as_ref(), big.as_slice());
+
+    let truncated = Bytes::copy_from_slice(&body[..body.len() - 12]);
+    assert!(matches!(
+        parse(CONTENT_TYPE, truncated, usize::MAX).await,
+        Err(MultipartError::Malformed(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_dispatched_upload_is_not_copied_to_the_text_body() {
+    use cobalto::router::{Response, Router, handler};
+    use cobalto::settings::Settings;
+
+    let mut router = Router::new(Settings::default());
+    router.add_route(
+        "POST",
+        "/upload",
+        handler(|req: Request| async move {
+            let form = req.multipart().await.unwrap();
+            let photo = form.file("photo").unwrap();
+            Response::html(format!("{}:{}", req.body.len(), photo.size))
+        }),
+        "upload",
+    );
+    let resp = router
+        .dispatch_with_headers(
+            "POST",
+            "/upload",
+            HashMap::from([("Content-Type".to_string(), CONTENT_TYPE.to_string())]),
+            body(&[1, 2, 3]).into(),
+        )
+        .await;
+    assert_eq!(resp.body, "0:3");
+}
@@ -0,0 +1,42 @@
+use cobalto::obfuscate::*;
+
+#[test]
+fn test_codec_roundtrip_and_non_sequential() {
+    let codec = IdCodec::new("s3cret");
+    for id in [0u64, 1, 2, 42, 1_000_000, u64::MAX] {
+        assert_eq!(codec.decode(&codec.encode(id)), Some(id));
+    }
+    assert_ne!(codec.encode(1), codec.encode(2));
+    assert_ne!(codec.encode(1), IdCodec::new("other").encode(1));
+}
+
+#[test]
+fn test_codec_rejects_garbage() {
+    let codec = IdCodec::new("s3cret");
+    assert_eq!(codec.decode(""), None);
+    assert_eq!(codec.decode("!!"), None);
+}
+
+#[test]
+fn test_serde_adapter() {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Order {
+        #[serde(with = "cobalto::obfuscate::sqid")]
+        id: u64,
+    }
+    set_secret_key("s3cret");
+    let json = serde_json::to_string(&Order { id: 7 }).unwrap();
+    assert!(!json.contains(":7"));
+    let back: Order = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.id, 7);
+}
+
+#[test]
+fn test_empty_secret_key_is_refused() {
+    set_secret_key("s3cret");
+    let encoded = encode_id(7).unwrap();
+    set_secret_key("");
+    assert!(is_configured());
+    assert_eq!(encode_id(7), Some(encoded));
+    assert_eq!(IdCodec::new("s3cret").encode(7), encode_id(7).unwrap());
+}
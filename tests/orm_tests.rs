@@ -25,3 +25,763 @@ async fn test_db_basic_crud() {
     let names: Vec<String> = people.into_iter().map(|person| person.name).collect();
     assert_eq!(names, vec!["Alice"]);
 }
+
+#[tokio::test]
+async fn test_sqlite_hardening_pragmas() {
+    use cobalto::orm::{Db, SqliteOptions};
+
+    let path = std::env::temp_dir().join("cobalto_pragmas_test.db");
+    let _ = std::fs::remove_file(&path);
+    let db = Db::connect_with(path.to_str().unwrap(), SqliteOptions::default())
+        .await
+        .unwrap();
+
+    let mode: (String,) = sqlx::query_as("PRAGMA journal_mode")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(mode.0.to_lowercase(), "wal");
+    let fk: (i64,) = sqlx::query_as("PRAGMA foreign_keys")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(fk.0, 1);
+
+    db.pool.close().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_on_delete_behaviours() {
+    use cobalto::orm::{Db, DeleteError, ForeignKey, OnDelete};
+
+    assert_eq!(OnDelete::from_attr("set_null"), Some(OnDelete::SetNull));
+    let comments = ForeignKey::new("comment", "post_id", "post", OnDelete::Cascade);
+    assert_eq!(
+        comments.ddl(),
+        "FOREIGN KEY (post_id) REFERENCES post(id) ON DELETE CASCADE"
+    );
+    let likes = ForeignKey::new("likes", "post_id", "post", OnDelete::Restrict);
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute("CREATE TABLE post (id INTEGER PRIMARY KEY, deleted_at TEXT)")
+        .await
+        .unwrap();
+    db.execute("CREATE TABLE comment (id INTEGER PRIMARY KEY, post_id INTEGER, deleted_at TEXT)")
+        .await
+        .unwrap();
+    db.execute("CREATE TABLE likes (id INTEGER PRIMARY KEY, post_id INTEGER, deleted_at TEXT)")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO post (id) VALUES (1), (2)")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO comment (post_id) VALUES (1), (1), (2)")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO likes (post_id) VALUES (2)")
+        .await
+        .unwrap();
+
+    let relations = [comments, likes];
+    // Soft delete cascades to comments
+    assert!(
+        db.delete_with_relations("post", "id", 1.into(), &relations, true)
+            .await
+            .unwrap()
+    );
+    let live: Vec<(i64,)> = db
+        .fetch_all("SELECT id FROM comment WHERE deleted_at IS NULL")
+        .await
+        .unwrap();
+    assert_eq!(live.len(), 1);
+
+    // Likes restrict the delete of post 2
+    let err = db
+        .delete_with_relations("post", "id", 2.into(), &relations, false)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DeleteError::Restricted(table, 1) if table == "likes"));
+}
+
+#[tokio::test]
+async fn test_delete_follows_the_relation_graph() {
+    use cobalto::orm::{Db, ForeignKey, OnDelete, SqlValue};
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute("CREATE TABLE shelf (code TEXT PRIMARY KEY)")
+        .await
+        .unwrap();
+    db.execute("CREATE TABLE book (id INTEGER PRIMARY KEY, shelf_code TEXT)")
+        .await
+        .unwrap();
+    db.execute("CREATE TABLE page (id INTEGER PRIMARY KEY, book_id INTEGER)")
+        .await
+        .unwrap();
+    db.execute("CREATE TABLE loan (id INTEGER PRIMARY KEY, page_id INTEGER)")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO shelf (code) VALUES ('a'), ('b')")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO book (id, shelf_code) VALUES (1, 'a'), (2, 'b')")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO page (id, book_id) VALUES (1, 1), (2, 1), (3, 2)")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO loan (page_id) VALUES (2)")
+        .await
+        .unwrap();
+
+    let mut books = ForeignKey::new("book", "shelf_code", "shelf", OnDelete::Cascade);
+    books.references_column = "code".to_string();
+    let relations = [
+        books,
+        ForeignKey::new("page", "book_id", "book", OnDelete::Cascade),
+        ForeignKey::new("loan", "page_id", "page", OnDelete::SetNull),
+    ];
+    let deleted = db
+        .delete_with_relations("shelf", "code", SqlValue::from("a"), &relations, false)
+        .await
+        .unwrap();
+    assert!(deleted);
+    let pages: Vec<(i64,)> = db.fetch_all("SELECT id FROM page").await.unwrap();
+    assert_eq!(pages, vec![(3,)]);
+    let loans: Vec<(Option<i64>,)> = db.fetch_all("SELECT page_id FROM loan").await.unwrap();
+    assert_eq!(loans, vec![(None,)]);
+}
+
+#[test]
+fn test_index_ddl_per_backend() {
+    use cobalto::orm::{Backend, Index};
+
+    let idx = Index::new("users", &["email"])
+        .unique()
+        .filter("deleted_at IS NULL");
+    assert_eq!(
+        idx.create_sql(Backend::Sqlite),
+        "CREATE UNIQUE INDEX IF NOT EXISTS users_email_idx ON users (email) WHERE deleted_at IS NULL"
+    );
+    assert_eq!(
+        idx.create_sql(Backend::Postgres),
+        "CREATE UNIQUE INDEX CONCURRENTLY IF NOT EXISTS users_email_idx ON users (email) WHERE deleted_at IS NULL"
+    );
+    assert_eq!(
+        idx.create_sql(Backend::MySql),
+        "CREATE UNIQUE INDEX users_email_idx ON users (email) ALGORITHM=INPLACE LOCK=NONE"
+    );
+}
+
+#[test]
+fn test_backend_from_url_and_placeholders() {
+    use cobalto::orm::Backend;
+
+    assert_eq!(
+        Backend::from_url("postgres://u@localhost/app"),
+        Backend::Postgres
+    );
+    assert_eq!(
+        Backend::from_url("postgresql://localhost/app"),
+        Backend::Postgres
+    );
+    assert_eq!(
+        Backend::from_url("mysql://root@localhost/app"),
+        Backend::MySql
+    );
+    assert_eq!(Backend::from_url("sqlite://app.db"), Backend::Sqlite);
+    assert_eq!(Backend::from_url("app.db"), Backend::Sqlite);
+
+    let sql = "SELECT * FROM t WHERE a = ? AND b = '?' AND c IN (?, ?)";
+    assert_eq!(Backend::Sqlite.placeholders(sql), sql);
+    assert_eq!(
+        Backend::Postgres.placeholders(sql),
+        "SELECT * FROM t WHERE a = $1 AND b = '?' AND c IN ($2, $3)"
+    );
+}
+
+#[tokio::test]
+async fn test_check_indexes_reports_missing_and_unused() {
+    use cobalto::orm::{Db, Index};
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT, name TEXT)")
+        .await
+        .unwrap();
+    db.execute("CREATE INDEX users_legacy_idx ON users (name)")
+        .await
+        .unwrap();
+
+    let declared = vec![Index::new("users", &["email"]).unique()];
+    let report = db.check_indexes(&declared).await.unwrap();
+    assert_eq!(report.missing, vec!["users_email_idx"]);
+    assert_eq!(report.unused, vec!["users_legacy_idx"]);
+
+    db.create_indexes(&declared).await.unwrap();
+    // Existing indexes are left alone
+    db.create_indexes(&declared).await.unwrap();
+    assert!(
+        db.check_indexes(&declared)
+            .await
+            .unwrap()
+            .missing
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_explain_flags_scans_and_missing_indexes() {
+    use cobalto::orm::Db;
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute("CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT)")
+        .await
+        .unwrap();
+    db.execute(
+        "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000) \
+         INSERT INTO posts (title) SELECT 'post ' || i FROM n",
+    )
+    .await
+    .unwrap();
+
+    let sql = "SELECT * FROM posts WHERE title = 'post 7'";
+    let plan = db.explain(sql).await.unwrap();
+    assert!(plan.steps.iter().any(|s| s.starts_with("SCAN")));
+    assert!(
+        plan.warnings
+            .iter()
+            .any(|w| w.contains("large table 'posts'"))
+    );
+    assert!(
+        plan.warnings
+            .iter()
+            .any(|w| w.contains("posts_title_idx ON posts (title)"))
+    );
+
+    db.execute("CREATE INDEX posts_title_idx ON posts (title)")
+        .await
+        .unwrap();
+    assert!(db.explain(sql).await.unwrap().warnings.is_empty());
+}
+
+#[tokio::test]
+async fn test_slow_queries_are_captured_with_plan() {
+    use cobalto::orm::{Db, SLOW_QUERY_LOG_SIZE, slow_queries};
+    use std::time::Duration;
+
+    let db = Db::connect(":memory:")
+        .await
+        .unwrap()
+        .with_slow_query_log(Duration::ZERO);
+    db.execute("CREATE TABLE slow_t (id INTEGER PRIMARY KEY, v TEXT)")
+        .await
+        .unwrap();
+    let _: Vec<(i64,)> = db
+        .fetch_all("SELECT id FROM slow_t WHERE v = 'a'")
+        .await
+        .unwrap();
+    let captured = slow_queries();
+    let select = captured
+        .iter()
+        .find(|q| q.sql.starts_with("SELECT id FROM slow_t"))
+        .unwrap();
+    assert!(
+        select
+            .plan
+            .as_ref()
+            .unwrap()
+            .steps
+            .iter()
+            .any(|s| s.contains("slow_t"))
+    );
+
+    // Only the most recent queries are kept
+    for i in 0..=SLOW_QUERY_LOG_SIZE {
+        db.execute(&format!("UPDATE slow_t SET v = 'run {}'", i))
+            .await
+            .unwrap();
+    }
+    let captured = slow_queries();
+    assert!(captured.len() <= SLOW_QUERY_LOG_SIZE);
+    let last = format!("UPDATE slow_t SET v = 'run {}'", SLOW_QUERY_LOG_SIZE);
+    assert!(captured.iter().any(|q| q.sql == last));
+    assert!(!captured.iter().any(|q| q.sql == "UPDATE slow_t SET v = 'run 0'"));
+}
+
+#[derive(Debug, sqlx::FromRow, PartialEq)]
+struct Member {
+    id: i64,
+    name: String,
+    age: i64,
+}
+
+impl cobalto::orm::Model for Member {
+    fn table_name() -> &'static str {
+        "member"
+    }
+}
+
+async fn member_db() -> cobalto::orm::Db {
+    let db = cobalto::orm::Db::connect(":memory:").await.unwrap();
+    db.execute(
+        "CREATE TABLE member (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INTEGER NOT NULL)",
+    )
+    .await
+    .unwrap();
+    db.execute(
+        "INSERT INTO member (name, age) VALUES ('Ada', 36), ('Alan', 41), ('Grace', 85), ('Linus', 17)",
+    )
+    .await
+    .unwrap();
+    db
+}
+
+#[tokio::test]
+async fn test_queryset_filter_order_limit() {
+    use cobalto::orm::Model;
+
+    let db = member_db().await;
+    let adults = Member::objects(&db)
+        .filter("age__gte", 18)
+        .order_by("-age")
+        .limit(2)
+        .all()
+        .await
+        .unwrap();
+    let names: Vec<&str> = adults.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, vec!["Grace", "Alan"]);
+
+    let qs = Member::objects(&db)
+        .filter("name__startswith", "A")
+        .exclude("age__in", vec![41]);
+    assert_eq!(qs.count().await.unwrap(), 1);
+    assert_eq!(qs.first().await.unwrap().unwrap().name, "Ada");
+
+    assert_eq!(
+        Member::objects(&db)
+            .filter("name__icontains", "RAC")
+            .count()
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        Member::objects(&db)
+            .filter("age__lt", 18)
+            .delete()
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(Member::objects(&db).count().await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_queryset_parameterizes_values_and_rejects_bad_fields() {
+    use cobalto::orm::{Model, SqlValue};
+
+    let db = member_db().await;
+    let (sql, params) = Member::objects(&db)
+        .filter("name", "x' OR 1=1 --")
+        .offset(5)
+        .to_sql();
+    assert_eq!(sql, "SELECT * FROM member WHERE name = ? LIMIT -1 OFFSET 5");
+    assert_eq!(params, vec![SqlValue::Text("x' OR 1=1 --".into())]);
+
+    let err = Member::objects(&db)
+        .filter("age; DROP TABLE member", 1)
+        .all()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, sqlx::Error::ColumnNotFound(_)));
+    assert_eq!(Member::objects(&db).count().await.unwrap(), 4);
+}
+
+#[tokio::test]
+async fn test_field_attributes_drive_ddl_and_writes() {
+    use cobalto::orm::{
+        Backend, Db, Field, FieldType, Model, create_table_sql, insert_sql, update_sql,
+    };
+
+    struct Account;
+
+    impl Model for Account {
+        fn table_name() -> &'static str {
+            "account"
+        }
+
+        fn fields() -> Vec<Field> {
+            vec![
+                Field::new("id", FieldType::Integer).primary_key(),
+                Field::new("email", FieldType::Text)
+                    .max_length(255)
+                    .unique(),
+                Field::new("created_at", FieldType::DateTime).default("now()"),
+                Field::new("bio", FieldType::Text).nullable(),
+            ]
+        }
+    }
+
+    assert_eq!(Account::primary_key(), "id");
+    assert_eq!(
+        create_table_sql::<Account>(Backend::Postgres, &[]),
+        "CREATE TABLE IF NOT EXISTS account (id BIGINT PRIMARY KEY GENERATED BY DEFAULT AS IDENTITY, \
+         email VARCHAR(255) NOT NULL UNIQUE, created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP, bio TEXT)"
+    );
+    assert_eq!(
+        insert_sql::<Account>(),
+        "INSERT INTO account (email, created_at, bio) VALUES (?, COALESCE(?, CURRENT_TIMESTAMP), ?)"
+    );
+    assert_eq!(
+        update_sql::<Account>(),
+        "UPDATE account SET email = ?, created_at = ?, bio = ? WHERE id = ?"
+    );
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Account>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    db.execute_with(
+        &insert_sql::<Account>(),
+        vec![
+            "a@example.com".into(),
+            None::<String>.into(),
+            None::<String>.into(),
+        ],
+    )
+    .await
+    .unwrap();
+    let (id, created): (i64, String) = sqlx::query_as("SELECT id, created_at FROM account")
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(id, 1);
+    assert!(!created.is_empty());
+
+    let too_long = "x".repeat(256);
+    assert!(
+        db.execute_with(
+            &insert_sql::<Account>(),
+            vec![
+                too_long.into(),
+                None::<String>.into(),
+                None::<String>.into()
+            ],
+        )
+        .await
+        .is_err()
+    );
+    assert!(
+        db.execute_with(
+            &insert_sql::<Account>(),
+            vec![
+                "a@example.com".into(),
+                None::<String>.into(),
+                None::<String>.into()
+            ],
+        )
+        .await
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_model_crud_methods() {
+    use cobalto::orm::{Backend, Db, Field, FieldType, Model, SqlValue, create_table_sql};
+
+    #[derive(Debug, sqlx::FromRow, PartialEq)]
+    struct Note {
+        id: i64,
+        title: String,
+    }
+
+    impl Model for Note {
+        fn table_name() -> &'static str {
+            "note"
+        }
+
+        fn fields() -> Vec<Field> {
+            vec![
+                Field::new("id", FieldType::Integer).primary_key(),
+                Field::new("title", FieldType::Text),
+            ]
+        }
+
+        fn values(&self) -> Vec<SqlValue> {
+            vec![self.id.into(), self.title.clone().into()]
+        }
+
+        fn set_primary_key(&mut self, id: i64) {
+            self.id = id;
+        }
+    }
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Note>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+
+    let mut note = Note {
+        id: 0,
+        title: "draft".into(),
+    };
+    note.save(&db).await.unwrap();
+    assert_eq!(note.id, 1);
+
+    note.title = "final".into();
+    note.save(&db).await.unwrap();
+    let loaded = Note::get(&db, 1).await.unwrap().unwrap();
+    assert_eq!(loaded, note);
+    assert_eq!(Note::all(&db).await.unwrap().len(), 1);
+
+    assert!(note.delete(&db).await.unwrap());
+    assert!(!note.delete(&db).await.unwrap());
+    assert!(Note::get(&db, 1).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_soft_deleted_rows_are_hidden() {
+    use cobalto::orm::{Db, Field, FieldType, Model, SqlValue};
+
+    #[derive(Debug, sqlx::FromRow, PartialEq)]
+    struct Memo {
+        id: i64,
+        deleted_at: Option<String>,
+    }
+
+    impl Model for Memo {
+        fn table_name() -> &'static str {
+            "memo"
+        }
+
+        fn fields() -> Vec<Field> {
+            vec![
+                Field::new("id", FieldType::Integer).primary_key(),
+                Field::new("deleted_at", FieldType::DateTime).nullable(),
+            ]
+        }
+
+        fn values(&self) -> Vec<SqlValue> {
+            vec![self.id.into(), self.deleted_at.clone().into()]
+        }
+    }
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute("CREATE TABLE memo (id INTEGER PRIMARY KEY, deleted_at TEXT)")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO memo (id) VALUES (1), (2)")
+        .await
+        .unwrap();
+
+    assert!(Memo::soft_delete());
+    let memo = Memo::get(&db, 1).await.unwrap().unwrap();
+    assert!(memo.delete(&db).await.unwrap());
+    assert!(!memo.delete(&db).await.unwrap());
+    assert!(Memo::get(&db, 1).await.unwrap().is_none());
+    assert_eq!(Memo::all(&db).await.unwrap().len(), 1);
+    assert_eq!(Memo::objects(&db).with_deleted().count().await.unwrap(), 2);
+}
+
+#[derive(Clone, Debug, sqlx::FromRow, PartialEq)]
+struct Author {
+    id: i64,
+    username: String,
+}
+
+impl cobalto::orm::Model for Author {
+    fn table_name() -> &'static str {
+        "author"
+    }
+
+    fn fields() -> Vec<cobalto::orm::Field> {
+        vec![
+            cobalto::orm::Field::new("id", cobalto::orm::FieldType::Integer).primary_key(),
+            cobalto::orm::Field::new("username", cobalto::orm::FieldType::Text),
+        ]
+    }
+
+    fn values(&self) -> Vec<cobalto::orm::SqlValue> {
+        vec![self.id.into(), self.username.clone().into()]
+    }
+}
+
+#[derive(Debug, sqlx::FromRow, PartialEq)]
+struct Article {
+    id: i64,
+    title: String,
+    author_id: i64,
+}
+
+impl cobalto::orm::Model for Article {
+    fn table_name() -> &'static str {
+        "article"
+    }
+
+    fn fields() -> Vec<cobalto::orm::Field> {
+        vec![
+            cobalto::orm::Field::new("id", cobalto::orm::FieldType::Integer).primary_key(),
+            cobalto::orm::Field::new("title", cobalto::orm::FieldType::Text),
+            cobalto::orm::Field::new("author_id", cobalto::orm::FieldType::Integer)
+                .foreign_key::<Author>(),
+        ]
+    }
+
+    fn values(&self) -> Vec<cobalto::orm::SqlValue> {
+        vec![
+            self.id.into(),
+            self.title.clone().into(),
+            self.author_id.into(),
+        ]
+    }
+}
+
+async fn article_db() -> cobalto::orm::Db {
+    use cobalto::orm::{Backend, Db, create_table_sql};
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Author>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    db.execute(&create_table_sql::<Article>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    db.execute("INSERT INTO author (username) VALUES ('bob'), ('alice')")
+        .await
+        .unwrap();
+    db.execute(
+        "INSERT INTO article (title, author_id) VALUES ('one', 1), ('two', 2), ('three', 1)",
+    )
+    .await
+    .unwrap();
+    db
+}
+
+#[tokio::test]
+async fn test_foreign_keys_and_related_lookups() {
+    use cobalto::orm::{Model, SqlValue};
+
+    let db = article_db().await;
+    let (sql, params) = Article::objects(&db)
+        .filter("author__username", "bob")
+        .to_sql();
+    assert_eq!(
+        sql,
+        "SELECT * FROM article WHERE author_id IN (SELECT id FROM author WHERE username = ?)"
+    );
+    assert_eq!(params, vec![SqlValue::from("bob")]);
+    let bobs = Article::objects(&db)
+        .filter("author__username", "bob")
+        .order_by("id")
+        .all()
+        .await
+        .unwrap();
+    assert_eq!(bobs.len(), 2);
+    assert_eq!(
+        Article::objects(&db)
+            .filter("author", 2)
+            .count()
+            .await
+            .unwrap(),
+        1
+    );
+
+    let article = &bobs[0];
+    let author: Author = article.related(&db, "author_id").await.unwrap().unwrap();
+    assert_eq!(author.username, "bob");
+    let written: Vec<Article> = author.related_set(&db).await.unwrap();
+    assert_eq!(written.len(), 2);
+}
+
+#[tokio::test]
+async fn test_select_and_prefetch_related() {
+    use cobalto::orm::Model;
+
+    let db = article_db().await;
+    let rows = Article::objects(&db)
+        .order_by("id")
+        .select_related::<Author>("author")
+        .await
+        .unwrap();
+    let pairs: Vec<(&str, &str)> = rows
+        .iter()
+        .map(|(a, u)| (a.title.as_str(), u.as_ref().unwrap().username.as_str()))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![("one", "bob"), ("two", "alice"), ("three", "bob")]
+    );
+
+    let authors = Author::objects(&db)
+        .order_by("id")
+        .prefetch_related::<Article>()
+        .await
+        .unwrap();
+    let counts: Vec<(&str, usize)> = authors
+        .iter()
+        .map(|(u, articles)| (u.username.as_str(), articles.len()))
+        .collect();
+    assert_eq!(counts, vec![("bob", 2), ("alice", 1)]);
+
+    assert!(
+        Article::objects(&db)
+            .select_related::<Author>("title")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_field_on_delete_drives_ddl_and_model_delete() {
+    use cobalto::orm::{
+        Backend, Db, Field, FieldType, Model, OnDelete, SqlValue, create_table_sql,
+        register_relations,
+    };
+
+    #[derive(Debug, sqlx::FromRow)]
+    struct Reply {
+        id: i64,
+        author_id: i64,
+    }
+
+    impl Model for Reply {
+        fn table_name() -> &'static str {
+            "reply"
+        }
+
+        fn fields() -> Vec<Field> {
+            vec![
+                Field::new("id", FieldType::Integer).primary_key(),
+                Field::new("author_id", FieldType::Integer)
+                    .foreign_key::<Author>()
+                    .on_delete(OnDelete::Cascade),
+            ]
+        }
+
+        fn values(&self) -> Vec<SqlValue> {
+            vec![self.id.into(), self.author_id.into()]
+        }
+    }
+
+    let ddl = create_table_sql::<Reply>(Backend::Sqlite, &[]);
+    assert!(ddl.ends_with(
+        "FOREIGN KEY (author_id) REFERENCES author(id) ON DELETE CASCADE)"
+    ));
+
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Author>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    db.execute(&ddl).await.unwrap();
+    db.execute("INSERT INTO author (username) VALUES ('bob')")
+        .await
+        .unwrap();
+    db.execute("INSERT INTO reply (author_id) VALUES (1), (1)")
+        .await
+        .unwrap();
+    register_relations(&Reply::foreign_keys());
+
+    let bob = Author::get(&db, 1).await.unwrap().unwrap();
+    assert!(bob.delete(&db).await.unwrap());
+    assert_eq!(Reply::objects(&db).count().await.unwrap(), 0);
+}
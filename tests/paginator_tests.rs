@@ -0,0 +1,82 @@
+use cobalto::orm::{Db, Model};
+use cobalto::paginator::Paginator;
+use cobalto::template::{TemplateValue, parse_tokens, render_nodes, tokenize_template};
+use std::collections::HashMap;
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow, PartialEq)]
+struct Item {
+    id: i64,
+    name: String,
+}
+
+impl Model for Item {
+    fn table_name() -> &'static str {
+        "item"
+    }
+}
+
+async fn item_db(count: usize) -> Db {
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute("CREATE TABLE item (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .await
+        .unwrap();
+    for i in 1..=count {
+        db.execute(&format!("INSERT INTO item (name) VALUES ('item {}')", i))
+            .await
+            .unwrap();
+    }
+    db
+}
+
+#[tokio::test]
+async fn test_pages_and_navigation() {
+    let db = item_db(7).await;
+    let paginator = Paginator::new(Item::objects(&db).order_by("id"), 3);
+
+    let first = paginator.page(1).await.unwrap();
+    assert_eq!(first.items.len(), 3);
+    assert_eq!((first.total_items, first.total_pages), (7, 3));
+    assert!(first.has_next() && !first.has_previous());
+    assert_eq!(first.next_page_number(), Some(2));
+
+    let last = paginator.page(3).await.unwrap();
+    assert_eq!(last.items[0].name, "item 7");
+    assert_eq!((last.start_index(), last.end_index()), (7, 7));
+    assert!(!last.has_next() && last.has_previous());
+
+    // Out of range and invalid numbers are clamped
+    assert_eq!(paginator.page(99).await.unwrap().number, 3);
+    assert_eq!(paginator.page_param(Some("abc")).await.unwrap().number, 1);
+
+    let empty = Paginator::new(Item::objects(&item_db(0).await), 3)
+        .page(1)
+        .await
+        .unwrap();
+    assert_eq!((empty.total_pages, empty.start_index()), (1, 0));
+}
+
+#[tokio::test]
+async fn test_page_template_context() {
+    let db = item_db(5).await;
+    let page = Paginator::new(Item::objects(&db).order_by("id"), 2)
+        .page(2)
+        .await
+        .unwrap();
+
+    let mut context = HashMap::new();
+    context.insert("page".to_string(), page.context());
+    let nodes = parse_tokens(&tokenize_template(
+        "{% for item in page.items %}{{ item.name }};{% endfor %}\
+         {% if page.has_previous %}<a href=\"?page={{ page.previous }}\">prev</a>{% endif %}\
+         {{ page.number }}/{{ page.total_pages }}\
+         {% for n in page.page_range %}[{{ n }}]{% endfor %}",
+    ));
+    assert_eq!(
+        render_nodes(&nodes, &context),
+        "item 3;item 4;<a href=\"?page=1\">prev</a>2/3[1][2][3]"
+    );
+    assert!(matches!(
+        page.context_with(|item| TemplateValue::String(item.name.clone())),
+        TemplateValue::Object(ref o) if matches!(o.get("items"), Some(TemplateValue::List(l)) if l.len() == 2)
+    ));
+}
@@ -0,0 +1,138 @@
+#![cfg(feature = "payments")]
+
+use chrono::{TimeZone, Utc};
+use cobalto::payments::*;
+use cobalto::router::Request;
+use cobalto::test::freeze_time;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const SECRET: &str = "whsec_test";
+
+fn signed(payload: &str, t: i64) -> String {
+    format!("t={},v1={}", t, sign_payload(SECRET, t, payload))
+}
+
+#[test]
+fn test_verify_webhook_signature_and_tolerance() {
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let frozen = freeze_time(now);
+    let payload = r#"{"type":"ping"}"#;
+    let t = now.timestamp();
+
+    assert!(verify_webhook(SECRET, &signed(payload, t), payload).is_ok());
+    assert_eq!(
+        verify_webhook(SECRET, &signed(payload, t), r#"{"type":"pong"}"#),
+        Err(WebhookError::InvalidSignature)
+    );
+    assert_eq!(
+        verify_webhook(SECRET, "v1=abc", payload),
+        Err(WebhookError::MissingSignature)
+    );
+    frozen.advance(chrono::Duration::minutes(10));
+    assert_eq!(
+        verify_webhook(SECRET, &signed(payload, t), payload),
+        Err(WebhookError::Expired)
+    );
+}
+
+#[tokio::test]
+async fn test_completed_checkout_is_fulfilled_once() {
+    use cobalto::orm::{Db, Model};
+    use cobalto::tasks::TaskQueue;
+
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let _frozen = freeze_time(now);
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(Payment::CREATE_TABLE).await.unwrap();
+    let queue = TaskQueue::new(8);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let webhook = StripeWebhook::new(
+        StripeConfig {
+            secret_key: "sk_test".into(),
+            webhook_secret: SECRET.into(),
+        },
+        db.clone(),
+        queue.clone(),
+    )
+    .unwrap()
+    .on_fulfilled(move |p| {
+        let sink = sink.clone();
+        async move { sink.lock().unwrap().push(p) }
+    });
+
+    let payload = r#"{"type":"checkout.session.completed","data":{"object":{"id":"cs_1","amount_total":500,"currency":"eur","payment_status":"paid"}}}"#;
+    let req = Request {
+        params: HashMap::new(),
+        body: payload.to_string(),
+        query: HashMap::new(),
+        ..Default::default()
+    };
+    let signature = signed(payload, now.timestamp());
+    assert_eq!(webhook.handle(&req, &signature).await.status, 200);
+    // Stripe retrying the delivery
+    assert_eq!(webhook.handle(&req, &signature).await.status, 200);
+    assert_eq!(queue.run_pending().await, 1);
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].session_id, "cs_1");
+    assert_eq!(seen[0].amount_total, 500);
+    let stored = Payment::all(&db).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].status, "paid");
+}
+
+#[test]
+fn test_webhook_requires_a_secret() {
+    use cobalto::settings::Settings;
+
+    let mut settings = Settings::default();
+    settings
+        .other
+        .insert("stripe_secret_key".into(), "sk_test".into());
+    assert!(StripeConfig::from_settings(&settings).is_none());
+    settings
+        .other
+        .insert("stripe_webhook_secret".into(), String::new());
+    assert!(StripeConfig::from_settings(&settings).is_none());
+
+    let payload = r#"{"type":"ping"}"#;
+    let t = cobalto::clock::now().timestamp();
+    let forged = format!("t={},v1={}", t, sign_payload("", t, payload));
+    assert_eq!(
+        verify_webhook("", &forged, payload),
+        Err(WebhookError::InvalidSignature)
+    );
+}
+
+#[test]
+fn test_checkout_form_params() {
+    let req = CheckoutRequest {
+        price_id: "price_1".into(),
+        quantity: 2,
+        success_url: "https://x/ok".into(),
+        cancel_url: "https://x/no".into(),
+        customer_email: None,
+        idempotency_key: Some("order-7".into()),
+    };
+    let params = req.form_params();
+    assert!(params.contains(&("line_items[0][quantity]".to_string(), "2".to_string())));
+}
+
+#[test]
+fn test_buy_button_escapes_its_arguments() {
+    use cobalto::template::{TagArgs, TemplateValue};
+    let args = TagArgs {
+        positional: vec!["price".into()],
+        named: HashMap::from([("label".to_string(), "\"<b>Buy</b>\"".to_string())]),
+    };
+    let context = HashMap::from([(
+        "price".to_string(),
+        TemplateValue::String("p\"><script>x</script>".into()),
+    )]);
+    let html = buy_button_tag(&args, &context);
+    assert!(html.contains(r#"value="p&quot;&gt;&lt;script&gt;x&lt;/script&gt;""#));
+    assert!(html.contains("<button type=\"submit\">&lt;b&gt;Buy&lt;/b&gt;</button>"));
+}
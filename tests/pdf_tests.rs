@@ -0,0 +1,54 @@
+#![cfg(feature = "pdf")]
+
+use cobalto::router::Response;
+use cobalto::template::{TemplateEngine, TemplateValue};
+use std::collections::HashMap;
+
+#[test]
+fn test_pdf_response_headers() {
+    let resp = Response::pdf(b"%PDF-1.4".to_vec(), "invoice.pdf");
+    assert_eq!(resp.headers.get("Content-Type").unwrap(), "application/pdf");
+    assert_eq!(
+        resp.headers.get("Content-Disposition").unwrap(),
+        "inline; filename=\"invoice.pdf\""
+    );
+    assert_eq!(resp.binary.as_deref(), Some(&b"%PDF-1.4"[..]));
+}
+
+#[tokio::test]
+async fn test_pdf_from_missing_template() {
+    let engine = TemplateEngine::default();
+    let resp =
+        Response::pdf_from_template(&engine, "hopefully_missing_invoice.html", &HashMap::new())
+            .await;
+    assert_eq!(resp.status, 404);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pdf_is_streamed_from_the_converter() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join("cobalto_pdf_test");
+    std::fs::create_dir_all(dir.join("templates")).unwrap();
+    std::fs::write(dir.join("templates/invoice.html"), "<p>{{ total }}</p>").unwrap();
+    // Echoes the HTML back, standing in for wkhtmltopdf
+    let bin = dir.join("fake-pdf");
+    std::fs::write(&bin, "#!/bin/sh\ncat\n").unwrap();
+    std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+    unsafe { std::env::set_var("COBALTO_PDF_BIN", &bin) };
+
+    let engine = TemplateEngine::new(dir.join("templates").to_str().unwrap());
+    let context = HashMap::from([(
+        "total".to_string(),
+        TemplateValue::String("42".to_string()),
+    )]);
+    let resp = Response::pdf_from_template(&engine, "invoice.html", &context).await;
+    assert_eq!(resp.status, 200);
+    assert!(resp.stream.is_some());
+    assert_eq!(
+        resp.headers["Content-Disposition"],
+        "inline; filename=\"invoice.pdf\""
+    );
+    assert_eq!(resp.body_bytes().await.unwrap(), b"<p>42</p>");
+}
@@ -0,0 +1,83 @@
+use cobalto::orm::{Db, Model};
+use cobalto::plugins::*;
+use cobalto::router::{Handler, Response, Router};
+use cobalto::settings::Settings;
+use std::sync::Arc;
+
+struct Greeting;
+
+impl Model for Greeting {
+    fn table_name() -> &'static str {
+        "greeting"
+    }
+}
+
+struct HelloPlugin;
+
+impl CobaltoPlugin for HelloPlugin {
+    fn name(&self) -> &'static str {
+        "hello"
+    }
+
+    fn configure(&self, settings: &mut Settings) {
+        settings
+            .other
+            .insert("hello_configured".into(), "yes".into());
+    }
+
+    fn routes(&self, router: &mut Router) {
+        let handler: Handler = Arc::new(|_| Box::pin(async { Response::html("hello") }));
+        router.add_route("GET", "/hello", handler, "hello");
+    }
+
+    fn wrap(&self, handler: Handler) -> Handler {
+        Arc::new(move |req| {
+            let handler = handler.clone();
+            Box::pin(async move { handler(req).await.add_header("X-Plugin", "hello") })
+        })
+    }
+
+    fn models(&self) -> Vec<ModelSchema> {
+        vec![ModelSchema::of::<Greeting>(
+            "CREATE TABLE IF NOT EXISTS greeting (id INTEGER PRIMARY KEY)",
+        )]
+    }
+}
+
+cobalto::register_plugin!(HelloPlugin);
+
+#[tokio::test]
+async fn test_enabled_plugin_is_installed() {
+    let mut settings = Settings::default();
+    settings.other.insert("plugins".into(), "hello".into());
+    let mut router = Router::new(settings);
+    // Configured before the router applies the settings
+    assert_eq!(router.settings.other["hello_configured"], "yes");
+    let home: Handler = Arc::new(|_| Box::pin(async { Response::html("home") }));
+    router.add_route("GET", "/", home, "home");
+
+    assert_eq!(router.install_plugins(), vec!["hello"]);
+    let resp = router.dispatch("GET", "/hello", "").await;
+    assert_eq!(resp.body, "hello");
+    // Plugin middleware wraps application routes too, even those added later
+    let resp = router.dispatch("GET", "/", "").await;
+    assert_eq!(resp.headers["X-Plugin"], "hello");
+    let late: Handler = Arc::new(|_| Box::pin(async { Response::html("late") }));
+    router.add_route("GET", "/late", late, "late");
+    let resp = router.dispatch("GET", "/late", "").await;
+    assert_eq!(resp.headers["X-Plugin"], "hello");
+
+    let db = Db::connect(":memory:").await.unwrap();
+    migrate_plugins(&db, &router.settings).await.unwrap();
+    db.execute("INSERT INTO greeting (id) VALUES (1)")
+        .await
+        .unwrap();
+}
+
+#[test]
+fn test_linked_but_unlisted_plugins_stay_disabled() {
+    assert!(available_plugins().iter().any(|p| p.name() == "hello"));
+    let mut router = Router::new(Settings::default());
+    assert!(router.install_plugins().is_empty());
+    assert!(router.routes.is_empty());
+}
@@ -0,0 +1,103 @@
+use cobalto::cache::Cache;
+use cobalto::progress::*;
+use cobalto::pubsub::PubSub;
+use cobalto::tasks::TaskQueue;
+use cobalto::template::{parse_tokens, render_nodes, tokenize_template};
+use cobalto::test::freeze_time;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[test]
+fn test_progress_updates_are_stored_and_published() {
+    let tracker = ProgressTracker::new(PubSub::new());
+    let mut sub = tracker.subscribe("job-1");
+    let progress = tracker.start("job-1");
+    progress.update(150, "Crunching");
+
+    let latest = tracker.get("job-1").unwrap();
+    assert_eq!(
+        (latest.percent, latest.message.as_str()),
+        (100, "Crunching")
+    );
+
+    let first: ProgressUpdate = serde_json::from_str(&sub.try_recv().unwrap()).unwrap();
+    assert_eq!(first.message, "Queued");
+
+    progress.finish("Done");
+    assert!(tracker.get("job-1").unwrap().done);
+}
+
+#[test]
+fn test_progress_sse_frame_and_template_tag() {
+    let update = ProgressUpdate {
+        task_id: "t".into(),
+        percent: 40,
+        message: "half".into(),
+        done: false,
+    };
+    assert!(update.to_sse().starts_with("event: progress\ndata: {"));
+    assert!(update.to_sse().ends_with("\n\n"));
+
+    let nodes = parse_tokens(&tokenize_template(r#"{% progress_bar "job-9" %}"#));
+    let html = render_nodes(&nodes, &HashMap::new());
+    assert!(html.contains(r#"new EventSource("/progress/job-9")"#));
+
+    // Task ids from the context cannot break out of the attribute or script
+    let context = HashMap::from([(
+        "job".to_string(),
+        cobalto::template::TemplateValue::String(r#"x"></div></script><script>alert(1)//"#.into()),
+    )]);
+    let nodes = parse_tokens(&tokenize_template(r#"{% progress_bar job %}"#));
+    let html = render_nodes(&nodes, &context);
+    assert!(!html.contains("</script><script>"));
+    assert!(html.contains(r#"id="progress-x&quot;&gt;&lt;/div&gt;"#));
+    assert!(html.contains(r#"new EventSource("/progress/x%22%3E%3C%2Fdiv%3E"#));
+}
+
+#[test]
+fn test_finished_tasks_are_evicted() {
+    let now = chrono::Utc::now();
+    let frozen = freeze_time(now);
+    let cache = Cache::new();
+    let hub = PubSub::new();
+    let tracker = ProgressTracker::new(hub.clone())
+        .cache(cache.clone())
+        .ttl(Duration::from_secs(600), Duration::from_secs(60));
+    let mut sub = tracker.subscribe("job-2");
+    let progress = tracker.start("job-2");
+    assert!(cache.get("progress:job-2").is_some());
+
+    progress.finish("Done");
+    // The final update is delivered, then the topic is gone
+    assert_eq!(sub.try_recv().map(|m| m.contains("Queued")), Some(true));
+    assert_eq!(sub.try_recv().map(|m| m.contains("Done")), Some(true));
+    assert!(hub.stats("progress:job-2").is_none());
+    assert!(progress.is_finished());
+
+    frozen.advance(chrono::Duration::seconds(61));
+    assert!(tracker.get("job-2").is_none());
+
+    // Tasks that stop reporting expire too
+    tracker.start("job-3").update(10, "Stuck");
+    frozen.advance(chrono::Duration::seconds(601));
+    assert!(tracker.get("job-3").is_none());
+}
+
+#[tokio::test]
+async fn test_progress_of_queued_tasks() {
+    let queue = TaskQueue::new(4);
+    let tracker = ProgressTracker::new(PubSub::new()).cache(Cache::new());
+    let progress = tracker
+        .enqueue(&queue, "import-1", |progress| async move {
+            progress.update(50, "Halfway");
+        })
+        .await
+        .unwrap();
+    assert_eq!(tracker.get("import-1").unwrap().message, "Queued");
+    assert!(!progress.is_finished());
+
+    assert_eq!(queue.run_pending().await, 1);
+    let last = tracker.get("import-1").unwrap();
+    assert!(last.done);
+    assert_eq!((last.percent, last.message.as_str()), (100, "Done"));
+}
@@ -0,0 +1,86 @@
+use cobalto::proxy::*;
+use cobalto::router::{Request, RequestScope, with_request_scope};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+fn ip(s: &str) -> Option<IpAddr> {
+    Some(s.parse().unwrap())
+}
+
+fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_forwarded_headers_only_from_trusted_proxies() {
+    let proxies = TrustedProxies::new(&["127.0.0.1", "10.0.0.0/8"]);
+    let sent = headers(&[
+        ("host", "internal:8000"),
+        ("x-forwarded-for", "203.0.113.7, 198.51.100.2, 10.1.2.3"),
+        ("x-forwarded-proto", "https"),
+        ("x-forwarded-host", "example.com"),
+    ]);
+
+    let via_proxy = proxies.resolve(ip("127.0.0.1"), &sent, false);
+    assert_eq!(via_proxy.client_ip, "198.51.100.2");
+    assert_eq!(via_proxy.scheme, "https");
+    assert_eq!(via_proxy.host, "example.com");
+
+    // A client sending the headers itself is not believed
+    let direct = proxies.resolve(ip("198.51.100.9"), &sent, false);
+    assert_eq!(direct.client_ip, "198.51.100.9");
+    assert_eq!(direct.scheme, "http");
+    assert_eq!(direct.host, "internal:8000");
+
+    // Values the client put in front of the ones the proxies append are
+    // ignored, like its own `X-Forwarded-For` entries
+    let spoofed = headers(&[
+        ("x-forwarded-for", "203.0.113.7, 198.51.100.2"),
+        ("x-forwarded-proto", "https, http"),
+        ("x-forwarded-host", "evil.example, example.com"),
+    ]);
+    let via_proxy = proxies.resolve(ip("127.0.0.1"), &spoofed, false);
+    assert_eq!(via_proxy.client_ip, "198.51.100.2");
+    assert_eq!(via_proxy.scheme, "http");
+    assert_eq!(via_proxy.host, "example.com");
+    // Two trusted proxies: the outer one's values are used
+    let chained = headers(&[
+        ("x-forwarded-for", "198.51.100.2, 10.1.2.3"),
+        ("x-forwarded-proto", "http, https, http"),
+    ]);
+    assert_eq!(proxies.resolve(ip("127.0.0.1"), &chained, false).scheme, "https");
+
+    assert!(proxies.is_trusted("::ffff:10.9.9.9".parse().unwrap()));
+    assert!(!TrustedProxies::default().is_trusted("127.0.0.1".parse().unwrap()));
+    assert!(is_valid_proxy("fd00::/8") && is_valid_proxy("*"));
+    assert!(!is_valid_proxy("10.0.0.0/33") && !is_valid_proxy("nginx"));
+    assert!(
+        cobalto::settings::Settings::default()
+            .set("trusted_proxies", "[\"10.0.0.1\", \"bogus\"]")
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_request_scheme_host_and_absolute_url() {
+    let scope = RequestScope {
+        forwarded: Forwarded {
+            client_ip: "203.0.113.7".into(),
+            scheme: "https".into(),
+            host: "example.com".into(),
+        },
+        ..Default::default()
+    };
+    let req = Request::default();
+    with_request_scope(scope, async {
+        assert!(req.is_secure());
+        assert_eq!(req.client_ip().as_deref(), Some("203.0.113.7"));
+        assert_eq!(req.absolute_url("/posts/1"), "https://example.com/posts/1");
+    })
+    .await;
+    assert_eq!(req.scheme(), "http");
+    assert_eq!(req.absolute_url("/posts/1"), "/posts/1");
+}
@@ -0,0 +1,46 @@
+use cobalto::pubsub::*;
+
+#[tokio::test]
+async fn test_publish_and_subscribe() {
+    let hub = PubSub::new();
+    let mut sub = hub.subscribe("news");
+    assert!(hub.publish("news", "hello"));
+    assert_eq!(sub.recv().await.as_deref(), Some("hello"));
+    assert_eq!(hub.subscriber_count("news"), 1);
+}
+
+#[test]
+fn test_drop_oldest_counts_lag() {
+    let hub = PubSub::new();
+    hub.configure(
+        "t",
+        TopicConfig {
+            capacity: 2,
+            backpressure: Backpressure::DropOldest,
+        },
+    );
+    let mut sub = hub.subscribe("t");
+    for i in 0..4 {
+        hub.publish("t", i.to_string());
+    }
+    assert_eq!(sub.try_recv().as_deref(), Some("2"));
+    let (published, dropped, lagged) = hub.stats("t").unwrap();
+    assert_eq!((published, dropped, lagged), (4, 0, 2));
+}
+
+#[test]
+fn test_drop_newest_refuses_when_full() {
+    let hub = PubSub::new();
+    hub.configure(
+        "t",
+        TopicConfig {
+            capacity: 1,
+            backpressure: Backpressure::DropNewest,
+        },
+    );
+    let mut sub = hub.subscribe("t");
+    assert!(hub.publish("t", "a"));
+    assert!(!hub.publish("t", "b"));
+    assert_eq!(sub.try_recv().as_deref(), Some("a"));
+    assert_eq!(hub.stats("t").unwrap().1, 1);
+}
@@ -0,0 +1,113 @@
+use chrono::{Duration, TimeZone, Utc};
+use cobalto::orm::{Backend, Db, Field, FieldType, Model, SqlValue, create_table_sql};
+use cobalto::quota::{QuotaLimits, Quotas};
+use cobalto::router::{Handler, Request, Response};
+use cobalto::test::freeze_time;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn tenant_request(tenant: &str) -> Request {
+    Request {
+        params: HashMap::from([("tenant".to_string(), tenant.to_string())]),
+        body: String::new(),
+        query: HashMap::new(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_daily_request_quota_resets_next_day() {
+    let frozen = freeze_time(Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap());
+    let quotas = Quotas::new(|req| req.params.get("tenant").cloned())
+        .with_defaults(QuotaLimits::new().requests_per_day(2));
+    let ok: Handler = Arc::new(|_| Box::pin(async { Response::html("ok") }));
+    let handler = quotas.wrap(ok);
+
+    assert_eq!(handler(tenant_request("acme")).await.status, 200);
+    assert_eq!(handler(tenant_request("acme")).await.status, 200);
+    assert_eq!(handler(tenant_request("acme")).await.status, 429);
+    assert_eq!(handler(tenant_request("globex")).await.status, 200);
+    assert_eq!(quotas.usage("acme").requests_today, 2);
+
+    frozen.advance(Duration::days(1));
+    assert_eq!(handler(tenant_request("acme")).await.status, 200);
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Doc {
+    id: i64,
+    tenant: String,
+    size: i64,
+}
+
+impl Model for Doc {
+    fn table_name() -> &'static str {
+        "doc"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("tenant", FieldType::Text),
+            Field::new("size", FieldType::Integer),
+        ]
+    }
+
+    fn values(&self) -> Vec<SqlValue> {
+        vec![self.id.into(), self.tenant.clone().into(), self.size.into()]
+    }
+
+    fn set_primary_key(&mut self, id: i64) {
+        self.id = id;
+    }
+}
+
+#[tokio::test]
+async fn test_row_and_storage_quotas_follow_the_database() {
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Doc>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+    db.execute("INSERT INTO doc (tenant, size) VALUES ('acme', 50), ('acme', 20), ('globex', 5)")
+        .await
+        .unwrap();
+
+    let quotas = Quotas::new(|_| None);
+    quotas.set_limits(
+        "acme",
+        QuotaLimits::new().rows::<Doc>(3).storage_bytes(100),
+    );
+    quotas.seed_rows::<Doc>(&db, "tenant").await.unwrap();
+    quotas.seed_storage::<Doc>(&db, "tenant", "size").await.unwrap();
+    assert_eq!(quotas.usage("acme").rows["doc"], 2);
+    assert_eq!(quotas.usage("acme").storage_bytes, 70);
+    assert_eq!(quotas.usage("globex").rows["doc"], 1);
+
+    quotas.track_rows::<Doc, _>(|doc| Some(doc.tenant.clone()));
+    quotas.track_storage::<Doc, _, _>(|doc| Some(doc.tenant.clone()), |doc| doc.size as u64);
+
+    assert!(quotas.check_rows::<Doc>("acme", 1).is_ok());
+    assert!(quotas.check_storage("acme", 30).is_ok());
+    let mut doc = Doc {
+        id: 0,
+        tenant: "acme".into(),
+        size: 30,
+    };
+    doc.save(&db).await.unwrap();
+    assert_eq!(quotas.usage("acme").rows["doc"], 3);
+    assert_eq!(quotas.usage("acme").storage_bytes, 100);
+    let err = quotas.check_rows::<Doc>("acme", 1).unwrap_err();
+    assert_eq!(err.resource, "rows:doc");
+    assert!(quotas.check_storage("acme", 1).is_err());
+
+    // Updates leave the counters alone, deletes release the row
+    doc.save(&db).await.unwrap();
+    assert_eq!(quotas.usage("acme").rows["doc"], 3);
+    assert!(doc.delete(&db).await.unwrap());
+    assert_eq!(quotas.usage("acme").rows["doc"], 2);
+    assert_eq!(quotas.usage("acme").storage_bytes, 70);
+
+    // Storage outside the models, and unlimited defaults for other tenants
+    assert!(quotas.add_storage("acme", 40).is_err());
+    assert!(quotas.add_storage("globex", 1_000).is_ok());
+}
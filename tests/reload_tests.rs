@@ -0,0 +1,38 @@
+use cobalto::reload::{Change, Watcher, inject_script, version};
+use cobalto::router::Response;
+
+#[test]
+fn test_watcher_reports_asset_and_source_changes() {
+    let root = std::env::temp_dir().join(format!("cobalto_reload_{}", std::process::id()));
+    let templates = root.join("templates");
+    let src = root.join("src");
+    std::fs::create_dir_all(&templates).unwrap();
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(templates.join("a.html"), "a").unwrap();
+
+    let mut watcher = Watcher::new().assets(&templates).source(&src);
+    assert_eq!(watcher.check(), None);
+
+    let before = version();
+    std::fs::write(templates.join("b.html"), "b").unwrap();
+    assert_eq!(watcher.check(), Some(Change::Assets));
+    assert!(version() > before);
+    assert_eq!(watcher.check(), None);
+
+    std::fs::write(src.join("main.rs"), "fn main() {}").unwrap();
+    assert_eq!(watcher.check(), Some(Change::Source));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_inject_script_only_into_html_pages() {
+    let page = inject_script(Response::html("<html><body><p>hi</p></body></html>"));
+    assert!(page.body.contains("/__cobalto/reload"));
+    assert!(page.body.ends_with("</script></body></html>"));
+
+    let fragment = inject_script(Response::html("<p>no body tag</p>"));
+    assert_eq!(fragment.body, "<p>no body tag</p>");
+    let json = inject_script(Response::json(serde_json::json!({"body": "</body>"})));
+    assert!(!json.body.contains("<script>"));
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "html-rewrite")]
+
+use cobalto::rewrite::{CspNonce, DEFAULT_CSP, HtmlRewriter};
+use cobalto::router::{Request, Response, Router, handler};
+use cobalto::settings::Settings;
+
+#[test]
+fn test_builtin_rewrites() {
+    let rewriter = HtmlRewriter::new()
+        .append_to_body("<script src=\"/a.js\"></script>")
+        .cdn_images("https://cdn.example.com/");
+    let html = rewriter.process(
+        r#"<body><img src="/logo.png"><img src="https://x.org/y.png"><script>1</script></body>"#,
+        Some("abc"),
+    );
+    assert!(html.contains(r#"<img src="https://cdn.example.com/logo.png">"#));
+    assert!(html.contains(r#"<img src="https://x.org/y.png">"#));
+    // Scripts already in the page are not trusted
+    assert!(html.contains("<script>1</script>"));
+    // Injected ones are, and are not rewritten further
+    assert!(html.ends_with(r#"<script src="/a.js" nonce="abc"></script></body>"#));
+}
+
+#[tokio::test]
+async fn test_installed_rewriter_uses_a_nonce_per_request() {
+    let rewriter = HtmlRewriter::new()
+        .on("h1", |el| {
+            el.set_inner_content("Hi", lol_html::html_content::ContentType::Text)
+        })
+        .unwrap()
+        .append_to_body("<script>track()</script>")
+        .csp_nonces(DEFAULT_CSP);
+    let mut router = Router::new(Settings::default());
+    rewriter.install(&mut router);
+    router.add_route(
+        "GET",
+        "/page",
+        handler(|req: Request| async move {
+            let nonce = req.extension::<CspNonce>().unwrap();
+            Response::html(format!("<body><h1>Hello</h1><p>{}</p></body>", nonce.0))
+        }),
+        "page",
+    );
+    router.add_route(
+        "GET",
+        "/stream",
+        handler(|_req: Request| async {
+            let chunks = futures::stream::iter(vec![
+                actix_web::web::Bytes::from("<body><h"),
+                actix_web::web::Bytes::from("1>Hello</h1></body>"),
+            ]);
+            Response::stream(chunks).add_header("Content-Type", "text/html")
+        }),
+        "stream",
+    );
+    router.add_route(
+        "GET",
+        "/api",
+        handler(|_req: Request| async { Response::json("<h1>Hello</h1>") }),
+        "api",
+    );
+
+    let first = router.dispatch("GET", "/page", "").await;
+    let second = router.dispatch("GET", "/page", "").await;
+    let nonce = |resp: &Response| {
+        let start = resp.body.find("<p>").unwrap() + 3;
+        resp.body[start..start + 32].to_string()
+    };
+    assert_ne!(nonce(&first), nonce(&second));
+    assert!(first.body.contains("<h1>Hi</h1>"));
+    assert!(first.body.contains(&format!(
+        "<script nonce=\"{}\">track()</script>",
+        nonce(&first)
+    )));
+    assert_eq!(
+        first.headers["Content-Security-Policy"],
+        DEFAULT_CSP.replace("{nonce}", &nonce(&first))
+    );
+
+    let streamed = router.dispatch("GET", "/stream", "").await;
+    let body = String::from_utf8(streamed.body_bytes().await.unwrap()).unwrap();
+    assert!(body.starts_with("<body><h1>Hi</h1><script nonce=\""));
+
+    let api = router.dispatch("GET", "/api", "").await;
+    assert_eq!(api.body, "\"<h1>Hello</h1>\"");
+    assert!(!api.headers.contains_key("Content-Security-Policy"));
+    assert!(HtmlRewriter::new().on("::bogus(", |_| {}).is_err());
+}
@@ -0,0 +1,58 @@
+use cobalto::route_tree::{RouteError, RouteTree};
+
+#[test]
+fn test_most_specific_segment_wins() {
+    let mut tree = RouteTree::new();
+    tree.insert("GET", "/users/:name", "by-name").unwrap();
+    tree.insert("GET", "/users/:id<i64>", "by-id").unwrap();
+    tree.insert("GET", "/users/new", "new").unwrap();
+    tree.insert("GET", "/users/:id/posts", "posts").unwrap();
+    tree.insert("GET", "/*rest", "fallback").unwrap();
+
+    assert_eq!(tree.find("GET", "/users/new").unwrap().0, &"new");
+    let (route, params) = tree.find("get", "/users/42").unwrap();
+    assert_eq!((route, params["id"].as_str()), (&"by-id", "42"));
+    let (route, params) = tree.find("GET", "/users/ada").unwrap();
+    assert_eq!((route, params["name"].as_str()), (&"by-name", "ada"));
+    // Backtracks out of the typed branch, which has no `/posts` child
+    let (route, params) = tree.find("GET", "/users/7/posts").unwrap();
+    assert_eq!((route, params["id"].as_str()), (&"posts", "7"));
+    let (route, params) = tree.find("GET", "/users/7/likes").unwrap();
+    assert_eq!(
+        (route, params["rest"].as_str()),
+        (&"fallback", "users/7/likes")
+    );
+    assert!(tree.find("POST", "/users/new").is_none());
+}
+
+#[test]
+fn test_conflicting_and_invalid_patterns_are_rejected() {
+    let mut tree = RouteTree::new();
+    tree.insert("GET", "/users/:id", 1).unwrap();
+    tree.insert("POST", "/users/:id", 2).unwrap();
+    tree.insert("GET", "/posts/:page?", 3).unwrap();
+
+    assert_eq!(
+        tree.insert("get", "/users/:pk", 4),
+        Err(RouteError::Conflict {
+            method: "GET".to_string(),
+            pattern: "/users/:pk".to_string(),
+            existing: "/users/:id".to_string(),
+        })
+    );
+    assert!(matches!(
+        tree.insert("GET", "/posts", 5),
+        Err(RouteError::Conflict { .. })
+    ));
+    assert!(matches!(
+        tree.insert("GET", "/a/*rest/b", 6),
+        Err(RouteError::Invalid { .. })
+    ));
+    assert!(matches!(
+        tree.insert("GET", "/a/:x?/b", 7),
+        Err(RouteError::Invalid { .. })
+    ));
+    assert_eq!(tree.find("GET", "/users/1").unwrap().0, &1);
+    assert_eq!(tree.allowed_methods("/users/1"), vec!["GET", "POST"]);
+    assert!(tree.allowed_methods("/nope").is_empty());
+}
@@ -3,37 +3,39 @@ use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use cobalto::route_tree::RouteTree;
+use cobalto::settings::Settings;
+
+/// Parameters of `path` matched against a single route `pattern`.
+fn match_path(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let mut tree = RouteTree::new();
+    tree.insert("GET", pattern, ()).unwrap();
+    tree.find("GET", path).map(|(_, params)| params)
+}
+
 // ========== Response struct (JSON, HTML) ==========
 
 #[test]
-fn test_response_ok() {
-    let resp = Response::ok("hello world");
-    assert_eq!(resp.status_code, 200);
+fn test_response_html() {
+    let resp = Response::html("hello world");
+    assert_eq!(resp.status, 200);
     assert_eq!(resp.body, "hello world");
-    // Should default to empty headers for text/html stub
-    assert!(resp.headers.is_empty());
+    assert_eq!(resp.headers["Content-Type"], "text/html; charset=utf-8");
 }
 
 #[test]
-fn test_response_forbidden() {
-    let resp = Response::forbidden("nope");
-    assert_eq!(resp.status_code, 403);
+fn test_response_with_status() {
+    let resp = Response::html("nope").with_status(403);
+    assert_eq!(resp.status, 403);
     assert_eq!(resp.body, "nope");
 }
 
-#[test]
-fn test_response_not_found() {
-    let resp = Response::not_found();
-    assert_eq!(resp.status_code, 404);
-    assert!(resp.body.contains("404"));
-}
-
 #[test]
 fn test_response_json_success() {
-    let mut headers = HashMap::new();
-    headers.insert("X-Test".into(), "yes".into());
-    let resp = Response::json(json!({"foo": "bar"}), 201, headers.clone());
-    assert_eq!(resp.status_code, 201);
+    let resp = Response::json(json!({"foo": "bar"}))
+        .with_status(201)
+        .add_header("X-Test", "yes");
+    assert_eq!(resp.status, 201);
     assert_eq!(
         resp.headers.get("Content-Type").unwrap(),
         "application/json; charset=utf-8"
@@ -45,76 +47,37 @@ fn test_response_json_success() {
 #[test]
 fn test_match_path_static() {
     // Exact match
-    assert!(cobalto::router::match_path("/foo", "/foo").is_some());
+    assert!(match_path("/foo", "/foo").is_some());
     // Parameter extraction
-    let params = cobalto::router::match_path("/user/:id", "/user/99").unwrap();
+    let params = match_path("/user/:id", "/user/99").unwrap();
     assert_eq!(params.get("id").unwrap(), "99");
     // No match for different length
-    assert!(cobalto::router::match_path("/a/b", "/a").is_none());
+    assert!(match_path("/a/b", "/a").is_none());
     // No match when value not matching
-    assert!(cobalto::router::match_path("/foo/bar", "/foo/qux").is_none());
+    assert!(match_path("/foo/bar", "/foo/qux").is_none());
 }
 
-#[test]
-fn test_middleware_execution_and_post_middleware() {
+#[tokio::test]
+async fn test_middleware_execution_and_post_middleware() {
+    let mut router = Router::new(Settings::default());
     // Middleware that intercepts all, returns a custom response
-    let mw: Middleware = Arc::new(move |_| Some(Response::forbidden("blocked")));
-
+    router.add_middleware(Arc::new(|_| {
+        Some(Response::html("blocked").with_status(403))
+    }));
     // Post-middleware always bumps status to 401
-    let pmw: PostMiddleware = Arc::new(|_ctx, mut resp| {
-        resp.status_code = 401;
+    router.add_post_middleware(Arc::new(|_ctx, mut resp: Response| {
+        resp.status = 401;
         resp
-    });
-
-    let mut router = Router::new();
-    router.add_middleware(mw);
-    router.add_post_middleware(pmw);
-
-    // Add dummy route
-    let handler: Handler = Arc::new(|_params| Box::pin(async { Response::ok("Hello!") }));
-    router.add_route("/blocked", handler, vec![]);
-
-    // Simulate middleware execution
-    let mut ctx = RequestContext {
-        path: "/blocked".to_string(),
-        params: HashMap::new(),
-        is_authenticated: false,
-        start_time: None,
-    };
+    }));
+    let page: Handler = Arc::new(|_req| Box::pin(async { Response::html("Hello!") }));
+    router.add_route("GET", "/blocked", page, "blocked");
 
-    // Should be intercepted by pre-middleware and adjusted by post-middleware
-    let mut response = Response::not_found();
-    for mw in &router.middlewares {
-        if let Some(resp) = mw(&mut ctx) {
-            response = resp;
-            break;
-        }
-    }
-    for pmw in &router.post_middlewares {
-        response = pmw(&ctx, response);
-    }
-    assert_eq!(response.status_code, 401);
+    // Intercepted by the pre-middleware and adjusted by the post-middleware
+    let response = router.dispatch("GET", "/blocked", "").await;
+    assert_eq!(response.status, 401);
     assert!(response.body.contains("blocked"));
 }
 
-#[test]
-fn test_response_ok_and_json() {
-    let resp = Response::ok("hello");
-    assert_eq!(resp.status_code, 200);
-    assert_eq!(resp.body, "hello");
-
-    let mut headers = HashMap::new();
-    headers.insert("X-Test".into(), "works".into());
-    let resp = Response::json(serde_json::json!({"test":"json"}), 201, headers.clone());
-    assert_eq!(resp.status_code, 201);
-    assert_eq!(
-        resp.headers.get("Content-Type").unwrap(),
-        "application/json; charset=utf-8"
-    );
-    assert_eq!(resp.headers.get("X-Test").unwrap(), "works");
-    assert!(resp.body.contains("\"test\":\"json\""));
-}
-
 // Test match_path logic
 #[test]
 fn test_static_and_param_matching() {
@@ -127,64 +90,34 @@ fn test_static_and_param_matching() {
 }
 
 // Middleware/pre and post order
-#[test]
-fn test_middleware_and_post_middleware() {
-    let before: Middleware = Arc::new(|ctx| {
-        if ctx.path == "/blocked" {
-            Some(Response::forbidden("block"))
-        } else {
-            None
-        }
-    });
-    let post: PostMiddleware = Arc::new(|_ctx, mut resp| {
+#[tokio::test]
+async fn test_middleware_and_post_middleware() {
+    let mut router = Router::new(Settings::default());
+    router.add_middleware(Arc::new(|ctx: &mut RequestContext| {
+        (ctx.path == "/blocked").then(|| Response::html("block").with_status(403))
+    }));
+    router.add_post_middleware(Arc::new(|_ctx, mut resp: Response| {
         resp.body = format!("{}+PM", resp.body);
         resp
-    });
+    }));
+    let page: Handler = Arc::new(|_req| Box::pin(async { Response::html("allowed") }));
+    router.add_route("GET", "/blocked", page.clone(), "blocked");
+    router.add_route("GET", "/open", page, "open");
 
-    let mut router = Router::new();
-    router.add_middleware(before);
-    router.add_post_middleware(post);
-    let handler: Handler = Arc::new(|_params| Box::pin(async { Response::ok("allowed") }));
-    router.add_route("/blocked", handler.clone(), vec![]);
-    router.add_route("/open", handler, vec![]);
-
-    // Simulate pre middleware triggering a block
-    let mut ctx = RequestContext {
-        path: "/blocked".to_string(),
-        params: HashMap::new(),
-        is_authenticated: false,
-        start_time: None,
-    };
-    let mut resp = Response::not_found();
-    for mw in &router.middlewares {
-        if let Some(r) = mw(&mut ctx) {
-            resp = r;
-            break;
-        }
-    }
-    for pmw in &router.post_middlewares {
-        resp = pmw(&ctx, resp);
-    }
-    assert_eq!(resp.status_code, 403);
+    // The pre middleware blocks, the post middleware still runs
+    let resp = router.dispatch("GET", "/blocked", "").await;
+    assert_eq!(resp.status, 403);
     assert_eq!(resp.body, "block+PM");
 
     // For open, post-middleware only
-    let open_ctx = RequestContext {
-        path: "/open".to_string(),
-        ..ctx
-    };
-    let mut resp = Response::ok("hello");
-    for pmw in &router.post_middlewares {
-        resp = pmw(&open_ctx, resp);
-    }
-    assert_eq!(resp.body, "hello+PM");
+    assert_eq!(router.dispatch("GET", "/open", "").await.body, "allowed+PM");
 }
 
 // Register a dummy user websocket handler and check storage
 #[test]
 fn test_user_websocket_registration() {
-    let ws_handler: WsHandler = Arc::new(|_ctx, _ws| Box::pin(async { () }));
-    let mut router = Router::new();
+    let ws_handler: WsHandler = Arc::new(|_ctx, _ws| Box::pin(async {}));
+    let mut router = Router::new(Settings::default());
     router.add_websocket("/ws/echo", ws_handler.clone());
     assert_eq!(router.ws_routes.len(), 1);
     assert_eq!(router.ws_routes[0].path_pattern, "/ws/echo");
@@ -205,13 +138,8 @@ impl Serialize for AlwaysFailsSerialize {
 
 #[test]
 fn test_response_json_error_branch_always_fails() {
-    let mut headers = HashMap::new();
-    headers.insert("Test-Head".to_string(), "Y".to_string());
-    let value = AlwaysFailsSerialize;
-    let resp = Response::json(value, 200, headers.clone());
-    // Should hit error branch and status_code becomes 500
-    assert_eq!(resp.status_code, 500);
-    assert!(resp.body.contains("Serialization failed"));
+    let resp = Response::json(AlwaysFailsSerialize).add_header("Test-Head", "Y");
+    assert!(resp.body.contains("Failed to serialize body"));
     assert_eq!(
         resp.headers.get("Content-Type").unwrap(),
         "application/json; charset=utf-8"
@@ -219,54 +147,36 @@ fn test_response_json_error_branch_always_fails() {
     assert_eq!(resp.headers.get("Test-Head").unwrap(), "Y");
 }
 
-#[test]
-fn test_empty_middleware_and_postorder_chain() {
-    let mut router = Router::new();
-    let handler: Handler = Arc::new(|_params| Box::pin(async { Response::ok("hi") }));
-    router.add_route("/basic", handler, vec![]);
-
-    let mut ctx = RequestContext {
-        path: "/basic".to_string(),
-        params: HashMap::new(),
-        is_authenticated: false,
-        start_time: None,
-    };
-    let mut resp = Response::ok("start");
-    for mw in &router.middlewares {
-        if let Some(r) = mw(&mut ctx) {
-            resp = r;
-        }
-    }
-    for pmw in &router.post_middlewares {
-        resp = pmw(&ctx, resp);
-    }
-    assert_eq!(resp.body, "start");
+#[tokio::test]
+async fn test_empty_middleware_and_postorder_chain() {
+    let mut router = Router::new(Settings::default());
+    let page: Handler = Arc::new(|_req| Box::pin(async { Response::html("start") }));
+    router.add_route("GET", "/basic", page, "basic");
+    assert!(router.middlewares.is_empty() && router.post_middlewares.is_empty());
+    assert_eq!(router.dispatch("GET", "/basic", "").await.body, "start");
 }
 
-#[test]
-fn test_parameterless_and_param_route() {
-    let handler: Handler = Arc::new(|params| {
+#[tokio::test]
+async fn test_parameterless_and_param_route() {
+    let echo: Handler = Arc::new(|req: Request| {
         Box::pin(async move {
-            let id = params.get("id").cloned().unwrap_or_default();
-            Response::ok(id)
+            let id = req.params.get("id").cloned().unwrap_or_default();
+            Response::html(id)
         })
     });
 
-    let mut router = Router::new();
-    router.add_route("/about", handler.clone(), vec![]);
-    router.add_route("/user/:id", handler, vec![]);
+    let mut router = Router::new(Settings::default());
+    router.add_route("GET", "/about", echo.clone(), "about");
+    router.add_route("GET", "/user/:id", echo, "user");
 
-    // match_path for /about
-    assert!(match_path("/about", "/about").is_some());
-    // match_path for parameter
-    let params = match_path("/user/:id", "/user/314");
-    assert_eq!(params.unwrap().get("id").unwrap(), "314");
+    assert_eq!(router.dispatch("GET", "/about", "").await.body, "");
+    assert_eq!(router.dispatch("GET", "/user/314", "").await.body, "314");
 }
 
 #[test]
 fn test_ws_route_storage_and_registration() {
-    let ws_handler: WsHandler = Arc::new(|_ctx, _ws| Box::pin(async { () }));
-    let mut router = Router::new();
+    let ws_handler: WsHandler = Arc::new(|_ctx, _ws| Box::pin(async {}));
+    let mut router = Router::new(Settings::default());
     router.add_websocket("/ws/test", ws_handler);
     assert_eq!(router.ws_routes.len(), 1);
     assert_eq!(router.ws_routes[0].path_pattern, "/ws/test");
@@ -280,101 +190,686 @@ fn test_match_path_non_matching() {
     assert!(match_path("/only", "/only/extra").is_none());
 }
 
-#[test]
-fn test_post_middleware_chain_order_and_context_isolation() {
-    let mut router = Router::new();
-    let h: Handler = Arc::new(|_p| Box::pin(async { Response::ok("x") }));
-    router.add_route("/a", h, vec![]);
+#[tokio::test]
+async fn test_post_middleware_chain_order_and_context_isolation() {
+    let mut router = Router::new(Settings::default());
+    let page: Handler = Arc::new(|_req| Box::pin(async { Response::html("abc") }));
+    router.add_route("GET", "/a", page, "a");
 
     // Add two post-middlewares (simulates a filter chain)
-    router.add_post_middleware(Arc::new(|_ctx, mut r| {
+    router.add_post_middleware(Arc::new(|_ctx, mut r: Response| {
         r.body.push('1');
         r
     }));
-    router.add_post_middleware(Arc::new(|_ctx, mut r| {
+    router.add_post_middleware(Arc::new(|_ctx, mut r: Response| {
         r.body.push('2');
         r
     }));
 
-    let ctx = RequestContext {
-        path: "/a".to_string(),
-        params: HashMap::new(),
-        is_authenticated: false,
-        start_time: None,
-    };
-    let resp = Response::ok("abc");
-    let mut result = resp;
-    for pmw in &router.post_middlewares {
-        result = pmw(&ctx, result);
-    }
-    assert_eq!(result.body, "abc12");
+    assert_eq!(router.dispatch("GET", "/a", "").await.body, "abc12");
 }
 
-#[test]
-fn test_handler_with_params_and_middleware_modification() {
-    let mut router = Router::new();
-    let h: Handler = Arc::new(|params| {
-        Box::pin(async move {
-            let who = params
+#[tokio::test]
+async fn test_handler_with_params_and_middleware_modification() {
+    let mut router = Router::new(Settings::default());
+    router.group("", |g| {
+        // A middleware that overwrites params
+        g.add_middleware(Arc::new(|ctx: &mut RequestContext| {
+            ctx.params
+                .insert("who".to_string(), "overridden".to_string());
+            None
+        }));
+        g.get("/hi/:who", |req: Request| async move {
+            let who = req
+                .params
                 .get("who")
                 .cloned()
                 .unwrap_or_else(|| "nobody".to_string());
-            Response::ok(format!("hello {who}"))
-        })
+            Response::html(format!("hello {who}"))
+        });
     });
 
-    // Simulate a middleware that overwrites params
+    let resp = router.dispatch("GET", "/hi/tomato", "").await;
+    assert_eq!(resp.body, "hello overridden");
+}
+
+#[test]
+fn test_router_keeps_settings_and_websockets() {
+    let settings = Settings {
+        debug: true,
+        host: "x".into(),
+        port: 1,
+        ws_port: 2,
+        ..Default::default()
+    };
+    let mut router = Router::new(settings);
+    let wsh: WsHandler = Arc::new(|_, _| Box::pin(async {}));
+    router.add_websocket("/ws/api", wsh);
+    assert!(router.settings.debug);
+    assert_eq!((router.settings.port, router.settings.ws_port), (1, 2));
+    assert_eq!(router.ws_routes.len(), 1);
+}
+
+#[test]
+fn test_suggest_routes_near_misses() {
+    let patterns = ["/users", "/users/:id", "/about", "/contact"];
+    assert_eq!(suggest_routes(&patterns, "/user", 5), vec!["/users"]);
+    assert_eq!(suggest_routes(&patterns, "/abuot", 5), vec!["/about"]);
+    assert!(suggest_routes(&patterns, "/completely/unrelated/path", 5).is_empty());
+}
+
+#[derive(serde::Serialize)]
+struct Post {
+    id: i64,
+    title: String,
+}
+
+impl cobalto::orm::Model for Post {
+    fn table_name() -> &'static str {
+        "post"
+    }
+}
+
+#[tokio::test]
+async fn test_models_into_json_responses() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
     router.add_route(
-        "/hi/:who",
-        h,
-        vec![Arc::new(|ctx| {
-            ctx.params
-                .insert("who".to_string(), "overridden".to_string());
-            None
-        })],
+        "GET",
+        "/posts/:id",
+        handler(|_req| async {
+            Ok::<_, sqlx::Error>(Post {
+                id: 1,
+                title: "Hello".into(),
+            })
+        }),
+        "show",
+    );
+    router.add_route(
+        "GET",
+        "/missing",
+        handler(|_req| async { Err::<Post, _>(sqlx::Error::RowNotFound) }),
+        "missing",
+    );
+    router.add_route(
+        "GET",
+        "/broken",
+        handler(|_req| async {
+            Err::<Post, _>(sqlx::Error::Protocol("relation \"post\" does not exist".into()))
+        }),
+        "broken",
+    );
+    let resp = router.dispatch("GET", "/posts/1", "").await;
+    assert_eq!(resp.status, 200);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&resp.body).unwrap(),
+        json!({"id": 1, "title": "Hello"})
     );
+    assert_eq!(router.dispatch("GET", "/missing", "").await.status, 404);
+    let broken = router.dispatch("GET", "/broken", "").await;
+    assert_eq!(broken.status, 500);
+    assert!(!broken.body.contains("relation"));
+}
+
+#[tokio::test]
+async fn test_warmup_tasks_run_in_order_before_ready() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    for name in ["templates", "cache"] {
+        let log = log.clone();
+        router.warmup(name, move || {
+            let log = log.clone();
+            async move { log.lock().unwrap().push(name) }
+        });
+    }
+    assert!(!router.is_ready());
+    let timings = router.run_warmups().await;
+    assert!(router.is_ready());
+    assert_eq!(*log.lock().unwrap(), vec!["templates", "cache"]);
+    let names: Vec<_> = timings.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(names, vec!["templates", "cache"]);
+}
 
-    let params = match_path("/hi/:who", "/hi/tomato").unwrap();
-    let mut ctx = RequestContext {
-        path: "/hi/tomato".to_string(),
-        params,
-        is_authenticated: false,
-        start_time: None,
+#[tokio::test]
+async fn test_lifecycle_hooks_run_in_registration_order() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    for name in ["migrate", "connect"] {
+        let log = log.clone();
+        router.on_startup(move || {
+            let log = log.clone();
+            async move { log.lock().unwrap().push(name) }
+        });
+    }
+    let shutdown_log = log.clone();
+    router.on_shutdown(move || {
+        let log = shutdown_log.clone();
+        async move { log.lock().unwrap().push("close") }
+    });
+
+    router.run_startup_hooks().await;
+    assert_eq!(*log.lock().unwrap(), vec!["migrate", "connect"]);
+    router.run_shutdown_hooks().await;
+    assert_eq!(*log.lock().unwrap(), vec!["migrate", "connect", "close"]);
+}
+
+#[tokio::test]
+async fn test_replace_routes_swaps_atomically() {
+    let page = |body: &'static str| -> Handler {
+        Arc::new(move |_req| Box::pin(async move { Response::html(body) }))
     };
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router.add_route("GET", "/", page("v1"), "home");
+    let swapper = router.route_swapper();
+    swapper.replace(router.routes.clone());
+    let before = swapper.current();
+
+    let v2 = vec![Route {
+        method: "GET".into(),
+        path: "/".into(),
+        handler: page("v2"),
+        handler_name: "home".into(),
+        name: None,
+        groups: Vec::new(),
+    }];
+    router.replace_routes(v2);
+    assert_eq!(router.dispatch("GET", "/", "").await.body, "v2");
+    // A snapshot taken before the swap still serves the old handler
+    let (old, _) = before.find("GET", "/").unwrap();
+    assert_eq!(
+        (old.handler)(Request {
+            params: HashMap::new(),
+            body: String::new(),
+            query: HashMap::new(),
+            ..Default::default()
+        })
+        .await
+        .body,
+        "v1"
+    );
+    let (new, _) = swapper
+        .current()
+        .find("GET", "/")
+        .map(|(r, p)| (r.handler.clone(), p))
+        .unwrap();
+    assert_eq!(
+        new(Request {
+            params: HashMap::new(),
+            body: String::new(),
+            query: HashMap::new(),
+            ..Default::default()
+        })
+        .await
+        .body,
+        "v2"
+    );
+}
+
+#[tokio::test]
+async fn test_reload_routes_endpoint_requires_token() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    let swapper = router.route_swapper();
+    router.add_route(
+        "POST",
+        "/_admin/reload",
+        reload_routes_handler(swapper.clone(), "s3cret", Vec::new),
+        "reload",
+    );
+    let token = |value: &str| HashMap::from([("X-Reload-Token".to_string(), value.to_string())]);
+    let denied = router
+        .dispatch_with_headers("POST", "/_admin/reload", token("nope"), Default::default())
+        .await;
+    assert_eq!(denied.status, 403);
+    // The token is not read from the query string
+    let denied = router
+        .dispatch("POST", "/_admin/reload?token=s3cret", "")
+        .await;
+    assert_eq!(denied.status, 403);
+    let ok = router
+        .dispatch_with_headers("POST", "/_admin/reload", token("s3cret"), Default::default())
+        .await;
+    assert_eq!(ok.status, 200);
+
+    // The rebuilt table keeps the reload endpoint
+    let table = swapper.current();
+    assert_eq!(table.routes.len(), 1);
+    assert!(table.find("POST", "/_admin/reload").is_some());
+    assert!(table.find("GET", "/_admin/reload").is_none());
+
+    // Other methods are refused even where the route accepts them
+    router.add_route(
+        "GET",
+        "/_admin/reload-get",
+        reload_routes_handler(swapper.clone(), "s3cret", Vec::new),
+        "reload-get",
+    );
+    let refused = router
+        .dispatch_with_headers("GET", "/_admin/reload-get", token("s3cret"), Default::default())
+        .await;
+    assert_eq!(refused.status, 405);
+}
 
-    // Middleware should override param
-    for mw in &router.routes[0].middlewares {
-        let _ = mw(&mut ctx);
+#[tokio::test]
+async fn test_typed_path_params() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router.add_route(
+        "GET",
+        "/user/:id<i64>",
+        handler(|req: Request| async move {
+            req.param::<i64>("id")
+                .map(|id| Response::html(format!("user {}", id + 1)))
+        }),
+        "user",
+    );
+    router.add_route(
+        "GET",
+        "/page/:n",
+        handler(|req: Request| async move {
+            req.param::<u8>("n").map(|n| Response::html(n.to_string()))
+        }),
+        "page",
+    );
+    assert_eq!(router.dispatch("GET", "/user/41", "").await.body, "user 42");
+    assert_eq!(router.dispatch("GET", "/user/abc", "").await.status, 404);
+    assert_eq!(router.dispatch("GET", "/page/7", "").await.body, "7");
+    let bad = router.dispatch("GET", "/page/999", "").await;
+    assert_eq!(bad.status, 400);
+    assert!(bad.body.contains("'999' is not a valid u8"));
+}
+
+#[tokio::test]
+async fn test_query_string_parsing() {
+    #[derive(serde::Deserialize)]
+    struct Listing {
+        page: u32,
+        sort: Option<String>,
+        #[serde(default)]
+        desc: bool,
     }
-    use futures::executor::block_on;
-    let resp = block_on((router.routes[0].handler)(ctx.params.clone()));
-    assert_eq!(resp.body, "hello overridden");
+
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router.add_route(
+        "GET",
+        "/items",
+        handler(|req: Request| async move {
+            match req.query_as::<Listing>() {
+                Ok(l) => Response::html(format!(
+                    "{}:{}:{}",
+                    l.page,
+                    l.sort.unwrap_or_default(),
+                    l.desc
+                )),
+                Err(e) => Response::html(e.to_string()).with_status(400),
+            }
+        }),
+        "items",
+    );
+    let resp = router
+        .dispatch("GET", "/items?page=2&sort=na%20me", "")
+        .await;
+    assert_eq!(resp.body, "2:na me:false");
+    let resp = router.dispatch("GET", "/items?page=2&desc=true", "").await;
+    assert_eq!(resp.body, "2::true");
+    assert_eq!(
+        router.dispatch("GET", "/items?page=x", "").await.status,
+        400
+    );
+}
+
+#[tokio::test]
+async fn test_middleware_pipeline_in_dispatch() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router.add_middleware(Arc::new(|ctx: &mut RequestContext| {
+        if ctx.path.starts_with("/admin") {
+            return Some(Response::html("blocked").with_status(403));
+        }
+        ctx.params.insert("via".into(), "mw".into());
+        None
+    }));
+    router.add_post_middleware(Arc::new(|ctx: &RequestContext, resp: Response| {
+        assert!(ctx.start_time.is_some());
+        resp.add_header("X-Post", "1")
+    }));
+    let echo = handler(|req: Request| async move {
+        Response::html(req.params.get("via").cloned().unwrap_or_default())
+    });
+    router.add_route("GET", "/admin", echo.clone(), "admin");
+    router.add_route("GET", "/open", echo, "open");
+
+    let blocked = router.dispatch("GET", "/admin", "").await;
+    assert_eq!((blocked.status, blocked.body.as_str()), (403, "blocked"));
+    assert_eq!(blocked.headers["X-Post"], "1");
+    let open = router.dispatch("GET", "/open", "").await;
+    assert_eq!(open.body, "mw");
+    assert_eq!(open.headers["X-Post"], "1");
+}
+
+#[tokio::test]
+async fn test_route_groups_share_prefix_and_middleware() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router.group("/api/v1", |g| {
+        g.get("/users", |_req| async { Response::html("users") });
+        g.group("/admin", |admin| {
+            admin.add_middleware(Arc::new(|ctx: &mut RequestContext| {
+                (!ctx.path.ends_with("/login")).then(|| Response::html("denied").with_status(401))
+            }));
+            admin.add_post_middleware(Arc::new(|_ctx, resp: Response| {
+                resp.add_header("X-Admin", "1")
+            }));
+            admin.get("/stats", |_req| async { Response::html("stats") });
+            admin.post("/login", |_req| async { Response::html("welcome") });
+        });
+    });
+
+    let paths: Vec<_> = router.list_routes().into_iter().map(|(_, p)| p).collect();
+    assert_eq!(
+        paths,
+        [
+            "/api/v1/users",
+            "/api/v1/admin/stats",
+            "/api/v1/admin/login"
+        ]
+    );
+    let users = router.dispatch("GET", "/api/v1/users", "").await;
+    assert_eq!(users.body, "users");
+    assert!(!users.headers.contains_key("X-Admin"));
+
+    let stats = router.dispatch("GET", "/api/v1/admin/stats", "").await;
+    assert_eq!(stats.status, 401);
+    assert_eq!(stats.headers["X-Admin"], "1");
+    assert_eq!(
+        router
+            .dispatch("POST", "/api/v1/admin/login", "")
+            .await
+            .body,
+        "welcome"
+    );
+}
+
+#[tokio::test]
+async fn test_redirect_file_and_stream_responses() {
+    let found = Response::redirect("/login");
+    assert_eq!(found.status, 302);
+    assert_eq!(found.headers["Location"], "/login");
+    assert_eq!(Response::redirect_permanent("/new").status, 301);
+
+    let path = std::env::temp_dir().join("cobalto_response_file.json");
+    std::fs::write(&path, "{\"ok\": true}").unwrap();
+    let file = Response::file(&path);
+    assert_eq!(file.headers["Content-Type"], "application/json");
+    assert!(file.stream.is_some());
+    assert_eq!(file.body_bytes().await.unwrap(), b"{\"ok\": true}");
+    assert_eq!(Response::file("/definitely/missing.txt").status, 404);
+
+    let chunks = futures::stream::iter(["a", "b", "c"].map(actix_web::web::Bytes::from_static));
+    let streamed = Response::stream(chunks);
+    assert_eq!(streamed.body_bytes().await.unwrap(), b"abc");
+}
+
+#[tokio::test]
+async fn test_named_routes_reverse() {
+    use cobalto::template::{TemplateValue, parse_tokens, render_nodes, tokenize_template};
+
+    let page: Handler = Arc::new(|_req| Box::pin(async { Response::html("ok") }));
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router
+        .add_route("GET", "/users/:id<i64>", page.clone(), "user")
+        .name("user-detail");
+    router.group("/files", |g| {
+        g.add_route("GET", "/:name", page, "file")
+            .name("file-detail");
+    });
+
+    assert_eq!(
+        router.url_for("user-detail", &[("id", "42")]).as_deref(),
+        Some("/users/42")
+    );
+    assert_eq!(
+        router
+            .url_for("file-detail", &[("name", "a b.txt")])
+            .as_deref(),
+        Some("/files/a%20b.txt")
+    );
+    assert_eq!(router.url_for("user-detail", &[]), None);
+    assert_eq!(router.url_for("nope", &[("id", "1")]), None);
+
+    let mut user = HashMap::new();
+    user.insert("id".to_string(), TemplateValue::Number(7.0));
+    let mut context = HashMap::new();
+    context.insert("user".to_string(), TemplateValue::Object(user));
+    let nodes = parse_tokens(&tokenize_template(r#"{% url "user-detail" id=user.id %}"#));
+    assert_eq!(render_nodes(&nodes, &context), "");
+
+    // `{% url %}` and `reverse` resolve against the router handling the request
+    router.add_route(
+        "GET",
+        "/link",
+        Arc::new(move |_req| {
+            let (nodes, context) = (nodes.clone(), context.clone());
+            Box::pin(async move { Response::html(render_nodes(&nodes, &context)) })
+        }),
+        "link",
+    );
+    let mut other = Router::new(cobalto::settings::Settings::default());
+    other
+        .add_route(
+            "GET",
+            "/people/:id",
+            Arc::new(|_req| {
+                Box::pin(async {
+                    Response::html(reverse("user-detail", &[("id", "7")]).unwrap_or_default())
+                })
+            }),
+            "person",
+        )
+        .name("user-detail");
+    assert_eq!(router.dispatch("GET", "/link", "").await.body, "/users/7");
+    assert_eq!(other.dispatch("GET", "/people/1", "").await.body, "/people/7");
 }
 
 #[test]
-fn test_status_text_variants() {
-    assert_eq!(cobalto::router::status_text(200), "OK");
-    assert_eq!(cobalto::router::status_text(403), "Forbidden");
-    assert_eq!(cobalto::router::status_text(404), "Not Found");
-    assert_eq!(cobalto::router::status_text(590), "Unknown");
+fn test_constant_time_eq() {
+    assert!(constant_time_eq("s3cret", "s3cret"));
+    assert!(!constant_time_eq("s3cret", "s3creT"));
+    assert!(!constant_time_eq("s3cret", "s3cre"));
+    assert!(constant_time_eq("", ""));
+}
+
+#[tokio::test]
+async fn test_reversed_paths_dispatch_to_decoded_params() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    let echo = |name: &'static str| {
+        handler(move |req: Request| async move { Response::html(req.params[name].clone()) })
+    };
+    router
+        .add_route("GET", "/posts/:slug", echo("slug"), "post")
+        .name("post-roundtrip");
+    router
+        .add_route("GET", "/media/*path", echo("path"), "media")
+        .name("media-roundtrip");
+
+    for (name, key, value) in [
+        ("post-roundtrip", "slug", "a b+c"),
+        ("post-roundtrip", "slug", "café/100%"),
+        ("media-roundtrip", "path", "docs/a b.txt"),
+    ] {
+        let path = router.url_for(name, &[(key, value)]).unwrap();
+        let resp = router.dispatch("GET", &path, "").await;
+        assert_eq!(resp.body, value, "{}", path);
+    }
+    assert_eq!(match_path("/t/:tag", "/t/rust%2Bweb").unwrap()["tag"], "rust+web");
+}
+
+#[tokio::test]
+async fn test_catch_all_and_optional_segments() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router
+        .add_route(
+            "GET",
+            "/files/*path",
+            handler(|req: Request| async move {
+                req.param::<String>("path")
+                    .map(|path| Response::html(format!("file [{}]", path)))
+            }),
+            "files",
+        )
+        .name("file-path");
+    router
+        .add_route(
+            "GET",
+            "/posts/:page<u32>?",
+            handler(|req: Request| async move {
+                Response::html(format!("page {}", req.param::<u32>("page").unwrap_or(1)))
+            }),
+            "posts",
+        )
+        .name("posts");
+
+    assert_eq!(
+        router.dispatch("GET", "/files/a/b/c.txt", "").await.body,
+        "file [a/b/c.txt]"
+    );
+    assert_eq!(router.dispatch("GET", "/files", "").await.body, "file []");
+    assert_eq!(router.dispatch("GET", "/posts", "").await.body, "page 1");
+    assert_eq!(router.dispatch("GET", "/posts/3", "").await.body, "page 3");
+    assert_eq!(router.dispatch("GET", "/posts/x", "").await.status, 404);
+    assert_eq!(router.dispatch("GET", "/posts/3/4", "").await.status, 404);
+
+    assert_eq!(
+        router
+            .url_for("file-path", &[("path", "docs/a b.md")])
+            .as_deref(),
+        Some("/files/docs/a%20b.md")
+    );
+    assert_eq!(router.url_for("posts", &[]).as_deref(), Some("/posts"));
+    assert_eq!(
+        router.url_for("posts", &[("page", "2")]).as_deref(),
+        Some("/posts/2")
+    );
 }
 
 #[test]
-fn test_build_ws_axum_router_with_and_without_reload() {
-    let mut router = Router::new();
-    let wsh: WsHandler = Arc::new(|_, _| Box::pin(async {}));
-    router.add_websocket("/ws/api", wsh.clone());
-    let mut settings = cobalto::settings::Settings {
-        debug: false,
-        host: "x".into(),
-        port: 1,
-        ws_port: 2,
-        template: cobalto::settings::TemplateSettings {
-            dir: ".".into(),
-            debug: false,
-        },
-        other: HashMap::new(),
+#[should_panic(expected = "route GET /users/:pk conflicts with GET /users/:id")]
+fn test_conflicting_routes_panic_at_registration() {
+    let page: Handler = Arc::new(|_req| Box::pin(async { Response::html("ok") }));
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    router.add_route("GET", "/users/:id", page.clone(), "user");
+    router.add_route("POST", "/users/:pk", page.clone(), "update_user");
+    router.add_route("GET", "/users/:pk", page, "user_again");
+}
+
+#[tokio::test]
+async fn test_per_route_body_limits() {
+    let mut settings = cobalto::settings::Settings::default();
+    settings.set("body_limits.avatar", "8").unwrap();
+    let mut router = Router::new(settings);
+    let page: Handler = Arc::new(|req| Box::pin(async move { Response::html(req.body) }));
+    router
+        .add_route("POST", "/avatar", page.clone(), "upload_avatar")
+        .name("avatar");
+    router.add_route("POST", "/notes", page, "notes");
+
+    assert_eq!(
+        router.dispatch("POST", "/avatar", "12345678").await.status,
+        200
+    );
+    let refused = router.dispatch("POST", "/avatar", "123456789").await;
+    assert_eq!(refused.status, 413);
+    assert!(refused.body.contains("larger than 8 bytes"));
+    assert_eq!(
+        router.dispatch("POST", "/notes", "123456789").await.status,
+        200
+    );
+}
+
+#[tokio::test]
+async fn test_request_method_path_and_headers() {
+    let mut router = Router::new(cobalto::settings::Settings::default());
+    let describe = |req: Request| async move {
+        let auth = req.header("Authorization").unwrap_or_default();
+        let headers = req.headers();
+        Response::html(format!(
+            "{} {} {} {}",
+            req.method(),
+            req.path(),
+            auth,
+            headers.get("X-TRACE").unwrap_or("-")
+        ))
     };
-    settings.debug = true;
-    // Not a deep inspection, but it covers branching logic
+    router.group("", |g| {
+        g.get("/items/:id", describe);
+        g.post("/items/:id", describe);
+    });
+
+    let headers = HashMap::from([("authorization".to_string(), "Bearer t0k".to_string())]);
+    let response = router
+        .dispatch_with_headers("POST", "/items/3?x=1", headers, Default::default())
+        .await;
+    assert_eq!(response.body, "POST /items/3 Bearer t0k -");
+    let headers = HashMap::from([("X-Trace".to_string(), "on".to_string())]);
+    let response = router
+        .dispatch_with_headers("GET", "/items/3", headers, Default::default())
+        .await;
+    assert_eq!(response.body, "GET /items/3  on");
+    assert_eq!(Request::default().method(), "");
+
+    // Built by hand, outside any request
+    let req = Request {
+        method: "PUT".to_string(),
+        headers: HashMap::from([("Accept".to_string(), "text/html".to_string())]).into(),
+        ..Default::default()
+    };
+    assert_eq!(req.method(), "PUT");
+    assert_eq!(req.header("accept"), Some("text/html"));
+}
+
+#[tokio::test]
+async fn test_middleware_chain_lists_layers_in_execution_order() {
+    let page = |body: &'static str| -> Handler {
+        Arc::new(move |_req| Box::pin(async move { Response::html(body) }))
+    };
+    let mut router = Router::new(Settings::default());
+    let pass: Middleware = Arc::new(|_| None);
+    let keep: PostMiddleware = Arc::new(|_, resp| resp);
+    router.add_middleware(pass.clone());
+    router.add_post_middleware(keep.clone());
+    router.wrap_handlers(|h| h);
+    router.group("/api", |api| {
+        api.add_middleware(pass.clone());
+        api.add_post_middleware(keep.clone());
+        api.group("/admin", |admin| {
+            admin.wrap_handlers(|h| h);
+            admin.wrap_handlers(|h| h);
+            admin.add_route("GET", "/users/:id", page("user"), "user_detail");
+        });
+        api.add_route("GET", "/ping", page("pong"), "ping");
+    });
+
+    let routes = router.matching_routes("/api/admin/users/7");
+    assert_eq!(routes.len(), 1);
+    assert_eq!(
+        router.middleware_chain(routes[0]),
+        vec![
+            "pre      global middleware 1",
+            "wrap     global wrapper 1",
+            "pre      group /api middleware 1",
+            "wrap     group /api/admin wrapper 2",
+            "wrap     group /api/admin wrapper 1",
+            "handler  user_detail",
+            "post     group /api post-middleware 1",
+            "post     global post-middleware 1",
+        ]
+    );
+    let ping = router.matching_routes("/api/ping");
+    assert_eq!(router.middleware_chain(ping[0]).len(), 6);
+    assert!(router.matching_routes("/nope").is_empty());
+}
+
+#[test]
+fn test_percent_decode_needs_two_hex_digits() {
+    let form = parse_urlencoded("a=%2B&b=%+1&c=%-f&d=%4");
+    assert_eq!(form["a"], "+");
+    assert_eq!(form["b"], "% 1");
+    assert_eq!(form["c"], "%-f");
+    assert_eq!(form["d"], "%4");
+    assert_eq!(percent_decode_path("/a%2Fb/%+1"), "/a/b/%+1");
 }
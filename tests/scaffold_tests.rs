@@ -0,0 +1,150 @@
+use cobalto::scaffold::*;
+
+fn scratch(name: &str) -> std::path::PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("cobalto_scaffold_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_startproject_then_startapp() {
+    let dir = scratch("project");
+    let files = start_project("mysite", &dir).unwrap();
+    let root = dir.join("mysite");
+    for file in [
+        "Cargo.toml",
+        "settings.toml",
+        "src/main.rs",
+        "src/models.rs",
+        "templates/index.html",
+    ] {
+        assert!(files.contains(&root.join(file)), "{file} not created");
+    }
+    let cargo = std::fs::read_to_string(root.join("Cargo.toml")).unwrap();
+    assert!(cargo.contains("name = \"mysite\""));
+    cobalto::settings::Settings::default()
+        .merge_file(root.join("settings.toml"))
+        .unwrap();
+
+    let files = start_app("blog_posts", &root).unwrap();
+    assert_eq!(files.len(), 4);
+    let module = std::fs::read_to_string(root.join("src/blog_posts/mod.rs")).unwrap();
+    assert!(module.contains("App::new(\"blog_posts\", \"/blog_posts\")"));
+    assert!(module.contains("models::BlogPostsItem"));
+    assert!(
+        root.join("src/blog_posts/templates/blog_posts/index.html")
+            .is_file()
+    );
+
+    // Nothing is overwritten
+    assert!(matches!(
+        start_project("mysite", &dir),
+        Err(ScaffoldError::Exists(_))
+    ));
+    assert!(matches!(
+        start_app("blog_posts", &root),
+        Err(ScaffoldError::Exists(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_names_and_project_checks() {
+    assert!(valid_name("blog"));
+    assert!(valid_name("shop2_items"));
+    for name in ["", "Blog", "2fa", "my-site", "mod", "self"] {
+        assert!(!valid_name(name), "{name:?} accepted");
+    }
+    let dir = scratch("names");
+    assert!(matches!(
+        start_project("My Site", &dir),
+        Err(ScaffoldError::InvalidName(_))
+    ));
+    assert!(matches!(
+        start_app("blog", &dir),
+        Err(ScaffoldError::NotAProject(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_blog_example() {
+    let dir = scratch("example");
+    let files = start_example("blog", "myblog", &dir).unwrap();
+    let root = dir.join("myblog");
+    for file in [
+        "src/lib.rs",
+        "src/accounts.rs",
+        "src/blog/notifications.rs",
+        "src/blog/templates/blog/index.html",
+        "templates/login.html",
+        "tests/blog_tests.rs",
+    ] {
+        assert!(files.contains(&root.join(file)), "{file} not created");
+    }
+    let main = std::fs::read_to_string(root.join("src/main.rs")).unwrap();
+    assert!(main.contains("myblog::router(settings, db)"));
+    let tests = std::fs::read_to_string(root.join("tests/blog_tests.rs")).unwrap();
+    assert!(tests.contains("use myblog::blog::models::Post;"));
+    for file in &files {
+        let content = std::fs::read_to_string(file).unwrap();
+        assert!(!content.contains("$project"), "{} not filled in", file.display());
+    }
+    let settings = cobalto::settings::Settings::default()
+        .merge_file(root.join("settings.toml"))
+        .unwrap();
+    assert_eq!(settings.get_str("admin_password"), Some(""));
+
+    assert!(matches!(
+        start_example("shop", "myshop", &dir),
+        Err(ScaffoldError::UnknownExample(_))
+    ));
+    assert!(matches!(
+        start_example("blog", "myblog", &dir),
+        Err(ScaffoldError::Exists(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// `cargo <args>` in the generated project `root`, against this checkout of
+/// cobalto rather than the published crate.
+fn cargo_in_project(root: &std::path::Path, args: &[&str]) {
+    let manifest = root.join("Cargo.toml");
+    let cargo = std::fs::read_to_string(&manifest).unwrap().replace(
+        "cobalto = \"0.1\"",
+        &format!("cobalto = {{ path = {:?} }}", env!("CARGO_MANIFEST_DIR")),
+    );
+    std::fs::write(&manifest, cargo).unwrap();
+    let status = std::process::Command::new(env!("CARGO"))
+        .args(args)
+        .current_dir(root)
+        .env(
+            "CARGO_TARGET_DIR",
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("target/scaffold"),
+        )
+        .status()
+        .unwrap();
+    assert!(status.success(), "cargo {:?} failed in {}", args, root.display());
+}
+
+#[test]
+#[ignore = "builds the generated projects with cargo: run with --ignored"]
+fn test_generated_projects_build_and_pass_their_tests() {
+    let dir = scratch("build");
+    start_project("mysite", &dir).unwrap();
+    let site = dir.join("mysite");
+    start_app("notes", &site).unwrap();
+    let main = std::fs::read_to_string(site.join("src/main.rs")).unwrap();
+    let main = format!("mod notes;\n{}", main).replace(
+        "// router.mount(blog::app());",
+        "router.mount(notes::app());",
+    );
+    std::fs::write(site.join("src/main.rs"), main).unwrap();
+    cargo_in_project(&site, &["build", "--quiet"]);
+
+    start_example("blog", "myblog", &dir).unwrap();
+    cargo_in_project(&dir.join("myblog"), &["test", "--quiet"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
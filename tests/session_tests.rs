@@ -0,0 +1,82 @@
+use cobalto::orm::Db;
+use cobalto::router::{Response, Router, handler};
+use cobalto::session::{DbStore, Sessions};
+use cobalto::settings::Settings;
+
+fn cookie_id(set_cookie: &str) -> String {
+    let pair = set_cookie.split(';').next().unwrap();
+    pair.split_once('=').unwrap().1.to_string()
+}
+
+#[tokio::test]
+async fn test_session_cookie_issued_only_when_modified() {
+    let mut router = Router::new(Settings::default());
+    router.use_sessions(Sessions::memory());
+    router.add_route(
+        "POST",
+        "/login",
+        handler(|req| async move {
+            req.session().unwrap().set("user", "alice");
+            Response::html("ok")
+        }),
+        "login",
+    );
+    router.add_route(
+        "GET",
+        "/",
+        handler(|_req| async { Response::html("home") }),
+        "home",
+    );
+
+    let resp = router.dispatch("POST", "/login", "").await;
+    let cookie = resp.headers.get("Set-Cookie").unwrap();
+    assert!(cookie.starts_with("cobalto_session="));
+    assert!(cookie.contains("HttpOnly"));
+    assert_eq!(cookie_id(cookie).len(), 64);
+
+    let resp = router.dispatch("GET", "/", "").await;
+    assert!(!resp.headers.contains_key("Set-Cookie"));
+}
+
+#[tokio::test]
+async fn test_memory_sessions_round_trip_and_destroy() {
+    let sessions = Sessions::memory();
+    let session = sessions.load(None).await;
+    session.set("cart", "3");
+    let cookie = sessions.commit(&session).await.unwrap();
+    let header = format!("theme=dark; {}", cookie.split(';').next().unwrap());
+
+    let again = sessions.load(Some(&header)).await;
+    assert_eq!(again.get("cart").as_deref(), Some("3"));
+    assert_eq!(again.delete("cart").as_deref(), Some("3"));
+    again.destroy();
+    let cleared = sessions.commit(&again).await.unwrap();
+    assert!(cleared.contains("Max-Age=0"));
+    assert!(sessions.load(Some(&header)).await.id().is_none());
+}
+
+#[tokio::test]
+async fn test_db_store_persists_and_cycles_id() {
+    let db = Db::connect(":memory:").await.unwrap();
+    let store = DbStore::new(db);
+    store.create_table().await.unwrap();
+    let sessions = Sessions::new(store);
+
+    let session = sessions.load(None).await;
+    session.set("user", "42");
+    let first = cookie_id(&sessions.commit(&session).await.unwrap());
+
+    let header = format!("cobalto_session={first}");
+    let loaded = sessions.load(Some(&header)).await;
+    assert_eq!(loaded.get("user").as_deref(), Some("42"));
+
+    loaded.cycle_id();
+    let second = cookie_id(&sessions.commit(&loaded).await.unwrap());
+    assert_ne!(first, second);
+    assert!(sessions.load(Some(&header)).await.get("user").is_none());
+    let header = format!("cobalto_session={second}");
+    assert_eq!(
+        sessions.load(Some(&header)).await.get("user").as_deref(),
+        Some("42")
+    );
+}
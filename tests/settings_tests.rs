@@ -0,0 +1,90 @@
+use cobalto::settings::{Settings, SettingsError};
+
+fn write_config(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_file_then_env_overrides() {
+    let path = write_config(
+        "cobalto_settings_test.toml",
+        r#"
+port = 9000
+debug = true
+secret_key = "abc"
+
+[template]
+dir = "views"
+
+[other]
+page_size = 25
+"#,
+    );
+    let settings = Settings::from_file(&path)
+        .unwrap()
+        .merge_vars([
+            ("COBALTO_PORT".to_string(), "9100".to_string()),
+            ("COBALTO_TEMPLATE__DEBUG".to_string(), "yes".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ])
+        .unwrap();
+
+    assert_eq!(settings.port, 9100);
+    assert!(settings.debug);
+    assert_eq!(settings.template.dir, "views");
+    assert!(settings.template.debug);
+    assert_eq!(settings.get_str("secret_key"), Some("abc"));
+    assert_eq!(settings.get::<u32>("page_size").unwrap(), Some(25));
+    assert_eq!(settings.get_or("missing", 7u8), 7);
+    assert!(!settings.other.contains_key("home"));
+}
+
+#[test]
+fn test_invalid_values_are_reported() {
+    let err = Settings::default()
+        .merge_vars([("COBALTO_PORT".to_string(), "http".to_string())])
+        .unwrap_err();
+    assert!(matches!(&err, SettingsError::Invalid { key, .. } if key == "port"));
+    assert!(err.to_string().contains("port number"));
+
+    let path = write_config("cobalto_settings_bad.toml", "port = [");
+    assert!(matches!(
+        Settings::from_file(&path),
+        Err(SettingsError::Parse { .. })
+    ));
+
+    let mut settings = Settings::default();
+    settings.set("page_size", "many").unwrap();
+    assert!(settings.get::<u32>("page_size").is_err());
+    settings.static_url = "static".into();
+    assert!(settings.validate().is_err());
+}
+
+#[test]
+fn test_server_tuning_settings() {
+    let path = write_config(
+        "cobalto_server_settings_test.toml",
+        "[server]\nworkers = 4\nkeep_alive = 0\nmax_connections = 1000\nhttp2 = true\n",
+    );
+    let settings = Settings::default()
+        .merge_file(&path)
+        .unwrap()
+        .merge_vars([(
+            "COBALTO_SERVER__CLIENT_REQUEST_TIMEOUT".to_string(),
+            "10".to_string(),
+        )])
+        .unwrap();
+    assert_eq!(settings.workers, Some(4));
+    assert_eq!(settings.server.keep_alive, Some(0));
+    assert_eq!(settings.server.client_request_timeout, Some(10));
+    assert_eq!(settings.server.max_connections, Some(1000));
+    assert!(settings.server.http2);
+    assert!(
+        Settings::default()
+            .set("server.keep_alive", "soon")
+            .is_err()
+    );
+    std::fs::remove_file(path).unwrap();
+}
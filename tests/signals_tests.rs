@@ -0,0 +1,120 @@
+use cobalto::orm::{Backend, Db, Field, FieldType, Model, SqlValue, create_table_sql};
+use cobalto::signals::{self, Signal};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, sqlx::FromRow, PartialEq)]
+struct Entry {
+    id: i64,
+    title: String,
+}
+
+impl Model for Entry {
+    fn table_name() -> &'static str {
+        "entry"
+    }
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("id", FieldType::Integer).primary_key(),
+            Field::new("title", FieldType::Text),
+        ]
+    }
+
+    fn values(&self) -> Vec<SqlValue> {
+        vec![self.id.into(), self.title.clone().into()]
+    }
+
+    fn set_primary_key(&mut self, id: i64) {
+        self.id = id;
+    }
+}
+
+#[tokio::test]
+async fn test_save_and_delete_send_signals() {
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute(&create_table_sql::<Entry>(Backend::Sqlite, &[]))
+        .await
+        .unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut ids = Vec::new();
+    for signal in [
+        Signal::PreSave,
+        Signal::PostSave,
+        Signal::PreDelete,
+        Signal::PostDelete,
+    ] {
+        let events = events.clone();
+        ids.push(signals::connect::<Entry, _>(signal, move |entry, event| {
+            events
+                .lock()
+                .unwrap()
+                .push((event.signal, event.created, entry.id));
+        }));
+    }
+
+    let mut entry = Entry {
+        id: 0,
+        title: "draft".into(),
+    };
+    entry.save(&db).await.unwrap();
+    entry.title = "final".into();
+    entry.save(&db).await.unwrap();
+    assert!(entry.delete(&db).await.unwrap());
+    assert!(!entry.delete(&db).await.unwrap());
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            (Signal::PreSave, false, 0),
+            (Signal::PostSave, true, 1),
+            (Signal::PreSave, false, 1),
+            (Signal::PostSave, false, 1),
+            (Signal::PreDelete, false, 1),
+            (Signal::PostDelete, false, 1),
+            (Signal::PreDelete, false, 1),
+        ]
+    );
+
+    for id in ids {
+        assert!(signals::disconnect(id));
+    }
+}
+
+#[test]
+fn test_disconnect_stops_receiver() {
+    #[derive(Debug, sqlx::FromRow)]
+    struct Other {
+        id: i64,
+    }
+
+    impl Model for Other {
+        fn table_name() -> &'static str {
+            "other"
+        }
+
+        fn fields() -> Vec<Field> {
+            vec![Field::new("id", FieldType::Integer).primary_key()]
+        }
+
+        fn values(&self) -> Vec<SqlValue> {
+            vec![self.id.into()]
+        }
+    }
+
+    let calls = Arc::new(Mutex::new(0));
+    let counter = calls.clone();
+    let id = signals::connect::<Other, _>(Signal::PostSave, move |_, _| {
+        *counter.lock().unwrap() += 1;
+    });
+
+    signals::send(Signal::PostSave, &Other { id: 1 }, true);
+    // Other signals and other models don't reach the receiver
+    signals::send(Signal::PreSave, &Other { id: 1 }, false);
+    assert_eq!(*calls.lock().unwrap(), 1);
+
+    assert!(signals::disconnect(id));
+    assert!(!signals::disconnect(id));
+    signals::send(Signal::PostSave, &Other { id: 1 }, true);
+    assert_eq!(*calls.lock().unwrap(), 1);
+}
@@ -0,0 +1,45 @@
+use cobalto::pubsub::PubSub;
+use cobalto::router::Response;
+use cobalto::sse::*;
+use futures::StreamExt;
+use std::time::Duration;
+
+#[test]
+fn test_event_wire_format() {
+    let event = Event::data("line one\nline two")
+        .event("update")
+        .id("7")
+        .retry(Duration::from_secs(3));
+    assert_eq!(
+        event.to_string(),
+        "event: update\nid: 7\nretry: 3000\ndata: line one\ndata: line two\n\n"
+    );
+    assert_eq!(
+        Event::json(&serde_json::json!({"n": 1})).to_string(),
+        "data: {\"n\":1}\n\n"
+    );
+}
+
+#[tokio::test]
+async fn test_sse_response_streams_events_and_pings() {
+    let events = futures::stream::iter(["a", "b"]).then(|data| async move {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        Event::data(data)
+    });
+    let response = Response::sse_with_keep_alive(events, Duration::from_millis(10));
+    assert_eq!(response.headers["Content-Type"], "text/event-stream");
+    assert_eq!(response.headers["Cache-Control"], "no-cache");
+
+    let body = String::from_utf8(response.body_bytes().await.unwrap()).unwrap();
+    assert!(body.starts_with(": ping\n\n"), "{body}");
+    assert!(body.contains("data: a\n\n: ping\n\n"), "{body}");
+    assert!(body.ends_with("data: b\n\n"), "{body}");
+}
+
+#[tokio::test]
+async fn test_subscription_events() {
+    let hub = PubSub::new();
+    let mut events = Box::pin(subscription_events(hub.subscribe("jobs"), "job"));
+    hub.publish("jobs", "done");
+    assert_eq!(events.next().await, Some(Event::data("done").event("job")));
+}
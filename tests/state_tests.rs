@@ -0,0 +1,47 @@
+use cobalto::orm::Db;
+use cobalto::router::{Response, Router, handler};
+use cobalto::settings::Settings;
+use cobalto::state::AppState;
+
+#[derive(Clone)]
+struct SiteName(String);
+
+#[tokio::test]
+async fn test_handlers_read_managed_db() {
+    let db = Db::connect(":memory:").await.unwrap();
+    db.execute("CREATE TABLE item (name TEXT)").await.unwrap();
+    db.execute("INSERT INTO item (name) VALUES ('a'), ('b')")
+        .await
+        .unwrap();
+
+    let mut router = Router::new(Settings::default());
+    router.manage(db);
+    router.manage(SiteName("demo".into()));
+    router.add_route(
+        "GET",
+        "/count",
+        handler(|req| async move {
+            let db = req.state::<Db>().unwrap();
+            let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM item")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+            let site = req.state::<SiteName>().unwrap();
+            Response::html(format!("{} {}", site.0, count))
+        }),
+        "count",
+    );
+    assert_eq!(router.dispatch("GET", "/count", "").await.body, "demo 2");
+}
+
+#[test]
+fn test_app_state_lookup_by_type() {
+    let mut state = AppState::new();
+    assert!(state.is_empty());
+    state.insert(SiteName("one".into()));
+    state.insert(SiteName("two".into()));
+    state.insert(7u32);
+    assert_eq!(state.get::<SiteName>().unwrap().0, "two");
+    assert_eq!(*state.get_arc::<u32>().unwrap(), 7);
+    assert!(state.get::<i64>().is_none());
+}
@@ -0,0 +1,65 @@
+use cobalto::staticfiles::StaticFiles;
+use cobalto::template::{parse_tokens, render_nodes, tokenize_template};
+use std::collections::HashMap;
+
+fn fixture_dir() -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join("cobalto_static_test");
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    let css = dir.join("css/app.css");
+    // Rewriting would change the mtime, and so the ETag, under a running test
+    if std::fs::read(&css).ok().as_deref() != Some(&b"body { color: red; }"[..]) {
+        std::fs::write(&css, "body { color: red; }").unwrap();
+    }
+    dir
+}
+
+#[tokio::test]
+async fn test_serves_file_with_content_type_and_etag() {
+    let files = StaticFiles::new(fixture_dir(), "/static/");
+    let resp = files.serve("css/app.css", None, None).await;
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.headers["Content-Type"], "text/css; charset=utf-8");
+    let etag = resp.headers["ETag"].clone();
+    assert_eq!(resp.body_bytes().await.unwrap(), b"body { color: red; }");
+
+    let cached = files.serve("css/app.css", Some(&etag), None).await;
+    assert_eq!(cached.status, 304);
+    assert!(cached.binary.is_none());
+    // A compressed response's weakened tag still matches
+    let weak = format!("W/{}", etag);
+    assert_eq!(files.serve("css/app.css", Some(&weak), None).await.status, 304);
+
+    assert_eq!(files.serve("../etc/passwd", None, None).await.status, 404);
+    assert_eq!(files.serve("css", None, None).await.status, 404);
+}
+
+#[tokio::test]
+async fn test_range_requests() {
+    let files = StaticFiles::new(fixture_dir(), "/static");
+    let resp = files.serve("css/app.css", None, Some("bytes=0-3")).await;
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.headers["Content-Range"], "bytes 0-3/20");
+    assert_eq!(resp.body_bytes().await.unwrap(), b"body");
+
+    let tail = files.serve("css/app.css", None, Some("bytes=-2")).await;
+    assert_eq!(tail.body_bytes().await.unwrap(), b" }");
+
+    let bad = files.serve("css/app.css", None, Some("bytes=50-")).await;
+    assert_eq!(bad.status, 416);
+}
+
+#[test]
+fn test_static_tag_prefixes_url() {
+    let nodes = parse_tokens(&tokenize_template(r#"{% static "css/app.css" %}"#));
+    assert_eq!(render_nodes(&nodes, &HashMap::new()), "/static/css/app.css");
+
+    let nodes = parse_tokens(&tokenize_template(r#"{% static path %}"#));
+    let context = HashMap::from([(
+        "path".to_string(),
+        cobalto::template::TemplateValue::String("a.css\"><script>".to_string()),
+    )]);
+    assert_eq!(
+        render_nodes(&nodes, &context),
+        "/static/a.css&quot;&gt;&lt;script&gt;"
+    );
+}
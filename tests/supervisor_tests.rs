@@ -0,0 +1,59 @@
+use cobalto::supervisor::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+#[test]
+fn test_parse_roles() {
+    let all = vec![Role::Web, Role::Worker, Role::Scheduler];
+    assert_eq!(parse_roles(["start", "--all"]).unwrap(), all);
+    assert_eq!(parse_roles(["start"]).unwrap(), all);
+    assert_eq!(
+        parse_roles(["start", "--worker", "--worker"]).unwrap(),
+        vec![Role::Worker]
+    );
+    assert!(parse_roles(["start", "--bogus"]).is_err());
+}
+
+#[tokio::test]
+async fn test_supervisor_runs_selected_roles_only() {
+    let ran = Arc::new(AtomicBool::new(false));
+    let mut sup = Supervisor::new();
+    let flag = ran.clone();
+    sup.add_role(Role::Worker, move |_| async move {
+        flag.store(true, Ordering::SeqCst);
+        Ok(())
+    });
+    sup.add_role(Role::Web, |_| async {
+        Err(std::io::Error::other("web should not start"))
+    });
+    sup.run(&[Role::Worker]).await.unwrap();
+    assert!(ran.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_exiting_role_stops_the_others_gracefully() {
+    let drained = Arc::new(AtomicBool::new(false));
+    let mut sup = Supervisor::new();
+    sup.add_role(Role::Scheduler, |_| async { Ok(()) });
+    let flag = drained.clone();
+    sup.add_role(Role::Worker, move |shutdown| async move {
+        shutdown.requested().await;
+        // Finishing in-flight work is not cut short
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        flag.store(true, Ordering::SeqCst);
+        Ok(())
+    });
+    sup.run(&[Role::Worker, Role::Scheduler]).await.unwrap();
+    assert!(drained.load(Ordering::SeqCst));
+
+    // Roles that ignore the request are aborted after the timeout
+    let mut sup = Supervisor::new();
+    sup.shutdown_timeout = Duration::from_millis(20);
+    sup.add_role(Role::Scheduler, |_| async { Ok(()) });
+    sup.add_role(Role::Worker, |_| std::future::pending());
+    tokio::time::timeout(Duration::from_secs(5), sup.run(&[Role::Worker, Role::Scheduler]))
+        .await
+        .expect("stuck role aborted")
+        .unwrap();
+}
@@ -0,0 +1,111 @@
+use chrono::{TimeZone, Utc};
+use cobalto::tasks::{CronExpr, Scheduler, TaskQueue};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[test]
+fn test_cron_next_run() {
+    let at = |y, mo, d, h, mi| Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap();
+    let next = |expr: &str, after| expr.parse::<CronExpr>().unwrap().next_after(after);
+
+    assert_eq!(
+        next("*/15 * * * *", at(2026, 3, 1, 10, 7)),
+        Some(at(2026, 3, 1, 10, 15))
+    );
+    assert_eq!(
+        next("30 2 * * *", at(2026, 3, 1, 2, 30)),
+        Some(at(2026, 3, 2, 2, 30))
+    );
+    // 2026-03-01 is a Sunday; weekdays only
+    assert_eq!(
+        next("0 9 * * 1-5", at(2026, 2, 27, 9, 0)),
+        Some(at(2026, 3, 2, 9, 0))
+    );
+    assert_eq!(
+        next("@monthly", at(2026, 12, 15, 0, 0)),
+        Some(at(2027, 1, 1, 0, 0))
+    );
+    assert_eq!(next("0 0 31 2 *", at(2026, 1, 1, 0, 0)), None);
+
+    assert!("* * * *".parse::<CronExpr>().is_err());
+    assert!("60 * * * *".parse::<CronExpr>().is_err());
+    assert!("*/0 * * * *".parse::<CronExpr>().is_err());
+}
+
+#[tokio::test]
+async fn test_jobs_do_not_overlap() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut scheduler = Scheduler::new();
+    let counter = runs.clone();
+    scheduler.every("slow", Duration::from_secs(60), move || {
+        let counter = counter.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    assert!(scheduler.cron("bad", "not cron", || async {}).is_err());
+    assert_eq!(scheduler.jobs(), vec!["slow"]);
+
+    let first = scheduler.trigger("slow").unwrap();
+    assert!(scheduler.trigger("slow").is_none());
+    first.await.unwrap();
+    scheduler.trigger("slow").unwrap().await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+    assert!(scheduler.trigger("missing").is_none());
+}
+
+#[tokio::test]
+async fn test_task_queue_runs_enqueued_tasks() {
+    let runs = Arc::new(AtomicUsize::new(0));
+    let queue = TaskQueue::new(4);
+    for _ in 0..3 {
+        let counter = runs.clone();
+        queue
+            .enqueue("count", async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .await
+            .unwrap();
+    }
+    queue.enqueue("boom", async { panic!("task failed") }).await.unwrap();
+    assert_eq!(queue.pending(), 4);
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+    // A panicking task doesn't stop the others
+    assert_eq!(queue.run_pending().await, 4);
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    assert_eq!(queue.pending(), 0);
+}
+
+#[tokio::test]
+async fn test_worker_bounds_concurrency_and_drains_on_shutdown() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let queue = TaskQueue::new(16).concurrency(2);
+    for _ in 0..6 {
+        let (running, peak) = (running.clone(), peak.clone());
+        queue
+            .enqueue("slow", async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+            .await
+            .unwrap();
+    }
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let worker = tokio::spawn(queue.clone().run_until(async {
+        let _ = stopped.await;
+    }));
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    // Busy workers leave the rest queued
+    assert!(queue.pending() >= 3);
+    stop.send(()).unwrap();
+    worker.await.unwrap().unwrap();
+    // Tasks already picked up were finished before returning
+    assert_eq!(running.load(Ordering::SeqCst), 0);
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
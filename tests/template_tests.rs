@@ -96,6 +96,87 @@ fn test_render_for_loop() {
     assert_eq!(rendered, "Apple,Banana,");
 }
 
+#[test]
+fn test_for_loop_forloop_variables() {
+    let src = "{% for item in items %}{{ forloop.counter }}/{{ forloop.length }}:{{ item }}\
+{% if forloop.first %}(first){% endif %}{% if forloop.last %}(last){% else %}, {% endif %}{% endfor %}";
+    let mut context = HashMap::new();
+    context.insert(
+        "items".to_string(),
+        TemplateValue::List(vec![
+            TemplateValue::String("a".to_string()),
+            TemplateValue::String("b".to_string()),
+            TemplateValue::String("c".to_string()),
+        ]),
+    );
+    let rendered = render_nodes(&parse_tokens(&tokenize_template(src)), &context);
+    assert_eq!(rendered, "1/3:a(first), 2/3:b, 3/3:c(last)");
+}
+
+#[test]
+fn test_if_expressions_and_elif() {
+    let mut user = HashMap::new();
+    user.insert("age".to_string(), TemplateValue::Number(20.0));
+    user.insert(
+        "name".to_string(),
+        TemplateValue::String("admin".to_string()),
+    );
+    let mut context = HashMap::new();
+    context.insert("user".to_string(), TemplateValue::Object(user));
+    context.insert("banned".to_string(), TemplateValue::Bool(false));
+    context.insert(
+        "roles".to_string(),
+        TemplateValue::List(vec![TemplateValue::String("editor".to_string())]),
+    );
+
+    assert!(evaluate_condition(
+        "user.age >= 18 and not banned",
+        &context
+    ));
+    assert!(evaluate_condition(r#"user.name == "admin""#, &context));
+    assert!(evaluate_condition(
+        "'editor' in roles and 'owner' not in roles",
+        &context
+    ));
+    assert!(evaluate_condition(
+        "(banned or user.age < 10) == false",
+        &context
+    ));
+    assert!(!evaluate_condition("missing or user.age != 20", &context));
+    assert!(!evaluate_condition("user.age >=", &context));
+
+    let src = "{% if user.age < 13 %}child{% elif user.age < 18 %}teen{% else %}adult{% endif %}";
+    let nodes = parse_tokens(&tokenize_template(src));
+    assert_eq!(render_nodes(&nodes, &context), "adult");
+    if let Some(TemplateValue::Object(user)) = context.get_mut("user") {
+        user.insert("age".to_string(), TemplateValue::Number(15.0));
+    }
+    assert_eq!(render_nodes(&nodes, &context), "teen");
+}
+
+#[test]
+fn test_variables_are_autoescaped() {
+    let mut context = HashMap::new();
+    context.insert(
+        "bio".to_string(),
+        TemplateValue::String(r#"<script>alert("x")</script> & 'co'"#.to_string()),
+    );
+    let render = |src: &str| render_nodes(&parse_tokens(&tokenize_template(src)), &context);
+
+    assert_eq!(
+        render("{{ bio }}"),
+        "&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; &#x27;co&#x27;"
+    );
+    assert_eq!(
+        render("{{ bio|safe }}"),
+        r#"<script>alert("x")</script> & 'co'"#
+    );
+    assert_eq!(
+        render("{% autoescape off %}{{ bio }}{% endautoescape %}|{{ bio|safe|escape }}"),
+        r#"<script>alert("x")</script> & 'co'|&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; &#x27;co&#x27;"#
+    );
+}
+
 #[test]
 fn test_tailwind_tag_inserts_cdn() {
     let nodes = vec![
@@ -162,3 +243,533 @@ fn test_template_logging_coverage() {
     let _ = render_template("hopefully_does_not_exist_zzz999.html", &ctx);
     set_display_logs(false); // for cleanup
 }
+
+#[test]
+fn test_qrcode_tag_renders_svg() {
+    let nodes = parse_tokens(&tokenize_template("{% qrcode link size=120 %}"));
+    assert!(matches!(&nodes[0], Node::Custom { name, .. } if name == "qrcode"));
+    let mut context = HashMap::new();
+    context.insert(
+        "link".to_string(),
+        TemplateValue::String("https://example.com".to_string()),
+    );
+    let html = render_nodes(&nodes, &context);
+    assert!(html.contains("<svg"));
+}
+
+#[test]
+fn test_register_custom_tag() {
+    register_tag("shout", |args, ctx| {
+        TagArgs::resolve(&args.positional[0], ctx).to_uppercase()
+    });
+    let nodes = parse_tokens(&tokenize_template(r#"{% shout "hey" %}!"#));
+    assert_eq!(render_nodes(&nodes, &HashMap::new()), "HEY!");
+}
+
+#[tokio::test]
+async fn test_request_params_injected_into_context() {
+    use cobalto::router::{RequestScope, with_request_scope};
+    use std::fs;
+
+    fs::create_dir_all("templates").unwrap();
+    fs::write(
+        "templates/test_request_ctx.html",
+        "{{ request.params.id }}/{{ request.query.tab }}",
+    )
+    .unwrap();
+
+    let scope = RequestScope {
+        params: HashMap::from([("id".to_string(), "7".to_string())]),
+        query: HashMap::from([("tab".to_string(), "info".to_string())]),
+        ..Default::default()
+    };
+    let resp = with_request_scope(scope, async {
+        render_template("test_request_ctx.html", &HashMap::new())
+    })
+    .await;
+    assert_eq!(resp.body, "7/info");
+
+    fs::remove_file("templates/test_request_ctx.html").unwrap();
+}
+
+#[test]
+fn test_render_cached_hits_and_invalidates() {
+    use std::fs;
+    use std::time::Duration;
+
+    fs::create_dir_all("templates").unwrap();
+    fs::write("templates/test_cached.html", "v1 {{ name }}").unwrap();
+    let mut ctx = HashMap::new();
+    ctx.insert("name".to_string(), TemplateValue::String("a".into()));
+
+    let ttl = Duration::from_secs(60);
+    assert_eq!(
+        render_cached_tagged("test_cached.html", &ctx, ttl, &["model:t"]).body,
+        "v1 a"
+    );
+    fs::write("templates/test_cached.html", "v2 {{ name }}").unwrap();
+    // Same context: served from cache
+    assert_eq!(render_cached("test_cached.html", &ctx, ttl).body, "v1 a");
+
+    cobalto::cache::default_cache().invalidate_tag("model:t");
+    assert_eq!(render_cached("test_cached.html", &ctx, ttl).body, "v2 a");
+
+    fs::remove_file("templates/test_cached.html").unwrap();
+}
+
+#[test]
+fn test_render_cached_runs_context_processors_once() {
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    register_context_processor(|context| {
+        if context.contains_key("count_processor_calls") {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+
+    fs::create_dir_all("templates").unwrap();
+    fs::write("templates/test_cached_once.html", "once").unwrap();
+    let mut ctx = HashMap::new();
+    ctx.insert("count_processor_calls".to_string(), TemplateValue::Bool(true));
+    let ttl = Duration::from_secs(60);
+    assert_eq!(render_cached("test_cached_once.html", &ctx, ttl).body, "once");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    // A hit still resolves the context for its key, and nothing more
+    assert_eq!(render_cached("test_cached_once.html", &ctx, ttl).body, "once");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    fs::remove_file("templates/test_cached_once.html").unwrap();
+}
+
+#[test]
+fn test_model_changes_invalidate_cached_pages() {
+    use cobalto::orm::{Field, FieldType, Model, SqlValue};
+    use cobalto::signals::{self, Signal};
+    use std::fs;
+    use std::time::Duration;
+
+    #[derive(Debug, sqlx::FromRow)]
+    struct Headline {
+        id: i64,
+    }
+
+    impl Model for Headline {
+        fn table_name() -> &'static str {
+            "headline"
+        }
+
+        fn fields() -> Vec<Field> {
+            vec![Field::new("id", FieldType::Integer).primary_key()]
+        }
+
+        fn values(&self) -> Vec<SqlValue> {
+            vec![self.id.into()]
+        }
+    }
+
+    invalidate_cached_pages::<Headline>();
+    invalidate_cached_pages::<Headline>();
+    assert_eq!(model_tag::<Headline>(), "model:headline");
+
+    fs::create_dir_all("templates").unwrap();
+    let ctx = HashMap::new();
+    let ttl = Duration::from_secs(60);
+    let render = || render_cached_tagged("test_headlines.html", &ctx, ttl, &["model:headline"]);
+    for signal in [Signal::PostSave, Signal::PostDelete] {
+        fs::write("templates/test_headlines.html", "old").unwrap();
+        assert_eq!(render().body, "old");
+        fs::write("templates/test_headlines.html", "new").unwrap();
+        assert_eq!(render().body, "old");
+        signals::send(signal, &Headline { id: 1 }, false);
+        assert_eq!(render().body, "new");
+        cobalto::cache::default_cache().invalidate_tag("model:headline");
+    }
+
+    fs::remove_file("templates/test_headlines.html").unwrap();
+}
+
+#[test]
+fn test_custom_delimiters() {
+    let delimiters = Delimiters::new(("[[", "]]"), ("[%", "%]"));
+    let tokens = tokenize_with("{{ keep }} [[ name ]][% if ok %]!,[% endif %]", &delimiters);
+    let nodes = parse_tokens(&tokens);
+    let mut context = HashMap::new();
+    context.insert("name".to_string(), TemplateValue::String("x".into()));
+    context.insert("ok".to_string(), TemplateValue::Bool(true));
+    assert_eq!(render_nodes(&nodes, &context), "{{ keep }} x!,");
+}
+
+#[test]
+fn test_engine_instance_renders_with_its_delimiters() {
+    use std::fs;
+
+    fs::create_dir_all("templates").unwrap();
+    fs::write(
+        "templates/test_helm.yaml",
+        "image: {{ .Values.image }}\nname: [[ app ]]",
+    )
+    .unwrap();
+    let engine = TemplateEngine::new("templates")
+        .with_delimiters(Delimiters::new(("[[", "]]"), ("[%", "%]")));
+    let mut context = HashMap::new();
+    context.insert("app".to_string(), TemplateValue::String("web".into()));
+    let resp = engine.render("test_helm.yaml", &context);
+    assert_eq!(resp.body, "image: {{ .Values.image }}\nname: web");
+    fs::remove_file("templates/test_helm.yaml").unwrap();
+}
+
+#[test]
+fn test_template_value_from_model() {
+    #[derive(serde::Serialize)]
+    struct Author {
+        name: String,
+        tags: Vec<String>,
+        bio: Option<String>,
+    }
+    let value = TemplateValue::from_serialize(&Author {
+        name: "Ada".into(),
+        tags: vec!["math".into()],
+        bio: None,
+    });
+    let TemplateValue::Object(map) = value else {
+        panic!("expected an object");
+    };
+    assert_eq!(map["name"].as_string(), "Ada");
+    assert!(matches!(&map["tags"], TemplateValue::List(items) if items.len() == 1));
+    assert_eq!(map["bio"].as_string(), "");
+}
+
+#[test]
+fn test_dependency_graph_tracks_extends() {
+    use std::fs;
+
+    let dir = "target/test_templates_graph";
+    fs::create_dir_all(format!("{}/blog", dir)).unwrap();
+    fs::write(
+        format!("{}/base.html", dir),
+        "{% block content %}{% endblock %}",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}/layout.html", dir),
+        r#"{% extends "base.html" %}{% block content %}L{% endblock %}"#,
+    )
+    .unwrap();
+    fs::write(
+        format!("{}/blog/post.html", dir),
+        r#"{% extends "layout.html" %}{% block content %}P{% endblock %}"#,
+    )
+    .unwrap();
+
+    let graph = TemplateEngine::new(dir).dependency_graph();
+    assert_eq!(graph.dependencies("blog/post.html"), vec!["layout.html"]);
+    assert!(graph.dependencies("base.html").is_empty());
+    assert_eq!(
+        graph.dependents("base.html"),
+        vec!["blog/post.html", "layout.html"]
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_parsed_templates_reload_when_file_changes() {
+    use std::fs;
+
+    fs::create_dir_all("templates").unwrap();
+    fs::write("templates/test_compiled.html", "one {{ n }}").unwrap();
+    let mut ctx = HashMap::new();
+    ctx.insert("n".to_string(), TemplateValue::Number(1.0));
+
+    assert_eq!(render_template("test_compiled.html", &ctx).body, "one 1");
+    assert_eq!(render_template("test_compiled.html", &ctx).body, "one 1");
+    fs::write("templates/test_compiled.html", "second {{ n }}").unwrap();
+    assert_eq!(render_template("test_compiled.html", &ctx).body, "second 1");
+
+    fs::remove_file("templates/test_compiled.html").unwrap();
+    assert_eq!(render_template("test_compiled.html", &ctx).status, 404);
+}
+
+#[test]
+fn test_cache_tag_renders_fragment_once_per_key() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static RENDERS: AtomicUsize = AtomicUsize::new(0);
+    register_tag("expensive_sidebar", |_, _| {
+        (RENDERS.fetch_add(1, Ordering::SeqCst) + 1).to_string()
+    });
+    let nodes = parse_tokens(&tokenize_template(
+        r#"{% cache 300 "test_sidebar" user.id %}{{ user.id }}#{% expensive_sidebar %}{% endcache %}"#,
+    ));
+    let context = |id: &str| {
+        let user = HashMap::from([("id".to_string(), TemplateValue::String(id.into()))]);
+        HashMap::from([("user".to_string(), TemplateValue::Object(user))])
+    };
+
+    assert_eq!(render_nodes(&nodes, &context("1")), "1#1");
+    assert_eq!(render_nodes(&nodes, &context("1")), "1#1");
+    assert_eq!(render_nodes(&nodes, &context("2")), "2#2");
+    assert!(
+        cobalto::cache::default_cache()
+            .get(&fragment_cache_key("test_sidebar", &["1"]))
+            .is_some()
+    );
+
+    assert_eq!(
+        cobalto::cache::default_cache().invalidate_tag("fragment:test_sidebar"),
+        2
+    );
+    assert_eq!(render_nodes(&nodes, &context("1")), "1#3");
+}
+
+#[tokio::test]
+async fn test_stream_template_sends_chunks_as_it_renders() {
+    use futures::StreamExt;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("cobalto_stream_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("base.html"),
+        "<ul>{% block rows %}{% endblock %}</ul>",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("rows.html"),
+        r#"{% extends "base.html" %}{% block rows %}{% for row in rows %}<li>{{ row }}</li>{% endfor %}{% endblock %}"#,
+    )
+    .unwrap();
+    let rows: Vec<TemplateValue> = (0..5000)
+        .map(|i| TemplateValue::String(format!("row <{}>", i)))
+        .collect();
+    let context = HashMap::from([("rows".to_string(), TemplateValue::List(rows))]);
+    let engine = TemplateEngine::new(dir.to_str().unwrap());
+
+    let expected = engine.render("rows.html", &context).body;
+    let response = engine.render_stream("rows.html", &context);
+    assert_eq!(response.headers["Content-Type"], "text/html; charset=utf-8");
+    let mut chunks = response.stream.as_ref().unwrap().take().unwrap();
+    let mut streamed = Vec::new();
+    let mut count = 0;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.unwrap();
+        assert!(chunk.len() < STREAM_CHUNK_BYTES + 64);
+        streamed.extend_from_slice(&chunk);
+        count += 1;
+    }
+    assert!(count > 10);
+    assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+
+    assert_eq!(engine.render_stream("missing.html", &context).status, 404);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_with_and_set_bind_local_values() {
+    let items = TemplateValue::List(vec![
+        TemplateValue::String("a".into()),
+        TemplateValue::String("b".into()),
+        TemplateValue::String("c".into()),
+    ]);
+    let context = HashMap::from([
+        ("items".to_string(), items),
+        ("name".to_string(), TemplateValue::String("ada".into())),
+    ]);
+    let render = |src: &str| render_nodes(&parse_tokens(&tokenize_template(src)), &context);
+
+    assert_eq!(
+        render(
+            "{% with total=items|length who=name|upper %}{{ who }}: {{ total }}{% endwith %}[{{ total }}]"
+        ),
+        "ADA: 3[]"
+    );
+    assert_eq!(
+        render(
+            r#"{% if items|length > 2 %}{% set joined = items|join:", " %}{{ joined }}/{{ joined|length }}{% endif %}"#
+        ),
+        "a, b, c/7"
+    );
+    // `set` lasts until the end of the enclosing body
+    assert_eq!(
+        render(
+            "{% for i in items %}{% set last = i %}{% endfor %}{{ last }}|{{ missing|default:\"none\" }}"
+        ),
+        "|none"
+    );
+    assert_eq!(render("{{ items|first }}{{ items|last|upper }}"), "aC");
+}
+
+#[test]
+fn test_comments_and_verbatim() {
+    let context = HashMap::from([("name".to_string(), TemplateValue::String("Ada".into()))]);
+    let render = |src: &str| render_nodes(&parse_tokens(&tokenize_template(src)), &context);
+
+    assert_eq!(render("a{# {{ name }} is hidden #}b"), "ab");
+    assert_eq!(
+        render("a{% comment \"todo\" %}{{ name }}{% if x %}{% endcomment %}b {{ name }}"),
+        "ab Ada"
+    );
+    assert_eq!(
+        render(
+            "<p v-text=\"{% verbatim %}{{ msg }}{% if %}{# x #}{% endverbatim %}\">{{ name }}</p>"
+        ),
+        "<p v-text=\"{{ msg }}{% if %}{# x #}\">Ada</p>"
+    );
+    // A named verbatim can contain a plain endverbatim
+    assert_eq!(
+        render("{% verbatim raw %}{% verbatim %}{% endverbatim %}{% endverbatim raw %}!"),
+        "{% verbatim %}{% endverbatim %}!"
+    );
+}
+
+#[test]
+fn test_strict_engine_reports_undefined_variables_and_unknown_tags() {
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("cobalto_strict_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("base.html"),
+        "<h1>{% block title %}{% endblock %}</h1>\n{% bogus_tag 1 %}",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("profile.html"),
+        "{% extends \"base.html\" %}\n{% block title %}\n{{ user.name }} {{ user.nmae }}{{ nick|default:\"-\" }}\n{% endblock %}",
+    )
+    .unwrap();
+    let user = HashMap::from([("name".to_string(), TemplateValue::String("Ada".into()))]);
+    let context = HashMap::from([("user".to_string(), TemplateValue::Object(user))]);
+    let dir = dir.to_str().unwrap();
+
+    let html = TemplateEngine::new(dir)
+        .strict(true)
+        .render("profile.html", &context)
+        .body;
+    assert!(html.contains("Ada"));
+    assert!(
+        html.contains("[undefined variable 'user.nmae' at profile.html:3]"),
+        "{html}"
+    );
+    assert!(html.contains("unknown tag"));
+    assert!(html.contains("base.html:2"));
+    assert!(!html.contains("nick"));
+
+    // Lenient engines keep rendering them as nothing
+    let html = TemplateEngine::new(dir)
+        .strict(false)
+        .render("profile.html", &context)
+        .body;
+    assert_eq!(html, "<h1>\nAda -\n</h1>\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_engine_from_settings_searches_dirs_and_is_managed() {
+    use std::fs;
+    let root = std::env::temp_dir().join(format!("cobalto_engine_{}", std::process::id()));
+    let own = root.join("templates");
+    let shared = root.join("shared");
+    fs::create_dir_all(&own).unwrap();
+    fs::create_dir_all(&shared).unwrap();
+    fs::write(own.join("page.html"), "own {{ name }}").unwrap();
+    fs::write(shared.join("page.html"), "shared").unwrap();
+    fs::write(shared.join("footer.html"), "footer").unwrap();
+
+    let mut settings = cobalto::settings::Settings::default();
+    settings.set("template.dir", own.to_str().unwrap()).unwrap();
+    settings
+        .set("template.dirs", &format!("[\"{}\"]", shared.display()))
+        .unwrap();
+    let engine = TemplateEngine::from_settings(&settings);
+    assert_eq!(engine.search_paths, vec![shared.display().to_string()]);
+    let context = HashMap::new();
+    assert_eq!(engine.render("footer.html", &context).body, "footer");
+
+    let mut router = cobalto::router::Router::new(settings);
+    router.group("", |g| {
+        g.get("/", |req| async move {
+            let context =
+                HashMap::from([("name".to_string(), TemplateValue::String("Ada".into()))]);
+            req.templates().render("page.html", &context)
+        });
+    });
+    assert_eq!(router.dispatch("GET", "/", "").await.body, "own Ada");
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_block_super_through_multi_level_inheritance() {
+    use std::fs;
+    let dir = std::env::temp_dir().join(format!("cobalto_super_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("base.html"),
+        "<title>{% block title %}Site{% endblock %}</title>{% block body %}{% block nav %}home{% endblock %}{% endblock %}",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("section.html"),
+        r#"{% extends "base.html" %}{% block title %}Blog | {{ block.super }}{% endblock %}{% block nav %}{{ block.super }} > blog{% endblock %}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("post.html"),
+        r#"{% extends "section.html" %}{% block title %}{{ title }} | {{ block.super }}{% endblock %}{% block nav %}{{ block.super }} > post{% endblock %}"#,
+    )
+    .unwrap();
+    let engine = TemplateEngine::new(dir.to_str().unwrap());
+    let context = HashMap::from([("title".to_string(), TemplateValue::String("Hi".into()))]);
+
+    assert_eq!(
+        engine.render("post.html", &context).body,
+        "<title>Hi | Blog | Site</title>home > blog > post"
+    );
+    assert_eq!(
+        engine.render("section.html", &context).body,
+        "<title>Blog | Site</title>home > blog"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_for_over_objects_and_unpacked_pairs() {
+    let scores = HashMap::from([
+        ("bob".to_string(), TemplateValue::Number(2.0)),
+        ("ada".to_string(), TemplateValue::Number(3.0)),
+    ]);
+    let pairs = TemplateValue::List(vec![
+        TemplateValue::List(vec![
+            TemplateValue::String("x".into()),
+            TemplateValue::Number(1.0),
+        ]),
+        TemplateValue::List(vec![
+            TemplateValue::String("y".into()),
+            TemplateValue::Number(2.0),
+        ]),
+    ]);
+    let context = HashMap::from([
+        ("scores".to_string(), TemplateValue::Object(scores)),
+        ("pairs".to_string(), pairs),
+    ]);
+    let render = |src: &str| render_nodes(&parse_tokens(&tokenize_template(src)), &context);
+
+    assert_eq!(
+        render("{% for name, score in scores %}{{ name }}={{ score }};{% endfor %}"),
+        "ada=3;bob=2;"
+    );
+    assert_eq!(
+        render("{% for k,v in scores.items %}{{ forloop.counter }}{{ k }}{% endfor %}"),
+        "1ada2bob"
+    );
+    assert_eq!(
+        render("{% for key in scores %}{{ key }} {% endfor %}"),
+        "ada bob "
+    );
+    assert_eq!(
+        render("{% for label, n in pairs %}{{ label }}{{ n }}{% endfor %}"),
+        "x1y2"
+    );
+}
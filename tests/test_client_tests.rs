@@ -0,0 +1,104 @@
+use cobalto::cookie::Cookie;
+use cobalto::extract::{Json, extract};
+use cobalto::router::{Request, Response, Router, handler};
+use cobalto::settings::Settings;
+use cobalto::test::Client;
+use serde_json::{Value, json};
+
+fn app() -> Router {
+    let mut router = Router::new(Settings::default());
+    router.add_route(
+        "POST",
+        "/echo",
+        extract(|Json(body): Json<Value>| async move { Response::json(body) }),
+        "echo",
+    );
+    router.add_route(
+        "GET",
+        "/login",
+        handler(|_req: Request| async move {
+            Response::html("hi").set_cookie(Cookie::new("user", "ada"))
+        }),
+        "login",
+    );
+    router.add_route(
+        "GET",
+        "/logout",
+        handler(|_req: Request| async move { Response::html("bye").remove_cookie("user") }),
+        "logout",
+    );
+    router.add_route(
+        "GET",
+        "/me",
+        handler(|req: Request| async move {
+            Response::html(req.cookie("user").unwrap_or_else(|| "anonymous".into()))
+        }),
+        "me",
+    );
+    router
+}
+
+#[tokio::test]
+async fn test_client_sends_bodies_and_reports_responses() {
+    let client = Client::new(app());
+    let response = client
+        .post("/echo")
+        .json(&json!({"name": "Ada"}))
+        .send()
+        .await;
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        serde_json::from_str::<Value>(&response.body).unwrap(),
+        json!({"name": "Ada"})
+    );
+    assert_eq!(client.get("/missing").send().await.status, 404);
+    assert_eq!(client.get("/echo").send().await.status, 404);
+}
+
+#[tokio::test]
+async fn test_client_keeps_cookies_between_requests() {
+    let client = Client::new(app());
+    assert_eq!(client.get("/me").send().await.body, "anonymous");
+    client.get("/login").send().await;
+    assert_eq!(client.cookie("user").as_deref(), Some("ada"));
+    assert_eq!(client.get("/me").send().await.body, "ada");
+    // An explicit header wins over the jar
+    let other = client.get("/me").header("Cookie", "user=bob").send().await;
+    assert_eq!(other.body, "bob");
+    client.get("/logout").send().await;
+    assert_eq!(client.get("/me").send().await.body, "anonymous");
+}
+
+#[tokio::test]
+async fn test_request_id_is_echoed_or_generated() {
+    let mut router = Router::new(Settings::default());
+    router.add_route(
+        "GET",
+        "/id",
+        handler(|req: Request| async move { Response::html(req.request_id()) }),
+        "id",
+    );
+    let client = Client::new(router);
+
+    let response = client
+        .get("/id")
+        .header("X-Request-Id", "trace-abc-123")
+        .send()
+        .await;
+    assert_eq!(response.body, "trace-abc-123");
+    assert_eq!(
+        response.headers.get("X-Request-Id").map(String::as_str),
+        Some("trace-abc-123")
+    );
+
+    // Missing or unusable IDs are replaced by a generated one
+    for header in [None, Some("has spaces"), Some("")] {
+        let mut request = client.get("/id");
+        if let Some(value) = header {
+            request = request.header("X-Request-Id", value);
+        }
+        let response = request.send().await;
+        assert_eq!(response.body.len(), 32);
+        assert_eq!(response.headers.get("X-Request-Id"), Some(&response.body));
+    }
+}
@@ -0,0 +1,104 @@
+use cobalto::router::{Handler, Request, Response};
+use cobalto::throttle::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn test_scopes_counted_per_identity() {
+    let t = Throttle::new(
+        vec![ThrottleScope::burst(2), ThrottleScope::sustained(100)],
+        |_| None,
+    );
+    assert!(t.check("alice").unwrap().allowed);
+    let d = t.check("alice").unwrap();
+    assert!(d.allowed);
+    assert_eq!((d.scope.as_str(), d.remaining), ("burst", 0));
+    assert!(!t.check("alice").unwrap().allowed);
+    // Another identity has its own quota
+    assert!(t.check("bob").unwrap().allowed);
+}
+
+#[test]
+fn test_window_resets() {
+    let t = Throttle::new(
+        vec![ThrottleScope::new("tiny", 1, Duration::from_millis(0))],
+        |_| None,
+    );
+    assert!(t.check("k").unwrap().allowed);
+    assert!(t.check("k").unwrap().allowed);
+}
+
+#[tokio::test]
+async fn test_wrapped_handler_sets_headers_and_429() {
+    let t = Throttle::new(vec![ThrottleScope::burst(1)], |req: &Request| {
+        req.params.get("key").cloned()
+    });
+    let handler: Handler = Arc::new(|_req| Box::pin(async { Response::html("ok") }));
+    let wrapped = t.wrap(handler);
+    let req = || Request {
+        params: HashMap::from([("key".to_string(), "api-1".to_string())]),
+        body: String::new(),
+        query: HashMap::new(),
+        ..Default::default()
+    };
+
+    let resp = wrapped(req()).await;
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.headers.get("X-RateLimit-Remaining").unwrap(), "0");
+
+    let resp = wrapped(req()).await;
+    assert_eq!(resp.status, 429);
+    assert_eq!(resp.headers.get("X-RateLimit-Limit").unwrap(), "1");
+}
+
+#[test]
+fn test_rejected_hit_not_counted_in_other_scopes() {
+    let frozen = cobalto::test::freeze_time(chrono::Utc::now());
+    let t = Throttle::new(
+        vec![ThrottleScope::burst(2), ThrottleScope::sustained(5)],
+        |_| None,
+    );
+    assert!(t.check("alice").unwrap().allowed);
+    assert!(t.check("alice").unwrap().allowed);
+    let d = t.check("alice").unwrap();
+    assert_eq!((d.allowed, d.scope.as_str()), (false, "burst"));
+    assert!(!t.check("alice").unwrap().allowed);
+    // Only the allowed hit was counted against `sustained`
+    frozen.advance(chrono::Duration::minutes(2));
+    let d = t.check("alice").unwrap();
+    assert_eq!((d.scope.as_str(), d.remaining), ("sustained", 2));
+}
+
+#[test]
+fn test_expired_windows_are_pruned() {
+    let t = Throttle::new(
+        vec![ThrottleScope::new("tiny", 1, Duration::from_millis(0))],
+        |_| None,
+    );
+    for id in ["a", "b", "c"] {
+        t.check(id);
+    }
+    assert_eq!(t.tracked_windows(), 1);
+}
+
+#[tokio::test]
+async fn test_attach_throttles_route_group() {
+    let t = Throttle::new(vec![ThrottleScope::burst(1)], |req: &Request| {
+        req.header("x-api-key").map(str::to_string)
+    });
+    let mut router = cobalto::router::Router::new(cobalto::settings::Settings::default());
+    router.group("/api", |g| {
+        t.attach(g);
+        g.get("/users", |_req: Request| async { Response::html("users") });
+    });
+    let headers = || HashMap::from([("X-Api-Key".to_string(), "k1".to_string())]);
+    let resp = router
+        .dispatch_with_headers("GET", "/api/users", headers(), Default::default())
+        .await;
+    assert_eq!(resp.status, 200);
+    let resp = router
+        .dispatch_with_headers("GET", "/api/users", headers(), Default::default())
+        .await;
+    assert_eq!(resp.status, 429);
+}
@@ -0,0 +1,46 @@
+use cobalto::settings::Settings;
+use cobalto::tls::{https_url, load_rustls_config};
+
+#[test]
+fn test_https_redirect_urls() {
+    assert_eq!(
+        https_url("example.com:80", "/login?next=/", 443),
+        "https://example.com/login?next=/"
+    );
+    assert_eq!(
+        https_url("example.com", "", 8443),
+        "https://example.com:8443/"
+    );
+    assert_eq!(https_url("[::1]:8080", "/a", 443), "https://[::1]/a");
+    assert_eq!(https_url("[::1]", "/a", 443), "https://[::1]/a");
+}
+
+#[test]
+fn test_tls_settings_are_validated() {
+    let mut settings = Settings::default();
+    settings.set("tls.cert", "cert.pem").unwrap();
+    assert!(!settings.tls.enabled());
+    assert!(settings.validate().is_err());
+
+    settings.set("tls.key", "key.pem").unwrap();
+    settings.set("tls.redirect_port", "8080").unwrap();
+    assert!(settings.tls.enabled());
+    assert!(settings.validate().is_ok());
+    assert_eq!(settings.tls.redirect_port, Some(8080));
+
+    settings.set("tls.redirect_port", "8000").unwrap();
+    assert!(settings.validate().is_err());
+}
+
+#[test]
+fn test_missing_or_empty_pem_files_are_errors() {
+    let err = load_rustls_config("/nonexistent/cert.pem", "/nonexistent/key.pem").unwrap_err();
+    assert!(err.to_string().contains("/nonexistent/cert.pem"));
+
+    let empty = std::env::temp_dir().join("cobalto_tls_empty.pem");
+    std::fs::write(&empty, "").unwrap();
+    let path = empty.to_str().unwrap();
+    let err = load_rustls_config(path, path).unwrap_err();
+    assert!(err.to_string().contains("no certificate found"));
+    let _ = std::fs::remove_file(&empty);
+}
@@ -0,0 +1,41 @@
+use cobalto::router::Router;
+use cobalto::settings::Settings;
+use cobalto::websocket::{WebSocket, WsContext, WsHandler, WsMessage};
+use std::sync::Arc;
+
+fn echo_handler() -> WsHandler {
+    Arc::new(|ctx: WsContext, mut ws: WebSocket| {
+        Box::pin(async move {
+            let room: i64 = ctx.param("room").unwrap();
+            while let Some(msg) = ws.recv().await {
+                if let WsMessage::Text(text) = msg {
+                    if ws.send_text(format!("{room}: {text}")).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        })
+    })
+}
+
+#[tokio::test]
+async fn test_websocket_route_echoes_with_path_params() {
+    let mut router = Router::new(Settings::default());
+    router.add_websocket("/ws/chat/:room<i64>", echo_handler());
+    assert_eq!(router.ws_routes.len(), 1);
+    assert!(router.connect_websocket("/ws/chat/lobby").is_none());
+
+    let mut client = router.connect_websocket("/ws/chat/7?nick=bob").unwrap();
+    client.send_text("hi").await.unwrap();
+    assert_eq!(client.recv().await, Some(WsMessage::Text("7: hi".into())));
+}
+
+#[tokio::test]
+async fn test_websocket_pair_reports_close() {
+    let (server, mut client) = WebSocket::pair();
+    let sender = server.sender();
+    sender.send_text("bye").await.unwrap();
+    server.close().await;
+    assert_eq!(client.recv().await, Some(WsMessage::Text("bye".into())));
+    assert_eq!(client.recv().await, None);
+}
@@ -0,0 +1,71 @@
+use cobalto::router::{Request, Response};
+use cobalto::session::Session;
+use cobalto::wizard::*;
+use std::collections::HashMap;
+
+fn post(body: &str) -> Request {
+    Request {
+        params: HashMap::new(),
+        body: body.to_string(),
+        query: HashMap::new(),
+        ..Default::default()
+    }
+}
+
+fn wizard() -> FormWizard {
+    FormWizard::new(
+        "signup",
+        vec![
+            WizardStep::new("account", "wizard_account.html").validate(|form| {
+                if form.get("email").map(|e| e.contains('@')).unwrap_or(false) {
+                    Ok(())
+                } else {
+                    let mut errors = HashMap::new();
+                    errors.insert("email".to_string(), "Invalid email".to_string());
+                    Err(errors)
+                }
+            }),
+            WizardStep::new("profile", "wizard_profile.html"),
+        ],
+        |data| Response::html(format!("{}|{}", data[0]["email"], data[1]["name"])),
+    )
+}
+
+#[test]
+fn test_wizard_full_flow() {
+    let w = wizard();
+    let session = Session::new();
+
+    let resp = w.handle(&post("email=bad"), &session);
+    assert_eq!(resp.status, 422);
+    assert_eq!(w.load_state(&session).current, 0);
+
+    w.handle(&post("email=a%40b.c"), &session);
+    assert_eq!(w.load_state(&session).current, 1);
+
+    let resp = w.handle(&post("name=Ann+Lee"), &session);
+    assert_eq!(resp.body, "a@b.c|Ann Lee");
+    assert!(session.get("wizard:signup").is_none());
+}
+
+#[test]
+fn test_wizard_back_navigation() {
+    let w = wizard();
+    let session = Session::new();
+    w.handle(&post("email=a%40b.c"), &session);
+    w.handle(&post("wizard_action=back"), &session);
+    let state = w.load_state(&session);
+    assert_eq!(state.current, 0);
+    assert_eq!(state.data[0]["email"], "a@b.c");
+}
+
+#[test]
+fn test_wizard_resets_state_past_the_last_step() {
+    let w = wizard();
+    let session = Session::new();
+    session.set("wizard:signup", r#"{"current":5,"data":[]}"#);
+    assert_eq!(w.load_state(&session), WizardState::default());
+
+    w.handle(&post("email=a%40b.c"), &session);
+    assert_eq!(w.load_state(&session).current, 1);
+}